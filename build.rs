@@ -2,23 +2,84 @@
 
 use clap::{CommandFactory, Parser};
 
-// TODO: this is a pasted copy of some of minidaw.rs. When we ship a real CLI as
-// part of the bundled package, refactor its Args code to be shareable.
+// TODO: this is a pasted copy of groove-cli.rs's Args. When we ship a real CLI
+// as part of the bundled package, refactor its Args code to be shareable. Until
+// then, options whose types live in the crate are Strings here, since a build
+// script can't see the crate; the man page reads the same either way.
 
 #[derive(Parser, Debug, Default)]
 #[command(author, about, long_about = None)]
 struct Args {
-    /// Names of files to process. Currently accepts JSON-format projects.
+    /// Names of files to process. Can be JSON, JSON5, MIDI, or scripts.
     input: Vec<String>,
 
     /// Render as WAVE file(s) (file will appear next to source file)
     #[clap(short = 'w', long, value_parser)]
     wav: bool,
 
+    /// Render as FLAC file(s) (file will appear next to source file)
+    #[clap(short = 'f', long, value_parser)]
+    flac: bool,
+
+    /// FLAC compression level, from 0 (fastest) to 8 (smallest)
+    #[clap(long, value_parser, default_value = "5")]
+    flac_level: u8,
+
+    /// Render as MP3 file(s) (not yet implemented)
+    #[clap(short = 'm', long, value_parser)]
+    mp3: bool,
+
+    /// Write the rendered WAVE (or FLAC, with --flac) file here instead of next to the source
+    /// file (only with a single input)
+    #[clap(short = 'o', long, value_parser)]
+    output: Option<std::path::PathBuf>,
+
+    /// Stop rendering after this many seconds
+    #[clap(long, value_parser)]
+    duration: Option<f64>,
+
+    /// Render only these bars, counting from 1, like "9-16"
+    #[clap(long, value_parser)]
+    bars: Option<String>,
+
+    /// After the song ends, keep rendering until it falls silent, for at
+    /// most this many seconds
+    #[clap(long, value_parser)]
+    max_tail: Option<f64>,
+
+    /// Don't run the render through the master limiter, even if it clips
+    #[clap(long, value_parser)]
+    no_limiter: bool,
+
+    /// The WAVE or FLAC output's sample format: 16, 24, or 32f (WAVE only)
+    #[clap(long, value_parser, default_value = "16")]
+    bit_depth: String,
+
+    /// How to dither integer WAVE or FLAC output: none, tpdf, or shaped
+    #[clap(long, value_parser, default_value = "tpdf")]
+    dither: String,
+
+    /// Ignore the project's loop, so the render ends with the arrangement
+    #[clap(long, value_parser)]
+    no_loop: bool,
+
+    /// Render this many beats without saving them, and report how much faster
+    /// than realtime the render ran
+    #[clap(long, value_parser)]
+    benchmark: Option<usize>,
+
     /// Enable debug mode
     #[clap(short = 'd', long, value_parser)]
     debug: bool,
 
+    /// Print perf information
+    #[clap(short = 'p', long, value_parser)]
+    perf: bool,
+
+    /// Suppress status updates while processing
+    #[clap(short = 'q', long, value_parser)]
+    quiet: bool,
+
     /// Print version and exit
     #[clap(short = 'v', long, value_parser)]
     version: bool,
@@ -15,6 +15,12 @@ mod obsolete {
         uid: Uid,
         oscillator: Oscillator,
 
+        #[control]
+        #[params]
+        dca: Dca,
+
+        sample: StereoSample,
+
         is_playing: bool,
         when_to_stop_playing: f64,
         current_measure: usize,
@@ -22,11 +28,7 @@ mod obsolete {
     }
     impl Generates<StereoSample> for Metronome {
         fn value(&self) -> StereoSample {
-            if self.is_playing {
-                self.oscillator.value().into()
-            } else {
-                StereoSample::SILENCE
-            }
+            self.sample
         }
 
         fn generate_batch_values(&mut self, _values: &mut [StereoSample]) {
@@ -57,6 +59,12 @@ mod obsolete {
             }
             self.clock.tick(tick_count);
             self.oscillator.tick(tick_count);
+            self.sample = if self.is_playing {
+                self.dca
+                    .transform_audio_to_stereo(self.oscillator.value().into())
+            } else {
+                StereoSample::SILENCE
+            };
         }
     }
     impl HandlesMidi for Metronome {}
@@ -71,6 +79,8 @@ mod obsolete {
                 clock: Clock::new_with(&clock_params),
                 uid: Default::default(),
                 oscillator: Oscillator::new_with(&oscillator_params),
+                dca: Dca::new_with(&params.dca),
+                sample: Default::default(),
                 is_playing: false,
                 when_to_stop_playing: Default::default(),
                 current_measure: usize::MAX,
@@ -93,6 +103,14 @@ mod obsolete {
         pub fn set_bpm(&mut self, bpm: ParameterType) {
             self.clock.set_bpm(bpm);
         }
+
+        pub fn dca(&self) -> &Dca {
+            &self.dca
+        }
+
+        pub fn set_dca(&mut self, dca: Dca) {
+            self.dca = dca;
+        }
     }
 
     mod gui {
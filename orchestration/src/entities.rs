@@ -16,6 +16,7 @@
 //     },
 //     instruments::{Drumkit, FmSynth, Sampler, WelshSynth},
 // };
+// use groove::mini::{DrumSequencer, FrozenSampler, SampleAndHoldLfo};
 // //use groove_proc_macros::Everything;
 // use groove_toys::{DebugSynth, ToyEffect, ToySynth};
 
@@ -76,12 +77,18 @@
 //     //#[everything(effect, controllable)]
 //     Delay(Delay),
 
+//     //#[everything(controller, midi)]
+//     DrumSequencer(DrumSequencer),
+
 //     //#[everything(instrument, midi)]
 //     Drumkit(Drumkit),
 
 //     //#[everything(instrument, midi, controllable)]
 //     FmSynth(FmSynth),
 
+//     //#[everything(instrument)]
+//     FrozenSampler(FrozenSampler),
+
 //     //#[everything(effect, controllable)]
 //     Gain(Gain),
 
@@ -105,6 +112,9 @@
 //     //#[everything(effect, controllable)]
 //     Reverb(Reverb),
 
+//     //#[everything(controller)]
+//     SampleAndHoldLfo(SampleAndHoldLfo),
+
 //     //#[everything(instrument, midi)]
 //     Sampler(Sampler),
 
@@ -3,14 +3,253 @@
 //! The [helpers](crate::helpers) module contains structs and methods that make
 //! it easier to use the Groove engine.
 
+use anyhow::anyhow;
 use cpal::{
     traits::{DeviceTrait, HostTrait},
     SupportedStreamConfig,
 };
 use ensnare_core::prelude::*;
+use std::str::FromStr;
+
+#[cfg(obsolete)]
+use {crate::orchestrator::Performance, std::path::Path};
+
+/// How to hide the error of rounding samples to integers when exporting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Round to the nearest integer. Quiet passages pick up distortion that
+    /// follows the signal.
+    None,
+    /// Add triangular (TPDF) noise of ±1 LSB before rounding, which turns the
+    /// distortion into a steady, signal-independent hiss.
+    #[default]
+    Tpdf,
+    /// TPDF, plus first-order error feedback that pushes the hiss up toward
+    /// the top of the spectrum, where it's harder to hear.
+    NoiseShaped,
+}
+impl FromStr for Dither {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Dither::None),
+            "tpdf" => Ok(Dither::Tpdf),
+            "shaped" | "noise-shaped" => Ok(Dither::NoiseShaped),
+            _ => Err(anyhow!(
+                "Unknown dither '{s}'; expected none, tpdf, or shaped"
+            )),
+        }
+    }
+}
+
+/// The sample format of an exported WAV file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BitDepth {
+    /// CD quality, and what everything can play.
+    #[default]
+    Sixteen,
+    /// For mastering. The noise floor is far below anything audible.
+    TwentyFour,
+    /// 32-bit float. Samples are written exactly as rendered, so peaks above
+    /// full scale survive for a later stage to deal with.
+    ThirtyTwoFloat,
+}
+impl BitDepth {
+    #[allow(missing_docs)]
+    pub fn bits_per_sample(&self) -> u16 {
+        match self {
+            BitDepth::Sixteen => 16,
+            BitDepth::TwentyFour => 24,
+            BitDepth::ThirtyTwoFloat => 32,
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn sample_format(&self) -> hound::SampleFormat {
+        match self {
+            BitDepth::Sixteen | BitDepth::TwentyFour => hound::SampleFormat::Int,
+            BitDepth::ThirtyTwoFloat => hound::SampleFormat::Float,
+        }
+    }
+
+    /// The [hound::WavSpec] for a stereo file at this depth.
+    pub fn wav_spec(&self, sample_rate: SampleRate) -> hound::WavSpec {
+        hound::WavSpec {
+            channels: 2,
+            sample_rate: sample_rate.value() as u32,
+            bits_per_sample: self.bits_per_sample(),
+            sample_format: self.sample_format(),
+        }
+    }
+}
+impl FromStr for BitDepth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "16" => Ok(BitDepth::Sixteen),
+            "24" => Ok(BitDepth::TwentyFour),
+            "32" | "32f" | "float" => Ok(BitDepth::ThirtyTwoFloat),
+            _ => Err(anyhow!("Unknown bit depth '{s}'; expected 16, 24, or 32f")),
+        }
+    }
+}
+
+/// Options for writing a performance to a WAV file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WavExportOptions {
+    #[allow(missing_docs)]
+    pub bit_depth: BitDepth,
+
+    /// Ignored for [BitDepth::ThirtyTwoFloat], which doesn't round.
+    pub dither: Dither,
+}
+
+/// Options for writing a performance to a FLAC file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlacExportOptions {
+    /// FLAC stores integers only, so [BitDepth::ThirtyTwoFloat] is an error.
+    pub bit_depth: BitDepth,
+
+    #[allow(missing_docs)]
+    pub dither: Dither,
+
+    /// 0 (fastest) to [FlacExportOptions::MAX_COMPRESSION_LEVEL] (smallest),
+    /// like the reference encoder's -0 through -8. Every level is lossless.
+    pub compression_level: u8,
+}
+impl Default for FlacExportOptions {
+    fn default() -> Self {
+        Self {
+            bit_depth: Default::default(),
+            dither: Default::default(),
+            compression_level: 5,
+        }
+    }
+}
+impl FlacExportOptions {
+    #[allow(missing_docs)]
+    pub const MAX_COMPRESSION_LEVEL: u8 = 8;
+
+    // Roughly follows the reference encoder's presets: low levels skip LPC
+    // and use short blocks, and higher levels search deeper predictors.
+    fn encoder_config(&self) -> flacenc::config::Encoder {
+        let level = self.compression_level.min(Self::MAX_COMPRESSION_LEVEL) as usize;
+        let mut config = flacenc::config::Encoder::default();
+        config.block_size = if level < 3 { 1152 } else { 4096 };
+        config.subframe_coding.use_lpc = level > 2;
+        config.subframe_coding.qlpc.lpc_order = match level {
+            0..=2 => 0,
+            3..=5 => 8,
+            _ => 12,
+        };
+        config.stereo_coding.use_leftside = level > 0;
+        config.stereo_coding.use_rightside = level > 0;
+        config.stereo_coding.use_midside = level > 0;
+        config
+    }
+}
+
+/// Encodes stereo frames as a complete FLAC stream, ready to be written to
+/// a file. It quantizes with the same [Ditherer] as WAV export, so a FLAC
+/// and a WAV exported with the same options decode to the same samples.
+pub fn encode_flac(
+    samples: &[StereoSample],
+    sample_rate: SampleRate,
+    options: &FlacExportOptions,
+) -> anyhow::Result<Vec<u8>> {
+    use flacenc::{component::BitRepr, error::Verify};
+
+    if options.bit_depth == BitDepth::ThirtyTwoFloat {
+        return Err(anyhow!("FLAC can't store floating-point samples"));
+    }
+    let bits = options.bit_depth.bits_per_sample();
+    let mut ditherer = Ditherer::new_with(options.dither, bits, IOHelper::DITHER_SEED);
+    let interleaved: Vec<i32> = samples
+        .iter()
+        .flat_map(|sample| ditherer.quantize_frame(sample))
+        .collect();
+    let config = options
+        .encoder_config()
+        .into_verified()
+        .map_err(|e| anyhow!("Bad FLAC encoder configuration: {e:?}"))?;
+    let source = flacenc::source::MemSource::from_samples(
+        &interleaved,
+        2,
+        bits as usize,
+        sample_rate.value(),
+    );
+    let stream = flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
+        .map_err(|e| anyhow!("Couldn't encode FLAC: {e:?}"))?;
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    stream
+        .write(&mut sink)
+        .map_err(|e| anyhow!("Couldn't write FLAC stream: {e:?}"))?;
+    Ok(sink.as_slice().to_vec())
+}
+
+/// Converts samples to integers of a given width with the chosen [Dither]. It
+/// keeps separate error-feedback state for each channel, so it should see one
+/// whole stream of frames in order.
+#[derive(Debug)]
+pub struct Ditherer {
+    dither: Dither,
+    /// The largest value a sample can have, which is what full scale maps to.
+    amplitude: f64,
+    rng: oorandom::Rand64,
+    /// Each channel's quantization error from the previous frame, in LSBs.
+    error: [f64; 2],
+}
+impl Ditherer {
+    /// Quantizes to `bits` bits, up to 32. The same seed always produces the
+    /// same noise, so exports are repeatable.
+    pub fn new_with(dither: Dither, bits: u16, seed: u128) -> Self {
+        let bits = bits.clamp(2, 32);
+        Self {
+            dither,
+            amplitude: ((1i64 << (bits - 1)) - 1) as f64,
+            rng: oorandom::Rand64::new(seed),
+            error: [0.0; 2],
+        }
+    }
+
+    /// Converts one sample of the given channel (0 is left, 1 is right).
+    pub fn quantize(&mut self, channel: usize, sample: Sample) -> i32 {
+        let mut value = sample.0 * self.amplitude;
+        if self.dither == Dither::NoiseShaped {
+            value -= self.error[channel];
+        }
+        let noise = match self.dither {
+            Dither::None => 0.0,
+            Dither::Tpdf | Dither::NoiseShaped => self.rng.rand_float() - self.rng.rand_float(),
+        };
+        let rounded = (value + noise).round();
+        let quantized = rounded.clamp(-self.amplitude - 1.0, self.amplitude);
+
+        // A clipped sample's error is the overshoot, not quantization noise.
+        // Feeding that back would push the next sample further out, and the
+        // error would grow for as long as the signal stays over full scale.
+        self.error[channel] = if quantized == rounded {
+            quantized - value
+        } else {
+            0.0
+        };
+        quantized as i32
+    }
+
+    /// Converts both channels of one frame.
+    pub fn quantize_frame(&mut self, sample: &StereoSample) -> [i32; 2] {
+        [self.quantize(0, sample.0), self.quantize(1, sample.1)]
+    }
+}
 
 pub struct IOHelper {}
 impl IOHelper {
+    /// Dither noise is seeded, so that rendering a project twice produces
+    /// identical files.
+    pub const DITHER_SEED: u128 = 0x6772_6f6f_7665;
+
     pub fn default_output_device() -> cpal::Device {
         if let Some(device) = cpal::default_host().default_output_device() {
             device
@@ -71,28 +310,217 @@ impl IOHelper {
     //     panic!()
     // }
 
-    // pub fn send_performance_to_file(
-    //     performance: &Performance,
-    //     output_path: &PathBuf,
-    // ) -> anyhow::Result<()> {
-    //     const AMPLITUDE: SampleType = i16::MAX as SampleType;
-    //     let spec = hound::WavSpec {
-    //         channels: 2,
-    //         sample_rate: performance.sample_rate.value() as u32,
-    //         bits_per_sample: 16,
-    //         sample_format: hound::SampleFormat::Int,
-    //     };
-    //     if let Some(path) = output_path.to_str() {
-    //         let mut writer = hound::WavWriter::create(path, spec).unwrap();
-
-    //         while !performance.worker.is_empty() {
-    //             let sample = performance.worker.pop().unwrap_or_default();
-    //             let _ = writer.write_sample((sample.0 .0 * AMPLITUDE) as i16);
-    //             let _ = writer.write_sample((sample.1 .0 * AMPLITUDE) as i16);
-    //         }
-    //         Ok(())
-    //     } else {
-    //         Err(anyhow!("Couldn't create path from {:?}", output_path))
-    //     }
-    // }
+    /// Writes the performance to a stereo WAV file with the default
+    /// [WavExportOptions].
+    #[cfg(obsolete)]
+    pub fn send_performance_to_file(
+        performance: &Performance,
+        output_path: &Path,
+    ) -> anyhow::Result<()> {
+        Self::send_performance_to_file_with(performance, output_path, &Default::default())
+    }
+
+    /// Writes the performance to a stereo WAV file in the format that
+    /// `options` asks for.
+    #[cfg(obsolete)]
+    pub fn send_performance_to_file_with(
+        performance: &Performance,
+        output_path: &Path,
+        options: &WavExportOptions,
+    ) -> anyhow::Result<()> {
+        let bit_depth = options.bit_depth;
+        let spec = bit_depth.wav_spec(performance.sample_rate);
+        let mut writer = hound::WavWriter::create(output_path, spec)
+            .map_err(|e| anyhow!("Couldn't create {}: {e}", output_path.display()))?;
+        let mut ditherer = Ditherer::new_with(
+            options.dither,
+            bit_depth.bits_per_sample(),
+            Self::DITHER_SEED,
+        );
+        while let Some(sample) = performance.worker.pop() {
+            match bit_depth {
+                BitDepth::Sixteen => {
+                    for value in ditherer.quantize_frame(&sample) {
+                        writer.write_sample(value as i16)?;
+                    }
+                }
+                BitDepth::TwentyFour => {
+                    for value in ditherer.quantize_frame(&sample) {
+                        writer.write_sample(value)?;
+                    }
+                }
+                BitDepth::ThirtyTwoFloat => {
+                    writer.write_sample(sample.0 .0 as f32)?;
+                    writer.write_sample(sample.1 .0 as f32)?;
+                }
+            }
+        }
+        writer.finalize()?;
+        Ok(())
+    }
+
+    /// Writes the performance to a stereo FLAC file.
+    #[cfg(obsolete)]
+    pub fn send_performance_to_flac(
+        performance: &Performance,
+        output_path: &Path,
+        options: &FlacExportOptions,
+    ) -> anyhow::Result<()> {
+        let mut samples = Vec::with_capacity(performance.worker.len());
+        while let Some(sample) = performance.worker.pop() {
+            samples.push(sample);
+        }
+        let bytes = encode_flac(&samples, performance.sample_rate, options)?;
+        std::fs::write(output_path, bytes)
+            .map_err(|e| anyhow!("Couldn't write {}: {e}", output_path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+
+    // A sine a few LSBs tall, which is where undithered rounding sounds worst.
+    fn quiet_sine() -> Vec<f64> {
+        (0..48000)
+            .map(|i| 3.3 / i16::MAX as f64 * (TAU * 441.0 * i as f64 / 48000.0).sin())
+            .collect()
+    }
+
+    // How much the quantization error follows the signal, as the correlation
+    // between the error and the sine's own harmonics. Undithered rounding
+    // error is a distorted copy of the signal; dithered error is just noise.
+    fn error_harmonic_correlation(dither: Dither) -> f64 {
+        let mut ditherer = Ditherer::new_with(dither, 16, 1);
+        let input = quiet_sine();
+        let mut correlation = 0.0f64;
+        for harmonic in [3.0, 5.0] {
+            let (mut sin_sum, mut cos_sum) = (0.0, 0.0);
+            for (i, x) in input.iter().enumerate() {
+                let q = ditherer.quantize(0, Sample(*x)) as f64;
+                let error = q - x * i16::MAX as f64;
+                let phase = TAU * 441.0 * harmonic * i as f64 / 48000.0;
+                sin_sum += error * phase.sin();
+                cos_sum += error * phase.cos();
+            }
+            let magnitude = (sin_sum * sin_sum + cos_sum * cos_sum).sqrt() / input.len() as f64;
+            correlation = correlation.max(magnitude);
+        }
+        correlation
+    }
+
+    #[test]
+    fn dither_decorrelates_the_noise_floor() {
+        let undithered = error_harmonic_correlation(Dither::None);
+        let tpdf = error_harmonic_correlation(Dither::Tpdf);
+        let shaped = error_harmonic_correlation(Dither::NoiseShaped);
+        assert!(
+            tpdf * 5.0 < undithered,
+            "TPDF should remove the distortion ({tpdf} vs. {undithered})"
+        );
+        assert!(
+            shaped * 5.0 < undithered,
+            "noise shaping should too ({shaped} vs. {undithered})"
+        );
+    }
+
+    #[test]
+    fn dither_is_small_and_repeatable() {
+        let mut a = Ditherer::new_with(Dither::Tpdf, 16, 42);
+        let mut b = Ditherer::new_with(Dither::Tpdf, 16, 42);
+        for _ in 0..1000 {
+            let q = a.quantize(0, Sample(0.0));
+            assert!(q.abs() <= 1, "TPDF never moves a sample more than 1 LSB");
+            assert_eq!(q, b.quantize(0, Sample(0.0)));
+        }
+        let mut none = Ditherer::new_with(Dither::None, 16, 42);
+        assert_eq!(none.quantize(0, Sample(1.0)), i16::MAX as i32);
+        assert_eq!(none.quantize(1, Sample(-2.0)), i16::MIN as i32);
+        assert_eq!("shaped".parse::<Dither>().unwrap(), Dither::NoiseShaped);
+        assert!("fancy".parse::<Dither>().is_err());
+    }
+
+    #[test]
+    fn noise_shaping_recovers_from_clipping() {
+        let mut ditherer = Ditherer::new_with(Dither::NoiseShaped, 16, 7);
+        for _ in 0..1000 {
+            assert_eq!(ditherer.quantize(0, Sample(1.5)), i16::MAX as i32);
+        }
+
+        // Back under full scale, the very next sample lands where it should,
+        // rather than staying pinned while a huge error drains away.
+        let expected = 0.5 * i16::MAX as f64;
+        for _ in 0..100 {
+            let q = ditherer.quantize(0, Sample(0.5)) as f64;
+            assert!(
+                (q - expected).abs() <= 3.0,
+                "{q} should be within a few LSBs of {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn bit_depths_scale_to_their_width() {
+        let mut ditherer = Ditherer::new_with(Dither::None, 24, 0);
+        assert_eq!(ditherer.quantize(0, Sample(1.0)), (1 << 23) - 1);
+        assert_eq!(ditherer.quantize(0, Sample(-1.5)), -(1 << 23));
+        assert_eq!(ditherer.quantize(0, Sample(0.5)), 1 << 22);
+
+        let spec = BitDepth::ThirtyTwoFloat.wav_spec(SampleRate::new(48000));
+        assert_eq!(spec.bits_per_sample, 32);
+        assert_eq!(spec.sample_format, hound::SampleFormat::Float);
+        assert_eq!(spec.sample_rate, 48000);
+        assert_eq!(
+            BitDepth::TwentyFour
+                .wav_spec(SampleRate::new(44100))
+                .sample_format,
+            hound::SampleFormat::Int
+        );
+        assert_eq!("32f".parse::<BitDepth>().unwrap(), BitDepth::ThirtyTwoFloat);
+        assert!("8".parse::<BitDepth>().is_err());
+    }
+
+    #[test]
+    fn flac_round_trips_bit_exactly() {
+        let sample_rate = SampleRate::new(44100);
+        let samples: Vec<StereoSample> = (0..10000)
+            .map(|i| {
+                let t = i as f64 / 44100.0;
+                StereoSample(
+                    Sample(0.5 * (TAU * 440.0 * t).sin()),
+                    Sample(0.25 * (TAU * 660.0 * t).sin()),
+                )
+            })
+            .collect();
+        for (bit_depth, bits) in [(BitDepth::Sixteen, 16), (BitDepth::TwentyFour, 24)] {
+            let options = FlacExportOptions {
+                bit_depth,
+                dither: Dither::None,
+                ..Default::default()
+            };
+            let bytes = encode_flac(&samples, sample_rate, &options).unwrap();
+
+            let mut reader = claxon::FlacReader::new(std::io::Cursor::new(bytes)).unwrap();
+            let info = reader.streaminfo();
+            assert_eq!(info.sample_rate, 44100);
+            assert_eq!(info.channels, 2);
+            assert_eq!(info.bits_per_sample, bits);
+            assert_eq!(info.samples, Some(samples.len() as u64));
+
+            let mut ditherer = Ditherer::new_with(Dither::None, bits as u16, 0);
+            let expected: Vec<i32> = samples
+                .iter()
+                .flat_map(|s| ditherer.quantize_frame(s))
+                .collect();
+            let decoded: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+            assert_eq!(decoded, expected, "{bits}-bit FLAC should be lossless");
+        }
+
+        let options = FlacExportOptions {
+            bit_depth: BitDepth::ThirtyTwoFloat,
+            ..Default::default()
+        };
+        assert!(encode_flac(&samples, sample_rate, &options).is_err());
+    }
 }
@@ -9,6 +9,8 @@
 
 pub mod helpers;
 pub mod messages;
+pub mod osc;
+pub mod web_api;
 
 mod entities;
 #[cfg(obsolete)]
@@ -22,7 +24,8 @@ mod metrics;
 mod tests {
     mod params {
         use ensnare_core::{prelude::*, traits::prelude::*};
-        use ensnare_proc_macros::{Control, Params, Uid};
+        use ensnare_proc_macros::Uid;
+        use groove_proc_macros::{Control, Params};
         use serde::{Deserialize, Serialize};
         use strum::EnumCount;
         use strum_macros::{EnumCount as EnumCountMacro, FromRepr};
@@ -63,11 +66,6 @@ mod tests {
             Yellow,
         }
         impl Cherry {
-            // TODO: this is a hack that needs to be automated in the Control derive macro.
-            // As a workaround, structs that incorporate enums can declare them #[params(leaf=true)]
-            #[allow(dead_code)]
-            pub const STRUCT_SIZE: usize = 1;
-
             fn next_cherry(&self) -> Self {
                 Cherry::from_repr((*self as usize + 1) % Cherry::COUNT).unwrap()
             }
@@ -117,14 +115,14 @@ mod tests {
             apple_count: usize,
 
             #[params]
-            #[control]
+            #[control(min = 0.0, max = 1.0, default = 0.5, unit = "bananas")]
             banana_quality: f32,
 
-            #[params(leaf = true)]
-            #[control(leaf = true)]
+            #[params]
+            #[control(setter = true)]
             cherry: Cherry,
 
-            #[params(leaf = true)]
+            #[params]
             abnormal: Abnormal,
         }
         impl Configurable for Stuff {}
@@ -172,7 +170,6 @@ mod tests {
                 self.cherry
             }
 
-            #[allow(dead_code)]
             fn set_cherry(&mut self, cherry: Cherry) {
                 self.cherry = cherry;
                 self.clear_precomputed();
@@ -253,6 +250,38 @@ mod tests {
             }
         }
 
+        // Has no setter, so the Control derive assigns the field directly.
+        #[derive(Control, Debug, Default)]
+        pub struct Orchard {
+            #[control]
+            cherry: Cherry,
+        }
+
+        #[test]
+        fn params_infer_leaves() {
+            let params = MiscParams::make_fake();
+
+            // Enums are their own Params, and nested structs get theirs.
+            let cherry: Cherry = params.stuff().cherry();
+            let abnormal: Abnormal = params.stuff().abnormal();
+            let stuff: &StuffParams = params.stuff();
+            assert_eq!(stuff.cherry(), cherry);
+            assert_eq!(stuff.abnormal(), abnormal);
+
+            let misc = Misc::new(MiscParams::make_fake());
+            assert_eq!(misc.to_params(), params);
+        }
+
+        #[test]
+        fn control_leaf_without_setter() {
+            let mut orchard = Orchard::default();
+            assert_eq!(orchard.control_index_count(), 1);
+
+            let value = orchard.cherry_to_control_value(Cherry::Maraschino);
+            orchard.control_set_param_by_name("cherry", value);
+            assert_eq!(orchard.cherry, Cherry::Maraschino);
+        }
+
         #[test]
         fn control_params_by_name() {
             let a_params = StuffParams::make_fake();
@@ -296,6 +325,35 @@ mod tests {
             assert_eq!(a, b);
         }
 
+        #[test]
+        fn control_value_converters() {
+            let a: Stuff = Stuff::new(StuffParams::make_fake());
+
+            let value = a.cherry_to_control_value(Cherry::Maraschino);
+            assert_eq!(a.control_value_to_cherry(value), Cherry::Maraschino);
+
+            let value = a.banana_quality_to_control_value(0.5);
+            assert_eq!(a.control_value_to_banana_quality(value), 0.5);
+        }
+
+        #[test]
+        fn control_param_descriptors() {
+            let a = Misc::new(MiscParams::make_fake());
+
+            let descriptors = a.control_param_descriptors();
+            assert_eq!(descriptors.len(), a.control_index_count());
+
+            let d = &descriptors[0];
+            assert_eq!(d.name, "cat-count");
+            assert_eq!((d.min, d.max, d.default), (0.0, 1.0, 0.0));
+            assert!(d.unit.is_empty());
+
+            let d = &descriptors[3];
+            assert_eq!(d.name, "stuff-banana-quality");
+            assert_eq!((d.min, d.max, d.default), (0.0, 1.0, 0.5));
+            assert_eq!(d.unit, "bananas");
+        }
+
         #[test]
         fn control_ergonomics() {
             let a: Stuff = Stuff::new(StuffParams::make_fake());
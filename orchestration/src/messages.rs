@@ -18,12 +18,27 @@ pub enum GrooveInput {
     /// MidiInputHandler.
     MidiFromExternal(MidiChannel, MidiMessage),
 
+    /// Like [GrooveInput::MidiFromExternal], but to be delivered right before
+    /// the given free-running frame, so that it keeps the timing it was played
+    /// with. See [MidiStamper](crate::orchestrator::MidiStamper).
+    MidiFromExternalAt(u64, MidiChannel, MidiMessage),
+
     /// Ask the engine to add a control link.
     AddControlLink(ControlLink),
 
     /// Ask the engine to remove a control link.
     RemoveControlLink(ControlLink),
 
+    /// Set the named #[control] parameter of the given entity, as a remote
+    /// control surface does when the user moves a fader.
+    SetParam(Uid, String, ControlValue),
+
+    /// Patch the first entity's audio output into the second's input.
+    Patch(Uid, Uid),
+
+    /// Solo the given entity in place, or with None, go back to the full mix.
+    Audition(Option<Uid>),
+
     /// Orchestrator should ask everyone to start playing.
     Play,
 
@@ -33,6 +48,13 @@ pub enum GrooveInput {
     /// Orchestrator should ask everyone to reset to start of performance.
     SkipToStart,
 
+    /// Orchestrator should move the playhead to the given position.
+    Seek(MusicalTime),
+
+    /// Orchestrator should silence every note on every channel, inside and
+    /// outside Groove.
+    MidiPanic,
+
     /// Someone has requested this sample rate.
     SetSampleRate(SampleRate),
 }
@@ -52,6 +74,18 @@ pub enum GrooveEvent {
 
     /// A MIDI message that should be routed from Groove to outside.
     MidiToExternal(MidiChannel, MidiMessage),
+
+    /// Rendering kept falling behind, with the given load, so the load guard
+    /// bypassed this effect. See
+    /// [Orchestrator::set_load_guard()](crate::orchestrator::Orchestrator::set_load_guard).
+    LoadTrimmed(Uid, f64),
+
+    /// The load has come down, so the load guard put this effect back.
+    LoadRestored(Uid),
+
+    /// Rendering kept falling behind, with the given load, and the load guard
+    /// had nothing left that it's allowed to trim. Expect dropouts.
+    Overloaded(f64),
 }
 impl MessageBounds for GrooveEvent {}
 
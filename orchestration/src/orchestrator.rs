@@ -9,6 +9,7 @@ use core::fmt::Debug;
 use crossbeam::deque::Worker;
 use ensnare::{prelude::*, uid::IsUid};
 use ensnare_proc_macros::Uid;
+use groove::mini::{FrozenSampler, SeedBank, Seeded, Transport};
 use groove_core::{
     control::{ControlIndex, ControlValue},
     midi::{MidiChannel, MidiMessage},
@@ -22,8 +23,10 @@ use groove_entities::{
 use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::VecDeque,
     io::{self, Write},
     ops::Range,
+    time::{Duration, Instant},
 };
 
 #[cfg(feature = "metrics")]
@@ -45,6 +48,628 @@ impl Performance {
     }
 }
 
+/// How fast an offline render ran compared to realtime.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BenchmarkReport {
+    /// Seconds of audio that were rendered
+    pub audio_seconds: f64,
+    /// Wall-clock seconds that the render took
+    pub wall_seconds: f64,
+}
+impl BenchmarkReport {
+    /// How many seconds of audio were rendered per second of wall-clock time.
+    /// Anything below 1.0 can't keep up with a live performance.
+    pub fn realtime_factor(&self) -> f64 {
+        if self.wall_seconds > 0.0 {
+            self.audio_seconds / self.wall_seconds
+        } else {
+            f64::INFINITY
+        }
+    }
+}
+impl std::fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "rendered {:.1}s of audio in {:.1}s ({:.1}x realtime)",
+            self.audio_seconds,
+            self.wall_seconds,
+            self.realtime_factor()
+        )
+    }
+}
+
+/// Controls what happens after an offline render's performance ends.
+/// Rendering continues until the output stays below `tail_threshold` for
+/// [RenderOptions::SILENCE_WINDOW] seconds, or until `max_tail` seconds have
+/// passed, whichever comes first. The trailing silence isn't kept.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderOptions {
+    /// In dBFS.
+    pub tail_threshold: f64,
+    /// In seconds. This keeps a self-oscillating feedback loop from rendering
+    /// forever. Zero ends the render exactly where the performance ends.
+    pub max_tail: f64,
+    /// The ceiling of the [MasterLimiter] on the rendered output, in dBFS, or
+    /// None to leave the output alone.
+    pub limiter_ceiling: Option<f64>,
+}
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            tail_threshold: -60.0,
+            max_tail: 10.0,
+            limiter_ceiling: Some(Self::DEFAULT_LIMITER_CEILING),
+        }
+    }
+}
+impl RenderOptions {
+    /// How long the output has to stay below the threshold to count as
+    /// silent, in seconds.
+    pub const SILENCE_WINDOW: f64 = 0.1;
+
+    /// A little below full scale, so that the peaks that the limiter lets
+    /// through don't turn into inter-sample overs when they're played back.
+    pub const DEFAULT_LIMITER_CEILING: f64 = -0.3;
+
+    /// Whether both channels of the sample are below the threshold.
+    pub fn is_below_threshold(&self, sample: &StereoSample) -> bool {
+        let threshold = 10.0f64.powf(self.tail_threshold / 20.0);
+        sample.0 .0.abs() < threshold && sample.1 .0.abs() < threshold
+    }
+}
+
+/// A brickwall limiter for the main mixer's output. It looks ahead
+/// [MasterLimiter::LOOKAHEAD_SECONDS], so it can turn the gain down smoothly
+/// before a peak arrives instead of clamping the peak itself, and it lets the
+/// gain back up slowly over [MasterLimiter::RELEASE_SECONDS], so that
+/// transients don't make the mix pump. No sample leaves it above the ceiling.
+///
+/// The lookahead delays the signal by [MasterLimiter::latency_frames()].
+/// [MasterLimiter::process_offline()] compensates for that, so a limited
+/// render lines up with an unlimited one.
+#[derive(Clone, Debug)]
+pub struct MasterLimiter {
+    /// Linear, not dBFS.
+    ceiling: f64,
+    lookahead: usize,
+    attack_coefficient: f64,
+    release_coefficient: f64,
+    /// Each delayed frame, with the gain that would bring it down to the
+    /// ceiling.
+    delay: VecDeque<(StereoSample, f64)>,
+    gain: f64,
+}
+impl MasterLimiter {
+    #[allow(missing_docs)]
+    pub const LOOKAHEAD_SECONDS: f64 = 0.005;
+
+    #[allow(missing_docs)]
+    pub const RELEASE_SECONDS: f64 = 0.1;
+
+    /// `ceiling` is in dBFS.
+    pub fn new_with(ceiling: f64, sample_rate: SampleRate) -> Self {
+        let frames_per_second = sample_rate.value() as f64;
+        let lookahead = ((Self::LOOKAHEAD_SECONDS * frames_per_second) as usize).max(1);
+        Self {
+            ceiling: 10.0f64.powf(ceiling.min(0.0) / 20.0),
+            lookahead,
+            // Close enough to the target within the lookahead that the last
+            // step to it is inaudible.
+            attack_coefficient: (4.0 / lookahead as f64).min(1.0),
+            release_coefficient: 1.0 - (-1.0 / (Self::RELEASE_SECONDS * frames_per_second)).exp(),
+            delay: VecDeque::with_capacity(lookahead + 1),
+            gain: 1.0,
+        }
+    }
+
+    /// How many frames late the limited signal comes out.
+    pub fn latency_frames(&self) -> usize {
+        self.lookahead
+    }
+
+    /// Takes one frame in and returns the frame from
+    /// [MasterLimiter::latency_frames()] ago, limited. The first frames out
+    /// are silence.
+    pub fn process(&mut self, sample: StereoSample) -> StereoSample {
+        let peak = sample.0 .0.abs().max(sample.1 .0.abs());
+        let required = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+        self.delay.push_back((sample, required));
+        let target = self
+            .delay
+            .iter()
+            .map(|(_, required)| *required)
+            .fold(1.0, f64::min);
+        if target < self.gain {
+            self.gain = (self.gain - (self.gain - target) * self.attack_coefficient).max(target);
+        } else {
+            self.gain += (target - self.gain) * self.release_coefficient;
+        }
+        if self.delay.len() <= self.lookahead {
+            return StereoSample::SILENCE;
+        }
+        if let Some((sample, required)) = self.delay.pop_front() {
+            // The envelope nearly always gets there first, but a peak must
+            // never get through.
+            let gain = self.gain.min(required);
+            StereoSample(Sample(sample.0 .0 * gain), Sample(sample.1 .0 * gain))
+        } else {
+            StereoSample::SILENCE
+        }
+    }
+
+    /// Limits a whole render. The result is the same length as `samples` and
+    /// lines up with it.
+    pub fn process_offline(&mut self, samples: &[StereoSample]) -> Vec<StereoSample> {
+        let latency = self.latency_frames();
+        samples
+            .iter()
+            .copied()
+            .chain(std::iter::repeat(StereoSample::SILENCE).take(latency))
+            .map(|sample| self.process(sample))
+            .skip(latency)
+            .collect()
+    }
+}
+
+/// Headphone crossfeed for the main mixer's output. Each channel gets a
+/// delayed, low-passed share of the other, the way each ear hears both
+/// speakers in a room, so that hard-panned material doesn't sit inside one
+/// ear. The result is scaled so that a centered signal keeps its level.
+#[derive(Clone, Debug)]
+pub struct Crossfeed {
+    amount: f64,
+    /// In seconds.
+    delay: f64,
+    /// Both channels, as they were [Crossfeed::delay()] ago.
+    delay_line: VecDeque<StereoSample>,
+    lowpass_coefficient: f64,
+    /// The low-passed bleed into (left, right).
+    lowpassed: (f64, f64),
+}
+impl Crossfeed {
+    /// Above this, the head shadows the far ear, so that's about where the
+    /// bleed rolls off.
+    pub const CUTOFF_HZ: f64 = 700.0;
+
+    /// How far apart the ears are, more or less, in seconds.
+    pub const DEFAULT_DELAY: f64 = 0.0003;
+
+    /// Longer delays sound like an echo rather than a room.
+    pub const MAX_DELAY: f64 = 0.002;
+
+    /// `amount` is how much of the other channel to bleed in, 0.0..=1.0, and
+    /// `delay` is in seconds, up to [Crossfeed::MAX_DELAY].
+    pub fn new_with(amount: f64, delay: f64, sample_rate: SampleRate) -> Self {
+        let mut r = Self {
+            amount: amount.clamp(0.0, 1.0),
+            delay: delay.clamp(0.0, Self::MAX_DELAY),
+            delay_line: Default::default(),
+            lowpass_coefficient: Default::default(),
+            lowpassed: Default::default(),
+        };
+        r.update_sample_rate(sample_rate);
+        r
+    }
+
+    #[allow(missing_docs)]
+    pub fn amount(&self) -> f64 {
+        self.amount
+    }
+
+    #[allow(missing_docs)]
+    pub fn delay(&self) -> f64 {
+        self.delay
+    }
+
+    /// Resizes the delay and recomputes the filter for the new rate. This
+    /// starts over from silence.
+    pub fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        let frames_per_second = sample_rate.value() as f64;
+        let delay_frames = (self.delay * frames_per_second).round() as usize;
+        self.delay_line = std::iter::repeat(StereoSample::SILENCE)
+            .take(delay_frames)
+            .collect();
+        self.lowpass_coefficient =
+            1.0 - (-2.0 * std::f64::consts::PI * Self::CUTOFF_HZ / frames_per_second).exp();
+        self.lowpassed = Default::default();
+    }
+
+    /// Takes one frame in and returns it with the crossfeed mixed in.
+    pub fn process(&mut self, sample: StereoSample) -> StereoSample {
+        self.delay_line.push_back(sample);
+        let delayed = self.delay_line.pop_front().unwrap_or(StereoSample::SILENCE);
+        let (into_left, into_right) = &mut self.lowpassed;
+        *into_left += (delayed.1 .0 - *into_left) * self.lowpass_coefficient;
+        *into_right += (delayed.0 .0 - *into_right) * self.lowpass_coefficient;
+        let scale = 1.0 / (1.0 + self.amount);
+        StereoSample(
+            Sample((sample.0 .0 + *into_left * self.amount) * scale),
+            Sample((sample.1 .0 + *into_right * self.amount) * scale),
+        )
+    }
+}
+
+/// Shifts the keys of notes by a fixed interval. It remembers where each
+/// sounding note was sent, so that its note-off goes to the same place even if
+/// the interval changes while the note is held.
+#[derive(Clone, Debug, Default)]
+pub struct Transposer {
+    semitones: i8,
+    transposes_drums: bool,
+    /// (channel, incoming key) -> the key that the note-on was sent as
+    sounding: FxHashMap<(MidiChannel, u8), u8>,
+}
+impl Transposer {
+    /// Drums are traditionally on channel 10, which is MidiChannel(9). Their
+    /// keys select sounds rather than pitches.
+    pub const DRUM_CHANNEL: MidiChannel = MidiChannel(9);
+
+    #[allow(missing_docs)]
+    pub fn semitones(&self) -> i8 {
+        self.semitones
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_semitones(&mut self, semitones: i8) {
+        self.semitones = semitones;
+    }
+
+    /// Whether notes on [Transposer::DRUM_CHANNEL] are transposed along with
+    /// everything else. Off by default, so that a kit's key mapping survives.
+    pub fn transposes_drums(&self) -> bool {
+        self.transposes_drums
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_transposes_drums(&mut self, transposes_drums: bool) {
+        self.transposes_drums = transposes_drums;
+    }
+
+    /// Returns the message with its key shifted, or None if the shifted key
+    /// would be outside 0..=127, in which case the note is dropped. Messages
+    /// without a key pass through unchanged.
+    pub fn transpose(&mut self, channel: MidiChannel, message: MidiMessage) -> Option<MidiMessage> {
+        if channel == Self::DRUM_CHANNEL && !self.transposes_drums {
+            return Some(message);
+        }
+        match message {
+            MidiMessage::NoteOn { key, vel } if vel.as_int() != 0 => {
+                let new_key = self.shifted(key.as_int())?;
+                self.sounding.insert((channel, key.as_int()), new_key);
+                Some(MidiMessage::NoteOn {
+                    key: new_key.into(),
+                    vel,
+                })
+            }
+            // A note-on with zero velocity is a note-off.
+            MidiMessage::NoteOn { key, vel } => {
+                let new_key = self.release(channel, key.as_int())?;
+                Some(MidiMessage::NoteOn {
+                    key: new_key.into(),
+                    vel,
+                })
+            }
+            MidiMessage::NoteOff { key, vel } => {
+                let new_key = self.release(channel, key.as_int())?;
+                Some(MidiMessage::NoteOff {
+                    key: new_key.into(),
+                    vel,
+                })
+            }
+            MidiMessage::Aftertouch { key, vel } => {
+                let new_key = self
+                    .sounding
+                    .get(&(channel, key.as_int()))
+                    .copied()
+                    .or_else(|| self.shifted(key.as_int()))?;
+                Some(MidiMessage::Aftertouch {
+                    key: new_key.into(),
+                    vel,
+                })
+            }
+            _ => Some(message),
+        }
+    }
+
+    fn shifted(&self, key: u8) -> Option<u8> {
+        let key = key as i16 + self.semitones as i16;
+        if (0..=127).contains(&key) {
+            Some(key as u8)
+        } else {
+            None
+        }
+    }
+
+    fn release(&mut self, channel: MidiChannel, key: u8) -> Option<u8> {
+        self.sounding
+            .remove(&(channel, key))
+            .or_else(|| self.shifted(key))
+    }
+}
+
+/// Tracks whether an entity is bypassed, and ramps between its processed and
+/// unprocessed signal so that toggling doesn't click.
+#[derive(Clone, Debug)]
+pub struct Bypass {
+    is_bypassed: bool,
+    /// How much of the processed signal to use. 1.0 is fully in the path, and
+    /// 0.0 is fully bypassed.
+    level: f64,
+}
+impl Default for Bypass {
+    fn default() -> Self {
+        Self {
+            is_bypassed: false,
+            level: 1.0,
+        }
+    }
+}
+impl Bypass {
+    /// How long a bypass toggle takes to fade in or out. About 6 milliseconds
+    /// at 44.1KHz.
+    pub const CROSSFADE_FRAMES: usize = 256;
+
+    #[allow(missing_docs)]
+    pub fn is_bypassed(&self) -> bool {
+        self.is_bypassed
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_bypassed(&mut self, is_bypassed: bool) {
+        self.is_bypassed = is_bypassed;
+    }
+
+    /// The processed signal's share for the current frame.
+    pub fn level(&self) -> f64 {
+        self.level
+    }
+
+    /// Advances the crossfade by one frame and returns the processed signal's
+    /// share for that frame.
+    pub fn next_level(&mut self) -> f64 {
+        let step = 1.0 / Self::CROSSFADE_FRAMES as f64;
+        self.level = if self.is_bypassed {
+            (self.level - step).max(0.0)
+        } else {
+            (self.level + step).min(1.0)
+        };
+        self.level
+    }
+
+    /// True when the entity is fully back in the path, so there's nothing
+    /// left to track.
+    pub fn is_settled_in(&self) -> bool {
+        !self.is_bypassed && self.level >= 1.0
+    }
+
+    /// Mixes the unprocessed and processed signals for the given level.
+    pub fn mix(level: f64, dry: StereoSample, processed: StereoSample) -> StereoSample {
+        let dry_level = 1.0 - level;
+        StereoSample(
+            Sample(dry.0 .0 * dry_level + processed.0 .0 * level),
+            Sample(dry.1 .0 * dry_level + processed.1 .0 * level),
+        )
+    }
+}
+
+/// Converts the timestamps that MIDI input devices put on incoming messages,
+/// in microseconds on the device's clock, to frames on the engine's clock.
+///
+/// The two clocks have unrelated origins, so the first message anchors them:
+/// it's scheduled at the frame being rendered when it arrives, and every later
+/// message lands at the same distance from that frame as its stamp is from the
+/// first stamp. That keeps the spacing between notes as the player played
+/// them, rather than bunching them up at buffer boundaries. If a stamp would
+/// land in a buffer that has already been rendered, or implausibly far in the
+/// future, the clocks have drifted or the device has restarted, and the
+/// stamper re-anchors.
+#[derive(Clone, Debug)]
+pub struct MidiStamper {
+    sample_rate: SampleRate,
+    /// (device stamp, engine frame) that the clocks were last anchored at.
+    anchor: Option<(u64, u64)>,
+}
+impl MidiStamper {
+    /// A stamp this far past the current frame means the clocks have come
+    /// apart, in seconds.
+    pub const MAX_LOOKAHEAD: f64 = 1.0;
+
+    #[allow(missing_docs)]
+    pub fn new_with(sample_rate: SampleRate) -> Self {
+        Self {
+            sample_rate,
+            anchor: None,
+        }
+    }
+
+    /// Returns the engine frame for a message stamped `stamp` microseconds
+    /// that arrived while the engine was about to render `current_frame`.
+    pub fn frame_for(&mut self, stamp: u64, current_frame: u64) -> u64 {
+        if let Some((anchor_stamp, anchor_frame)) = self.anchor {
+            if stamp >= anchor_stamp {
+                let elapsed =
+                    (stamp - anchor_stamp) as u128 * self.sample_rate.0 as u128 / 1_000_000;
+                let frame = anchor_frame.saturating_add(elapsed as u64);
+                let max_lookahead = (Self::MAX_LOOKAHEAD * self.sample_rate.0 as f64) as u64;
+                if frame >= current_frame && frame - current_frame <= max_lookahead {
+                    return frame;
+                }
+            }
+        }
+        self.anchor = Some((stamp, current_frame));
+        current_frame
+    }
+
+    /// Forgets the anchor, as after switching input ports.
+    pub fn reset(&mut self) {
+        self.anchor = None;
+    }
+
+    #[allow(missing_docs)]
+    pub fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        self.reset();
+    }
+}
+
+/// The recording state of an entity that's armed to record: whether live input
+/// is monitored through it, and everything captured since it was armed.
+#[derive(Clone, Debug, Default)]
+pub struct RecordArm {
+    is_monitoring: bool,
+    /// Whether the entity handles MIDI, and so records MIDI input.
+    takes_midi: bool,
+    /// Live audio input, one frame for every frame rendered while armed.
+    audio: Vec<StereoSample>,
+    /// External MIDI, with the free-running frame it was delivered at.
+    midi: Vec<(u64, MidiChannel, MidiMessage)>,
+}
+impl RecordArm {
+    #[allow(missing_docs)]
+    pub fn is_monitoring(&self) -> bool {
+        self.is_monitoring
+    }
+
+    /// The live audio input captured while armed. It's the dry input, not
+    /// what was heard through the entity.
+    pub fn captured_audio(&self) -> &[StereoSample] {
+        &self.audio
+    }
+
+    /// The external MIDI captured while armed, as (free-running frame,
+    /// channel, message).
+    pub fn captured_midi(&self) -> &[(u64, MidiChannel, MidiMessage)] {
+        &self.midi
+    }
+}
+
+/// How [LoadGuard] decides that rendering is falling behind, and when it's
+/// caught up again. Load is a buffer's render time divided by the time it
+/// takes to play, so 1.0 means rendering only just kept up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadGuardPolicy {
+    /// A buffer that renders with more load than this is overloaded.
+    pub trim_load: f64,
+
+    /// A buffer that renders with less load than this is relaxed. It should be
+    /// well under trim_load, or the guard will flap.
+    pub restore_load: f64,
+
+    /// How many overloaded buffers in a row it takes to trim. One slow buffer
+    /// is usually the OS, not the project.
+    pub trim_after: usize,
+
+    /// How many relaxed buffers in a row it takes to restore one trim.
+    pub restore_after: usize,
+
+    /// The most effects that the guard bypasses at once.
+    pub max_trims: usize,
+}
+impl Default for LoadGuardPolicy {
+    fn default() -> Self {
+        Self {
+            trim_load: 0.8,
+            restore_load: 0.5,
+            trim_after: 8,
+            restore_after: 500,
+            max_trims: 4,
+        }
+    }
+}
+
+/// What [LoadGuard] wants done about the load.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadGuardAction {
+    /// Rendering has been falling behind. Lighten the load.
+    Trim,
+
+    /// Rendering has had room to spare for a while. Undo the most recent trim.
+    Restore,
+}
+
+/// Watches the load of each rendered buffer, and says when to trim the
+/// project and when to put it back, according to a [LoadGuardPolicy]. Trims
+/// happen one at a time, each only after a fresh run of overloaded buffers,
+/// so that the guard takes away no more than it needs to.
+#[derive(Clone, Debug, Default)]
+pub struct LoadGuard {
+    policy: LoadGuardPolicy,
+    trim_count: usize,
+    overloaded_run: usize,
+    relaxed_run: usize,
+    load: f64,
+}
+impl LoadGuard {
+    #[allow(missing_docs)]
+    pub fn new_with(policy: LoadGuardPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn policy(&self) -> &LoadGuardPolicy {
+        &self.policy
+    }
+
+    /// The load of the most recent buffer.
+    pub fn load(&self) -> f64 {
+        self.load
+    }
+
+    /// How many trims are in effect.
+    pub fn trim_count(&self) -> usize {
+        self.trim_count
+    }
+
+    /// Records one buffer's load, and returns what to do about it, if
+    /// anything. The caller should call trimmed() or restored() after acting.
+    pub fn record(&mut self, load: f64) -> Option<LoadGuardAction> {
+        self.load = load;
+        if load > self.policy.trim_load {
+            self.overloaded_run += 1;
+            self.relaxed_run = 0;
+        } else if load < self.policy.restore_load {
+            self.relaxed_run += 1;
+            self.overloaded_run = 0;
+        } else {
+            self.overloaded_run = 0;
+            self.relaxed_run = 0;
+        }
+
+        if self.overloaded_run >= self.policy.trim_after {
+            self.overloaded_run = 0;
+            Some(LoadGuardAction::Trim)
+        } else if self.trim_count > 0 && self.relaxed_run >= self.policy.restore_after {
+            self.relaxed_run = 0;
+            Some(LoadGuardAction::Restore)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the policy allows another trim.
+    pub fn can_trim(&self) -> bool {
+        self.trim_count < self.policy.max_trims
+    }
+
+    #[allow(missing_docs)]
+    pub fn trimmed(&mut self) {
+        self.trim_count += 1;
+    }
+
+    #[allow(missing_docs)]
+    pub fn restored(&mut self) {
+        self.trim_count = self.trim_count.saturating_sub(1);
+    }
+}
+
 /// [Orchestrator] manages all [Entities](EntityObsolete) (controllers, effects, and
 /// instruments). It also manages their virtual patch cables, virtual MIDI
 /// cables, and control relationships. When you're ready to render a song, it
@@ -98,6 +723,151 @@ mod obsolete {
 
         #[serde(skip)]
         last_time_range: Range<MusicalTime>,
+
+        /// Scales musical time relative to wall time. 2.0 plays events twice
+        /// as fast; pitches are unaffected.
+        #[serde(skip)]
+        playback_rate: f64,
+
+        /// Subtrees that have been rendered to audio by freeze(), keyed by
+        /// the Uid of the sampler that replaced them.
+        #[serde(skip)]
+        frozen: FxHashMap<Uid, FrozenSubtree>,
+
+        /// Frames rendered since the Orchestrator was created, whether or not
+        /// it was performing. While stopped, controllers get their time ranges
+        /// from this so that interactive devices like arpeggiators keep
+        /// responding to external MIDI.
+        #[serde(skip)]
+        free_running_frames: u64,
+
+        /// Aux buses, in the order they were created.
+        #[serde(skip)]
+        aux_buses: Vec<AuxBus>,
+
+        /// Every entity that sends to an aux bus. Rebuilt by set_send().
+        #[serde(skip)]
+        send_source_uids: FxHashSet<Uid>,
+
+        /// Each send source's output for the current frame, captured during
+        /// gather_audio() so that the aux buses can use it.
+        #[serde(skip)]
+        send_taps: FxHashMap<Uid, StereoSample>,
+
+        /// Shifts every note routed through the Orchestrator.
+        #[serde(skip)]
+        transposer: Transposer,
+
+        /// Each entity's output for the most recently gathered frame.
+        #[serde(skip)]
+        output_levels: FxHashMap<Uid, StereoSample>,
+
+        /// The loudest sample on each channel of the main output in the most
+        /// recent tick.
+        #[serde(skip)]
+        main_output_level: StereoSample,
+
+        /// Entities that are bypassed, or fading in or out of bypass. Each
+        /// one's level is for the frame being rendered. See
+        /// advance_bypasses().
+        #[serde(skip)]
+        bypasses: FxHashMap<Uid, Bypass>,
+
+        /// How long, in seconds, each effect keeps sounding after its input
+        /// goes silent.
+        #[serde(skip)]
+        tail_lengths: FxHashMap<Uid, f64>,
+
+        /// The longest that a render continues past the end of the
+        /// performance to let tails ring out, in seconds.
+        #[serde(skip)]
+        release_window: f64,
+
+        /// External MIDI waiting for the frame it was played at, as
+        /// (free-running frame, channel, message), in frame order.
+        #[serde(skip)]
+        scheduled_midi: VecDeque<(u64, MidiChannel, MidiMessage)>,
+
+        /// Entities that are armed to record.
+        #[serde(skip)]
+        record_arms: FxHashMap<Uid, RecordArm>,
+
+        /// Live audio input that hasn't been rendered yet.
+        #[serde(skip)]
+        live_input: VecDeque<StereoSample>,
+
+        /// The length of the buffer that tick() was last asked to fill.
+        #[serde(skip)]
+        last_tick_len: usize,
+
+        /// Headphone crossfeed on the main output, or None when it's off.
+        #[serde(skip)]
+        crossfeed: Option<Crossfeed>,
+
+        /// A temporary tempo change from nudge_tempo().
+        #[serde(skip)]
+        nudge: Option<TempoNudge>,
+
+        /// Trims the project when rendering falls behind, or None when it's
+        /// off.
+        #[serde(skip)]
+        load_guard: Option<LoadGuard>,
+
+        /// About how long, in seconds, each effect takes to process a frame.
+        /// Measured only while the load guard is on.
+        #[serde(skip)]
+        effect_costs: FxHashMap<Uid, f64>,
+
+        /// Effects that the load guard has bypassed, oldest first.
+        #[serde(skip)]
+        load_trims: Vec<Uid>,
+
+        /// The entity that's soloed in place, if any. See audition().
+        #[serde(skip)]
+        audition_uid: Option<Uid>,
+
+        /// Frames of test tone played since the audition started.
+        #[serde(skip)]
+        audition_tone_frames: usize,
+
+        /// Where every entity's random seed comes from. It's saved with the
+        /// project, so the project renders the same way each time it's loaded.
+        #[serde(default)]
+        seed_bank: SeedBank,
+    }
+
+    /// An aux bus collects a share of the output of any number of sources,
+    /// runs the sum through its own effect chain, and adds the result to the
+    /// main mix. This is how a single reverb can serve several tracks.
+    #[derive(Debug)]
+    struct AuxBus {
+        uid: Uid,
+        /// Each source and how much of its output goes to the bus.
+        sends: Vec<(Uid, Normal)>,
+        /// The bus's own mixer followed by its effects, in processing order.
+        chain: Vec<Uid>,
+    }
+
+    /// A temporary change to the playback rate. See
+    /// [Orchestrator::nudge_tempo()].
+    #[derive(Debug)]
+    struct TempoNudge {
+        /// Multiplies the playback rate.
+        factor: f64,
+        frames_remaining: usize,
+    }
+
+    /// A subtree that [Orchestrator::freeze()] has rendered to audio. It
+    /// remembers what it replaced so that [Orchestrator::unfreeze()] can put
+    /// everything back. The audio itself is in the [FrozenSampler].
+    #[derive(Debug)]
+    struct FrozenSubtree {
+        /// The root of the subtree that this sampler replaced.
+        source_uid: Uid,
+        /// The entities that the root's output was patched into.
+        sink_uids: Vec<Uid>,
+        /// Automation that targeted the subtree, disabled while frozen.
+        disabled_control_links: Vec<ControlLink>,
     }
     impl Orchestrator {
         // TODO: prefix these to reserve internal ID namespace
@@ -106,6 +876,15 @@ mod obsolete {
         pub const BEAT_SEQUENCER_UVID: &str = "beat-sequencer";
         pub const METRONOME_UVID: &str = "metronome";
 
+        /// The MIDI controller number for All Notes Off.
+        const ALL_NOTES_OFF_CC: u8 = 123;
+
+        #[allow(missing_docs)]
+        pub const DEFAULT_RELEASE_WINDOW: f64 = 10.0;
+
+        /// The most that nudge_tempo() will speed up or slow down.
+        pub const MAX_NUDGE_PERCENT: f64 = 10.0;
+
         #[cfg(feature = "metrics")]
         fn install_entity_metric(&mut self, uvid: Option<&str>, uid: Uid) {
             let name = format!("entity {}", uvid.unwrap_or(format!("uid {uid}").as_str()));
@@ -126,6 +905,7 @@ mod obsolete {
                 .as_configurable_mut()
                 .update_sample_rate(self.clock.sample_rate());
             let uid = self.store.add(uvid, entity);
+            self.reseed_entity(uid);
 
             #[cfg(feature = "metrics")]
             self.install_entity_metric(Some(uvid), uid);
@@ -133,6 +913,37 @@ mod obsolete {
             uid
         }
 
+        // The entities whose output depends on a random sequence.
+        fn as_seeded_mut(entity: &mut EntityObsolete) -> Option<&mut dyn Seeded> {
+            match entity {
+                EntityObsolete::DrumSequencer(e) => Some(e.as_mut()),
+                EntityObsolete::SampleAndHoldLfo(e) => Some(e.as_mut()),
+                _ => None,
+            }
+        }
+
+        // Gives the entity its seed from the seed bank, if it uses one.
+        fn reseed_entity(&mut self, uid: Uid) {
+            let seed_bank = self.seed_bank;
+            if let Some(entity) = self.store.get_mut(uid).and_then(Self::as_seeded_mut) {
+                seed_bank.reseed(uid, entity);
+            }
+        }
+
+        /// The master seed that every entity's random seed is derived from.
+        pub fn master_seed(&self) -> u64 {
+            self.seed_bank.master_seed()
+        }
+
+        /// Changes the master seed and reseeds every entity from it.
+        pub fn set_master_seed(&mut self, master_seed: u64) {
+            self.seed_bank.set_master_seed(master_seed);
+            let uids: Vec<Uid> = self.store.iter().map(|(uid, _)| *uid).collect();
+            for uid in uids {
+                self.reseed_entity(uid);
+            }
+        }
+
         pub fn add(&mut self, entity: EntityObsolete) -> Uid {
             self.add_with_optional_uvid(entity, None)
         }
@@ -171,6 +982,69 @@ mod obsolete {
             self.store.get_uid(uvid)
         }
 
+        /// The human-readable ID that the entity was added with, if any.
+        pub fn get_uvid_by_uid(&self, uid: Uid) -> Option<&str> {
+            self.store.get_uvid(uid)
+        }
+
+        /// The entities whose audio output is patched into `input_uid`.
+        pub fn patch_sources(&self, input_uid: Uid) -> &[Uid] {
+            self.store
+                .patches(input_uid)
+                .map(|v| v.as_slice())
+                .unwrap_or_default()
+        }
+
+        /// The MIDI channel that the entity listens to, if any.
+        pub fn midi_channel_for(&self, uid: Uid) -> Option<MidiChannel> {
+            self.store.midi_channel_for(uid)
+        }
+
+        /// Whether this is one of the entities that every Orchestrator creates
+        /// for itself, rather than one that came from a project.
+        pub fn is_built_in(&self, uid: Uid) -> bool {
+            uid == self.main_mixer_uid
+                || uid == self.pattern_manager_uid
+                || uid == self.sequencer_uid
+                || uid == self.metronome_uid
+        }
+
+        /// Describes the entities and how they're connected in Graphviz DOT
+        /// format, for debugging. Patch cables are solid edges that point the
+        /// way audio flows, and control links are dashed edges labeled with
+        /// the target's control index. Try `dot -Tpng`.
+        pub fn to_dot(&self) -> String {
+            let mut uids: Vec<Uid> = self.store.iter().map(|(uid, _)| *uid).collect();
+            uids.sort_by_key(|uid| uid.0);
+
+            let mut dot = String::from("digraph orchestrator {\n    rankdir=LR;\n");
+            for uid in uids.iter() {
+                if let Some(entity) = self.store.get(*uid) {
+                    let label = match self.get_uvid_by_uid(*uid) {
+                        Some(uvid) => format!("{uvid}\\n{}", entity.name()),
+                        None => format!("{uid}\\n{}", entity.name()),
+                    };
+                    dot.push_str(&format!(
+                        "    \"{uid}\" [label=\"{}\"];\n",
+                        label.replace('"', "\\\"")
+                    ));
+                }
+            }
+            for sink_uid in uids.iter() {
+                for source_uid in self.patch_sources(*sink_uid) {
+                    dot.push_str(&format!("    \"{source_uid}\" -> \"{sink_uid}\";\n"));
+                }
+            }
+            for link in self.connections() {
+                dot.push_str(&format!(
+                    "    \"{}\" -> \"{}\" [style=dashed, label=\"{}\"];\n",
+                    link.source_uid, link.target_uid, link.control_index.0
+                ));
+            }
+            dot.push_str("}\n");
+            dot
+        }
+
         pub fn link_control_by_id(
             &mut self,
             source_uid: Uid,
@@ -243,11 +1117,36 @@ mod obsolete {
                 .unlink_control(controller_uid, target_uid, control_index);
         }
 
-        #[cfg(test)]
-        pub(crate) fn unlink_control_by_name(
+        /// Sets the entity's #[control] parameter named `param_name`, the way a
+        /// linked controller would.
+        pub fn set_param_by_name(
             &mut self,
-            controller_uid: Uid,
-            target_uid: Uid,
+            uid: Uid,
+            param_name: &str,
+            value: ControlValue,
+        ) -> anyhow::Result<()> {
+            let Some(entity) = self.store.get_mut(uid) else {
+                return Err(anyhow!("couldn't find entity ID {}", uid));
+            };
+            let Some(controllable) = entity.as_controllable_mut() else {
+                return Err(anyhow!("entity ID {} is not of a controllable type", uid));
+            };
+            let Some(control_index) = controllable.control_index_for_name(param_name) else {
+                return Err(anyhow!(
+                    "entity ID {} does not have a controllable parameter named `{}`",
+                    uid,
+                    param_name
+                ));
+            };
+            controllable.control_set_param_by_index(control_index, value);
+            Ok(())
+        }
+
+        #[cfg(test)]
+        pub(crate) fn unlink_control_by_name(
+            &mut self,
+            controller_uid: Uid,
+            target_uid: Uid,
             param_name: &str,
         ) {
             if let Some(target) = self.store.get(target_uid) {
@@ -324,6 +1223,245 @@ mod obsolete {
             Ok(())
         }
 
+        /// Renders the audio subtree rooted at `uid` (the entity and everything
+        /// patched into it) from the start of the song through its end (or the
+        /// end of the loop, if looping is enabled), and swaps in a
+        /// [FrozenSampler] that plays back the result. This saves CPU for heavy
+        /// chains. The sampler follows the song position, so it stays in sync
+        /// at any playback rate. Automation targeting the subtree is disabled
+        /// until unfreeze(). The transport is left where it was. Returns the
+        /// Uid of the sampler.
+        pub fn freeze(&mut self, uid: Uid) -> anyhow::Result<Uid> {
+            if uid == self.main_mixer_uid {
+                return Err(anyhow!("The main mixer can't be frozen"));
+            }
+            if self.frozen.contains_key(&uid) {
+                return Err(anyhow!(
+                    "Entity {uid} is a frozen sampler; unfreeze it first"
+                ));
+            }
+            if let Some(entity) = self.store.get(uid) {
+                if entity.as_is_instrument().is_none() && entity.as_is_effect().is_none() {
+                    return Err(anyhow!("Entity {uid} doesn't output audio"));
+                }
+            } else {
+                return Err(anyhow!("Couldn't find entity {uid}"));
+            }
+
+            let subtree_uids = self.subtree_uids(uid);
+            let samples = self.render_subtree(uid);
+
+            let sampler_uid = self.add(EntityObsolete::FrozenSampler(Box::new(
+                FrozenSampler::new_with(samples, self.sample_rate()),
+            )));
+            let sink_uids: Vec<Uid> = self
+                .store
+                .audio_sink_uid_to_source_uids
+                .iter()
+                .filter(|(_, source_uids)| source_uids.contains(&uid))
+                .map(|(sink_uid, _)| *sink_uid)
+                .collect();
+            for &sink_uid in &sink_uids {
+                self.store.unpatch(uid, sink_uid);
+                self.store.patch(sampler_uid, sink_uid);
+            }
+            if self.main_mixer_source_uids.remove(&uid) {
+                self.main_mixer_source_uids.insert(sampler_uid);
+            }
+
+            let disabled_control_links: Vec<ControlLink> = self
+                .store
+                .flattened_control_links()
+                .iter()
+                .filter(|link| subtree_uids.contains(&link.target_uid))
+                .cloned()
+                .collect();
+            for link in &disabled_control_links {
+                self.store
+                    .unlink_control(link.source_uid, link.target_uid, link.control_index);
+            }
+
+            self.frozen.insert(
+                sampler_uid,
+                FrozenSubtree {
+                    source_uid: uid,
+                    sink_uids,
+                    disabled_control_links,
+                },
+            );
+            Ok(sampler_uid)
+        }
+
+        /// Undoes freeze(), given the Uid of the sampler that it returned.
+        pub fn unfreeze(&mut self, sampler_uid: Uid) -> anyhow::Result<()> {
+            if let Some(frozen) = self.frozen.remove(&sampler_uid) {
+                for &sink_uid in &frozen.sink_uids {
+                    self.store.unpatch(sampler_uid, sink_uid);
+                    self.store.patch(frozen.source_uid, sink_uid);
+                }
+                if self.main_mixer_source_uids.remove(&sampler_uid) {
+                    self.main_mixer_source_uids.insert(frozen.source_uid);
+                }
+                for link in frozen.disabled_control_links {
+                    self.store
+                        .link_control(link.source_uid, link.target_uid, link.control_index);
+                }
+                self.store.remove(sampler_uid);
+                Ok(())
+            } else {
+                Err(anyhow!("{sampler_uid} isn't a frozen sampler"))
+            }
+        }
+
+        pub fn is_frozen(&self, uid: Uid) -> bool {
+            self.frozen.contains_key(&uid)
+        }
+
+        // Returns the given entity plus everything upstream of it.
+        fn subtree_uids(&self, uid: Uid) -> FxHashSet<Uid> {
+            let mut uids = FxHashSet::default();
+            let mut stack = vec![uid];
+            while let Some(uid) = stack.pop() {
+                if uids.insert(uid) {
+                    if let Some(source_uids) = self.store.patches(uid) {
+                        stack.extend(source_uids);
+                    }
+                }
+            }
+            uids
+        }
+
+        // Runs the whole performance, but collects audio only from the given
+        // subtree. Controllers still do their work so that the subtree hears
+        // the same MIDI and automation it would during a normal performance.
+        // The render is at normal speed, whatever the playback rate, and the
+        // transport goes back to where it was afterward.
+        fn render_subtree(&mut self, uid: Uid) -> Vec<StereoSample> {
+            let position = self.position();
+            let was_performing = self.is_performing;
+            let playback_rate = self.playback_rate;
+            let nudge = self.nudge.take();
+            self.playback_rate = 1.0;
+
+            let mut buffer = [StereoSample::SILENCE; 64];
+            let mut rendered = Vec::default();
+            self.skip_to_start();
+            self.play();
+            loop {
+                let (_commands, ticks_completed) = self.handle_work(buffer.len());
+                self.gather_audio_from(uid, 0, &mut buffer);
+                self.clock.tick_batch(ticks_completed);
+                rendered.extend(&buffer[0..ticks_completed]);
+                if ticks_completed < buffer.len() {
+                    break;
+                }
+                if self.is_loop_enabled {
+                    if let Some(range) = self.loop_range.as_ref() {
+                        if self.clock.beats() * self.rate() >= range.end.0 {
+                            break;
+                        }
+                    }
+                }
+            }
+            self.stop();
+            self.playback_rate = playback_rate;
+            self.nudge = nudge;
+            self.seek(position);
+            if was_performing {
+                self.play();
+            }
+            rendered
+        }
+
+        /// Creates an aux bus and returns its Uid. Route audio to it with
+        /// set_send(), and give it effects with add_bus_effect().
+        pub fn create_bus(&mut self) -> Uid {
+            let uid = self.add(EntityObsolete::Mixer(Box::new(Mixer::default())));
+            self.aux_buses.push(AuxBus {
+                uid,
+                sends: Default::default(),
+                chain: vec![uid],
+            });
+            uid
+        }
+
+        /// Sends `amount` of `source_uid`'s output to the bus. The source's
+        /// regular patching is unaffected. An amount of zero removes the send.
+        pub fn set_send(
+            &mut self,
+            source_uid: Uid,
+            bus_uid: Uid,
+            amount: Normal,
+        ) -> anyhow::Result<()> {
+            if let Some(source) = self.store.get(source_uid) {
+                if source.as_is_instrument().is_none() && source.as_is_effect().is_none() {
+                    return Err(anyhow!("Entity {source_uid} doesn't output audio"));
+                }
+            } else {
+                return Err(anyhow!("Couldn't find source_uid {source_uid}"));
+            }
+            let Some(bus) = self.aux_buses.iter_mut().find(|b| b.uid == bus_uid) else {
+                return Err(anyhow!("{bus_uid} isn't an aux bus"));
+            };
+            bus.sends.retain(|(uid, _)| *uid != source_uid);
+            if amount.value() > 0.0 {
+                bus.sends.push((source_uid, amount));
+            }
+            self.send_source_uids = self
+                .aux_buses
+                .iter()
+                .flat_map(|bus| bus.sends.iter().map(|(uid, _)| *uid))
+                .collect();
+            Ok(())
+        }
+
+        /// Appends an effect to the end of the bus's effect chain.
+        pub fn add_bus_effect(&mut self, bus_uid: Uid, effect_uid: Uid) -> anyhow::Result<()> {
+            if let Some(effect) = self.store.get(effect_uid) {
+                if effect.as_is_effect().is_none() {
+                    return Err(anyhow!("Entity {effect_uid} doesn't transform audio"));
+                }
+            } else {
+                return Err(anyhow!("Couldn't find effect_uid {effect_uid}"));
+            }
+            let Some(bus) = self.aux_buses.iter_mut().find(|b| b.uid == bus_uid) else {
+                return Err(anyhow!("{bus_uid} isn't an aux bus"));
+            };
+            bus.chain.push(effect_uid);
+            Ok(())
+        }
+
+        fn is_send_source(&self, uid: Uid) -> bool {
+            self.send_source_uids.contains(&uid)
+        }
+
+        // Runs each aux bus on the current frame's send taps, and returns the
+        // sum of their outputs.
+        fn process_aux_buses(&mut self) -> StereoSample {
+            let mut total = StereoSample::default();
+            for bus_index in 0..self.aux_buses.len() {
+                let mut bus_sum = StereoSample::default();
+                for &(source_uid, amount) in self.aux_buses[bus_index].sends.iter() {
+                    if let Some(tap) = self.send_taps.get(&source_uid) {
+                        let amount = amount.value();
+                        bus_sum +=
+                            StereoSample(Sample(tap.0 .0 * amount), Sample(tap.1 .0 * amount));
+                    }
+                }
+                for chain_index in 0..self.aux_buses[bus_index].chain.len() {
+                    let uid = self.aux_buses[bus_index].chain[chain_index];
+                    if let Some(entity) = self.store.get_mut(uid) {
+                        if let Some(effect) = entity.as_is_effect_mut() {
+                            bus_sum = effect.transform_audio(bus_sum);
+                        }
+                    }
+                }
+                total += bus_sum;
+            }
+            self.send_taps.clear();
+            total
+        }
+
         #[allow(dead_code)]
         pub(crate) fn unpatch(&mut self, output_uid: Uid, input_uid: Uid) -> anyhow::Result<()> {
             if input_uid == self.main_mixer_uid {
@@ -365,17 +1503,85 @@ mod obsolete {
         // marker pops up, eval with the current sum (nodes are effects, so they
         // take an input), then add to the running sum.
         fn gather_audio(&mut self, samples: &mut [StereoSample]) {
-            for sample in samples {
+            // Forget entities that have since been disconnected.
+            self.output_levels.clear();
+            self.gather_audio_from(self.main_mixer_uid, 0, samples);
+        }
+
+        // Advances every bypass crossfade in progress by one frame. This
+        // happens once per frame, before the traversal, because an entity
+        // might be visited more than once in a frame, or not at all. An entity
+        // whose fade back in finishes is dropped, which leaves it at the
+        // default level of 1.0.
+        fn advance_bypasses(&mut self) {
+            self.bypasses.retain(|_, bypass| {
+                bypass.next_level();
+                !bypass.is_settled_in()
+            });
+        }
+
+        // Tells each frozen sampler where the song is for the given frame. The
+        // audio was rendered at normal speed, so the playback rate scales the
+        // position. While stopped, the samplers are silent.
+        fn position_frozen_samplers(&mut self, frame: usize) {
+            if self.frozen.is_empty() {
+                return;
+            }
+            let position = self
+                .is_performing
+                .then(|| frame as f64 * self.rate() / self.sample_rate().value() as f64);
+            for uid in self.frozen.keys() {
+                if let Some(EntityObsolete::FrozenSampler(sampler)) = self.store.get_mut(*uid) {
+                    sampler.set_position(position);
+                }
+            }
+        }
+
+        // Returns how much of the entity's processed signal to use for the
+        // current frame.
+        fn bypass_level(&self, uid: Uid) -> f64 {
+            self.bypasses.get(&uid).map_or(1.0, Bypass::level)
+        }
+
+        /// Same as gather_audio(), but starting at an arbitrary entity rather
+        /// than the main mixer. `frame_offset` is where `samples` starts
+        /// within the current buffer.
+        fn gather_audio_from(
+            &mut self,
+            root_uid: Uid,
+            frame_offset: usize,
+            samples: &mut [StereoSample],
+        ) {
+            let start_frame = self.clock.frames() + frame_offset;
+
+            // Aux buses feed the main mix, so they don't apply when rendering
+            // an arbitrary subtree.
+            let is_gathering_sends = root_uid == self.main_mixer_uid && !self.aux_buses.is_empty();
+
+            // Likewise, live input is consumed only by a full render.
+            let is_taking_input = root_uid == self.main_mixer_uid && !self.record_arms.is_empty();
+
+            // The load guard needs to know which effects are expensive. Timing
+            // the first frame of each segment is enough to rank them.
+            let is_measuring_costs = self.load_guard.is_some();
+            for (i, sample) in samples.iter_mut().enumerate() {
+                self.advance_bypasses();
+                self.position_frozen_samplers(start_frame + i);
+                let live_input = if is_taking_input {
+                    self.next_live_input()
+                } else {
+                    StereoSample::default()
+                };
                 enum StackEntry {
                     ToVisit(Uid),
-                    CollectResultFor(Uid, StereoSample),
+                    CollectResultFor(Uid, StereoSample, f64),
                 }
                 #[cfg(feature = "metrics")]
                 let gather_audio_start_time = self.metrics.gather_audio_fn_timer.start();
 
                 let mut stack = Vec::new();
                 let mut sum = StereoSample::default();
-                stack.push(StackEntry::ToVisit(self.main_mixer_uid));
+                stack.push(StackEntry::ToVisit(root_uid));
 
                 #[cfg(feature = "metrics")]
                 self.metrics.mark_stack_loop_entry.mark();
@@ -391,6 +1597,7 @@ mod obsolete {
                             // hope was to avoid an entity lookup. But we have to look
                             // up the patch cables. So I think it's six of one, a
                             // half-dozen of another.
+                            let bypass_level = self.bypass_level(uid);
                             if let Some(entity) = self.store.get_mut(uid) {
                                 // If it's a leaf, eval it now and add it to the
                                 // running sum.
@@ -407,13 +1614,29 @@ mod obsolete {
                                     #[cfg(not(feature = "metrics"))]
                                     entity.tick(1);
 
-                                    sum += entity.value();
+                                    // A bypassed instrument keeps running so
+                                    // that it's in the right state when it
+                                    // comes back, but it isn't heard.
+                                    let value = Bypass::mix(
+                                        bypass_level,
+                                        StereoSample::default(),
+                                        entity.value(),
+                                    );
+                                    sum += value;
+                                    self.output_levels.insert(uid, value);
+                                    if is_gathering_sends && self.is_send_source(uid) {
+                                        self.send_taps.insert(uid, value);
+                                    }
                                 } else if entity.as_is_effect().is_some() {
                                     // If it's a node, push its children on the stack,
                                     // then evaluate the result.
 
                                     // Tell us to process sum.
-                                    stack.push(StackEntry::CollectResultFor(uid, sum));
+                                    stack.push(StackEntry::CollectResultFor(
+                                        uid,
+                                        sum,
+                                        bypass_level,
+                                    ));
                                     sum = StereoSample::default();
                                     if let Some(source_uids) = self.store.patches(uid) {
                                         for &source_uid in &source_uids.to_vec() {
@@ -435,25 +1658,55 @@ mod obsolete {
                         // lookup for instruments and controllers. And if we're going to
                         // optimize for avoiding lookups, we might as well unroll the
                         // whole tree and zip through it, as mentioned earlier.
-                        StackEntry::CollectResultFor(uid, accumulated_sum) => {
+                        StackEntry::CollectResultFor(uid, accumulated_sum, bypass_level) => {
+                            // An armed effect that's monitoring hears the
+                            // live input along with whatever is patched in.
+                            if self
+                                .record_arms
+                                .get(&uid)
+                                .is_some_and(|arm| arm.is_monitoring)
+                            {
+                                sum += live_input;
+                            }
                             if let Some(entity) = self.store.get_mut(uid) {
                                 if let Some(entity) = entity.as_is_effect_mut() {
-                                    #[cfg(feature = "metrics")]
-                                    let entity_value = if let Some(timer) =
-                                        self.metrics.entity_audio_times.get(&uid)
-                                    {
-                                        let start_time = timer.start();
-                                        let transformed_audio = entity.transform_audio(sum);
-                                        timer.stop(start_time);
-                                        transformed_audio
+                                    // A fully bypassed effect passes its input
+                                    // straight through without running.
+                                    let entity_value = if bypass_level == 0.0 {
+                                        sum
                                     } else {
-                                        entity.transform_audio(sum)
-                                    };
+                                        let cost_start_time =
+                                            (is_measuring_costs && i == 0).then(Instant::now);
+
+                                        #[cfg(feature = "metrics")]
+                                        let transformed_audio = if let Some(timer) =
+                                            self.metrics.entity_audio_times.get(&uid)
+                                        {
+                                            let start_time = timer.start();
+                                            let transformed_audio = entity.transform_audio(sum);
+                                            timer.stop(start_time);
+                                            transformed_audio
+                                        } else {
+                                            entity.transform_audio(sum)
+                                        };
+
+                                        #[cfg(not(feature = "metrics"))]
+                                        let transformed_audio = entity.transform_audio(sum);
 
-                                    #[cfg(not(feature = "metrics"))]
-                                    let entity_value = entity.transform_audio(sum);
+                                        if let Some(cost_start_time) = cost_start_time {
+                                            let cost = cost_start_time.elapsed().as_secs_f64();
+                                            let average =
+                                                self.effect_costs.entry(uid).or_insert(cost);
+                                            *average += (cost - *average) * 0.1;
+                                        }
+                                        Bypass::mix(bypass_level, sum, transformed_audio)
+                                    };
 
                                     sum = accumulated_sum + entity_value;
+                                    self.output_levels.insert(uid, entity_value);
+                                    if is_gathering_sends && self.is_send_source(uid) {
+                                        self.send_taps.insert(uid, entity_value);
+                                    }
                                 }
                             }
                         }
@@ -465,6 +1718,9 @@ mod obsolete {
                     .gather_audio_fn_timer
                     .stop(gather_audio_start_time);
 
+                if is_gathering_sends {
+                    sum += self.process_aux_buses();
+                }
                 *sample = sum;
             }
         }
@@ -537,6 +1793,30 @@ mod obsolete {
                 loop_range: Default::default(),
                 is_loop_enabled: Default::default(),
                 last_time_range: Default::default(),
+                playback_rate: 1.0,
+                frozen: Default::default(),
+                free_running_frames: Default::default(),
+                aux_buses: Default::default(),
+                send_source_uids: Default::default(),
+                send_taps: Default::default(),
+                transposer: Default::default(),
+                output_levels: Default::default(),
+                main_output_level: Default::default(),
+                bypasses: Default::default(),
+                tail_lengths: Default::default(),
+                release_window: Self::DEFAULT_RELEASE_WINDOW,
+                scheduled_midi: Default::default(),
+                record_arms: Default::default(),
+                live_input: Default::default(),
+                last_tick_len: Default::default(),
+                crossfeed: Default::default(),
+                nudge: Default::default(),
+                load_guard: Default::default(),
+                effect_costs: Default::default(),
+                load_trims: Default::default(),
+                audition_uid: Default::default(),
+                audition_tone_frames: Default::default(),
+                seed_bank: SeedBank::new_random(),
 
                 gui: Default::default(),
             };
@@ -559,6 +1839,7 @@ mod obsolete {
                 r.metronome_uid = r.add_with_uvid(
                     EntityObsolete::Metronome(Box::new(Metronome::new_with(&MetronomeParams {
                         bpm: r.bpm(),
+                        dca: DcaParams::default(),
                     }))),
                     Self::METRONOME_UVID,
                 );
@@ -597,7 +1878,10 @@ mod obsolete {
                             }
                         },
                         GrooveInput::MidiFromExternal(channel, message) => {
-                            self.broadcast_midi_messages(&[(channel, message)]);
+                            self.handle_external_midi(self.free_running_frames, channel, message);
+                        }
+                        GrooveInput::MidiFromExternalAt(frame, channel, message) => {
+                            self.schedule_midi_from_external(frame, channel, message);
                         }
                         GrooveInput::AddControlLink(link) => {
                             // The UI has asked us to link a control.
@@ -615,9 +1899,27 @@ mod obsolete {
                                 link.control_index,
                             );
                         }
+                        GrooveInput::SetParam(uid, param_name, value) => {
+                            if let Err(e) = self.set_param_by_name(uid, &param_name, value) {
+                                eprintln!("Warning: {e}");
+                            }
+                        }
+                        GrooveInput::Patch(output_uid, input_uid) => {
+                            if let Err(e) = self.patch(output_uid, input_uid) {
+                                eprintln!("Warning: {e}");
+                            }
+                        }
+                        GrooveInput::Audition(Some(uid)) => {
+                            if let Err(e) = self.audition(uid) {
+                                eprintln!("Warning: {e}");
+                            }
+                        }
+                        GrooveInput::Audition(None) => self.end_audition(),
                         GrooveInput::Play => self.play(),
                         GrooveInput::Stop => self.stop(),
                         GrooveInput::SkipToStart => self.skip_to_start(),
+                        GrooveInput::Seek(time) => self.seek(time),
+                        GrooveInput::MidiPanic => unhandled_commands.push(self.midi_panic()),
                         GrooveInput::SetSampleRate(sample_rate) => {
                             self.update_sample_rate(sample_rate)
                         }
@@ -630,16 +1932,20 @@ mod obsolete {
         // Call every Controller's work() and gather their responses.
         fn handle_work(&mut self, tick_count: usize) -> (Response<GrooveEvent>, usize) {
             let uids: Vec<Uid> = self.store.controller_uids().collect();
-            let time_start = MusicalTime::new_with_units(MusicalTime::frames_to_units(
-                Tempo::from(self.bpm()),
-                SampleRate::from(self.sample_rate()),
-                self.clock.frames(),
-            ));
-            let mut time_end = MusicalTime::new_with_units(MusicalTime::frames_to_units(
-                Tempo::from(self.bpm()),
+            let start_frame = if self.is_performing {
+                self.clock.frames() as u64
+            } else {
+                self.free_running_frames
+            };
+            // The Clock doesn't know about the playback rate, so convert with
+            // a Transport at the effective tempo, which rounds exactly as the
+            // new engine does.
+            let transport = Transport::new_with(
+                Tempo::from(self.effective_bpm()),
                 SampleRate::from(self.sample_rate()),
-                self.clock.frames() + tick_count,
-            ));
+            );
+            let time_start = transport.frames_to_musical_time(start_frame);
+            let mut time_end = transport.frames_to_musical_time(start_frame + tick_count as u64);
             if time_start == time_end {
                 time_end = time_start + MusicalTime::new_with_units(1);
             }
@@ -711,9 +2017,86 @@ mod obsolete {
             &mut self,
             channel_message_tuples: &[(MidiChannel, MidiMessage)],
         ) {
-            let mut v = Vec::from(channel_message_tuples);
+            let v = channel_message_tuples
+                .iter()
+                .filter_map(|(channel, message)| {
+                    self.transposer
+                        .transpose(*channel, *message)
+                        .map(|message| (*channel, message))
+                })
+                .collect();
+            self.deliver_midi_messages(v);
+        }
+
+        // Broadcasts every scheduled external MIDI message that's due at or
+        // before the given free-running frame.
+        fn deliver_scheduled_midi(&mut self, frame: u64) {
+            while let Some((scheduled_frame, channel, message)) = self
+                .scheduled_midi
+                .front()
+                .filter(|(f, _, _)| *f <= frame)
+                .copied()
+            {
+                self.scheduled_midi.pop_front();
+                self.handle_external_midi(scheduled_frame, channel, message);
+            }
+        }
+
+        // Broadcasts a message from outside Groove. Entities that are armed to
+        // record capture it, and those that are monitoring hear it whatever
+        // channel they listen to.
+        fn handle_external_midi(&mut self, frame: u64, channel: MidiChannel, message: MidiMessage) {
+            if self.record_arms.is_empty() {
+                self.broadcast_midi_messages(&[(channel, message)]);
+                return;
+            }
+            let mut monitoring_uids = Vec::default();
+            for (uid, arm) in self.record_arms.iter_mut() {
+                if arm.takes_midi {
+                    arm.midi.push((frame, channel, message));
+                    if arm.is_monitoring {
+                        monitoring_uids.push(*uid);
+                    }
+                }
+            }
+            let Some(message) = self.transposer.transpose(channel, message) else {
+                return;
+            };
+            self.deliver_midi_messages(vec![(channel, message)]);
+
+            let receiver_uids = self.store.midi_receivers(&channel).clone();
+            for uid in monitoring_uids {
+                if receiver_uids.contains(&uid) {
+                    continue;
+                }
+                let mut responses = Vec::default();
+                if let Some(e) = self.store.get_mut(uid) {
+                    if let Some(e) = e.as_handles_midi_mut() {
+                        e.handle_midi_message(channel, message, &mut |channel, message| {
+                            responses.push((channel, message));
+                        });
+                    }
+                }
+                self.deliver_midi_messages(responses);
+            }
+        }
+
+        // Takes the next frame of live input, capturing it for every entity
+        // that's armed to record.
+        fn next_live_input(&mut self) -> StereoSample {
+            let input = self.live_input.pop_front().unwrap_or_default();
+            for arm in self.record_arms.values_mut() {
+                arm.audio.push(input);
+            }
+            input
+        }
+
+        // Like broadcast_midi_messages(), but without transposing. Responses
+        // are delivered as-is, because they're reactions to notes that were
+        // already transposed.
+        fn deliver_midi_messages(&mut self, mut v: Vec<(MidiChannel, MidiMessage)>) {
             while let Some((channel, message)) = v.pop() {
-                if let Some(responses) = self.broadcast_midi_message(channel, message) {
+                if let Some(responses) = self.deliver_midi_message(channel, message) {
                     v.extend(responses);
                 }
             }
@@ -723,6 +2106,15 @@ mod obsolete {
             &mut self,
             channel: MidiChannel,
             message: MidiMessage,
+        ) -> Option<Vec<(MidiChannel, MidiMessage)>> {
+            let message = self.transposer.transpose(channel, message)?;
+            self.deliver_midi_message(channel, message)
+        }
+
+        fn deliver_midi_message(
+            &mut self,
+            channel: MidiChannel,
+            message: MidiMessage,
         ) -> Option<Vec<(MidiChannel, MidiMessage)>> {
             let receiver_uids = self.store.midi_receivers(&channel).clone();
             if receiver_uids.is_empty() {
@@ -805,17 +2197,125 @@ mod obsolete {
             buffer: &mut [StereoSample],
             quiet: bool,
         ) -> anyhow::Result<Performance> {
+            self.run_performance_for(buffer, quiet, None)
+        }
+
+        /// Like run_performance(), but stops after `max_frames` frames if the
+        /// performance hasn't already ended by then. A performance with an
+        /// enabled loop never ends on its own, so offline renders of looping
+        /// projects need either a limit or the loop disabled.
+        ///
+        /// The render continues past the end of the performance only as long
+        /// as effects' advertised tails need it to. See set_tail_length().
+        pub fn run_performance_for(
+            &mut self,
+            buffer: &mut [StereoSample],
+            quiet: bool,
+            max_frames: Option<usize>,
+        ) -> anyhow::Result<Performance> {
+            let options = self.default_render_options();
+            self.run_performance_with(buffer, quiet, max_frames, &options)
+        }
+
+        /// The options that run_performance_for() uses: the defaults, with a
+        /// tail long enough for the project's effects.
+        pub fn default_render_options(&self) -> RenderOptions {
+            RenderOptions {
+                max_tail: self.advertised_tail_length(),
+                ..Default::default()
+            }
+        }
+
+        /// Like run_performance_for(), but after the performance ends, keeps
+        /// rendering as `options` says so that the final notes' decay isn't
+        /// chopped off.
+        pub fn run_performance_with(
+            &mut self,
+            buffer: &mut [StereoSample],
+            quiet: bool,
+            max_frames: Option<usize>,
+            options: &RenderOptions,
+        ) -> anyhow::Result<Performance> {
+            self.perform(buffer, quiet, max_frames, options, None)
+        }
+
+        /// Renders only `range` of the song, as when bouncing a selection.
+        ///
+        /// The song is evaluated as if it began at `range.start`. Seeking
+        /// there silences everything, so a note that started before the range
+        /// and is still held isn't heard, which is also what happens each time
+        /// a loop of the same range comes back around. Its note-off, if it
+        /// falls inside the range, finds nothing to stop. At the end of the
+        /// range, controllers are stopped so that the notes they're holding
+        /// are released, and rendering continues as `options` says so that
+        /// those releases and the effects' tails aren't cut off. The project's
+        /// loop is ignored.
+        pub fn run_performance_range(
+            &mut self,
+            buffer: &mut [StereoSample],
+            quiet: bool,
+            range: &Range<MusicalTime>,
+            options: &RenderOptions,
+        ) -> anyhow::Result<Performance> {
+            if range.end <= range.start {
+                return Err(anyhow!("Render range {range:?} is empty"));
+            }
+            let beats = (range.end.total_units() - range.start.total_units()) as f64
+                / MusicalTime::UNITS_IN_BEAT as f64;
+            let seconds = beats * 60.0 / self.effective_bpm();
+            let frames = (seconds * self.sample_rate().value() as f64).round() as usize;
+
+            let is_loop_enabled = self.is_loop_enabled;
+            self.is_loop_enabled = false;
+            let performance = self.perform(buffer, quiet, Some(frames), options, Some(range.start));
+            self.is_loop_enabled = is_loop_enabled;
+            performance
+        }
+
+        // The body of run_performance_with() and run_performance_range(). If
+        // `range_start` is set, then `max_frames` is the length of the range,
+        // and the tail follows it rather than being cut off by it.
+        fn perform(
+            &mut self,
+            buffer: &mut [StereoSample],
+            quiet: bool,
+            max_frames: Option<usize>,
+            options: &RenderOptions,
+            range_start: Option<MusicalTime>,
+        ) -> anyhow::Result<Performance> {
+            if max_frames.is_none() && self.is_loop_enabled && self.loop_range.is_some() {
+                return Err(anyhow!(
+                    "Project has a loop enabled; disable it or specify a duration"
+                ));
+            }
             let sample_rate = self.clock.sample_rate();
             let mut tick_count = 0;
             let performance = Performance::new_with(sample_rate);
+
+            // Crossfeed is for listening on headphones. It doesn't belong in
+            // an export. Nor does anything the load guard would trim, since
+            // an export can take as long as it needs.
+            let crossfeed = self.crossfeed.take();
+            let load_guard = self.load_guard.take();
             let progress_indicator_quantum: usize = sample_rate.value() / 2;
             let mut next_progress_indicator: usize = progress_indicator_quantum;
 
             self.skip_to_start();
+            if let Some(start) = range_start {
+                self.seek(start);
+            }
             self.play();
+            let mut leftover = None;
             loop {
+                let buffer_len = match max_frames {
+                    Some(max_frames) => buffer.len().min(max_frames - tick_count),
+                    None => buffer.len(),
+                };
+                if buffer_len == 0 {
+                    break;
+                }
                 // If we want external MIDI to work here, then we need to figure out what to do with commands.
-                let (_commands, ticks_completed) = self.tick(buffer);
+                let (_commands, ticks_completed) = self.tick(&mut buffer[..buffer_len]);
                 if next_progress_indicator <= tick_count {
                     if !quiet {
                         print!(".");
@@ -824,15 +2324,69 @@ mod obsolete {
                     next_progress_indicator += progress_indicator_quantum;
                 }
                 tick_count += ticks_completed;
-                if ticks_completed < buffer.len() {
+                for sample in buffer[..ticks_completed].iter() {
+                    performance.worker.push(*sample);
+                }
+                if ticks_completed < buffer_len {
+                    leftover = Some(ticks_completed..buffer_len);
                     break;
                 }
-                for (i, sample) in buffer.iter().enumerate() {
-                    if i < ticks_completed {
-                        performance.worker.push(*sample);
-                    } else {
-                        break;
+            }
+
+            // A range ends wherever it ends, not when the performance does, so
+            // stop the controllers to release whatever they're holding. The
+            // tail starts with a fresh buffer.
+            if range_start.is_some() && leftover.is_none() {
+                self.stop();
+                leftover = Some(0..0);
+            }
+
+            // The performance is over, but reverbs and delays might still be
+            // ringing. tick() rendered the rest of the last buffer anyway, so
+            // the tail picks up from there.
+            if let Some(mut pending) = leftover {
+                let frames_per_second = sample_rate.value() as f64;
+                let mut max_tail_frames = (options.max_tail.max(0.0) * frames_per_second) as usize;
+                if let (Some(max_frames), None) = (max_frames, range_start) {
+                    max_tail_frames = max_tail_frames.min(max_frames - tick_count);
+                }
+                let silence_window_frames =
+                    ((RenderOptions::SILENCE_WINDOW * frames_per_second) as usize).max(1);
+                let mut tail = Vec::default();
+                let mut quiet_frames = 0;
+                'tail: while tail.len() < max_tail_frames {
+                    for sample in buffer[pending.clone()].iter() {
+                        if tail.len() >= max_tail_frames {
+                            break 'tail;
+                        }
+                        tail.push(*sample);
+                        if options.is_below_threshold(sample) {
+                            quiet_frames += 1;
+                            if quiet_frames >= silence_window_frames {
+                                break 'tail;
+                            }
+                        } else {
+                            quiet_frames = 0;
+                        }
                     }
+                    let _ = self.tick(buffer);
+                    pending = 0..buffer.len();
+                }
+
+                // Leave off the trailing silence.
+                let audible_len = tail.len() - quiet_frames;
+                for sample in tail[..audible_len].iter() {
+                    performance.worker.push(*sample);
+                }
+            }
+            if let Some(ceiling) = options.limiter_ceiling {
+                let mut samples = Vec::with_capacity(performance.worker.len());
+                while let Some(sample) = performance.worker.pop() {
+                    samples.push(sample);
+                }
+                let mut limiter = MasterLimiter::new_with(ceiling, sample_rate);
+                for sample in limiter.process_offline(&samples) {
+                    performance.worker.push(sample);
                 }
             }
             if !quiet {
@@ -842,9 +2396,35 @@ mod obsolete {
             if self.should_output_perf {
                 self.metrics.report();
             }
+            self.crossfeed = crossfeed;
+            self.load_guard = load_guard;
             Ok(performance)
         }
 
+        /// Renders `duration` of the project as fast as possible, discarding
+        /// the audio, and reports how that compared to realtime. With the
+        /// `metrics` feature, per-entity timings are printed as well.
+        pub fn benchmark(&mut self, duration: MusicalTime) -> anyhow::Result<BenchmarkReport> {
+            let beats = duration.total_units() as f64 / MusicalTime::UNITS_IN_BEAT as f64;
+            let seconds = beats * 60.0 / self.effective_bpm();
+            let max_frames = (seconds * self.sample_rate().value() as f64).round() as usize;
+
+            let should_output_perf = self.should_output_perf;
+            self.should_output_perf = true;
+            let mut buffer = [StereoSample::SILENCE; 64];
+            let start_instant = Instant::now();
+            let performance = self.run_performance_for(&mut buffer, true, Some(max_frames));
+            let wall_seconds = start_instant.elapsed().as_secs_f64();
+            self.should_output_perf = should_output_perf;
+
+            let performance = performance?;
+            Ok(BenchmarkReport {
+                audio_seconds: performance.worker.len() as f64
+                    / performance.sample_rate.value() as f64,
+                wall_seconds,
+            })
+        }
+
         /// Runs the whole world for the given number of frames, returning each
         /// frame's output as a StereoSample.
         ///
@@ -854,9 +2434,47 @@ mod obsolete {
         /// Returns the actual number of frames filled. If this number is shorter
         /// than the slice length, then the performance is complete.
         pub fn tick(&mut self, samples: &mut [StereoSample]) -> (Response<GrooveEvent>, usize) {
+            let start_instant = self.load_guard.is_some().then(Instant::now);
             let tick_count = samples.len();
+            self.last_tick_len = tick_count;
             let (commands, ticks_completed) = self.handle_work(tick_count);
-            self.gather_audio(samples);
+
+            // Audio is rendered whether or not we're performing, so that notes
+            // from external MIDI sound (and stop sounding) while stopped.
+            //
+            // Scheduled external MIDI is delivered right before the frame it
+            // was played at, so the buffer is rendered in segments that end
+            // wherever the next scheduled message lands.
+            self.output_levels.clear();
+            let mut start = 0;
+            while start < tick_count {
+                self.deliver_scheduled_midi(self.free_running_frames + start as u64);
+                let end = self
+                    .scheduled_midi
+                    .front()
+                    .map_or(tick_count, |(frame, _, _)| {
+                        (frame - self.free_running_frames).min(tick_count as u64) as usize
+                    });
+                match self.audition_uid {
+                    Some(uid) => self.render_audition(uid, start, &mut samples[start..end]),
+                    None => {
+                        self.gather_audio_from(self.main_mixer_uid, start, &mut samples[start..end])
+                    }
+                }
+                start = end;
+            }
+            self.free_running_frames += tick_count as u64;
+            if let Some(crossfeed) = self.crossfeed.as_mut() {
+                for sample in samples.iter_mut() {
+                    *sample = crossfeed.process(*sample);
+                }
+            }
+            self.main_output_level = samples.iter().fold(StereoSample::default(), |peak, s| {
+                StereoSample(
+                    Sample(peak.0 .0.max(s.0 .0.abs())),
+                    Sample(peak.1 .0.max(s.1 .0.abs())),
+                )
+            });
 
             if self.is_performing {
                 self.clock.tick_batch(ticks_completed);
@@ -864,15 +2482,26 @@ mod obsolete {
             if ticks_completed < tick_count {
                 self.is_performing = false;
             }
+            self.advance_nudge(tick_count);
 
             if self.is_loop_enabled {
                 if let Some(range) = self.loop_range.as_ref() {
-                    if self.clock.beats() >= range.end.0 {
-                        self.clock.seek_beats(range.start.0);
+                    // The clock counts beats at the nominal tempo, so scale
+                    // them to get the position in the song.
+                    if self.clock.beats() * self.rate() >= range.end.0 {
+                        self.clock.seek_beats(range.start.0 / self.rate());
                     }
                 }
             }
 
+            if let Some(start_instant) = start_instant {
+                let budget = tick_count as f64 / self.sample_rate().value() as f64;
+                if budget > 0.0 {
+                    let load = start_instant.elapsed().as_secs_f64() / budget;
+                    let load_events = self.report_load(load);
+                    return (Response::batch([commands, load_events]), ticks_completed);
+                }
+            }
             (commands, ticks_completed)
         }
 
@@ -896,45 +2525,597 @@ mod obsolete {
 
         pub fn set_bpm(&mut self, bpm: ParameterType) {
             self.clock.set_bpm(bpm);
+            self.store.update_tempo(Tempo::from(self.effective_bpm()));
         }
 
-        pub fn clock(&self) -> &Clock {
-            &self.clock
+        /// Speeds up or slows down the performance without changing pitch. The
+        /// whole musical timeline (sequencers, tempo-synced LFOs, loops)
+        /// stretches to match, but instruments keep generating audio at their
+        /// usual frequencies. 1.0 is normal speed.
+        pub fn set_playback_rate(&mut self, playback_rate: f64) {
+            if playback_rate <= 0.0 || !playback_rate.is_finite() {
+                eprintln!("Warning: ignoring invalid playback rate {playback_rate}");
+                return;
+            }
+            self.change_rate(|o| o.playback_rate = playback_rate);
         }
 
-        #[allow(dead_code)]
-        fn handle_control(&mut self, uid: Uid, param_id: ControlIndex, value: ControlValue) {
-            if let Some(entity) = self.store.get_mut(uid) {
-                if let Some(controllable) = entity.as_controllable_mut() {
-                    controllable.control_set_param_by_index(param_id, value);
-                }
+        pub fn playback_rate(&self) -> f64 {
+            self.playback_rate
+        }
+
+        /// Speeds up or slows down by `percent` for `duration` of wall-clock
+        /// time, then goes back to the normal tempo, the way a DJ nudges a
+        /// record to pull it back into phase. Unlike set_bpm(), this doesn't
+        /// change the project. The musical position carries on smoothly when
+        /// the nudge begins and ends; it just advances faster or slower in
+        /// between. The nudge ends at the end of the buffer in which
+        /// `duration` runs out.
+        ///
+        /// A new nudge replaces one that's in progress, and a zero percent or
+        /// zero duration cancels it.
+        pub fn nudge_tempo(&mut self, percent: f64, duration: Duration) {
+            if !percent.is_finite() || percent.abs() > Self::MAX_NUDGE_PERCENT {
+                eprintln!("Warning: ignoring invalid tempo nudge {percent}%");
+                return;
             }
+            let frames = (duration.as_secs_f64() * self.sample_rate().value() as f64) as usize;
+            let nudge = (percent != 0.0 && frames > 0).then(|| TempoNudge {
+                factor: 1.0 + percent / 100.0,
+                frames_remaining: frames,
+            });
+            self.change_rate(|o| o.nudge = nudge);
         }
 
-        pub fn loop_range(&self) -> Option<&Range<PerfectTimeUnit>> {
-            self.loop_range.as_ref()
+        #[allow(missing_docs)]
+        pub fn is_nudging(&self) -> bool {
+            self.nudge.is_some()
         }
 
-        pub fn is_loop_enabled(&self) -> bool {
-            self.is_loop_enabled
+        /// The current position in the song.
+        pub fn position(&self) -> MusicalTime {
+            let beats = self.clock.beats() * self.rate();
+            MusicalTime::new_with_units((beats * MusicalTime::UNITS_IN_BEAT as f64).round() as usize)
         }
-    }
-    impl Controls for Orchestrator {
-        fn update_time(&mut self, _range: &Range<MusicalTime>) {
-            todo!()
+
+        // Counts down a nudge in progress, and ends it when it runs out.
+        fn advance_nudge(&mut self, frames: usize) {
+            let Some(nudge) = self.nudge.as_mut() else {
+                return;
+            };
+            nudge.frames_remaining = nudge.frames_remaining.saturating_sub(frames);
+            if nudge.frames_remaining == 0 {
+                self.change_rate(|o| o.nudge = None);
+            }
         }
 
-        fn work(&mut self, _control_events_fn: &mut groove_core::traits::ControlEventsFn) {
-            todo!()
+        /// Shifts the key of every note routed to entities by the given number
+        /// of semitones, so that a whole arrangement can change key without
+        /// editing patterns. Notes that would land outside the MIDI range are
+        /// dropped. Notes that are already sounding are released at the key
+        /// they started on.
+        pub fn set_transpose(&mut self, semitones: i8) {
+            self.transposer.set_semitones(semitones);
         }
 
-        fn is_finished(&self) -> bool {
-            todo!()
+        pub fn transpose(&self) -> i8 {
+            self.transposer.semitones()
         }
 
-        // The difference between play() and tick() is that play() tells devices
-        // that it's time to do work, and tick() actually gives them gives them a
-        // slice of time to do that work. To illustrate: suppose we start calling
+        /// Whether [Orchestrator::set_transpose()] applies to channel 10, where
+        /// drum kits conventionally live. Off by default.
+        pub fn set_transpose_drums(&mut self, transpose_drums: bool) {
+            self.transposer.set_transposes_drums(transpose_drums);
+        }
+
+        /// Returns what the entity produced for the last frame of the most
+        /// recent render, for meters and the like. This doesn't render
+        /// anything. Returns None for entities that don't produce audio, and
+        /// for ones that weren't part of the render, such as instruments that
+        /// aren't connected to anything.
+        pub fn entity_output_level(&self, uid: Uid) -> Option<StereoSample> {
+            self.output_levels.get(&uid).copied()
+        }
+
+        /// Returns the peak of each channel of the main output over the most
+        /// recent tick(), for the master meters. Unlike
+        /// [Orchestrator::entity_output_level()], this catches peaks anywhere
+        /// in the buffer, not just the last frame.
+        pub fn main_output_level(&self) -> StereoSample {
+            self.main_output_level
+        }
+
+        /// Tells the Orchestrator that the effect keeps sounding for up to
+        /// `seconds` after its input goes silent, like a reverb or a delay.
+        /// run_performance() keeps rendering past the end of the performance
+        /// until the longest such tail has rung out, up to the release window.
+        /// Realtime playback needs nothing special, because audio keeps
+        /// flowing while the transport is stopped.
+        pub fn set_tail_length(&mut self, uid: Uid, seconds: f64) -> anyhow::Result<()> {
+            let Some(entity) = self.store.get(uid) else {
+                return Err(anyhow!("Couldn't find entity {uid}"));
+            };
+            if entity.as_is_effect().is_none() {
+                return Err(anyhow!("Entity {uid} isn't an effect"));
+            }
+            if !seconds.is_finite() || seconds < 0.0 {
+                return Err(anyhow!("Invalid tail length {seconds}"));
+            }
+            if seconds == 0.0 {
+                self.tail_lengths.remove(&uid);
+            } else {
+                self.tail_lengths.insert(uid, seconds);
+            }
+            Ok(())
+        }
+
+        /// Sets the longest that a render continues past the end of the
+        /// performance, in seconds, however long the effects say their tails
+        /// are. Zero ends renders exactly where the performance ends.
+        pub fn set_release_window(&mut self, seconds: f64) {
+            if !seconds.is_finite() || seconds < 0.0 {
+                eprintln!("Warning: ignoring invalid release window {seconds}");
+                return;
+            }
+            self.release_window = seconds;
+        }
+
+        #[allow(missing_docs)]
+        pub fn release_window(&self) -> f64 {
+            self.release_window
+        }
+
+        // How long, in seconds, a render might need to continue after the
+        // performance ends for the effects' tails.
+        fn advertised_tail_length(&self) -> f64 {
+            self.tail_lengths
+                .iter()
+                .filter(|(uid, _)| self.store.get(**uid).is_some())
+                .map(|(_, seconds)| *seconds)
+                .fold(0.0, f64::max)
+                .min(self.release_window)
+        }
+
+        /// Takes an instrument or effect out of the signal path, or puts it
+        /// back. A bypassed effect passes its input through untouched, and a
+        /// bypassed instrument keeps running but is silent. The change fades
+        /// over [Bypass::CROSSFADE_FRAMES] so that it doesn't click.
+        pub fn set_bypassed(&mut self, uid: Uid, is_bypassed: bool) -> anyhow::Result<()> {
+            let Some(entity) = self.store.get(uid) else {
+                return Err(anyhow!("Couldn't find entity {uid}"));
+            };
+            if entity.as_is_instrument().is_none() && entity.as_is_effect().is_none() {
+                return Err(anyhow!("Entity {uid} doesn't output audio"));
+            }
+            if is_bypassed || self.bypasses.contains_key(&uid) {
+                self.bypasses
+                    .entry(uid)
+                    .or_default()
+                    .set_bypassed(is_bypassed);
+            }
+            Ok(())
+        }
+
+        #[allow(missing_docs)]
+        pub fn is_bypassed(&self, uid: Uid) -> bool {
+            self.bypasses
+                .get(&uid)
+                .is_some_and(|bypass| bypass.is_bypassed())
+        }
+
+        /// Solos an entity in place: until end_audition(), the main output
+        /// plays only that entity's output, wherever it sits in the graph. An
+        /// instrument buried in a chain is heard before its effects. An effect
+        /// is heard processing whatever is patched into it, or, if nothing
+        /// is, a [Self::AUDITION_TONE_FREQUENCY] test tone. Entities outside
+        /// the auditioned one's subtree aren't rendered meanwhile.
+        pub fn audition(&mut self, uid: Uid) -> anyhow::Result<()> {
+            let Some(entity) = self.store.get(uid) else {
+                return Err(anyhow!("Couldn't find entity {uid}"));
+            };
+            if entity.as_is_instrument().is_none() && entity.as_is_effect().is_none() {
+                return Err(anyhow!("Entity {uid} doesn't output audio"));
+            }
+            self.audition_uid = Some(uid);
+            self.audition_tone_frames = 0;
+            Ok(())
+        }
+
+        /// Goes back to playing the full mix.
+        pub fn end_audition(&mut self) {
+            self.audition_uid = None;
+        }
+
+        /// The entity that's soloed in place, if any.
+        pub fn audition_uid(&self) -> Option<Uid> {
+            self.audition_uid
+        }
+
+        /// The frequency of the tone that audition() feeds an effect that has
+        /// nothing patched into it.
+        pub const AUDITION_TONE_FREQUENCY: f64 = 440.0;
+
+        /// The level of the audition test tone. Quiet enough not to startle.
+        const AUDITION_TONE_LEVEL: f64 = 0.25;
+
+        fn render_audition(&mut self, uid: Uid, frame_offset: usize, samples: &mut [StereoSample]) {
+            let is_unpatched_effect = self.store.patches(uid).map_or(true, |p| p.is_empty())
+                && self
+                    .store
+                    .get(uid)
+                    .is_some_and(|e| e.as_is_effect().is_some());
+            if !is_unpatched_effect {
+                self.gather_audio_from(uid, frame_offset, samples);
+                return;
+            }
+            let frames_per_second = self.sample_rate().value() as f64;
+            let Some(effect) = self.store.get_mut(uid).and_then(|e| e.as_is_effect_mut()) else {
+                return;
+            };
+            for sample in samples.iter_mut() {
+                let seconds = self.audition_tone_frames as f64 / frames_per_second;
+                self.audition_tone_frames += 1;
+                let tone = Self::AUDITION_TONE_LEVEL
+                    * (std::f64::consts::TAU * Self::AUDITION_TONE_FREQUENCY * seconds).sin();
+                *sample = effect.transform_audio(StereoSample::from(tone));
+            }
+        }
+
+        /// Turns on headphone crossfeed for the main output. `amount` is how
+        /// much of each channel bleeds into the other, 0.0..=1.0, and `delay`
+        /// is how late it arrives, in seconds. See [Crossfeed]. An amount of
+        /// zero turns crossfeed off, which leaves the output exactly as it
+        /// would be without it. Offline renders never include crossfeed.
+        pub fn set_crossfeed(&mut self, amount: f64, delay: f64) {
+            if !amount.is_finite() || !delay.is_finite() || delay < 0.0 {
+                eprintln!("Warning: ignoring invalid crossfeed {amount} / {delay}");
+                return;
+            }
+            self.crossfeed = (amount > 0.0)
+                .then(|| Crossfeed::new_with(amount, delay, self.clock.sample_rate()));
+        }
+
+        #[allow(missing_docs)]
+        pub fn crossfeed(&self) -> Option<&Crossfeed> {
+            self.crossfeed.as_ref()
+        }
+
+        /// Turns on the load guard, which watches how long each tick() takes
+        /// to render and, when rendering keeps falling behind, bypasses the
+        /// most expensive effect so that the output doesn't drop out. Each
+        /// trim sends [GrooveEvent::LoadTrimmed], and once the load has stayed
+        /// low for a while the trims are undone, newest first, with
+        /// [GrooveEvent::LoadRestored]. None turns the guard off and undoes
+        /// any trims in effect.
+        pub fn set_load_guard(&mut self, policy: Option<LoadGuardPolicy>) {
+            for uid in std::mem::take(&mut self.load_trims) {
+                let _ = self.set_bypassed(uid, false);
+            }
+            self.effect_costs.clear();
+            self.load_guard = policy.map(LoadGuard::new_with);
+        }
+
+        #[allow(missing_docs)]
+        pub fn load_guard(&self) -> Option<&LoadGuard> {
+            self.load_guard.as_ref()
+        }
+
+        /// Effects that the load guard has bypassed, oldest first.
+        pub fn load_trims(&self) -> &[Uid] {
+            &self.load_trims
+        }
+
+        /// Feeds one buffer's load to the load guard and carries out whatever
+        /// it decides. tick() calls this with its own render time. A caller
+        /// that can measure the whole audio callback instead, which is closer
+        /// to what the sound card sees, can call it directly.
+        pub fn report_load(&mut self, load: f64) -> Response<GrooveEvent> {
+            let Some(guard) = self.load_guard.as_mut() else {
+                return Response::none();
+            };
+            match guard.record(load) {
+                Some(LoadGuardAction::Trim) => {
+                    let can_trim = guard.can_trim();
+                    match self.most_expensive_effect().filter(|_| can_trim) {
+                        Some(uid) => {
+                            let _ = self.set_bypassed(uid, true);
+                            self.load_trims.push(uid);
+                            if let Some(guard) = self.load_guard.as_mut() {
+                                guard.trimmed();
+                            }
+                            Response::single(GrooveEvent::LoadTrimmed(uid, load))
+                        }
+                        None => Response::single(GrooveEvent::Overloaded(load)),
+                    }
+                }
+                Some(LoadGuardAction::Restore) => {
+                    guard.restored();
+                    if let Some(uid) = self.load_trims.pop() {
+                        let _ = self.set_bypassed(uid, false);
+                        Response::single(GrooveEvent::LoadRestored(uid))
+                    } else {
+                        Response::none()
+                    }
+                }
+                None => Response::none(),
+            }
+        }
+
+        // The effect that costs the most to run, among those still running.
+        // The main mixer is an effect too, but it's not optional.
+        fn most_expensive_effect(&self) -> Option<Uid> {
+            self.effect_costs
+                .iter()
+                .filter(|(uid, _)| **uid != self.main_mixer_uid && !self.is_bypassed(**uid))
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(uid, _)| *uid)
+        }
+
+        /// Arms or disarms an entity to record. An armed effect records live
+        /// audio input, and an armed entity that handles MIDI records external
+        /// MIDI. Arming turns on input monitoring, so the input is heard
+        /// through the entity while it's captured. Disarming discards what
+        /// was captured; call take_recording() first to keep it.
+        pub fn set_record_armed(&mut self, uid: Uid, is_armed: bool) -> anyhow::Result<()> {
+            let Some(entity) = self.store.get(uid) else {
+                return Err(anyhow!("Couldn't find entity {uid}"));
+            };
+            let takes_midi = entity.as_handles_midi().is_some();
+            if !takes_midi && entity.as_is_effect().is_none() {
+                return Err(anyhow!("Entity {uid} takes neither audio nor MIDI input"));
+            }
+            if is_armed {
+                self.record_arms.entry(uid).or_insert_with(|| RecordArm {
+                    is_monitoring: true,
+                    takes_midi,
+                    ..Default::default()
+                });
+            } else {
+                self.record_arms.remove(&uid);
+                if self.record_arms.is_empty() {
+                    self.live_input.clear();
+                }
+            }
+            Ok(())
+        }
+
+        #[allow(missing_docs)]
+        pub fn is_record_armed(&self, uid: Uid) -> bool {
+            self.record_arms.contains_key(&uid)
+        }
+
+        /// Turns input monitoring on or off for an armed entity. Input is
+        /// captured either way.
+        pub fn set_input_monitoring(
+            &mut self,
+            uid: Uid,
+            is_monitoring: bool,
+        ) -> anyhow::Result<()> {
+            let Some(arm) = self.record_arms.get_mut(&uid) else {
+                return Err(anyhow!("Entity {uid} isn't armed to record"));
+            };
+            arm.is_monitoring = is_monitoring;
+            Ok(())
+        }
+
+        /// What an armed entity has captured so far.
+        pub fn recording(&self, uid: Uid) -> Option<&RecordArm> {
+            self.record_arms.get(&uid)
+        }
+
+        /// Disarms the entity and hands back what it captured.
+        pub fn take_recording(&mut self, uid: Uid) -> Option<RecordArm> {
+            self.record_arms.remove(&uid)
+        }
+
+        /// Queues live audio input, such as frames from an
+        /// AudioInputStream, to be rendered through the armed effects. Each
+        /// rendered frame consumes one frame of input. Input that arrives
+        /// while nothing is armed is dropped.
+        pub fn push_live_input(&mut self, frames: &[StereoSample]) {
+            if !self.record_arms.is_empty() {
+                self.live_input.extend(frames);
+            }
+        }
+
+        /// How far behind the live input the monitored output is, in
+        /// seconds: the input that's queued but not yet rendered, plus one
+        /// output buffer. Subtract it from recorded positions to line a take
+        /// up with what the performer heard.
+        pub fn monitoring_latency(&self) -> f64 {
+            (self.live_input.len() + self.last_tick_len) as f64 / self.sample_rate().value() as f64
+        }
+
+        /// Frames rendered since the Orchestrator was created, whether or not
+        /// it was performing. This is the clock that scheduled external MIDI
+        /// uses; see [MidiStamper].
+        pub fn free_running_frames(&self) -> u64 {
+            self.free_running_frames
+        }
+
+        /// Queues an external MIDI message to be delivered right before the
+        /// given free-running frame is rendered, rather than at the start of
+        /// the next buffer. A frame that has already been rendered is
+        /// delivered at the start of the next buffer.
+        pub fn schedule_midi_from_external(
+            &mut self,
+            frame: u64,
+            channel: MidiChannel,
+            message: MidiMessage,
+        ) {
+            // Messages for the same frame keep the order they arrived in.
+            let index = self.scheduled_midi.partition_point(|(f, _, _)| *f <= frame);
+            self.scheduled_midi.insert(index, (frame, channel, message));
+        }
+
+        /// Moves the playhead to the given position in the song. Anything
+        /// that's currently sounding is sent a note-off first, because the
+        /// controllers that started those notes won't get a chance to stop
+        /// them.
+        pub fn seek(&mut self, time: MusicalTime) {
+            self.silence_all_notes();
+            let beats = time.total_units() as f64 / MusicalTime::UNITS_IN_BEAT as f64;
+            self.clock.seek_beats(beats / self.rate());
+
+            // Make sure the next handle_work() doesn't think it has already
+            // seen this time range, so controllers re-evaluate at the new
+            // position.
+            self.last_time_range = Range {
+                start: MusicalTime::TIME_MAX,
+                end: MusicalTime::TIME_MAX,
+            };
+        }
+
+        /// Sends All Notes Off (CC 123) and a note-off for every key on every
+        /// channel, to every entity that handles MIDI, and then hard-resets
+        /// every voiced instrument so that nothing keeps sounding through a
+        /// release tail. The returned events send the same messages out the
+        /// external MIDI port. This works whether or not we're performing, and
+        /// leaves the transport alone.
+        pub fn midi_panic(&mut self) -> Response<GrooveEvent> {
+            let mut messages = Vec::with_capacity(16 * 129);
+            for channel in 0..16u8 {
+                let channel = MidiChannel(channel);
+                messages.push((
+                    channel,
+                    MidiMessage::Controller {
+                        controller: Self::ALL_NOTES_OFF_CC.into(),
+                        value: 0.into(),
+                    },
+                ));
+                messages.extend((0..=127u8).map(|key| {
+                    (
+                        channel,
+                        MidiMessage::NoteOff {
+                            key: key.into(),
+                            vel: 0.into(),
+                        },
+                    )
+                }));
+            }
+
+            // Entities such as arpeggiators might respond to the panic with
+            // more MIDI; deliver_midi_messages() routes those too. The panic
+            // already covers every key, so it isn't transposed.
+            self.deliver_midi_messages(messages.clone());
+            self.reset_voices();
+            Response::batch(messages.into_iter().map(|(channel, message)| {
+                Response::single(GrooveEvent::MidiToExternal(channel, message))
+            }))
+        }
+
+        // Rebuilds each voiced instrument from its own parameters. A note-off
+        // only starts an envelope's release, and an instrument that ignores
+        // CC 123 would keep its voices, so this is the only way to be sure
+        // that every voice and envelope is back at rest. The instrument keeps
+        // its UID, and so its patches, MIDI routing, and automation.
+        fn reset_voices(&mut self) {
+            use groove_entities::instruments::{FmSynth, WelshSynth};
+            use groove_toys::ToyInstrument;
+
+            let sample_rate = self.clock.sample_rate();
+            for (uid, entity) in self.store.uid_to_item.iter_mut() {
+                *entity = match entity {
+                    EntityObsolete::ToyInstrument(e) => EntityObsolete::ToyInstrument(Box::new(
+                        ToyInstrument::new_with(&e.to_params()),
+                    )),
+                    EntityObsolete::WelshSynth(e) => {
+                        EntityObsolete::WelshSynth(Box::new(WelshSynth::new_with(&e.to_params())))
+                    }
+                    EntityObsolete::FmSynth(e) => {
+                        EntityObsolete::FmSynth(Box::new(FmSynth::new_with(&e.to_params())))
+                    }
+                    _ => continue,
+                };
+                entity.as_has_uid_mut().set_uid(*uid);
+                entity.as_configurable_mut().update_sample_rate(sample_rate);
+            }
+        }
+
+        // Sends a note-off for every key on every channel that has a listener.
+        // This is a blunt instrument, but it's the only way to be sure that
+        // nothing is left hanging, since we don't track which notes are on.
+        fn silence_all_notes(&mut self) {
+            let channels: Vec<MidiChannel> = self
+                .store
+                .midi_channel_to_receiver_uid
+                .iter()
+                .filter(|(_, uids)| !uids.is_empty())
+                .map(|(channel, _)| *channel)
+                .collect();
+            for channel in channels {
+                let messages: Vec<(MidiChannel, MidiMessage)> = (0..=127u8)
+                    .map(|key| {
+                        (
+                            channel,
+                            MidiMessage::NoteOff {
+                                key: key.into(),
+                                vel: 0.into(),
+                            },
+                        )
+                    })
+                    .collect();
+                self.deliver_midi_messages(messages);
+            }
+        }
+
+        /// The tempo that musical time actually advances at, after applying
+        /// the playback rate.
+        fn effective_bpm(&self) -> ParameterType {
+            self.bpm() * self.rate()
+        }
+
+        // The playback rate, including any nudge in progress.
+        fn rate(&self) -> f64 {
+            self.playback_rate * self.nudge.as_ref().map_or(1.0, |nudge| nudge.factor)
+        }
+
+        // Makes a change that affects rate() without moving the current
+        // musical position.
+        fn change_rate(&mut self, change: impl FnOnce(&mut Self)) {
+            let beats = self.clock.beats() * self.rate();
+            change(self);
+            self.clock.seek_beats(beats / self.rate());
+            self.store.update_tempo(Tempo::from(self.effective_bpm()));
+        }
+
+        pub fn clock(&self) -> &Clock {
+            &self.clock
+        }
+
+        #[allow(dead_code)]
+        fn handle_control(&mut self, uid: Uid, param_id: ControlIndex, value: ControlValue) {
+            if let Some(entity) = self.store.get_mut(uid) {
+                if let Some(controllable) = entity.as_controllable_mut() {
+                    controllable.control_set_param_by_index(param_id, value);
+                }
+            }
+        }
+
+        pub fn loop_range(&self) -> Option<&Range<PerfectTimeUnit>> {
+            self.loop_range.as_ref()
+        }
+
+        pub fn is_loop_enabled(&self) -> bool {
+            self.is_loop_enabled
+        }
+    }
+    impl Controls for Orchestrator {
+        fn update_time(&mut self, _range: &Range<MusicalTime>) {
+            todo!()
+        }
+
+        fn work(&mut self, _control_events_fn: &mut groove_core::traits::ControlEventsFn) {
+            todo!()
+        }
+
+        fn is_finished(&self) -> bool {
+            todo!()
+        }
+
+        // The difference between play() and tick() is that play() tells devices
+        // that it's time to do work, and tick() actually gives them gives them a
+        // slice of time to do that work. To illustrate: suppose we start calling
         // tick() without first having called play(). We'll generally get silence.
         // If a MIDI note-on event comes in from an external device, though, sound
         // will be produced, because the MIDI event itself represented work for
@@ -1019,6 +3200,9 @@ mod obsolete {
         fn update_sample_rate(&mut self, sample_rate: SampleRate) {
             self.clock.update_sample_rate(sample_rate);
             self.store.update_sample_rate(sample_rate);
+            if let Some(crossfeed) = self.crossfeed.as_mut() {
+                crossfeed.update_sample_rate(sample_rate);
+            }
         }
     }
 
@@ -1220,6 +3404,13 @@ impl Store {
         self.uid_to_item.get(&uid)
     }
 
+    // Forgets the entity and its name. The caller should already have
+    // unpatched and disconnected it.
+    pub(crate) fn remove(&mut self, uid: Uid) -> Option<EntityObsolete> {
+        self.uvid_to_uid.retain(|_, v| *v != uid);
+        self.uid_to_item.remove(&uid)
+    }
+
     pub fn get_mut(&mut self, uid: Uid) -> Option<&mut EntityObsolete> {
         self.uid_to_item.get_mut(&uid)
     }
@@ -1244,6 +3435,13 @@ impl Store {
         self.uvid_to_uid.get(uvid).copied()
     }
 
+    pub(crate) fn get_uvid(&self, uid: Uid) -> Option<&str> {
+        self.uvid_to_uid
+            .iter()
+            .find(|(_, &v)| v == uid)
+            .map(|(k, _)| k.as_str())
+    }
+
     pub fn iter(&self) -> std::collections::hash_map::Iter<Uid, EntityObsolete> {
         self.uid_to_item.iter()
     }
@@ -1359,6 +3557,13 @@ impl Store {
             .push(receiver_uid);
     }
 
+    pub(crate) fn midi_channel_for(&self, receiver_uid: Uid) -> Option<MidiChannel> {
+        self.midi_channel_to_receiver_uid
+            .iter()
+            .find(|(_, uids)| uids.contains(&receiver_uid))
+            .map(|(channel, _)| *channel)
+    }
+
     pub(crate) fn disconnect_midi_receiver(&mut self, receiver_uid: Uid, channel: MidiChannel) {
         self.midi_channel_to_receiver_uid
             .entry(channel)
@@ -1392,13 +3597,28 @@ impl Configurable for Store {
             e.as_configurable_mut().update_sample_rate(sample_rate);
         })
     }
+
+    fn update_tempo(&mut self, tempo: Tempo) {
+        self.values_mut().for_each(|e| {
+            e.as_configurable_mut().update_tempo(tempo);
+        })
+    }
 }
 
 #[cfg(test)]
 pub mod tests {
-    use super::Orchestrator;
-    use crate::{entities::EntityObsolete, tests::DEFAULT_BPM};
+    use super::{
+        BenchmarkReport, Bypass, Crossfeed, LoadGuard, LoadGuardAction, LoadGuardPolicy,
+        MasterLimiter, MidiStamper, Orchestrator, Performance, RecordArm, RenderOptions,
+        Transposer,
+    };
+    use crate::{
+        entities::EntityObsolete,
+        messages::{GrooveEvent, GrooveInput, Internal},
+        tests::DEFAULT_BPM,
+    };
     use ensnare::prelude::*;
+    use groove::mini::{NoteDivision, SampleAndHoldLfo};
     use groove_core::{
         midi::{MidiChannel, MidiMessage},
         time::{Clock, PerfectTimeUnit},
@@ -1412,7 +3632,142 @@ pub mod tests {
         },
         effects::{Gain, GainParams},
     };
-    use groove_toys::{ToyAudioSource, ToyAudioSourceParams, ToyInstrument, ToyInstrumentParams};
+    use groove_toys::{
+        ToyAudioSource, ToyAudioSourceParams, ToyInstrument, ToyInstrumentParams, ToySynth,
+        ToySynthParams,
+    };
+    use groove_utils::{PathType, Paths};
+    use std::{
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    /// Set this environment variable to record the current output as the new
+    /// reference instead of comparing against the old one.
+    const UPDATE_RENDERS_VAR: &str = "GROOVE_UPDATE_RENDERS";
+
+    /// Renders the [Orchestrator]'s performance and checks that it matches the
+    /// reference WAV at `reference_wav_path` (relative to the test-data
+    /// directory), sample by sample, within `tolerance`. If the reference
+    /// doesn't exist or doesn't match, the actual output is written next to it
+    /// as `<name>-actual.wav` so that it can be inspected or promoted to be
+    /// the new reference. With `GROOVE_UPDATE_RENDERS` set, the output
+    /// replaces the reference.
+    pub fn assert_renders_to(o: &mut Orchestrator, reference_wav_path: &Path, tolerance: f64) {
+        let mut path = Paths::hive(PathType::Cwd);
+        path.push(Paths::test_data_rel());
+        path.push(reference_wav_path);
+
+        // The references were recorded before renders had a limiter.
+        let options = RenderOptions {
+            limiter_ceiling: None,
+            ..o.default_render_options()
+        };
+        let mut buffer = [StereoSample::SILENCE; 64];
+        let performance = o
+            .run_performance_with(&mut buffer, true, None, &options)
+            .expect("rendering the performance failed");
+        let mut actual = Vec::default();
+        while let Some(sample) = performance.worker.pop() {
+            actual.push(sample);
+        }
+
+        if std::env::var_os(UPDATE_RENDERS_VAR).is_some() {
+            write_wav(&path, performance.sample_rate, &actual)
+                .expect("couldn't write the new reference");
+            return;
+        }
+        let reference = match read_reference_wav(&path) {
+            Ok(reference) => reference,
+            Err(e) => {
+                let actual_path = write_actual_wav(&path, performance.sample_rate, &actual);
+                panic!(
+                    "couldn't read reference {}: {e}. Actual output is at {}. To record it as the reference, rerun with {UPDATE_RENDERS_VAR}=1",
+                    path.display(),
+                    actual_path.display()
+                );
+            }
+        };
+
+        let first_mismatch = if actual.len() != reference.len() {
+            Some(actual.len().min(reference.len()))
+        } else {
+            actual.iter().zip(reference.iter()).position(|(a, r)| {
+                (a.0 .0 - r.0 .0).abs() > tolerance || (a.1 .0 - r.1 .0).abs() > tolerance
+            })
+        };
+        if let Some(index) = first_mismatch {
+            let actual_path = write_actual_wav(&path, performance.sample_rate, &actual);
+            panic!(
+                "render differs from {} starting at frame {index} (actual {} frames, reference {} frames). Actual output is at {}",
+                path.display(),
+                actual.len(),
+                reference.len(),
+                actual_path.display()
+            );
+        }
+    }
+
+    fn read_reference_wav(path: &Path) -> anyhow::Result<Vec<StereoSample>> {
+        let mut reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        let values: Vec<f64> = match spec.sample_format {
+            hound::SampleFormat::Float => reader
+                .samples::<f32>()
+                .map(|s| s.map(|s| s as f64))
+                .collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = (1i64 << (spec.bits_per_sample - 1)) as f64;
+                reader
+                    .samples::<i32>()
+                    .map(|s| s.map(|s| s as f64 / scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        Ok(match spec.channels {
+            1 => values.iter().map(|v| StereoSample::from(*v)).collect(),
+            2 => values
+                .chunks_exact(2)
+                .map(|c| StereoSample::new(c[0].into(), c[1].into()))
+                .collect(),
+            n => return Err(anyhow::anyhow!("unsupported channel count {n}")),
+        })
+    }
+
+    fn write_actual_wav(
+        reference_path: &Path,
+        sample_rate: SampleRate,
+        samples: &[StereoSample],
+    ) -> PathBuf {
+        let mut actual_path = reference_path.to_path_buf();
+        let stem = reference_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        actual_path.set_file_name(format!("{stem}-actual.wav"));
+        let _ = write_wav(&actual_path, sample_rate, samples);
+        actual_path
+    }
+
+    fn write_wav(
+        path: &Path,
+        sample_rate: SampleRate,
+        samples: &[StereoSample],
+    ) -> anyhow::Result<()> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: sample_rate.value() as u32,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(path, spec)?;
+        for sample in samples {
+            writer.write_sample(sample.0 .0 as f32)?;
+            writer.write_sample(sample.1 .0 as f32)?;
+        }
+        writer.finalize()?;
+        Ok(())
+    }
 
     impl Orchestrator {
         /// Warning! This method exists only as a debug shortcut to
@@ -1473,52 +3828,323 @@ pub mod tests {
     }
 
     #[test]
-    fn gather_audio() {
+    fn entity_output_levels_track_last_render() {
         let mut o = Orchestrator::new_with(Clock::default());
-        let level_1_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
             ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
         )));
-        let gain_1_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+        let gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
             &GainParams {
                 ceiling: Normal::new(0.5),
             },
         ))));
-        let level_2_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
-            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.2 }),
-        )));
-        let level_3_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
-            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.3 }),
-        )));
-        let level_4_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
-            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.4 }),
-        )));
+        assert!(o.patch_chain_to_main_mixer(&[source_uid, gain_uid]).is_ok());
+        assert!(o.entity_output_level(source_uid).is_none());
 
-        // Nothing connected: should output silence.
-        let mut samples: [StereoSample; 1] = Default::default();
+        let mut samples: [StereoSample; 4] = Default::default();
         o.gather_audio(&mut samples);
-        assert_eq!(samples[0], StereoSample::SILENCE);
+        assert!(o
+            .entity_output_level(source_uid)
+            .unwrap()
+            .almost_equals(StereoSample::from(0.1)));
+        assert!(o
+            .entity_output_level(gain_uid)
+            .unwrap()
+            .almost_equals(StereoSample::from(0.1 * 0.5)));
+        assert!(samples[3].almost_equals(StereoSample::from(0.1 * 0.5)));
 
-        // Just the single-level instrument; should get that.
-        assert!(o.connect_to_main_mixer(level_1_uid).is_ok());
+        // Once it's out of the graph, it has no level.
+        assert!(o.unpatch_all().is_ok());
         o.gather_audio(&mut samples);
-        assert!(samples[0].almost_equals(StereoSample::from(0.1)));
+        assert!(o.entity_output_level(source_uid).is_none());
+    }
 
-        // Gain alone; that's weird, but it shouldn't explode.
-        assert!(o.disconnect_from_main_mixer(level_1_uid).is_ok());
-        assert!(o.connect_to_main_mixer(gain_1_uid).is_ok());
-        o.gather_audio(&mut samples);
-        assert_eq!(samples[0], StereoSample::SILENCE);
+    #[test]
+    fn main_output_level_is_the_peak_of_the_last_tick() {
+        let mut o = Orchestrator::new_with(Clock::default());
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: -0.1 }),
+        )));
+        assert!(o.patch_chain_to_main_mixer(&[source_uid]).is_ok());
+        assert!(o.main_output_level().almost_equals(StereoSample::default()));
 
-        // Disconnect/reconnect and connect just the single-level instrument again.
-        assert!(o.disconnect_from_main_mixer(gain_1_uid).is_ok());
-        assert!(o.connect_to_main_mixer(level_1_uid).is_ok());
-        o.gather_audio(&mut samples);
-        assert!(samples[0].almost_equals(StereoSample::from(0.1)));
+        let mut samples: [StereoSample; 64] = [StereoSample::default(); 64];
+        let _ = o.tick(&mut samples);
+        assert!(o.main_output_level().almost_equals(StereoSample::from(0.1)));
 
-        // Instrument to gain should result in (instrument x gain).
         assert!(o.unpatch_all().is_ok());
-        assert!(o
-            .patch_chain_to_main_mixer(&[level_1_uid, gain_1_uid])
+        let _ = o.tick(&mut samples);
+        assert!(o.main_output_level().almost_equals(StereoSample::default()));
+    }
+
+    #[test]
+    fn set_param_by_name_from_a_remote() {
+        let mut o = Orchestrator::new_with(Clock::default());
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
+        )));
+        let gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+            &GainParams {
+                ceiling: Normal::new(0.5),
+            },
+        ))));
+        assert!(o.patch_chain_to_main_mixer(&[source_uid, gain_uid]).is_ok());
+
+        o.update(GrooveInput::SetParam(
+            gain_uid,
+            "ceiling".to_string(),
+            ControlValue(0.25),
+        ));
+        let mut samples: [StereoSample; 4] = Default::default();
+        o.gather_audio(&mut samples);
+        assert!(samples[3].almost_equals(StereoSample::from(0.1 * 0.25)));
+
+        assert!(o
+            .set_param_by_name(gain_uid, "no-such-param", ControlValue(1.0))
+            .is_err());
+        assert!(o
+            .set_param_by_name(Uid(9999), "ceiling", ControlValue(1.0))
+            .is_err());
+    }
+
+    #[test]
+    fn bypass_crossfades_around_an_entity() {
+        let mut o = Orchestrator::new_with(Clock::default());
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
+        )));
+        let gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+            &GainParams {
+                ceiling: Normal::new(0.5),
+            },
+        ))));
+        assert!(o.patch_chain_to_main_mixer(&[source_uid, gain_uid]).is_ok());
+        assert!(o.set_bypassed(Uid(9999), true).is_err());
+
+        let mut samples = [StereoSample::default(); Bypass::CROSSFADE_FRAMES];
+        assert!(o.set_bypassed(gain_uid, true).is_ok());
+        assert!(o.is_bypassed(gain_uid));
+        o.gather_audio(&mut samples);
+        let first = samples[0].0 .0;
+        assert!(
+            first > 0.1 * 0.5 && first < 0.1,
+            "the first frame should be mid-crossfade, not {first}"
+        );
+        assert!(samples[Bypass::CROSSFADE_FRAMES - 1].almost_equals(StereoSample::from(0.1)));
+
+        // Fully bypassed, the input passes straight through.
+        o.gather_audio(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::from(0.1)));
+
+        assert!(o.set_bypassed(gain_uid, false).is_ok());
+        assert!(!o.is_bypassed(gain_uid));
+        o.gather_audio(&mut samples);
+        assert!(samples[Bypass::CROSSFADE_FRAMES - 1].almost_equals(StereoSample::from(0.1 * 0.5)));
+
+        // A bypassed instrument is silent.
+        assert!(o.set_bypassed(source_uid, true).is_ok());
+        o.gather_audio(&mut samples);
+        o.gather_audio(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::default()));
+    }
+
+    #[test]
+    fn bypass_fades_once_per_frame_however_often_visited() {
+        let mut o = Orchestrator::new_with(Clock::default());
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
+        )));
+        let gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+            &GainParams {
+                ceiling: Normal::new(0.5),
+            },
+        ))));
+
+        // The source is heard twice: directly, and through the gain.
+        assert!(o.patch_chain_to_main_mixer(&[source_uid, gain_uid]).is_ok());
+        assert!(o.connect_to_main_mixer(source_uid).is_ok());
+
+        // Halfway through the fade, the source is at half level on both paths.
+        assert!(o.set_bypassed(source_uid, true).is_ok());
+        let mut samples = [StereoSample::default(); Bypass::CROSSFADE_FRAMES / 2];
+        o.gather_audio(&mut samples);
+        assert!(samples[Bypass::CROSSFADE_FRAMES / 2 - 1]
+            .almost_equals(StereoSample::from((0.1 + 0.1 * 0.5) * 0.5)));
+    }
+
+    #[test]
+    fn audition_solos_an_entity_in_place() {
+        let mut o = Orchestrator::new_with(Clock::default());
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
+        )));
+        let gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+            &GainParams {
+                ceiling: Normal::new(0.5),
+            },
+        ))));
+        let loose_gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+            &GainParams {
+                ceiling: Normal::new(0.5),
+            },
+        ))));
+        assert!(o.patch_chain_to_main_mixer(&[source_uid, gain_uid]).is_ok());
+        assert!(o.audition(Uid(9999)).is_err());
+
+        let mut samples = [StereoSample::SILENCE; 64];
+        o.tick(&mut samples);
+        assert!(samples[63].almost_equals(StereoSample::from(0.1 * 0.5)));
+
+        // The instrument, without the gain after it.
+        assert!(o.audition(source_uid).is_ok());
+        assert_eq!(o.audition_uid(), Some(source_uid));
+        o.tick(&mut samples);
+        assert!(samples[63].almost_equals(StereoSample::from(0.1)));
+
+        // An effect with nothing patched in gets a test tone.
+        assert!(o.audition(loose_gain_uid).is_ok());
+        o.tick(&mut samples);
+        assert!(samples.iter().any(|s| s.0 .0 != 0.0));
+        assert!(samples.iter().all(|s| s.0 .0.abs() <= 0.25 * 0.5 + 0.0001));
+
+        o.end_audition();
+        assert_eq!(o.audition_uid(), None);
+        o.tick(&mut samples);
+        assert!(samples[63].almost_equals(StereoSample::from(0.1 * 0.5)));
+    }
+
+    #[test]
+    fn load_guard_needs_a_run_of_bad_buffers() {
+        let mut guard = LoadGuard::new_with(LoadGuardPolicy {
+            trim_after: 3,
+            restore_after: 2,
+            ..Default::default()
+        });
+        assert_eq!(guard.record(0.9), None);
+        assert_eq!(guard.record(0.9), None);
+        assert_eq!(guard.record(0.6), None, "in between breaks the run");
+        assert_eq!(guard.record(0.9), None);
+        assert_eq!(guard.record(0.9), None);
+        assert_eq!(guard.record(0.9), Some(LoadGuardAction::Trim));
+        guard.trimmed();
+        assert_eq!(guard.trim_count(), 1);
+
+        assert_eq!(guard.record(0.1), None);
+        assert_eq!(guard.record(0.1), Some(LoadGuardAction::Restore));
+        guard.restored();
+
+        // Nothing to restore, so no amount of idling asks for it.
+        assert_eq!(guard.record(0.1), None);
+        assert_eq!(guard.record(0.1), None);
+        assert_eq!(guard.load(), 0.1);
+    }
+
+    #[test]
+    fn load_guard_trims_and_restores_effects() {
+        let mut o = Orchestrator::new_with(Clock::default());
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
+        )));
+        let gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+            &GainParams {
+                ceiling: Normal::new(0.5),
+            },
+        ))));
+        assert!(o.patch_chain_to_main_mixer(&[source_uid, gain_uid]).is_ok());
+
+        // Off by default, and reporting does nothing.
+        assert!(o.load_guard().is_none());
+        assert!(matches!(o.report_load(10.0).0, Internal::None));
+
+        let policy = LoadGuardPolicy {
+            trim_after: 2,
+            restore_after: 2,
+            max_trims: 1,
+            ..Default::default()
+        };
+        o.set_load_guard(Some(policy.clone()));
+        let mut samples = [StereoSample::SILENCE; 64];
+        o.tick(&mut samples);
+
+        assert!(matches!(o.report_load(1.5).0, Internal::None));
+        let Internal::Single(GrooveEvent::LoadTrimmed(uid, load)) = o.report_load(1.5).0 else {
+            panic!("expected a trim");
+        };
+        assert_eq!(uid, gain_uid);
+        assert_eq!(load, 1.5);
+        assert!(o.is_bypassed(gain_uid));
+        assert_eq!(o.load_trims(), &[gain_uid]);
+
+        // The policy allows only one trim.
+        o.report_load(1.5);
+        assert!(matches!(
+            o.report_load(1.5).0,
+            Internal::Single(GrooveEvent::Overloaded(_))
+        ));
+
+        o.report_load(0.1);
+        assert!(matches!(
+            o.report_load(0.1).0,
+            Internal::Single(GrooveEvent::LoadRestored(uid)) if uid == gain_uid
+        ));
+        assert!(!o.is_bypassed(gain_uid));
+
+        // Turning the guard off undoes its trims.
+        o.report_load(1.5);
+        o.report_load(1.5);
+        assert!(o.is_bypassed(gain_uid));
+        o.set_load_guard(None);
+        assert!(!o.is_bypassed(gain_uid));
+        assert!(o.load_trims().is_empty());
+    }
+
+    #[test]
+    fn gather_audio() {
+        let mut o = Orchestrator::new_with(Clock::default());
+        let level_1_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
+        )));
+        let gain_1_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+            &GainParams {
+                ceiling: Normal::new(0.5),
+            },
+        ))));
+        let level_2_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.2 }),
+        )));
+        let level_3_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.3 }),
+        )));
+        let level_4_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.4 }),
+        )));
+
+        // Nothing connected: should output silence.
+        let mut samples: [StereoSample; 1] = Default::default();
+        o.gather_audio(&mut samples);
+        assert_eq!(samples[0], StereoSample::SILENCE);
+
+        // Just the single-level instrument; should get that.
+        assert!(o.connect_to_main_mixer(level_1_uid).is_ok());
+        o.gather_audio(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::from(0.1)));
+
+        // Gain alone; that's weird, but it shouldn't explode.
+        assert!(o.disconnect_from_main_mixer(level_1_uid).is_ok());
+        assert!(o.connect_to_main_mixer(gain_1_uid).is_ok());
+        o.gather_audio(&mut samples);
+        assert_eq!(samples[0], StereoSample::SILENCE);
+
+        // Disconnect/reconnect and connect just the single-level instrument again.
+        assert!(o.disconnect_from_main_mixer(gain_1_uid).is_ok());
+        assert!(o.connect_to_main_mixer(level_1_uid).is_ok());
+        o.gather_audio(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::from(0.1)));
+
+        // Instrument to gain should result in (instrument x gain).
+        assert!(o.unpatch_all().is_ok());
+        assert!(o
+            .patch_chain_to_main_mixer(&[level_1_uid, gain_1_uid])
             .is_ok());
         o.gather_audio(&mut samples);
         assert!(samples[0].almost_equals(StereoSample::from(0.1 * 0.5)));
@@ -1736,6 +4362,833 @@ pub mod tests {
         }
     }
 
+    // A ToySynth playing a short arpeggio: four notes, each held for half a
+    // beat, so that the reference covers attacks, releases, and pitch.
+    #[test]
+    fn renders_match_reference() {
+        const CHANNEL: MidiChannel = MidiChannel(0);
+        let ts = TimeSignature::default();
+        let mut sequencer = Box::new(Sequencer::new_with(&SequencerParams { bpm: 240.0 }));
+        let mut programmer = PatternProgrammer::new_with(ts);
+        let pattern = Pattern {
+            note_value: Some(BeatValue::Quarter),
+            notes: vec![[60, 64, 67, 72]
+                .into_iter()
+                .map(|key| Note {
+                    key,
+                    velocity: 127,
+                    duration: PerfectTimeUnit(0.5),
+                })
+                .collect()],
+        };
+        programmer.insert_pattern_at_cursor(&mut sequencer, &CHANNEL, &pattern);
+
+        let mut clock = Clock::default();
+        clock.set_bpm(240.0);
+        let mut o = Orchestrator::new_with(clock);
+        o.update_sample_rate(SampleRate::new(2400));
+        let _ = o.add(EntityObsolete::Sequencer(sequencer));
+        let synth_uid = o.add(EntityObsolete::ToySynth(Box::new(ToySynth::new_with(
+            &ToySynthParams::default(),
+        ))));
+        o.connect_midi_downstream(synth_uid, CHANNEL);
+        assert!(o.connect_to_main_mixer(synth_uid).is_ok());
+
+        assert_renders_to(&mut o, Path::new("renders/toy-synth-notes.wav"), 0.001);
+    }
+
+    #[test]
+    fn playback_rate_scales_event_timing() {
+        let mut clock = Clock::default();
+        clock.set_bpm(240.0);
+        let mut o = Orchestrator::new_with(clock);
+        o.update_sample_rate(SampleRate::new(24000));
+        let _ = o.add(EntityObsolete::Timer(Box::new(Timer::new_with(
+            MusicalTime::new_with_beats(4),
+        ))));
+        o.set_playback_rate(2.0);
+        assert_eq!(o.playback_rate(), 2.0);
+
+        let mut sample_buffer = [StereoSample::SILENCE; 64];
+        if let Ok(samples) = o.run(&mut sample_buffer) {
+            assert_eq!(samples.len(), 24000 / 2);
+        } else {
+            panic!("run failed");
+        }
+
+        o.set_playback_rate(0.0);
+        assert_eq!(o.playback_rate(), 2.0, "invalid rates should be ignored");
+    }
+
+    #[test]
+    fn nudge_tempo_is_temporary_and_continuous() {
+        let mut clock = Clock::default();
+        clock.set_bpm(240.0);
+        let mut o = Orchestrator::new_with(clock);
+        o.update_sample_rate(SampleRate::new(24000));
+        let _ = o.add(EntityObsolete::Timer(Box::new(Timer::new_with(
+            MusicalTime::new_with_beats(100),
+        ))));
+        let beats = |o: &Orchestrator| {
+            o.position().total_units() as f64 / MusicalTime::UNITS_IN_BEAT as f64
+        };
+        // 6000 frames is a beat at the normal tempo. Positions are checked
+        // to within a frame or so, because the clock counts whole frames.
+        let mut samples = [StereoSample::SILENCE; 600];
+        let mut tick_beat = |o: &mut Orchestrator| {
+            for _ in 0..10 {
+                let _ = o.tick(&mut samples);
+            }
+        };
+
+        o.play();
+        tick_beat(&mut o);
+        assert!((beats(&o) - 1.0).abs() < 1e-3);
+
+        // A quarter second is a beat's worth of frames, which now covers
+        // 10% more than a beat.
+        o.nudge_tempo(10.0, Duration::from_millis(250));
+        assert!(o.is_nudging());
+        assert!((beats(&o) - 1.0).abs() < 1e-3, "no jump when it starts");
+        tick_beat(&mut o);
+        assert!(!o.is_nudging());
+        assert!((beats(&o) - 2.1).abs() < 1e-3, "no jump when it ends");
+        tick_beat(&mut o);
+        assert!((beats(&o) - 3.1).abs() < 1e-3);
+        assert_eq!(o.playback_rate(), 1.0);
+        assert_eq!(o.bpm(), 240.0);
+
+        o.nudge_tempo(50.0, Duration::from_millis(250));
+        assert!(!o.is_nudging(), "too big a nudge should be ignored");
+    }
+
+    #[test]
+    fn freeze_and_unfreeze() {
+        let mut clock = Clock::default();
+        clock.set_bpm(240.0);
+        let mut o = Orchestrator::new_with(clock);
+        o.update_sample_rate(SampleRate::new(2400));
+        let _ = o.add(EntityObsolete::Timer(Box::new(Timer::new_with(
+            MusicalTime::new_with_beats(4),
+        ))));
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
+        )));
+        let gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+            &GainParams {
+                ceiling: Normal::new(0.5),
+            },
+        ))));
+        assert!(o.patch_chain_to_main_mixer(&[source_uid, gain_uid]).is_ok());
+
+        assert!(o.freeze(o.main_mixer_uid()).is_err());
+        assert!(o.freeze(Uid(9999)).is_err());
+
+        // Freezing leaves the transport where it was.
+        o.seek(MusicalTime::new_with_beats(1));
+        let position = o.position();
+        let sampler_uid = o.freeze(gain_uid).unwrap();
+        assert!(o.is_frozen(sampler_uid));
+        assert!(o.freeze(sampler_uid).is_err());
+        assert_eq!(o.position(), position);
+        assert!(!o.is_performing());
+        assert_eq!(
+            o.store.patches(o.main_mixer_uid()),
+            Some(&vec![sampler_uid])
+        );
+        assert!(matches!(
+            o.get(sampler_uid),
+            Some(EntityObsolete::FrozenSampler(_))
+        ));
+
+        // The sampler should sound just like the chain it replaced.
+        o.play();
+        let mut samples: [StereoSample; 1] = Default::default();
+        o.gather_audio(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::from(0.1 * 0.5)));
+
+        assert!(o.unfreeze(sampler_uid).is_ok());
+        assert!(!o.is_frozen(sampler_uid));
+        assert!(o.get(sampler_uid).is_none());
+        assert!(o.unfreeze(sampler_uid).is_err());
+        assert_eq!(o.store.patches(o.main_mixer_uid()), Some(&vec![gain_uid]));
+        o.gather_audio(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::from(0.1 * 0.5)));
+    }
+
+    #[test]
+    fn frozen_audio_follows_the_playback_rate() {
+        const SAMPLE_RATE: usize = 2400;
+        let mut clock = Clock::default();
+        clock.set_bpm(240.0);
+        let mut o = Orchestrator::new_with(clock);
+        o.update_sample_rate(SampleRate::new(SAMPLE_RATE));
+        let _ = o.add(EntityObsolete::Timer(Box::new(Timer::new_with(
+            MusicalTime::new_with_beats(8),
+        ))));
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
+        )));
+        assert!(o.connect_to_main_mixer(source_uid).is_ok());
+        let sampler_uid = o.freeze(source_uid).unwrap();
+
+        // The render covers the Timer's eight beats, which at 240 BPM is two
+        // seconds.
+        let Some(EntityObsolete::FrozenSampler(sampler)) = o.get(sampler_uid) else {
+            panic!("the frozen sampler should be an entity");
+        };
+        assert!((sampler.duration() - 2.0).abs() < 0.1);
+
+        // At double speed, beat 6 is three quarters of a second of clock
+        // time, but it's still a second and a half into the frozen audio.
+        o.set_playback_rate(2.0);
+        o.seek(MusicalTime::new_with_beats(6));
+        assert_eq!(o.clock().frames(), SAMPLE_RATE * 3 / 4);
+        o.play();
+        let mut samples: [StereoSample; 1] = Default::default();
+        o.gather_audio(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::from(0.1)));
+
+        // Beat 10 is only a second and a quarter of clock time, but it's past
+        // the end of the frozen audio.
+        o.seek(MusicalTime::new_with_beats(10));
+        o.gather_audio(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::SILENCE));
+    }
+
+    #[test]
+    fn benchmark_report_formats_realtime_factor() {
+        let report = BenchmarkReport {
+            audio_seconds: 60.0,
+            wall_seconds: 3.2,
+        };
+        assert_eq!(report.realtime_factor(), 18.75);
+        assert_eq!(
+            report.to_string(),
+            "rendered 60.0s of audio in 3.2s (18.8x realtime)"
+        );
+    }
+
+    #[test]
+    fn render_continues_until_tails_ring_out() {
+        let mut clock = Clock::default();
+        clock.set_bpm(240.0);
+        let mut o = Orchestrator::new_with(clock);
+        o.update_sample_rate(SampleRate::new(24000));
+        let _ = o.add(EntityObsolete::Timer(Box::new(Timer::new_with(
+            MusicalTime::new_with_beats(4),
+        ))));
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
+        )));
+        let gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+            &GainParams {
+                ceiling: Normal::new(0.5),
+            },
+        ))));
+        assert!(o.patch_chain_to_main_mixer(&[source_uid, gain_uid]).is_ok());
+        assert!(o.set_tail_length(source_uid, 1.0).is_err());
+        assert!(o.set_tail_length(gain_uid, -1.0).is_err());
+
+        let mut buffer = [StereoSample::SILENCE; 64];
+        let performance = o.run_performance(&mut buffer, true).unwrap();
+        assert_eq!(performance.worker.len(), 24000, "no tails, no extension");
+
+        // The source never goes quiet, so the tail runs its full length.
+        assert!(o.set_tail_length(gain_uid, 0.5).is_ok());
+        let performance = o.run_performance(&mut buffer, true).unwrap();
+        assert_eq!(performance.worker.len(), 24000 + 12000);
+
+        // The release window caps it.
+        o.set_release_window(0.25);
+        let performance = o.run_performance(&mut buffer, true).unwrap();
+        assert_eq!(performance.worker.len(), 24000 + 6000);
+        o.set_release_window(Orchestrator::DEFAULT_RELEASE_WINDOW);
+
+        // Once the output is silent, the render ends.
+        assert!(o.set_bypassed(source_uid, true).is_ok());
+        let _ = o.run_performance(&mut buffer, true).unwrap();
+        let performance = o.run_performance(&mut buffer, true).unwrap();
+        assert!(performance.worker.len() <= 24000 + buffer.len());
+    }
+
+    #[test]
+    fn render_range_bounces_a_selection() {
+        let mut clock = Clock::default();
+        clock.set_bpm(240.0);
+        let mut o = Orchestrator::new_with(clock);
+        o.update_sample_rate(SampleRate::new(24000));
+        let _ = o.add(EntityObsolete::Timer(Box::new(Timer::new_with(
+            MusicalTime::new_with_beats(8),
+        ))));
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
+        )));
+        assert!(o.patch_chain_to_main_mixer(&[source_uid]).is_ok());
+        o.set_loop(&(PerfectTimeUnit(0.0)..PerfectTimeUnit(2.0)));
+        o.set_loop_enabled(true);
+
+        // At 240 BPM and 24KHz, a beat is 6000 frames. Beats 2 through 5
+        // are 18000 frames, starting in the middle of the song and ignoring
+        // the loop.
+        let options = RenderOptions {
+            max_tail: 0.0,
+            limiter_ceiling: None,
+            ..Default::default()
+        };
+        let mut buffer = [StereoSample::SILENCE; 64];
+        let range = MusicalTime::new_with_beats(2)..MusicalTime::new_with_beats(5);
+        let performance = o
+            .run_performance_range(&mut buffer, true, &range, &options)
+            .unwrap();
+        assert_eq!(performance.worker.len(), 18000);
+        assert!(o.is_loop_enabled(), "the project's loop should be restored");
+
+        // The tail follows the range rather than being cut off by it.
+        let options = RenderOptions {
+            max_tail: 0.5,
+            ..options
+        };
+        let performance = o
+            .run_performance_range(&mut buffer, true, &range, &options)
+            .unwrap();
+        assert_eq!(performance.worker.len(), 18000 + 12000);
+
+        let empty = range.end..range.start;
+        assert!(o
+            .run_performance_range(&mut buffer, true, &empty, &options)
+            .is_err());
+    }
+
+    #[test]
+    fn crossfeed_narrows_the_image() {
+        let sample_rate = SampleRate::new(48000);
+        let mut crossfeed = Crossfeed::new_with(0.5, Crossfeed::DEFAULT_DELAY, sample_rate);
+
+        // A hard-left signal reaches the right channel late and quieter.
+        let delay_frames = (Crossfeed::DEFAULT_DELAY * 48000.0).round() as usize;
+        let out: Vec<StereoSample> = (0..4800)
+            .map(|_| crossfeed.process(StereoSample(Sample(1.0), Sample(0.0))))
+            .collect();
+        assert_eq!(out[delay_frames - 1].1 .0, 0.0);
+        assert!(out[delay_frames].1 .0 > 0.0);
+        assert!((out[4799].0 .0 - 1.0 / 1.5).abs() < 1e-9);
+        assert!((out[4799].1 .0 - 0.5 / 1.5).abs() < 1e-3);
+
+        // A centered signal keeps its level.
+        let mut crossfeed = Crossfeed::new_with(0.5, Crossfeed::DEFAULT_DELAY, sample_rate);
+        let last = (0..4800)
+            .map(|_| crossfeed.process(StereoSample::from(0.5)))
+            .last()
+            .unwrap();
+        assert!(last.almost_equals(StereoSample::from(0.5)));
+
+        // Turning it off leaves the output exactly as it was.
+        let new_orchestrator = || {
+            let mut o = Orchestrator::new_with(Clock::default());
+            let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+                ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.5 }),
+            )));
+            assert!(o.patch_chain_to_main_mixer(&[source_uid]).is_ok());
+            o
+        };
+        let render = |o: &mut Orchestrator| {
+            let mut samples = [StereoSample::SILENCE; 64];
+            o.tick(&mut samples);
+            samples
+        };
+        let mut o = new_orchestrator();
+        let dry = render(&mut o);
+        let mut o = new_orchestrator();
+        o.set_crossfeed(0.5, Crossfeed::DEFAULT_DELAY);
+        assert_eq!(o.crossfeed().unwrap().amount(), 0.5);
+        assert_ne!(render(&mut o), dry);
+        let mut o = new_orchestrator();
+        o.set_crossfeed(0.5, Crossfeed::DEFAULT_DELAY);
+        o.set_crossfeed(0.0, Crossfeed::DEFAULT_DELAY);
+        assert!(o.crossfeed().is_none());
+        assert_eq!(render(&mut o), dry);
+    }
+
+    #[test]
+    fn master_limiter_catches_peaks() {
+        let sample_rate = SampleRate::new(48000);
+        let mut limiter = MasterLimiter::new_with(-6.0, sample_rate);
+        let ceiling = 10.0f64.powf(-6.0 / 20.0);
+        assert_eq!(limiter.latency_frames(), 240);
+
+        // Quiet material with one hot transient in the middle.
+        let mut samples: Vec<StereoSample> = (0..4800)
+            .map(|i| StereoSample::from(0.25 * (i as f64 * 0.05).sin()))
+            .collect();
+        samples[2400] = StereoSample(Sample(1.5), Sample(-0.9));
+        let limited = limiter.process_offline(&samples);
+        assert_eq!(limited.len(), samples.len());
+        assert!(limited
+            .iter()
+            .all(|s| s.0 .0.abs() <= ceiling + 1e-9 && s.1 .0.abs() <= ceiling + 1e-9));
+
+        // Latency is compensated, and material well before the peak is
+        // untouched.
+        assert_eq!(limited[1000], samples[1000]);
+        assert!((limited[2400].0 .0 - ceiling).abs() < 1e-9);
+
+        // The gain eases into the peak rather than stepping down on it.
+        let before = limited[2399].0 .0 / samples[2399].0 .0;
+        assert!(
+            before < 1.0 && before > 0.3,
+            "gain before the peak: {before}"
+        );
+
+        // A render is limited by default, and the limiter can be turned off.
+        let mut o = Orchestrator::new_with(Clock::default());
+        o.update_sample_rate(SampleRate::new(24000));
+        let _ = o.add(EntityObsolete::Timer(Box::new(Timer::new_with(
+            MusicalTime::new_with_beats(1),
+        ))));
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 2.0 }),
+        )));
+        assert!(o.patch_chain_to_main_mixer(&[source_uid]).is_ok());
+        let mut buffer = [StereoSample::SILENCE; 64];
+        let peak = |performance: &Performance| {
+            let mut peak: f64 = 0.0;
+            while let Some(sample) = performance.worker.pop() {
+                peak = peak.max(sample.0 .0.abs()).max(sample.1 .0.abs());
+            }
+            peak
+        };
+        let performance = o.run_performance(&mut buffer, true).unwrap();
+        assert!(peak(&performance) <= 10.0f64.powf(RenderOptions::DEFAULT_LIMITER_CEILING / 20.0));
+        let options = RenderOptions {
+            limiter_ceiling: None,
+            ..o.default_render_options()
+        };
+        let performance = o
+            .run_performance_with(&mut buffer, true, None, &options)
+            .unwrap();
+        assert!(peak(&performance) > 1.0);
+    }
+
+    #[test]
+    fn render_options_control_the_tail() {
+        let mut clock = Clock::default();
+        clock.set_bpm(240.0);
+        let mut o = Orchestrator::new_with(clock);
+        o.update_sample_rate(SampleRate::new(24000));
+        let _ = o.add(EntityObsolete::Timer(Box::new(Timer::new_with(
+            MusicalTime::new_with_beats(4),
+        ))));
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
+        )));
+        assert!(o.patch_chain_to_main_mixer(&[source_uid]).is_ok());
+        let mut buffer = [StereoSample::SILENCE; 64];
+
+        // A source that never decays runs into the guard.
+        let options = RenderOptions {
+            tail_threshold: -60.0,
+            max_tail: 1.0,
+            ..Default::default()
+        };
+        let performance = o
+            .run_performance_with(&mut buffer, true, None, &options)
+            .unwrap();
+        assert_eq!(performance.worker.len(), 24000 + 24000);
+
+        // If the threshold is above the source's level, it counts as silence,
+        // which isn't kept.
+        let options = RenderOptions {
+            tail_threshold: -6.0,
+            max_tail: 1.0,
+            ..Default::default()
+        };
+        let performance = o
+            .run_performance_with(&mut buffer, true, None, &options)
+            .unwrap();
+        assert_eq!(performance.worker.len(), 24000);
+
+        // The overall limit still applies.
+        let performance = o
+            .run_performance_with(&mut buffer, true, Some(30000), &RenderOptions::default())
+            .unwrap();
+        assert_eq!(performance.worker.len(), 30000);
+
+        assert!(RenderOptions::default().is_below_threshold(&StereoSample::from(0.0009)));
+        assert!(!RenderOptions::default()
+            .is_below_threshold(&StereoSample(Sample(0.0), Sample(-0.0011))));
+    }
+
+    #[test]
+    fn same_master_seed_renders_bit_identically() {
+        let render = |master_seed: u64| {
+            let mut o = Orchestrator::new_with(Clock::default());
+            o.update_sample_rate(SampleRate::new(24000));
+            o.set_master_seed(master_seed);
+            let _ = o.add(EntityObsolete::Timer(Box::new(Timer::new_with(
+                MusicalTime::new_with_beats(4),
+            ))));
+            let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+                ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.5 }),
+            )));
+            let gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+                &GainParams {
+                    ceiling: Normal::new(1.0),
+                },
+            ))));
+            // The seed passed here is replaced by the one from the seed bank.
+            let lfo_uid = o.add(EntityObsolete::SampleAndHoldLfo(Box::new(
+                SampleAndHoldLfo::new_with(NoteDivision::Sixteenth, 0),
+            )));
+            assert!(o.patch_chain_to_main_mixer(&[source_uid, gain_uid]).is_ok());
+            assert!(o.link_control_by_name(lfo_uid, gain_uid, "ceiling").is_ok());
+
+            let mut buffer = [StereoSample::SILENCE; 64];
+            let performance = o.run_performance(&mut buffer, true).unwrap();
+            let mut samples = Vec::default();
+            while let Some(sample) = performance.worker.pop() {
+                samples.push((sample.0 .0.to_bits(), sample.1 .0.to_bits()));
+            }
+            samples
+        };
+
+        let first = render(1234);
+        assert!(first.iter().any(|(left, _)| f64::from_bits(*left) != 0.0));
+        assert_eq!(
+            first,
+            render(1234),
+            "the same seed should render the same bits"
+        );
+        assert_ne!(
+            first,
+            render(4321),
+            "a different seed should change the sample-and-hold steps"
+        );
+    }
+
+    #[test]
+    fn looping_performance_needs_a_limit() {
+        let mut o = Orchestrator::new_with(Clock::default());
+        o.set_loop(&(PerfectTimeUnit(0.0)..PerfectTimeUnit(4.0)));
+        o.set_loop_enabled(true);
+
+        let mut buffer = [StereoSample::SILENCE; 64];
+        assert!(
+            o.run_performance_for(&mut buffer, true, None).is_err(),
+            "an unlimited render of a looping project would never end"
+        );
+        let performance = o
+            .run_performance_for(&mut buffer, true, Some(1000))
+            .unwrap();
+        assert!(performance.worker.len() <= 1000);
+    }
+
+    #[test]
+    fn aux_bus_sends() {
+        let mut o = Orchestrator::new_with(Clock::default());
+        let source_uid = o.add(EntityObsolete::ToyAudioSource(Box::new(
+            ToyAudioSource::new_with(&ToyAudioSourceParams { level: 0.1 }),
+        )));
+        assert!(o.connect_to_main_mixer(source_uid).is_ok());
+        let bus_uid = o.create_bus();
+        let gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+            &GainParams {
+                ceiling: Normal::new(0.5),
+            },
+        ))));
+        assert!(o.add_bus_effect(bus_uid, gain_uid).is_ok());
+        assert!(o.add_bus_effect(bus_uid, source_uid).is_err());
+        assert!(o.set_send(source_uid, Uid(9999), Normal::new(1.0)).is_err());
+
+        // With no sends, the bus is silent.
+        let mut samples: [StereoSample; 1] = Default::default();
+        o.gather_audio(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::from(0.1)));
+
+        // The dry signal plus the send, processed by the bus's gain.
+        assert!(o.set_send(source_uid, bus_uid, Normal::new(0.8)).is_ok());
+        assert!(o.is_send_source(source_uid));
+        o.gather_audio(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::from(0.1 + 0.1 * 0.8 * 0.5)));
+
+        // Removing the send removes its contribution.
+        assert!(o.set_send(source_uid, bus_uid, Normal::new(0.0)).is_ok());
+        assert!(!o.is_send_source(source_uid));
+        o.gather_audio(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::from(0.1)));
+    }
+
+    #[test]
+    fn midi_panic_reaches_everything_and_leaves_transport_alone() {
+        const CHANNEL: MidiChannel = MidiChannel(3);
+        let mut o = Orchestrator::new_with(Clock::default());
+        o.update_sample_rate(SampleRate::DEFAULT);
+        let instrument_uid = o.add(EntityObsolete::ToyInstrument(Box::new(
+            ToyInstrument::new_with(&ToyInstrumentParams {
+                fake_value: Normal::from(0.5),
+                dca: DcaParams::default(),
+            }),
+        )));
+        o.connect_midi_downstream(instrument_uid, CHANNEL);
+        o.debug_send_midi_note(CHANNEL, true);
+        o.update(GrooveInput::Seek(MusicalTime::new_with_beats(2)));
+        let frames_before = o.clock().frames();
+
+        let response = o.update(GrooveInput::MidiPanic);
+        let Internal::Batch(events) = response.0 else {
+            panic!("panic should produce a batch of external MIDI events");
+        };
+        assert_eq!(
+            events.len(),
+            16 * 129,
+            "CC 123 plus 128 note-offs per channel"
+        );
+        assert!(events
+            .iter()
+            .all(|e| matches!(e, GrooveEvent::MidiToExternal(..))));
+
+        assert!(!o.is_performing());
+        assert_eq!(o.clock().frames(), frames_before);
+
+        // The instrument was reset rather than left in its release, and it
+        // kept its place in the project.
+        assert!(o.get(instrument_uid).is_some());
+        assert!(o.connect_to_main_mixer(instrument_uid).is_ok());
+        let mut samples: [StereoSample; 4] = Default::default();
+        o.gather_audio(&mut samples);
+        assert!(samples
+            .iter()
+            .all(|s| s.almost_equals(StereoSample::SILENCE)));
+    }
+
+    #[test]
+    fn transpose_shifts_notes_but_not_drums() {
+        const CHANNEL: MidiChannel = MidiChannel(0);
+        let note_on = |key: u8| MidiMessage::NoteOn {
+            key: key.into(),
+            vel: 100.into(),
+        };
+        let note_off = |key: u8| MidiMessage::NoteOff {
+            key: key.into(),
+            vel: 0.into(),
+        };
+
+        let mut o = Orchestrator::new_with(Clock::default());
+        o.set_transpose(5);
+        assert_eq!(o.transpose(), 5);
+
+        // Up a perfect fourth.
+        let mut t = Transposer::default();
+        t.set_semitones(5);
+        assert_eq!(t.transpose(CHANNEL, note_on(60)), Some(note_on(65)));
+
+        // Changing the interval mid-note releases the key that was sent.
+        t.set_semitones(-3);
+        assert_eq!(t.transpose(CHANNEL, note_off(60)), Some(note_off(65)));
+        assert_eq!(t.transpose(CHANNEL, note_off(60)), Some(note_off(57)));
+
+        // Out-of-range notes are dropped rather than clamped to the edge.
+        assert_eq!(t.transpose(CHANNEL, note_on(1)), None);
+        t.set_semitones(12);
+        assert_eq!(t.transpose(CHANNEL, note_on(120)), None);
+
+        // Drums stay put unless asked.
+        assert_eq!(
+            t.transpose(Transposer::DRUM_CHANNEL, note_on(36)),
+            Some(note_on(36))
+        );
+        t.set_transposes_drums(true);
+        assert_eq!(
+            t.transpose(Transposer::DRUM_CHANNEL, note_on(36)),
+            Some(note_on(48))
+        );
+
+        // Other messages pass through.
+        let cc = MidiMessage::Controller {
+            controller: 1.into(),
+            value: 64.into(),
+        };
+        assert_eq!(t.transpose(CHANNEL, cc), Some(cc));
+    }
+
+    #[test]
+    fn armed_entities_monitor_and_capture_input() {
+        const CHANNEL: MidiChannel = MidiChannel(5);
+        let mut o = Orchestrator::new_with(Clock::default());
+        o.update_sample_rate(SampleRate::DEFAULT);
+        let gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+            &GainParams {
+                ceiling: Normal::new(0.5),
+            },
+        ))));
+        assert!(o.patch_chain_to_main_mixer(&[gain_uid]).is_ok());
+        let instrument_uid = o.add(EntityObsolete::ToyInstrument(Box::new(
+            ToyInstrument::new_with(&ToyInstrumentParams {
+                fake_value: Normal::from(0.5),
+                dca: DcaParams::default(),
+            }),
+        )));
+        o.connect_midi_downstream(instrument_uid, CHANNEL);
+        assert!(o.set_record_armed(Uid(9999), true).is_err());
+        assert!(o.set_input_monitoring(gain_uid, true).is_err());
+
+        // Live audio is heard through an armed effect.
+        let mut samples = [StereoSample::default(); 64];
+        o.push_live_input(&[StereoSample::from(0.5); 64]);
+        o.tick(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::default()));
+        assert!(o.set_record_armed(gain_uid, true).is_ok());
+        assert!(o.is_record_armed(gain_uid));
+        o.push_live_input(&[StereoSample::from(0.5); 64]);
+        o.tick(&mut samples);
+        assert!(samples[63].almost_equals(StereoSample::from(0.25)));
+
+        // Without monitoring, it's captured but not heard.
+        assert!(o.set_input_monitoring(gain_uid, false).is_ok());
+        o.push_live_input(&[StereoSample::from(0.5); 64]);
+        o.tick(&mut samples);
+        assert!(samples[0].almost_equals(StereoSample::default()));
+        assert!(
+            (o.monitoring_latency() - 64.0 / SampleRate::DEFAULT.value() as f64).abs() < 1e-9,
+            "an empty input queue leaves just the output buffer"
+        );
+        let recording: RecordArm = o.take_recording(gain_uid).unwrap();
+        assert!(!o.is_record_armed(gain_uid));
+        assert_eq!(recording.captured_audio().len(), 128);
+        assert!(recording.captured_midi().is_empty());
+
+        // External MIDI on another channel still reaches an armed instrument.
+        assert!(o.set_record_armed(instrument_uid, true).is_ok());
+        let note_on = MidiMessage::NoteOn {
+            key: 60.into(),
+            vel: 127.into(),
+        };
+        o.update(GrooveInput::MidiFromExternal(MidiChannel(0), note_on));
+        let recording = o.recording(instrument_uid).unwrap();
+        assert!(recording.is_monitoring());
+        assert_eq!(
+            recording.captured_midi(),
+            &[(o.free_running_frames(), MidiChannel(0), note_on)]
+        );
+    }
+
+    #[test]
+    fn to_dot_describes_the_graph() {
+        let mut o = Orchestrator::new_with(Clock::default());
+        let source_uid = o.add_with_uvid(
+            EntityObsolete::ToyAudioSource(Box::new(ToyAudioSource::new_with(
+                &ToyAudioSourceParams { level: 0.1 },
+            ))),
+            "source",
+        );
+        let gain_uid = o.add(EntityObsolete::Gain(Box::new(Gain::new_with(
+            &GainParams {
+                ceiling: Normal::new(0.5),
+            },
+        ))));
+        assert!(o.patch_chain_to_main_mixer(&[source_uid, gain_uid]).is_ok());
+
+        let dot = o.to_dot();
+        assert!(dot.starts_with("digraph orchestrator {"));
+        assert!(dot.trim_end().ends_with('}'));
+        assert!(dot.contains(&format!("\"{source_uid}\" [label=\"source\\n")));
+        assert!(dot.contains(&format!("\"{source_uid}\" -> \"{gain_uid}\";")));
+        assert!(dot.contains(&format!("\"{gain_uid}\" -> \"{}\";", o.main_mixer_uid())));
+        assert!(!dot.contains("dashed"), "there are no control links yet");
+    }
+
+    #[test]
+    fn midi_stamps_convert_to_frames() {
+        let mut stamper = MidiStamper::new_with(SampleRate::from(48000));
+
+        // The first stamp anchors the clocks, and later ones keep their spacing.
+        assert_eq!(stamper.frame_for(5_000_000, 1000), 1000);
+        assert_eq!(stamper.frame_for(5_001_000, 1000), 1048);
+        assert_eq!(stamper.frame_for(5_010_000, 1256), 1480);
+
+        // A stamp that would land in a buffer that's already gone re-anchors.
+        assert_eq!(stamper.frame_for(5_011_000, 2000), 2000);
+        assert_eq!(stamper.frame_for(5_012_000, 2000), 2048);
+
+        // So does one that's far in the future, or one from before the anchor.
+        assert_eq!(stamper.frame_for(9_000_000, 2048), 2048);
+        assert_eq!(stamper.frame_for(1_000, 2048), 2048);
+    }
+
+    #[test]
+    fn scheduled_midi_lands_at_its_frame() {
+        const CHANNEL: MidiChannel = MidiChannel(0);
+        let mut o = Orchestrator::new_with(Clock::default());
+        o.update_sample_rate(SampleRate::DEFAULT);
+        let instrument_uid = o.add(EntityObsolete::ToyInstrument(Box::new(
+            ToyInstrument::new_with(&ToyInstrumentParams {
+                fake_value: Normal::from(0.5),
+                dca: DcaParams::default(),
+            }),
+        )));
+        o.connect_midi_downstream(instrument_uid, CHANNEL);
+        assert!(o.patch_chain_to_main_mixer(&[instrument_uid]).is_ok());
+
+        let mut samples = [StereoSample::SILENCE; 256];
+        o.tick(&mut samples);
+        let frame = o.free_running_frames() + 100;
+        let note_on = MidiMessage::NoteOn {
+            key: 60.into(),
+            vel: 127.into(),
+        };
+        o.update(GrooveInput::MidiFromExternalAt(frame, CHANNEL, note_on));
+
+        o.tick(&mut samples);
+        assert!(
+            samples[..100].iter().all(|s| *s == StereoSample::SILENCE),
+            "nothing should sound before the scheduled frame"
+        );
+        assert!(
+            samples[100..].iter().any(|s| *s != StereoSample::SILENCE),
+            "the note should start at the scheduled frame, not the next buffer"
+        );
+    }
+
+    #[test]
+    fn seek_moves_clock() {
+        const CHANNEL: MidiChannel = MidiChannel(3);
+        let mut o = Orchestrator::new_with(Clock::default());
+        o.update_sample_rate(SampleRate::DEFAULT);
+        let instrument_uid = o.add(EntityObsolete::ToyInstrument(Box::new(
+            ToyInstrument::new_with(&ToyInstrumentParams {
+                fake_value: Normal::from(0.5),
+                dca: DcaParams::default(),
+            }),
+        )));
+        o.connect_midi_downstream(instrument_uid, CHANNEL);
+        assert!(o.connect_to_main_mixer(instrument_uid).is_ok());
+        o.debug_send_midi_note(CHANNEL, true);
+
+        // The note is held...
+        let mut samples = [StereoSample::SILENCE; 64];
+        o.gather_audio(&mut samples);
+        assert!(
+            samples
+                .iter()
+                .any(|s| !s.almost_equals(StereoSample::SILENCE)),
+            "the instrument should be playing before the seek"
+        );
+
+        // ...until the seek releases it.
+        o.update(GrooveInput::Seek(MusicalTime::new_with_beats(2)));
+        assert!((o.clock().beats() - 2.0).abs() < 0.001);
+        o.gather_audio(&mut samples);
+        assert!(
+            samples
+                .iter()
+                .all(|s| s.almost_equals(StereoSample::SILENCE)),
+            "the seek should have released the held note"
+        );
+
+        o.update(GrooveInput::Seek(MusicalTime::default()));
+        assert_eq!(o.clock().frames(), 0);
+    }
+
     #[test]
     fn patch_fails_with_bad_id() {
         let mut o = Orchestrator::new_with(Clock::default());
@@ -0,0 +1,564 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+//! The [osc](crate::osc) module lets remote control surfaces, like TouchOSC
+//! on a tablet, drive Groove over [OSC](https://opensoundcontrol.stanford.edu/),
+//! and lets remote displays follow along.
+
+use crate::messages::GrooveInput;
+use anyhow::anyhow;
+use crossbeam::channel::{Receiver, Sender};
+use ensnare_core::prelude::*;
+use rosc::{OscBundle, OscMessage, OscPacket, OscTime, OscType};
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Turns an incoming OSC message into the [GrooveInput] that it asks for.
+/// `uid_for_uvid` looks up an entity by the ID it was added with, as
+/// Orchestrator::get_uid_by_uvid() does.
+///
+/// | Address | Arguments | Input |
+/// |---|---|---|
+/// | `/entity/<uvid>/param/<name>` | value, 0.0..=1.0 | [GrooveInput::SetParam] |
+/// | `/transport/play` | | [GrooveInput::Play] |
+/// | `/transport/stop` | | [GrooveInput::Stop] |
+/// | `/transport/skip-to-start` | | [GrooveInput::SkipToStart] |
+/// | `/transport/seek` | position in beats | [GrooveInput::Seek] |
+/// | `/midi/panic` | | [GrooveInput::MidiPanic] |
+///
+/// Buttons on most control surfaces send 1 when pressed and 0 when released.
+/// Only the press counts, so a command whose first argument is zero returns
+/// None.
+pub fn osc_to_groove_input(
+    message: &OscMessage,
+    uid_for_uvid: impl Fn(&str) -> Option<Uid>,
+) -> anyhow::Result<Option<GrooveInput>> {
+    let parts: Vec<&str> = message.addr.trim_start_matches('/').split('/').collect();
+    let first_arg = message.args.first().and_then(number);
+    let input = match parts.as_slice() {
+        ["entity", uvid, "param", param_name] => {
+            let Some(uid) = uid_for_uvid(uvid) else {
+                return Err(anyhow!("couldn't find entity {uvid}"));
+            };
+            let Some(value) = first_arg else {
+                return Err(anyhow!("{} needs a numeric value", message.addr));
+            };
+            return Ok(Some(GrooveInput::SetParam(
+                uid,
+                param_name.to_string(),
+                ControlValue(value),
+            )));
+        }
+        ["transport", "seek"] => {
+            let Some(beats) = first_arg else {
+                return Err(anyhow!("{} needs a position in beats", message.addr));
+            };
+            return Ok(Some(GrooveInput::Seek(MusicalTime::new_with_units(
+                (beats.max(0.0) * MusicalTime::UNITS_IN_BEAT as f64) as usize,
+            ))));
+        }
+        ["transport", "play"] => GrooveInput::Play,
+        ["transport", "stop"] => GrooveInput::Stop,
+        ["transport", "skip-to-start"] => GrooveInput::SkipToStart,
+        ["midi", "panic"] => GrooveInput::MidiPanic,
+        _ => return Err(anyhow!("unrecognized OSC address {}", message.addr)),
+    };
+    if first_arg == Some(0.0) {
+        Ok(None)
+    } else {
+        Ok(Some(input))
+    }
+}
+
+// Control surfaces disagree about which numeric type to send, so take any.
+fn number(arg: &OscType) -> Option<f64> {
+    match arg {
+        OscType::Float(v) => Some(*v as f64),
+        OscType::Double(v) => Some(*v),
+        OscType::Int(v) => Some(*v as f64),
+        OscType::Long(v) => Some(*v as f64),
+        OscType::Bool(v) => Some(if *v { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+// Bundles can nest. Their timetags are ignored, so everything in a bundle
+// takes effect as soon as it arrives.
+fn flatten(packet: OscPacket, messages: &mut Vec<OscMessage>) {
+    match packet {
+        OscPacket::Message(message) => messages.push(message),
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                flatten(packet, messages);
+            }
+        }
+    }
+}
+
+/// Listens for OSC messages on a UDP port, on its own thread, and sends the
+/// [GrooveInput]s they ask for to a channel. The app drains the channel into
+/// Orchestrator::update(). Messages that don't make sense are reported and
+/// dropped. The server stops when it's dropped.
+///
+/// OSC has no authentication, so anyone who can reach the port can drive the
+/// app. The server listens only on this machine unless it's given another
+/// address.
+#[derive(Debug)]
+pub struct OscServer {
+    local_addr: SocketAddr,
+    is_running: Arc<AtomicBool>,
+}
+impl OscServer {
+    /// The port that TouchOSC sends to unless told otherwise.
+    pub const DEFAULT_PORT: u16 = 8000;
+
+    /// Where the server listens unless told otherwise: [Self::DEFAULT_PORT]
+    /// on the loopback interface.
+    pub const DEFAULT_ADDRESS: SocketAddr =
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, Self::DEFAULT_PORT));
+
+    /// How often the listening thread checks whether it should stop.
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Starts listening on `address`. Use 0.0.0.0 as its IP address to accept
+    /// messages from other devices, like a tablet. `uid_for_uvid` resolves
+    /// the entity IDs in addresses; it runs on the listening thread.
+    pub fn start(
+        address: SocketAddr,
+        uid_for_uvid: impl Fn(&str) -> Option<Uid> + Send + 'static,
+    ) -> anyhow::Result<(Self, Receiver<GrooveInput>)> {
+        let socket = UdpSocket::bind(address)
+            .map_err(|e| anyhow!("couldn't listen for OSC on {address}: {e}"))?;
+        socket.set_read_timeout(Some(Self::POLL_INTERVAL))?;
+        let local_addr = socket.local_addr()?;
+        let is_running = Arc::new(AtomicBool::new(true));
+        let (sender, receiver) = crossbeam::channel::unbounded();
+
+        let thread_is_running = Arc::clone(&is_running);
+        std::thread::spawn(move || {
+            Self::listen(socket, thread_is_running, uid_for_uvid, sender);
+        });
+
+        Ok((
+            Self {
+                local_addr,
+                is_running,
+            },
+            receiver,
+        ))
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    fn listen(
+        socket: UdpSocket,
+        is_running: Arc<AtomicBool>,
+        uid_for_uvid: impl Fn(&str) -> Option<Uid>,
+        sender: Sender<GrooveInput>,
+    ) {
+        let mut buffer = [0u8; rosc::decoder::MTU];
+        while is_running.load(Ordering::Relaxed) {
+            let Ok((len, _from)) = socket.recv_from(&mut buffer) else {
+                // Most likely the read timed out.
+                continue;
+            };
+            let packet = match rosc::decoder::decode_udp(&buffer[..len]) {
+                Ok((_, packet)) => packet,
+                Err(e) => {
+                    eprintln!("Warning: couldn't decode OSC packet: {e:?}");
+                    continue;
+                }
+            };
+            let mut messages = Vec::default();
+            flatten(packet, &mut messages);
+            for message in messages {
+                match osc_to_groove_input(&message, &uid_for_uvid) {
+                    Ok(Some(input)) => {
+                        if sender.send(input).is_err() {
+                            // Nobody's listening anymore.
+                            return;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => eprintln!("Warning: {e}"),
+                }
+            }
+        }
+    }
+}
+impl Drop for OscServer {
+    fn drop(&mut self) {
+        self.is_running.store(false, Ordering::Relaxed);
+    }
+}
+
+/// What [OscFeed] reports. The app fills one in from the Orchestrator each
+/// time it calls [OscFeed::update()].
+#[derive(Clone, Debug, Default)]
+pub struct OscFeedSnapshot {
+    #[allow(missing_docs)]
+    pub position: MusicalTime,
+    #[allow(missing_docs)]
+    pub time_signature: TimeSignature,
+    #[allow(missing_docs)]
+    pub tempo: Tempo,
+    #[allow(missing_docs)]
+    pub is_performing: bool,
+    /// The main output's peak level, as from Orchestrator::main_output_level().
+    pub main_level: StereoSample,
+    /// Levels of individual entities, by uvid, as from
+    /// Orchestrator::entity_output_level().
+    pub entity_levels: Vec<(String, StereoSample)>,
+}
+
+/// Sends Groove's state to a remote display over OSC: the playhead position,
+/// tempo, play state, and meter levels.
+///
+/// | Address | Arguments |
+/// |---|---|
+/// | `/transport/position` | bar, beat (both counting from 1), total beats |
+/// | `/transport/tempo` | BPM |
+/// | `/transport/playing` | 1 or 0 |
+/// | `/meter/main` | left, right |
+/// | `/meter/entity/<uvid>` | left, right |
+///
+/// The app can call [OscFeed::update()] as often as it likes, but the feed
+/// sends at most [OscFeed::DEFAULT_RATE_HZ] (or the rate it was created with)
+/// updates per second, each as one bundle, so it doesn't flood the network.
+/// Meters report the peak since the last update rather than whatever level
+/// happened to be current, so short transients still show up. A change in
+/// play state goes out right away, without waiting its turn.
+#[derive(Debug)]
+pub struct OscFeed {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    interval: Duration,
+    last_sent: Option<Instant>,
+    last_is_performing: Option<bool>,
+    main_peak: StereoSample,
+    entity_peaks: Vec<(String, StereoSample)>,
+}
+impl OscFeed {
+    /// Often enough for a smooth meter, and no more.
+    pub const DEFAULT_RATE_HZ: f64 = 30.0;
+
+    /// Creates a feed that sends to `destination` up to `rate_hz` times per
+    /// second.
+    pub fn new_with(destination: SocketAddr, rate_hz: f64) -> anyhow::Result<Self> {
+        if !rate_hz.is_finite() || rate_hz <= 0.0 {
+            return Err(anyhow!("Invalid OSC feed rate {rate_hz}"));
+        }
+        let socket = UdpSocket::bind(("0.0.0.0", 0))
+            .map_err(|e| anyhow!("couldn't open a socket for the OSC feed: {e}"))?;
+        Ok(Self {
+            socket,
+            destination,
+            interval: Duration::from_secs_f64(1.0 / rate_hz),
+            last_sent: None,
+            last_is_performing: None,
+            main_peak: Default::default(),
+            entity_peaks: Default::default(),
+        })
+    }
+
+    #[allow(missing_docs)]
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+
+    /// Takes in the current state, and sends it if it's time to. Returns
+    /// whether it sent anything.
+    pub fn update(&mut self, snapshot: &OscFeedSnapshot, now: Instant) -> bool {
+        if !self.accumulate(snapshot, now) {
+            return false;
+        }
+        let packet = OscPacket::Bundle(OscBundle {
+            timetag: OscTime::from((0, 1)),
+            content: self
+                .messages(snapshot)
+                .into_iter()
+                .map(OscPacket::Message)
+                .collect(),
+        });
+        self.main_peak = Default::default();
+        self.entity_peaks.clear();
+        match rosc::encoder::encode(&packet) {
+            Ok(bytes) => {
+                if let Err(e) = self.socket.send_to(&bytes, self.destination) {
+                    eprintln!("Warning: couldn't send OSC feed: {e}");
+                }
+            }
+            Err(e) => eprintln!("Warning: couldn't encode OSC feed: {e:?}"),
+        }
+        true
+    }
+
+    // Folds the snapshot's levels into the peaks, and decides whether it's
+    // time to send.
+    fn accumulate(&mut self, snapshot: &OscFeedSnapshot, now: Instant) -> bool {
+        self.main_peak = Self::peak(self.main_peak, snapshot.main_level);
+        for (uvid, level) in snapshot.entity_levels.iter() {
+            if let Some((_, peak)) = self.entity_peaks.iter_mut().find(|(u, _)| u == uvid) {
+                *peak = Self::peak(*peak, *level);
+            } else {
+                self.entity_peaks
+                    .push((uvid.clone(), Self::peak(StereoSample::default(), *level)));
+            }
+        }
+
+        let is_due = self.last_sent.map_or(true, |last| {
+            now.saturating_duration_since(last) >= self.interval
+        });
+        let play_state_changed = self.last_is_performing != Some(snapshot.is_performing);
+        if is_due || play_state_changed {
+            self.last_sent = Some(now);
+            self.last_is_performing = Some(snapshot.is_performing);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn messages(&self, snapshot: &OscFeedSnapshot) -> Vec<OscMessage> {
+        let beats_per_bar = snapshot.time_signature.top.max(1);
+        let units = snapshot.position.total_units();
+        let whole_beats = units / MusicalTime::UNITS_IN_BEAT;
+        let total_beats = units as f64 / MusicalTime::UNITS_IN_BEAT as f64;
+        let level =
+            |l: StereoSample| vec![OscType::Float(l.0 .0 as f32), OscType::Float(l.1 .0 as f32)];
+
+        let mut messages = vec![
+            OscMessage {
+                addr: "/transport/position".to_string(),
+                args: vec![
+                    OscType::Int((whole_beats / beats_per_bar + 1) as i32),
+                    OscType::Int((whole_beats % beats_per_bar + 1) as i32),
+                    OscType::Float(total_beats as f32),
+                ],
+            },
+            OscMessage {
+                addr: "/transport/tempo".to_string(),
+                args: vec![OscType::Float(snapshot.tempo.0 as f32)],
+            },
+            OscMessage {
+                addr: "/transport/playing".to_string(),
+                args: vec![OscType::Int(snapshot.is_performing as i32)],
+            },
+            OscMessage {
+                addr: "/meter/main".to_string(),
+                args: level(self.main_peak),
+            },
+        ];
+        messages.extend(self.entity_peaks.iter().map(|(uvid, peak)| OscMessage {
+            addr: format!("/meter/entity/{uvid}"),
+            args: level(*peak),
+        }));
+        messages
+    }
+
+    fn peak(a: StereoSample, b: StereoSample) -> StereoSample {
+        StereoSample(
+            Sample(a.0 .0.max(b.0 .0.abs())),
+            Sample(a.1 .0.max(b.1 .0.abs())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(addr: &str, args: Vec<OscType>) -> OscMessage {
+        OscMessage {
+            addr: addr.to_string(),
+            args,
+        }
+    }
+
+    fn uid_for_uvid(uvid: &str) -> Option<Uid> {
+        (uvid == "synth").then_some(Uid(42))
+    }
+
+    #[test]
+    fn addresses_map_to_inputs() {
+        let input = osc_to_groove_input(
+            &message(
+                "/entity/synth/param/filter-cutoff",
+                vec![OscType::Float(0.5)],
+            ),
+            uid_for_uvid,
+        )
+        .unwrap();
+        let Some(GrooveInput::SetParam(uid, param_name, value)) = input else {
+            panic!("expected SetParam, got {input:?}");
+        };
+        assert_eq!(uid, Uid(42));
+        assert_eq!(param_name, "filter-cutoff");
+        assert_eq!(value.0, 0.5);
+
+        assert!(matches!(
+            osc_to_groove_input(&message("/transport/play", vec![]), uid_for_uvid),
+            Ok(Some(GrooveInput::Play))
+        ));
+        assert!(matches!(
+            osc_to_groove_input(
+                &message("/transport/stop", vec![OscType::Float(1.0)]),
+                uid_for_uvid
+            ),
+            Ok(Some(GrooveInput::Stop))
+        ));
+        let input = osc_to_groove_input(
+            &message("/transport/seek", vec![OscType::Int(8)]),
+            uid_for_uvid,
+        );
+        let Ok(Some(GrooveInput::Seek(time))) = input else {
+            panic!("expected Seek, got {input:?}");
+        };
+        assert_eq!(time, MusicalTime::new_with_beats(8));
+    }
+
+    #[test]
+    fn button_releases_and_bad_messages() {
+        assert!(matches!(
+            osc_to_groove_input(
+                &message("/transport/play", vec![OscType::Float(0.0)]),
+                uid_for_uvid
+            ),
+            Ok(None)
+        ));
+
+        // A fader at zero is still a value.
+        assert!(matches!(
+            osc_to_groove_input(
+                &message("/entity/synth/param/gain", vec![OscType::Float(0.0)]),
+                uid_for_uvid
+            ),
+            Ok(Some(GrooveInput::SetParam(..)))
+        ));
+
+        assert!(osc_to_groove_input(
+            &message("/entity/nobody/param/gain", vec![OscType::Float(0.5)]),
+            uid_for_uvid
+        )
+        .is_err());
+        assert!(osc_to_groove_input(
+            &message(
+                "/entity/synth/param/gain",
+                vec![OscType::String("loud".to_string())]
+            ),
+            uid_for_uvid
+        )
+        .is_err());
+        assert!(osc_to_groove_input(&message("/mixer/fader1", vec![]), uid_for_uvid).is_err());
+    }
+
+    #[test]
+    fn bundles_are_flattened() {
+        let bundle = OscPacket::Bundle(OscBundle {
+            timetag: OscTime::from((0, 1)),
+            content: vec![
+                OscPacket::Message(message("/transport/play", vec![])),
+                OscPacket::Bundle(OscBundle {
+                    timetag: OscTime::from((0, 1)),
+                    content: vec![OscPacket::Message(message("/transport/stop", vec![]))],
+                }),
+            ],
+        });
+        let mut messages = Vec::default();
+        flatten(bundle, &mut messages);
+        let addrs: Vec<&str> = messages.iter().map(|m| m.addr.as_str()).collect();
+        assert_eq!(addrs, vec!["/transport/play", "/transport/stop"]);
+    }
+
+    fn feed() -> OscFeed {
+        OscFeed::new_with(SocketAddr::from(([127, 0, 0, 1], 9)), 10.0).unwrap()
+    }
+
+    #[test]
+    fn feed_is_throttled() {
+        let mut feed = feed();
+        let start = Instant::now();
+        let snapshot = OscFeedSnapshot::default();
+        assert!(
+            feed.accumulate(&snapshot, start),
+            "the first update goes out"
+        );
+        assert!(!feed.accumulate(&snapshot, start + Duration::from_millis(50)));
+        assert!(feed.accumulate(&snapshot, start + Duration::from_millis(100)));
+
+        // A change in play state doesn't wait.
+        let playing = OscFeedSnapshot {
+            is_performing: true,
+            ..Default::default()
+        };
+        assert!(feed.accumulate(&playing, start + Duration::from_millis(110)));
+        assert!(!feed.accumulate(&playing, start + Duration::from_millis(120)));
+
+        assert!(OscFeed::new_with(SocketAddr::from(([127, 0, 0, 1], 9)), 0.0).is_err());
+    }
+
+    #[test]
+    fn feed_reports_position_and_peak_levels() {
+        let mut feed = feed();
+        let start = Instant::now();
+        let mut snapshot = OscFeedSnapshot {
+            // Bar 3, beat 2, in 4/4.
+            position: MusicalTime::new_with_beats(9),
+            tempo: Tempo(128.0),
+            main_level: StereoSample(Sample(0.9), Sample(-0.8)),
+            entity_levels: vec![("synth".to_string(), StereoSample::from(0.5))],
+            ..Default::default()
+        };
+        assert!(feed.accumulate(&snapshot, start));
+        snapshot.main_level = StereoSample::from(0.1);
+        let _ = feed.accumulate(&snapshot, start + Duration::from_millis(10));
+
+        let messages = feed.messages(&snapshot);
+        let addrs: Vec<&str> = messages.iter().map(|m| m.addr.as_str()).collect();
+        assert_eq!(
+            addrs,
+            vec![
+                "/transport/position",
+                "/transport/tempo",
+                "/transport/playing",
+                "/meter/main",
+                "/meter/entity/synth"
+            ]
+        );
+        assert_eq!(messages[0].args[0], OscType::Int(3));
+        assert_eq!(messages[0].args[1], OscType::Int(2));
+        assert_eq!(messages[1].args[0], OscType::Float(128.0));
+        assert_eq!(messages[2].args[0], OscType::Int(0));
+        assert_eq!(
+            messages[3].args,
+            vec![OscType::Float(0.9), OscType::Float(0.8)],
+            "the meter should hold the peak since the last update"
+        );
+    }
+
+    #[test]
+    fn server_listens_on_loopback_by_default() {
+        assert!(OscServer::DEFAULT_ADDRESS.ip().is_loopback());
+
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        let (server, receiver) = OscServer::start(address, uid_for_uvid).unwrap();
+        assert!(server.local_addr().ip().is_loopback());
+
+        let packet = OscPacket::Message(message("/transport/play", vec![]));
+        let bytes = rosc::encoder::encode(&packet).unwrap();
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.send_to(&bytes, server.local_addr()).unwrap();
+        assert!(matches!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok(GrooveInput::Play)
+        ));
+    }
+}
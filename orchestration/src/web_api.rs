@@ -0,0 +1,473 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+//! The [web_api](crate::web_api) module lets a browser front end drive the
+//! engine with JSON over a WebSocket. Each text message from the browser is
+//! an [ApiCommand], and each text message to it is an [ApiEvent], both
+//! tagged with a `type` field, e.g.
+//!
+//! ```json
+//! {"type": "set-param", "entity": "synth", "param": "filter-cutoff", "value": 0.5}
+//! ```
+//!
+//! The schema mirrors [GrooveInput] for commands and the transport and meter
+//! state that the OSC feed reports for events.
+//!
+//! The server listens only on this machine, and it refuses WebSocket upgrades
+//! from pages that aren't served from this machine (see [is_local_origin()]),
+//! so that a web page elsewhere can't drive the app through the user's
+//! browser.
+
+use crate::messages::GrooveInput;
+use anyhow::anyhow;
+use ensnare_core::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// A command from a browser.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ApiCommand {
+    /// [GrooveInput::Play]
+    Play,
+    /// [GrooveInput::Stop]
+    Stop,
+    /// [GrooveInput::SkipToStart]
+    SkipToStart,
+    /// [GrooveInput::Seek]
+    Seek {
+        #[allow(missing_docs)]
+        beats: f64,
+    },
+    /// [GrooveInput::SetParam]. The entity is given by its uvid.
+    SetParam {
+        #[allow(missing_docs)]
+        entity: String,
+        #[allow(missing_docs)]
+        param: String,
+        #[allow(missing_docs)]
+        value: f64,
+    },
+    /// [GrooveInput::Patch], from one entity's output to another's input, by
+    /// uvid.
+    Patch {
+        #[allow(missing_docs)]
+        from: String,
+        #[allow(missing_docs)]
+        to: String,
+    },
+    /// Add a new entity of the given kind. There's no [GrooveInput] for this;
+    /// the app passes it to its orchestrator as it would a choice from the
+    /// palette.
+    AddEntity {
+        #[allow(missing_docs)]
+        key: String,
+    },
+    /// Ask for an [ApiEvent::Entities].
+    ListEntities,
+}
+impl ApiCommand {
+    /// The [GrooveInput] that carries out this command, or None for commands
+    /// that the app handles itself. `uid_for_uvid` looks up an entity as
+    /// Orchestrator::get_uid_by_uvid() does.
+    pub fn to_groove_input(
+        &self,
+        uid_for_uvid: impl Fn(&str) -> Option<Uid>,
+    ) -> anyhow::Result<Option<GrooveInput>> {
+        let uid =
+            |uvid: &str| uid_for_uvid(uvid).ok_or_else(|| anyhow!("couldn't find entity {uvid}"));
+        Ok(Some(match self {
+            ApiCommand::Play => GrooveInput::Play,
+            ApiCommand::Stop => GrooveInput::Stop,
+            ApiCommand::SkipToStart => GrooveInput::SkipToStart,
+            ApiCommand::Seek { beats } => {
+                if !beats.is_finite() || *beats < 0.0 {
+                    return Err(anyhow!("Invalid seek position {beats}"));
+                }
+                GrooveInput::Seek(MusicalTime::new_with_units(
+                    (beats * MusicalTime::UNITS_IN_BEAT as f64) as usize,
+                ))
+            }
+            ApiCommand::SetParam {
+                entity,
+                param,
+                value,
+            } => GrooveInput::SetParam(uid(entity)?, param.clone(), ControlValue(*value)),
+            ApiCommand::Patch { from, to } => GrooveInput::Patch(uid(from)?, uid(to)?),
+            ApiCommand::AddEntity { .. } | ApiCommand::ListEntities => return Ok(None),
+        }))
+    }
+}
+
+/// One entity in an [ApiEvent::Entities].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ApiEntity {
+    #[allow(missing_docs)]
+    pub uid: String,
+    /// The ID the entity was added with, if any. This is what commands use.
+    pub uvid: Option<String>,
+}
+
+/// An event sent to every connected browser.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ApiEvent {
+    /// Where the playhead is. Bar and beat count from 1.
+    Transport {
+        #[allow(missing_docs)]
+        bar: usize,
+        #[allow(missing_docs)]
+        beat: usize,
+        #[allow(missing_docs)]
+        beats: f64,
+        #[allow(missing_docs)]
+        bpm: f64,
+        #[allow(missing_docs)]
+        is_performing: bool,
+    },
+    /// Peak levels, as `[left, right]`.
+    Meters {
+        #[allow(missing_docs)]
+        main: [f64; 2],
+        /// By uvid.
+        entities: Vec<(String, [f64; 2])>,
+    },
+    /// Every entity in the project.
+    Entities {
+        #[allow(missing_docs)]
+        entities: Vec<ApiEntity>,
+    },
+    /// A command couldn't be carried out.
+    Error {
+        #[allow(missing_docs)]
+        message: String,
+    },
+}
+impl ApiEvent {
+    /// Builds an [ApiEvent::Transport].
+    pub fn transport(
+        position: MusicalTime,
+        time_signature: &TimeSignature,
+        tempo: Tempo,
+        is_performing: bool,
+    ) -> Self {
+        let beats_per_bar = time_signature.top.max(1);
+        let units = position.total_units();
+        let whole_beats = units / MusicalTime::UNITS_IN_BEAT;
+        ApiEvent::Transport {
+            bar: whole_beats / beats_per_bar + 1,
+            beat: whole_beats % beats_per_bar + 1,
+            beats: units as f64 / MusicalTime::UNITS_IN_BEAT as f64,
+            bpm: tempo.0,
+            is_performing,
+        }
+    }
+
+    /// Builds an [ApiEvent::Meters].
+    pub fn meters(main: StereoSample, entities: &[(String, StereoSample)]) -> Self {
+        let level = |s: StereoSample| [s.0 .0, s.1 .0];
+        ApiEvent::Meters {
+            main: level(main),
+            entities: entities
+                .iter()
+                .map(|(uvid, s)| (uvid.clone(), level(*s)))
+                .collect(),
+        }
+    }
+}
+
+/// Whether a browser's `Origin` header names a page served from this
+/// machine, like `http://localhost:3000` or `http://127.0.0.1`.
+pub fn is_local_origin(origin: &str) -> bool {
+    let Some(authority) = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+    else {
+        return false;
+    };
+    let host = authority
+        .rsplit_once(':')
+        .filter(|(_, port)| port.parse::<u16>().is_ok())
+        .map_or(authority, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+#[cfg(feature = "websocket")]
+pub use server::WebApiServer;
+
+#[cfg(feature = "websocket")]
+mod server {
+    use super::{is_local_origin, ApiCommand, ApiEvent};
+    use async_std::{
+        channel::Sender as AsyncSender,
+        net::{TcpListener, TcpStream},
+        task,
+    };
+    use async_tungstenite::tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        http::StatusCode,
+        Message,
+    };
+    use crossbeam::channel::{Receiver, Sender};
+    use futures::{SinkExt, StreamExt};
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::{Arc, Mutex},
+    };
+
+    type Clients = Arc<Mutex<Vec<AsyncSender<String>>>>;
+
+    /// Serves the JSON API over WebSocket. It runs an async-std executor on
+    /// its own thread. Commands from every browser arrive on one channel for
+    /// the app to handle; events that the app publishes go to every browser.
+    /// A command that doesn't parse gets an [ApiEvent::Error] back, to the
+    /// browser that sent it.
+    #[derive(Debug)]
+    pub struct WebApiServer {
+        local_addr: SocketAddr,
+        clients: Clients,
+    }
+    impl WebApiServer {
+        #[allow(missing_docs)]
+        pub const DEFAULT_PORT: u16 = 8080;
+
+        /// Starts listening on `port` of the loopback interface. Returns the
+        /// server and the receiving end of the commands that browsers send.
+        pub fn start(port: u16) -> anyhow::Result<(Self, Receiver<ApiCommand>)> {
+            let listener = task::block_on(TcpListener::bind((Ipv4Addr::LOCALHOST, port)))?;
+            let local_addr = listener.local_addr()?;
+            let clients = Clients::default();
+            let (sender, receiver) = crossbeam::channel::unbounded();
+
+            let thread_clients = Arc::clone(&clients);
+            std::thread::spawn(move || {
+                task::block_on(Self::accept(listener, thread_clients, sender));
+            });
+
+            Ok((
+                Self {
+                    local_addr,
+                    clients,
+                },
+                receiver,
+            ))
+        }
+
+        /// The address the server is listening on.
+        pub fn local_addr(&self) -> SocketAddr {
+            self.local_addr
+        }
+
+        /// Whether any browser is connected, so the app can skip building
+        /// events that nobody will see.
+        pub fn has_clients(&self) -> bool {
+            self.clients
+                .lock()
+                .map(|clients| !clients.is_empty())
+                .unwrap_or_default()
+        }
+
+        /// Sends the event to every connected browser.
+        pub fn publish(&self, event: &ApiEvent) {
+            let json = match serde_json::to_string(event) {
+                Ok(json) => json,
+                Err(e) => {
+                    eprintln!("Warning: couldn't serialize {event:?}: {e}");
+                    return;
+                }
+            };
+            if let Ok(mut clients) = self.clients.lock() {
+                // A client that has gone away has dropped its receiver.
+                clients.retain(|client| client.try_send(json.clone()).is_ok());
+            }
+        }
+
+        async fn accept(listener: TcpListener, clients: Clients, commands: Sender<ApiCommand>) {
+            while let Some(stream) = listener.incoming().next().await {
+                match stream {
+                    Ok(stream) => {
+                        task::spawn(Self::serve(stream, Arc::clone(&clients), commands.clone()));
+                    }
+                    Err(e) => eprintln!("Warning: WebSocket connection failed: {e}"),
+                }
+            }
+        }
+
+        async fn serve(stream: TcpStream, clients: Clients, commands: Sender<ApiCommand>) {
+            let websocket =
+                match async_tungstenite::accept_hdr_async(stream, Self::check_origin).await {
+                    Ok(websocket) => websocket,
+                    Err(e) => {
+                        eprintln!("Warning: WebSocket handshake failed: {e}");
+                        return;
+                    }
+                };
+            let (mut outgoing, mut incoming) = websocket.split();
+            let (events, event_receiver) = async_std::channel::unbounded();
+            if let Ok(mut clients) = clients.lock() {
+                clients.push(events.clone());
+            }
+
+            let writer = task::spawn(async move {
+                while let Ok(json) = event_receiver.recv().await {
+                    if outgoing.send(Message::Text(json)).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            while let Some(Ok(message)) = incoming.next().await {
+                let Message::Text(text) = message else {
+                    continue;
+                };
+                match serde_json::from_str::<ApiCommand>(&text) {
+                    Ok(command) => {
+                        if commands.send(command).is_err() {
+                            // The app has gone away.
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let error = ApiEvent::Error {
+                            message: format!("couldn't parse command: {e}"),
+                        };
+                        if let Ok(json) = serde_json::to_string(&error) {
+                            let _ = events.send(json).await;
+                        }
+                    }
+                }
+            }
+            writer.cancel().await;
+        }
+
+        // Browsers always send an Origin header, so a request without one
+        // didn't come from a web page.
+        #[allow(clippy::result_large_err)]
+        fn check_origin(request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+            let Some(origin) = request.headers().get("origin") else {
+                return Ok(response);
+            };
+            if origin.to_str().is_ok_and(is_local_origin) {
+                Ok(response)
+            } else {
+                let mut error = ErrorResponse::new(Some("origin not allowed".to_string()));
+                *error.status_mut() = StatusCode::FORBIDDEN;
+                Err(error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uid_for_uvid(uvid: &str) -> Option<Uid> {
+        match uvid {
+            "synth" => Some(Uid(1)),
+            "reverb" => Some(Uid(2)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn commands_parse_and_map_to_inputs() {
+        let command: ApiCommand = serde_json::from_str(r#"{"type": "play"}"#).unwrap();
+        assert_eq!(command, ApiCommand::Play);
+        assert!(matches!(
+            command.to_groove_input(uid_for_uvid),
+            Ok(Some(GrooveInput::Play))
+        ));
+
+        let command: ApiCommand = serde_json::from_str(
+            r#"{"type": "set-param", "entity": "synth", "param": "filter-cutoff", "value": 0.5}"#,
+        )
+        .unwrap();
+        let input = command.to_groove_input(uid_for_uvid).unwrap();
+        let Some(GrooveInput::SetParam(uid, param, value)) = input else {
+            panic!("expected SetParam, got {input:?}");
+        };
+        assert_eq!(
+            (uid, param.as_str(), value.0),
+            (Uid(1), "filter-cutoff", 0.5)
+        );
+
+        let command: ApiCommand =
+            serde_json::from_str(r#"{"type": "patch", "from": "synth", "to": "reverb"}"#).unwrap();
+        assert!(matches!(
+            command.to_groove_input(uid_for_uvid),
+            Ok(Some(GrooveInput::Patch(Uid(1), Uid(2))))
+        ));
+
+        let command: ApiCommand = serde_json::from_str(r#"{"type": "seek", "beats": 8}"#).unwrap();
+        let input = command.to_groove_input(uid_for_uvid).unwrap();
+        let Some(GrooveInput::Seek(time)) = input else {
+            panic!("expected Seek, got {input:?}");
+        };
+        assert_eq!(time, MusicalTime::new_with_beats(8));
+
+        // The app handles these itself.
+        let command: ApiCommand =
+            serde_json::from_str(r#"{"type": "add-entity", "key": "toy-synth"}"#).unwrap();
+        assert!(matches!(command.to_groove_input(uid_for_uvid), Ok(None)));
+
+        let command = ApiCommand::Patch {
+            from: "synth".to_string(),
+            to: "nobody".to_string(),
+        };
+        assert!(command.to_groove_input(uid_for_uvid).is_err());
+        assert!(serde_json::from_str::<ApiCommand>(r#"{"type": "dance"}"#).is_err());
+    }
+
+    #[test]
+    fn only_local_origins_are_allowed() {
+        for origin in [
+            "http://localhost",
+            "http://localhost:3000",
+            "https://LOCALHOST:8443",
+            "http://127.0.0.1:8080",
+            "http://[::1]",
+            "http://[::1]:5173",
+        ] {
+            assert!(is_local_origin(origin), "{origin} should be allowed");
+        }
+        for origin in [
+            "http://example.com",
+            "https://localhost.example.com",
+            "http://192.168.1.20:8080",
+            "http://[2001:db8::1]:80",
+            "file://",
+            "null",
+            "",
+        ] {
+            assert!(!is_local_origin(origin), "{origin} should be refused");
+        }
+    }
+
+    #[test]
+    fn events_serialize_with_a_type_tag() {
+        let event = ApiEvent::transport(
+            MusicalTime::new_with_beats(9),
+            &TimeSignature::default(),
+            Tempo(128.0),
+            true,
+        );
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "type": "transport",
+                "bar": 3,
+                "beat": 2,
+                "beats": 9.0,
+                "bpm": 128.0,
+                "is_performing": true,
+            })
+        );
+
+        let event = ApiEvent::meters(StereoSample(Sample(0.5), Sample(0.25)), &[]);
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"type": "meters", "main": [0.5, 0.25], "entities": []})
+        );
+    }
+}
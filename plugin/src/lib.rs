@@ -0,0 +1,333 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+#![warn(missing_docs)]
+
+//! The `groove-plugin` crate builds a Groove patch, one instrument and its
+//! effects chain, as a VST3 and CLAP plugin, so that it can be played inside
+//! another DAW. It's the inverse of
+//! [HostedPlugin](groove::mini::HostedPlugin).
+//!
+//! The patch is compiled in from `patch.json` (see [PluginPatch]). When the
+//! host initializes the plugin, it builds an [Orchestrator] with a single MIDI
+//! track holding the patch's chain. From then on, the host's MIDI goes to that
+//! track, its tempo goes to the orchestrator, and the orchestrator's output
+//! goes to the host. The patch's macros show up as automatable parameters.
+
+pub use patch::{MacroSettings, MacroTargetSettings, PluginPatch, MAX_MACROS};
+
+mod patch;
+
+use anyhow::anyhow;
+use ensnare_core::{
+    entities::EntityFactory,
+    midi::{MidiChannel, MidiMessage},
+    orchestration::{Orchestrator, OrchestratorBuilder},
+    prelude::*,
+    traits::prelude::*,
+};
+use groove::mini::{register_mini_entities, MacroControl};
+use midly::live::LiveEvent;
+use nih_plug::prelude::*;
+use std::{num::NonZeroU32, sync::Arc};
+
+/// The host-visible parameters: one knob per macro.
+#[derive(Params)]
+pub struct GrooveParams {
+    #[nested(array, group = "Macros")]
+    macros: [MacroParam; MAX_MACROS],
+}
+impl GrooveParams {
+    fn new_with(patch: &PluginPatch) -> Self {
+        Self {
+            macros: std::array::from_fn(|i| {
+                let name = patch
+                    .macros
+                    .get(i)
+                    .map(|m| m.name.clone())
+                    .unwrap_or_else(|| format!("Macro {} (unused)", i + 1));
+                MacroParam {
+                    value: FloatParam::new(name, 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+                }
+            }),
+        }
+    }
+}
+
+#[derive(Params)]
+struct MacroParam {
+    #[id = "value"]
+    value: FloatParam,
+}
+
+/// The plugin. Everything that touches the engine happens in the host's
+/// audio thread, except for building the [Orchestrator], which happens in
+/// initialize().
+pub struct GroovePlugin {
+    params: Arc<GrooveParams>,
+    patch: PluginPatch,
+    orchestrator: Option<Orchestrator>,
+    macros: Vec<MacroControl>,
+
+    // Preallocated in initialize() so that process() doesn't allocate.
+    buffer: Vec<StereoSample>,
+    macro_updates: Vec<(Uid, ControlIndex, ControlValue)>,
+}
+impl Default for GroovePlugin {
+    fn default() -> Self {
+        let patch = PluginPatch::new_from_json(PluginPatch::BUILT_IN)
+            .expect("the built-in patch should be valid");
+        Self {
+            params: Arc::new(GrooveParams::new_with(&patch)),
+            patch,
+            orchestrator: None,
+            macros: Default::default(),
+            buffer: Default::default(),
+            macro_updates: Default::default(),
+        }
+    }
+}
+impl GroovePlugin {
+    /// Builds an [Orchestrator] with one MIDI track holding the patch's chain.
+    /// Returns it along with the [Uid]s of the chain's entities, in order.
+    pub fn build_orchestrator(patch: &PluginPatch) -> anyhow::Result<(Orchestrator, Vec<Uid>)> {
+        // Fails harmlessly if a previous instance already did it.
+        let _ = EntityFactory::initialize(ensnare_not_core::register_factory_entities(
+            register_mini_entities(EntityFactory::default()),
+        ));
+
+        let mut o = OrchestratorBuilder::default().build()?;
+        let track_uid = o.new_midi_track()?;
+        let track = o
+            .get_track_mut(&track_uid)
+            .ok_or_else(|| anyhow!("couldn't find the new track"))?;
+        let mut uids = Vec::default();
+        for key in patch.chain() {
+            let entity = EntityFactory::global()
+                .new_entity(&EntityKey::from(key.as_str()))
+                .ok_or_else(|| anyhow!("unknown entity key {key}"))?;
+            uids.push(track.append_entity(entity)?);
+        }
+        Ok((o, uids))
+    }
+
+    // Sends the macro knobs that moved since last time to their targets, or
+    // all of them if `force` is set.
+    fn apply_macros(&mut self, force: bool) {
+        let Some(o) = self.orchestrator.as_mut() else {
+            return;
+        };
+        for (control, param) in self.macros.iter_mut().zip(self.params.macros.iter()) {
+            let value = Normal::from(param.value.value() as f64);
+            control.set_value_into(value, &mut self.macro_updates);
+            if force {
+                control.updates_into(&mut self.macro_updates);
+            }
+            for &(uid, index, value) in &self.macro_updates {
+                if let Some(controllable) =
+                    o.get_entity_mut(&uid).and_then(|e| e.as_controllable_mut())
+                {
+                    controllable.control_set_param_by_index(index, value);
+                }
+            }
+        }
+    }
+}
+
+/// Translates a host note event into the MIDI message that the engine
+/// understands. Events without a MIDI equivalent, like polyphonic modulation,
+/// come back as None.
+pub fn midi_from_note_event<S: SysExMessage>(
+    event: NoteEvent<S>,
+) -> Option<(MidiChannel, MidiMessage)> {
+    let MidiResult::Basic(bytes) = event.as_midi()? else {
+        return None;
+    };
+    // Program change and channel pressure have one data byte, not two.
+    let len = match bytes[0] & 0xf0 {
+        0xc0 | 0xd0 => 2,
+        _ => 3,
+    };
+    match LiveEvent::parse(&bytes[..len]).ok()? {
+        LiveEvent::Midi { channel, message } => Some((MidiChannel(channel.as_int()), message)),
+        _ => None,
+    }
+}
+
+impl Plugin for GroovePlugin {
+    const NAME: &'static str = "Groove";
+    const VENDOR: &'static str = "Mike Tsao";
+    const URL: &'static str = "https://github.com/sowbug/groove";
+    const EMAIL: &'static str = "mike@sowbug.com";
+    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
+        main_input_channels: None,
+        main_output_channels: NonZeroU32::new(2),
+        ..AudioIOLayout::const_default()
+    }];
+    const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
+
+    type SysExMessage = ();
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn initialize(
+        &mut self,
+        _audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        match Self::build_orchestrator(&self.patch) {
+            Ok((mut o, uids)) => {
+                o.update_sample_rate(SampleRate(buffer_config.sample_rate as usize));
+                self.macros = self.patch.macro_controls(&uids);
+                self.orchestrator = Some(o);
+                self.buffer = vec![StereoSample::SILENCE; buffer_config.max_buffer_size as usize];
+                self.macro_updates = Vec::with_capacity(
+                    self.macros
+                        .iter()
+                        .map(|m| m.targets().len())
+                        .max()
+                        .unwrap_or_default(),
+                );
+
+                // Bring the targets in line with whatever the host restored.
+                self.apply_macros(true);
+                true
+            }
+            Err(e) => {
+                nih_error!("couldn't build patch {}: {e}", self.patch.name);
+                false
+            }
+        }
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        self.apply_macros(false);
+        let Some(o) = self.orchestrator.as_mut() else {
+            return ProcessStatus::Error("not initialized");
+        };
+        if let Some(bpm) = context.transport().tempo {
+            if o.tempo().0 != bpm {
+                o.update_tempo(Tempo(bpm));
+            }
+        }
+
+        // Render up to each event, then handle it, so that notes start where
+        // the host put them rather than at the top of the buffer.
+        let frame_count = buffer.samples().min(self.buffer.len());
+        let outputs = buffer.as_slice();
+        let mut next_event = context.next_event();
+        let mut start = 0;
+        while start < frame_count {
+            while let Some(event) = next_event {
+                if event.timing() as usize > start {
+                    break;
+                }
+                if let Some((channel, message)) = midi_from_note_event(event) {
+                    o.handle_midi_message(channel, message, &mut |_, _| {});
+                }
+                next_event = context.next_event();
+            }
+            let end = next_event
+                .map(|e| e.timing() as usize)
+                .unwrap_or(frame_count)
+                .min(frame_count);
+            let frames = &mut self.buffer[start..end];
+            o.generate_batch_values(frames);
+            for (i, sample) in frames.iter().enumerate() {
+                outputs[0][start + i] = sample.0 .0 as f32;
+                outputs[1][start + i] = sample.1 .0 as f32;
+            }
+            start = end;
+        }
+        ProcessStatus::KeepAlive
+    }
+}
+
+impl ClapPlugin for GroovePlugin {
+    const CLAP_ID: &'static str = "com.sowbug.groove";
+    const CLAP_DESCRIPTION: Option<&'static str> = Some("A Groove patch");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::Instrument,
+        ClapFeature::Synthesizer,
+        ClapFeature::Stereo,
+    ];
+}
+
+impl Vst3Plugin for GroovePlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"SowbugGroovePtch";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Instrument, Vst3SubCategory::Synth];
+}
+
+nih_export_clap!(GroovePlugin);
+nih_export_vst3!(GroovePlugin);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn note_events_become_midi() {
+        let on: NoteEvent<()> = NoteEvent::NoteOn {
+            timing: 0,
+            voice_id: None,
+            channel: 3,
+            note: 60,
+            velocity: 1.0,
+        };
+        assert_eq!(
+            midi_from_note_event(on),
+            Some((
+                MidiChannel(3),
+                MidiMessage::NoteOn {
+                    key: 60.into(),
+                    vel: 127.into()
+                }
+            ))
+        );
+
+        let program: NoteEvent<()> = NoteEvent::MidiProgramChange {
+            timing: 0,
+            channel: 0,
+            program: 5,
+        };
+        assert_eq!(
+            midi_from_note_event(program),
+            Some((
+                MidiChannel(0),
+                MidiMessage::ProgramChange { program: 5.into() }
+            ))
+        );
+
+        let modulation: NoteEvent<()> = NoteEvent::PolyModulation {
+            timing: 0,
+            voice_id: 1,
+            poly_modulation_id: 0,
+            normalized_offset: 0.5,
+        };
+        assert_eq!(midi_from_note_event(modulation), None);
+    }
+
+    #[test]
+    fn unused_macros_are_labeled() {
+        let patch = PluginPatch::new_from_json(PluginPatch::BUILT_IN).unwrap();
+        let params = GrooveParams::new_with(&patch);
+        assert_eq!(params.macros[0].value.name(), patch.macros[0].name);
+        assert_eq!(
+            params.macros[MAX_MACROS - 1].value.name(),
+            format!("Macro {MAX_MACROS} (unused)")
+        );
+    }
+}
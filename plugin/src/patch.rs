@@ -0,0 +1,167 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use anyhow::anyhow;
+use ensnare_core::prelude::*;
+use groove::mini::MacroControl;
+use serde::{Deserialize, Serialize};
+
+/// The most macro knobs that a plugin can show the host. Plugin formats want
+/// a fixed parameter list, so there are always this many, and the ones that
+/// the patch doesn't use do nothing.
+pub const MAX_MACROS: usize = 8;
+
+/// The part of an arrangement that gets built into the plugin: one instrument,
+/// the effects that follow it, and the macro knobs that the host can automate.
+/// Entities are named by their [EntityFactory](ensnare_core::entities::EntityFactory)
+/// keys, as in the palette.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PluginPatch {
+    /// What the host calls the plugin's program.
+    pub name: String,
+
+    #[allow(missing_docs)]
+    pub instrument: String,
+
+    /// Applied in order after the instrument.
+    #[serde(default)]
+    pub effects: Vec<String>,
+
+    #[allow(missing_docs)]
+    #[serde(default)]
+    pub macros: Vec<MacroSettings>,
+}
+
+/// One of the host-visible knobs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MacroSettings {
+    #[allow(missing_docs)]
+    pub name: String,
+
+    #[allow(missing_docs)]
+    #[serde(default)]
+    pub targets: Vec<MacroTargetSettings>,
+}
+
+/// A parameter that a macro knob moves. See [MacroTarget](groove::mini::MacroTarget).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MacroTargetSettings {
+    /// Which entity in the chain: 0 is the instrument, 1 is the first effect,
+    /// and so on.
+    pub entity: usize,
+
+    /// The index of the entity's #[control] parameter.
+    pub control: usize,
+
+    /// From -1.0 to 1.0, as in [MacroTarget](groove::mini::MacroTarget).
+    #[serde(default = "MacroTargetSettings::default_depth")]
+    pub depth: f64,
+}
+impl MacroTargetSettings {
+    fn default_depth() -> f64 {
+        1.0
+    }
+}
+
+impl PluginPatch {
+    /// The patch that's compiled into the plugin. Edit `patch.json` and
+    /// rebuild to export a different one.
+    pub const BUILT_IN: &'static str = include_str!("../patch.json");
+
+    /// Parses and checks a patch.
+    pub fn new_from_json(json: &str) -> anyhow::Result<Self> {
+        let patch: Self =
+            serde_json::from_str(json).map_err(|e| anyhow!("couldn't parse patch: {e}"))?;
+        patch.validate()?;
+        Ok(patch)
+    }
+
+    /// Keys of the instrument and then the effects, in chain order.
+    pub fn chain(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.instrument).chain(self.effects.iter())
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.macros.len() > MAX_MACROS {
+            return Err(anyhow!(
+                "patch has {} macros, but plugins can have at most {MAX_MACROS}",
+                self.macros.len()
+            ));
+        }
+        let entity_count = 1 + self.effects.len();
+        for m in self.macros.iter() {
+            for target in m.targets.iter() {
+                if target.entity >= entity_count {
+                    return Err(anyhow!(
+                        "macro {} targets entity #{}, but the chain has only {entity_count}",
+                        m.name,
+                        target.entity
+                    ));
+                }
+                if !(-1.0..=1.0).contains(&target.depth) {
+                    return Err(anyhow!(
+                        "macro {} has depth {}, which is outside -1.0..=1.0",
+                        m.name,
+                        target.depth
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds a [MacroControl] for each macro, given the [Uid]s that the chain's
+    /// entities got, in chain order.
+    pub fn macro_controls(&self, uids: &[Uid]) -> Vec<MacroControl> {
+        self.macros
+            .iter()
+            .map(|m| {
+                let mut control = MacroControl::default();
+                for target in m.targets.iter() {
+                    if let Some(uid) = uids.get(target.entity) {
+                        control.add_target(
+                            *uid,
+                            ControlIndex(target.control),
+                            BipolarNormal::from(target.depth),
+                        );
+                    }
+                }
+                control
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_patch_is_valid() {
+        let patch = PluginPatch::new_from_json(PluginPatch::BUILT_IN).unwrap();
+        assert!(patch.macros.len() <= MAX_MACROS);
+        assert_eq!(patch.chain().count(), 1 + patch.effects.len());
+    }
+
+    #[test]
+    fn macros_must_target_the_chain() {
+        let json = r#"{
+            "name": "test",
+            "instrument": "welsh-synth",
+            "effects": ["reverb"],
+            "macros": [{ "name": "m", "targets": [{ "entity": 2, "control": 0 }] }]
+        }"#;
+        assert!(PluginPatch::new_from_json(json).is_err());
+
+        let json = json.replace("\"entity\": 2", "\"entity\": 1");
+        let patch = PluginPatch::new_from_json(&json).unwrap();
+        assert_eq!(
+            patch.macros[0].targets[0].depth, 1.0,
+            "depth defaults to 1.0"
+        );
+
+        let controls = patch.macro_controls(&[Uid(7), Uid(8)]);
+        assert_eq!(controls.len(), 1);
+        assert_eq!(controls[0].targets()[0].target_uid, Uid(8));
+        assert_eq!(controls[0].targets()[0].control_index, ControlIndex(0));
+    }
+}
@@ -1,13 +1,15 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
-use crate::core_crate_name;
+use crate::{core_crate_name, groove_crate_name};
 use convert_case::{Case, Casing};
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
 use std::collections::HashSet;
 use syn::{
-    parse_macro_input, Attribute, Data, DataStruct, DeriveInput, Fields, Ident, Lit, Meta,
-    NestedMeta,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Attribute, Data, DataStruct, DeriveInput, Fields, Ident, Lit, Token,
 };
 
 // TODO: see
@@ -23,6 +25,7 @@ pub(crate) fn impl_control_derive(input: TokenStream, primitives: &HashSet<Ident
         let struct_name = &input.ident;
         let (_impl_generics, ty_generics, _where_clause) = generics.split_for_impl();
         let core_crate = format_ident!("{}", core_crate_name());
+        let groove_crate = format_ident!("{}", groove_crate_name());
 
         // Code adapted from https://blog.turbo.fish/proc-macro-error-handling/
         // Thank you!
@@ -33,6 +36,8 @@ pub(crate) fn impl_control_derive(input: TokenStream, primitives: &HashSet<Ident
             }) => &fields.named,
             _ => panic!("this derive macro works only on structs with named fields"),
         };
+        let mut field_attributes = Vec::default();
+        let mut errors: Option<syn::Error> = None;
         let attr_fields = fields.into_iter().fold(Vec::default(), |mut v, f| {
             let attrs: Vec<_> = f
                 .attrs
@@ -40,11 +45,25 @@ pub(crate) fn impl_control_derive(input: TokenStream, primitives: &HashSet<Ident
                 .filter(|attr| attr.path.is_ident("control"))
                 .collect();
             if !attrs.is_empty() {
-                let is_leaf = parse_control_meta(attrs[0]);
+                let control_attributes = match parse_control_meta(attrs[0]) {
+                    Ok(control_attributes) => control_attributes,
+                    Err(e) => {
+                        match errors.as_mut() {
+                            Some(errors) => errors.combine(e),
+                            None => errors = Some(e),
+                        }
+                        ControlAttributes::default()
+                    }
+                };
                 match &f.ty {
                     syn::Type::Path(t) => {
                         if let Some(ident) = t.path.get_ident() {
-                            v.push((f.ident.as_ref().unwrap().clone(), ident.clone(), is_leaf));
+                            v.push((
+                                f.ident.as_ref().unwrap().clone(),
+                                ident.clone(),
+                                control_attributes.is_leaf,
+                            ));
+                            field_attributes.push(control_attributes);
                         }
                     }
                     _ => todo!(),
@@ -52,6 +71,9 @@ pub(crate) fn impl_control_derive(input: TokenStream, primitives: &HashSet<Ident
             }
             v
         });
+        if let Some(errors) = errors {
+            return errors.to_compile_error().into();
+        }
 
         // FOO_SIZE = 3; self.foo contains 3 controlled fields
         fn size_const_id(ident: &Ident) -> Ident {
@@ -82,7 +104,20 @@ pub(crate) fn impl_control_derive(input: TokenStream, primitives: &HashSet<Ident
             if primitives.contains(ident_type) || *is_leaf {
                 size_const_values.push(quote! { 1 });
             } else {
-                size_const_values.push(quote! { #ident_type::STRUCT_SIZE });
+                // #[derive(Control)] types have an inherent STRUCT_SIZE, which
+                // takes precedence over the blanket trait's. Anything else
+                // (like a plain enum) falls through to the trait and counts as
+                // a single leaf.
+                size_const_values.push(quote! {
+                    {
+                        #[allow(dead_code)]
+                        trait LeafStructSize {
+                            const STRUCT_SIZE: usize = 1;
+                        }
+                        impl<T: ?Sized> LeafStructSize for T {}
+                        <#ident_type>::STRUCT_SIZE
+                    }
+                });
             }
         });
         let size_const_body = quote! {
@@ -151,24 +186,171 @@ pub(crate) fn impl_control_derive(input: TokenStream, primitives: &HashSet<Ident
             }
         };
 
+        // A leaf with a declared range receives a ControlValue that's scaled
+        // from 0..=1 to that range before it's converted to the field's type,
+        // so that #[control(min = -24.0, max = 24.0)] gain: f64 is set in dB.
+        let mut from_control_values = Vec::default();
+        let mut into_control_values = Vec::default();
+        field_attributes.iter().for_each(|attributes| {
+            if attributes.has_range() {
+                let min = attributes.min.unwrap_or(0.0);
+                let max = attributes.max.unwrap_or(1.0);
+                from_control_values.push(quote! {
+                    #core_crate::control::ControlValue(#min + value.0 * (#max - #min))
+                });
+                into_control_values.push(quote! {
+                    {
+                        let value: #core_crate::control::ControlValue = value.into();
+                        #core_crate::control::ControlValue((value.0 - #min) / (#max - #min))
+                    }
+                });
+            } else {
+                from_control_values.push(quote! { value });
+                into_control_values.push(quote! { value.into() });
+            }
+        });
+
         let mut id_bodies = Vec::default();
         let mut setter_bodies = Vec::default();
-        attr_fields.iter().for_each(|(ident, ident_type, is_leaf)| {
+        attr_fields.iter().enumerate().for_each(|(i, (ident, ident_type, is_leaf))| {
             let id = ident.to_string().to_case(Case::Kebab);
+            let from_control_value = &from_control_values[i];
             if primitives.contains(ident_type) || *is_leaf {
                 let name_const = format_ident!("set_{}", ident);
                 id_bodies.push(quote! {Some(#id.to_string())});
-                setter_bodies.push(quote! {self.#name_const(value.into());});
+                setter_bodies.push(quote! {self.#name_const(#from_control_value.into());});
             } else {
+                // We can't tell from here whether the field's type is itself
+                // #[derive(Control)], so we generate code that asks at compile
+                // time. See node_fallback_body below.
                 let field_index_name = index_const_id(ident);
                 let name_const = name_const_id(ident);
-                id_bodies.push(quote! { Some(format!("{}-{}", Self::#name_const, self.#ident.control_name_for_index(#core_crate::control::ControlIndex(index.0 - Self::#field_index_name)).unwrap()))});
-                setter_bodies
-                    .push(quote! {self.#ident.control_set_param_by_index(#core_crate::control::ControlIndex(index.0 - Self::#field_index_name), value);});
+                // A leaf of a non-primitive type is assigned directly unless
+                // the field opts into its setter with #[control(setter=true)].
+                // Node types never get here at runtime, but this still has to
+                // compile for them, so it mustn't require a setter that a
+                // node-typed field has no reason to have.
+                let assign_leaf = if field_attributes[i].has_setter {
+                    let setter_name = format_ident!("set_{}", ident);
+                    quote! { self.#setter_name(value); }
+                } else {
+                    quote! { self.#ident = value; }
+                };
+                id_bodies.push(quote! {
+                    if let Some(node) = self.#ident.__control_as_node() {
+                        Some(format!("{}-{}", Self::#name_const, node.control_name_for_index(#core_crate::control::ControlIndex(index.0 - Self::#field_index_name)).unwrap()))
+                    } else {
+                        Some(#id.to_string())
+                    }
+                });
+                if field_attributes[i].has_range() {
+                    // A ranged field is a leaf by definition. Its type's own
+                    // From<ControlValue> might apply a range of its own, so
+                    // the scaled value goes through f64 instead, as with
+                    // #[control(min = 20.0, max = 20000.0)] frequency: FrequencyHz.
+                    setter_bodies.push(quote! {
+                        let value = <#ident_type>::from(#from_control_value.0);
+                        #assign_leaf
+                    });
+                } else {
+                    setter_bodies.push(quote! {
+                        if let Some(node) = self.#ident.__control_as_node_mut() {
+                            node.control_set_param_by_index(#core_crate::control::ControlIndex(index.0 - Self::#field_index_name), value);
+                        } else if let Some(value) = <#ident_type>::__control_leaf_from(value) {
+                            #assign_leaf
+                        }
+                    });
+                }
+            }
+        });
+
+        // Types that #[derive(Control)] get inherent __control_* methods (see
+        // below), which win method resolution over these blanket traits.
+        // Everything else uses the traits, and is treated as a leaf that
+        // converts from ControlValue.
+        let node_fallback_body = quote! {
+            #[allow(dead_code)]
+            trait ControlNodeFallback {
+                fn __control_as_node(&self) -> Option<&dyn #core_crate::traits::Controllable> {
+                    None
+                }
+                fn __control_as_node_mut(&mut self) -> Option<&mut dyn #core_crate::traits::Controllable> {
+                    None
+                }
+            }
+            impl<T: ?Sized> ControlNodeFallback for T {}
+            #[allow(dead_code)]
+            trait ControlLeafFallback: Sized {
+                fn __control_leaf_from(value: #core_crate::control::ControlValue) -> Option<Self>;
+            }
+            impl<T: From<#core_crate::control::ControlValue>> ControlLeafFallback for T {
+                fn __control_leaf_from(value: #core_crate::control::ControlValue) -> Option<Self> {
+                    Some(value.into())
+                }
+            }
+            #[allow(dead_code)]
+            trait ControlLeafIntoFallback {
+                fn __control_leaf_into(self) -> Option<#core_crate::control::ControlValue>;
+            }
+            impl<T: Into<#core_crate::control::ControlValue>> ControlLeafIntoFallback for T {
+                fn __control_leaf_into(self) -> Option<#core_crate::control::ControlValue> {
+                    Some(self.into())
+                }
+            }
+        };
+
+        // control_value_to_foo() and foo_to_control_value() let a UI translate
+        // between a ControlValue and the field's own type without knowing
+        // which setter or conversion the field uses.
+        let mut converter_methods = Vec::default();
+        attr_fields.iter().enumerate().for_each(|(i, (ident, ident_type, is_leaf))| {
+            let from_name = format_ident!("control_value_to_{}", ident);
+            let from_control_value = &from_control_values[i];
+            let into_control_value = &into_control_values[i];
+            let into_name = format_ident!("{}_to_control_value", ident);
+            if primitives.contains(ident_type) || *is_leaf {
+                converter_methods.push(quote! {
+                    #[allow(missing_docs)]
+                    pub fn #from_name(&self, value: #core_crate::control::ControlValue) -> #ident_type {
+                        #from_control_value.into()
+                    }
+                    #[allow(missing_docs)]
+                    pub fn #into_name(&self, value: #ident_type) -> #core_crate::control::ControlValue {
+                        #into_control_value
+                    }
+                });
+            } else if field_attributes[i].has_range() {
+                let min = field_attributes[i].min.unwrap_or(0.0);
+                let max = field_attributes[i].max.unwrap_or(1.0);
+                converter_methods.push(quote! {
+                    #[allow(missing_docs)]
+                    pub fn #from_name(&self, value: #core_crate::control::ControlValue) -> #ident_type {
+                        <#ident_type>::from(#from_control_value.0)
+                    }
+                    #[allow(missing_docs)]
+                    pub fn #into_name(&self, value: #ident_type) -> #core_crate::control::ControlValue {
+                        #core_crate::control::ControlValue((f64::from(value) - #min) / (#max - #min))
+                    }
+                });
+            } else {
+                let message = format!("{} is a #[derive(Control)] node, not a leaf", ident);
+                converter_methods.push(quote! {
+                    /// Panics if the field is a #[derive(Control)] node.
+                    pub fn #from_name(&self, value: #core_crate::control::ControlValue) -> #ident_type {
+                        #node_fallback_body
+                        <#ident_type>::__control_leaf_from(value).expect(#message)
+                    }
+                    /// Panics if the field is a #[derive(Control)] node.
+                    pub fn #into_name(&self, value: #ident_type) -> #core_crate::control::ControlValue {
+                        #node_fallback_body
+                        value.__control_leaf_into().expect(#message)
+                    }
+                });
             }
         });
         let control_name_for_index_body = quote! {
             fn control_name_for_index(&self, index: #core_crate::control::ControlIndex) -> Option<String> {
+                #node_fallback_body
                 match index.0 {
                     #( Self::#index_const_ids..=Self::#index_const_range_end_ids => {#id_bodies}, )*
                     _ => {None},
@@ -177,6 +359,7 @@ pub(crate) fn impl_control_derive(input: TokenStream, primitives: &HashSet<Ident
         };
         let control_set_param_by_index_bodies = quote! {
             fn control_set_param_by_index(&mut self, index: #core_crate::control::ControlIndex, value: #core_crate::control::ControlValue) {
+                #node_fallback_body
                 match index.0 {
                     #( Self::#index_const_ids..=Self::#index_const_range_end_ids => {#setter_bodies}, )*
                     _ => {},
@@ -209,14 +392,19 @@ pub(crate) fn impl_control_derive(input: TokenStream, primitives: &HashSet<Ident
         });
         let control_index_for_name_body = quote! {
             fn control_index_for_name(&self, name: &str) -> Option<#core_crate::control::ControlIndex> {
+                #node_fallback_body
                 match name {
                     #( #leaf_names => Some(#core_crate::control::ControlIndex(#leaf_indexes)), )*
                     _ => {
                         #(
-                            if name.starts_with(#node_names) {
-                                if let Some(r) = self.#node_fields.control_index_for_name(&name[#node_field_lens..]) {
-                                    return Some(#core_crate::control::ControlIndex(r.0 + #node_indexes))
+                            if let Some(node) = self.#node_fields.__control_as_node() {
+                                if name.starts_with(#node_names) {
+                                    if let Some(r) = node.control_index_for_name(&name[#node_field_lens..]) {
+                                        return Some(#core_crate::control::ControlIndex(r.0 + #node_indexes))
+                                    }
                                 }
+                            } else if name == #node_names {
+                                return Some(#core_crate::control::ControlIndex(#node_indexes))
                             }
                         )*
                         None
@@ -225,6 +413,71 @@ pub(crate) fn impl_control_derive(input: TokenStream, primitives: &HashSet<Ident
             }
         };
 
+        // Describes each leaf's range and unit so that a UI can lay out an
+        // editor for an entity it knows nothing else about. Nodes delegate to
+        // the nested struct, then substitute the fully qualified name and index.
+        let mut descriptor_bodies = Vec::default();
+        attr_fields
+            .iter()
+            .zip(field_attributes.iter())
+            .for_each(|((ident, ident_type, is_leaf), attributes)| {
+                let min = attributes.min.unwrap_or(0.0);
+                let max = attributes.max.unwrap_or(1.0);
+                let default = attributes.default.unwrap_or(min);
+                let unit = attributes.unit.clone().unwrap_or_default();
+                let leaf_descriptor = quote! {
+                    #groove_crate::control::ParamDescriptor {
+                        name,
+                        index,
+                        min: #min,
+                        max: #max,
+                        default: #default,
+                        unit: #unit.to_string(),
+                    }
+                };
+                if primitives.contains(ident_type) || *is_leaf {
+                    descriptor_bodies.push(quote! { Some(#leaf_descriptor) });
+                } else {
+                    let field_index_name = index_const_id(ident);
+                    descriptor_bodies.push(quote! {
+                        if let Some(mut descriptor) = self.#ident.control_param_descriptor(#core_crate::control::ControlIndex(index.0 - Self::#field_index_name)) {
+                            descriptor.name = name;
+                            descriptor.index = index;
+                            Some(descriptor)
+                        } else {
+                            Some(#leaf_descriptor)
+                        }
+                    });
+                }
+            });
+        let control_param_descriptor_body = quote! {
+            /// Returns the name, range, default, and unit of the control at
+            /// the given index.
+            pub fn control_param_descriptor(&self, index: #core_crate::control::ControlIndex) -> Option<#groove_crate::control::ParamDescriptor> {
+                #[allow(dead_code)]
+                trait ControlDescriptorFallback {
+                    fn control_param_descriptor(&self, _index: #core_crate::control::ControlIndex) -> Option<#groove_crate::control::ParamDescriptor> {
+                        None
+                    }
+                }
+                impl<T: ?Sized> ControlDescriptorFallback for T {}
+
+                let name = #core_crate::traits::Controllable::control_name_for_index(self, index)?;
+                match index.0 {
+                    #( Self::#index_const_ids..=Self::#index_const_range_end_ids => {#descriptor_bodies}, )*
+                    _ => None,
+                }
+            }
+
+            /// Returns descriptors for all this struct's controls, in index
+            /// order.
+            pub fn control_param_descriptors(&self) -> Vec<#groove_crate::control::ParamDescriptor> {
+                (0..Self::STRUCT_SIZE)
+                    .filter_map(|i| self.control_param_descriptor(#core_crate::control::ControlIndex(i)))
+                    .collect()
+            }
+        };
+
         let quote = quote! {
             #[automatically_derived]
             impl #generics #struct_name #ty_generics {
@@ -232,6 +485,27 @@ pub(crate) fn impl_control_derive(input: TokenStream, primitives: &HashSet<Ident
                 #main_const_body
                 #range_const_body
                 #struct_size_const_body
+
+                #[doc(hidden)]
+                pub fn __control_as_node(&self) -> Option<&dyn #core_crate::traits::Controllable> {
+                    Some(self)
+                }
+                #[doc(hidden)]
+                pub fn __control_as_node_mut(&mut self) -> Option<&mut dyn #core_crate::traits::Controllable> {
+                    Some(self)
+                }
+                #[doc(hidden)]
+                pub fn __control_leaf_from(_value: #core_crate::control::ControlValue) -> Option<Self> {
+                    None
+                }
+                #[doc(hidden)]
+                pub fn __control_leaf_into(self) -> Option<#core_crate::control::ControlValue> {
+                    None
+                }
+
+                #( #converter_methods )*
+
+                #control_param_descriptor_body
             }
             #[automatically_derived]
             impl #generics #core_crate::traits::Controllable for #struct_name #ty_generics {
@@ -252,42 +526,150 @@ pub(crate) fn impl_control_derive(input: TokenStream, primitives: &HashSet<Ident
     })
 }
 
-fn parse_control_meta(attr: &Attribute) -> bool {
-    let mut is_leaf = false;
-    if let Ok(meta) = attr.parse_meta() {
-        let meta_list = match meta {
-            Meta::List(list) => list,
-            _ => {
-                return is_leaf;
-            }
-        };
+#[derive(Debug, Default)]
+struct ControlAttributes {
+    is_leaf: bool,
+    has_setter: bool,
+    min: Option<f64>,
+    max: Option<f64>,
+    default: Option<f64>,
+    unit: Option<String>,
+}
+impl ControlAttributes {
+    fn has_range(&self) -> bool {
+        self.min.is_some() || self.max.is_some()
+    }
+}
 
-        let punctuated = match meta_list.nested.len() {
-            0 => return is_leaf,
-            _ => &meta_list.nested,
-        };
+// One `name = value` inside #[control(...)]. A number may have a leading
+// minus sign, which a plain syn::Lit can't.
+struct ControlArg {
+    name: Ident,
+    minus: Option<Token![-]>,
+    value: Lit,
+}
+impl Parse for ControlArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        Ok(Self {
+            name,
+            minus: input.parse()?,
+            value: input.parse()?,
+        })
+    }
+}
+impl ControlArg {
+    fn error(&self, message: &str) -> syn::Error {
+        syn::Error::new(self.value.span(), format!("`{}` {message}", self.name))
+    }
 
-        punctuated.iter().for_each(|nested| {
-            if let NestedMeta::Meta(Meta::NameValue(name_value)) = nested {
-                if name_value.path.is_ident("leaf") {
-                    is_leaf = get_bool_from_lit(name_value);
-                } else {
-                    // Unsupported attribute; ignore
-                }
-            } else {
-                // Unexpected stuff; ignore
+    fn bool(&self) -> syn::Result<bool> {
+        match (&self.minus, &self.value) {
+            (None, Lit::Bool(b)) => Ok(b.value()),
+            _ => Err(self.error("must be true or false")),
+        }
+    }
+
+    fn f64(&self) -> syn::Result<f64> {
+        let value = match &self.value {
+            Lit::Float(f) => f.base10_parse::<f64>().ok(),
+            Lit::Int(i) => i.base10_parse::<f64>().ok(),
+            // Before negative numbers could be written directly, they were
+            // written as strings, like "-24.0".
+            Lit::Str(s) if self.minus.is_none() => s.value().parse::<f64>().ok(),
+            _ => None,
+        };
+        match value {
+            Some(value) if value.is_finite() => {
+                Ok(if self.minus.is_some() { -value } else { value })
             }
-        });
+            _ => Err(self.error("must be a number")),
+        }
+    }
+
+    fn string(&self) -> syn::Result<String> {
+        match (&self.minus, &self.value) {
+            (None, Lit::Str(s)) => Ok(s.value()),
+            _ => Err(self.error("must be a string")),
+        }
     }
-    is_leaf
 }
 
-fn get_bool_from_lit(name_value: &syn::MetaNameValue) -> bool {
-    match &name_value.lit {
-        Lit::Bool(bool_val) => {
-            return bool_val.value();
+fn parse_control_meta(attr: &Attribute) -> syn::Result<ControlAttributes> {
+    let mut attributes = ControlAttributes::default();
+    if attr.tokens.is_empty() {
+        return Ok(attributes);
+    }
+    let args = attr.parse_args_with(Punctuated::<ControlArg, Token![,]>::parse_terminated)?;
+    for arg in args.iter() {
+        if arg.name == "leaf" {
+            attributes.is_leaf = arg.bool()?;
+        } else if arg.name == "setter" {
+            attributes.has_setter = arg.bool()?;
+        } else if arg.name == "min" {
+            attributes.min = Some(arg.f64()?);
+        } else if arg.name == "max" {
+            attributes.max = Some(arg.f64()?);
+        } else if arg.name == "default" {
+            attributes.default = Some(arg.f64()?);
+        } else if arg.name == "unit" {
+            attributes.unit = Some(arg.string()?);
+        } else {
+            // Unsupported attribute; ignore
+        }
+    }
+
+    // The range is used to scale a ControlValue, so an empty or backward one
+    // would divide by zero or flip the control.
+    if attributes.has_range() {
+        let min = attributes.min.unwrap_or(0.0);
+        let max = attributes.max.unwrap_or(1.0);
+        if min >= max {
+            return Err(syn::Error::new_spanned(
+                attr,
+                format!("#[control] min ({min}) must be less than max ({max})"),
+            ));
         }
-        _ => {}
     }
-    false
+    Ok(attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn negative_numbers_are_accepted() {
+        let attr: Attribute = parse_quote!(#[control(min = -24.0, max = 24, default = -6.5)]);
+        let attributes = parse_control_meta(&attr).unwrap();
+        assert_eq!(attributes.min, Some(-24.0));
+        assert_eq!(attributes.max, Some(24.0));
+        assert_eq!(attributes.default, Some(-6.5));
+
+        let attr: Attribute = parse_quote!(#[control(min = "-24.0", max = 24.0, unit = "dB")]);
+        let attributes = parse_control_meta(&attr).unwrap();
+        assert_eq!(attributes.min, Some(-24.0));
+        assert_eq!(attributes.unit.as_deref(), Some("dB"));
+
+        let attr: Attribute = parse_quote!(#[control]);
+        assert!(parse_control_meta(&attr).is_ok());
+    }
+
+    #[test]
+    fn bad_ranges_and_values_are_errors() {
+        let attr: Attribute = parse_quote!(#[control(min = 1.0, max = 1.0)]);
+        assert!(parse_control_meta(&attr).is_err(), "min == max");
+        let attr: Attribute = parse_quote!(#[control(min = 10.0, max = -10.0)]);
+        assert!(parse_control_meta(&attr).is_err(), "min > max");
+        let attr: Attribute = parse_quote!(#[control(min = 2.0)]);
+        assert!(parse_control_meta(&attr).is_err(), "min > the default max");
+        let attr: Attribute = parse_quote!(#[control(min = "loud", max = 1.0)]);
+        assert!(parse_control_meta(&attr).is_err(), "unparseable");
+        let attr: Attribute = parse_quote!(#[control(max = 'x')]);
+        assert!(parse_control_meta(&attr).is_err(), "not a number");
+        let attr: Attribute = parse_quote!(#[control(setter = -true)]);
+        assert!(parse_control_meta(&attr).is_err());
+    }
 }
@@ -32,10 +32,12 @@ pub fn uid_derive(input: TokenStream) -> TokenStream {
 /// generates a FooParams struct containing all the fields annotated #[params].
 /// It automatically converts fields whose types are #[derive(Params)] to Params
 /// structs as well. So a `#[derive(Params)] struct Foo` with `#[params] bar:
-/// Bar` will generate `struct FooParams` with `bar: BarParams`. It tries to
-/// handle primitives automatically. If you have a type that doesn't need its
-/// own XxxParams struct (e.g., a NewType(u32)), then you should annotate with
-/// #[params(leaf=true)], and it will be treated as a primitive.
+/// Bar` will generate `struct FooParams` with `bar: BarParams`. Any other
+/// [Copy] type (e.g., an enum or a NewType(u32)) is treated as a leaf and used
+/// as is. That's decided through `groove::params::ParamsOf`, so the deriving
+/// crate needs to depend on groove, and a #[derive(Params)] struct can't be
+/// [Copy]. #[params(leaf=true)] is still accepted to force a field to be a
+/// primitive-style leaf.
 #[proc_macro_derive(Params, attributes(params))]
 pub fn params_derive(input: TokenStream) -> TokenStream {
     impl_params_derive(input, &make_primitives())
@@ -117,8 +119,23 @@ fn make_primitives() -> HashSet<Ident> {
 }
 
 /// The [Control] macro derives the code that allows automation (one entity's
-/// output driving another entity's control). Annotate a field with
-/// #[control(leaf=true)] if it is neither a primitive nor #[derive(Control)].
+/// output driving another entity's control). Fields whose types are
+/// #[derive(Control)] are flattened into the parent's control indexes. Any
+/// other type (such as an enum that implements `From<ControlValue>`) is
+/// treated as a single leaf, and is assigned directly, or through the field's
+/// `set_xxx()` method if annotated #[control(setter=true)]. Primitives are
+/// always set through `set_xxx()`. #[control(leaf=true)] is still accepted to
+/// force a field to be a primitive-style leaf.
+///
+/// Leaf fields can also describe their range and unit for UIs, e.g.,
+/// #[control(min=20.0, max=20000.0, default=1000.0, unit="Hz")] or
+/// #[control(min=-24.0, max=24.0)]. A ranged field's setter gets values in
+/// that range rather than 0..1. A ranged field of a non-primitive type is
+/// always a leaf, and converts to and from f64. Fields without a range default to 0..1. A
+/// range whose min isn't less than its max, or a value that isn't a number,
+/// is a compile error. See `control_param_descriptor()`, which returns a
+/// `groove::control::ParamDescriptor`, so the deriving crate needs to depend on
+/// groove.
 #[proc_macro_derive(Control, attributes(control))]
 pub fn derive_control(input: TokenStream) -> TokenStream {
     impl_control_derive(input, &make_primitives())
@@ -134,6 +151,11 @@ fn core_crate_name() -> String {
     // const CORE_CRATE_NAME: &str = "ensnare"; // if you named it with dashes -- my-crate
     // const CORE_CRATE_NAME_FOR_USE: &str = "ensnare"; // substitute underscores for dashes -- my_crate
 
+    // Crates that have already moved to ensnare get the same traits from
+    // ensnare-core.
+    const MIGRATED_CORE_CRATE_NAME: &str = "ensnare-core";
+    const MIGRATED_CORE_CRATE_NAME_FOR_USE: &str = "ensnare_core";
+
     if let Ok(found_crate) = crate_name(CORE_CRATE_NAME) {
         match found_crate {
             proc_macro_crate::FoundCrate::Itself => {
@@ -147,7 +169,25 @@ fn core_crate_name() -> String {
                 quote!(#ident).to_string()
             }
         }
+    } else if crate_name(MIGRATED_CORE_CRATE_NAME).is_ok() {
+        let ident = format_ident!("{}", MIGRATED_CORE_CRATE_NAME_FOR_USE);
+        quote!(#ident).to_string()
     } else {
         panic!("forgot to import {}", CORE_CRATE_NAME);
     }
 }
+
+// Like core_crate_name(), but for the groove crate, which hosts the support
+// types (like ParamDescriptor) that generated code refers to.
+fn groove_crate_name() -> String {
+    const GROOVE_CRATE_NAME: &str = "groove";
+
+    match crate_name(GROOVE_CRATE_NAME) {
+        Ok(proc_macro_crate::FoundCrate::Itself) => quote!(crate).to_string(),
+        Ok(proc_macro_crate::FoundCrate::Name(name)) => {
+            let ident = format_ident!("{}", name);
+            quote!(#ident).to_string()
+        }
+        Err(_) => panic!("forgot to import {}", GROOVE_CRATE_NAME),
+    }
+}
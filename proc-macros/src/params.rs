@@ -2,7 +2,7 @@
 
 use std::collections::HashSet;
 
-use crate::core_crate_name;
+use crate::{core_crate_name, groove_crate_name};
 use convert_case::{Case, Casing};
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
@@ -58,16 +58,23 @@ pub(crate) fn impl_params_derive(input: TokenStream, primitives: &HashSet<Ident>
         let mut setter_methods = Vec::default();
         let mut to_params_exprs = Vec::default();
         let _core_crate = format_ident!("{}", core_crate_name());
+        let groove_crate = format_ident!("{}", groove_crate_name());
+        let params_of = quote! { #groove_crate::params::ParamsOf };
         for (field_name, field_type, is_leaf) in attr_fields {
             let field_name_pascal_case =
                 format_ident!("{}", field_name.to_string().to_case(Case::Pascal),);
             variant_names.push(field_name_pascal_case.clone());
             field_names.push(field_name.clone());
             let is_leaf_or_primitive = primitives.contains(&field_type) || is_leaf;
+
+            // Anything else might be a #[derive(Params)] struct, which maps to
+            // its XxxParams, or a Copy type like an enum, which maps to
+            // itself. We can't tell which from here, so ParamsOf decides at
+            // compile time.
             let field_params_type = if is_leaf_or_primitive {
-                field_type.clone()
+                quote! { #field_type }
             } else {
-                format_ident!("{}Params", &field_type)
+                quote! { <#field_type as #params_of>::Params }
             };
 
             getter_methods.push(if is_leaf_or_primitive {
@@ -82,7 +89,9 @@ pub(crate) fn impl_params_derive(input: TokenStream, primitives: &HashSet<Ident>
                 }
             } else {
                 quote! {
-                    pub fn #field_name(&self) -> &#field_params_type { &self.#field_name }
+                    pub fn #field_name(&self) -> <#field_type as #params_of>::Get<'_> {
+                        <#field_type as #params_of>::params_get(&self.#field_name)
+                    }
                 }
             });
             to_params_exprs.push(if is_leaf_or_primitive {
@@ -92,7 +101,7 @@ pub(crate) fn impl_params_derive(input: TokenStream, primitives: &HashSet<Ident>
                     quote! { self.#field_name() }
                 }
             } else {
-                quote! { self.#field_name.to_params() }
+                quote! { #params_of::params_of(&self.#field_name) }
             });
             field_types.push(field_params_type.clone());
             let setter_method_name = format_ident!("set_{}", field_name.to_string());
@@ -139,6 +148,21 @@ pub(crate) fn impl_params_derive(input: TokenStream, primitives: &HashSet<Ident>
             }
         };
 
+        let params_of_block = quote! {
+            impl #generics #params_of for #struct_name #ty_generics {
+                type Params = #params_name;
+                type Get<'a> = &'a #params_name;
+
+                fn params_of(&self) -> #params_name {
+                    self.to_params()
+                }
+
+                fn params_get(params: &#params_name) -> &#params_name {
+                    params
+                }
+            }
+        };
+
         quote! {
             #[automatically_derived]
             #params_struct_block
@@ -146,6 +170,8 @@ pub(crate) fn impl_params_derive(input: TokenStream, primitives: &HashSet<Ident>
             #getter_setter_block
             #[automatically_derived]
             #to_params_block
+            #[automatically_derived]
+            #params_of_block
         }
     })
 }
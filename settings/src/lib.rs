@@ -8,13 +8,15 @@
 //! set of serialized structs separate from the inner engine helps reduce
 //! file-format churn.
 
-use ensnare::time::BeatValue;
+use ensnare::time::{BeatValue, TimeSignature};
 //pub use songs::SongSettings;
 
 pub mod controllers;
 pub(crate) mod effects;
 pub(crate) mod instruments;
+pub mod notes;
 pub(crate) mod patches;
+pub mod recovery;
 #[cfg(obsolete)]
 pub(crate) mod songs;
 
@@ -22,6 +24,7 @@ use self::{
     controllers::{ControlTargetSettings, ControllerSettings},
     effects::EffectSettings,
     instruments::InstrumentSettings,
+    notes::{parse_note_with, OctaveConvention},
 };
 use groove_core::{midi::MidiChannel, time::PerfectTimeUnit};
 use groove_entities::controllers::{Note, Pattern};
@@ -35,7 +38,71 @@ pub enum LoadError {
     #[allow(dead_code)]
     FileError,
     FormatError,
+    /// A patch or control link refers to a device that the project doesn't
+    /// have.
+    MissingDevice(DeviceId),
+    /// The time signature can't be used. The string says why.
+    InvalidTimeSignature(String),
+    /// The project has dangling references or duplicate IDs. Every problem
+    /// that validation found is listed.
+    Invalid(Vec<ValidationError>),
 }
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::FileError => write!(f, "couldn't read the file"),
+            LoadError::FormatError => write!(f, "the file isn't in the expected format"),
+            LoadError::MissingDevice(id) => write!(f, "no device has the ID '{id}'"),
+            LoadError::InvalidTimeSignature(reason) => write!(f, "{reason}"),
+            LoadError::Invalid(problems) => {
+                write!(f, "the project has {} problem(s): ", problems.len())?;
+                for (i, problem) in problems.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{problem}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+impl std::error::Error for LoadError {}
+
+/// A problem that `SongSettings::validate()` found in a project.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    /// More than one device, pattern, track, path, trip, or control has this
+    /// ID.
+    DuplicateId(DeviceId),
+    /// Something refers to a device that the project doesn't have. The first
+    /// string says what.
+    UnknownDevice(String, DeviceId),
+    /// A track (first) lists a pattern (second) that the project doesn't have.
+    UnknownPattern(DeviceId, DeviceId),
+    /// A control trip (first) lists a path (second) that the project doesn't
+    /// have.
+    UnknownPath(DeviceId, DeviceId),
+}
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::DuplicateId(id) => {
+                write!(f, "more than one thing has the ID '{id}'")
+            }
+            ValidationError::UnknownDevice(referrer, id) => {
+                write!(f, "{referrer} references unknown device '{id}'")
+            }
+            ValidationError::UnknownPattern(track, pattern) => {
+                write!(f, "track {track} references unknown pattern '{pattern}'")
+            }
+            ValidationError::UnknownPath(trip, path) => {
+                write!(f, "trip {trip} references unknown path '{path}'")
+            }
+        }
+    }
+}
+impl std::error::Error for ValidationError {}
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -45,13 +112,65 @@ pub enum DeviceSettings {
     Effect(DeviceId, EffectSettings),
 }
 
+/// Notes are written as MIDI note numbers, but when reading, names like "C#4"
+/// work too. See [parse_note_with()].
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", try_from = "PatternSettingsFile")]
 pub struct PatternSettings {
     pub id: DeviceId,
     pub note_value: Option<BeatValueSettings>,
     pub notes: Vec<Vec<u8>>,
 }
+
+/// A note in a project file, either as a MIDI note number or as a name.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum NoteSettings {
+    Key(u8),
+    Name(String),
+}
+
+/// [PatternSettings] as it appears in a project file, before note names are
+/// resolved.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct PatternSettingsFile {
+    id: DeviceId,
+    note_value: Option<BeatValueSettings>,
+    /// Which octave middle C is in, for note names.
+    #[serde(default)]
+    octave_convention: OctaveConvention,
+    notes: Vec<Vec<NoteSettings>>,
+}
+impl TryFrom<PatternSettingsFile> for PatternSettings {
+    type Error = String;
+
+    fn try_from(file: PatternSettingsFile) -> Result<Self, Self::Error> {
+        let notes = file
+            .notes
+            .into_iter()
+            .map(|sequence| {
+                sequence
+                    .into_iter()
+                    .map(|note| match note {
+                        NoteSettings::Key(key) if key <= 127 => Ok(key),
+                        NoteSettings::Key(key) => Err(format!(
+                            "pattern {}: note {key} is outside the MIDI range",
+                            file.id
+                        )),
+                        NoteSettings::Name(name) => parse_note_with(&name, file.octave_convention)
+                            .ok_or_else(|| format!("pattern {}: '{name}' isn't a note", file.id)),
+                    })
+                    .collect::<Result<Vec<u8>, String>>()
+            })
+            .collect::<Result<Vec<Vec<u8>>, String>>()?;
+        Ok(Self {
+            id: file.id,
+            note_value: file.note_value,
+            notes,
+        })
+    }
+}
 impl PatternSettings {
     pub fn into_pattern(&self) -> Pattern<Note> {
         let note_value = self
@@ -95,6 +214,24 @@ pub struct ControlSettings {
     pub target: ControlTargetSettings,
 }
 
+/// A single virtual audio cable from one device's output to another's input.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PatchSettings {
+    pub output: DeviceId,
+    pub input: DeviceId,
+}
+
+/// Like [ControlSettings], but it names the target parameter by its index
+/// rather than its name, so that it works for parameters that don't have one.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ControlLinkSettings {
+    pub source: DeviceId,
+    pub target: DeviceId,
+    pub control_index: usize,
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct TimeSignatureSettings {
@@ -106,17 +243,31 @@ impl Default for TimeSignatureSettings {
         Self { top: 4, bottom: 4 }
     }
 }
-// #[allow(clippy::from_over_into)]
-// impl Into<TimeSignature> for TimeSignatureSettings {
-//     fn into(self) -> TimeSignature {
-//         let r = TimeSignature::new_with(self.top, self.bottom);
-//         if let Ok(ts) = r {
-//             ts
-//         } else {
-//             panic!("Failed to instantiate TimeSignature: {}", r.err().unwrap())
-//         }
-//     }
-// }
+impl TryFrom<TimeSignatureSettings> for TimeSignature {
+    type Error = LoadError;
+
+    // Project files are hand-edited, so we check the common mistakes
+    // ourselves to give a better message than TimeSignature::new_with() would.
+    fn try_from(value: TimeSignatureSettings) -> Result<Self, Self::Error> {
+        if value.top == 0 {
+            return Err(LoadError::InvalidTimeSignature(
+                "time signature top must be at least 1".to_string(),
+            ));
+        }
+        if !value.bottom.is_power_of_two() {
+            return Err(LoadError::InvalidTimeSignature(format!(
+                "time signature bottom must be a power of two, not {}",
+                value.bottom
+            )));
+        }
+        TimeSignature::new_with(value.top, value.bottom).map_err(|e| {
+            LoadError::InvalidTimeSignature(format!(
+                "time signature {}/{} is invalid: {e}",
+                value.top, value.bottom
+            ))
+        })
+    }
+}
 
 #[derive(Clone, Debug, Default, Deserialize, FromRepr, Serialize)]
 #[serde(rename_all = "kebab-case")]
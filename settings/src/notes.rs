@@ -0,0 +1,127 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+//! Note names, as people write them in project files.
+
+use serde::{Deserialize, Serialize};
+
+/// Which octave number middle C (MIDI note 60) gets. Manufacturers never
+/// agreed on this, so "C3" means middle C in some DAWs and an octave below it
+/// in others.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OctaveConvention {
+    /// Scientific pitch notation: middle C is C4, and MIDI note 0 is C-1.
+    #[default]
+    C4,
+
+    /// The Yamaha convention: middle C is C3, and MIDI note 0 is C-2.
+    C3,
+}
+impl OctaveConvention {
+    /// The octave number of MIDI note 0.
+    fn lowest_octave(&self) -> i32 {
+        match self {
+            OctaveConvention::C4 => -1,
+            OctaveConvention::C3 => -2,
+        }
+    }
+}
+
+/// Parses a note name like `C#4`, `Db4`, or `c4`, or a bare MIDI note number
+/// like `61`, into a MIDI note number, with middle C as C4. See
+/// [parse_note_with()].
+pub fn parse_note(s: &str) -> Option<u8> {
+    parse_note_with(s, OctaveConvention::default())
+}
+
+/// Parses a note name or MIDI note number, with the given octave convention.
+///
+/// A name is a letter from A to G, any number of sharps (`#`) or any number of
+/// flats (`b`), and an octave number, which may be negative. Accidentals can
+/// cross octave lines, so `Cb4` is B3. The octave is required, because without
+/// it the note is ambiguous. Anything that isn't a note, or that's outside the
+/// MIDI range of 0..=127, is None.
+pub fn parse_note_with(s: &str, convention: OctaveConvention) -> Option<u8> {
+    let s = s.trim();
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+        return s.parse::<u8>().ok().filter(|n| *n <= 127);
+    }
+
+    let mut chars = s.chars();
+    let mut semitone: i32 = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let octave_start = rest.find(|c: char| c == '-' || c.is_ascii_digit())?;
+    let (accidentals, octave) = rest.split_at(octave_start);
+    if accidentals.chars().all(|c| c == '#') {
+        semitone += accidentals.len() as i32;
+    } else if accidentals.chars().all(|c| c == 'b') {
+        semitone -= accidentals.len() as i32;
+    } else {
+        return None;
+    }
+    let octave: i32 = octave.parse().ok()?;
+    let note = (octave - convention.lowest_octave()) * 12 + semitone;
+    u8::try_from(note).ok().filter(|n| *n <= 127)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PatternSettings;
+
+    #[test]
+    fn spellings_of_the_same_note_agree() {
+        assert_eq!(parse_note("Db4"), Some(61));
+        assert_eq!(parse_note("C#4"), Some(61));
+        assert_eq!(parse_note("61"), Some(61));
+        assert_eq!(parse_note(" c#4 "), Some(61));
+        assert_eq!(parse_note("Cb4"), Some(59), "flats can cross the octave");
+        assert_eq!(parse_note("B#3"), Some(60));
+        assert_eq!(parse_note("Ebb4"), Some(62));
+        assert_eq!(parse_note("C-1"), Some(0));
+        assert_eq!(parse_note("G9"), Some(127));
+    }
+
+    #[test]
+    fn octave_conventions() {
+        assert_eq!(parse_note_with("C3", OctaveConvention::C3), Some(60));
+        assert_eq!(parse_note_with("C4", OctaveConvention::C4), Some(60));
+        assert_eq!(parse_note_with("C-2", OctaveConvention::C3), Some(0));
+        assert_eq!(parse_note_with("C-2", OctaveConvention::C4), None);
+
+        // Numbers don't depend on the convention.
+        assert_eq!(parse_note_with("60", OctaveConvention::C3), Some(60));
+    }
+
+    #[test]
+    fn patterns_read_names_and_numbers() {
+        let pattern: PatternSettings = json5::from_str(
+            r#"{ id: "riff", octave-convention: "c3", notes: [[60, "C3", "Db3"], ["c#3"]] }"#,
+        )
+        .unwrap();
+        assert_eq!(pattern.notes, vec![vec![60, 60, 61], vec![61]]);
+
+        let r = json5::from_str::<PatternSettings>(r#"{ id: "riff", notes: [["X4"]] }"#);
+        assert!(r.unwrap_err().to_string().contains("'X4' isn't a note"));
+        let r = json5::from_str::<PatternSettings>(r#"{ id: "riff", notes: [[200]] }"#);
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn rejects_ambiguous_and_out_of_range() {
+        for s in [
+            "", "C", "H4", "C#b4", "G#9", "128", "-1", "Cb-1", "C 4", "C+4", "C4x", "4C",
+        ] {
+            assert_eq!(parse_note(s), None, "{s:?} should not parse");
+        }
+    }
+}
@@ -5,16 +5,17 @@ use convert_case::{Boundary, Case, Casing};
 use ensnare::prelude::*;
 use groove_core::{
     generators::{EnvelopeParams, Oscillator, OscillatorParams, Waveform},
-    midi::{note_to_frequency, GeneralMidiProgram},
+    midi::{note_to_frequency, GeneralMidiProgram, MidiChannel, MidiMessage},
+    traits::{Configurable, Generates, HandlesMidi, MidiMessagesFn, Ticks},
     DcaParams,
 };
 use groove_entities::{
     effects::{BiQuadFilter, BiQuadFilterLowPass24dbParams},
-    instruments::{LfoRouting, WelshSynthParams, WelshVoiceParams},
+    instruments::{LfoRouting, WelshSynth, WelshSynthParams, WelshVoiceParams},
 };
 use groove_utils::Paths;
 use serde::{Deserialize, Serialize};
-use std::{io::Read, path::Path};
+use std::{collections::BTreeMap, io::Read, path::Path};
 use strum_macros::IntoStaticStr;
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -688,6 +689,155 @@ impl WelshPatchSettings {
     }
 }
 
+/// A bank of Welsh synth presets keyed by MIDI program number, so that a
+/// sequencer can switch sounds mid-song with Program Change messages. See
+/// [WelshPresetSynth].
+#[derive(Debug, Default)]
+pub struct WelshPresetBank {
+    presets: BTreeMap<u8, WelshSynthParams>,
+}
+impl WelshPresetBank {
+    /// Assigns a preset to a program number, replacing any that was there.
+    pub fn insert(&mut self, program: u8, params: WelshSynthParams) {
+        self.presets.insert(program, params);
+    }
+
+    /// Loads the named patch file and assigns it to a program number.
+    pub fn insert_by_name(&mut self, paths: &Paths, program: u8, name: &str) {
+        self.insert(
+            program,
+            WelshPatchSettings::by_name(paths, name).derive_welsh_synth_params(),
+        );
+    }
+
+    /// Returns the preset for a program number, if there is one.
+    pub fn get(&self, program: u8) -> Option<&WelshSynthParams> {
+        self.presets.get(&program)
+    }
+
+    /// If the message is a Program Change for a program in this bank, returns
+    /// that program's preset. Anything else returns None.
+    pub fn preset_for_message(&self, message: &MidiMessage) -> Option<&WelshSynthParams> {
+        if let MidiMessage::ProgramChange { program } = message {
+            self.get(program.as_int())
+        } else {
+            None
+        }
+    }
+
+    /// The program numbers that have presets, in order.
+    pub fn programs(&self) -> impl Iterator<Item = &u8> {
+        self.presets.keys()
+    }
+}
+
+/// [WelshPresetSynth] is a [WelshSynth] that switches to a preset from its
+/// [WelshPresetBank] when it receives a Program Change.
+///
+/// The new preset applies to new note-ons only. The synth that was playing
+/// keeps its voices and gets their note-offs, so notes that are sounding at
+/// the switch finish with the sound they started with instead of clicking.
+/// Only the synth from the most recent switch is kept for this, so a note
+/// held across two switches is cut off at the second one.
+#[derive(Debug)]
+pub struct WelshPresetSynth {
+    bank: WelshPresetBank,
+    synth: WelshSynth,
+    releasing: Option<WelshSynth>,
+    sample_rate: SampleRate,
+    scratch: Vec<StereoSample>,
+}
+impl WelshPresetSynth {
+    /// Creates a synth that starts with `params` and switches among the
+    /// presets in `bank`.
+    pub fn new_with(bank: WelshPresetBank, params: &WelshSynthParams) -> Self {
+        Self {
+            bank,
+            synth: WelshSynth::new_with(params),
+            releasing: None,
+            sample_rate: Default::default(),
+            scratch: Default::default(),
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn bank(&self) -> &WelshPresetBank {
+        &self.bank
+    }
+
+    // Starts playing new notes with the program's preset, if the bank has one.
+    fn select_program(&mut self, program: u8) {
+        if let Some(params) = self.bank.get(program) {
+            let mut synth = WelshSynth::new_with(params);
+            synth.update_sample_rate(self.sample_rate);
+            self.releasing = Some(std::mem::replace(&mut self.synth, synth));
+        }
+    }
+}
+impl HandlesMidi for WelshPresetSynth {
+    fn handle_midi_message(
+        &mut self,
+        channel: MidiChannel,
+        message: MidiMessage,
+        midi_messages_fn: &mut MidiMessagesFn,
+    ) {
+        match message {
+            MidiMessage::ProgramChange { program } => self.select_program(program.as_int()),
+            MidiMessage::NoteOn { vel, .. } if vel != 0 => {
+                self.synth
+                    .handle_midi_message(channel, message, midi_messages_fn);
+            }
+            _ => {
+                if let Some(releasing) = self.releasing.as_mut() {
+                    releasing.handle_midi_message(channel, message, midi_messages_fn);
+                }
+                self.synth
+                    .handle_midi_message(channel, message, midi_messages_fn);
+            }
+        }
+    }
+}
+impl Generates<StereoSample> for WelshPresetSynth {
+    fn value(&self) -> StereoSample {
+        match self.releasing.as_ref() {
+            Some(releasing) => self.synth.value() + releasing.value(),
+            None => self.synth.value(),
+        }
+    }
+
+    fn generate_batch_values(&mut self, values: &mut [StereoSample]) {
+        self.synth.generate_batch_values(values);
+        if let Some(releasing) = self.releasing.as_mut() {
+            self.scratch.resize(values.len(), StereoSample::SILENCE);
+            releasing.generate_batch_values(&mut self.scratch);
+            for (value, released) in values.iter_mut().zip(self.scratch.iter()) {
+                *value = *value + *released;
+            }
+        }
+    }
+}
+impl Ticks for WelshPresetSynth {
+    fn tick(&mut self, tick_count: usize) {
+        self.synth.tick(tick_count);
+        if let Some(releasing) = self.releasing.as_mut() {
+            releasing.tick(tick_count);
+        }
+    }
+}
+impl Configurable for WelshPresetSynth {
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        self.synth.update_sample_rate(sample_rate);
+        if let Some(releasing) = self.releasing.as_mut() {
+            releasing.update_sample_rate(sample_rate);
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct FmSynthesizerSettings {
@@ -718,6 +868,7 @@ impl FmSynthesizerSettings {
 mod tests {
     use super::{
         FilterPreset, LfoDepth, LfoPreset, LfoRoutingType, PolyphonySettings, WelshPatchSettings,
+        WelshPresetBank, WelshPresetSynth,
     };
     use crate::patches::OscillatorSettings;
     use convert_case::{Case, Casing};
@@ -725,8 +876,9 @@ mod tests {
     use float_cmp::approx_eq;
     use groove_core::{
         generators::{Envelope, EnvelopeParams, Waveform},
+        midi::{MidiChannel, MidiMessage},
         time::Seconds,
-        traits::{Configurable, Generates, PlaysNotes, Ticks},
+        traits::{Configurable, Generates, HandlesMidi, PlaysNotes, Ticks},
         util::tests::TestOnlyPaths,
     };
     use groove_entities::instruments::WelshVoice;
@@ -935,6 +1087,86 @@ mod tests {
         );
     }
 
+    #[test]
+    fn preset_bank_responds_to_program_change() {
+        let mut bank = WelshPresetBank::default();
+        bank.insert(0, boring_test_patch().derive_welsh_synth_params());
+        bank.insert(42, cello_patch().derive_welsh_synth_params());
+        assert_eq!(bank.programs().copied().collect::<Vec<u8>>(), vec![0, 42]);
+
+        assert!(bank
+            .preset_for_message(&MidiMessage::ProgramChange { program: 42.into() })
+            .is_some());
+        assert!(
+            bank.preset_for_message(&MidiMessage::ProgramChange { program: 7.into() })
+                .is_none(),
+            "programs without presets should be ignored"
+        );
+        assert!(
+            bank.preset_for_message(&MidiMessage::NoteOn {
+                key: 42.into(),
+                vel: 127.into()
+            })
+            .is_none(),
+            "only Program Change should select a preset"
+        );
+    }
+
+    #[test]
+    fn program_change_applies_to_new_notes_only() {
+        let mut bank = WelshPresetBank::default();
+        bank.insert(42, cello_patch().derive_welsh_synth_params());
+        let mut synth =
+            WelshPresetSynth::new_with(bank, &boring_test_patch().derive_welsh_synth_params());
+        synth.update_sample_rate(SampleRate::DEFAULT);
+        let send = |synth: &mut WelshPresetSynth, message: MidiMessage| {
+            synth.handle_midi_message(MidiChannel(0), message, &mut |_, _| {})
+        };
+
+        send(
+            &mut synth,
+            MidiMessage::NoteOn {
+                key: 60.into(),
+                vel: 127.into(),
+            },
+        );
+        synth.tick(100);
+        assert_ne!(synth.value(), StereoSample::SILENCE);
+
+        send(&mut synth, MidiMessage::ProgramChange { program: 7.into() });
+        assert!(
+            synth.releasing.is_none(),
+            "a program without a preset changes nothing"
+        );
+
+        send(
+            &mut synth,
+            MidiMessage::ProgramChange { program: 42.into() },
+        );
+        assert!(synth.releasing.is_some());
+        synth.tick(1);
+        assert_ne!(
+            synth.value(),
+            StereoSample::SILENCE,
+            "the held note keeps sounding through the switch"
+        );
+        assert_eq!(
+            synth.synth.value(),
+            StereoSample::SILENCE,
+            "the new preset hasn't been asked to play anything yet"
+        );
+
+        send(
+            &mut synth,
+            MidiMessage::NoteOn {
+                key: 64.into(),
+                vel: 127.into(),
+            },
+        );
+        synth.tick(100);
+        assert_ne!(synth.synth.value(), StereoSample::SILENCE);
+    }
+
     #[cfg(obsolete)]
     #[test]
     fn basic_synth_patch() {
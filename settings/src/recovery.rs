@@ -0,0 +1,184 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+//! Crash recovery. While a project is open, the app periodically writes its
+//! current state to a recovery file. If the app dies before the user saves, the
+//! next launch finds a recovery file that's newer than the last explicit save
+//! and can offer to restore it.
+
+use anyhow::anyhow;
+use groove_utils::Paths;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// A recovery file at a fixed location.
+#[derive(Debug, Clone)]
+pub struct RecoveryFile {
+    path: PathBuf,
+}
+impl Default for RecoveryFile {
+    fn default() -> Self {
+        Self::new_with(Self::default_path())
+    }
+}
+impl RecoveryFile {
+    /// The recovery file's name within the config directory.
+    pub const FILENAME: &'static str = "recovery.json";
+
+    pub fn new_with(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// The recovery file lives next to the preferences file.
+    pub fn default_path() -> PathBuf {
+        let prefs_file = Paths::prefs_file();
+        let mut path = prefs_file
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_default();
+        path.push(Self::FILENAME);
+        path
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the settings to the recovery file. The contents go to a
+    /// temporary file first and are then renamed into place, so a crash
+    /// during the write can't leave a truncated recovery file behind.
+    pub fn save<T: Serialize>(&self, settings: &T) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(settings)
+            .map_err(|e| anyhow!("Unable to serialize recovery data: {e}"))?;
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| anyhow!("Unable to create recovery directory: {e}"))?;
+        }
+        let temp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&temp_path, json)
+            .map_err(|e| anyhow!("Unable to write recovery file: {e}"))?;
+        std::fs::rename(&temp_path, &self.path)
+            .map_err(|e| anyhow!("Unable to move recovery file into place: {e}"))
+    }
+
+    /// Reads the settings back from the recovery file.
+    pub fn load<T: DeserializeOwned>(&self) -> anyhow::Result<T> {
+        let contents = std::fs::read_to_string(&self.path)
+            .map_err(|e| anyhow!("Couldn't read recovery file: {e}"))?;
+        serde_json::from_str(&contents).map_err(|e| anyhow!("Couldn't parse recovery file: {e}"))
+    }
+
+    /// Whether the recovery file holds work that the user never saved.
+    /// `last_saved` is the modification time of the project file, or None if
+    /// the project has never been saved.
+    pub fn is_stale(&self, last_saved: Option<SystemTime>) -> bool {
+        let Ok(modified) = std::fs::metadata(&self.path).and_then(|m| m.modified()) else {
+            return false;
+        };
+        match last_saved {
+            Some(last_saved) => modified > last_saved,
+            None => true,
+        }
+    }
+
+    /// Deletes the recovery file, typically after an explicit save or after
+    /// the user declines to restore it.
+    pub fn discard(&self) {
+        if self.path.exists() {
+            if let Err(e) = std::fs::remove_file(&self.path) {
+                eprintln!("Warning: couldn't remove recovery file: {e}");
+            }
+        }
+    }
+}
+
+/// Decides when it's time to write the next recovery file.
+#[derive(Debug)]
+pub struct AutoSaver {
+    interval: Duration,
+    last_save: Option<Instant>,
+}
+impl Default for AutoSaver {
+    fn default() -> Self {
+        Self::new_with(Self::DEFAULT_INTERVAL)
+    }
+}
+impl AutoSaver {
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn new_with(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_save: None,
+        }
+    }
+
+    /// Whether an autosave should happen now.
+    pub fn is_due(&self, now: Instant) -> bool {
+        match self.last_save {
+            Some(last_save) => now.duration_since(last_save) >= self.interval,
+            None => true,
+        }
+    }
+
+    /// Records that an autosave (or an explicit save) just happened.
+    pub fn mark_saved(&mut self, now: Instant) {
+        self.last_save = Some(now);
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Project {
+        title: String,
+        bpm: f64,
+    }
+
+    #[test]
+    fn recovery_file_round_trip() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("groove-recovery-test-{}", std::process::id()));
+        path.push(RecoveryFile::FILENAME);
+        let recovery = RecoveryFile::new_with(path);
+        recovery.discard();
+        assert!(!recovery.is_stale(None), "no file means nothing to recover");
+
+        let before_save = SystemTime::now() - Duration::from_secs(60);
+        let project = Project {
+            title: "Unsaved".to_string(),
+            bpm: 128.0,
+        };
+        assert!(recovery.save(&project).is_ok());
+        assert!(recovery.is_stale(None));
+        assert!(recovery.is_stale(Some(before_save)));
+        assert!(!recovery.is_stale(Some(SystemTime::now() + Duration::from_secs(60))));
+        assert_eq!(recovery.load::<Project>().unwrap(), project);
+
+        recovery.discard();
+        assert!(!recovery.path().exists());
+    }
+
+    #[test]
+    fn autosaver_waits_for_interval() {
+        let mut saver = AutoSaver::new_with(Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(saver.is_due(start), "first save should happen right away");
+        saver.mark_saved(start);
+        assert!(!saver.is_due(start + Duration::from_secs(5)));
+        assert!(saver.is_due(start + Duration::from_secs(10)));
+    }
+}
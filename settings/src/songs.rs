@@ -1,14 +1,21 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
 use super::{
-    controllers::{ControlPathSettings, ControlTripSettings},
-    ControlSettings, DeviceId, DeviceSettings, PatternSettings, TrackSettings,
+    controllers::{ControlPathSettings, ControlTripSettings, ControllerSettings},
+    effects::EffectSettings,
+    instruments::InstrumentSettings,
+    ControlLinkSettings, ControlSettings, DeviceId, DeviceSettings, LoadError, MidiChannel,
+    PatchSettings, PatternSettings, TimeSignatureSettings, TrackSettings, ValidationError,
 };
 use anyhow::{anyhow, Result};
-use groove_entities::controllers::{ControlPath, Note, Pattern, PatternProgrammer};
+use ensnare::prelude::*;
+use groove_core::control::ControlIndex;
+use groove_entities::controllers::{
+    ControlPath, MidiChannelInputParams, MidiChannelParams, Note, Pattern, PatternProgrammer,
+};
 use groove_orchestration::EntityObsolete;
 use groove_utils::Paths;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
@@ -33,11 +40,19 @@ pub struct SongSettings {
     #[serde(default)]
     pub patch_cables: Vec<PatchCable>,
 
+    /// Virtual audio cables connecting one device to another
+    #[serde(default)]
+    pub patches: Vec<PatchSettings>,
+
     /// Automation links between a source device and a target device's
     /// controllable parameter
     #[serde(default)]
     pub controls: Vec<ControlSettings>,
 
+    /// Automation links that name the target parameter by index
+    #[serde(default)]
+    pub control_links: Vec<ControlLinkSettings>,
+
     /// Tracker-style note patterns
     #[serde(default)]
     pub patterns: Vec<PatternSettings>,
@@ -88,16 +103,236 @@ impl SongSettings {
         }
     }
 
+    /// Makes a deep copy of a pattern, with a new ID that nothing else in the
+    /// project has, and puts it right after the original. Tracks keep referring to the
+    /// original, so editing the copy doesn't change anything that's already
+    /// placed. Returns the new ID, or None if there's no such pattern.
+    pub fn duplicate_pattern(&mut self, id: &str) -> Option<DeviceId> {
+        let index = self.patterns.iter().position(|p| p.id == id)?;
+        let mut new_id = format!("{id}-copy");
+        let mut suffix = 1;
+        while new_id == Orchestrator::MAIN_MIXER_UVID || self.defined_ids().any(|i| *i == new_id) {
+            suffix += 1;
+            new_id = format!("{id}-copy-{suffix}");
+        }
+        let mut copy = self.patterns[index].clone();
+        copy.id = new_id.clone();
+        self.patterns.insert(index + 1, copy);
+        Some(new_id)
+    }
+
+    /// Changes an ID and every reference to it: patch cables, patches,
+    /// automation sources and targets, and the pattern and path lists of
+    /// tracks and control trips. Devices, patterns, tracks, paths, trips, and
+    /// controls all share one namespace, so any of them can be renamed.
+    ///
+    /// Fails without changing anything if `old_id` doesn't exist, or if
+    /// `new_id` is already taken.
+    pub fn rename_device(&mut self, old_id: &str, new_id: &str) -> anyhow::Result<()> {
+        if old_id == Orchestrator::MAIN_MIXER_UVID {
+            return Err(anyhow!("the main mixer can't be renamed"));
+        }
+        if new_id.is_empty() {
+            return Err(anyhow!("can't rename {old_id} to an empty ID"));
+        }
+        if !self.defined_ids().any(|id| id == old_id) {
+            return Err(anyhow!(LoadError::MissingDevice(old_id.to_string())));
+        }
+        if new_id == Orchestrator::MAIN_MIXER_UVID || self.defined_ids().any(|id| id == new_id) {
+            return Err(anyhow!(
+                "can't rename {old_id} to {new_id}, which is already taken"
+            ));
+        }
+
+        // Nothing below can fail, so the rename is all or nothing.
+        let rename = |id: &mut DeviceId| {
+            if *id == old_id {
+                *id = new_id.to_string();
+            }
+        };
+        for device in self.devices.iter_mut() {
+            match device {
+                DeviceSettings::Instrument(id, _)
+                | DeviceSettings::Controller(id, _)
+                | DeviceSettings::Effect(id, _) => rename(id),
+            }
+        }
+        self.patch_cables.iter_mut().flatten().for_each(rename);
+        for patch in self.patches.iter_mut() {
+            rename(&mut patch.output);
+            rename(&mut patch.input);
+        }
+        for control in self.controls.iter_mut() {
+            rename(&mut control.id);
+            rename(&mut control.source);
+            rename(&mut control.target.id);
+        }
+        for link in self.control_links.iter_mut() {
+            rename(&mut link.source);
+            rename(&mut link.target);
+        }
+        for pattern in self.patterns.iter_mut() {
+            rename(&mut pattern.id);
+        }
+        for track in self.tracks.iter_mut() {
+            rename(&mut track.id);
+            track.pattern_ids.iter_mut().for_each(rename);
+        }
+        for path in self.paths.iter_mut() {
+            rename(&mut path.id);
+        }
+        for trip in self.trips.iter_mut() {
+            rename(&mut trip.id);
+            rename(&mut trip.target.id);
+            trip.path_ids.iter_mut().for_each(rename);
+        }
+        Ok(())
+    }
+
+    /// Describes the project without loading it: its devices and patterns by
+    /// ID, its tempo, and its time signature. Nothing is instantiated, so this
+    /// is cheap enough for a file browser's preview.
+    pub fn summary(&self) -> ProjectSummary {
+        let mut r = ProjectSummary {
+            title: self.title.clone(),
+            bpm: self.clock.bpm(),
+            time_signature: TimeSignatureSettings {
+                top: self.clock.time_signature().top,
+                bottom: self.clock.time_signature().bottom,
+            },
+            patterns: self.patterns.iter().map(|p| p.id.clone()).collect(),
+            ..Default::default()
+        };
+        for device in self.devices.iter() {
+            match device {
+                DeviceSettings::Instrument(id, _) => r.instruments.push(id.clone()),
+                DeviceSettings::Controller(id, _) => r.controllers.push(id.clone()),
+                DeviceSettings::Effect(id, _) => r.effects.push(id.clone()),
+            }
+        }
+        r
+    }
+
+    /// Checks that every ID the project refers to exists, and that no two
+    /// things share an ID. Returns every problem it finds, so that a
+    /// hand-edited project can be fixed in one go rather than one error at a
+    /// time.
+    pub fn validate(&self) -> Vec<ValidationError> {
+        let mut problems = Vec::default();
+
+        let mut seen = FxHashSet::default();
+        let mut reported = FxHashSet::default();
+        for id in self.defined_ids() {
+            if !seen.insert(id) && reported.insert(id) {
+                problems.push(ValidationError::DuplicateId(id.clone()));
+            }
+        }
+
+        let devices: FxHashSet<&str> = self
+            .devices
+            .iter()
+            .map(|d| match d {
+                DeviceSettings::Instrument(id, _)
+                | DeviceSettings::Controller(id, _)
+                | DeviceSettings::Effect(id, _) => id.as_str(),
+            })
+            .chain(std::iter::once(Orchestrator::MAIN_MIXER_UVID))
+            .collect();
+        let mut check_device = |referrer: &dyn Fn() -> String, id: &DeviceId| {
+            if !devices.contains(id.as_str()) {
+                problems.push(ValidationError::UnknownDevice(referrer(), id.clone()));
+            }
+        };
+        for (i, cable) in self.patch_cables.iter().enumerate() {
+            for id in cable {
+                check_device(&|| format!("patch cable #{}", i + 1), id);
+            }
+        }
+        for patch in self.patches.iter() {
+            let referrer = || format!("patch {} -> {}", patch.output, patch.input);
+            check_device(&referrer, &patch.output);
+            check_device(&referrer, &patch.input);
+        }
+        for control in self.controls.iter() {
+            let referrer = || format!("control {}", control.id);
+            check_device(&referrer, &control.source);
+            check_device(&referrer, &control.target.id);
+        }
+        for (i, link) in self.control_links.iter().enumerate() {
+            let referrer = || format!("control link #{}", i + 1);
+            check_device(&referrer, &link.source);
+            check_device(&referrer, &link.target);
+        }
+        for trip in self.trips.iter() {
+            check_device(&|| format!("trip {}", trip.id), &trip.target.id);
+        }
+
+        let patterns: FxHashSet<&DeviceId> = self.patterns.iter().map(|p| &p.id).collect();
+        for track in self.tracks.iter() {
+            for id in track.pattern_ids.iter() {
+                if !patterns.contains(id) {
+                    problems.push(ValidationError::UnknownPattern(
+                        track.id.clone(),
+                        id.clone(),
+                    ));
+                }
+            }
+        }
+        let paths: FxHashSet<&DeviceId> = self.paths.iter().map(|p| &p.id).collect();
+        for trip in self.trips.iter() {
+            for id in trip.path_ids.iter() {
+                if !paths.contains(id) {
+                    problems.push(ValidationError::UnknownPath(trip.id.clone(), id.clone()));
+                }
+            }
+        }
+
+        problems
+    }
+
+    // The IDs that the project gives to things, as opposed to the ones that it
+    // uses to refer to them.
+    fn defined_ids(&self) -> impl Iterator<Item = &DeviceId> {
+        self.devices
+            .iter()
+            .map(|d| match d {
+                DeviceSettings::Instrument(id, _)
+                | DeviceSettings::Controller(id, _)
+                | DeviceSettings::Effect(id, _) => id,
+            })
+            .chain(self.patterns.iter().map(|p| &p.id))
+            .chain(self.tracks.iter().map(|t| &t.id))
+            .chain(self.paths.iter().map(|p| &p.id))
+            .chain(self.trips.iter().map(|t| &t.id))
+            .chain(self.controls.iter().map(|c| &c.id))
+    }
+
     pub fn instantiate(
         &self,
         paths: &Paths,
         load_only_test_entities: bool,
     ) -> Result<Orchestrator> {
+        // A bad time signature would otherwise panic deep inside the engine.
+        let time_signature = self.clock.time_signature();
+        TimeSignature::try_from(TimeSignatureSettings {
+            top: time_signature.top,
+            bottom: time_signature.bottom,
+        })?;
+
+        // Report every dangling reference up front, rather than only the
+        // first one that happens to fail the load.
+        let problems = self.validate();
+        if !problems.is_empty() {
+            return Err(anyhow!(LoadError::Invalid(problems)));
+        }
+
         let mut o: Orchestrator = Orchestrator::new_with(self.clock);
         o.set_title(self.title.clone());
         self.instantiate_devices(paths, &mut o, load_only_test_entities);
         self.instantiate_patch_cables(&mut o)?;
+        self.instantiate_patches(&mut o)?;
         self.instantiate_controls(&mut o)?;
+        self.instantiate_control_links(&mut o)?;
         self.instantiate_tracks(&mut o);
         self.instantiate_control_trips(&mut o, &self.clock.time_signature());
         Ok(o)
@@ -163,6 +398,50 @@ impl SongSettings {
         Ok(())
     }
 
+    // Unlike patch_cables, which skip IDs they can't find, these fail the load,
+    // because a project that silently loses part of its signal graph sounds
+    // wrong in ways that are hard to track down.
+    fn instantiate_patches(&self, orchestrator: &mut Orchestrator) -> anyhow::Result<()> {
+        for patch in &self.patches {
+            let output_uid = Self::resolve_device_id(orchestrator, &patch.output)?;
+            let input_uid = Self::resolve_device_id(orchestrator, &patch.input)?;
+            if let Err(e) = orchestrator.patch(output_uid, input_uid) {
+                return Err(anyhow!(
+                    "couldn't patch {} into {}: {e}",
+                    patch.output,
+                    patch.input
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn instantiate_control_links(&self, orchestrator: &mut Orchestrator) -> anyhow::Result<()> {
+        for link in &self.control_links {
+            let source_uid = Self::resolve_device_id(orchestrator, &link.source)?;
+            let target_uid = Self::resolve_device_id(orchestrator, &link.target)?;
+            if let Err(e) = orchestrator.link_control_by_id(
+                source_uid,
+                target_uid,
+                ControlIndex(link.control_index),
+            ) {
+                return Err(anyhow!(
+                    "couldn't link {} to {}'s control #{}: {e}",
+                    link.source,
+                    link.target,
+                    link.control_index
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_device_id(orchestrator: &Orchestrator, id: &DeviceId) -> anyhow::Result<Uid> {
+        orchestrator
+            .get_uid_by_uvid(id)
+            .ok_or_else(|| anyhow!(LoadError::MissingDevice(id.clone())))
+    }
+
     fn instantiate_controls(&self, orchestrator: &mut Orchestrator) -> anyhow::Result<()> {
         for control in self.controls.iter() {
             let source_uvid = &control.source;
@@ -306,9 +585,272 @@ impl SongSettings {
     }
 }
 
+/// What's in a project, as [SongSettings::summary()] reports it.
+#[derive(Clone, Debug, Default)]
+pub struct ProjectSummary {
+    pub title: Option<String>,
+    pub bpm: ParameterType,
+    pub time_signature: TimeSignatureSettings,
+
+    /// IDs, in the order that the project lists them.
+    pub instruments: Vec<DeviceId>,
+    pub effects: Vec<DeviceId>,
+    pub controllers: Vec<DeviceId>,
+    pub patterns: Vec<DeviceId>,
+}
+impl std::fmt::Display for ProjectSummary {
+    /// A one-line description, like "3 instruments, 2 effects, 128 BPM, 4/4".
+    /// Kinds of things that the project has none of are left out.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (ids, singular, plural) in [
+            (&self.instruments, "instrument", "instruments"),
+            (&self.effects, "effect", "effects"),
+            (&self.controllers, "controller", "controllers"),
+            (&self.patterns, "pattern", "patterns"),
+        ] {
+            match ids.len() {
+                0 => {}
+                1 => write!(f, "1 {singular}, ")?,
+                n => write!(f, "{n} {plural}, ")?,
+            }
+        }
+        write!(
+            f,
+            "{} BPM, {}/{}",
+            self.bpm, self.time_signature.top, self.time_signature.bottom
+        )
+    }
+}
+
+/// Captures a live [Orchestrator] as [SongSettings], so that a project that
+/// has been edited in the app can be saved and reloaded.
+pub trait ToSettings {
+    fn to_settings(&self) -> SongSettings;
+}
+impl ToSettings for Orchestrator {
+    fn to_settings(&self) -> SongSettings {
+        SongSettings::from(self)
+    }
+}
+
+impl From<&Orchestrator> for SongSettings {
+    // TODO: patterns, tracks, and control trips live inside the sequencer and
+    // ControlTrip entities in a form that can't yet be turned back into
+    // settings, so they aren't captured.
+    fn from(o: &Orchestrator) -> Self {
+        let mut r = SongSettings {
+            title: o.title(),
+            clock: *o.clock(),
+            ..Default::default()
+        };
+
+        // Walk the entities in Uid order so that the output is stable from one
+        // save to the next.
+        let mut uids: Vec<Uid> = o
+            .entity_iter()
+            .map(|(uid, _)| *uid)
+            .filter(|uid| !o.is_built_in(*uid))
+            .collect();
+        uids.sort();
+
+        // Keep the IDs that entities were loaded with. Entities that were
+        // created in the app get a generated ID that doesn't collide with any
+        // existing one.
+        let mut uid_to_id: FxHashMap<Uid, DeviceId> = FxHashMap::default();
+        uid_to_id.insert(
+            o.main_mixer_uid(),
+            Orchestrator::MAIN_MIXER_UVID.to_string(),
+        );
+        for uid in uids.iter() {
+            if let Some(uvid) = o.get_uvid_by_uid(*uid) {
+                uid_to_id.insert(*uid, uvid.to_string());
+            }
+        }
+        for uid in uids.iter() {
+            if uid_to_id.contains_key(uid) {
+                continue;
+            }
+            let prefix = o
+                .get(*uid)
+                .map(|e| Self::id_prefix_for(e))
+                .unwrap_or("device");
+            let mut id = format!("{prefix}-{uid}");
+            let mut suffix = 1;
+            while uid_to_id.values().any(|v| *v == id) {
+                suffix += 1;
+                id = format!("{prefix}-{uid}-{suffix}");
+            }
+            uid_to_id.insert(*uid, id);
+        }
+
+        for uid in uids.iter() {
+            let Some(entity) = o.get(*uid) else {
+                continue;
+            };
+            let id = uid_to_id[uid].clone();
+            let channel = o.midi_channel_for(*uid).unwrap_or_default();
+            if let Some(device) = Self::device_settings_for(id.clone(), channel, entity) {
+                r.devices.push(device);
+            } else {
+                eprintln!("Warning: device {id} can't be saved; skipping");
+                uid_to_id.remove(uid);
+            }
+        }
+
+        // Sinks are visited in Uid order (main mixer last) to keep the output
+        // stable.
+        let mut sink_uids: Vec<Uid> = uids.clone();
+        sink_uids.push(o.main_mixer_uid());
+        for sink_uid in sink_uids {
+            let Some(sink_id) = uid_to_id.get(&sink_uid) else {
+                continue;
+            };
+            for source_uid in o.patch_sources(sink_uid) {
+                if let Some(source_id) = uid_to_id.get(source_uid) {
+                    r.patches.push(PatchSettings {
+                        output: source_id.clone(),
+                        input: sink_id.clone(),
+                    });
+                }
+            }
+        }
+
+        for link in o.connections().iter() {
+            let (Some(source_id), Some(target_id)) = (
+                uid_to_id.get(&link.source_uid),
+                uid_to_id.get(&link.target_uid),
+            ) else {
+                continue;
+            };
+            r.control_links.push(ControlLinkSettings {
+                source: source_id.clone(),
+                target: target_id.clone(),
+                control_index: link.control_index.0,
+            });
+        }
+
+        r
+    }
+}
+impl SongSettings {
+    fn id_prefix_for(entity: &EntityObsolete) -> &'static str {
+        if entity.as_is_controller().is_some() {
+            "controller"
+        } else if entity.as_is_instrument().is_some() {
+            "instrument"
+        } else {
+            "effect"
+        }
+    }
+
+    // TODO: controllers don't expose their MIDI output channel, so we assume it
+    // matches the input channel.
+    fn device_settings_for(
+        id: DeviceId,
+        channel: MidiChannel,
+        entity: &EntityObsolete,
+    ) -> Option<DeviceSettings> {
+        let midi_in = MidiChannelInputParams { midi_in: channel };
+        let midi = MidiChannelParams {
+            midi_in: channel,
+            midi_out: channel,
+        };
+        let settings = match entity {
+            EntityObsolete::ToyInstrument(e) => DeviceSettings::Instrument(
+                id,
+                InstrumentSettings::ToyInstrument(midi_in, e.to_params()),
+            ),
+            EntityObsolete::WelshSynth(e) => {
+                DeviceSettings::Instrument(id, InstrumentSettings::WelshRaw(midi_in, e.to_params()))
+            }
+            EntityObsolete::Drumkit(e) => {
+                DeviceSettings::Instrument(id, InstrumentSettings::Drumkit(midi_in, e.to_params()))
+            }
+            EntityObsolete::Sampler(e) => {
+                DeviceSettings::Instrument(id, InstrumentSettings::Sampler(midi_in, e.to_params()))
+            }
+            EntityObsolete::FmSynth(e) => DeviceSettings::Instrument(
+                id,
+                InstrumentSettings::FmSynthesizer(midi_in, e.to_params()),
+            ),
+            EntityObsolete::ToyController(_) => {
+                DeviceSettings::Controller(id, ControllerSettings::Test(midi))
+            }
+            EntityObsolete::Arpeggiator(e) => {
+                DeviceSettings::Controller(id, ControllerSettings::Arpeggiator(midi, e.to_params()))
+            }
+            EntityObsolete::LfoController(e) => DeviceSettings::Controller(
+                id,
+                ControllerSettings::LfoController(midi, e.to_params()),
+            ),
+            EntityObsolete::SignalPassthroughController(_) => DeviceSettings::Controller(
+                id,
+                ControllerSettings::SignalPassthroughController(midi),
+            ),
+            EntityObsolete::ToyEffect(e) => {
+                DeviceSettings::Effect(id, EffectSettings::Toy(e.to_params()))
+            }
+            EntityObsolete::Mixer(e) => {
+                DeviceSettings::Effect(id, EffectSettings::Mixer(e.to_params()))
+            }
+            EntityObsolete::Gain(e) => {
+                DeviceSettings::Effect(id, EffectSettings::Gain(e.to_params()))
+            }
+            EntityObsolete::Limiter(e) => {
+                DeviceSettings::Effect(id, EffectSettings::Limiter(e.to_params()))
+            }
+            EntityObsolete::Bitcrusher(e) => {
+                DeviceSettings::Effect(id, EffectSettings::Bitcrusher(e.to_params()))
+            }
+            EntityObsolete::Chorus(e) => {
+                DeviceSettings::Effect(id, EffectSettings::Chorus(e.to_params()))
+            }
+            EntityObsolete::Compressor(e) => {
+                DeviceSettings::Effect(id, EffectSettings::Compressor(e.to_params()))
+            }
+            EntityObsolete::Delay(e) => {
+                DeviceSettings::Effect(id, EffectSettings::Delay(e.to_params()))
+            }
+            EntityObsolete::Reverb(e) => {
+                DeviceSettings::Effect(id, EffectSettings::Reverb(e.to_params()))
+            }
+            EntityObsolete::BiQuadFilterLowPass12db(e) => {
+                DeviceSettings::Effect(id, EffectSettings::FilterLowPass12db(e.to_params()))
+            }
+            EntityObsolete::BiQuadFilterLowPass24db(e) => {
+                DeviceSettings::Effect(id, EffectSettings::FilterLowPass24db(e.to_params()))
+            }
+            EntityObsolete::BiQuadFilterHighPass(e) => {
+                DeviceSettings::Effect(id, EffectSettings::FilterHighPass12db(e.to_params()))
+            }
+            EntityObsolete::BiQuadFilterBandPass(e) => {
+                DeviceSettings::Effect(id, EffectSettings::FilterBandPass12db(e.to_params()))
+            }
+            EntityObsolete::BiQuadFilterBandStop(e) => {
+                DeviceSettings::Effect(id, EffectSettings::FilterBandStop12db(e.to_params()))
+            }
+            EntityObsolete::BiQuadFilterAllPass(e) => {
+                DeviceSettings::Effect(id, EffectSettings::FilterAllPass12db(e.to_params()))
+            }
+            EntityObsolete::BiQuadFilterPeakingEq(e) => {
+                DeviceSettings::Effect(id, EffectSettings::FilterPeakingEq12db(e.to_params()))
+            }
+            EntityObsolete::BiQuadFilterLowShelf(e) => {
+                DeviceSettings::Effect(id, EffectSettings::FilterLowShelf12db(e.to_params()))
+            }
+            EntityObsolete::BiQuadFilterHighShelf(e) => {
+                DeviceSettings::Effect(id, EffectSettings::FilterHighShelf12db(e.to_params()))
+            }
+            _ => return None,
+        };
+        Some(settings)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::SongSettings;
+    use super::{LoadError, PatchSettings, SongSettings, ToSettings, ValidationError};
+    use groove_utils::Paths;
 
     #[test]
     fn empty_file_fails_with_proper_error() {
@@ -333,4 +875,266 @@ mod tests {
         let r = SongSettings::new_from_json5("{\"foo\": 1}");
         assert!(r.unwrap_err().to_string().contains("missing field "));
     }
+
+    #[test]
+    fn orchestrator_round_trips_through_settings() {
+        let json = r#"{
+            title: "Round trip",
+            clock: { bpm: 120.0, midi-ticks-per-second: 960, time-signature: [4, 4] },
+            devices: [
+                { instrument: ["synth", { toy-instrument: [{ midi-in: 0 }, { fake-value: 0.5, dca: { gain: 1.0, pan: 0.0 } }] }] },
+                { effect: ["gain", { gain: { ceiling: 0.5 } }] },
+            ],
+            patch-cables: [["synth", "gain", "main-mixer"]],
+        }"#;
+        let settings = SongSettings::new_from_json5(json).unwrap();
+        let paths = Paths::default();
+        let o = settings.instantiate(&paths, false).unwrap();
+
+        let saved = o.to_settings();
+        assert_eq!(saved.title.as_deref(), Some("Round trip"));
+        assert_eq!(saved.devices.len(), 2);
+        assert_eq!(saved.patches.len(), 2);
+        assert!(saved.patches.contains(&PatchSettings {
+            output: "synth".to_string(),
+            input: "gain".to_string()
+        }));
+        assert!(saved.patches.contains(&PatchSettings {
+            output: "gain".to_string(),
+            input: "main-mixer".to_string()
+        }));
+
+        // The saved settings must be reloadable, and reloading them must
+        // produce the same project.
+        let reloaded_json = serde_json::to_string(&saved).unwrap();
+        let reloaded = SongSettings::new_from_json5(&reloaded_json).unwrap();
+        let o2 = reloaded.instantiate(&paths, false).unwrap();
+        let resaved = o2.to_settings();
+        assert_eq!(
+            serde_json::to_string(&resaved).unwrap(),
+            reloaded_json,
+            "second save should match the first"
+        );
+    }
+
+    #[test]
+    fn patch_to_missing_device_names_it() {
+        let json = r#"{
+            clock: { bpm: 120.0, midi-ticks-per-second: 960, time-signature: [4, 4] },
+            devices: [
+                { effect: ["gain", { gain: { ceiling: 0.5 } }] },
+            ],
+            patches: [{ output: "gain", input: "main-mixer" }, { output: "synth", input: "gain" }],
+        }"#;
+        let settings = SongSettings::new_from_json5(json).unwrap();
+        let r = settings.instantiate(&Paths::default(), false);
+        assert!(r.unwrap_err().to_string().contains("'synth'"));
+
+        let json = r#"{
+            clock: { bpm: 120.0, midi-ticks-per-second: 960, time-signature: [4, 4] },
+            devices: [
+                { effect: ["gain", { gain: { ceiling: 0.5 } }] },
+            ],
+            control-links: [{ source: "lfo", target: "gain", control-index: 0 }],
+        }"#;
+        let settings = SongSettings::new_from_json5(json).unwrap();
+        let r = settings.instantiate(&Paths::default(), false);
+        assert!(r.unwrap_err().to_string().contains("'lfo'"));
+    }
+
+    #[test]
+    fn bad_time_signature_fails_with_proper_error() {
+        let json = r#"{
+            clock: { bpm: 120.0, midi-ticks-per-second: 960, time-signature: [4, 0] },
+            devices: [],
+        }"#;
+        let settings = SongSettings::new_from_json5(json).unwrap();
+        let r = settings.instantiate(&Paths::default(), false);
+        assert!(r
+            .unwrap_err()
+            .to_string()
+            .contains("must be a power of two"));
+
+        let json = r#"{
+            clock: { bpm: 120.0, midi-ticks-per-second: 960, time-signature: [0, 4] },
+            devices: [],
+        }"#;
+        let settings = SongSettings::new_from_json5(json).unwrap();
+        let r = settings.instantiate(&Paths::default(), false);
+        assert!(r
+            .unwrap_err()
+            .to_string()
+            .contains("top must be at least 1"));
+    }
+
+    #[test]
+    fn summary_describes_without_loading() {
+        let json = r#"{
+            title: "Preview",
+            clock: { bpm: 128.0, midi-ticks-per-second: 960, time-signature: [3, 4] },
+            devices: [
+                { instrument: ["lead", { toy-instrument: [{ midi-in: 0 }, { fake-value: 0.5, dca: { gain: 1.0, pan: 0.0 } }] }] },
+                { effect: ["gain", { gain: { ceiling: 0.5 } }] },
+                { instrument: ["bass", { toy-instrument: [{ midi-in: 1 }, { fake-value: 0.5, dca: { gain: 1.0, pan: 0.0 } }] }] },
+            ],
+            patterns: [{ id: "verse", notes: [[60]] }],
+        }"#;
+        let summary = SongSettings::new_from_json5(json).unwrap().summary();
+        assert_eq!(summary.title.as_deref(), Some("Preview"));
+        assert_eq!(summary.instruments, vec!["lead", "bass"]);
+        assert_eq!(summary.effects, vec!["gain"]);
+        assert!(summary.controllers.is_empty());
+        assert_eq!(
+            summary.to_string(),
+            "2 instruments, 1 effect, 1 pattern, 128 BPM, 3/4"
+        );
+    }
+
+    #[test]
+    fn validation_lists_every_dangling_reference() {
+        let json = r#"{
+            clock: { bpm: 120.0, midi-ticks-per-second: 960, time-signature: [4, 4] },
+            devices: [
+                { effect: ["gain", { gain: { ceiling: 0.5 } }] },
+                { effect: ["gain", { gain: { ceiling: 0.25 } }] },
+            ],
+            patch-cables: [["synth", "gain", "main-mixer"]],
+            control-links: [{ source: "lfo", target: "gain", control-index: 0 }],
+            patterns: [{ id: "verse", notes: [[60]] }],
+            tracks: [{ id: "lead", midi-channel: 0, patterns: ["verse", "chorus"] }],
+        }"#;
+        let settings = SongSettings::new_from_json5(json).unwrap();
+        let problems = settings.validate();
+        assert_eq!(
+            problems,
+            vec![
+                ValidationError::DuplicateId("gain".to_string()),
+                ValidationError::UnknownDevice("patch cable #1".to_string(), "synth".to_string()),
+                ValidationError::UnknownDevice("control link #1".to_string(), "lfo".to_string()),
+                ValidationError::UnknownPattern("lead".to_string(), "chorus".to_string()),
+            ]
+        );
+        assert_eq!(
+            problems[3].to_string(),
+            "track lead references unknown pattern 'chorus'"
+        );
+
+        // Loading fails with all of them, not just the first.
+        let e = settings.instantiate(&Paths::default(), false).unwrap_err();
+        match e.downcast_ref::<LoadError>() {
+            Some(LoadError::Invalid(reported)) => assert_eq!(reported, &problems),
+            _ => panic!("unexpected error {e}"),
+        }
+
+        let json = r#"{
+            clock: { bpm: 120.0, midi-ticks-per-second: 960, time-signature: [4, 4] },
+            devices: [{ effect: ["gain", { gain: { ceiling: 0.5 } }] }],
+            patches: [{ output: "gain", input: "main-mixer" }],
+        }"#;
+        let settings = SongSettings::new_from_json5(json).unwrap();
+        assert!(settings.validate().is_empty());
+    }
+
+    #[test]
+    fn renamed_device_keeps_its_connections() {
+        let json = r#"{
+            clock: { bpm: 120.0, midi-ticks-per-second: 960, time-signature: [4, 4] },
+            devices: [
+                { instrument: ["synth-1", { toy-instrument: [{ midi-in: 0 }, { fake-value: 0.5, dca: { gain: 1.0, pan: 0.0 } }] }] },
+                { effect: ["gain", { gain: { ceiling: 0.5 } }] },
+                { controller: ["lfo", { lfo: [{ midi-in: 0, midi-out: 0 }, { waveform: "triangle", frequency: 2 }] }] },
+            ],
+            patch-cables: [["synth-1", "gain"]],
+            patches: [{ output: "gain", input: "main-mixer" }],
+            control-links: [{ source: "lfo", target: "synth-1", control-index: 0 }],
+        }"#;
+        let mut settings = SongSettings::new_from_json5(json).unwrap();
+
+        // Taken IDs are rejected, and nothing changes.
+        for taken in ["gain", "main-mixer"] {
+            assert!(settings.rename_device("synth-1", taken).is_err());
+        }
+        assert!(settings.rename_device("main-mixer", "master").is_err());
+        assert!(settings.rename_device("nonexistent", "lead").is_err());
+        assert_eq!(settings.patch_cables[0][0], "synth-1");
+        assert_eq!(settings.control_links[0].target, "synth-1");
+
+        assert!(settings.rename_device("synth-1", "lead").is_ok());
+        assert_eq!(settings.patch_cables[0], vec!["lead", "gain"]);
+        assert_eq!(settings.control_links[0].target, "lead");
+
+        let o = settings.instantiate(&Paths::default(), false).unwrap();
+        let saved = o.to_settings();
+        assert!(saved.patches.contains(&PatchSettings {
+            output: "lead".to_string(),
+            input: "gain".to_string()
+        }));
+        assert_eq!(saved.control_links.len(), 1);
+        assert_eq!(saved.control_links[0].target, "lead");
+    }
+
+    #[test]
+    fn duplicated_pattern_is_independent() {
+        let json = r#"{
+            clock: { bpm: 120.0, midi-ticks-per-second: 960, time-signature: [4, 4] },
+            devices: [],
+            patterns: [
+                { id: "riff", notes: [[60, 62, 64, 65]] },
+                { id: "riff-copy", notes: [[48]] },
+            ],
+            tracks: [
+                { id: "lead", midi-channel: 0, patterns: ["riff", "riff"] },
+                { id: "pad", midi-channel: 1, patterns: ["riff"] },
+            ],
+        }"#;
+        let mut settings = SongSettings::new_from_json5(json).unwrap();
+
+        let new_id = settings.duplicate_pattern("riff").unwrap();
+        assert_eq!(new_id, "riff-copy-2", "doesn't collide with existing IDs");
+        assert_eq!(settings.patterns.len(), 3);
+        assert_eq!(settings.patterns[1].id, new_id, "goes after the original");
+        assert_eq!(settings.patterns[1].notes, settings.patterns[0].notes);
+
+        settings.patterns[1].notes[0][0] = 72;
+        assert_eq!(
+            settings.patterns[0].notes[0][0], 60,
+            "editing the copy leaves the original alone"
+        );
+        assert!(
+            settings
+                .tracks
+                .iter()
+                .all(|t| t.pattern_ids.iter().all(|id| id == "riff")),
+            "tracks still play the original"
+        );
+
+        assert!(settings.duplicate_pattern("nonexistent").is_none());
+    }
+
+    #[test]
+    fn duplicated_pattern_id_avoids_other_kinds_of_ids() {
+        let json = r#"{
+            clock: { bpm: 120.0, midi-ticks-per-second: 960, time-signature: [4, 4] },
+            devices: [
+                { effect: ["riff-copy", { gain: { ceiling: 0.5 } }] },
+            ],
+            patterns: [
+                { id: "riff", notes: [[60, 62, 64, 65]] },
+            ],
+            tracks: [
+                { id: "riff-copy-2", midi-channel: 0, patterns: ["riff"] },
+            ],
+        }"#;
+        let mut settings = SongSettings::new_from_json5(json).unwrap();
+
+        let new_id = settings.duplicate_pattern("riff").unwrap();
+        assert_eq!(
+            new_id, "riff-copy-3",
+            "a device and a track already have the first candidates"
+        );
+        assert_eq!(
+            settings.defined_ids().filter(|id| **id == new_id).count(),
+            1
+        );
+    }
 }
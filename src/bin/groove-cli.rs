@@ -9,11 +9,15 @@ mod obsolete {
     use ensnare_core::prelude::*;
     use groove::{app_version, DEFAULT_BPM};
     use groove_core::SAMPLE_BUFFER_SIZE;
-    use groove_orchestration::{helpers::IOHelper, Orchestrator};
+    use groove_orchestration::{
+        helpers::{BitDepth, Dither, FlacExportOptions, IOHelper, WavExportOptions},
+        Orchestrator,
+    };
     use groove_settings::SongSettings;
     use groove_utils::Paths;
     use regex::Regex;
     use std::{
+        ops::Range,
         path::{Path, PathBuf},
         time::Instant,
     };
@@ -31,10 +35,57 @@ mod obsolete {
         #[clap(short = 'w', long, value_parser)]
         wav: bool,
 
+        /// Render as FLAC file(s) (file will appear next to source file)
+        #[clap(short = 'f', long, value_parser)]
+        flac: bool,
+
+        /// FLAC compression level, from 0 (fastest) to 8 (smallest)
+        #[clap(long, value_parser, default_value = "5")]
+        flac_level: u8,
+
         /// Render as MP3 file(s) (not yet implemented)
         #[clap(short = 'm', long, value_parser)]
         mp3: bool,
 
+        /// Write the rendered WAVE (or FLAC, with --flac) file here instead of next to the source
+        /// file (only with a single input)
+        #[clap(short = 'o', long, value_parser)]
+        output: Option<PathBuf>,
+
+        /// Stop rendering after this many seconds
+        #[clap(long, value_parser)]
+        duration: Option<f64>,
+
+        /// Render only these bars, counting from 1, like "9-16"
+        #[clap(long, value_parser = parse_bars)]
+        bars: Option<Range<usize>>,
+
+        /// After the song ends, keep rendering until it falls silent, for at
+        /// most this many seconds
+        #[clap(long, value_parser)]
+        max_tail: Option<f64>,
+
+        /// Don't run the render through the master limiter, even if it clips
+        #[clap(long, value_parser)]
+        no_limiter: bool,
+
+        /// The WAVE or FLAC output's sample format: 16, 24, or 32f (WAVE only)
+        #[clap(long, value_parser, default_value = "16")]
+        bit_depth: BitDepth,
+
+        /// How to dither integer WAVE or FLAC output: none, tpdf, or shaped
+        #[clap(long, value_parser, default_value = "tpdf")]
+        dither: Dither,
+
+        /// Ignore the project's loop, so the render ends with the arrangement
+        #[clap(long, value_parser)]
+        no_loop: bool,
+
+        /// Render this many beats without saving them, and report how much
+        /// faster than realtime the render ran
+        #[clap(long, value_parser)]
+        benchmark: Option<usize>,
+
         /// Enable debug mode
         #[clap(short = 'd', long, value_parser)]
         debug: bool,
@@ -51,6 +102,21 @@ mod obsolete {
         #[clap(short = 'v', long, value_parser)]
         version: bool,
     }
+
+    // Turns "9-16" into 8..16, the zero-based range of bars to render.
+    fn parse_bars(s: &str) -> anyhow::Result<Range<usize>> {
+        let (first, last) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow::anyhow!("expected bars like 9-16, got '{s}'"))?;
+        let first: usize = first.trim().parse()?;
+        let last: usize = last.trim().parse()?;
+        if first == 0 || last < first {
+            return Err(anyhow::anyhow!(
+                "'{s}' isn't a range of bars counting from 1"
+            ));
+        }
+        Ok(first - 1..last)
+    }
 }
 
 fn main() -> anyhow::Result<()> {
@@ -64,6 +130,14 @@ fn main() -> anyhow::Result<()> {
             println!("groove-cli {}", app_version());
             return Ok(());
         }
+        if args.mp3 {
+            return Err(anyhow::anyhow!("MP3 output isn't supported yet"));
+        }
+        if args.output.is_some() && args.input.iter().filter(|i| *i != "-").count() > 1 {
+            return Err(anyhow::anyhow!(
+                "--output can be used only with a single input file"
+            ));
+        }
 
         for input_filename in args.input {
             if input_filename == "-" {
@@ -108,18 +182,55 @@ fn main() -> anyhow::Result<()> {
             };
 
             orchestrator.set_should_output_perf(args.perf);
+            if args.no_loop {
+                orchestrator.set_loop_enabled(false);
+            }
+
+            if let Some(beats) = args.benchmark {
+                let report = orchestrator.benchmark(MusicalTime::new_with_beats(beats))?;
+                println!("{input_filename}: {report}");
+                continue;
+            }
 
             if !args.quiet {
                 print!("Performing to queue ");
             }
-            orchestrator.update_sample_rate(if args.wav {
+            let is_exporting = args.wav || args.flac || args.output.is_some();
+            orchestrator.update_sample_rate(if is_exporting {
                 SampleRate::DEFAULT
             } else {
                 IOHelper::get_output_device_sample_rate()
             });
             let start_instant = Instant::now();
             let mut sample_buffer = [StereoSample::SILENCE; SAMPLE_BUFFER_SIZE];
-            let performance = orchestrator.run_performance(&mut sample_buffer, args.quiet)?;
+            let max_frames = args.duration.map(|seconds| {
+                (seconds * orchestrator.sample_rate().value() as f64).round() as usize
+            });
+            let mut options = orchestrator.default_render_options();
+            if let Some(max_tail) = args.max_tail {
+                options.max_tail = max_tail;
+            }
+            if args.no_limiter {
+                options.limiter_ceiling = None;
+            }
+            let performance = if let Some(bars) = args.bars.as_ref() {
+                let beats_per_bar = orchestrator.time_signature().top;
+                let range = MusicalTime::new_with_beats(bars.start * beats_per_bar)
+                    ..MusicalTime::new_with_beats(bars.end * beats_per_bar);
+                orchestrator.run_performance_range(
+                    &mut sample_buffer,
+                    args.quiet,
+                    &range,
+                    &options,
+                )?
+            } else {
+                orchestrator.run_performance_with(
+                    &mut sample_buffer,
+                    args.quiet,
+                    max_frames,
+                    &options,
+                )?
+            };
             if args.perf {
                 println!(
                     "\n Orchestrator performance time: {:.2?}",
@@ -140,16 +251,43 @@ fn main() -> anyhow::Result<()> {
             if !args.quiet {
                 println!("Rendering queue");
             }
-            if args.wav {
-                let re = Regex::new(r"\.json5$").unwrap();
-                let output_filename = re.replace(&input_filename, ".wav");
-                if input_filename == output_filename {
-                    panic!("would overwrite input file; couldn't generate output filename");
+            if is_exporting {
+                let output_path = if let Some(output) = args.output.as_ref() {
+                    output.clone()
+                } else {
+                    let re = Regex::new(r"\.json5?$").unwrap();
+                    let extension = if args.flac { ".flac" } else { ".wav" };
+                    let output_filename = re.replace(&input_filename, extension);
+                    if input_filename == output_filename {
+                        return Err(anyhow::anyhow!(
+                            "would overwrite input file; couldn't generate output filename"
+                        ));
+                    }
+                    PathBuf::from(output_filename.to_string())
+                };
+                if args.flac {
+                    IOHelper::send_performance_to_flac(
+                        &performance,
+                        &output_path,
+                        &FlacExportOptions {
+                            bit_depth: args.bit_depth,
+                            dither: args.dither,
+                            compression_level: args.flac_level,
+                        },
+                    )?;
+                } else {
+                    IOHelper::send_performance_to_file_with(
+                        &performance,
+                        &output_path,
+                        &WavExportOptions {
+                            bit_depth: args.bit_depth,
+                            dither: args.dither,
+                        },
+                    )?;
+                }
+                if !args.quiet {
+                    println!("Wrote {}", output_path.display());
                 }
-                IOHelper::send_performance_to_file(
-                    &performance,
-                    &PathBuf::from(output_filename.to_string()),
-                )?;
             } else {
                 //  send_performance_to_output_device(&performance)?;
             }
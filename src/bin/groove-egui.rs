@@ -17,25 +17,43 @@ mod obsolete {
         CreationContext,
     };
     use egui_toast::{Toast, ToastOptions, Toasts};
-    use ensnare_core::{midi::prelude::*, prelude::*, traits::prelude::*};
+    use ensnare_core::{core::AudioQueue, midi::prelude::*, prelude::*, traits::prelude::*};
     use groove::{
         app_version,
+        mini::{MidiClockEvent, Transport},
         panels::{
-            ControlBar, EntityBrowser, EntityBrowserEvent, MidiPanel, MidiSettings, OldAudioPanel,
-            Preferences,
+            AudioPanel, AudioPanelEvent, ControlBar, ControlPanel, ControlPanelAction,
+            EntityBrowser, EntityBrowserEvent, MidiOutputSender, MidiPanel, MidiSettings,
+            NeedsAudioFn, Preferences,
         },
     };
     use groove_core::{
         midi::{MidiChannel, MidiMessage},
         time::{ClockParams, TimeSignatureParams},
+        SAMPLE_BUFFER_SIZE,
+    };
+    #[cfg(feature = "websocket")]
+    use groove_orchestration::web_api::{ApiCommand, ApiEntity, ApiEvent, WebApiServer};
+    use groove_orchestration::{
+        messages::{GrooveEvent, GrooveInput, Internal},
+        osc::{OscFeed, OscFeedSnapshot, OscServer},
+        Orchestrator,
+    };
+    use groove_settings::{
+        recovery::{AutoSaver, RecoveryFile},
+        SongSettings,
     };
-    use groove_orchestration::{messages::GrooveInput, Orchestrator};
     use groove_utils::Paths;
     use std::{
         path::{Path, PathBuf},
         sync::{Arc, Mutex},
         time::Instant,
     };
+    #[cfg(feature = "link")]
+    use {
+        groove::mini::{LinkAction, LinkSession, LinkSync, TransportSnapshot},
+        std::time::Duration,
+    };
 
     /// Any part of the system can send a [Message] to the app.
     #[derive(Debug)]
@@ -62,11 +80,27 @@ mod obsolete {
         orchestrator: Arc<Mutex<Orchestrator>>,
 
         control_bar: ControlBar,
-        audio_panel: OldAudioPanel,
+        control_panel: ControlPanel,
+        // Only for tap tempo, which needs to remember earlier taps.
+        tap_tempo: Transport,
+        audio_panel: AudioPanel,
         midi_panel: MidiPanel,
         thing_browser: EntityBrowser,
         toasts: Toasts,
 
+        // Remote control over OSC. None if the port was unavailable.
+        #[allow(dead_code)]
+        osc_server: Option<OscServer>,
+        osc_receiver: Option<Receiver<GrooveInput>>,
+        osc_feed: Option<OscFeed>,
+
+        // The JSON API for browser front ends. None if the port was
+        // unavailable.
+        #[cfg(feature = "websocket")]
+        web_api: Option<(WebApiServer, Receiver<ApiCommand>)>,
+        #[cfg(feature = "websocket")]
+        last_web_api_update: Instant,
+
         #[allow(dead_code)]
         regular_font_id: FontId,
         #[allow(dead_code)]
@@ -75,10 +109,31 @@ mod obsolete {
 
         frames: usize,
         start_of_time: Instant,
+
+        recovery_file: RecoveryFile,
+        auto_saver: AutoSaver,
+        /// True while we're asking whether to restore the recovery file. We
+        /// don't autosave until the user answers, so that the unsaved work
+        /// isn't overwritten.
+        is_offering_recovery: bool,
+
+        #[cfg(feature = "link")]
+        link: LinkSession,
+        /// The tempo as of the last follow_link(), to tell whether the user
+        /// has changed it since.
+        #[cfg(feature = "link")]
+        link_bpm: f64,
     }
     impl eframe::App for GrooveApp {
         fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
             self.handle_message_queue();
+            #[cfg(feature = "link")]
+            self.follow_link();
+            self.update_osc_feed();
+            #[cfg(feature = "websocket")]
+            self.update_web_api();
+            self.autosave();
+            self.preferences.save_if_due(Instant::now());
 
             // TODO: the entity browser also acts on the tab. I'm probably looking
             // at keys the wrong way.
@@ -112,6 +167,21 @@ mod obsolete {
             top.show(ctx, |ui| {
                 if let Ok(mut o) = self.orchestrator.lock() {
                     self.control_bar.show(ui, &mut o);
+                    self.control_panel.set_transport(Self::transport_for(&o));
+                }
+                #[cfg(feature = "link")]
+                self.control_panel
+                    .set_link_status(self.link.is_enabled().then(|| self.link.peer_count()));
+                if let Some(action) = self.control_panel.show_with_action(ui) {
+                    self.handle_control_panel_action(action);
+                }
+                #[cfg(feature = "link")]
+                {
+                    let mut is_enabled = self.link.is_enabled();
+                    let label = format!("Link ({} peers)", self.link.peer_count());
+                    if ui.checkbox(&mut is_enabled, label).changed() {
+                        self.link.set_enabled(is_enabled);
+                    }
                 }
             });
             bottom.show(ctx, |ui| {
@@ -140,15 +210,17 @@ mod obsolete {
                         .show(ui, &self.paths, Arc::clone(&self.orchestrator));
                 });
             });
-            right.show(ctx, |ui| {
-                egui::ScrollArea::vertical().show(ui, |ui| {
-                    ui.vertical(|ui| {
-                        self.preferences.ui(ui);
-                        self.midi_panel.ui(ui);
-                        self.audio_panel.ui(ui);
-                    });
-                })
-            });
+            if self.preferences.is_panel_open(Preferences::SETTINGS_PANEL) {
+                right.show(ctx, |ui| {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.vertical(|ui| {
+                            self.preferences.ui(ui);
+                            self.midi_panel.ui(ui);
+                            self.audio_panel.ui(ui);
+                        });
+                    })
+                });
+            }
             center.show(ctx, |ui| {
                 ScrollArea::vertical().show(ui, |ui| {
                     if let Ok(mut o) = self.orchestrator.lock() {
@@ -157,6 +229,9 @@ mod obsolete {
                 });
                 self.toasts.show(ctx);
             });
+            if self.is_offering_recovery {
+                self.show_recovery_offer(ctx);
+            }
 
             // TODO: this is how to keep redrawing when the system doesn't otherwise
             // know that a repaint is needed. This is fine for now, but it's
@@ -165,6 +240,10 @@ mod obsolete {
 
             self.frames += 1;
         }
+
+        fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+            self.preferences.save_if_dirty();
+        }
     }
     impl GrooveApp {
         pub const FONT_REGULAR: &str = "font-regular";
@@ -196,26 +275,78 @@ mod obsolete {
                 Ok(preferences) => preferences,
                 Err(e) => {
                     eprintln!("While loading preferences: {:?}", e);
-                    Preferences::default()
+                    // First run, most likely. Start with the settings showing.
+                    let mut preferences = Preferences::default();
+                    preferences.set_panel_open(Preferences::SETTINGS_PANEL, true);
+                    preferences
                 }
             };
 
             // TODO: this is wrong, but it's to get legacy code to keep building.
             let settings = Arc::new(Mutex::new(MidiSettings::default()));
 
+            let (osc_server, osc_receiver) = match preferences.osc_server_address() {
+                Some(address) => {
+                    let osc_orchestrator = Arc::clone(&orchestrator);
+                    let server = address
+                        .parse()
+                        .map_err(|e| anyhow::anyhow!("invalid OSC address {address}: {e}"))
+                        .and_then(|address| {
+                            OscServer::start(address, move |uvid| {
+                                osc_orchestrator.lock().ok()?.get_uid_by_uvid(uvid)
+                            })
+                        });
+                    match server {
+                        Ok((server, receiver)) => (Some(server), Some(receiver)),
+                        Err(e) => {
+                            eprintln!("Warning: couldn't start the OSC server: {e}");
+                            (None, None)
+                        }
+                    }
+                }
+                None => (None, None),
+            };
+
+            let midi_panel = MidiPanel::new_with(settings);
+
+            #[cfg(feature = "websocket")]
+            let web_api = if preferences.is_web_api_enabled() {
+                match WebApiServer::start(WebApiServer::DEFAULT_PORT) {
+                    Ok(web_api) => Some(web_api),
+                    Err(e) => {
+                        eprintln!("Warning: couldn't start the web API: {e}");
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
             let mut r = Self {
                 paths: paths.clone(),
 
                 orchestrator: Arc::clone(&orchestrator),
 
                 control_bar: ControlBar::default(),
-                midi_panel: MidiPanel::new_with(settings),
-                audio_panel: OldAudioPanel::new_with(Arc::clone(&orchestrator)),
+                control_panel: ControlPanel::default(),
+                tap_tempo: Transport::default(),
+                audio_panel: AudioPanel::new_with(Self::needs_audio_fn(
+                    Arc::clone(&orchestrator),
+                    midi_panel.output_sender(),
+                )),
+                midi_panel,
                 preferences,
                 thing_browser: EntityBrowser::scan_everything(&paths, extra_paths),
                 toasts: Toasts::new()
                     .anchor(Align2::RIGHT_BOTTOM, (-10.0, -10.0))
                     .direction(egui::Direction::BottomUp),
+                osc_server,
+                osc_receiver,
+                osc_feed: None,
+                #[cfg(feature = "websocket")]
+                web_api,
+                #[cfg(feature = "websocket")]
+                last_web_api_update: Instant::now(),
 
                 regular_font_id: FontId::proportional(14.0),
                 bold_font_id: FontId::new(12.0, FontFamily::Name(Self::FONT_BOLD.into())),
@@ -224,16 +355,153 @@ mod obsolete {
                 frames: Default::default(),
                 start_of_time: Instant::now(),
 
+                recovery_file: RecoveryFile::default(),
+                auto_saver: AutoSaver::default(),
+                is_offering_recovery: false,
+
+                #[cfg(feature = "link")]
+                link: LinkSession::new_with(Tempo(clock_params.bpm), LinkSync::default()),
+                #[cfg(feature = "link")]
+                link_bpm: clock_params.bpm,
+
                 // Keep these last to avoid a bunch of temporary variables
                 sender,
                 receiver,
             };
 
+            r.restore_audio_output();
             r.load_project_at_startup();
+            r.check_for_recovery_file();
+            r.start_osc_feed();
 
             r
         }
 
+        // Brings the orchestrator in line with the Link session, if we're in
+        // one. This runs once per UI frame, which is often enough, because
+        // phase errors are corrected gradually.
+        #[cfg(feature = "link")]
+        fn follow_link(&mut self) {
+            let Ok(mut o) = self.orchestrator.lock() else {
+                return;
+            };
+            if o.bpm() != self.link_bpm {
+                self.link.set_tempo(Tempo(o.bpm()));
+            }
+            let local = TransportSnapshot {
+                tempo: Tempo(o.bpm()),
+                position: o.position(),
+                is_performing: o.is_performing(),
+            };
+            for action in self.link.update(Duration::ZERO, &local) {
+                match action {
+                    LinkAction::SetTempo(tempo) => o.set_bpm(tempo.0),
+                    LinkAction::Start(position) => {
+                        o.seek(position);
+                        o.play();
+                    }
+                    LinkAction::Stop => o.stop(),
+                    LinkAction::Nudge { percent, duration } => o.nudge_tempo(percent, duration),
+                    LinkAction::Seek(position) => o.seek(position),
+                }
+            }
+            self.link_bpm = o.bpm();
+        }
+
+        fn start_osc_feed(&mut self) {
+            let Some(destination) = self.preferences.osc_feed_destination() else {
+                return;
+            };
+            let feed = destination
+                .parse()
+                .map_err(|e| anyhow::anyhow!("invalid OSC feed destination {destination}: {e}"))
+                .and_then(|destination| OscFeed::new_with(destination, OscFeed::DEFAULT_RATE_HZ));
+            match feed {
+                Ok(feed) => self.osc_feed = Some(feed),
+                Err(e) => self.add_error_toast(e.to_string()),
+            }
+        }
+
+        // Runs once per UI frame. The feed decides for itself whether it's
+        // time to send.
+        fn update_osc_feed(&mut self) {
+            let Some(feed) = self.osc_feed.as_mut() else {
+                return;
+            };
+            let Ok(o) = self.orchestrator.lock() else {
+                return;
+            };
+            let snapshot = OscFeedSnapshot {
+                position: o.position(),
+                time_signature: o.time_signature().clone(),
+                tempo: Tempo(o.bpm()),
+                is_performing: o.is_performing(),
+                main_level: o.main_output_level(),
+                entity_levels: Default::default(),
+            };
+            let _ = feed.update(&snapshot, Instant::now());
+        }
+
+        /// How often browsers hear about the transport and meters.
+        #[cfg(feature = "websocket")]
+        const WEB_API_UPDATE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+        // Carries out commands from browsers, and keeps them up to date.
+        #[cfg(feature = "websocket")]
+        fn update_web_api(&mut self) {
+            let Some((server, commands)) = self.web_api.as_ref() else {
+                return;
+            };
+            let Ok(mut o) = self.orchestrator.lock() else {
+                return;
+            };
+            while let Ok(command) = commands.try_recv() {
+                let result = command.to_groove_input(|uvid| o.get_uid_by_uvid(uvid));
+                match (command, result) {
+                    (_, Ok(Some(input))) => {
+                        let _ = o.update(input);
+                    }
+                    (ApiCommand::ListEntities, Ok(None)) => {
+                        let entities = o
+                            .entity_iter()
+                            .map(|(uid, _)| ApiEntity {
+                                uid: uid.to_string(),
+                                uvid: o.get_uvid_by_uid(*uid).map(|uvid| uvid.to_string()),
+                            })
+                            .collect();
+                        server.publish(&ApiEvent::Entities { entities });
+                    }
+                    (ApiCommand::AddEntity { key }, Ok(None)) => {
+                        // The legacy orchestrator builds entities only from
+                        // project files, so it can't add one by key.
+                        server.publish(&ApiEvent::Error {
+                            message: format!("adding {key} isn't supported here"),
+                        });
+                    }
+                    (_, Ok(None)) => {}
+                    (_, Err(e)) => server.publish(&ApiEvent::Error {
+                        message: e.to_string(),
+                    }),
+                }
+            }
+
+            let now = Instant::now();
+            if !server.has_clients()
+                || now.saturating_duration_since(self.last_web_api_update)
+                    < Self::WEB_API_UPDATE_INTERVAL
+            {
+                return;
+            }
+            self.last_web_api_update = now;
+            server.publish(&ApiEvent::transport(
+                o.position(),
+                o.time_signature(),
+                Tempo(o.bpm()),
+                o.is_performing(),
+            ));
+            server.publish(&ApiEvent::meters(o.main_output_level(), &[]));
+        }
+
         fn set_up_extra_paths() -> Vec<PathBuf> {
             let mut local_projects = Paths::hive(groove_utils::PathType::Cwd);
             local_projects.push(Paths::projects_rel());
@@ -318,18 +586,132 @@ mod obsolete {
             ctx.set_style(style);
         }
 
-        fn load_project_at_startup(&mut self) {
-            if self.preferences.should_reload_last_project() {
-                if let Some(path) = self.preferences.project_filename() {
-                    if let Err(err) = Preferences::handle_load(
-                        &self.paths,
-                        Path::new(path),
-                        Arc::clone(&self.orchestrator),
-                    ) {
-                        self.preferences.set_should_reload_last_project(false);
-                        self.add_error_toast(err.to_string());
+        // Renders the orchestrator into the audio queue a buffer at a time.
+        // Everything a buffer sends to external MIDI devices goes out in one
+        // batch.
+        fn needs_audio_fn(
+            orchestrator: Arc<Mutex<Orchestrator>>,
+            midi_output: MidiOutputSender,
+        ) -> NeedsAudioFn {
+            let mut samples = [StereoSample::SILENCE; SAMPLE_BUFFER_SIZE];
+            let mut midi_messages = Vec::default();
+            Box::new(move |queue: &AudioQueue, count: usize| {
+                let Ok(mut o) = orchestrator.lock() else {
+                    return;
+                };
+                for _ in 0..count / SAMPLE_BUFFER_SIZE {
+                    let (response, _ticks_completed) = o.tick(&mut samples);
+                    for sample in samples {
+                        let _ = queue.push(sample);
                     }
+                    let events = match response.0 {
+                        Internal::None => Vec::default(),
+                        Internal::Single(event) => vec![event],
+                        Internal::Batch(events) => events,
+                    };
+                    midi_messages.clear();
+                    midi_messages.extend(events.into_iter().filter_map(|event| match event {
+                        GrooveEvent::MidiToExternal(channel, message) => Some((channel, message)),
+                        _ => None,
+                    }));
+                    midi_output.send_batch(&midi_messages);
                 }
+            })
+        }
+
+        // Goes back to the output device chosen last time, if it's still
+        // plugged in.
+        fn restore_audio_output(&mut self) {
+            let devices = self.audio_panel.refresh_output_devices();
+            if let Some(name) =
+                Preferences::restorable_device(self.preferences.selected_audio_output(), &devices)
+            {
+                // Failure is reported through the panel's channel.
+                let _ = self.audio_panel.select_output_device(name);
+            }
+        }
+
+        fn load_project_at_startup(&mut self) {
+            if let Some(path) = self.preferences.restorable_project_filename() {
+                if let Err(err) = Preferences::handle_load(
+                    &self.paths,
+                    Path::new(path),
+                    Arc::clone(&self.orchestrator),
+                ) {
+                    self.preferences.set_should_reload_last_project(false);
+                    self.add_error_toast(err.to_string());
+                }
+            }
+        }
+
+        // A snapshot of the orchestrator's tempo and play state, in the form
+        // that the control panel displays.
+        fn transport_for(o: &Orchestrator) -> ensnare_core::time::Transport {
+            let mut transport = ensnare_core::time::Transport::default();
+            transport.set_tempo(Tempo(o.bpm()));
+            if o.is_performing() {
+                transport.play();
+            }
+            transport
+        }
+
+        // The recovery file is worth offering only if it's newer than the
+        // project as last saved.
+        fn check_for_recovery_file(&mut self) {
+            let last_saved = self
+                .preferences
+                .project_filename()
+                .and_then(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok());
+            self.is_offering_recovery = self.recovery_file.is_stale(last_saved);
+        }
+
+        fn show_recovery_offer(&mut self, ctx: &Context) {
+            egui::Window::new("Recover unsaved work?")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(Align2::CENTER_CENTER, (0.0, 0.0))
+                .show(ctx, |ui| {
+                    ui.label("Groove found changes that weren't saved before it last quit.");
+                    ui.horizontal(|ui| {
+                        if ui.button("Restore").clicked() {
+                            self.is_offering_recovery = false;
+                            if let Err(err) = self.restore_recovery_file() {
+                                self.add_error_toast(err.to_string());
+                            }
+                        }
+                        if ui.button("Discard").clicked() {
+                            self.is_offering_recovery = false;
+                            self.recovery_file.discard();
+                        }
+                    });
+                });
+        }
+
+        fn restore_recovery_file(&mut self) -> anyhow::Result<()> {
+            let settings: SongSettings = self.recovery_file.load()?;
+            let instance = settings.instantiate(&self.paths, false)?;
+            if let Ok(mut o) = self.orchestrator.lock() {
+                let sample_rate = o.sample_rate();
+                *o = instance;
+                o.update_sample_rate(sample_rate);
+            }
+            Ok(())
+        }
+
+        // Runs once per UI frame. The AutoSaver decides whether it's time.
+        fn autosave(&mut self) {
+            let now = Instant::now();
+            if self.is_offering_recovery || !self.auto_saver.is_due(now) {
+                return;
+            }
+            self.auto_saver.mark_saved(now);
+            let settings = if let Ok(o) = self.orchestrator.lock() {
+                SongSettings::from(&*o)
+            } else {
+                return;
+            };
+            if let Err(e) = self.recovery_file.save(&settings) {
+                eprintln!("Warning: couldn't autosave: {e}");
             }
         }
 
@@ -355,6 +737,23 @@ mod obsolete {
                                 o.update(GrooveInput::MidiFromExternal(channel, message));
                             }
                         }
+                        groove::panels::MidiPanelEvent::Mpe(..) => {
+                            // The legacy orchestrator has no per-voice
+                            // routing, so MPE input isn't supported here.
+                        }
+                        groove::panels::MidiPanelEvent::Clock(event) => {
+                            if let Ok(mut o) = self.orchestrator.lock() {
+                                match event {
+                                    MidiClockEvent::Tempo(tempo) => o.set_bpm(tempo.0),
+                                    MidiClockEvent::Start => {
+                                        o.skip_to_start();
+                                        o.play();
+                                    }
+                                    MidiClockEvent::Continue => o.play(),
+                                    MidiClockEvent::Stop => o.stop(),
+                                }
+                            }
+                        }
                         groove::panels::MidiPanelEvent::SelectInput(port) => {
                             self.preferences.set_selected_midi_input(&port.to_string())
                         }
@@ -366,6 +765,24 @@ mod obsolete {
                         }
                     }
                 }
+                if let Ok(message) = self.audio_panel.receiver().try_recv() {
+                    received = true;
+                    match message {
+                        AudioPanelEvent::OutputDeviceSelected(name) => {
+                            self.preferences.set_selected_audio_output(&name)
+                        }
+                        AudioPanelEvent::OutputDeviceError(text) => self.add_error_toast(text),
+                        AudioPanelEvent::InterfaceChanged
+                        | AudioPanelEvent::InputReset(..)
+                        | AudioPanelEvent::OutputDevices(_) => {}
+                    }
+                }
+                if let Some(Ok(input)) = self.osc_receiver.as_ref().map(|r| r.try_recv()) {
+                    received = true;
+                    if let Ok(mut o) = self.orchestrator.lock() {
+                        o.update(input);
+                    }
+                }
                 if let Ok(message) = self.thing_browser.receiver().try_recv() {
                     received = true;
                     match message {
@@ -383,6 +800,81 @@ mod obsolete {
             }
         }
 
+        // Carries out what the user asked for in the control panel.
+        fn handle_control_panel_action(&mut self, action: ControlPanelAction) {
+            match action {
+                ControlPanelAction::Play => {
+                    if let Ok(mut o) = self.orchestrator.lock() {
+                        o.play();
+                    }
+                }
+                ControlPanelAction::Stop => {
+                    if let Ok(mut o) = self.orchestrator.lock() {
+                        o.stop();
+                    }
+                }
+                ControlPanelAction::Panic => {
+                    let Ok(mut o) = self.orchestrator.lock() else {
+                        return;
+                    };
+                    // The orchestrator silences everything inside Groove, and
+                    // hands back the same messages for whatever is listening
+                    // on the MIDI output.
+                    let events = match o.update(GrooveInput::MidiPanic).0 {
+                        Internal::None => Vec::default(),
+                        Internal::Single(event) => vec![event],
+                        Internal::Batch(events) => events,
+                    };
+                    let messages: Vec<_> = events
+                        .into_iter()
+                        .filter_map(|event| match event {
+                            GrooveEvent::MidiToExternal(channel, message) => {
+                                Some((channel, message))
+                            }
+                            _ => None,
+                        })
+                        .collect();
+                    self.midi_panel.send_batch(&messages);
+                }
+                ControlPanelAction::Open(path) => {
+                    match Preferences::handle_load(
+                        &self.paths,
+                        &path,
+                        Arc::clone(&self.orchestrator),
+                    ) {
+                        Ok(path) => self.preferences.set_project_filename(&path),
+                        Err(err) => self.add_error_toast(err.to_string()),
+                    }
+                }
+                ControlPanelAction::ToggleLoop => {
+                    if let Ok(mut o) = self.orchestrator.lock() {
+                        let is_loop_enabled = o.is_loop_enabled();
+                        o.set_loop_enabled(!is_loop_enabled);
+                    }
+                }
+                ControlPanelAction::ToggleLink => {
+                    #[cfg(feature = "link")]
+                    self.link.set_enabled(!self.link.is_enabled());
+                    #[cfg(not(feature = "link"))]
+                    self.add_error_toast("This build doesn't include Ableton Link".to_string());
+                }
+                ControlPanelAction::TapTempo => {
+                    if let Some(tempo) = self.tap_tempo.tap() {
+                        if let Ok(mut o) = self.orchestrator.lock() {
+                            o.set_bpm(tempo.0);
+                        }
+                    }
+                }
+                ControlPanelAction::ToggleSettings => {
+                    let is_open = self.preferences.is_panel_open(Preferences::SETTINGS_PANEL);
+                    self.preferences
+                        .set_panel_open(Preferences::SETTINGS_PANEL, !is_open);
+                }
+                // The control bar still handles these.
+                ControlPanelAction::New | ControlPanelAction::Save(_) => {}
+            }
+        }
+
         fn add_error_toast(&mut self, text: String) {
             self.toasts.add(Toast {
                 kind: egui_toast::ToastKind::Error,
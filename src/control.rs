@@ -0,0 +1,42 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+pub use ensnare_core::control::ControlIndex;
+
+/// Describes one control of a #[derive(Control)] struct: what it's called,
+/// where it lives, and what range of values it takes. Generated by
+/// `control_param_descriptor()`, and used by
+/// [ControlEditorPanel](crate::panels::ControlEditorPanel) to edit entities it
+/// otherwise knows nothing about.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamDescriptor {
+    /// The fully qualified kebab-case name, such as "stuff-banana-quality".
+    pub name: String,
+
+    /// The index that `control_set_param_by_index()` expects for this
+    /// control. For nested structs, it's the flattened index in the
+    /// outermost struct.
+    pub index: ControlIndex,
+
+    /// The smallest value in the control's native units.
+    pub min: f64,
+
+    /// The largest value in the control's native units.
+    pub max: f64,
+
+    /// The initial value in the control's native units.
+    pub default: f64,
+
+    /// A label for the native units, like "Hz". Empty if unitless.
+    pub unit: String,
+}
+impl ParamDescriptor {
+    /// Maps a value in native units to the 0..=1 range that
+    /// [ControlValue](ensnare_core::control::ControlValue) setters expect.
+    pub fn normalize(&self, value: f64) -> f64 {
+        if self.max > self.min {
+            ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
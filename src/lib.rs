@@ -14,9 +14,15 @@ use ensnare_core::prelude::ParameterType;
 /// Widgets for egui
 pub mod panels;
 
+/// Support types for code generated by #[derive(Control)].
+pub mod control;
+
 /// Temp home for minidaw research results
 pub mod mini;
 
+/// Support types for code generated by #[derive(Params)].
+pub mod params;
+
 /// Recommended imports for first-time users.
 pub mod prelude {
     pub use ensnare_core::core::StereoSample;
@@ -0,0 +1,201 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::{
+    control::{ControlIndex, ControlValue},
+    midi::prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+/// Which of an instrument's controls key pressure drives, and over what range.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PressureRoute {
+    /// The index of the instrument's #[control] parameter, e.g. filter cutoff
+    /// for brightness or LFO depth for vibrato.
+    pub control_index: usize,
+
+    /// The control value for no pressure.
+    pub min: f64,
+
+    /// The control value for full pressure.
+    pub max: f64,
+}
+
+/// A change that [PressureRouting::route()] asks the instrument to make.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PressureChange {
+    /// The key of the one voice that should change, for polyphonic
+    /// aftertouch, or None to change every voice, for channel pressure.
+    pub key: Option<u8>,
+
+    #[allow(missing_docs)]
+    pub control_index: ControlIndex,
+
+    #[allow(missing_docs)]
+    pub value: ControlValue,
+}
+
+/// Turns aftertouch into control changes, the way [CcRouting](super::CcRouting)
+/// does for CCs. Channel pressure (`ChannelAftertouch`) applies to the whole
+/// instrument; polyphonic aftertouch (`Aftertouch`) applies only to the voice
+/// playing the matching key. The instrument's `HandlesMidi` implementation
+/// passes every message through [PressureRouting::route()] and applies each
+/// change either with `control_set_param_by_index()` or on the voice for
+/// [PressureChange::key].
+///
+/// A key's pressure goes back to zero when the key is released, so the next
+/// note on that key doesn't start out bright.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PressureRouting {
+    route: Option<PressureRoute>,
+
+    #[serde(skip)]
+    channel_pressure: u8,
+    #[serde(skip)]
+    key_pressure: Vec<(u8, u8)>,
+}
+impl PressureRouting {
+    #[allow(missing_docs)]
+    pub fn new_with(route: PressureRoute) -> Self {
+        Self {
+            route: Some(route),
+            ..Default::default()
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn route_to(&self) -> Option<&PressureRoute> {
+        self.route.as_ref()
+    }
+
+    /// Sets the destination. None turns aftertouch off for this instrument.
+    pub fn set_route(&mut self, route: Option<PressureRoute>) {
+        self.route = route;
+    }
+
+    /// The current pressure on the given key, 0..=127, counting both channel
+    /// pressure and that key's own aftertouch.
+    pub fn pressure_for(&self, key: u8) -> u8 {
+        let key_pressure = self
+            .key_pressure
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, p)| *p)
+            .unwrap_or_default();
+        key_pressure.max(self.channel_pressure)
+    }
+
+    // Forgets the key's aftertouch. Returns the key if there was any to
+    // forget.
+    fn release(&mut self, key: u8) -> Option<u8> {
+        let had_pressure = self.key_pressure.iter().any(|(k, _)| *k == key);
+        self.key_pressure.retain(|(k, _)| *k != key);
+        had_pressure.then_some(key)
+    }
+
+    /// Tracks pressure from `message` and returns the control change it
+    /// causes, if any. Releasing a key that had aftertouch returns a change
+    /// that resets its voice.
+    pub fn route(&mut self, message: &MidiMessage) -> Option<PressureChange> {
+        let (key, pressure) = match message {
+            MidiMessage::ChannelAftertouch { vel } => {
+                self.channel_pressure = vel.as_int();
+                (None, self.channel_pressure)
+            }
+            MidiMessage::Aftertouch { key, vel } => {
+                let key = key.as_int();
+                self.key_pressure.retain(|(k, _)| *k != key);
+                if vel.as_int() != 0 {
+                    self.key_pressure.push((key, vel.as_int()));
+                }
+                (Some(key), self.pressure_for(key))
+            }
+            MidiMessage::NoteOff { key, .. } => (Some(self.release(key.as_int())?), 0),
+            MidiMessage::NoteOn { key, vel } if vel.as_int() == 0 => {
+                (Some(self.release(key.as_int())?), 0)
+            }
+            _ => return None,
+        };
+        let route = self.route.as_ref()?;
+        let amount = pressure as f64 / 127.0;
+        Some(PressureChange {
+            key,
+            control_index: ControlIndex(route.control_index),
+            value: ControlValue(route.min + (route.max - route.min) * amount),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUTOFF_INDEX: usize = 2;
+
+    fn routing() -> PressureRouting {
+        PressureRouting::new_with(PressureRoute {
+            control_index: CUTOFF_INDEX,
+            min: 0.25,
+            max: 1.0,
+        })
+    }
+
+    #[test]
+    fn channel_pressure_changes_every_voice() {
+        let mut r = routing();
+        let change = r
+            .route(&MidiMessage::ChannelAftertouch { vel: 127.into() })
+            .unwrap();
+        assert_eq!(change.key, None);
+        assert_eq!(change.control_index, ControlIndex(CUTOFF_INDEX));
+        assert_eq!(change.value.0, 1.0);
+        assert_eq!(r.pressure_for(60), 127);
+
+        let change = r
+            .route(&MidiMessage::ChannelAftertouch { vel: 0.into() })
+            .unwrap();
+        assert_eq!(change.value.0, 0.25);
+
+        assert!(r
+            .route(&MidiMessage::Controller {
+                controller: 1.into(),
+                value: 64.into(),
+            })
+            .is_none());
+        r.set_route(None);
+        assert!(r
+            .route(&MidiMessage::ChannelAftertouch { vel: 64.into() })
+            .is_none());
+    }
+
+    #[test]
+    fn poly_aftertouch_changes_only_its_voice() {
+        let mut r = routing();
+        let change = r
+            .route(&MidiMessage::Aftertouch {
+                key: 64.into(),
+                vel: 127.into(),
+            })
+            .unwrap();
+        assert_eq!(change.key, Some(64));
+        assert_eq!(change.value.0, 1.0);
+        assert_eq!(r.pressure_for(64), 127);
+        assert_eq!(r.pressure_for(60), 0, "other keys shouldn't feel it");
+
+        // Releasing the key resets its voice and forgets its pressure.
+        let change = r
+            .route(&MidiMessage::NoteOff {
+                key: 64.into(),
+                vel: 0.into(),
+            })
+            .unwrap();
+        assert_eq!(change.key, Some(64));
+        assert_eq!(change.value.0, 0.25);
+        assert_eq!(r.pressure_for(64), 0);
+        assert!(r
+            .route(&MidiMessage::NoteOff {
+                key: 60.into(),
+                vel: 0.into(),
+            })
+            .is_none());
+    }
+}
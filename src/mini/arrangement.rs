@@ -0,0 +1,401 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::{midi::MidiChannel, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// A pattern placed at a specific spot on the timeline, as in an arrangement
+/// view. The same pattern can be placed any number of times.
+///
+/// A clip that's shorter than its pattern cuts the pattern off. A clip that's
+/// longer loops it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Clip<T> {
+    #[allow(missing_docs)]
+    pub pattern_id: T,
+
+    /// When the clip starts, which is also when the pattern's first pass
+    /// starts.
+    pub start: MusicalTime,
+
+    #[allow(missing_docs)]
+    pub length: MusicalTime,
+
+    /// The channel that the pattern's notes go out on.
+    pub channel: MidiChannel,
+}
+impl<T> Clip<T> {
+    #[allow(missing_docs)]
+    pub fn end(&self) -> MusicalTime {
+        self.start + self.length
+    }
+}
+
+/// The part of a [Clip] that falls within a time slice, as [Arrangement::slices()]
+/// reports it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClipSlice<T> {
+    #[allow(missing_docs)]
+    pub pattern_id: T,
+
+    #[allow(missing_docs)]
+    pub channel: MidiChannel,
+
+    /// The part of the pattern to play, in time relative to the pattern's
+    /// start.
+    pub pattern_range: Range<MusicalTime>,
+
+    /// Where the start of the pattern falls on the timeline for this pass.
+    /// Add it to a note's time within the pattern to get its time in the song.
+    pub pass_start: MusicalTime,
+}
+
+/// [Arrangement] places [Clip]s on a timeline. Unlike a pattern that the
+/// sequencer repeats for the whole song, a clip plays only within its span.
+///
+/// Like [PatternLauncher](super::PatternLauncher), it only decides what
+/// should play. The sequencer that owns it asks for the [ClipSlice]s of each
+/// time slice and plays the notes that fall in them.
+///
+/// Clips on different channels play together. On the same channel, a clip
+/// that starts while another is still going cuts the earlier one off, so that
+/// dropping a clip on top of another replaces it from that point rather than
+/// doubling every note. If two clips start at the same time on the same
+/// channel, the one added later wins.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Arrangement<T> {
+    /// Sorted by start time. Clips that start together stay in the order
+    /// they were added.
+    clips: Vec<Clip<T>>,
+}
+impl<T: Clone> Arrangement<T> {
+    /// Places a clip. Returns its index in [Self::clips()].
+    pub fn add_clip(&mut self, clip: Clip<T>) -> usize {
+        let index = self.clips.partition_point(|c| c.start <= clip.start);
+        self.clips.insert(index, clip);
+        index
+    }
+
+    /// Removes and returns the clip at the given index in [Self::clips()].
+    pub fn remove_clip(&mut self, index: usize) -> Option<Clip<T>> {
+        (index < self.clips.len()).then(|| self.clips.remove(index))
+    }
+
+    /// Places a copy of the clip at `index` right after it, so that it starts
+    /// where the original ends. The copy plays the same pattern. Returns the
+    /// copy's index, or None if there's no such clip.
+    pub fn duplicate_clip(&mut self, index: usize) -> Option<usize> {
+        let pattern_id = self.clips.get(index)?.pattern_id.clone();
+        self.duplicate_clip_with(index, pattern_id)
+    }
+
+    /// Like [Self::duplicate_clip()], but the copy plays `pattern_id` instead.
+    /// To get a copy that can be edited without changing the original, pass
+    /// the id of a fresh copy of the original's pattern.
+    pub fn duplicate_clip_with(&mut self, index: usize, pattern_id: T) -> Option<usize> {
+        let original = self.clips.get(index)?;
+        let copy = Clip {
+            pattern_id,
+            start: original.end(),
+            length: original.length,
+            channel: original.channel,
+        };
+        Some(self.add_clip(copy))
+    }
+
+    /// Opens up `length` of empty time at `at`, moving every clip that starts
+    /// there or later that much later. A clip that's already playing at `at`
+    /// stays where it is and keeps its length, so the new time follows it.
+    pub fn insert_time(&mut self, at: MusicalTime, length: MusicalTime) {
+        for clip in self.clips.iter_mut().filter(|c| c.start >= at) {
+            clip.start = clip.start + length;
+        }
+    }
+
+    /// Cuts `range` out of the timeline, moving everything after it earlier to
+    /// close the gap. Clips entirely inside the range go away. A clip that
+    /// straddles an edge of the range loses the part inside it; if that was
+    /// its beginning, it now starts at the cut, with its pattern starting
+    /// over from the top there.
+    pub fn remove_time(&mut self, range: Range<MusicalTime>) {
+        let (cut_start, cut_end) = (range.start.total_units(), range.end.total_units());
+        if cut_start >= cut_end {
+            return;
+        }
+        let cut_length = cut_end - cut_start;
+        self.clips
+            .retain(|c| !(c.start >= range.start && c.end() <= range.end));
+        for clip in self.clips.iter_mut() {
+            let start = clip.start.total_units();
+            let end = clip.end().total_units();
+            let (new_start, new_end) = if start >= cut_end {
+                (start - cut_length, end - cut_length)
+            } else if start >= cut_start {
+                (cut_start, end - cut_length)
+            } else if end > cut_end {
+                (start, end - cut_length)
+            } else {
+                (start, end.min(cut_start))
+            };
+            clip.start = MusicalTime::new_with_units(new_start);
+            clip.length = MusicalTime::new_with_units(new_end - new_start);
+        }
+    }
+
+    /// All the clips, in order of start time.
+    pub fn clips(&self) -> &[Clip<T>] {
+        &self.clips
+    }
+
+    /// When the last clip ends.
+    pub fn end(&self) -> MusicalTime {
+        self.clips.iter().map(|c| c.end()).max().unwrap_or_default()
+    }
+
+    /// When the clip at `index` actually stops: its own end, or the start of
+    /// the next clip on its channel, whichever is sooner.
+    pub fn effective_end(&self, index: usize) -> MusicalTime {
+        let clip = &self.clips[index];
+        self.clips
+            .iter()
+            .enumerate()
+            .filter(|(i, c)| {
+                *i != index
+                    && c.channel == clip.channel
+                    && (c.start > clip.start || (c.start == clip.start && *i > index))
+            })
+            .map(|(_, c)| c.start)
+            .fold(clip.end(), |end, start| end.min(start))
+    }
+
+    /// What to play during `range`. `pattern_length` returns the length of a
+    /// pattern, or None if it no longer exists, in which case its clips are
+    /// silent. Slices are in order of start time, and a clip that loops within
+    /// the range gets one slice per pass.
+    pub fn slices<F>(&self, range: &Range<MusicalTime>, pattern_length: F) -> Vec<ClipSlice<T>>
+    where
+        F: Fn(&T) -> Option<MusicalTime>,
+    {
+        let mut slices = Vec::default();
+        for (index, clip) in self.clips.iter().enumerate() {
+            if clip.start >= range.end {
+                break;
+            }
+            let start = range.start.max(clip.start).total_units();
+            let end = range.end.min(self.effective_end(index)).total_units();
+            if start >= end {
+                continue;
+            }
+            let Some(length) = pattern_length(&clip.pattern_id).map(|l| l.total_units()) else {
+                continue;
+            };
+            if length == 0 {
+                continue;
+            }
+            let clip_start = clip.start.total_units();
+            let first_pass = (start - clip_start) / length;
+            let last_pass = (end - 1 - clip_start) / length;
+            for pass in first_pass..=last_pass {
+                let pass_start = clip_start + pass * length;
+                let slice_start = start.max(pass_start);
+                let slice_end = end.min(pass_start + length);
+                slices.push(ClipSlice {
+                    pattern_id: clip.pattern_id.clone(),
+                    channel: clip.channel,
+                    pattern_range: MusicalTime::new_with_units(slice_start - pass_start)
+                        ..MusicalTime::new_with_units(slice_end - pass_start),
+                    pass_start: MusicalTime::new_with_units(pass_start),
+                });
+            }
+        }
+        slices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beats(n: usize) -> MusicalTime {
+        MusicalTime::new_with_beats(n)
+    }
+
+    fn clip(
+        pattern_id: &'static str,
+        start: usize,
+        length: usize,
+        channel: u8,
+    ) -> Clip<&'static str> {
+        Clip {
+            pattern_id,
+            start: beats(start),
+            length: beats(length),
+            channel: MidiChannel(channel),
+        }
+    }
+
+    // Every pattern is one bar long, with a note at the top of each beat.
+    fn notes_played(arrangement: &Arrangement<&'static str>, until: usize) -> Vec<(usize, u8)> {
+        let mut notes = Vec::default();
+        for beat in 0..until {
+            for slice in arrangement.slices(&(beats(beat)..beats(beat + 1)), |_| Some(beats(4))) {
+                for note in 0..4 {
+                    let note_time = beats(note);
+                    if slice.pattern_range.contains(&note_time) {
+                        let song_time = slice.pass_start + note_time;
+                        notes.push((
+                            song_time.total_units() / MusicalTime::UNITS_IN_BEAT,
+                            slice.channel.0,
+                        ));
+                    }
+                }
+            }
+        }
+        notes
+    }
+
+    #[test]
+    fn verse_chorus_verse_from_one_pattern() {
+        let mut arrangement = Arrangement::default();
+
+        // The chorus is the same riff an octave up, say, on another channel.
+        arrangement.add_clip(clip("riff", 16, 8, 1));
+        arrangement.add_clip(clip("riff", 0, 8, 0));
+        arrangement.add_clip(clip("riff", 8, 8, 1));
+        arrangement.add_clip(clip("riff", 24, 8, 0));
+        assert_eq!(arrangement.clips()[0].start, beats(0), "kept in order");
+        assert_eq!(arrangement.end(), beats(32));
+
+        let notes = notes_played(&arrangement, 40);
+        assert_eq!(notes.len(), 32, "one note per beat, and nothing after");
+        for (beat, channel) in notes {
+            let expected = if (8..24).contains(&beat) { 1 } else { 0 };
+            assert_eq!(channel, expected, "beat {beat}");
+        }
+    }
+
+    #[test]
+    fn clips_loop_or_truncate_their_pattern() {
+        let mut arrangement = Arrangement::default();
+        arrangement.add_clip(clip("long", 0, 6, 0));
+        let slices = arrangement.slices(&(beats(0)..beats(8)), |_| Some(beats(4)));
+        assert_eq!(slices.len(), 2);
+        assert_eq!(slices[0].pattern_range, beats(0)..beats(4));
+        assert_eq!(
+            slices[1].pattern_range,
+            beats(0)..beats(2),
+            "cut off mid-pass"
+        );
+        assert_eq!(slices[1].pass_start, beats(4));
+
+        // A missing pattern is silent rather than an error.
+        assert!(arrangement
+            .slices(&(beats(0)..beats(8)), |_| None)
+            .is_empty());
+    }
+
+    #[test]
+    fn later_clip_on_the_same_channel_takes_over() {
+        let mut arrangement = Arrangement::default();
+        arrangement.add_clip(clip("a", 0, 8, 0));
+        arrangement.add_clip(clip("b", 4, 4, 0));
+        arrangement.add_clip(clip("c", 2, 4, 1));
+        assert_eq!(arrangement.effective_end(0), beats(4));
+
+        let slices = arrangement.slices(&(beats(0)..beats(8)), |_| Some(beats(16)));
+        let spans: Vec<_> = slices
+            .iter()
+            .map(|s| {
+                (
+                    s.pattern_id,
+                    s.pass_start + s.pattern_range.start,
+                    s.pass_start + s.pattern_range.end,
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                ("a", beats(0), beats(4)),
+                ("c", beats(2), beats(6)),
+                ("b", beats(4), beats(8)),
+            ]
+        );
+
+        // A tie goes to the clip added later.
+        arrangement.add_clip(clip("d", 4, 4, 0));
+        let ids: Vec<_> = arrangement
+            .slices(&(beats(4)..beats(5)), |_| Some(beats(4)))
+            .iter()
+            .map(|s| s.pattern_id)
+            .collect();
+        assert_eq!(ids, vec!["c", "d"]);
+
+        assert_eq!(arrangement.remove_clip(0).unwrap().pattern_id, "a");
+        assert!(arrangement.remove_clip(99).is_none());
+    }
+
+    #[test]
+    fn inserting_and_removing_time_moves_what_follows() {
+        let mut arrangement = Arrangement::default();
+        arrangement.add_clip(clip("verse", 0, 16, 0));
+        arrangement.add_clip(clip("pad", 8, 16, 1));
+        arrangement.add_clip(clip("chorus", 16, 16, 0));
+
+        // Four bars before the chorus. The pad is already playing, so it
+        // stays put.
+        arrangement.insert_time(beats(16), beats(16));
+        assert_eq!(
+            arrangement.clips(),
+            &[
+                clip("verse", 0, 16, 0),
+                clip("pad", 8, 16, 1),
+                clip("chorus", 32, 16, 0),
+            ]
+        );
+
+        // And take them back out, along with the pad's second half.
+        arrangement.remove_time(beats(16)..beats(32));
+        assert_eq!(
+            arrangement.clips(),
+            &[
+                clip("verse", 0, 16, 0),
+                clip("pad", 8, 8, 1),
+                clip("chorus", 16, 16, 0),
+            ]
+        );
+
+        // A clip inside the range goes away, and one that starts inside it
+        // starts at the cut instead.
+        arrangement.remove_time(beats(4)..beats(20));
+        assert_eq!(
+            arrangement.clips(),
+            &[clip("verse", 0, 4, 0), clip("chorus", 4, 12, 0)]
+        );
+        assert_eq!(arrangement.end(), beats(16));
+    }
+
+    #[test]
+    fn duplicated_clip_follows_the_original() {
+        let mut arrangement = Arrangement::default();
+        arrangement.add_clip(clip("riff", 0, 4, 0));
+        arrangement.add_clip(clip("riff", 4, 4, 1));
+        arrangement.add_clip(clip("bass", 16, 4, 0));
+
+        let copy = arrangement.duplicate_clip(0).unwrap();
+        assert_eq!(arrangement.clips()[copy], clip("riff", 4, 4, 0));
+        assert_eq!(
+            arrangement.clips()[copy - 1],
+            clip("riff", 4, 4, 1),
+            "an existing clip at the same time stays ahead of the copy"
+        );
+
+        // The independent copy plays its own pattern.
+        let copy = arrangement.duplicate_clip_with(copy, "riff-copy").unwrap();
+        assert_eq!(arrangement.clips()[copy], clip("riff-copy", 8, 4, 0));
+        assert_eq!(arrangement.clips()[0].pattern_id, "riff");
+        assert_eq!(arrangement.clips().len(), 5);
+
+        assert!(arrangement.duplicate_clip(99).is_none());
+    }
+}
@@ -0,0 +1,478 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use eframe::egui::Ui;
+use ensnare_core::prelude::*;
+use ensnare_core::traits::{
+    Configurable, ControlEventsFn, Controls, Displays, EntityEvent, HandlesMidi, Serializable,
+};
+use ensnare_proc_macros::{Control, IsController, Uid};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// One recorded value of an automated parameter.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct AutomationPoint {
+    #[allow(missing_docs)]
+    pub time: MusicalTime,
+    #[allow(missing_docs)]
+    pub value: ControlValue,
+}
+
+/// Captures live changes to one parameter of one entity during a performance,
+/// and turns them into an [AutomationLane] that can play them back.
+///
+/// A knob twiddle can produce a new value on every UI frame or even every
+/// sample, so the recorder thins the stream as it goes. It keeps a point only
+/// when it's needed to reproduce the recorded curve, with straight lines in
+/// between, to within `tolerance`. A steady sweep becomes two points, and a
+/// parameter that doesn't move adds nothing.
+#[derive(Clone, Debug)]
+pub struct AutomationRecorder {
+    tolerance: f64,
+    target: Option<(Uid, ControlIndex)>,
+
+    /// Points that are definitely part of the result.
+    points: Vec<AutomationPoint>,
+
+    /// Points since the last committed one. All of them lie on a straight line
+    /// from that point to the last of them, within tolerance.
+    candidates: Vec<AutomationPoint>,
+}
+impl Default for AutomationRecorder {
+    fn default() -> Self {
+        Self::new_with(Self::DEFAULT_TOLERANCE)
+    }
+}
+impl AutomationRecorder {
+    /// Small enough that the error isn't audible on a typical parameter.
+    pub const DEFAULT_TOLERANCE: f64 = 0.002;
+
+    #[allow(missing_docs)]
+    pub fn new_with(tolerance: f64) -> Self {
+        Self {
+            tolerance: tolerance.abs(),
+            target: None,
+            points: Default::default(),
+            candidates: Default::default(),
+        }
+    }
+
+    /// Starts recording changes to the given parameter. Anything recorded
+    /// since the last arm is discarded.
+    pub fn arm_automation_recording(&mut self, target_uid: Uid, control_index: ControlIndex) {
+        self.target = Some((target_uid, control_index));
+        self.points.clear();
+        self.candidates.clear();
+    }
+
+    /// Whether a parameter is being recorded.
+    pub fn is_armed(&self) -> bool {
+        self.target.is_some()
+    }
+
+    /// Records a value if it's for the armed parameter. The caller should call
+    /// this only while performing, with the current song time.
+    pub fn record(
+        &mut self,
+        time: MusicalTime,
+        target_uid: Uid,
+        control_index: ControlIndex,
+        value: ControlValue,
+    ) {
+        if self.target != Some((target_uid, control_index)) {
+            return;
+        }
+        let point = AutomationPoint { time, value };
+        let Some(anchor) = self.points.last().copied() else {
+            self.points.push(point);
+            return;
+        };
+
+        // A second value at the same moment replaces the first.
+        if let Some(last) = self.candidates.last_mut() {
+            if last.time == time {
+                last.value = value;
+                return;
+            }
+        } else if anchor.time == time {
+            self.points.last_mut().unwrap().value = value;
+            return;
+        }
+
+        if self
+            .candidates
+            .iter()
+            .all(|c| Self::is_near_line(&anchor, &point, c, self.tolerance))
+        {
+            self.candidates.push(point);
+        } else {
+            // The new point can't be reached by a straight line from the anchor
+            // without misrepresenting something in between, so the last
+            // candidate becomes a real point, and the line starts again from
+            // there.
+            let end = self.candidates.pop().unwrap();
+            self.points.push(end);
+            self.candidates.clear();
+            self.candidates.push(point);
+        }
+    }
+
+    /// Stops recording, and returns the recording as a lane along with the
+    /// parameter it's for. The caller links the lane to that parameter to play
+    /// it back.
+    pub fn disarm(&mut self) -> Option<(Uid, ControlIndex, AutomationLane)> {
+        let (target_uid, control_index) = self.target.take()?;
+        let mut points = std::mem::take(&mut self.points);
+        if let Some(last) = self.candidates.pop() {
+            points.push(last);
+        }
+        self.candidates.clear();
+        Some((target_uid, control_index, AutomationLane::new_with(points)))
+    }
+
+    fn is_near_line(
+        start: &AutomationPoint,
+        end: &AutomationPoint,
+        point: &AutomationPoint,
+        tolerance: f64,
+    ) -> bool {
+        let start_units = start.time.total_units() as f64;
+        let span = end.time.total_units() as f64 - start_units;
+        let expected = if span > 0.0 {
+            let t = (point.time.total_units() as f64 - start_units) / span;
+            start.value.0 + (end.value.0 - start.value.0) * t
+        } else {
+            end.value.0
+        };
+        (point.value.0 - expected).abs() <= tolerance
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AutomationLaneEphemerals {
+    range: Range<MusicalTime>,
+    last_value: Option<f64>,
+    is_performing: bool,
+}
+
+/// [AutomationLane] plays back a recorded parameter curve. It's a controller:
+/// link its output to the parameter it was recorded from, and during a
+/// performance it sends that parameter's value, interpolated between the
+/// recorded points, whenever it changes.
+#[derive(Serialize, Deserialize, Clone, Control, IsController, Debug, Default, Uid)]
+pub struct AutomationLane {
+    uid: Uid,
+
+    points: Vec<AutomationPoint>,
+
+    #[serde(skip)]
+    e: AutomationLaneEphemerals,
+}
+impl AutomationLane {
+    /// Creates a lane from points in time order.
+    pub fn new_with(points: Vec<AutomationPoint>) -> Self {
+        Self {
+            points,
+            ..Default::default()
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn points(&self) -> &[AutomationPoint] {
+        &self.points
+    }
+
+    /// The parameter's value at the given time. Before the first point and
+    /// after the last, the value holds steady.
+    pub fn value_at(&self, time: MusicalTime) -> Option<ControlValue> {
+        let first = self.points.first()?;
+        if time <= first.time {
+            return Some(first.value);
+        }
+        let next_index = self.points.iter().position(|p| p.time > time);
+        let Some(next_index) = next_index else {
+            return self.points.last().map(|p| p.value);
+        };
+        let previous = &self.points[next_index - 1];
+        let next = &self.points[next_index];
+        let span = (next.time.total_units() - previous.time.total_units()) as f64;
+        let t = (time.total_units() - previous.time.total_units()) as f64 / span;
+        Some(ControlValue(
+            previous.value.0 + (next.value.0 - previous.value.0) * t,
+        ))
+    }
+
+    /// Opens up `length` of time at `at`, moving every point there or later
+    /// that much later. The parameter holds its value at `at` through the new
+    /// time, so the curve on either side is unchanged.
+    pub fn insert_time(&mut self, at: MusicalTime, length: MusicalTime) {
+        let index = self.points.partition_point(|p| p.time < at);
+        if index == self.points.len() {
+            return;
+        }
+        let held = (index > 0).then(|| self.value_at(at)).flatten();
+        for point in self.points[index..].iter_mut() {
+            point.time = point.time + length;
+        }
+        if let Some(value) = held {
+            let moved = at + length;
+            if self.points[index].time != moved {
+                self.points
+                    .insert(index, AutomationPoint { time: moved, value });
+            }
+            self.points
+                .insert(index, AutomationPoint { time: at, value });
+        }
+    }
+
+    /// Cuts `range` out of the lane, deleting the points inside it and moving
+    /// later points earlier to close the gap. The curve on either side is
+    /// unchanged, so the parameter jumps at the cut if its value differed at
+    /// the two ends of the range.
+    pub fn remove_time(&mut self, range: Range<MusicalTime>) {
+        let (cut_start, cut_end) = (range.start.total_units(), range.end.total_units());
+        if cut_start >= cut_end {
+            return;
+        }
+        let before = self
+            .points
+            .iter()
+            .any(|p| p.time < range.start)
+            .then(|| self.value_at(range.start))
+            .flatten();
+        let after = self
+            .points
+            .iter()
+            .any(|p| p.time >= range.end)
+            .then(|| self.value_at(range.end))
+            .flatten();
+        self.points.retain(|p| !range.contains(&p.time));
+        for point in self.points.iter_mut().filter(|p| p.time >= range.end) {
+            point.time =
+                MusicalTime::new_with_units(point.time.total_units() - (cut_end - cut_start));
+        }
+
+        // Pin both sides of the cut, unless a point is already there.
+        let index = self.points.partition_point(|p| p.time < range.start);
+        let already_there = self
+            .points
+            .get(index)
+            .map_or(false, |p| p.time == range.start);
+        let same = before.map(|v| v.0) == after.map(|v| v.0);
+        let mut boundary = Vec::default();
+        if let Some(value) = before {
+            if !(already_there && same) {
+                boundary.push(AutomationPoint {
+                    time: range.start,
+                    value,
+                });
+            }
+        }
+        if let Some(value) = after {
+            if !already_there && !same {
+                boundary.push(AutomationPoint {
+                    time: range.start,
+                    value,
+                });
+            }
+        }
+        self.points.splice(index..index, boundary);
+    }
+}
+impl HandlesMidi for AutomationLane {}
+impl Displays for AutomationLane {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        ui.label(format!("Automation: {} points", self.points.len()))
+    }
+}
+impl Serializable for AutomationLane {}
+impl Configurable for AutomationLane {}
+impl Controls for AutomationLane {
+    fn update_time(&mut self, range: &Range<MusicalTime>) {
+        self.e.range = range.clone();
+    }
+
+    fn work(&mut self, control_events_fn: &mut ControlEventsFn) {
+        if !self.e.is_performing {
+            return;
+        }
+        if let Some(value) = self.value_at(self.e.range.start) {
+            if self.e.last_value != Some(value.0) {
+                self.e.last_value = Some(value.0);
+                control_events_fn(self.uid, EntityEvent::Control(value));
+            }
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.points
+            .last()
+            .map_or(true, |last| self.e.range.start >= last.time)
+    }
+
+    fn play(&mut self) {
+        self.e.is_performing = true;
+    }
+
+    fn stop(&mut self) {
+        self.e.is_performing = false;
+    }
+
+    fn skip_to_start(&mut self) {
+        self.e.range = MusicalTime::default()..MusicalTime::default();
+        self.e.last_value = None;
+    }
+
+    fn is_performing(&self) -> bool {
+        self.e.is_performing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: Uid = Uid(7);
+    const INDEX: ControlIndex = ControlIndex(2);
+
+    fn units(n: usize) -> MusicalTime {
+        MusicalTime::new_with_units(n)
+    }
+
+    #[test]
+    fn dense_sweep_thins_to_its_corners() {
+        let mut r = AutomationRecorder::default();
+        r.arm_automation_recording(TARGET, INDEX);
+
+        // Up from 0.0 to 1.0, then hold, with a value every unit.
+        for i in 0..=100 {
+            r.record(units(i), TARGET, INDEX, ControlValue(i as f64 / 100.0));
+        }
+        for i in 101..=200 {
+            r.record(units(i), TARGET, INDEX, ControlValue(1.0));
+        }
+        // Values for other parameters are ignored.
+        r.record(units(50), TARGET, ControlIndex(0), ControlValue(0.0));
+
+        let (uid, index, lane) = r.disarm().unwrap();
+        assert_eq!((uid, index), (TARGET, INDEX));
+        assert!(!r.is_armed());
+        let times: Vec<usize> = lane.points().iter().map(|p| p.time.total_units()).collect();
+        assert_eq!(times, vec![0, 100, 200]);
+
+        // The lane reproduces the recording.
+        for i in 0..=200 {
+            let expected = (i as f64 / 100.0).min(1.0);
+            let actual = lane.value_at(units(i)).unwrap().0;
+            assert!((actual - expected).abs() <= AutomationRecorder::DEFAULT_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn recording_stays_within_tolerance_of_curves() {
+        let mut r = AutomationRecorder::default();
+        r.arm_automation_recording(TARGET, INDEX);
+        let curve = |i: usize| ((i as f64) / 200.0).sin() * 0.5 + 0.5;
+        for i in 0..=2000 {
+            r.record(units(i), TARGET, INDEX, ControlValue(curve(i)));
+        }
+        let (_, _, lane) = r.disarm().unwrap();
+        assert!(
+            lane.points().len() < 200,
+            "got {} points",
+            lane.points().len()
+        );
+        for i in 0..=2000 {
+            let actual = lane.value_at(units(i)).unwrap().0;
+            assert!(
+                (actual - curve(i)).abs() <= AutomationRecorder::DEFAULT_TOLERANCE + 1.0e-9,
+                "unit {i}: {actual} vs {}",
+                curve(i)
+            );
+        }
+    }
+
+    fn ramp() -> AutomationLane {
+        AutomationLane::new_with(vec![
+            AutomationPoint {
+                time: units(0),
+                value: ControlValue(0.0),
+            },
+            AutomationPoint {
+                time: units(100),
+                value: ControlValue(1.0),
+            },
+        ])
+    }
+
+    fn assert_value_at(lane: &AutomationLane, time: usize, expected: f64) {
+        let actual = lane.value_at(units(time)).unwrap().0;
+        assert!(
+            (actual - expected).abs() < 1.0e-9,
+            "unit {time}: {actual} vs {expected}"
+        );
+    }
+
+    #[test]
+    fn inserted_time_holds_the_value() {
+        let mut lane = ramp();
+        lane.insert_time(units(50), units(20));
+        let times: Vec<usize> = lane.points().iter().map(|p| p.time.total_units()).collect();
+        assert_eq!(times, vec![0, 50, 70, 120]);
+        assert_value_at(&lane, 25, 0.25);
+        assert_value_at(&lane, 60, 0.5);
+        assert_value_at(&lane, 95, 0.75);
+
+        // Time after the last point changes nothing.
+        let mut lane = ramp();
+        lane.insert_time(units(200), units(20));
+        assert_eq!(lane.points(), ramp().points());
+    }
+
+    #[test]
+    fn removed_time_closes_the_gap() {
+        let mut lane = ramp();
+        lane.remove_time(units(25)..units(75));
+        let times: Vec<usize> = lane.points().iter().map(|p| p.time.total_units()).collect();
+        assert_eq!(times, vec![0, 25, 25, 50]);
+        assert_value_at(&lane, 10, 0.1);
+        assert_value_at(&lane, 25, 0.75);
+        assert_value_at(&lane, 40, 0.9);
+
+        // Removing the end of the lane leaves it holding where it was cut,
+        // and removing the start picks up where the cut ends.
+        let mut lane = ramp();
+        lane.remove_time(units(50)..units(150));
+        assert_eq!(lane.points().last().unwrap().time, units(50));
+        assert_value_at(&lane, 60, 0.5);
+
+        let mut lane = ramp();
+        lane.remove_time(units(0)..units(50));
+        assert_value_at(&lane, 0, 0.5);
+        assert_value_at(&lane, 25, 0.75);
+    }
+
+    #[test]
+    fn lane_plays_back_only_changes() {
+        let mut lane = AutomationLane::new_with(vec![
+            AutomationPoint {
+                time: units(0),
+                value: ControlValue(0.25),
+            },
+            AutomationPoint {
+                time: units(10),
+                value: ControlValue(0.25),
+            },
+        ]);
+        lane.play();
+        let mut events = 0;
+        for i in 0..10 {
+            lane.update_time(&(units(i)..units(i + 1)));
+            lane.work(&mut |_, event| {
+                assert!(matches!(event, EntityEvent::Control(ControlValue(v)) if v == 0.25));
+                events += 1;
+            });
+        }
+        assert_eq!(events, 1, "a flat line should be sent once");
+    }
+}
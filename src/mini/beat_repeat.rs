@@ -0,0 +1,330 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use super::tempo_delay::NoteDivision;
+use ensnare_core::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+enum BeatRepeatState {
+    /// Passing audio through.
+    #[default]
+    Idle,
+    /// Waiting for the next division boundary to start capturing.
+    Armed,
+    /// Capturing and repeating. The slice that started at `start` is being
+    /// recorded while `slice` is zero, and replayed after that.
+    Active {
+        start: MusicalTime,
+        slice: usize,
+        frame_in_slice: usize,
+    },
+}
+
+/// [BeatRepeat] is a glitch/stutter effect. When triggered, it captures a
+/// slice of audio one [NoteDivision] long, starting on the beat grid, and then
+/// plays that slice over and over until it has filled `repeats` slices in all,
+/// after which it goes back to passing audio through.
+///
+/// Slice boundaries come from musical time rather than a frame count, so the
+/// repeats stay locked to the grid however the division's length rounds to
+/// frames. The owner calls [BeatRepeat::update_time()] with each time slice
+/// before passing the slice's audio through [BeatRepeat::transform_audio()].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BeatRepeat {
+    division: NoteDivision,
+
+    /// How many slices the whole effect lasts, counting the one that's
+    /// captured.
+    repeats: usize,
+
+    /// If set, the effect triggers itself on this beat of every bar, counting
+    /// from zero.
+    auto_trigger_beat: Option<usize>,
+
+    #[serde(skip)]
+    time_signature: TimeSignature,
+    #[serde(skip)]
+    state: BeatRepeatState,
+    #[serde(skip)]
+    buffer: Vec<StereoSample>,
+    #[serde(skip)]
+    last_trigger_value: f64,
+}
+impl Default for BeatRepeat {
+    fn default() -> Self {
+        Self::new_with(NoteDivision::Sixteenth, 4)
+    }
+}
+impl BeatRepeat {
+    #[allow(missing_docs)]
+    pub fn new_with(division: NoteDivision, repeats: usize) -> Self {
+        Self {
+            division,
+            repeats: repeats.max(1),
+            auto_trigger_beat: None,
+            time_signature: Default::default(),
+            state: Default::default(),
+            buffer: Default::default(),
+            last_trigger_value: 0.0,
+        }
+    }
+
+    /// Starts the effect at the next division boundary. Does nothing if it's
+    /// already running.
+    pub fn trigger(&mut self) {
+        if self.state == BeatRepeatState::Idle {
+            self.state = BeatRepeatState::Armed;
+        }
+    }
+
+    /// Triggers the effect when a control value rises past the halfway
+    /// point, so that a button or a controller can fire it.
+    pub fn set_trigger_value(&mut self, value: ControlValue) {
+        if value.0 >= 0.5 && self.last_trigger_value < 0.5 {
+            self.trigger();
+        }
+        self.last_trigger_value = value.0;
+    }
+
+    /// Whether the effect is capturing or repeating (or about to).
+    pub fn is_active(&self) -> bool {
+        self.state != BeatRepeatState::Idle
+    }
+
+    /// Tells the effect the musical time of the audio it's about to process.
+    pub fn update_time(&mut self, range: &Range<MusicalTime>) {
+        match self.state {
+            BeatRepeatState::Idle => {
+                if let Some(time) = self.auto_trigger_time_in(range) {
+                    self.start(time);
+                }
+            }
+            BeatRepeatState::Armed => {
+                let division = self.division.musical_time().total_units();
+                let boundary = range.start.total_units().div_ceil(division) * division;
+                if boundary < range.end.total_units() {
+                    self.start(MusicalTime::new_with_units(boundary));
+                }
+            }
+            BeatRepeatState::Active { start, slice, .. } => {
+                let elapsed = range
+                    .start
+                    .total_units()
+                    .saturating_sub(start.total_units());
+                let current_slice = elapsed / self.division.musical_time().total_units();
+                if current_slice >= self.repeats {
+                    self.state = BeatRepeatState::Idle;
+                    // The boundary that ended this run might also be an
+                    // auto-trigger point.
+                    if let Some(time) = self.auto_trigger_time_in(range) {
+                        self.start(time);
+                    }
+                } else if current_slice != slice {
+                    self.state = BeatRepeatState::Active {
+                        start,
+                        slice: current_slice,
+                        frame_in_slice: 0,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Processes one frame.
+    pub fn transform_audio(&mut self, input: StereoSample) -> StereoSample {
+        let BeatRepeatState::Active {
+            start,
+            slice,
+            frame_in_slice,
+        } = self.state
+        else {
+            return input;
+        };
+        let output = if slice == 0 {
+            self.buffer.push(input);
+            input
+        } else {
+            let last = self.buffer.len().saturating_sub(1);
+            self.buffer
+                .get(frame_in_slice.min(last))
+                .copied()
+                .unwrap_or(input)
+        };
+        self.state = BeatRepeatState::Active {
+            start,
+            slice,
+            frame_in_slice: frame_in_slice + 1,
+        };
+        output
+    }
+
+    #[allow(missing_docs)]
+    pub fn division(&self) -> NoteDivision {
+        self.division
+    }
+
+    /// Changes the slice length. It takes effect the next time the effect is
+    /// triggered.
+    pub fn set_division(&mut self, division: NoteDivision) {
+        if !matches!(self.state, BeatRepeatState::Active { .. }) {
+            self.division = division;
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn repeats(&self) -> usize {
+        self.repeats
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_repeats(&mut self, repeats: usize) {
+        self.repeats = repeats.max(1);
+    }
+
+    #[allow(missing_docs)]
+    pub fn auto_trigger_beat(&self) -> Option<usize> {
+        self.auto_trigger_beat
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_auto_trigger_beat(&mut self, beat: Option<usize>) {
+        self.auto_trigger_beat = beat;
+    }
+
+    #[allow(missing_docs)]
+    pub fn update_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+    }
+
+    fn start(&mut self, time: MusicalTime) {
+        self.buffer.clear();
+        self.state = BeatRepeatState::Active {
+            start: time,
+            slice: 0,
+            frame_in_slice: 0,
+        };
+    }
+
+    fn auto_trigger_time_in(&self, range: &Range<MusicalTime>) -> Option<MusicalTime> {
+        let beat = self.auto_trigger_beat?;
+        let bar = self.time_signature.top.max(1) * MusicalTime::UNITS_IN_BEAT;
+        let offset = (beat % self.time_signature.top.max(1)) * MusicalTime::UNITS_IN_BEAT;
+        let start = range.start.total_units();
+        let bar_start = start / bar * bar;
+        let candidate = if bar_start + offset >= start {
+            bar_start + offset
+        } else {
+            bar_start + bar + offset
+        };
+        if candidate < range.end.total_units() {
+            Some(MusicalTime::new_with_units(candidate))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // At 60 BPM and 1,000 frames per second, a beat is 1,000 frames.
+    const FRAMES_PER_BEAT: usize = 1000;
+
+    fn time_at(frame: usize) -> MusicalTime {
+        MusicalTime::new_with_units(frame * MusicalTime::UNITS_IN_BEAT / FRAMES_PER_BEAT)
+    }
+
+    fn input_at(frame: usize) -> StereoSample {
+        StereoSample::from(frame as f64 / 100_000.0)
+    }
+
+    // Runs the effect over a ramp, so each output frame says which input frame
+    // it came from.
+    fn run(
+        effect: &mut BeatRepeat,
+        frames: Range<usize>,
+        mut on_frame: impl FnMut(usize, &mut BeatRepeat),
+    ) -> Vec<StereoSample> {
+        frames
+            .map(|frame| {
+                on_frame(frame, effect);
+                effect.update_time(&(time_at(frame)..time_at(frame + 1)));
+                effect.transform_audio(input_at(frame))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stutters_last_beat_of_bar() {
+        let mut effect = BeatRepeat::new_with(NoteDivision::Eighth, 2);
+        effect.set_auto_trigger_beat(Some(3));
+        let output = run(&mut effect, 0..5 * FRAMES_PER_BEAT, |_, _| {});
+
+        let half_beat = FRAMES_PER_BEAT / 2;
+        let start = 3 * FRAMES_PER_BEAT;
+        for (frame, sample) in output.iter().enumerate() {
+            let expected = if (start + half_beat..start + 2 * half_beat).contains(&frame) {
+                // The second eighth of beat 3 repeats the first.
+                input_at(frame - half_beat)
+            } else {
+                input_at(frame)
+            };
+            assert_eq!(*sample, expected, "frame {frame}");
+        }
+        assert!(!effect.is_active());
+    }
+
+    #[test]
+    fn manual_trigger_waits_for_grid() {
+        let mut effect = BeatRepeat::new_with(NoteDivision::Quarter, 3);
+        let output = run(&mut effect, 0..6 * FRAMES_PER_BEAT, |frame, effect| {
+            if frame == 1234 {
+                effect.set_trigger_value(ControlValue(1.0));
+            }
+        });
+
+        // Triggered partway through beat 1, so capture starts on beat 2 and
+        // repeats through beats 3 and 4.
+        for (frame, sample) in output.iter().enumerate() {
+            let expected = if (3 * FRAMES_PER_BEAT..5 * FRAMES_PER_BEAT).contains(&frame) {
+                input_at(2 * FRAMES_PER_BEAT + frame % FRAMES_PER_BEAT)
+            } else {
+                input_at(frame)
+            };
+            assert_eq!(*sample, expected, "frame {frame}");
+        }
+    }
+
+    #[test]
+    fn repeats_stay_on_grid_when_slices_round() {
+        // A triplet doesn't divide a beat into whole frames, but each repeat
+        // must still start on its grid line rather than drifting.
+        let mut effect = BeatRepeat::new_with(NoteDivision::EighthTriplet, 6);
+        effect.trigger();
+        let mut slice_starts = Vec::default();
+        let mut was_active = false;
+        let mut last_slice = usize::MAX;
+        run(&mut effect, 0..4 * FRAMES_PER_BEAT, |frame, effect| {
+            if let BeatRepeatState::Active { slice, .. } = effect.state {
+                if slice != last_slice {
+                    slice_starts.push(frame - 1);
+                    last_slice = slice;
+                }
+                was_active = true;
+            }
+        });
+        assert!(was_active);
+        let slice_units = NoteDivision::EighthTriplet.musical_time().total_units();
+        for (i, frame) in slice_starts.iter().enumerate() {
+            let grid_units = i * slice_units;
+            let frame_units = time_at(*frame).total_units();
+            assert!(
+                frame_units.abs_diff(grid_units)
+                    <= MusicalTime::UNITS_IN_BEAT / FRAMES_PER_BEAT + 1,
+                "slice {i} started at frame {frame}"
+            );
+        }
+    }
+}
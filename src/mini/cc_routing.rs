@@ -0,0 +1,120 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::{
+    control::{ControlIndex, ControlValue},
+    midi::prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+/// Sends one MIDI CC number to one of an instrument's own controls.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CcRoute {
+    /// The MIDI controller number, e.g. 1 for the mod wheel.
+    pub cc: u8,
+
+    /// The index of the instrument's #[control] parameter.
+    pub control_index: usize,
+
+    /// The control value that CC value 0 maps to.
+    pub min: f64,
+
+    /// The control value that CC value 127 maps to.
+    pub max: f64,
+}
+
+/// A per-instrument table of MIDI CC routes. Unlike global MIDI learn, it
+/// belongs to the instrument and serializes with its patch. The instrument's
+/// `HandlesMidi` implementation passes Controller messages to
+/// [CcRouting::route()] and applies the results with
+/// `control_set_param_by_index()`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CcRouting {
+    routes: Vec<CcRoute>,
+}
+impl CcRouting {
+    /// The standard MIDI controller number of the mod wheel.
+    pub const MOD_WHEEL: u8 = 1;
+
+    /// Adds a route. One CC can drive several controls.
+    pub fn add_route(&mut self, route: CcRoute) {
+        self.routes.push(route);
+    }
+
+    /// Removes every route for the given CC.
+    pub fn remove_routes_for(&mut self, cc: u8) {
+        self.routes.retain(|route| route.cc != cc);
+    }
+
+    #[allow(missing_docs)]
+    pub fn routes(&self) -> &[CcRoute] {
+        &self.routes
+    }
+
+    /// Returns the control changes that a MIDI message should cause. Anything
+    /// other than a Controller message for a routed CC produces nothing.
+    pub fn route(&self, message: &MidiMessage) -> Vec<(ControlIndex, ControlValue)> {
+        if let MidiMessage::Controller { controller, value } = message {
+            let cc = controller.as_int();
+            let amount = value.as_int() as f64 / 127.0;
+            self.routes
+                .iter()
+                .filter(|route| route.cc == cc)
+                .map(|route| {
+                    (
+                        ControlIndex(route.control_index),
+                        ControlValue(route.min + (route.max - route.min) * amount),
+                    )
+                })
+                .collect()
+        } else {
+            Vec::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mod_wheel_drives_lfo_depth() {
+        const LFO_DEPTH_INDEX: usize = 3;
+        let mut routing = CcRouting::default();
+        routing.add_route(CcRoute {
+            cc: CcRouting::MOD_WHEEL,
+            control_index: LFO_DEPTH_INDEX,
+            min: 0.0,
+            max: 0.5,
+        });
+
+        let changes = routing.route(&MidiMessage::Controller {
+            controller: CcRouting::MOD_WHEEL.into(),
+            value: 127.into(),
+        });
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].0, ControlIndex(LFO_DEPTH_INDEX));
+        assert_eq!(changes[0].1 .0, 0.5);
+
+        let changes = routing.route(&MidiMessage::Controller {
+            controller: CcRouting::MOD_WHEEL.into(),
+            value: 0.into(),
+        });
+        assert_eq!(changes[0].1 .0, 0.0);
+
+        assert!(routing
+            .route(&MidiMessage::Controller {
+                controller: 7.into(),
+                value: 100.into(),
+            })
+            .is_empty());
+        assert!(routing
+            .route(&MidiMessage::NoteOn {
+                key: 1.into(),
+                vel: 127.into(),
+            })
+            .is_empty());
+
+        routing.remove_routes_for(CcRouting::MOD_WHEEL);
+        assert!(routing.routes().is_empty());
+    }
+}
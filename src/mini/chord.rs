@@ -0,0 +1,276 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::midi::prelude::*;
+use ensnare_core::prelude::*;
+
+/// The kinds of chords that can be entered at once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChordQuality {
+    #[default]
+    #[allow(missing_docs)]
+    Major,
+    #[allow(missing_docs)]
+    Minor,
+    #[allow(missing_docs)]
+    Diminished,
+    #[allow(missing_docs)]
+    Augmented,
+    #[allow(missing_docs)]
+    Sus2,
+    #[allow(missing_docs)]
+    Sus4,
+    #[allow(missing_docs)]
+    Major7,
+    #[allow(missing_docs)]
+    Minor7,
+    #[allow(missing_docs)]
+    Dom7,
+}
+impl ChordQuality {
+    /// Semitones above the root for each note of the chord, lowest first.
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            ChordQuality::Major => &[0, 4, 7],
+            ChordQuality::Minor => &[0, 3, 7],
+            ChordQuality::Diminished => &[0, 3, 6],
+            ChordQuality::Augmented => &[0, 4, 8],
+            ChordQuality::Sus2 => &[0, 2, 7],
+            ChordQuality::Sus4 => &[0, 5, 7],
+            ChordQuality::Major7 => &[0, 4, 7, 11],
+            ChordQuality::Minor7 => &[0, 3, 7, 10],
+            ChordQuality::Dom7 => &[0, 4, 7, 10],
+        }
+    }
+}
+
+/// One note of an expanded chord.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChordNote {
+    /// The MIDI key number.
+    pub key: u8,
+    /// When the note starts.
+    pub start: MusicalTime,
+}
+
+/// Expands a chord into its notes, starting at `start`. If `strum_ms` is
+/// nonzero, each note starts that many milliseconds after the one below it.
+/// Notes that would be above the MIDI key range are dropped.
+pub fn expand_chord(
+    root: u8,
+    quality: ChordQuality,
+    start: MusicalTime,
+    strum_ms: f64,
+    tempo: Tempo,
+) -> Vec<ChordNote> {
+    let strum = strum_to_musical_time(strum_ms, tempo);
+    quality
+        .intervals()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, interval)| {
+            let key = root as usize + *interval as usize;
+            if key > 127 {
+                return None;
+            }
+            Some(ChordNote {
+                key: key as u8,
+                start: start + MusicalTime::new_with_units(strum.total_units() * i),
+            })
+        })
+        .collect()
+}
+
+/// [ChordProgrammer] enters chords one after another, the way a pattern
+/// programmer enters patterns. Each chord starts at the cursor and lasts one
+/// chord duration, and then the cursor moves past it.
+///
+/// Notes are written through a callback that takes the time, channel, and
+/// message of each event, so chords can go into any sequencer.
+#[derive(Debug)]
+pub struct ChordProgrammer {
+    cursor: MusicalTime,
+    duration: MusicalTime,
+    tempo: Tempo,
+}
+impl ChordProgrammer {
+    /// The velocity of every note that [ChordProgrammer] inserts.
+    pub const VELOCITY: u8 = 127;
+
+    /// Creates a programmer whose chords each last `duration`, with strums
+    /// converted at `tempo`.
+    pub fn new_with(duration: MusicalTime, tempo: Tempo) -> Self {
+        Self {
+            cursor: MusicalTime::default(),
+            duration,
+            tempo,
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn cursor(&self) -> MusicalTime {
+        self.cursor
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_cursor(&mut self, cursor: MusicalTime) {
+        self.cursor = cursor;
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_tempo(&mut self, tempo: Tempo) {
+        self.tempo = tempo;
+    }
+
+    /// Inserts the chord built on `root` at the cursor, strummed upward by
+    /// `strum_ms` (see [expand_chord()]), and moves the cursor to the end of
+    /// the chord. Every note of the chord ends together, at the end of the
+    /// chord, however late the strum started it, and a note that the strum
+    /// would start after that is left out. Returns the notes that were
+    /// inserted.
+    pub fn insert_chord(
+        &mut self,
+        insert_fn: &mut dyn FnMut(MusicalTime, MidiChannel, MidiMessage),
+        channel: MidiChannel,
+        root: u8,
+        quality: ChordQuality,
+        strum_ms: f64,
+    ) -> Vec<ChordNote> {
+        let end = self.cursor + self.duration;
+        let notes: Vec<ChordNote> = expand_chord(root, quality, self.cursor, strum_ms, self.tempo)
+            .into_iter()
+            .filter(|note| note.start < end)
+            .collect();
+        for note in notes.iter() {
+            insert_fn(
+                note.start,
+                channel,
+                MidiMessage::NoteOn {
+                    key: note.key.into(),
+                    vel: Self::VELOCITY.into(),
+                },
+            );
+            insert_fn(
+                end,
+                channel,
+                MidiMessage::NoteOff {
+                    key: note.key.into(),
+                    vel: 0.into(),
+                },
+            );
+        }
+        self.cursor = end;
+        notes
+    }
+}
+
+/// Converts a strum offset in milliseconds to [MusicalTime] at the given
+/// tempo.
+pub fn strum_to_musical_time(strum_ms: f64, tempo: Tempo) -> MusicalTime {
+    let beats = (strum_ms.max(0.0) / 1000.0) * (tempo.0 / 60.0);
+    MusicalTime::new_with_units((beats * MusicalTime::UNITS_IN_BEAT as f64).round() as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strummed_major_seventh() {
+        let tempo = Tempo(120.0);
+        let start = MusicalTime::new_with_beats(1);
+        let notes = expand_chord(60, ChordQuality::Major7, start, 10.0, tempo);
+        assert_eq!(
+            notes.iter().map(|n| n.key).collect::<Vec<u8>>(),
+            vec![60, 64, 67, 71],
+            "Cmaj7 should be C E G B"
+        );
+
+        // 10ms at 120 BPM is 1/50 of a beat.
+        let strum = strum_to_musical_time(10.0, tempo);
+        assert_eq!(
+            strum.total_units(),
+            (MusicalTime::UNITS_IN_BEAT as f64 / 50.0).round() as usize
+        );
+        for (i, note) in notes.iter().enumerate() {
+            assert_eq!(
+                note.start,
+                start + MusicalTime::new_with_units(strum.total_units() * i),
+                "note {i} should be staggered by the strum offset"
+            );
+        }
+    }
+
+    #[test]
+    fn programmer_inserts_strummed_chords_at_the_cursor() {
+        let tempo = Tempo(120.0);
+        let bar = MusicalTime::new_with_beats(4);
+        let mut programmer = ChordProgrammer::new_with(bar, tempo);
+        let mut events = Vec::default();
+        let mut insert_fn = |time: MusicalTime, channel: MidiChannel, message: MidiMessage| {
+            events.push((time, channel, message))
+        };
+
+        let notes = programmer.insert_chord(
+            &mut insert_fn,
+            MidiChannel(2),
+            60,
+            ChordQuality::Major7,
+            10.0,
+        );
+        assert_eq!(programmer.cursor(), bar);
+        programmer.insert_chord(&mut insert_fn, MidiChannel(2), 65, ChordQuality::Major, 0.0);
+        assert_eq!(programmer.cursor(), bar + bar);
+
+        let strum = strum_to_musical_time(10.0, tempo);
+        let note_ons: Vec<(MusicalTime, u8)> = events
+            .iter()
+            .filter_map(|(time, channel, message)| {
+                assert_eq!(*channel, MidiChannel(2));
+                match message {
+                    MidiMessage::NoteOn { key, .. } => Some((*time, key.as_int())),
+                    _ => None,
+                }
+            })
+            .collect();
+        assert_eq!(
+            note_ons,
+            vec![
+                (MusicalTime::default(), 60),
+                (strum, 64),
+                (MusicalTime::new_with_units(strum.total_units() * 2), 67),
+                (MusicalTime::new_with_units(strum.total_units() * 3), 71),
+                (bar, 65),
+                (bar, 69),
+                (bar, 72),
+            ],
+            "Cmaj7 strummed upward, then F major on the next bar"
+        );
+        assert_eq!(notes.len(), 4);
+        assert!(events
+            .iter()
+            .filter(|(_, _, message)| matches!(message, MidiMessage::NoteOff { .. }))
+            .take(4)
+            .all(|(time, _, _)| *time == bar));
+    }
+
+    #[test]
+    fn unstrummed_chord_starts_together_and_stays_in_range() {
+        let notes = expand_chord(
+            0,
+            ChordQuality::Minor,
+            MusicalTime::default(),
+            0.0,
+            Tempo(128.0),
+        );
+        assert!(notes.iter().all(|n| n.start == MusicalTime::default()));
+
+        let notes = expand_chord(
+            125,
+            ChordQuality::Major,
+            MusicalTime::default(),
+            0.0,
+            Tempo(128.0),
+        );
+        assert_eq!(notes.len(), 1, "notes above 127 should be dropped");
+    }
+}
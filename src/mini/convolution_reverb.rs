@@ -0,0 +1,404 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use super::sample_data::SampleData;
+use eframe::egui::Ui;
+use ensnare_core::prelude::*;
+use ensnare_core::traits::{Configurable, Displays, Serializable, TransformsAudio};
+use ensnare_proc_macros::{Control, IsEffect, Uid};
+use groove_utils::Paths;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Convolves one channel with an impulse response using uniformly partitioned
+/// overlap-add. The IR is cut into blocks of `block_size` samples, and each is
+/// kept in the frequency domain. Each incoming block of input is transformed
+/// once, then multiplied against every IR partition through a delay line of
+/// past input spectra, so the cost per block is one FFT, one inverse FFT, and
+/// one complex multiply-add per partition, rather than the IR's full length
+/// per sample.
+///
+/// Output lags input by `block_size` samples.
+#[derive(Clone)]
+pub(crate) struct PartitionedConvolver {
+    block_size: usize,
+    forward: Arc<dyn Fft<f64>>,
+    inverse: Arc<dyn Fft<f64>>,
+
+    /// The IR's partitions, each zero-padded to twice the block size and
+    /// transformed.
+    partitions: Vec<Vec<Complex<f64>>>,
+
+    /// The spectra of recent input blocks, newest first. There's one per
+    /// partition.
+    history: VecDeque<Vec<Complex<f64>>>,
+
+    input: Vec<f64>,
+    output: Vec<f64>,
+    overlap: Vec<f64>,
+    position: usize,
+}
+impl Debug for PartitionedConvolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PartitionedConvolver")
+            .field("block_size", &self.block_size)
+            .field("partitions", &self.partitions.len())
+            .finish()
+    }
+}
+impl PartitionedConvolver {
+    /// `block_size` must be a power of two.
+    pub(crate) fn new_with(impulse_response: &[f64], block_size: usize) -> Self {
+        debug_assert!(block_size.is_power_of_two());
+        let fft_size = block_size * 2;
+        let mut planner = FftPlanner::new();
+        let forward = planner.plan_fft_forward(fft_size);
+        let inverse = planner.plan_fft_inverse(fft_size);
+
+        let partitions: Vec<Vec<Complex<f64>>> = impulse_response
+            .chunks(block_size)
+            .map(|chunk| {
+                let mut buffer = vec![Complex::default(); fft_size];
+                for (b, s) in buffer.iter_mut().zip(chunk) {
+                    b.re = *s;
+                }
+                forward.process(&mut buffer);
+                buffer
+            })
+            .collect();
+        let history = (0..partitions.len())
+            .map(|_| vec![Complex::default(); fft_size])
+            .collect();
+
+        Self {
+            block_size,
+            forward,
+            inverse,
+            partitions,
+            history,
+            input: vec![0.0; block_size],
+            output: vec![0.0; block_size],
+            overlap: vec![0.0; block_size],
+            position: 0,
+        }
+    }
+
+    /// Accepts one input sample and returns one output sample.
+    pub(crate) fn process(&mut self, sample: f64) -> f64 {
+        if self.partitions.is_empty() {
+            return 0.0;
+        }
+        self.input[self.position] = sample;
+        let output = self.output[self.position];
+        self.position += 1;
+        if self.position == self.block_size {
+            self.position = 0;
+            self.process_block();
+        }
+        output
+    }
+
+    fn process_block(&mut self) {
+        let fft_size = self.block_size * 2;
+
+        // Recycle the oldest spectrum's allocation for the newest.
+        let mut spectrum = self
+            .history
+            .pop_back()
+            .unwrap_or_else(|| vec![Complex::default(); fft_size]);
+        for (i, c) in spectrum.iter_mut().enumerate() {
+            *c = Complex::new(self.input.get(i).copied().unwrap_or_default(), 0.0);
+        }
+        self.forward.process(&mut spectrum);
+        self.history.push_front(spectrum);
+
+        let mut sum = vec![Complex::default(); fft_size];
+        for (past, partition) in self.history.iter().zip(self.partitions.iter()) {
+            for ((s, x), h) in sum.iter_mut().zip(past).zip(partition) {
+                *s += x * h;
+            }
+        }
+        self.inverse.process(&mut sum);
+
+        // rustfft doesn't normalize, so the round trip scales by fft_size.
+        let scale = 1.0 / fft_size as f64;
+        for i in 0..self.block_size {
+            self.output[i] = sum[i].re * scale + self.overlap[i];
+            self.overlap[i] = sum[i + self.block_size].re * scale;
+        }
+    }
+
+    /// Forgets all past input.
+    pub(crate) fn reset(&mut self) {
+        for spectrum in self.history.iter_mut() {
+            spectrum.fill(Complex::default());
+        }
+        self.input.fill(0.0);
+        self.output.fill(0.0);
+        self.overlap.fill(0.0);
+        self.position = 0;
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ConvolutionReverbEphemerals {
+    sample_rate: SampleRate,
+    impulse_response: Option<SampleData>,
+
+    /// One per channel.
+    convolvers: Vec<PartitionedConvolver>,
+}
+
+/// [ConvolutionReverb] places its input in a recorded space by convolving it
+/// with that space's impulse response, loaded from a WAV file. A mono IR is
+/// used for both channels, and a stereo one is applied channel by channel.
+///
+/// The wet signal lags the dry signal by [ConvolutionReverb::BLOCK_SIZE]
+/// frames, which is small enough to pass for pre-delay.
+#[derive(Serialize, Deserialize, Clone, Control, IsEffect, Debug, Uid)]
+pub struct ConvolutionReverb {
+    uid: Uid,
+
+    /// The WAV file holding the impulse response, found through [Paths].
+    impulse_response_filename: PathBuf,
+
+    /// 0.0 is all dry, 1.0 is all wet.
+    #[control]
+    mix: Normal,
+
+    #[serde(skip)]
+    e: ConvolutionReverbEphemerals,
+}
+impl Default for ConvolutionReverb {
+    fn default() -> Self {
+        Self {
+            uid: Default::default(),
+            impulse_response_filename: Default::default(),
+            mix: Normal::from(0.5),
+            e: ConvolutionReverbEphemerals {
+                sample_rate: SampleRate::DEFAULT,
+                ..Default::default()
+            },
+        }
+    }
+}
+impl ConvolutionReverb {
+    /// The size of each IR partition, and so the latency of the wet signal.
+    pub const BLOCK_SIZE: usize = 256;
+
+    /// Creates a reverb and loads its impulse response.
+    pub fn new_from_file(
+        paths: &Paths,
+        impulse_response_filename: &Path,
+        mix: Normal,
+    ) -> anyhow::Result<Self> {
+        let mut r = Self {
+            impulse_response_filename: impulse_response_filename.to_path_buf(),
+            mix,
+            ..Default::default()
+        };
+        r.load_impulse_response(paths)?;
+        Ok(r)
+    }
+
+    /// Creates a reverb from an impulse response that's already in memory.
+    pub fn new_with_impulse_response(impulse_response: SampleData, mix: Normal) -> Self {
+        let mut r = Self {
+            mix,
+            ..Default::default()
+        };
+        r.set_impulse_response(impulse_response);
+        r
+    }
+
+    /// (Re)loads the impulse response named by the serialized filename. This
+    /// has to be called after deserialization, because finding the file needs
+    /// [Paths].
+    pub fn load_impulse_response(&mut self, paths: &Paths) -> anyhow::Result<()> {
+        let impulse_response =
+            SampleData::new_from_file(paths, &self.impulse_response_filename, self.e.sample_rate)?;
+        self.set_impulse_response(impulse_response);
+        Ok(())
+    }
+
+    fn set_impulse_response(&mut self, mut impulse_response: SampleData) {
+        impulse_response.update_sample_rate(self.e.sample_rate);
+        self.e.impulse_response = Some(impulse_response);
+        self.rebuild_convolvers();
+    }
+
+    fn rebuild_convolvers(&mut self) {
+        self.e.convolvers = match &self.e.impulse_response {
+            Some(impulse_response) => {
+                let samples = impulse_response.samples();
+                let left: Vec<f64> = samples.iter().map(|s| s.0 .0).collect();
+                let right: Vec<f64> = samples.iter().map(|s| s.1 .0).collect();
+                vec![
+                    PartitionedConvolver::new_with(&left, Self::BLOCK_SIZE),
+                    PartitionedConvolver::new_with(&right, Self::BLOCK_SIZE),
+                ]
+            }
+            None => Vec::default(),
+        };
+    }
+
+    #[allow(missing_docs)]
+    pub fn impulse_response_filename(&self) -> &Path {
+        &self.impulse_response_filename
+    }
+
+    #[allow(missing_docs)]
+    pub fn mix(&self) -> Normal {
+        self.mix
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_mix(&mut self, mix: Normal) {
+        self.mix = mix;
+    }
+}
+impl TransformsAudio for ConvolutionReverb {
+    fn transform_channel(&mut self, channel: usize, input_sample: Sample) -> Sample {
+        let Some(convolver) = self.e.convolvers.get_mut(channel) else {
+            return input_sample;
+        };
+        let wet = convolver.process(input_sample.0);
+        let mix = self.mix.0;
+        Sample(input_sample.0 * (1.0 - mix) + wet * mix)
+    }
+}
+impl Configurable for ConvolutionReverb {
+    fn sample_rate(&self) -> SampleRate {
+        self.e.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.e.sample_rate = sample_rate;
+        if let Some(impulse_response) = self.e.impulse_response.as_mut() {
+            impulse_response.update_sample_rate(sample_rate);
+            self.rebuild_convolvers();
+        }
+    }
+}
+impl Serializable for ConvolutionReverb {}
+impl Displays for ConvolutionReverb {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        ui.label(format!(
+            "Convolution reverb: {}",
+            self.impulse_response_filename.display()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mini::{assert_audible_and_bounded, render_entity_for, EntityUnderTest};
+
+    fn naive_convolution(input: &[f64], impulse_response: &[f64]) -> Vec<f64> {
+        let mut output = vec![0.0; input.len() + impulse_response.len() - 1];
+        for (i, x) in input.iter().enumerate() {
+            for (j, h) in impulse_response.iter().enumerate() {
+                output[i + j] += x * h;
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn partitioned_matches_naive_convolution() {
+        // An IR that spans several partitions and ends mid-block, and an input
+        // that isn't a whole number of blocks either.
+        const BLOCK_SIZE: usize = 16;
+        let impulse_response: Vec<f64> = (0..53)
+            .map(|i| (i as f64 * 0.37).sin() * (-(i as f64) / 20.0).exp())
+            .collect();
+        let input: Vec<f64> = (0..100)
+            .map(|i| ((i * 7919) % 13) as f64 / 6.0 - 1.0)
+            .collect();
+        let expected = naive_convolution(&input, &impulse_response);
+
+        let mut convolver = PartitionedConvolver::new_with(&impulse_response, BLOCK_SIZE);
+        let output: Vec<f64> = input
+            .iter()
+            .copied()
+            .chain(std::iter::repeat(0.0))
+            .take(expected.len() + BLOCK_SIZE)
+            .map(|x| convolver.process(x))
+            .collect();
+
+        assert!(
+            output[..BLOCK_SIZE].iter().all(|s| *s == 0.0),
+            "output should lag by one block"
+        );
+        for (i, (actual, expected)) in output[BLOCK_SIZE..].iter().zip(&expected).enumerate() {
+            assert!(
+                (actual - expected).abs() < 1.0e-9,
+                "frame {i}: expected {expected}, got {actual}"
+            );
+        }
+
+        convolver.reset();
+        assert_eq!(convolver.process(0.0), 0.0);
+    }
+
+    #[test]
+    fn mix_and_channels() {
+        // An IR of a single impulse at frame 0 delays the input by one block.
+        let impulse_response = SampleData::new_with(
+            vec![StereoSample(Sample(1.0), Sample(0.5))],
+            SampleRate::DEFAULT,
+            SampleRate::DEFAULT,
+        );
+        let mut reverb =
+            ConvolutionReverb::new_with_impulse_response(impulse_response, Normal::from(1.0));
+        let mut output = Vec::default();
+        for i in 0..ConvolutionReverb::BLOCK_SIZE + 1 {
+            let input = if i == 0 { 1.0 } else { 0.0 };
+            output.push(reverb.transform_audio(StereoSample::from(input)));
+        }
+        assert_eq!(output[0], StereoSample::from(0.0));
+        let delayed = output[ConvolutionReverb::BLOCK_SIZE];
+        assert!(
+            (delayed.0 .0 - 1.0).abs() < 1.0e-9 && (delayed.1 .0 - 0.5).abs() < 1.0e-9,
+            "each channel uses its own side of the IR"
+        );
+
+        reverb.set_mix(Normal::from(0.0));
+        assert_eq!(
+            reverb.transform_audio(StereoSample::from(0.25)),
+            StereoSample::from(0.25)
+        );
+    }
+
+    #[test]
+    fn without_an_impulse_response_passes_through() {
+        let mut reverb = ConvolutionReverb::default();
+        assert_eq!(
+            reverb.transform_audio(StereoSample::from(0.25)),
+            StereoSample::from(0.25)
+        );
+    }
+
+    #[test]
+    fn renders_sanely() {
+        let decay: Vec<StereoSample> = (0..2000)
+            .map(|i| StereoSample::from(0.05 * (-(i as f64) / 300.0).exp()))
+            .collect();
+        let impulse_response =
+            SampleData::new_with(decay, SampleRate::DEFAULT, SampleRate::DEFAULT);
+        let mut reverb =
+            ConvolutionReverb::new_with_impulse_response(impulse_response, Normal::from(0.5));
+        let samples = render_entity_for(
+            EntityUnderTest::Effect(&mut reverb),
+            0.1,
+            SampleRate::DEFAULT,
+        );
+        assert_audible_and_bounded(&samples);
+    }
+}
@@ -0,0 +1,358 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use super::SampleData;
+use anyhow::anyhow;
+use eframe::egui::Ui;
+use ensnare_core::prelude::*;
+use ensnare_core::traits::{
+    Configurable, ControlEventsFn, Controls, Displays, HandlesMidi, Serializable,
+};
+use ensnare_proc_macros::{Control, IsController, Uid};
+use groove_utils::Paths;
+use serde::{Deserialize, Serialize};
+use std::{ops::Range, path::PathBuf};
+
+/// One entry in a [CueTrack]: a sample to play at a musical time.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CueEvent {
+    #[allow(missing_docs)]
+    pub time: MusicalTime,
+
+    /// Which of the track's samples to play.
+    pub sample: usize,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CueTrackEphemerals {
+    sample_rate: SampleRate,
+    /// Index-aligned with the track's sample filenames. None until loaded.
+    samples: Vec<Option<SampleData>>,
+    range: Range<MusicalTime>,
+    is_performing: bool,
+    /// Cues that are sounding, as (sample, position within it).
+    voices: Vec<(usize, usize)>,
+}
+
+/// [CueTrack] plays samples at given musical times for the performers' ears
+/// only, like a spoken "one, two, three, four" count-in or a "chorus" cue. It's
+/// independent of the metronome, and its cues can be anywhere in the song.
+///
+/// It's a controller rather than an instrument, so nothing patches it into the
+/// main mix. Instead, [CueTrack::render()] fills a separate monitor bus, which
+/// [OutputRouting](super::OutputRouting) can send to a headphone output.
+#[derive(Serialize, Deserialize, Clone, Control, IsController, Debug, Uid)]
+pub struct CueTrack {
+    uid: Uid,
+
+    /// The samples that cues refer to. Empty for samples that were added
+    /// from memory, which aren't saved with the project.
+    sample_filenames: Vec<PathBuf>,
+
+    /// Sorted by time.
+    events: Vec<CueEvent>,
+
+    #[control]
+    level: Normal,
+
+    #[serde(skip)]
+    e: CueTrackEphemerals,
+}
+impl Default for CueTrack {
+    fn default() -> Self {
+        Self {
+            uid: Default::default(),
+            sample_filenames: Default::default(),
+            events: Default::default(),
+            level: Normal::from(1.0),
+            e: CueTrackEphemerals {
+                sample_rate: SampleRate::DEFAULT,
+                ..Default::default()
+            },
+        }
+    }
+}
+impl CueTrack {
+    /// Finds the named WAV file in the usual places and adds it to the
+    /// track's samples. Returns the sample's index for [CueTrack::add_cue()].
+    pub fn add_sample_file(&mut self, paths: &Paths, filename: PathBuf) -> anyhow::Result<usize> {
+        let sample = SampleData::new_from_file(paths, &filename, self.e.sample_rate)?;
+        self.sample_filenames.push(filename);
+        self.e.samples.push(Some(sample));
+        Ok(self.sample_filenames.len() - 1)
+    }
+
+    /// Adds a sample that's already in memory. It plays, but it isn't saved
+    /// with the project.
+    pub fn add_sample_data(&mut self, mut sample: SampleData) -> usize {
+        sample.update_sample_rate(self.e.sample_rate);
+        self.sample_filenames.push(PathBuf::default());
+        self.e.samples.push(Some(sample));
+        self.sample_filenames.len() - 1
+    }
+
+    /// Loads the sample files, as after deserializing. Samples that can't be
+    /// loaded stay silent, and the first error is returned.
+    pub fn load_samples(&mut self, paths: &Paths) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        self.e.samples = self
+            .sample_filenames
+            .iter()
+            .map(|filename| {
+                if filename.as_os_str().is_empty() {
+                    return None;
+                }
+                match SampleData::new_from_file(paths, filename, self.e.sample_rate) {
+                    Ok(sample) => Some(sample),
+                    Err(err) => {
+                        if result.is_ok() {
+                            result = Err(err);
+                        }
+                        None
+                    }
+                }
+            })
+            .collect();
+        result
+    }
+
+    /// Schedules the given sample to play at `time`. Cues at the same time
+    /// play together.
+    pub fn add_cue(&mut self, time: MusicalTime, sample: usize) -> anyhow::Result<()> {
+        if sample >= self.sample_filenames.len() {
+            return Err(anyhow!("Cue track has no sample {sample}"));
+        }
+        let index = self.events.partition_point(|e| e.time <= time);
+        self.events.insert(index, CueEvent { time, sample });
+        Ok(())
+    }
+
+    /// Removes every cue at `time`.
+    pub fn remove_cues_at(&mut self, time: MusicalTime) {
+        self.events.retain(|e| e.time != time);
+    }
+
+    /// Moves every cue at or after `at` later by `length`.
+    pub fn insert_time(&mut self, at: MusicalTime, length: MusicalTime) {
+        for event in self.events.iter_mut().filter(|e| e.time >= at) {
+            event.time = event.time + length;
+        }
+    }
+
+    /// Deletes the cues in `range`, and moves the ones after it earlier to
+    /// close the gap.
+    pub fn remove_time(&mut self, range: Range<MusicalTime>) {
+        let (cut_start, cut_end) = (range.start.total_units(), range.end.total_units());
+        if cut_start >= cut_end {
+            return;
+        }
+        self.events.retain(|e| !range.contains(&e.time));
+        for event in self.events.iter_mut().filter(|e| e.time >= range.end) {
+            event.time =
+                MusicalTime::new_with_units(event.time.total_units() - (cut_end - cut_start));
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn cues(&self) -> &[CueEvent] {
+        &self.events
+    }
+
+    #[allow(missing_docs)]
+    pub fn level(&self) -> Normal {
+        self.level
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_level(&mut self, level: Normal) {
+        self.level = level;
+    }
+
+    /// Fills `frames` with the sounding cues. This is the monitor bus; it
+    /// replaces whatever was in `frames` rather than adding to it.
+    pub fn render(&mut self, frames: &mut [StereoSample]) {
+        let level = self.level.value();
+        let samples = &self.e.samples;
+        for frame in frames.iter_mut() {
+            let mut sum = StereoSample::SILENCE;
+            for (sample, position) in self.e.voices.iter_mut() {
+                if let Some(Some(data)) = samples.get(*sample) {
+                    if let Some(value) = data.samples().get(*position) {
+                        sum += *value;
+                    }
+                }
+                *position += 1;
+            }
+            *frame = StereoSample(Sample(sum.0 .0 * level), Sample(sum.1 .0 * level));
+        }
+        self.e.voices.retain(|(sample, position)| {
+            samples
+                .get(*sample)
+                .and_then(|data| data.as_ref())
+                .is_some_and(|data| *position < data.samples().len())
+        });
+    }
+}
+impl HandlesMidi for CueTrack {}
+impl Displays for CueTrack {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        ui.label(format!("Cue track: {} cues", self.events.len()))
+    }
+}
+impl Serializable for CueTrack {}
+impl Configurable for CueTrack {
+    fn sample_rate(&self) -> SampleRate {
+        self.e.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.e.sample_rate = sample_rate;
+        for sample in self.e.samples.iter_mut().flatten() {
+            sample.update_sample_rate(sample_rate);
+        }
+        // Positions in the old samples don't mean anything in the new ones.
+        self.e.voices.clear();
+    }
+}
+impl Controls for CueTrack {
+    fn update_time(&mut self, range: &Range<MusicalTime>) {
+        self.e.range = range.clone();
+    }
+
+    fn work(&mut self, _control_events_fn: &mut ControlEventsFn) {
+        if !self.e.is_performing {
+            return;
+        }
+        let first = self.events.partition_point(|e| e.time < self.e.range.start);
+        for event in self.events[first..].iter() {
+            if event.time >= self.e.range.end {
+                break;
+            }
+            self.e.voices.push((event.sample, 0));
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        // Cues never hold up the end of a song.
+        true
+    }
+
+    fn play(&mut self) {
+        self.e.is_performing = true;
+    }
+
+    fn stop(&mut self) {
+        self.e.is_performing = false;
+        self.e.voices.clear();
+    }
+
+    fn skip_to_start(&mut self) {
+        self.e.range = MusicalTime::default()..MusicalTime::default();
+    }
+
+    fn is_performing(&self) -> bool {
+        self.e.is_performing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn click(value: f64, len: usize) -> SampleData {
+        SampleData::new_with(
+            vec![StereoSample::from(value); len],
+            SampleRate::DEFAULT,
+            SampleRate::DEFAULT,
+        )
+    }
+
+    fn cues_in(track: &mut CueTrack, range: Range<MusicalTime>) {
+        track.update_time(&range);
+        track.work(&mut |_, _| {});
+    }
+
+    #[test]
+    fn plays_cues_at_their_times() {
+        let mut track = CueTrack::default();
+        let one = track.add_sample_data(click(0.25, 4));
+        let two = track.add_sample_data(click(0.5, 2));
+        assert!(track.add_cue(MusicalTime::new_with_beats(1), two).is_ok());
+        assert!(track.add_cue(MusicalTime::new_with_beats(0), one).is_ok());
+        assert!(track.add_cue(MusicalTime::new_with_beats(1), 99).is_err());
+        assert_eq!(
+            track.cues().iter().map(|e| e.sample).collect::<Vec<_>>(),
+            vec![one, two],
+            "cues should be kept in time order"
+        );
+
+        // Nothing plays until the transport does.
+        let mut frames = [StereoSample::SILENCE; 6];
+        cues_in(
+            &mut track,
+            MusicalTime::default()..MusicalTime::new_with_units(1),
+        );
+        track.render(&mut frames);
+        assert_eq!(frames[0], StereoSample::SILENCE);
+
+        track.play();
+        cues_in(
+            &mut track,
+            MusicalTime::default()..MusicalTime::new_with_units(1),
+        );
+        track.render(&mut frames);
+        assert_eq!(frames[3], StereoSample::from(0.25));
+        assert_eq!(frames[4], StereoSample::SILENCE);
+
+        // Overlapping cues are summed, and the level applies to the whole bus.
+        assert!(track.add_cue(MusicalTime::new_with_beats(1), one).is_ok());
+        track.set_level(Normal::from(0.5));
+        let beat = MusicalTime::new_with_beats(1);
+        cues_in(&mut track, beat..beat + MusicalTime::new_with_units(1));
+        track.render(&mut frames);
+        assert_eq!(frames[0], StereoSample::from(0.375));
+        assert_eq!(frames[2], StereoSample::from(0.125));
+        assert_eq!(frames[5], StereoSample::SILENCE);
+    }
+
+    #[test]
+    fn cues_can_be_removed() {
+        let mut track = CueTrack::default();
+        let sample = track.add_sample_data(click(1.0, 1));
+        let time = MusicalTime::new_with_beats(4);
+        assert!(track.add_cue(time, sample).is_ok());
+        assert!(track.add_cue(time, sample).is_ok());
+        track.remove_cues_at(time);
+        assert!(track.cues().is_empty());
+    }
+
+    #[test]
+    fn cues_follow_inserted_and_removed_time() {
+        let mut track = CueTrack::default();
+        let sample = track.add_sample_data(click(1.0, 1));
+        for beat in [0, 4, 8] {
+            assert!(track
+                .add_cue(MusicalTime::new_with_beats(beat), sample)
+                .is_ok());
+        }
+        let beats = |track: &CueTrack| -> Vec<usize> {
+            track
+                .cues()
+                .iter()
+                .map(|e| e.time.total_units() / MusicalTime::UNITS_IN_BEAT)
+                .collect()
+        };
+
+        track.insert_time(
+            MusicalTime::new_with_beats(4),
+            MusicalTime::new_with_beats(16),
+        );
+        assert_eq!(beats(&track), vec![0, 20, 24]);
+
+        track.remove_time(MusicalTime::new_with_beats(2)..MusicalTime::new_with_beats(22));
+        assert_eq!(
+            beats(&track),
+            vec![0, 4],
+            "the cue inside the range is gone"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Keeps feedback paths out of denormal float territory. A decaying reverb or
+/// filter tail multiplies its state by a factor below 1.0 forever, and once
+/// those values get small enough, many CPUs slow down dramatically on every
+/// operation that touches them. Passing each value written back into a
+/// feedback path through [DenormalGuard::process()] snaps anything inaudibly
+/// small to exactly zero, so tails end in true silence.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DenormalGuard {
+    /// Values with a smaller magnitude than this become 0.0.
+    threshold: f64,
+}
+impl Default for DenormalGuard {
+    fn default() -> Self {
+        Self::new_with(Self::DEFAULT_THRESHOLD)
+    }
+}
+impl DenormalGuard {
+    /// About -400dB, far below anything audible, and far above the f64
+    /// denormal range.
+    pub const DEFAULT_THRESHOLD: f64 = 1.0e-20;
+
+    #[allow(missing_docs)]
+    pub fn new_with(threshold: f64) -> Self {
+        Self {
+            threshold: threshold.abs(),
+        }
+    }
+
+    /// Returns the value, or 0.0 if it's below the threshold.
+    pub fn process(&self, value: f64) -> f64 {
+        if value.abs() < self.threshold {
+            0.0
+        } else {
+            value
+        }
+    }
+
+    /// Applies [DenormalGuard::process()] to both channels.
+    pub fn process_stereo(&self, sample: StereoSample) -> StereoSample {
+        StereoSample(
+            Sample(self.process(sample.0 .0)),
+            Sample(self.process(sample.1 .0)),
+        )
+    }
+
+    #[allow(missing_docs)]
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_threshold(&mut self, threshold: f64) {
+        self.threshold = threshold.abs();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A feedback comb, the building block of a reverb, fed one impulse and
+    // then silence.
+    fn run_comb(guard: Option<DenormalGuard>, iterations: usize) -> f64 {
+        const FEEDBACK: f64 = 0.5;
+        let mut state = 1.0;
+        for _ in 0..iterations {
+            state *= FEEDBACK;
+            if let Some(guard) = guard {
+                state = guard.process(state);
+            }
+        }
+        state
+    }
+
+    #[test]
+    fn feedback_tail_reaches_exact_zero() {
+        let unguarded = run_comb(None, 200);
+        assert_ne!(unguarded, 0.0, "without the guard, the tail never ends");
+        assert!(unguarded.abs() < DenormalGuard::DEFAULT_THRESHOLD);
+
+        assert_eq!(run_comb(Some(DenormalGuard::default()), 200), 0.0);
+    }
+
+    #[test]
+    fn audible_values_pass_unchanged() {
+        let guard = DenormalGuard::default();
+        for value in [1.0, -1.0, 0.5, -1.0e-6, 1.0e-12] {
+            assert_eq!(guard.process(value), value);
+        }
+        let sample = StereoSample(Sample(0.25), Sample(1.0e-30));
+        assert_eq!(
+            guard.process_stereo(sample),
+            StereoSample(Sample(0.25), Sample(0.0))
+        );
+    }
+}
@@ -0,0 +1,470 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use super::{humanize::Humanizer, rng::Seeded};
+use eframe::egui::Ui;
+use ensnare_core::midi::prelude::*;
+use ensnare_core::prelude::*;
+use ensnare_core::traits::{
+    Configurable, ControlEventsFn, Controls, Displays, EntityEvent, HandlesMidi, Serializable,
+};
+use ensnare_proc_macros::{Control, IsController, Uid};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// One row of a [DrumSequencer] grid, which plays a single drum sound.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct DrumLane {
+    /// The MIDI key of the drum this lane triggers.
+    pub key: u8,
+
+    /// One velocity per step. Zero means the step is off.
+    pub velocities: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DrumSequencerEphemerals {
+    range: Range<MusicalTime>,
+    active_keys: Vec<u8>,
+    is_performing: bool,
+}
+
+/// [DrumSequencer] is a classic step sequencer for a drumkit. It's a grid of
+/// steps × lanes, and each cell is the velocity of the note to play at that
+/// step. The pattern is one bar long and loops.
+#[derive(Serialize, Deserialize, Clone, Control, IsController, Debug, Uid)]
+pub struct DrumSequencer {
+    uid: Uid,
+
+    /// The MIDI channel that notes go out on. Drums are traditionally on
+    /// channel 10, which is MidiChannel(9).
+    channel: MidiChannel,
+
+    /// How many steps make up one bar.
+    steps_per_bar: usize,
+
+    /// How far to delay every other step, from 0.0 (straight) to 1.0 (half a
+    /// step late).
+    swing: f64,
+
+    lanes: Vec<DrumLane>,
+
+    time_signature: TimeSignature,
+
+    /// The most that a step may drift off the grid, in either direction. It's
+    /// capped at a quarter step, so that steps never trade places, even with
+    /// full swing.
+    #[serde(default)]
+    humanize_timing: MusicalTime,
+
+    /// The most that a note's velocity may vary, in either direction.
+    #[serde(default)]
+    humanize_velocity: u8,
+
+    #[serde(default)]
+    humanize_seed: u64,
+
+    #[serde(skip)]
+    e: DrumSequencerEphemerals,
+}
+impl Default for DrumSequencer {
+    fn default() -> Self {
+        Self::new_with(16)
+    }
+}
+impl DrumSequencer {
+    #[allow(missing_docs)]
+    pub fn new_with(steps_per_bar: usize) -> Self {
+        Self {
+            uid: Default::default(),
+            channel: MidiChannel(9),
+            steps_per_bar: steps_per_bar.max(1),
+            swing: Default::default(),
+            lanes: Default::default(),
+            time_signature: Default::default(),
+            humanize_timing: Default::default(),
+            humanize_velocity: Default::default(),
+            humanize_seed: Default::default(),
+            e: Default::default(),
+        }
+    }
+
+    /// Adds a lane for the given drum key, with all steps off. Returns the new
+    /// lane's index.
+    pub fn add_lane(&mut self, key: u8) -> usize {
+        self.lanes.push(DrumLane {
+            key,
+            velocities: vec![0; self.steps_per_bar],
+        });
+        self.lanes.len() - 1
+    }
+
+    /// Sets the velocity of one cell. A velocity of zero turns the step off.
+    pub fn set_step(&mut self, lane: usize, step: usize, velocity: u8) {
+        if let Some(lane) = self.lanes.get_mut(lane) {
+            if let Some(cell) = lane.velocities.get_mut(step) {
+                *cell = velocity.min(127);
+            }
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn step(&self, lane: usize, step: usize) -> u8 {
+        self.lanes
+            .get(lane)
+            .and_then(|lane| lane.velocities.get(step))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    #[allow(missing_docs)]
+    pub fn lanes(&self) -> &[DrumLane] {
+        &self.lanes
+    }
+
+    #[allow(missing_docs)]
+    pub fn steps_per_bar(&self) -> usize {
+        self.steps_per_bar
+    }
+
+    /// Changes the number of steps per bar. Lanes are padded with empty steps
+    /// or truncated to match.
+    pub fn set_steps_per_bar(&mut self, steps_per_bar: usize) {
+        self.steps_per_bar = steps_per_bar.max(1);
+        for lane in self.lanes.iter_mut() {
+            lane.velocities.resize(self.steps_per_bar, 0);
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn swing(&self) -> f64 {
+        self.swing
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_swing(&mut self, swing: f64) {
+        self.swing = swing.clamp(0.0, 1.0);
+    }
+
+    #[allow(missing_docs)]
+    pub fn channel(&self) -> MidiChannel {
+        self.channel
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_channel(&mut self, channel: MidiChannel) {
+        self.channel = channel;
+    }
+
+    /// Makes playback less mechanical by moving each step off the grid by up
+    /// to `timing_amount` and varying each note's velocity by up to
+    /// `velocity_amount`. The variations depend only on `seed` and the step,
+    /// so every pass through the song, and every render, sounds the same. Zero
+    /// amounts turn humanizing off.
+    pub fn set_humanize(&mut self, seed: u64, timing_amount: MusicalTime, velocity_amount: u8) {
+        self.humanize_seed = seed;
+        self.humanize_timing = timing_amount;
+        self.humanize_velocity = velocity_amount;
+    }
+
+    fn humanizer(&self) -> Humanizer {
+        let max_timing_units = self.step_length_units() / 4;
+        Humanizer::new_with(
+            self.humanize_seed,
+            MusicalTime::new_with_units(self.humanize_timing.total_units().min(max_timing_units)),
+            self.humanize_velocity,
+        )
+    }
+
+    fn step_length_units(&self) -> usize {
+        (self.time_signature.top * MusicalTime::UNITS_IN_BEAT / self.steps_per_bar).max(1)
+    }
+
+    // The time at which the given step (counting from the start of the song,
+    // not the bar) fires, including swing.
+    fn step_time_units(&self, step: usize) -> usize {
+        let step_length = self.step_length_units();
+        let swing = if step % 2 == 1 {
+            (self.swing * step_length as f64 / 2.0) as usize
+        } else {
+            0
+        };
+        step * step_length + swing
+    }
+
+    // Like step_time_units(), but humanized. A step never moves before the
+    // start of its bar.
+    fn humanized_step_time_units(&self, step: usize, humanizer: &mut Humanizer) -> usize {
+        let bar_start = (step - step % self.steps_per_bar) * self.step_length_units();
+        humanizer
+            .humanize_start(
+                MusicalTime::new_with_units(self.step_time_units(step)),
+                MusicalTime::new_with_units(bar_start),
+            )
+            .total_units()
+    }
+
+    fn fire_step(
+        &mut self,
+        step: usize,
+        humanizer: &mut Humanizer,
+        control_events_fn: &mut ControlEventsFn,
+    ) {
+        for key in self.e.active_keys.drain(..) {
+            control_events_fn(
+                self.uid,
+                EntityEvent::Midi(
+                    self.channel,
+                    MidiMessage::NoteOff {
+                        key: key.into(),
+                        vel: 0.into(),
+                    },
+                ),
+            );
+        }
+        let step = step % self.steps_per_bar;
+        for lane in self.lanes.iter() {
+            let velocity = lane.velocities.get(step).copied().unwrap_or_default();
+            if velocity != 0 {
+                let velocity = humanizer.humanize_velocity(velocity);
+                control_events_fn(
+                    self.uid,
+                    EntityEvent::Midi(
+                        self.channel,
+                        MidiMessage::NoteOn {
+                            key: lane.key.into(),
+                            vel: velocity.into(),
+                        },
+                    ),
+                );
+                self.e.active_keys.push(lane.key);
+            }
+        }
+    }
+}
+impl Seeded for DrumSequencer {
+    fn reseed(&mut self, seed: u64) {
+        self.humanize_seed = seed;
+    }
+}
+impl HandlesMidi for DrumSequencer {}
+impl Displays for DrumSequencer {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        ui.label(format!(
+            "Drum sequencer: {} lanes × {} steps",
+            self.lanes.len(),
+            self.steps_per_bar
+        ))
+    }
+}
+impl Serializable for DrumSequencer {}
+impl Configurable for DrumSequencer {
+    fn update_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+    }
+}
+impl Controls for DrumSequencer {
+    fn update_time(&mut self, range: &Range<MusicalTime>) {
+        self.e.range = range.clone();
+    }
+
+    fn work(&mut self, control_events_fn: &mut ControlEventsFn) {
+        if !self.e.is_performing {
+            return;
+        }
+        let start = self.e.range.start.total_units();
+        let end = self.e.range.end.total_units();
+        let step_length = self.step_length_units();
+
+        // Swing and humanizing can push a step past the start of the next
+        // one's slot, and humanizing can pull it into the previous one's, so
+        // look one step either way.
+        let first_step = (start / step_length).saturating_sub(1);
+        let last_step = end / step_length + 1;
+        let humanizer = self.humanizer();
+        for step in first_step..=last_step {
+            let mut humanizer = humanizer.for_event(step as u64);
+            let time = self.humanized_step_time_units(step, &mut humanizer);
+            if time >= start && time < end {
+                self.fire_step(step, &mut humanizer, control_events_fn);
+            }
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        // The pattern loops forever.
+        true
+    }
+
+    fn play(&mut self) {
+        self.e.is_performing = true;
+    }
+
+    fn stop(&mut self) {
+        self.e.is_performing = false;
+    }
+
+    fn skip_to_start(&mut self) {
+        self.e.range = MusicalTime::default()..MusicalTime::default();
+    }
+
+    fn is_performing(&self) -> bool {
+        self.e.is_performing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KICK: u8 = 36;
+    const SNARE: u8 = 38;
+    const HAT: u8 = 42;
+
+    fn four_on_the_floor() -> DrumSequencer {
+        let mut s = DrumSequencer::new_with(16);
+        let kick = s.add_lane(KICK);
+        let snare = s.add_lane(SNARE);
+        let hat = s.add_lane(HAT);
+        for step in (0..16).step_by(4) {
+            s.set_step(kick, step, 127);
+        }
+        s.set_step(snare, 4, 100);
+        s.set_step(snare, 12, 100);
+        for step in (0..16).step_by(2) {
+            s.set_step(hat, step, 64);
+        }
+        s
+    }
+
+    fn note_ons_in(s: &mut DrumSequencer, range: Range<MusicalTime>) -> Vec<(usize, u8)> {
+        let mut note_ons = Vec::default();
+        let step_length = s.step_length_units();
+        let mut units = range.start.total_units();
+        while units < range.end.total_units() {
+            let slice = MusicalTime::new_with_units(units)..MusicalTime::new_with_units(units + 1);
+            s.update_time(&slice);
+            s.work(&mut |_, event| {
+                if let EntityEvent::Midi(_, MidiMessage::NoteOn { key, .. }) = event {
+                    note_ons.push((units / step_length, key.as_int()));
+                }
+            });
+            units += 1;
+        }
+        note_ons
+    }
+
+    #[test]
+    fn plays_programmed_beat_and_loops() {
+        let mut s = four_on_the_floor();
+        s.play();
+        let bar = MusicalTime::new_with_beats(4);
+        let first = note_ons_in(&mut s, MusicalTime::default()..bar);
+        assert_eq!(first.iter().filter(|(_, key)| *key == KICK).count(), 4);
+        assert_eq!(first.iter().filter(|(_, key)| *key == SNARE).count(), 2);
+        assert_eq!(first.iter().filter(|(_, key)| *key == HAT).count(), 8);
+        assert!(first.contains(&(4, SNARE)));
+        assert!(first.contains(&(12, SNARE)));
+
+        let second = note_ons_in(&mut s, bar..MusicalTime::new_with_beats(8));
+        assert_eq!(
+            second
+                .iter()
+                .map(|(step, key)| (step - 16, *key))
+                .collect::<Vec<_>>(),
+            first,
+            "the second bar should repeat the first"
+        );
+    }
+
+    #[test]
+    fn stopped_sequencer_is_silent() {
+        let mut s = four_on_the_floor();
+        assert!(note_ons_in(
+            &mut s,
+            MusicalTime::default()..MusicalTime::new_with_beats(4)
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn swing_delays_odd_steps() {
+        let mut s = DrumSequencer::new_with(16);
+        s.set_swing(1.0);
+        let step_length = s.step_length_units();
+        assert_eq!(s.step_time_units(2), 2 * step_length);
+        assert_eq!(s.step_time_units(3), 3 * step_length + step_length / 2);
+    }
+
+    // Every note-on in `range`, as (time in units, key, velocity).
+    fn timed_note_ons_in(s: &mut DrumSequencer, range: Range<MusicalTime>) -> Vec<(usize, u8, u8)> {
+        let mut note_ons = Vec::default();
+        for units in range.start.total_units()..range.end.total_units() {
+            let slice = MusicalTime::new_with_units(units)..MusicalTime::new_with_units(units + 1);
+            s.update_time(&slice);
+            s.work(&mut |_, event| {
+                if let EntityEvent::Midi(_, MidiMessage::NoteOn { key, vel }) = event {
+                    note_ons.push((units, key.as_int(), vel.as_int()));
+                }
+            });
+        }
+        note_ons
+    }
+
+    #[test]
+    fn humanizing_is_repeatable_and_bounded() {
+        let bar = MusicalTime::new_with_beats(4);
+        let mut straight = four_on_the_floor();
+        straight.play();
+        let grid = timed_note_ons_in(&mut straight, MusicalTime::default()..bar);
+
+        let mut s = four_on_the_floor();
+        let step_length = s.step_length_units();
+        s.set_humanize(
+            7,
+            MusicalTime::new_with_units(MusicalTime::UNITS_IN_BEAT),
+            10,
+        );
+        s.play();
+        let first = timed_note_ons_in(&mut s, MusicalTime::default()..bar);
+        let again = timed_note_ons_in(&mut s, MusicalTime::default()..bar);
+        assert_eq!(first, again, "the same steps should humanize the same way");
+        assert_ne!(first, grid, "humanizing should change something");
+        assert_eq!(first.len(), grid.len(), "no note should be lost or doubled");
+
+        for (time, key, vel) in first.iter() {
+            let (grid_time, _, grid_vel) = grid
+                .iter()
+                .filter(|(_, grid_key, _)| grid_key == key)
+                .min_by_key(|(grid_time, _, _)| grid_time.abs_diff(*time))
+                .unwrap();
+            assert!(
+                time.abs_diff(*grid_time) <= step_length / 4,
+                "timing is capped at a quarter step"
+            );
+            assert!(vel.abs_diff(*grid_vel) <= 10);
+            assert!((1..=127).contains(vel));
+        }
+
+        let mut reseeded = four_on_the_floor();
+        reseeded.set_humanize(
+            8,
+            MusicalTime::new_with_units(MusicalTime::UNITS_IN_BEAT),
+            10,
+        );
+        reseeded.reseed(7);
+        reseeded.play();
+        assert_eq!(
+            timed_note_ons_in(&mut reseeded, MusicalTime::default()..bar),
+            first
+        );
+    }
+
+    #[test]
+    fn resizing_keeps_lanes_consistent() {
+        let mut s = four_on_the_floor();
+        s.set_steps_per_bar(8);
+        assert!(s.lanes().iter().all(|lane| lane.velocities.len() == 8));
+        assert_eq!(s.step(0, 4), 127);
+        assert_eq!(s.step(0, 99), 0);
+    }
+}
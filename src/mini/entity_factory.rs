@@ -1 +1,72 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
 
+use super::{
+    AudioInput, AutomationLane, ConvolutionReverb, CueTrack, DrumSequencer, NoteRepeat,
+    ParametricEq, RingModulator, SampleAndHoldLfo, Waveshaper,
+};
+use ensnare_core::{entities::EntityFactory, prelude::*};
+
+/// Registers the entities in [mini](crate::mini) with `factory`, so that the
+/// palette can offer them and projects can name them by key. Call it before
+/// handing the factory to ensnare's registration, which completes it:
+///
+/// ```ignore
+/// let _ = EntityFactory::initialize(ensnare_not_core::register_factory_entities(
+///     register_mini_entities(EntityFactory::default()),
+/// ));
+/// ```
+///
+/// Entities that can't be built from nothing, like
+/// [HostedPlugin](crate::mini::HostedPlugin), which needs a plugin URI, or
+/// [FrozenSampler](crate::mini::FrozenSampler), which needs a render, aren't
+/// registered.
+pub fn register_mini_entities(mut factory: EntityFactory) -> EntityFactory {
+    factory.register_entity(EntityKey::from("audio-input"), || {
+        Box::new(AudioInput::default())
+    });
+    factory.register_entity(EntityKey::from("automation-lane"), || {
+        Box::new(AutomationLane::default())
+    });
+    factory.register_entity(EntityKey::from("convolution-reverb"), || {
+        Box::new(ConvolutionReverb::default())
+    });
+    factory.register_entity(EntityKey::from("cue-track"), || {
+        Box::new(CueTrack::default())
+    });
+    factory.register_entity(EntityKey::from("drum-sequencer"), || {
+        Box::new(DrumSequencer::default())
+    });
+    factory.register_entity(EntityKey::from("note-repeat"), || {
+        Box::new(NoteRepeat::default())
+    });
+    factory.register_entity(EntityKey::from("parametric-eq"), || {
+        Box::new(ParametricEq::default())
+    });
+    factory.register_entity(EntityKey::from("ring-modulator"), || {
+        Box::new(RingModulator::default())
+    });
+    factory.register_entity(EntityKey::from("sample-and-hold-lfo"), || {
+        Box::new(SampleAndHoldLfo::default())
+    });
+    factory.register_entity(EntityKey::from("waveshaper"), || {
+        Box::new(Waveshaper::default())
+    });
+    factory
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mini_entities_are_created_by_key() {
+        let factory = register_mini_entities(EntityFactory::default());
+        for key in ["drum-sequencer", "parametric-eq", "waveshaper"] {
+            assert!(
+                factory.new_entity(&EntityKey::from(key)).is_some(),
+                "{key} should be registered"
+            );
+        }
+        assert!(factory.new_entity(&EntityKey::from("unicorn")).is_none());
+    }
+}
@@ -0,0 +1,538 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use super::ConfiguredSampleRate;
+use crate::panels::ControlEditorPanel;
+use eframe::egui::Ui;
+use ensnare_core::traits::{Configurable, Displays, Serializable, TransformsAudio};
+use ensnare_core::{control::ControlIndex, prelude::*};
+use ensnare_proc_macros::{IsEffect, Uid};
+use groove_proc_macros::Control;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+
+/// The response shape of one [EqBand].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EqBandShape {
+    /// Boosts or cuts everything below the frequency.
+    LowShelf,
+    /// Boosts or cuts a bell around the frequency, as wide as Q says.
+    #[default]
+    Peaking,
+    /// Boosts or cuts everything above the frequency.
+    HighShelf,
+}
+
+/// Biquad coefficients, normalized so that a0 is 1.0.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct BiquadCoefficients {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+impl BiquadCoefficients {
+    // The formulas are from Robert Bristow-Johnson's Audio EQ Cookbook.
+    fn new_with(
+        shape: EqBandShape,
+        frequency: FrequencyHz,
+        gain_db: f64,
+        q: f64,
+        sample_rate: SampleRate,
+    ) -> Self {
+        let a = 10.0f64.powf(gain_db / 40.0);
+        let w0 = TAU * frequency.0 / sample_rate.0 as f64;
+        let (sin_w0, cos_w0) = w0.sin_cos();
+        let alpha = sin_w0 / (2.0 * q);
+        let two_sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match shape {
+            EqBandShape::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 + two_sqrt_a_alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 - two_sqrt_a_alpha),
+                (a + 1.0) + (a - 1.0) * cos_w0 + two_sqrt_a_alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+                (a + 1.0) + (a - 1.0) * cos_w0 - two_sqrt_a_alpha,
+            ),
+            EqBandShape::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos_w0,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos_w0,
+                1.0 - alpha / a,
+            ),
+            EqBandShape::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 + two_sqrt_a_alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 - two_sqrt_a_alpha),
+                (a + 1.0) - (a - 1.0) * cos_w0 + two_sqrt_a_alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+                (a + 1.0) - (a - 1.0) * cos_w0 - two_sqrt_a_alpha,
+            ),
+        };
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+/// Direct Form I history for one channel.
+#[derive(Clone, Copy, Debug, Default)]
+struct BiquadState {
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct EqBandEphemerals {
+    sample_rate: ConfiguredSampleRate,
+
+    /// None when the band is flat, so that it can be skipped entirely.
+    coefficients: Option<BiquadCoefficients>,
+
+    /// One per channel.
+    state: [BiquadState; 2],
+}
+
+/// One band of a [ParametricEq].
+#[derive(Serialize, Deserialize, Clone, Control, Debug)]
+pub struct EqBand {
+    shape: EqBandShape,
+
+    #[control(
+        min = 20.0,
+        max = 20000.0,
+        default = 1000.0,
+        unit = "Hz",
+        setter = true
+    )]
+    frequency: FrequencyHz,
+
+    /// Boost or cut, in decibels. 0.0 is flat, and a flat band is skipped so
+    /// that it's exactly transparent.
+    #[control(min = -24.0, max = 24.0, default = 0.0, unit = "dB")]
+    gain: f64,
+
+    #[control(min = 0.1, max = 10.0, default = 0.707)]
+    q: f64,
+
+    #[serde(skip)]
+    e: EqBandEphemerals,
+}
+impl EqBand {
+    /// The largest boost or cut, in decibels.
+    pub const MAX_GAIN: f64 = 24.0;
+
+    #[allow(missing_docs)]
+    pub fn new_with(shape: EqBandShape, frequency: FrequencyHz, gain: f64, q: f64) -> Self {
+        let mut r = Self {
+            shape,
+            frequency,
+            gain: 0.0,
+            q: std::f64::consts::FRAC_1_SQRT_2,
+            e: Default::default(),
+        };
+        r.set_gain(gain);
+        r.set_q(q);
+        r
+    }
+
+    #[allow(missing_docs)]
+    pub fn shape(&self) -> EqBandShape {
+        self.shape
+    }
+
+    #[allow(missing_docs)]
+    pub fn frequency(&self) -> FrequencyHz {
+        self.frequency
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_frequency(&mut self, frequency: FrequencyHz) {
+        self.frequency = frequency;
+        self.update_coefficients();
+    }
+
+    #[allow(missing_docs)]
+    pub fn gain(&self) -> f64 {
+        self.gain
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_gain(&mut self, gain: f64) {
+        if gain.is_finite() {
+            self.gain = gain.clamp(-Self::MAX_GAIN, Self::MAX_GAIN);
+        } else {
+            eprintln!("Warning: ignoring invalid EQ gain {gain}");
+        }
+        self.update_coefficients();
+    }
+
+    #[allow(missing_docs)]
+    pub fn q(&self) -> f64 {
+        self.q
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_q(&mut self, q: f64) {
+        if q.is_finite() && q > 0.0 {
+            self.q = q;
+        } else {
+            eprintln!("Warning: ignoring invalid EQ Q {q}");
+        }
+        self.update_coefficients();
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: ConfiguredSampleRate) {
+        self.e.sample_rate = sample_rate;
+        self.update_coefficients();
+    }
+
+    // The current value of the control at `index` within this band, in the
+    // units of its descriptor.
+    fn control_native_value(&self, index: usize) -> Option<f64> {
+        match index {
+            Self::FREQUENCY_INDEX => Some(self.frequency.0),
+            Self::GAIN_INDEX => Some(self.gain),
+            Self::Q_INDEX => Some(self.q),
+            _ => None,
+        }
+    }
+
+    // Coefficients wait until the real sample rate is known.
+    fn update_coefficients(&mut self) {
+        let sample_rate = self.e.sample_rate.get();
+        let coefficients = if self.gain == 0.0 || !self.e.sample_rate.is_set() || sample_rate.0 == 0
+        {
+            None
+        } else {
+            // Keep the frequency below Nyquist, where the formulas fall apart.
+            let nyquist = sample_rate.0 as f64 / 2.0;
+            let frequency = FrequencyHz(self.frequency.0.clamp(1.0, nyquist * 0.99));
+            Some(BiquadCoefficients::new_with(
+                self.shape,
+                frequency,
+                self.gain,
+                self.q,
+                sample_rate,
+            ))
+        };
+        if coefficients.is_none() {
+            // Coming back into the chain later shouldn't replay old history.
+            self.e.state = Default::default();
+        }
+        self.e.coefficients = coefficients;
+    }
+
+    fn process(&mut self, channel: usize, x: f64) -> f64 {
+        let (Some(c), Some(s)) = (self.e.coefficients, self.e.state.get_mut(channel)) else {
+            return x;
+        };
+        let y = c.b0 * x + c.b1 * s.x1 + c.b2 * s.x2 - c.a1 * s.y1 - c.a2 * s.y2;
+        s.x2 = s.x1;
+        s.x1 = x;
+        s.y2 = s.y1;
+        s.y1 = y;
+        y
+    }
+}
+
+/// [ParametricEq] is a four-band equalizer: a low shelf, two peaking bands,
+/// and a high shelf, applied in that order. Every band's frequency, gain, and Q
+/// can be automated.
+#[derive(Serialize, Deserialize, Clone, Control, IsEffect, Debug, Uid)]
+pub struct ParametricEq {
+    uid: Uid,
+
+    #[control]
+    low_shelf: EqBand,
+
+    #[control]
+    low_mid: EqBand,
+
+    #[control]
+    high_mid: EqBand,
+
+    #[control]
+    high_shelf: EqBand,
+
+    #[serde(skip)]
+    sample_rate: ConfiguredSampleRate,
+
+    #[serde(skip)]
+    editor: ControlEditorPanel,
+}
+impl Default for ParametricEq {
+    fn default() -> Self {
+        Self {
+            uid: Default::default(),
+            low_shelf: EqBand::new_with(
+                EqBandShape::LowShelf,
+                FrequencyHz(100.0),
+                0.0,
+                std::f64::consts::FRAC_1_SQRT_2,
+            ),
+            low_mid: EqBand::new_with(EqBandShape::Peaking, FrequencyHz(400.0), 0.0, 1.0),
+            high_mid: EqBand::new_with(EqBandShape::Peaking, FrequencyHz(2500.0), 0.0, 1.0),
+            high_shelf: EqBand::new_with(
+                EqBandShape::HighShelf,
+                FrequencyHz(8000.0),
+                0.0,
+                std::f64::consts::FRAC_1_SQRT_2,
+            ),
+            sample_rate: Default::default(),
+            editor: Default::default(),
+        }
+    }
+}
+impl ParametricEq {
+    #[allow(missing_docs)]
+    pub fn low_shelf(&self) -> &EqBand {
+        &self.low_shelf
+    }
+
+    #[allow(missing_docs)]
+    pub fn low_shelf_mut(&mut self) -> &mut EqBand {
+        &mut self.low_shelf
+    }
+
+    #[allow(missing_docs)]
+    pub fn low_mid(&self) -> &EqBand {
+        &self.low_mid
+    }
+
+    #[allow(missing_docs)]
+    pub fn low_mid_mut(&mut self) -> &mut EqBand {
+        &mut self.low_mid
+    }
+
+    #[allow(missing_docs)]
+    pub fn high_mid(&self) -> &EqBand {
+        &self.high_mid
+    }
+
+    #[allow(missing_docs)]
+    pub fn high_mid_mut(&mut self) -> &mut EqBand {
+        &mut self.high_mid
+    }
+
+    #[allow(missing_docs)]
+    pub fn high_shelf(&self) -> &EqBand {
+        &self.high_shelf
+    }
+
+    #[allow(missing_docs)]
+    pub fn high_shelf_mut(&mut self) -> &mut EqBand {
+        &mut self.high_shelf
+    }
+
+    fn bands_mut(&mut self) -> [&mut EqBand; 4] {
+        [
+            &mut self.low_shelf,
+            &mut self.low_mid,
+            &mut self.high_mid,
+            &mut self.high_shelf,
+        ]
+    }
+
+    // The current value of the control at `index`, in the units of its
+    // descriptor, so that the editor starts from what the EQ is really doing.
+    fn control_native_value(&self, index: ControlIndex) -> Option<f64> {
+        [
+            (Self::LOW_SHELF_INDEX, &self.low_shelf),
+            (Self::LOW_MID_INDEX, &self.low_mid),
+            (Self::HIGH_MID_INDEX, &self.high_mid),
+            (Self::HIGH_SHELF_INDEX, &self.high_shelf),
+        ]
+        .into_iter()
+        .find_map(|(start, band)| {
+            index
+                .0
+                .checked_sub(start)
+                .filter(|i| *i < EqBand::STRUCT_SIZE)
+                .and_then(|i| band.control_native_value(i))
+        })
+    }
+}
+impl TransformsAudio for ParametricEq {
+    fn transform_channel(&mut self, channel: usize, input_sample: Sample) -> Sample {
+        if !self.sample_rate.is_set() {
+            let sample_rate = self.sample_rate.expect_set();
+            self.update_sample_rate(sample_rate);
+        }
+        let mut value = input_sample.0;
+        for band in self.bands_mut() {
+            value = band.process(channel, value);
+        }
+        Sample(value)
+    }
+}
+impl Configurable for ParametricEq {
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate.get()
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate.set(sample_rate);
+        let sample_rate = self.sample_rate;
+        for band in self.bands_mut() {
+            band.update_sample_rate(sample_rate);
+        }
+    }
+}
+impl Serializable for ParametricEq {
+    fn after_deser(&mut self) {
+        if self.sample_rate.is_set() {
+            let sample_rate = self.sample_rate.get();
+            self.update_sample_rate(sample_rate);
+        }
+    }
+}
+impl Displays for ParametricEq {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        let descriptors = self.control_param_descriptors();
+        let current_values: Vec<f64> = descriptors
+            .iter()
+            .map(|d| self.control_native_value(d.index).unwrap_or(d.default))
+            .collect();
+
+        // The editor edits self, so it can't also be borrowed from self.
+        let mut editor = std::mem::take(&mut self.editor);
+        let response = ui
+            .vertical(|ui| {
+                ui.label(format!(
+                    "EQ: {:+.1} / {:+.1} / {:+.1} / {:+.1} dB",
+                    self.low_shelf.gain,
+                    self.low_mid.gain,
+                    self.high_mid.gain,
+                    self.high_shelf.gain
+                ));
+                editor.show(ui, self.uid, &descriptors, &current_values, self);
+            })
+            .response;
+        self.editor = editor;
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mini::{assert_audible_and_bounded, render_entity_for, EntityUnderTest};
+    use ensnare_core::{control::ControlValue, traits::Controllable};
+
+    // Runs a sine at the given frequency through the EQ and returns the gain
+    // in decibels, measured after the filters have settled.
+    fn measure_gain(eq: &mut ParametricEq, frequency: f64) -> f64 {
+        let sample_rate = eq.sample_rate().0 as f64;
+        let mut peak: f64 = 0.0;
+        for i in 0..sample_rate as usize {
+            let x = (TAU * frequency * i as f64 / sample_rate).sin();
+            let y = eq.transform_channel(0, Sample(x)).0;
+            if i > sample_rate as usize / 2 {
+                peak = peak.max(y.abs());
+            }
+        }
+        20.0 * peak.log10()
+    }
+
+    #[test]
+    fn flat_bands_are_transparent() {
+        let mut eq = ParametricEq::default();
+        eq.update_sample_rate(SampleRate(44100));
+        for i in 0..1000 {
+            let x = ((i * 7919) % 101) as f64 / 50.0 - 1.0;
+            assert_eq!(eq.transform_channel(0, Sample(x)).0, x);
+        }
+
+        // Boosting then flattening a band should leave no trace.
+        eq.low_mid_mut().set_gain(6.0);
+        eq.transform_channel(0, Sample(1.0));
+        eq.low_mid_mut().set_gain(0.0);
+        assert_eq!(eq.transform_channel(0, Sample(0.25)).0, 0.25);
+    }
+
+    #[test]
+    fn dip_and_boost_on_one_insert() {
+        let mut eq = ParametricEq::default();
+        eq.update_sample_rate(SampleRate(44100));
+        eq.low_mid_mut().set_frequency(FrequencyHz(400.0));
+        eq.low_mid_mut().set_gain(-3.0);
+        eq.high_mid_mut().set_frequency(FrequencyHz(5000.0));
+        eq.high_mid_mut().set_gain(3.0);
+
+        let at_400 = measure_gain(&mut eq, 400.0);
+        assert!(
+            (at_400 + 3.0).abs() < 0.2,
+            "expected -3dB at 400Hz, got {at_400}"
+        );
+        let at_5000 = measure_gain(&mut eq, 5000.0);
+        assert!(
+            (at_5000 - 3.0).abs() < 0.2,
+            "expected +3dB at 5KHz, got {at_5000}"
+        );
+        let at_50 = measure_gain(&mut eq, 50.0);
+        assert!(at_50.abs() < 0.2, "expected flat at 50Hz, got {at_50}");
+    }
+
+    #[test]
+    fn shelves_shape_the_extremes() {
+        let mut eq = ParametricEq::default();
+        eq.update_sample_rate(SampleRate(44100));
+        eq.low_shelf_mut().set_gain(6.0);
+        eq.high_shelf_mut().set_gain(-6.0);
+        let low = measure_gain(&mut eq, 20.0);
+        assert!((low - 6.0).abs() < 0.5, "low shelf gave {low}");
+        let high = measure_gain(&mut eq, 18000.0);
+        assert!((high + 6.0).abs() < 0.5, "high shelf gave {high}");
+
+        eq.low_shelf_mut().set_q(-1.0);
+        assert_eq!(eq.low_shelf().q(), std::f64::consts::FRAC_1_SQRT_2);
+    }
+
+    #[test]
+    fn descriptors_drive_the_editor() {
+        let mut eq = ParametricEq::default();
+        let descriptors = eq.control_param_descriptors();
+        assert_eq!(descriptors.len(), eq.control_index_count());
+
+        let d = descriptors
+            .iter()
+            .find(|d| d.name == "low-mid-gain")
+            .unwrap();
+        assert_eq!((d.min, d.max, d.default), (-24.0, 24.0, 0.0));
+        assert_eq!(d.unit, "dB");
+
+        // What ControlEditorPanel sends for a 6dB slider position.
+        eq.control_set_param_by_index(d.index, ControlValue(d.normalize(6.0)));
+        assert_eq!(eq.low_mid().gain(), 6.0);
+        assert_eq!(eq.high_mid().gain(), 0.0);
+        assert_eq!(eq.control_native_value(d.index), Some(6.0));
+
+        let d = descriptors
+            .iter()
+            .find(|d| d.name == "high-shelf-frequency")
+            .unwrap();
+        assert_eq!((d.min, d.max, d.unit.as_str()), (20.0, 20000.0, "Hz"));
+        assert_eq!(eq.control_native_value(d.index), Some(8000.0));
+        eq.control_set_param_by_index(d.index, ControlValue(d.normalize(5000.0)));
+        assert!((eq.high_shelf().frequency().0 - 5000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn renders_sanely() {
+        let mut eq = ParametricEq::default();
+        eq.low_shelf_mut().set_gain(EqBand::MAX_GAIN);
+        eq.high_mid_mut().set_gain(-EqBand::MAX_GAIN);
+        let samples = render_entity_for(EntityUnderTest::Effect(&mut eq), 0.1, SampleRate::DEFAULT);
+        assert_audible_and_bounded(&samples);
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use super::SampleData;
+use eframe::egui::Ui;
+use ensnare_core::prelude::*;
+use ensnare_core::traits::{Configurable, Displays, Generates, HandlesMidi, Serializable, Ticks};
+use ensnare_proc_macros::{Control, IsInstrument, Uid};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default)]
+pub struct FrozenSamplerEphemerals {
+    audio: SampleData,
+    position: Option<f64>,
+    sample: StereoSample,
+}
+
+/// [FrozenSampler] plays back audio that was rendered from a chain of
+/// entities, so that it can stand in for the chain and save the CPU that the
+/// chain would have used. It's an ordinary instrument, so it can be patched,
+/// bypassed, and metered like the chain it replaces.
+///
+/// The audio runs from the start of the song. The owner tells the sampler
+/// where the song is with [FrozenSampler::set_position()] before each tick,
+/// which keeps it in step through seeks and changes in playback rate.
+#[derive(Debug, Default, Control, IsInstrument, Uid, Serialize, Deserialize)]
+pub struct FrozenSampler {
+    uid: Uid,
+
+    #[serde(skip)]
+    e: FrozenSamplerEphemerals,
+}
+impl FrozenSampler {
+    /// Creates a [FrozenSampler] that plays `samples`, which were rendered at
+    /// `sample_rate`.
+    pub fn new_with(samples: Vec<StereoSample>, sample_rate: SampleRate) -> Self {
+        Self {
+            e: FrozenSamplerEphemerals {
+                audio: SampleData::new_with(samples, sample_rate, sample_rate),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Sets where the next tick plays from, in seconds from the start of the
+    /// song at normal speed. None plays silence, as when the transport is
+    /// stopped. Positions between frames are interpolated, so a song that's
+    /// playing faster or slower than normal reads the audio faster or slower,
+    /// like tape.
+    pub fn set_position(&mut self, position: Option<f64>) {
+        self.e.position = position;
+    }
+
+    /// How long the audio is, in seconds.
+    pub fn duration(&self) -> f64 {
+        let sample_rate = self.e.audio.sample_rate().value();
+        if sample_rate == 0 {
+            0.0
+        } else {
+            self.e.audio.samples().len() as f64 / sample_rate as f64
+        }
+    }
+
+    fn sample_at_position(&self) -> StereoSample {
+        let Some(position) = self.e.position else {
+            return StereoSample::SILENCE;
+        };
+        let samples = self.e.audio.samples();
+        let frame = position * self.e.audio.sample_rate().value() as f64;
+        if frame < 0.0 || samples.is_empty() {
+            return StereoSample::SILENCE;
+        }
+        let index = frame.floor() as usize;
+        let Some(a) = samples.get(index) else {
+            return StereoSample::SILENCE;
+        };
+        let b = samples.get(index + 1).unwrap_or(a);
+        let fraction = frame - index as f64;
+        StereoSample(
+            Sample(a.0 .0 + (b.0 .0 - a.0 .0) * fraction),
+            Sample(a.1 .0 + (b.1 .0 - a.1 .0) * fraction),
+        )
+    }
+}
+impl Generates<StereoSample> for FrozenSampler {
+    fn value(&self) -> StereoSample {
+        self.e.sample
+    }
+
+    fn generate_batch_values(&mut self, values: &mut [StereoSample]) {
+        // Without a new position for each frame, every frame would be the
+        // same, so batches hold the current one.
+        self.e.sample = self.sample_at_position();
+        values.fill(self.e.sample);
+    }
+}
+impl Ticks for FrozenSampler {
+    fn tick(&mut self, _tick_count: usize) {
+        self.e.sample = self.sample_at_position();
+    }
+}
+impl Configurable for FrozenSampler {
+    fn sample_rate(&self) -> SampleRate {
+        self.e.audio.sample_rate()
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.e.audio.update_sample_rate(sample_rate);
+    }
+}
+// It follows the song position, not notes.
+impl HandlesMidi for FrozenSampler {}
+impl Serializable for FrozenSampler {}
+impl Displays for FrozenSampler {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        ui.label(format!("Frozen audio: {:.1}s", self.duration()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp() -> Vec<StereoSample> {
+        (0..100)
+            .map(|i| StereoSample(Sample(i as f64 / 100.0), Sample(-(i as f64) / 100.0)))
+            .collect()
+    }
+
+    #[test]
+    fn follows_the_position_it_is_given() {
+        let mut sampler = FrozenSampler::new_with(ramp(), SampleRate(100));
+        assert_eq!(sampler.duration(), 1.0);
+
+        sampler.tick(1);
+        assert_eq!(sampler.value(), StereoSample::SILENCE, "no position yet");
+
+        sampler.set_position(Some(0.5));
+        sampler.tick(1);
+        assert!(sampler
+            .value()
+            .almost_equals(StereoSample(Sample(0.5), Sample(-0.5))));
+
+        // Halfway between two frames, as at a non-unity playback rate.
+        sampler.set_position(Some(0.255));
+        sampler.tick(1);
+        assert!(sampler
+            .value()
+            .almost_equals(StereoSample(Sample(0.255), Sample(-0.255))));
+
+        sampler.set_position(Some(2.0));
+        sampler.tick(1);
+        assert_eq!(sampler.value(), StereoSample::SILENCE, "past the end");
+    }
+
+    #[test]
+    fn position_is_in_seconds_at_any_sample_rate() {
+        let mut sampler = FrozenSampler::new_with(ramp(), SampleRate(100));
+        sampler.update_sample_rate(SampleRate(200));
+        assert_eq!(sampler.duration(), 1.0);
+        sampler.set_position(Some(0.5));
+        sampler.tick(1);
+        assert!((sampler.value().0 .0 - 0.5).abs() < 0.01);
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::midi::prelude::*;
+use ensnare_core::prelude::*;
+use std::ops::Range;
+
+#[derive(Clone, Debug, PartialEq)]
+struct PendingNoteOff {
+    channel: MidiChannel,
+    key: u8,
+    time: MusicalTime,
+}
+
+/// [NoteGate] sets the articulation of a whole part. A sequencer hands it each
+/// note as it starts, with the note's written duration, and the gate decides
+/// when the note-off goes out: a gate of 0.5 cuts every note to half its
+/// length (staccato), 1.0 plays it as written, and anything above 1.0 lets it
+/// ring into the next note (legato).
+///
+/// When notes overlap, a note can start again on a key whose previous note
+/// hasn't ended yet. The gate ends the old note just before the new one
+/// starts, and forgets the old note's note-off, so that it doesn't cut off the
+/// new note.
+#[derive(Clone, Debug)]
+pub struct NoteGate {
+    gate: f64,
+    pending: Vec<PendingNoteOff>,
+}
+impl Default for NoteGate {
+    fn default() -> Self {
+        Self::new_with(1.0)
+    }
+}
+impl NoteGate {
+    /// The shortest gate. Anything shorter and notes become clicks.
+    pub const MIN_GATE: f64 = 0.01;
+
+    /// The longest gate: each note lasts up to twice its written duration.
+    pub const MAX_GATE: f64 = 2.0;
+
+    #[allow(missing_docs)]
+    pub fn new_with(gate: f64) -> Self {
+        let mut r = Self {
+            gate: 1.0,
+            pending: Default::default(),
+        };
+        r.set_gate(gate);
+        r
+    }
+
+    #[allow(missing_docs)]
+    pub fn gate(&self) -> f64 {
+        self.gate
+    }
+
+    /// Sets the gate, clamped to [NoteGate::MIN_GATE]..=[NoteGate::MAX_GATE].
+    /// Notes that are already sounding keep the length they started with.
+    pub fn set_gate(&mut self, gate: f64) {
+        self.gate = if gate.is_finite() {
+            gate.clamp(Self::MIN_GATE, Self::MAX_GATE)
+        } else {
+            1.0
+        };
+    }
+
+    /// The sounding length of a note with the given written duration. It's
+    /// never zero, so every note-on has a note-off strictly after it.
+    pub fn gated_duration(&self, duration: MusicalTime) -> MusicalTime {
+        let units = (duration.total_units() as f64 * self.gate).round() as usize;
+        MusicalTime::new_with_units(units.max(1))
+    }
+
+    /// Sends a note-on now, and schedules its note-off according to the gate.
+    /// If the key is still sounding from an earlier note on the same channel,
+    /// that note is ended first.
+    pub fn note_on(
+        &mut self,
+        channel: MidiChannel,
+        key: u8,
+        velocity: u8,
+        start: MusicalTime,
+        duration: MusicalTime,
+        midi_fn: &mut dyn FnMut(MidiChannel, MidiMessage),
+    ) {
+        if let Some(index) = self
+            .pending
+            .iter()
+            .position(|p| p.channel == channel && p.key == key)
+        {
+            let retriggered = self.pending.remove(index);
+            midi_fn(retriggered.channel, Self::note_off_message(retriggered.key));
+        }
+        midi_fn(
+            channel,
+            MidiMessage::NoteOn {
+                key: key.into(),
+                vel: velocity.into(),
+            },
+        );
+        self.pending.push(PendingNoteOff {
+            channel,
+            key,
+            time: start + self.gated_duration(duration),
+        });
+    }
+
+    /// Sends every note-off that's due before the end of `range`, in time
+    /// order.
+    pub fn work(
+        &mut self,
+        range: &Range<MusicalTime>,
+        midi_fn: &mut dyn FnMut(MidiChannel, MidiMessage),
+    ) {
+        let mut due: Vec<PendingNoteOff> = Vec::default();
+        self.pending.retain(|p| {
+            if p.time < range.end {
+                due.push(p.clone());
+                false
+            } else {
+                true
+            }
+        });
+        due.sort_by_key(|p| p.time);
+        for p in due {
+            midi_fn(p.channel, Self::note_off_message(p.key));
+        }
+    }
+
+    /// Ends every sounding note immediately, as when the transport stops.
+    pub fn release_all(&mut self, midi_fn: &mut dyn FnMut(MidiChannel, MidiMessage)) {
+        for p in self.pending.drain(..) {
+            midi_fn(p.channel, Self::note_off_message(p.key));
+        }
+    }
+
+    /// Whether any note-offs are still to come.
+    pub fn is_sounding(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    fn note_off_message(key: u8) -> MidiMessage {
+        MidiMessage::NoteOff {
+            key: key.into(),
+            vel: 0.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANNEL: MidiChannel = MidiChannel(0);
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        On(u8, usize),
+        Off(u8, usize),
+    }
+
+    // Plays one note per beat on the given keys, each written as a full beat
+    // long, and returns the note events along with the beat unit they landed
+    // in.
+    fn play(gate: &mut NoteGate, keys: &[u8]) -> Vec<Event> {
+        let beat = MusicalTime::UNITS_IN_BEAT;
+        let mut events = Vec::default();
+        let step = beat / 4;
+        for units in (0..(keys.len() + 2) * beat).step_by(step) {
+            let range =
+                MusicalTime::new_with_units(units)..MusicalTime::new_with_units(units + step);
+            gate.work(&range, &mut |_, message| {
+                if let MidiMessage::NoteOff { key, .. } = message {
+                    events.push(Event::Off(key.as_int(), units));
+                }
+            });
+            if units % beat == 0 {
+                if let Some(key) = keys.get(units / beat) {
+                    gate.note_on(
+                        CHANNEL,
+                        *key,
+                        100,
+                        range.start,
+                        MusicalTime::new_with_beats(1),
+                        &mut |_, message| match message {
+                            MidiMessage::NoteOn { key, .. } => {
+                                events.push(Event::On(key.as_int(), units))
+                            }
+                            MidiMessage::NoteOff { key, .. } => {
+                                events.push(Event::Off(key.as_int(), units))
+                            }
+                            _ => panic!(),
+                        },
+                    );
+                }
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn staccato_shortens_notes() {
+        let beat = MusicalTime::UNITS_IN_BEAT;
+        let mut gate = NoteGate::new_with(0.5);
+        assert_eq!(
+            play(&mut gate, &[60, 62]),
+            vec![
+                Event::On(60, 0),
+                Event::Off(60, beat / 2),
+                Event::On(62, beat),
+                Event::Off(62, beat + beat / 2),
+            ]
+        );
+        assert!(!gate.is_sounding());
+    }
+
+    #[test]
+    fn legato_overlaps_and_handles_retriggers() {
+        let beat = MusicalTime::UNITS_IN_BEAT;
+        let mut gate = NoteGate::new_with(1.5);
+        let events = play(&mut gate, &[60, 62, 62]);
+        assert_eq!(
+            events,
+            vec![
+                Event::On(60, 0),
+                Event::On(62, beat),
+                Event::Off(60, beat + beat / 2),
+                // The second 62 starts before the first has ended, so the
+                // first one ends right then...
+                Event::Off(62, 2 * beat),
+                Event::On(62, 2 * beat),
+                // ...and only the second one's note-off remains.
+                Event::Off(62, 3 * beat + beat / 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn gate_is_clamped_and_never_zero() {
+        let mut gate = NoteGate::new_with(100.0);
+        assert_eq!(gate.gate(), NoteGate::MAX_GATE);
+        gate.set_gate(0.0);
+        assert_eq!(gate.gate(), NoteGate::MIN_GATE);
+        assert_eq!(
+            gate.gated_duration(MusicalTime::new_with_units(1)),
+            MusicalTime::new_with_units(1)
+        );
+    }
+}
@@ -0,0 +1,900 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use crate::{control::ParamDescriptor, panels::encode_midi_message};
+use eframe::egui::Ui;
+use ensnare_core::{
+    control::{ControlIndex, ControlValue},
+    midi::prelude::*,
+    prelude::*,
+    traits::{
+        Configurable, Controllable, Displays, Generates, HandlesMidi, Serializable, Ticks,
+        TransformsAudio,
+    },
+};
+use ensnare_proc_macros::{IsEffect, IsInstrument, Uid};
+use serde::{Deserialize, Serialize};
+
+/// A control input that a hosted plugin exposes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PluginParam {
+    /// The name that automation and remote control use, in the same
+    /// kebab-case that #[derive(Control)] uses. See [PluginParam::name_from()].
+    pub name: String,
+
+    /// The plugin's own index for the port.
+    pub port: usize,
+
+    #[allow(missing_docs)]
+    pub min: f32,
+    #[allow(missing_docs)]
+    pub max: f32,
+    #[allow(missing_docs)]
+    pub default: f32,
+}
+impl PluginParam {
+    /// Turns a plugin's label for a port, like "Filter Cutoff (Hz)", into a
+    /// param name, like "filter-cutoff-hz".
+    pub fn name_from(label: &str) -> String {
+        label
+            .to_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    // Controls are 0.0..=1.0 across the plugin's range, so that LFOs and
+    // other controllers drive plugin params the way they drive native ones.
+    fn to_plugin_value(&self, value: ControlValue) -> f32 {
+        self.min + (self.max - self.min) * value.0.clamp(0.0, 1.0) as f32
+    }
+
+    fn to_control_value(&self, value: f32) -> ControlValue {
+        if self.max > self.min {
+            ControlValue(((value - self.min) / (self.max - self.min)) as f64)
+        } else {
+            ControlValue(0.0)
+        }
+    }
+}
+
+/// What [HostedPlugin] and [HostedEffect] need from a plugin binding. With the
+/// `lv2` feature, [Lv2Plugin] implements it for LV2 plugins.
+pub trait PluginInstance: core::fmt::Debug + Send {
+    /// The plugin's control inputs.
+    fn params(&self) -> Vec<PluginParam>;
+
+    #[allow(missing_docs)]
+    fn audio_input_count(&self) -> usize;
+
+    #[allow(missing_docs)]
+    fn audio_output_count(&self) -> usize;
+
+    /// Sets a control input, in the plugin's own units.
+    fn set_param(&mut self, port: usize, value: f32);
+
+    /// Gets ready to run at a new sample rate. Plugins that can't change
+    /// rates on the fly are loaded again.
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) -> anyhow::Result<()>;
+
+    /// Renders `frames` frames. `midi` holds the wire bytes of each message
+    /// to deliver, with the frame it lands on. There's one buffer in `inputs`
+    /// for each audio input, and one in `outputs` for each audio output, each
+    /// at least `frames` long.
+    fn process(
+        &mut self,
+        frames: usize,
+        midi: &[(usize, Vec<u8>)],
+        inputs: &[Vec<f32>],
+        outputs: &mut [Vec<f32>],
+    ) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Default)]
+struct PluginHostEphemerals {
+    instance: Option<Box<dyn PluginInstance>>,
+    params: Vec<PluginParam>,
+    sample_rate: SampleRate,
+    /// Pending MIDI. Only the first `midi_len` entries are pending; the rest
+    /// are kept from earlier blocks so that their buffers can be reused.
+    midi: Vec<(usize, Vec<u8>)>,
+    midi_len: usize,
+    inputs: Vec<Vec<f32>>,
+    outputs: Vec<Vec<f32>>,
+    /// The first error since the plugin was attached. It's kept for the UI
+    /// rather than printed, because it usually happens on the audio thread.
+    error: Option<anyhow::Error>,
+}
+
+/// The part of [HostedPlugin] and [HostedEffect] that talks to the plugin.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PluginHost {
+    /// Identifies the plugin, e.g. its LV2 URI.
+    uri: String,
+
+    /// Each param's value, in the plugin's units, so that a project keeps
+    /// its settings.
+    values: Vec<(String, f32)>,
+
+    #[serde(skip)]
+    e: PluginHostEphemerals,
+}
+impl PluginHost {
+    /// Plugins get at most this many frames at a time.
+    const MAX_BLOCK: usize = 1024;
+
+    fn new_with(uri: &str, instance: Box<dyn PluginInstance>) -> Self {
+        let mut r = Self {
+            uri: uri.to_string(),
+            values: instance
+                .params()
+                .iter()
+                .map(|p| (p.name.clone(), p.default))
+                .collect(),
+            ..Default::default()
+        };
+        r.attach(instance);
+        r
+    }
+
+    // Starts using the instance, and gives it the saved param values.
+    fn attach(&mut self, mut instance: Box<dyn PluginInstance>) {
+        let params = instance.params();
+        for (name, value) in self.values.iter() {
+            if let Some(param) = params.iter().find(|p| &p.name == name) {
+                instance.set_param(param.port, *value);
+            }
+        }
+        self.e.inputs = vec![vec![0.0; Self::MAX_BLOCK]; instance.audio_input_count()];
+        self.e.outputs = vec![vec![0.0; Self::MAX_BLOCK]; instance.audio_output_count()];
+        self.e.params = params;
+        self.e.instance = Some(instance);
+        self.e.error = None;
+    }
+
+    fn report_error(&mut self, e: anyhow::Error) {
+        if self.e.error.is_none() {
+            self.e.error = Some(e);
+        }
+    }
+
+    fn set_value(&mut self, index: usize, value: ControlValue) {
+        let Some(param) = self.e.params.get(index) else {
+            return;
+        };
+        let value = param.to_plugin_value(value);
+        if let Some(instance) = self.e.instance.as_mut() {
+            instance.set_param(param.port, value);
+        }
+        if let Some((_, v)) = self.values.iter_mut().find(|(n, _)| n == &param.name) {
+            *v = value;
+        } else {
+            self.values.push((param.name.clone(), value));
+        }
+    }
+
+    fn value(&self, index: usize) -> Option<ControlValue> {
+        let param = self.e.params.get(index)?;
+        let (_, value) = self.values.iter().find(|(n, _)| n == &param.name)?;
+        Some(param.to_control_value(*value))
+    }
+
+    fn descriptors(&self) -> Vec<ParamDescriptor> {
+        self.e
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, p)| ParamDescriptor {
+                name: p.name.clone(),
+                index: ControlIndex(i),
+                min: 0.0,
+                max: 1.0,
+                default: p.to_control_value(p.default).0,
+                unit: Default::default(),
+            })
+            .collect()
+    }
+
+    fn queue_midi(&mut self, frame: usize, channel: MidiChannel, message: &MidiMessage) {
+        if self.e.midi_len == self.e.midi.len() {
+            self.e.midi.push((frame, Vec::with_capacity(3)));
+        }
+        let (pending_frame, bytes) = &mut self.e.midi[self.e.midi_len];
+        *pending_frame = frame;
+        bytes.clear();
+        encode_midi_message(channel, message, bytes);
+        self.e.midi_len += 1;
+    }
+
+    // Runs the plugin for `frames` frames (no more than MAX_BLOCK), with
+    // whatever is in the input buffers, and delivers pending MIDI at the
+    // start. Leaves the result in the output buffers, or silence if there's
+    // no plugin or it failed.
+    fn process(&mut self, frames: usize) {
+        let midi = &self.e.midi[..self.e.midi_len];
+        let result = match self.e.instance.as_mut() {
+            Some(instance) => instance.process(frames, midi, &self.e.inputs, &mut self.e.outputs),
+            None => Ok(()),
+        };
+        self.e.midi_len = 0;
+        if let Err(e) = result {
+            self.report_error(e);
+            for output in self.e.outputs.iter_mut() {
+                output[..frames].fill(0.0);
+            }
+        }
+    }
+
+    // The i-th frame of the last process(). A mono plugin plays in both
+    // channels.
+    fn output_frame(&self, i: usize) -> StereoSample {
+        match self.e.outputs.as_slice() {
+            [] => StereoSample::SILENCE,
+            [mono] => StereoSample::from(mono[i] as f64),
+            [left, right, ..] => StereoSample(Sample(left[i] as f64), Sample(right[i] as f64)),
+        }
+    }
+
+    // Fills the i-th frame of the input buffers. A mono plugin hears both
+    // channels mixed.
+    fn set_input_frame(&mut self, i: usize, sample: StereoSample) {
+        match self.e.inputs.as_mut_slice() {
+            [] => {}
+            [mono] => mono[i] = ((sample.0 .0 + sample.1 .0) * 0.5) as f32,
+            [left, right, ..] => {
+                left[i] = sample.0 .0 as f32;
+                right[i] = sample.1 .0 as f32;
+            }
+        }
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.e.sample_rate = sample_rate;
+        if let Some(mut instance) = self.e.instance.take() {
+            match instance.update_sample_rate(sample_rate) {
+                // Reattach to restore the params, in case it was reloaded.
+                Ok(()) => self.attach(instance),
+                Err(e) => self.report_error(e),
+            }
+        } else {
+            // Just deserialized, so there's nothing loaded yet.
+            #[cfg(feature = "lv2")]
+            match Lv2Plugin::load(&self.uri, sample_rate) {
+                Ok(instance) => self.attach(Box::new(instance)),
+                Err(e) => self.report_error(e),
+            }
+        }
+    }
+
+    fn ui_label(&self, kind: &str) -> String {
+        if let Some(e) = self.e.error.as_ref() {
+            format!("{kind}: {} (failed: {e})", self.uri)
+        } else if self.e.instance.is_some() {
+            format!("{kind}: {}", self.uri)
+        } else {
+            format!("{kind}: {} (not loaded)", self.uri)
+        }
+    }
+}
+
+/// Implements [Controllable] for an entity with a `host` field, by exposing
+/// the hosted plugin's control inputs.
+macro_rules! impl_controllable_for_plugin {
+    ($name:ty) => {
+        impl Controllable for $name {
+            fn control_index_count(&self) -> usize {
+                self.host.e.params.len()
+            }
+
+            fn control_set_param_by_name(&mut self, name: &str, value: ControlValue) {
+                if let Some(index) = self.control_index_for_name(name) {
+                    self.control_set_param_by_index(index, value);
+                } else {
+                    eprintln!("Warning: couldn't set param named '{}'", name);
+                }
+            }
+
+            fn control_name_for_index(&self, index: ControlIndex) -> Option<String> {
+                self.host.e.params.get(index.0).map(|p| p.name.clone())
+            }
+
+            fn control_index_for_name(&self, name: &str) -> Option<ControlIndex> {
+                self.host
+                    .e
+                    .params
+                    .iter()
+                    .position(|p| p.name == name)
+                    .map(ControlIndex)
+            }
+
+            fn control_set_param_by_index(&mut self, index: ControlIndex, value: ControlValue) {
+                self.host.set_value(index.0, value);
+            }
+        }
+    };
+}
+
+/// [HostedPlugin] plays a third-party instrument plugin, such as an LV2
+/// synth, as if it were a native instrument. MIDI goes to the plugin, its
+/// audio outputs become the instrument's output, and its control inputs
+/// become #[control]-style params that can be automated, linked, and set by
+/// name like any other.
+///
+/// Only the URI and param values are saved. The plugin is loaded again when
+/// the sample rate is set, which happens when the entity joins a project.
+/// Without the `lv2` feature, or if the plugin can't be found, it's silent.
+#[derive(Debug, Default, IsInstrument, Uid, Serialize, Deserialize)]
+pub struct HostedPlugin {
+    uid: Uid,
+
+    #[serde(flatten)]
+    host: PluginHost,
+
+    #[serde(skip)]
+    value: StereoSample,
+}
+impl HostedPlugin {
+    /// Loads the LV2 plugin with the given URI.
+    #[cfg(feature = "lv2")]
+    pub fn new_lv2(uri: &str, sample_rate: SampleRate) -> anyhow::Result<Self> {
+        let mut r = Self::new_with_instance(uri, Box::new(Lv2Plugin::load(uri, sample_rate)?));
+        r.host.e.sample_rate = sample_rate;
+        Ok(r)
+    }
+
+    /// Wraps an instance that has already been loaded.
+    pub fn new_with_instance(uri: &str, instance: Box<dyn PluginInstance>) -> Self {
+        Self {
+            uid: Default::default(),
+            host: PluginHost::new_with(uri, instance),
+            value: Default::default(),
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn uri(&self) -> &str {
+        &self.host.uri
+    }
+
+    #[allow(missing_docs)]
+    pub fn is_loaded(&self) -> bool {
+        self.host.e.instance.is_some()
+    }
+
+    /// The first error that the plugin reported since it was loaded, if any.
+    pub fn error(&self) -> Option<&anyhow::Error> {
+        self.host.e.error.as_ref()
+    }
+
+    /// The current value of each param, as its control would set it.
+    pub fn control_value(&self, index: ControlIndex) -> Option<ControlValue> {
+        self.host.value(index.0)
+    }
+
+    /// Describes the plugin's params, for [ControlEditorPanel](crate::panels::ControlEditorPanel).
+    pub fn control_param_descriptors(&self) -> Vec<ParamDescriptor> {
+        self.host.descriptors()
+    }
+}
+impl Generates<StereoSample> for HostedPlugin {
+    fn value(&self) -> StereoSample {
+        self.value
+    }
+
+    fn generate_batch_values(&mut self, values: &mut [StereoSample]) {
+        for chunk in values.chunks_mut(PluginHost::MAX_BLOCK) {
+            self.host.process(chunk.len());
+            for (i, value) in chunk.iter_mut().enumerate() {
+                *value = self.host.output_frame(i);
+            }
+        }
+        if let Some(last) = values.last() {
+            self.value = *last;
+        }
+    }
+}
+impl Ticks for HostedPlugin {
+    fn tick(&mut self, tick_count: usize) {
+        let mut remaining = tick_count;
+        while remaining > 0 {
+            let frames = remaining.min(PluginHost::MAX_BLOCK);
+            self.host.process(frames);
+            self.value = self.host.output_frame(frames - 1);
+            remaining -= frames;
+        }
+    }
+}
+impl HandlesMidi for HostedPlugin {
+    fn handle_midi_message(
+        &mut self,
+        channel: MidiChannel,
+        message: MidiMessage,
+        _midi_messages_fn: &mut MidiMessagesFn,
+    ) {
+        self.host.queue_midi(0, channel, &message);
+    }
+}
+impl_controllable_for_plugin!(HostedPlugin);
+impl Configurable for HostedPlugin {
+    fn sample_rate(&self) -> SampleRate {
+        self.host.e.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.host.update_sample_rate(sample_rate);
+    }
+}
+impl Serializable for HostedPlugin {}
+impl Displays for HostedPlugin {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        ui.label(self.host.ui_label("Plugin"))
+    }
+}
+
+/// [HostedEffect] is [HostedPlugin] for effect plugins: the entity's input
+/// goes to the plugin's audio inputs.
+///
+/// Effects process one frame at a time, so an effect plugin runs with a
+/// block size of one. That costs more than running it in blocks, but it adds
+/// no latency.
+#[derive(Debug, Default, IsEffect, Uid, Serialize, Deserialize)]
+pub struct HostedEffect {
+    uid: Uid,
+
+    #[serde(flatten)]
+    host: PluginHost,
+
+    /// The frame that transform_channel(0, ...) rendered, which the other
+    /// channel takes its half from.
+    #[serde(skip)]
+    channel_frame: Option<StereoSample>,
+}
+impl HostedEffect {
+    /// Loads the LV2 plugin with the given URI.
+    #[cfg(feature = "lv2")]
+    pub fn new_lv2(uri: &str, sample_rate: SampleRate) -> anyhow::Result<Self> {
+        let mut r = Self::new_with_instance(uri, Box::new(Lv2Plugin::load(uri, sample_rate)?));
+        r.host.e.sample_rate = sample_rate;
+        Ok(r)
+    }
+
+    /// Wraps an instance that has already been loaded.
+    pub fn new_with_instance(uri: &str, instance: Box<dyn PluginInstance>) -> Self {
+        Self {
+            uid: Default::default(),
+            host: PluginHost::new_with(uri, instance),
+            channel_frame: None,
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn uri(&self) -> &str {
+        &self.host.uri
+    }
+
+    #[allow(missing_docs)]
+    pub fn is_loaded(&self) -> bool {
+        self.host.e.instance.is_some()
+    }
+
+    /// The first error that the plugin reported since it was loaded, if any.
+    pub fn error(&self) -> Option<&anyhow::Error> {
+        self.host.e.error.as_ref()
+    }
+
+    /// Describes the plugin's params, for [ControlEditorPanel](crate::panels::ControlEditorPanel).
+    pub fn control_param_descriptors(&self) -> Vec<ParamDescriptor> {
+        self.host.descriptors()
+    }
+}
+impl TransformsAudio for HostedEffect {
+    fn transform_audio(&mut self, input_sample: StereoSample) -> StereoSample {
+        self.channel_frame = None;
+        if !self.is_loaded() {
+            return input_sample;
+        }
+        self.host.set_input_frame(0, input_sample);
+        self.host.process(1);
+        self.host.output_frame(0)
+    }
+
+    // The plugin runs once per frame even when it's asked for a channel at a
+    // time: channel 0 renders the frame, and channel 1 returns the other half
+    // of it. The plugin hears channel 0's input in both of its inputs.
+    fn transform_channel(&mut self, channel: usize, input_sample: Sample) -> Sample {
+        let frame = match (channel, self.channel_frame.take()) {
+            (0, _) | (_, None) => {
+                let frame = self.transform_audio(StereoSample(input_sample, input_sample));
+                if channel == 0 {
+                    self.channel_frame = Some(frame);
+                }
+                frame
+            }
+            (_, Some(frame)) => frame,
+        };
+        if channel == 0 {
+            frame.0
+        } else {
+            frame.1
+        }
+    }
+}
+impl_controllable_for_plugin!(HostedEffect);
+impl Configurable for HostedEffect {
+    fn sample_rate(&self) -> SampleRate {
+        self.host.e.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.host.update_sample_rate(sample_rate);
+    }
+}
+impl Serializable for HostedEffect {}
+impl Displays for HostedEffect {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        ui.label(self.host.ui_label("Plugin effect"))
+    }
+}
+
+#[cfg(feature = "lv2")]
+pub use lv2::Lv2Plugin;
+
+#[cfg(feature = "lv2")]
+mod lv2 {
+    use super::{PluginHost, PluginInstance, PluginParam};
+    use anyhow::anyhow;
+    use ensnare_core::prelude::*;
+    use std::sync::Arc;
+
+    /// An LV2 plugin instance, through the [livi](https://crates.io/crates/livi)
+    /// host library.
+    pub struct Lv2Plugin {
+        uri: String,
+        instance: livi::Instance,
+        features: Arc<livi::Features>,
+        params: Vec<PluginParam>,
+        audio_input_count: usize,
+        audio_output_count: usize,
+        has_midi_input: bool,
+        midi: livi::event::LV2AtomSequence,
+    }
+    impl core::fmt::Debug for Lv2Plugin {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.debug_struct("Lv2Plugin")
+                .field("uri", &self.uri)
+                .field("params", &self.params)
+                .field("audio_input_count", &self.audio_input_count)
+                .field("audio_output_count", &self.audio_output_count)
+                .finish()
+        }
+    }
+    impl Lv2Plugin {
+        /// Room for this many bytes of MIDI per block.
+        const MIDI_CAPACITY: usize = 4096;
+
+        /// Finds the installed plugin with the given URI and instantiates it.
+        pub fn load(uri: &str, sample_rate: SampleRate) -> anyhow::Result<Self> {
+            let world = livi::World::new();
+            let plugin = world
+                .plugin_by_uri(uri)
+                .ok_or_else(|| anyhow!("couldn't find LV2 plugin {uri}"))?;
+            let features = world.build_features(livi::FeaturesBuilder {
+                min_block_length: 1,
+                max_block_length: PluginHost::MAX_BLOCK,
+            });
+            // SAFETY: livi requires that the plugin's library be trusted.
+            // It's one the user installed and asked for.
+            let instance = unsafe { plugin.instantiate(features.clone(), sample_rate.0 as f64) }
+                .map_err(|e| anyhow!("couldn't instantiate LV2 plugin {uri}: {e:?}"))?;
+            let params = plugin
+                .ports_with_type(livi::PortType::ControlInput)
+                .map(|port| PluginParam {
+                    name: PluginParam::name_from(&port.name),
+                    port: port.index.0,
+                    min: port.min_value.unwrap_or(0.0),
+                    max: port.max_value.unwrap_or(1.0),
+                    default: port.default_value,
+                })
+                .collect();
+            let counts = plugin.port_counts();
+            Ok(Self {
+                uri: uri.to_string(),
+                instance,
+                midi: livi::event::LV2AtomSequence::new(&features, Self::MIDI_CAPACITY),
+                features,
+                params,
+                audio_input_count: counts.audio_inputs,
+                audio_output_count: counts.audio_outputs,
+                has_midi_input: counts.atom_sequence_inputs > 0,
+            })
+        }
+    }
+    // SAFETY: an LV2 instance may be used from any one thread at a time, and
+    // the entity that owns it is only ever used from one thread at a time.
+    unsafe impl Send for Lv2Plugin {}
+    impl PluginInstance for Lv2Plugin {
+        fn params(&self) -> Vec<PluginParam> {
+            self.params.clone()
+        }
+
+        fn audio_input_count(&self) -> usize {
+            self.audio_input_count
+        }
+
+        fn audio_output_count(&self) -> usize {
+            self.audio_output_count
+        }
+
+        fn set_param(&mut self, port: usize, value: f32) {
+            let _ = self
+                .instance
+                .set_control_input(livi::PortIndex(port), value);
+        }
+
+        // LV2 instances are made for one sample rate.
+        fn update_sample_rate(&mut self, sample_rate: SampleRate) -> anyhow::Result<()> {
+            *self = Self::load(&self.uri, sample_rate)?;
+            Ok(())
+        }
+
+        fn process(
+            &mut self,
+            frames: usize,
+            midi: &[(usize, Vec<u8>)],
+            inputs: &[Vec<f32>],
+            outputs: &mut [Vec<f32>],
+        ) -> anyhow::Result<()> {
+            self.midi.clear();
+            for (frame, bytes) in midi {
+                self.midi
+                    .push_midi_event::<3>(*frame as i64, self.features.midi_urid(), bytes)
+                    .map_err(|e| anyhow!("couldn't queue MIDI: {e:?}"))?;
+            }
+            let ports = livi::EmptyPortConnections::new()
+                .with_atom_sequence_inputs(
+                    std::iter::once(&self.midi).take(self.has_midi_input as usize),
+                )
+                .with_audio_inputs(inputs.iter().map(|input| &input[..frames]))
+                .with_audio_outputs(outputs.iter_mut().map(|output| &mut output[..frames]));
+            // SAFETY: every port the plugin has is connected to a buffer at
+            // least `frames` long, and `frames` is within the block length
+            // that the features allow.
+            unsafe { self.instance.run(frames, ports) }
+                .map_err(|e| anyhow!("LV2 run failed: {e:?}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mini::{assert_audible_and_bounded, render_entity_for, EntityUnderTest};
+
+    /// Plays a constant at the "level" param while any key is held, on one
+    /// output. Or, as an effect, scales its two inputs by "level".
+    #[derive(Debug)]
+    struct FakePlugin {
+        is_effect: bool,
+        level: f32,
+        held: usize,
+        fails: bool,
+    }
+    impl FakePlugin {
+        const LEVEL_PORT: usize = 3;
+    }
+    impl PluginInstance for FakePlugin {
+        fn params(&self) -> Vec<PluginParam> {
+            vec![PluginParam {
+                name: PluginParam::name_from("Output Level (dB)"),
+                port: Self::LEVEL_PORT,
+                min: 0.0,
+                max: 2.0,
+                default: 0.5,
+            }]
+        }
+
+        fn audio_input_count(&self) -> usize {
+            if self.is_effect {
+                2
+            } else {
+                0
+            }
+        }
+
+        fn audio_output_count(&self) -> usize {
+            if self.is_effect {
+                2
+            } else {
+                1
+            }
+        }
+
+        fn set_param(&mut self, port: usize, value: f32) {
+            assert_eq!(port, Self::LEVEL_PORT);
+            self.level = value;
+        }
+
+        fn update_sample_rate(&mut self, _sample_rate: SampleRate) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn process(
+            &mut self,
+            frames: usize,
+            midi: &[(usize, Vec<u8>)],
+            inputs: &[Vec<f32>],
+            outputs: &mut [Vec<f32>],
+        ) -> anyhow::Result<()> {
+            if self.fails {
+                return Err(anyhow::anyhow!("fake failure"));
+            }
+            for (_, bytes) in midi {
+                match (bytes[0] & 0xf0, bytes.get(2)) {
+                    (0x90, Some(vel)) if *vel != 0 => self.held += 1,
+                    (0x80, _) | (0x90, _) => self.held = self.held.saturating_sub(1),
+                    _ => {}
+                }
+            }
+            if self.is_effect {
+                for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+                    for i in 0..frames {
+                        output[i] = input[i] * self.level;
+                    }
+                }
+            } else {
+                let value = if self.held > 0 { self.level } else { 0.0 };
+                outputs[0][..frames].fill(value);
+            }
+            Ok(())
+        }
+    }
+
+    fn fake(is_effect: bool) -> Box<dyn PluginInstance> {
+        Box::new(FakePlugin {
+            is_effect,
+            level: 0.0,
+            held: 0,
+            fails: false,
+        })
+    }
+
+    #[test]
+    fn plugin_params_are_controls() {
+        assert_eq!(
+            PluginParam::name_from("Output Level (dB)"),
+            "output-level-db"
+        );
+
+        let mut plugin = HostedPlugin::new_with_instance("urn:fake", fake(false));
+        assert_eq!(plugin.control_index_count(), 1);
+        assert_eq!(
+            plugin.control_name_for_index(ControlIndex(0)),
+            Some("output-level-db".to_string())
+        );
+        assert_eq!(
+            plugin.control_index_for_name("output-level-db"),
+            Some(ControlIndex(0))
+        );
+        assert_eq!(plugin.control_index_for_name("cutoff"), None);
+
+        // The plugin's default was applied when it loaded.
+        assert_eq!(
+            plugin.control_value(ControlIndex(0)).map(|v| v.0),
+            Some(0.25)
+        );
+        let descriptors = plugin.control_param_descriptors();
+        assert_eq!(descriptors.len(), 1);
+        assert_eq!((descriptors[0].min, descriptors[0].max), (0.0, 1.0));
+        assert_eq!(descriptors[0].default, 0.25);
+        assert_eq!(descriptors[0].index, ControlIndex(0));
+
+        // Controls span the plugin's range.
+        plugin.control_set_param_by_name("output-level-db", ControlValue(0.5));
+        plugin.handle_midi_message(
+            MidiChannel(0),
+            MidiMessage::NoteOn {
+                key: 60.into(),
+                vel: 127.into(),
+            },
+            &mut |_, _| {},
+        );
+        plugin.tick(1);
+        assert_eq!(plugin.value(), StereoSample::from(1.0));
+    }
+
+    #[test]
+    fn hosted_instrument_plays_midi() {
+        let mut plugin = HostedPlugin::new_with_instance("urn:fake", fake(false));
+        let samples = render_entity_for(
+            EntityUnderTest::Instrument(&mut plugin, Some(60)),
+            0.1,
+            SampleRate(4000),
+        );
+        assert_audible_and_bounded(&samples);
+        assert!(
+            samples.iter().all(|s| *s == StereoSample::from(0.5)),
+            "mono output should fill both channels"
+        );
+
+        plugin.handle_midi_message(
+            MidiChannel(0),
+            MidiMessage::NoteOff {
+                key: 60.into(),
+                vel: 0.into(),
+            },
+            &mut |_, _| {},
+        );
+        let mut samples = [StereoSample::from(1.0); 4];
+        plugin.generate_batch_values(&mut samples);
+        assert!(samples.iter().all(|s| *s == StereoSample::SILENCE));
+    }
+
+    #[test]
+    fn midi_buffers_are_reused() {
+        let mut plugin = HostedPlugin::new_with_instance("urn:fake", fake(false));
+        for key in 60..64 {
+            plugin.handle_midi_message(
+                MidiChannel(0),
+                MidiMessage::NoteOn {
+                    key: key.into(),
+                    vel: 127.into(),
+                },
+                &mut |_, _| {},
+            );
+            plugin.tick(1);
+        }
+        assert_eq!(plugin.host.e.midi.len(), 1, "one entry, reused each block");
+        assert_eq!(plugin.host.e.midi_len, 0, "nothing left pending");
+        assert_eq!(plugin.value(), StereoSample::from(0.5));
+    }
+
+    #[test]
+    fn failures_are_kept_for_the_ui() {
+        let mut plugin = HostedPlugin::new_with_instance(
+            "urn:fake",
+            Box::new(FakePlugin {
+                is_effect: false,
+                level: 1.0,
+                held: 0,
+                fails: true,
+            }),
+        );
+        assert!(plugin.error().is_none());
+        let mut samples = [StereoSample::from(1.0); 4];
+        plugin.generate_batch_values(&mut samples);
+        assert!(samples.iter().all(|s| *s == StereoSample::SILENCE));
+        assert_eq!(
+            plugin.error().map(|e| e.to_string()),
+            Some("fake failure".to_string())
+        );
+        assert!(plugin.host.ui_label("Plugin").contains("failed"));
+    }
+
+    #[test]
+    fn hosted_effect_transforms_audio() {
+        let mut effect = HostedEffect::new_with_instance("urn:fake", fake(true));
+        effect.control_set_param_by_index(ControlIndex(0), ControlValue(1.0));
+        let output = effect.transform_audio(StereoSample(Sample(0.25), Sample(-0.25)));
+        assert_eq!(output, StereoSample(Sample(0.5), Sample(-0.5)));
+
+        // With nothing loaded, the input passes through.
+        let mut effect = HostedEffect::default();
+        let input = StereoSample(Sample(0.25), Sample(-0.25));
+        assert_eq!(effect.transform_audio(input), input);
+    }
+
+    #[test]
+    fn hosted_effect_runs_once_per_frame_by_channel() {
+        let mut effect = HostedEffect::new_with_instance("urn:fake", fake(true));
+        effect.control_set_param_by_index(ControlIndex(0), ControlValue(0.5));
+        assert_eq!(effect.transform_channel(0, Sample(0.25)), Sample(0.25));
+
+        // If channel 1 ran the plugin again, it would hear the new level.
+        effect.control_set_param_by_index(ControlIndex(0), ControlValue(1.0));
+        assert_eq!(effect.transform_channel(1, Sample(0.25)), Sample(0.25));
+
+        // The next frame does.
+        assert_eq!(effect.transform_channel(0, Sample(0.25)), Sample(0.5));
+        assert_eq!(effect.transform_channel(1, Sample(0.25)), Sample(0.5));
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use super::rng::{Rng, SeedBank, Seeded};
+use ensnare_core::prelude::*;
+
+/// [Humanizer] is the opposite of quantization: it nudges note start times and
+/// velocities by small random amounts so that a programmed pattern sounds less
+/// mechanical. It's seeded, so the same seed always produces the same
+/// variations, and renders stay reproducible.
+///
+/// There's deliberately no `Pattern::humanize()`. A `Pattern<Note>` is a grid:
+/// each note's start is its position in the grid, so there's nowhere to store a
+/// note that starts a little early or late, and a humanized pattern would snap
+/// right back to the grid when it was programmed into a sequencer. Instead,
+/// sequencers humanize as they play, asking [Humanizer::for_event()] for each
+/// step so that a step always comes out the same way, even after a seek. See
+/// [DrumSequencer::set_humanize()](super::DrumSequencer::set_humanize).
+#[derive(Debug)]
+pub struct Humanizer {
+    rng: Rng,
+    seed: u64,
+
+    /// The most that a note's start time may move, in either direction.
+    timing_amount: MusicalTime,
+
+    /// The most that a note's velocity may change, in either direction.
+    velocity_amount: u8,
+}
+impl Humanizer {
+    #[allow(missing_docs)]
+    pub fn new_with(seed: u64, timing_amount: MusicalTime, velocity_amount: u8) -> Self {
+        Self {
+            rng: Rng::new_with_seed(seed as u128),
+            seed,
+            timing_amount,
+            velocity_amount,
+        }
+    }
+
+    /// Returns a [Humanizer] with the same amounts whose sequence depends only
+    /// on this one's seed and `index`, not on how many values this one has
+    /// already produced. A sequencer that may visit the same event more than
+    /// once, or visit events out of order, uses one per event.
+    pub fn for_event(&self, index: u64) -> Self {
+        Self::new_with(
+            SeedBank::mix(self.seed ^ SeedBank::mix(index)),
+            self.timing_amount,
+            self.velocity_amount,
+        )
+    }
+
+    /// Returns a randomly offset version of `start`. The result is never
+    /// earlier than `pattern_start`.
+    pub fn humanize_start(
+        &mut self,
+        start: MusicalTime,
+        pattern_start: MusicalTime,
+    ) -> MusicalTime {
+        let offset = self.random_offset(self.timing_amount.total_units() as i64);
+        let units = (start.total_units() as i64 + offset).max(pattern_start.total_units() as i64);
+        MusicalTime::new_with_units(units as usize)
+    }
+
+    /// Returns a randomly adjusted version of `velocity`, clamped to 1..=127
+    /// so that a note-on never turns into a note-off.
+    pub fn humanize_velocity(&mut self, velocity: u8) -> u8 {
+        let offset = self.random_offset(self.velocity_amount as i64);
+        (velocity as i64 + offset).clamp(1, 127) as u8
+    }
+
+    // Returns a value in -amount..=amount.
+    fn random_offset(&mut self, amount: i64) -> i64 {
+        if amount <= 0 {
+            return 0;
+        }
+        self.rng.0.rand_range(0..(amount as u64 * 2 + 1)) as i64 - amount
+    }
+}
+impl Seeded for Humanizer {
+    fn reseed(&mut self, seed: u64) {
+        self.rng = Rng::new_with_seed(seed as u128);
+        self.seed = seed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn humanize_is_deterministic_and_bounded() {
+        let timing_amount = MusicalTime::new_with_units(MusicalTime::UNITS_IN_BEAT / 32);
+        let start = MusicalTime::new_with_beats(1);
+        let pattern_start = MusicalTime::default();
+
+        let mut h1 = Humanizer::new_with(42, timing_amount, 10);
+        let mut h2 = Humanizer::new_with(42, timing_amount, 10);
+        let mut saw_change = false;
+        for _ in 0..100 {
+            let t1 = h1.humanize_start(start, pattern_start);
+            let t2 = h2.humanize_start(start, pattern_start);
+            assert_eq!(t1, t2, "the same seed should produce the same offsets");
+            assert!(t1 >= start - timing_amount && t1 <= start + timing_amount);
+
+            let v1 = h1.humanize_velocity(100);
+            let v2 = h2.humanize_velocity(100);
+            assert_eq!(v1, v2);
+            assert!((90..=110).contains(&v1));
+            if t1 != start || v1 != 100 {
+                saw_change = true;
+            }
+        }
+        assert!(saw_change, "humanizing should change something");
+    }
+
+    #[test]
+    fn humanize_respects_edges() {
+        let mut h = Humanizer::new_with(
+            1,
+            MusicalTime::new_with_units(MusicalTime::UNITS_IN_BEAT),
+            64,
+        );
+        let pattern_start = MusicalTime::new_with_beats(4);
+        for _ in 0..100 {
+            assert!(h.humanize_start(pattern_start, pattern_start) >= pattern_start);
+            assert!((1..=127).contains(&h.humanize_velocity(1)));
+            assert!((1..=127).contains(&h.humanize_velocity(127)));
+        }
+    }
+
+    #[test]
+    fn reseeding_restarts_the_sequence() {
+        let timing_amount = MusicalTime::new_with_units(MusicalTime::UNITS_IN_BEAT / 32);
+        let mut h = Humanizer::new_with(1, timing_amount, 10);
+        let first: Vec<u8> = (0..16).map(|_| h.humanize_velocity(100)).collect();
+        h.reseed(1);
+        let second: Vec<u8> = (0..16).map(|_| h.humanize_velocity(100)).collect();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn events_vary_independently_of_order() {
+        let h = Humanizer::new_with(
+            5,
+            MusicalTime::new_with_units(MusicalTime::UNITS_IN_BEAT / 8),
+            20,
+        );
+        let forward: Vec<u8> = (0..16)
+            .map(|i| h.for_event(i).humanize_velocity(100))
+            .collect();
+        let backward: Vec<u8> = (0..16)
+            .rev()
+            .map(|i| h.for_event(i).humanize_velocity(100))
+            .collect();
+        assert_eq!(forward, backward.into_iter().rev().collect::<Vec<_>>());
+        assert!(
+            forward.iter().any(|v| *v != forward[0]),
+            "different events should vary differently"
+        );
+    }
+
+    #[test]
+    fn humanize_with_zero_amounts_changes_nothing() {
+        let mut h = Humanizer::new_with(7, MusicalTime::default(), 0);
+        let start = MusicalTime::new_with_beats(2);
+        assert_eq!(h.humanize_start(start, MusicalTime::default()), start);
+        assert_eq!(h.humanize_velocity(64), 64);
+    }
+}
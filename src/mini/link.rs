@@ -0,0 +1,367 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use super::LaunchQuantum;
+use ensnare_core::prelude::*;
+use std::time::Duration;
+
+/// What an Ableton Link session says about the shared timeline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkSnapshot {
+    #[allow(missing_docs)]
+    pub tempo: Tempo,
+
+    /// Link's beat count at the moment the local position was taken. Only its
+    /// phase within the quantum is shared with peers; each peer counts beats
+    /// from its own origin.
+    pub beat: f64,
+
+    /// Whether the session is playing, if peers share start and stop.
+    pub is_playing: bool,
+}
+
+/// The local transport, as [LinkSync] needs to know it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransportSnapshot {
+    #[allow(missing_docs)]
+    pub tempo: Tempo,
+
+    #[allow(missing_docs)]
+    pub position: MusicalTime,
+
+    #[allow(missing_docs)]
+    pub is_performing: bool,
+}
+
+/// What the local transport should do to stay with the Link session.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkAction {
+    /// A peer changed the tempo.
+    SetTempo(Tempo),
+
+    /// The session started playing. Start from this position, which puts the
+    /// local bar lines where the session's are.
+    Start(MusicalTime),
+
+    /// The session stopped.
+    Stop,
+
+    /// The local position has drifted a little. Speed up or slow down by
+    /// `percent` for `duration`, as with a tempo nudge, to catch up without
+    /// an audible jump.
+    Nudge {
+        #[allow(missing_docs)]
+        percent: f64,
+        #[allow(missing_docs)]
+        duration: Duration,
+    },
+
+    /// The local position is too far off to nudge back. Jump here.
+    Seek(MusicalTime),
+}
+
+/// Keeps a transport in step with an Ableton Link session. Link peers agree
+/// on the tempo and on the phase within a quantum, usually a bar, so that
+/// everyone's downbeats land together even if they started at different
+/// times. [LinkSync] compares a snapshot of the session with a snapshot of
+/// the local transport and says what the transport should change.
+///
+/// The quantum is a [LaunchQuantum], the same grid that pattern launches
+/// snap to, so a pattern queued for the next bar launches on the session's
+/// next bar.
+///
+/// Small phase errors are corrected with a nudge rather than a seek, because
+/// seeking silences whatever is playing.
+#[derive(Clone, Debug, Default)]
+pub struct LinkSync {
+    quantum: LaunchQuantum,
+    time_signature: TimeSignature,
+}
+impl LinkSync {
+    /// Tempos closer than this, in BPM, count as the same.
+    pub const TEMPO_TOLERANCE: f64 = 0.005;
+
+    /// Phase errors smaller than this, in beats, are left alone. That's about
+    /// 2 milliseconds at 120 BPM.
+    pub const DEADBAND_BEATS: f64 = 0.004;
+
+    /// Phase errors larger than this, in beats, are fixed with a seek.
+    pub const SEEK_THRESHOLD_BEATS: f64 = 0.25;
+
+    /// How long a correcting nudge takes to make up the error.
+    pub const CORRECTION_DURATION: Duration = Duration::from_millis(500);
+
+    /// The largest correcting nudge, in percent.
+    pub const MAX_CORRECTION_PERCENT: f64 = 5.0;
+
+    #[allow(missing_docs)]
+    pub fn new_with(quantum: LaunchQuantum, time_signature: TimeSignature) -> Self {
+        Self {
+            quantum,
+            time_signature,
+        }
+    }
+
+    /// The quantum in beats, as Link wants it.
+    pub fn quantum_beats(&self) -> f64 {
+        self.quantum.beats(&self.time_signature) as f64
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_quantum(&mut self, quantum: LaunchQuantum) {
+        self.quantum = quantum;
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+    }
+
+    /// Compares the two and returns what the local transport should do, in
+    /// order.
+    pub fn update(&self, link: &LinkSnapshot, local: &TransportSnapshot) -> Vec<LinkAction> {
+        let mut actions = Vec::default();
+        if (link.tempo.0 - local.tempo.0).abs() > Self::TEMPO_TOLERANCE {
+            actions.push(LinkAction::SetTempo(link.tempo));
+        }
+        match (link.is_playing, local.is_performing) {
+            (true, false) => {
+                let phase = link.beat.rem_euclid(self.quantum_beats());
+                actions.push(LinkAction::Start(Self::beats_to_time(phase)));
+            }
+            (false, true) => actions.push(LinkAction::Stop),
+            (true, true) => {
+                if let Some(action) = self.correct_phase(link, local) {
+                    actions.push(action);
+                }
+            }
+            (false, false) => {}
+        }
+        actions
+    }
+
+    fn correct_phase(&self, link: &LinkSnapshot, local: &TransportSnapshot) -> Option<LinkAction> {
+        let quantum = self.quantum_beats();
+        let local_beat = Self::time_to_beats(local.position);
+        let error = (link.beat - local_beat).rem_euclid(quantum);
+        // Whichever way is shorter.
+        let error = if error > quantum / 2.0 {
+            error - quantum
+        } else {
+            error
+        };
+        if error.abs() <= Self::DEADBAND_BEATS {
+            None
+        } else if error.abs() > Self::SEEK_THRESHOLD_BEATS {
+            let mut target = local_beat + error;
+            if target < 0.0 {
+                target += quantum;
+            }
+            Some(LinkAction::Seek(Self::beats_to_time(target)))
+        } else {
+            let seconds = Self::CORRECTION_DURATION.as_secs_f64();
+            let percent = 100.0 * error * 60.0 / (seconds * link.tempo.0.max(1.0));
+            Some(LinkAction::Nudge {
+                percent: percent.clamp(-Self::MAX_CORRECTION_PERCENT, Self::MAX_CORRECTION_PERCENT),
+                duration: Self::CORRECTION_DURATION,
+            })
+        }
+    }
+
+    fn time_to_beats(time: MusicalTime) -> f64 {
+        time.total_units() as f64 / MusicalTime::UNITS_IN_BEAT as f64
+    }
+
+    fn beats_to_time(beats: f64) -> MusicalTime {
+        MusicalTime::new_with_units((beats.max(0.0) * MusicalTime::UNITS_IN_BEAT as f64) as usize)
+    }
+}
+
+/// A connection to an Ableton Link session on the local network.
+#[cfg(feature = "link")]
+pub struct LinkSession {
+    link: rusty_link::AblLink,
+    state: rusty_link::SessionState,
+    sync: LinkSync,
+}
+#[cfg(feature = "link")]
+impl core::fmt::Debug for LinkSession {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LinkSession")
+            .field("is_enabled", &self.link.is_enabled())
+            .field("sync", &self.sync)
+            .finish()
+    }
+}
+#[cfg(feature = "link")]
+impl LinkSession {
+    /// Creates a session that offers `tempo` to peers. It doesn't join the
+    /// network until set_enabled(true).
+    pub fn new_with(tempo: Tempo, sync: LinkSync) -> Self {
+        let link = rusty_link::AblLink::new(tempo.0);
+        link.enable_start_stop_sync(true);
+        Self {
+            link,
+            state: rusty_link::SessionState::new(),
+            sync,
+        }
+    }
+
+    /// Joins or leaves the session.
+    pub fn set_enabled(&mut self, is_enabled: bool) {
+        self.link.enable(is_enabled);
+    }
+
+    #[allow(missing_docs)]
+    pub fn is_enabled(&self) -> bool {
+        self.link.is_enabled()
+    }
+
+    /// How many other apps are in the session.
+    pub fn peer_count(&self) -> usize {
+        self.link.num_peers() as usize
+    }
+
+    #[allow(missing_docs)]
+    pub fn sync_mut(&mut self) -> &mut LinkSync {
+        &mut self.sync
+    }
+
+    /// Reads the session as it will be `latency` from now, which should be
+    /// how long it takes the local position to be heard.
+    pub fn capture(&mut self, latency: Duration) -> LinkSnapshot {
+        self.link.capture_app_session_state(&mut self.state);
+        let time = self.link.clock_micros() + latency.as_micros() as i64;
+        LinkSnapshot {
+            tempo: Tempo(self.state.tempo()),
+            beat: self
+                .state
+                .beat_at_time(time as _, self.sync.quantum_beats()),
+            is_playing: self.state.is_playing(),
+        }
+    }
+
+    /// Compares the session with the local transport. See [LinkSync::update()].
+    pub fn update(&mut self, latency: Duration, local: &TransportSnapshot) -> Vec<LinkAction> {
+        if !self.is_enabled() {
+            return Vec::default();
+        }
+        let link = self.capture(latency);
+        self.sync.update(&link, local)
+    }
+
+    /// Tells peers about a local tempo change.
+    pub fn set_tempo(&mut self, tempo: Tempo) {
+        self.link.capture_app_session_state(&mut self.state);
+        let now = self.link.clock_micros();
+        self.state.set_tempo(tempo.0, now as _);
+        self.link.commit_app_session_state(&self.state);
+    }
+
+    /// Asks peers to start. Link puts the start on the session's next
+    /// quantum boundary, so everyone's first downbeat lands together.
+    pub fn request_start(&mut self) {
+        self.link.capture_app_session_state(&mut self.state);
+        let now = self.link.clock_micros();
+        self.state.set_is_playing_and_request_beat_at_time(
+            true,
+            now as _,
+            0.0,
+            self.sync.quantum_beats(),
+        );
+        self.link.commit_app_session_state(&self.state);
+    }
+
+    /// Asks peers to stop.
+    pub fn request_stop(&mut self) {
+        self.link.capture_app_session_state(&mut self.state);
+        let now = self.link.clock_micros();
+        self.state.set_is_playing(false, now as _);
+        self.link.commit_app_session_state(&self.state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beats(n: f64) -> MusicalTime {
+        LinkSync::beats_to_time(n)
+    }
+
+    fn local(position: MusicalTime, is_performing: bool) -> TransportSnapshot {
+        TransportSnapshot {
+            tempo: Tempo(120.0),
+            position,
+            is_performing,
+        }
+    }
+
+    fn link(beat: f64, is_playing: bool) -> LinkSnapshot {
+        LinkSnapshot {
+            tempo: Tempo(120.0),
+            beat,
+            is_playing,
+        }
+    }
+
+    #[test]
+    fn follows_tempo_and_start_stop() {
+        let sync = LinkSync::new_with(LaunchQuantum::Bar, TimeSignature::default());
+        assert_eq!(sync.quantum_beats(), 4.0);
+        assert!(sync
+            .update(&link(0.0, false), &local(beats(0.0), false))
+            .is_empty());
+
+        let mut faster = link(0.0, false);
+        faster.tempo = Tempo(128.0);
+        assert_eq!(
+            sync.update(&faster, &local(beats(0.0), false)),
+            vec![LinkAction::SetTempo(Tempo(128.0))]
+        );
+
+        // Joining a session that's on beat 2 of bar 3 starts on beat 2, so
+        // the local downbeats line up with everyone else's.
+        assert_eq!(
+            sync.update(&link(9.0, true), &local(beats(0.0), false)),
+            vec![LinkAction::Start(beats(1.0))]
+        );
+        assert_eq!(
+            sync.update(&link(9.0, false), &local(beats(1.0), true)),
+            vec![LinkAction::Stop]
+        );
+    }
+
+    #[test]
+    fn corrects_phase_drift() {
+        let sync = LinkSync::new_with(LaunchQuantum::Bar, TimeSignature::default());
+
+        // In phase, even though the beat counts differ by a whole bar.
+        assert!(sync
+            .update(&link(6.0, true), &local(beats(2.0), true))
+            .is_empty());
+
+        // A little behind: speed up for a moment.
+        let actions = sync.update(&link(6.05, true), &local(beats(2.0), true));
+        let [LinkAction::Nudge { percent, duration }] = actions.as_slice() else {
+            panic!("expected a nudge, got {actions:?}");
+        };
+        assert!(
+            (percent - 5.0).abs() < 1e-9,
+            "0.05 beats over half a second"
+        );
+        assert_eq!(*duration, LinkSync::CORRECTION_DURATION);
+
+        // A little ahead, across a bar line: slow down.
+        let actions = sync.update(&link(7.99, true), &local(beats(0.0), true));
+        let [LinkAction::Nudge { percent, .. }] = actions.as_slice() else {
+            panic!("expected a nudge, got {actions:?}");
+        };
+        assert!(*percent < 0.0);
+
+        // Way off: jump to the right phase.
+        assert_eq!(
+            sync.update(&link(5.0, true), &local(beats(2.0), true)),
+            vec![LinkAction::Seek(beats(1.0))]
+        );
+    }
+}
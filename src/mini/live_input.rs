@@ -0,0 +1,137 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use eframe::egui::Ui;
+use ensnare_core::core::AudioQueue;
+use ensnare_core::prelude::*;
+use ensnare_core::traits::{Configurable, Displays, Generates, HandlesMidi, Serializable, Ticks};
+use ensnare_proc_macros::{Control, IsInstrument, Uid};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default)]
+pub struct AudioInputEphemerals {
+    queue: Option<AudioQueue>,
+    sample: StereoSample,
+    sample_rate: SampleRate,
+}
+
+/// [AudioInput] is an instrument whose sound comes from the audio input
+/// device. Give it the queue from
+/// [AudioPanelEvent::InputReset](crate::panels::AudioPanelEvent::InputReset),
+/// and it can go anywhere another instrument can, including in front of
+/// effects.
+#[derive(Debug, Default, Control, IsInstrument, Uid, Serialize, Deserialize)]
+pub struct AudioInput {
+    uid: Uid,
+
+    #[serde(skip)]
+    e: AudioInputEphemerals,
+}
+impl AudioInput {
+    /// If more than this many samples are waiting, we've fallen behind the
+    /// input device. Rather than let latency grow, we skip ahead.
+    const MAX_BACKLOG: usize = 2048;
+
+    /// Connects to the capture queue. Pass None to disconnect.
+    pub fn set_queue(&mut self, queue: Option<AudioQueue>) {
+        self.e.queue = queue;
+        self.e.sample = StereoSample::SILENCE;
+    }
+
+    #[allow(missing_docs)]
+    pub fn is_connected(&self) -> bool {
+        self.e.queue.is_some()
+    }
+
+    fn next_sample(&mut self) -> StereoSample {
+        if let Some(queue) = self.e.queue.as_ref() {
+            // Overrun: drop the oldest samples.
+            while queue.len() > Self::MAX_BACKLOG {
+                let _ = queue.pop();
+            }
+            // Underrun: if nothing has arrived, play silence.
+            queue.pop().unwrap_or(StereoSample::SILENCE)
+        } else {
+            StereoSample::SILENCE
+        }
+    }
+}
+impl Generates<StereoSample> for AudioInput {
+    fn value(&self) -> StereoSample {
+        self.e.sample
+    }
+
+    fn generate_batch_values(&mut self, values: &mut [StereoSample]) {
+        for value in values {
+            self.e.sample = self.next_sample();
+            *value = self.e.sample;
+        }
+    }
+}
+impl Ticks for AudioInput {
+    fn tick(&mut self, tick_count: usize) {
+        for _ in 0..tick_count {
+            self.e.sample = self.next_sample();
+        }
+    }
+}
+impl Configurable for AudioInput {
+    fn sample_rate(&self) -> SampleRate {
+        self.e.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.e.sample_rate = sample_rate;
+    }
+}
+// A source with no notes to play.
+impl HandlesMidi for AudioInput {}
+impl Serializable for AudioInput {}
+impl Displays for AudioInput {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        ui.label(if self.is_connected() {
+            "Audio input: live"
+        } else {
+            "Audio input: not connected"
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam::queue::ArrayQueue;
+    use std::sync::Arc;
+
+    #[test]
+    fn plays_queue_and_tolerates_underrun() {
+        let mut input = AudioInput::default();
+        input.tick(1);
+        assert_eq!(input.value(), StereoSample::SILENCE);
+
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(16));
+        input.set_queue(Some(Arc::clone(&queue)));
+        let sample = StereoSample(Sample(0.5), Sample(-0.5));
+        let _ = queue.push(sample);
+        input.tick(1);
+        assert_eq!(input.value(), sample);
+
+        input.tick(1);
+        assert_eq!(
+            input.value(),
+            StereoSample::SILENCE,
+            "an empty queue should produce silence"
+        );
+    }
+
+    #[test]
+    fn drops_backlog_on_overrun() {
+        let mut input = AudioInput::default();
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(AudioInput::MAX_BACKLOG * 2));
+        for _ in 0..AudioInput::MAX_BACKLOG * 2 {
+            let _ = queue.push(StereoSample::SILENCE);
+        }
+        input.set_queue(Some(Arc::clone(&queue)));
+        input.tick(1);
+        assert!(queue.len() < AudioInput::MAX_BACKLOG);
+    }
+}
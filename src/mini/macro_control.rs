@@ -0,0 +1,199 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// One parameter that a [MacroControl] drives.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MacroTarget {
+    #[allow(missing_docs)]
+    pub target_uid: Uid,
+
+    /// The index of the target's #[control] parameter.
+    pub control_index: ControlIndex,
+
+    /// How far, and in which direction, the macro moves the parameter. At
+    /// 1.0 the parameter follows the macro from 0.0 to 1.0. At -1.0 it moves
+    /// the opposite way, from 1.0 down to 0.0. At 0.5 it covers half the
+    /// range, starting from the bottom (or, if negative, the top).
+    pub depth: BipolarNormal,
+}
+impl MacroTarget {
+    fn scale(&self, value: f64) -> ControlValue {
+        let depth = self.depth.0;
+        ControlValue(if depth >= 0.0 {
+            value * depth
+        } else {
+            1.0 + value * depth
+        })
+    }
+}
+
+/// [MacroControl] is a single performance knob that moves several parameters
+/// at once, each with its own depth and polarity, such as opening a filter
+/// while pulling back a reverb.
+///
+/// Like [CcRouting](super::CcRouting), it works out the changes and leaves the
+/// applying to its owner, who passes each one to the target's
+/// `control_set_param_by_index()`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MacroControl {
+    targets: Vec<MacroTarget>,
+    value: Normal,
+}
+impl MacroControl {
+    /// Adds a target. If the parameter is already a target, its depth is
+    /// replaced.
+    pub fn add_target(
+        &mut self,
+        target_uid: Uid,
+        control_index: ControlIndex,
+        depth: BipolarNormal,
+    ) {
+        if let Some(target) = self
+            .targets
+            .iter_mut()
+            .find(|t| t.target_uid == target_uid && t.control_index == control_index)
+        {
+            target.depth = depth;
+        } else {
+            self.targets.push(MacroTarget {
+                target_uid,
+                control_index,
+                depth,
+            });
+        }
+    }
+
+    /// Removes a target, if it's there.
+    pub fn remove_target(&mut self, target_uid: Uid, control_index: ControlIndex) {
+        self.targets
+            .retain(|t| !(t.target_uid == target_uid && t.control_index == control_index));
+    }
+
+    #[allow(missing_docs)]
+    pub fn targets(&self) -> &[MacroTarget] {
+        &self.targets
+    }
+
+    #[allow(missing_docs)]
+    pub fn value(&self) -> Normal {
+        self.value
+    }
+
+    /// Turns the knob. Returns the new value of every target, or nothing if
+    /// the knob didn't actually move.
+    pub fn set_value(&mut self, value: Normal) -> Vec<(Uid, ControlIndex, ControlValue)> {
+        if value == self.value {
+            return Vec::default();
+        }
+        self.value = value;
+        self.updates()
+    }
+
+    /// The value of every target for the knob's current position. Useful for
+    /// bringing targets in line after adding one, or after loading.
+    pub fn updates(&self) -> Vec<(Uid, ControlIndex, ControlValue)> {
+        let mut updates = Vec::with_capacity(self.targets.len());
+        self.updates_into(&mut updates);
+        updates
+    }
+
+    /// Like [MacroControl::set_value()], but fills the given buffer instead of
+    /// returning a new one, so that the audio thread doesn't allocate. The
+    /// buffer is cleared first.
+    pub fn set_value_into(
+        &mut self,
+        value: Normal,
+        updates: &mut Vec<(Uid, ControlIndex, ControlValue)>,
+    ) {
+        if value == self.value {
+            updates.clear();
+        } else {
+            self.value = value;
+            self.updates_into(updates);
+        }
+    }
+
+    /// Like [MacroControl::updates()], but fills the given buffer. The buffer
+    /// is cleared first.
+    pub fn updates_into(&self, updates: &mut Vec<(Uid, ControlIndex, ControlValue)>) {
+        updates.clear();
+        updates.extend(
+            self.targets
+                .iter()
+                .map(|t| (t.target_uid, t.control_index, t.scale(self.value.0))),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTER: Uid = Uid(10);
+    const REVERB: Uid = Uid(11);
+    const CUTOFF: ControlIndex = ControlIndex(0);
+    const MIX: ControlIndex = ControlIndex(2);
+
+    #[test]
+    fn one_knob_drives_opposing_targets() {
+        let mut m = MacroControl::default();
+        m.add_target(FILTER, CUTOFF, BipolarNormal::from(1.0));
+        m.add_target(REVERB, MIX, BipolarNormal::from(-0.5));
+
+        let updates = m.set_value(Normal::from(1.0));
+        assert_eq!(
+            updates,
+            vec![
+                (FILTER, CUTOFF, ControlValue(1.0)),
+                (REVERB, MIX, ControlValue(0.5))
+            ]
+        );
+
+        let updates = m.set_value(Normal::from(0.0));
+        assert_eq!(
+            updates,
+            vec![
+                (FILTER, CUTOFF, ControlValue(0.0)),
+                (REVERB, MIX, ControlValue(1.0))
+            ]
+        );
+
+        assert!(
+            m.set_value(Normal::from(0.0)).is_empty(),
+            "no change, no updates"
+        );
+    }
+
+    #[test]
+    fn re_adding_a_target_replaces_its_depth() {
+        let mut m = MacroControl::default();
+        m.add_target(FILTER, CUTOFF, BipolarNormal::from(1.0));
+        m.add_target(FILTER, CUTOFF, BipolarNormal::from(0.25));
+        assert_eq!(m.targets().len(), 1);
+        let updates = m.set_value(Normal::from(1.0));
+        assert_eq!(updates, vec![(FILTER, CUTOFF, ControlValue(0.25))]);
+
+        m.remove_target(FILTER, CUTOFF);
+        assert!(m.updates().is_empty());
+    }
+
+    #[test]
+    fn filling_a_buffer_matches_returning_one() {
+        let mut m = MacroControl::default();
+        m.add_target(FILTER, CUTOFF, BipolarNormal::from(1.0));
+        m.add_target(REVERB, MIX, BipolarNormal::from(-0.5));
+
+        let mut updates = Vec::with_capacity(m.targets().len());
+        m.set_value_into(Normal::from(1.0), &mut updates);
+        assert_eq!(updates, m.updates());
+
+        let capacity = updates.capacity();
+        m.set_value_into(Normal::from(1.0), &mut updates);
+        assert!(updates.is_empty(), "no change, no updates");
+        m.updates_into(&mut updates);
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates.capacity(), capacity, "the buffer was reused");
+    }
+}
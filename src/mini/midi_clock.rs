@@ -0,0 +1,235 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::prelude::*;
+use std::{collections::VecDeque, time::Instant};
+
+/// The system realtime messages that drive MIDI clock sync.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiRealtime {
+    /// 0xF8, sent [MidiClockFollower::PPQN] times per quarter note.
+    Clock,
+    /// 0xFA. Play from the top.
+    Start,
+    /// 0xFB. Play from wherever the transport stopped.
+    Continue,
+    /// 0xFC.
+    Stop,
+}
+impl MidiRealtime {
+    /// Decodes a status byte. Other realtime bytes, like active sensing,
+    /// aren't part of clock sync and return None.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0xf8 => Some(Self::Clock),
+            0xfa => Some(Self::Start),
+            0xfb => Some(Self::Continue),
+            0xfc => Some(Self::Stop),
+            _ => None,
+        }
+    }
+}
+
+/// What the transport should do to follow an external clock.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MidiClockEvent {
+    /// The external clock's tempo has changed.
+    Tempo(Tempo),
+    #[allow(missing_docs)]
+    Start,
+    #[allow(missing_docs)]
+    Continue,
+    #[allow(missing_docs)]
+    Stop,
+}
+
+/// Follows an external MIDI clock. It measures the tempo from the time
+/// between clock ticks, and passes start, stop, and continue through.
+///
+/// Tick timestamps jitter, especially over USB, where ticks often arrive in
+/// bursts. Averaging over a whole beat of ticks cancels nearly all of that,
+/// because only the first and last tick's timing matter to the average. On
+/// top of that, the tempo is smoothed, reported at most every
+/// [MidiClockFollower::REPORT_EVERY_TICKS] ticks, and reported only when it
+/// moves by more than [MidiClockFollower::HYSTERESIS_BPM], so that the
+/// transport doesn't hunt around a steady tempo.
+///
+/// The first tempo comes one beat after the clock starts ticking. A gap much
+/// longer than the current tick interval means the clock went away, and
+/// measuring starts over.
+#[derive(Debug, Default)]
+pub struct MidiClockFollower {
+    last_tick: Option<Instant>,
+    /// The most recent tick intervals, in seconds, oldest first.
+    intervals: VecDeque<f64>,
+    smoothed_bpm: Option<f64>,
+    reported_bpm: Option<f64>,
+    ticks_since_report: usize,
+}
+impl MidiClockFollower {
+    /// MIDI clock ticks per quarter note.
+    pub const PPQN: usize = 24;
+
+    /// A sixteenth note's worth of ticks.
+    pub const REPORT_EVERY_TICKS: usize = 6;
+
+    /// Tempo changes smaller than this aren't reported.
+    pub const HYSTERESIS_BPM: f64 = 0.05;
+
+    /// How much of each new measurement goes into the smoothed tempo.
+    const SMOOTHING: f64 = 0.3;
+
+    /// Before there's a beat's worth of ticks to compare against, a gap
+    /// longer than this (10 BPM) counts as the clock going away.
+    const MAX_TICK_INTERVAL: f64 = 0.25;
+
+    /// A gap this many times the average tick interval counts as the clock
+    /// going away.
+    const DROPOUT_FACTOR: f64 = 4.0;
+
+    /// Handles a realtime message that arrived at `at`, and returns what the
+    /// transport should do about it, if anything.
+    pub fn handle(&mut self, message: MidiRealtime, at: Instant) -> Option<MidiClockEvent> {
+        match message {
+            MidiRealtime::Clock => self.tick(at).map(MidiClockEvent::Tempo),
+            MidiRealtime::Start => Some(MidiClockEvent::Start),
+            MidiRealtime::Continue => Some(MidiClockEvent::Continue),
+            MidiRealtime::Stop => Some(MidiClockEvent::Stop),
+        }
+    }
+
+    /// The tempo last reported, if the clock has been followed long enough
+    /// to know it.
+    pub fn tempo(&self) -> Option<Tempo> {
+        self.reported_bpm.map(Tempo)
+    }
+
+    /// Forgets everything, as when the input port changes.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn tick(&mut self, at: Instant) -> Option<Tempo> {
+        let last_tick = self.last_tick.replace(at)?;
+        let interval = at.saturating_duration_since(last_tick).as_secs_f64();
+        let limit = if self.intervals.len() == Self::PPQN {
+            self.average_interval() * Self::DROPOUT_FACTOR
+        } else {
+            Self::MAX_TICK_INTERVAL
+        };
+        if interval > limit {
+            *self = Self {
+                last_tick: Some(at),
+                ..Default::default()
+            };
+            return None;
+        }
+        self.intervals.push_back(interval);
+        if self.intervals.len() > Self::PPQN {
+            self.intervals.pop_front();
+        }
+        if self.intervals.len() < Self::PPQN {
+            return None;
+        }
+        let beat = self.average_interval() * Self::PPQN as f64;
+        if beat <= 0.0 {
+            return None;
+        }
+        let bpm = 60.0 / beat;
+        let smoothed = match self.smoothed_bpm {
+            Some(smoothed) => smoothed + (bpm - smoothed) * Self::SMOOTHING,
+            None => bpm,
+        };
+        self.smoothed_bpm = Some(smoothed);
+
+        self.ticks_since_report += 1;
+        if let Some(reported) = self.reported_bpm {
+            if self.ticks_since_report < Self::REPORT_EVERY_TICKS {
+                return None;
+            }
+            self.ticks_since_report = 0;
+            if (smoothed - reported).abs() < Self::HYSTERESIS_BPM {
+                return None;
+            }
+        }
+        self.ticks_since_report = 0;
+        self.reported_bpm = Some(smoothed);
+        Some(Tempo(smoothed))
+    }
+
+    fn average_interval(&self) -> f64 {
+        self.intervals.iter().sum::<f64>() / self.intervals.len().max(1) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // Ticks at `bpm`, with timestamps that wander up to a millisecond either
+    // way, and returns the last tempo reported.
+    fn tick_at(
+        follower: &mut MidiClockFollower,
+        start: Instant,
+        seconds: &mut f64,
+        bpm: f64,
+        ticks: usize,
+    ) -> Option<Tempo> {
+        let jitter = [0.0, 0.001, -0.001, 0.0005];
+        let mut tempo = None;
+        for i in 0..ticks {
+            *seconds += 60.0 / bpm / MidiClockFollower::PPQN as f64;
+            let at = start + Duration::from_secs_f64(*seconds + jitter[i % jitter.len()]);
+            if let Some(MidiClockEvent::Tempo(t)) = follower.handle(MidiRealtime::Clock, at) {
+                tempo = Some(t);
+            }
+        }
+        tempo
+    }
+
+    #[test]
+    fn follows_a_jittery_clock() {
+        let mut follower = MidiClockFollower::default();
+        let start = Instant::now() + Duration::from_secs(1);
+        let mut seconds = 0.0;
+
+        // Nothing until a beat's worth of ticks.
+        assert!(tick_at(&mut follower, start, &mut seconds, 120.0, 24).is_none());
+        let tempo = tick_at(&mut follower, start, &mut seconds, 120.0, 1).unwrap();
+        assert!((tempo.0 - 120.0).abs() < 0.5, "tempo {}", tempo.0);
+
+        // A steady clock settles down and stops reporting.
+        let _ = tick_at(&mut follower, start, &mut seconds, 120.0, 96);
+        assert!(tick_at(&mut follower, start, &mut seconds, 120.0, 96).is_none());
+        assert!((follower.tempo().unwrap().0 - 120.0).abs() < 0.5);
+
+        // A tempo change comes through within a few beats.
+        let _ = tick_at(&mut follower, start, &mut seconds, 100.0, 96);
+        assert!((follower.tempo().unwrap().0 - 100.0).abs() < 0.5);
+
+        // After the clock goes away, measuring starts over.
+        seconds += 2.0;
+        assert!(tick_at(&mut follower, start, &mut seconds, 140.0, 24).is_none());
+        assert!(follower.tempo().is_none());
+        let tempo = tick_at(&mut follower, start, &mut seconds, 140.0, 1).unwrap();
+        assert!((tempo.0 - 140.0).abs() < 1.0, "tempo {}", tempo.0);
+    }
+
+    #[test]
+    fn transport_messages_pass_through() {
+        let mut follower = MidiClockFollower::default();
+        let now = Instant::now();
+        for (byte, event) in [
+            (0xfa, MidiClockEvent::Start),
+            (0xfb, MidiClockEvent::Continue),
+            (0xfc, MidiClockEvent::Stop),
+        ] {
+            let message = MidiRealtime::from_byte(byte).unwrap();
+            assert_eq!(follower.handle(message, now), Some(event));
+        }
+        assert!(MidiRealtime::from_byte(0xfe).is_none());
+        assert!(follower
+            .handle(MidiRealtime::from_byte(0xf8).unwrap(), now)
+            .is_none());
+    }
+}
@@ -1,9 +1,98 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
+pub use aftertouch::{PressureChange, PressureRoute, PressureRouting};
+pub use arrangement::{Arrangement, Clip, ClipSlice};
+pub use automation::{AutomationLane, AutomationPoint, AutomationRecorder};
+pub use beat_repeat::BeatRepeat;
+pub use cc_routing::{CcRoute, CcRouting};
+pub use chord::{expand_chord, strum_to_musical_time, ChordNote, ChordProgrammer, ChordQuality};
+pub use convolution_reverb::ConvolutionReverb;
+pub use cue_track::{CueEvent, CueTrack};
+pub use denormal::DenormalGuard;
+pub use drum_sequencer::{DrumLane, DrumSequencer};
+pub use entity_factory::register_mini_entities;
+pub use equalizer::{EqBand, EqBandShape, ParametricEq};
+pub use frozen_sampler::FrozenSampler;
+pub use gate::NoteGate;
+pub use hard_sync::HardSyncOscillator;
+#[cfg(feature = "lv2")]
+pub use hosted_plugin::Lv2Plugin;
+pub use hosted_plugin::{HostedEffect, HostedPlugin, PluginInstance, PluginParam};
+pub use humanize::Humanizer;
+#[cfg(feature = "link")]
+pub use link::LinkSession;
+pub use link::{LinkAction, LinkSnapshot, LinkSync, TransportSnapshot};
+pub use live_input::AudioInput;
+pub use macro_control::{MacroControl, MacroTarget};
+pub use midi_clock::{MidiClockEvent, MidiClockFollower, MidiRealtime};
+pub use mpe::{HandlesMpe, MpeEvent, MpeExpression, MpeInput, MpeNote, MpeZone};
+pub use note_repeat::NoteRepeat;
+pub use output_routing::{OutputRoute, OutputRouting};
+pub use oversampler::{OversampleFactor, Oversampler};
+pub use pattern_launcher::{LaunchQuantum, PatternLauncher};
+pub use pitch_bend::PitchBender;
+pub use render::{
+    assert_audible_and_bounded, render_entity_for, EntityUnderTest, RenderableEffect,
+    RenderableInstrument, TEST_SIGNAL_FREQUENCY,
+};
+pub use ring_modulator::{CarrierWaveform, RingModulator};
+pub use rng::{SeedBank, Seeded};
+pub use sample_and_hold::SampleAndHoldLfo;
+pub use sample_data::{resample, SampleData};
+pub use sample_rate::ConfiguredSampleRate;
+pub use scala::ScalaScale;
+pub use scale::{Scale, ScaleMode, ScaleSnap};
+pub use shared_orchestrator::SharedOrchestrator;
+pub use smoothed_value::SmoothedValue;
+pub use spectrum::analyze_spectrum;
+pub use stereo_quantizer::StereoQuantizer;
+pub use tempo_delay::{NoteDivision, TempoSyncedDelay};
 pub use transport::Transport;
+pub use tuning::{Temperament, Tuning};
+pub use waveshaper::{Waveshaper, WaveshaperCurve};
 
+mod aftertouch;
+mod arrangement;
+mod automation;
+mod beat_repeat;
 mod bus_station;
+mod cc_routing;
+mod chord;
+mod convolution_reverb;
+mod cue_track;
+mod denormal;
+mod drum_sequencer;
 mod entity_factory;
+mod equalizer;
+mod frozen_sampler;
+mod gate;
+mod hard_sync;
+mod hosted_plugin;
+mod humanize;
+mod link;
+mod live_input;
+mod macro_control;
+mod midi_clock;
+mod mpe;
+mod note_repeat;
 mod orchestrator;
+mod output_routing;
+mod oversampler;
+mod pattern_launcher;
+mod pitch_bend;
+mod render;
+mod ring_modulator;
 mod rng;
+mod sample_and_hold;
+mod sample_data;
+mod sample_rate;
+mod scala;
+mod scale;
+mod shared_orchestrator;
+mod smoothed_value;
+mod spectrum;
+mod stereo_quantizer;
+mod tempo_delay;
 mod transport;
+mod tuning;
+mod waveshaper;
@@ -0,0 +1,432 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::midi::prelude::*;
+use ensnare_core::traits::{HandlesMidi, MidiMessagesFn};
+use serde::{Deserialize, Serialize};
+
+/// An MPE zone: one master channel for zone-wide messages, and a block of
+/// member channels next to it, each of which carries one note at a time
+/// along with that note's own pitch bend, pressure, and slide.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MpeZone {
+    /// MidiChannel(0) for a lower zone, MidiChannel(15) for an upper zone.
+    master: MidiChannel,
+
+    /// How many member channels the zone has, 1..=15.
+    member_count: u8,
+
+    /// How far a member channel's pitch bend reaches at its extremes, in
+    /// semitones.
+    pitch_bend_range: f64,
+}
+impl Default for MpeZone {
+    fn default() -> Self {
+        Self::new_lower(15)
+    }
+}
+impl MpeZone {
+    /// The MPE spec's default bend range for member channels.
+    pub const DEFAULT_PITCH_BEND_RANGE: f64 = 48.0;
+
+    /// The MIDI CC that MPE controllers send for slide (the Y axis).
+    pub const SLIDE_CC: u8 = 74;
+
+    /// A zone mastered on MIDI channel 1, with members on channels 2 and up.
+    pub fn new_lower(member_count: u8) -> Self {
+        Self {
+            master: MidiChannel(0),
+            member_count: member_count.clamp(1, 15),
+            pitch_bend_range: Self::DEFAULT_PITCH_BEND_RANGE,
+        }
+    }
+
+    /// A zone mastered on MIDI channel 16, with members on channels 15 and
+    /// down.
+    pub fn new_upper(member_count: u8) -> Self {
+        Self {
+            master: MidiChannel(15),
+            ..Self::new_lower(member_count)
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn master(&self) -> MidiChannel {
+        self.master
+    }
+
+    #[allow(missing_docs)]
+    pub fn member_count(&self) -> u8 {
+        self.member_count
+    }
+
+    #[allow(missing_docs)]
+    pub fn pitch_bend_range(&self) -> f64 {
+        self.pitch_bend_range
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_pitch_bend_range(&mut self, semitones: f64) {
+        self.pitch_bend_range = semitones.clamp(0.0, 96.0);
+    }
+
+    /// Whether `channel` is one of this zone's member channels.
+    pub fn is_member(&self, channel: MidiChannel) -> bool {
+        if self.master.0 == 0 {
+            (1..=self.member_count).contains(&channel.0)
+        } else {
+            (self.master.0 - self.member_count..self.master.0).contains(&channel.0)
+        }
+    }
+}
+
+/// Identifies one sounding MPE note, and so one instrument voice.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MpeNote {
+    /// The member channel the note arrived on.
+    pub channel: MidiChannel,
+
+    #[allow(missing_docs)]
+    pub key: u8,
+}
+
+/// One dimension of a single note's expression.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MpeExpression {
+    /// Pitch bend in semitones, already scaled by the zone's bend range.
+    Bend(f64),
+    /// Pressure (the Z axis), 0.0..=1.0.
+    Pressure(f64),
+    /// Slide (the Y axis, CC 74), 0.0..=1.0.
+    Slide(f64),
+}
+
+/// What [MpeInput] makes of an incoming message.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MpeEvent {
+    /// A note started. The note's current bend, pressure, and slide follow
+    /// immediately as [MpeEvent::Expression]s.
+    NoteOn {
+        #[allow(missing_docs)]
+        note: MpeNote,
+        #[allow(missing_docs)]
+        velocity: u8,
+    },
+    #[allow(missing_docs)]
+    NoteOff {
+        #[allow(missing_docs)]
+        note: MpeNote,
+        #[allow(missing_docs)]
+        velocity: u8,
+    },
+    /// Expression for one note only.
+    Expression {
+        #[allow(missing_docs)]
+        note: MpeNote,
+        #[allow(missing_docs)]
+        expression: MpeExpression,
+    },
+    /// A message on the master channel, which applies to every note in the
+    /// zone.
+    Master(MidiMessage),
+}
+
+#[derive(Clone, Debug, Default)]
+struct MemberChannel {
+    keys: Vec<u8>,
+    bend: f64,
+    pressure: f64,
+    slide: f64,
+}
+
+/// Turns MIDI from an MPE controller into per-note events. MPE gives each
+/// note its own member channel, so a member channel's pitch bend, channel
+/// pressure, and CC 74 belong to the note on that channel rather than to
+/// the whole instrument. Instruments get the result through [HandlesMpe].
+///
+/// Controllers send a note's starting bend, pressure, and slide just before
+/// its note-on, so the channel's expression is kept between notes and handed
+/// to each note as it starts.
+#[derive(Clone, Debug, Default)]
+pub struct MpeInput {
+    zone: MpeZone,
+    members: [MemberChannel; 16],
+}
+impl MpeInput {
+    #[allow(missing_docs)]
+    pub fn new_with(zone: MpeZone) -> Self {
+        Self {
+            zone,
+            members: Default::default(),
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn zone(&self) -> &MpeZone {
+        &self.zone
+    }
+
+    /// Translates one message. Returns None if the channel isn't in the zone,
+    /// in which case the caller should handle the message as ordinary MIDI.
+    pub fn handle(&mut self, channel: MidiChannel, message: MidiMessage) -> Option<Vec<MpeEvent>> {
+        if channel == self.zone.master {
+            return Some(vec![MpeEvent::Master(message)]);
+        }
+        if !self.zone.is_member(channel) {
+            return None;
+        }
+        let member = &mut self.members[channel.0 as usize];
+        let mut events = Vec::default();
+        match message {
+            MidiMessage::NoteOn { key, vel } if vel.as_int() != 0 => {
+                let note = MpeNote {
+                    channel,
+                    key: key.as_int(),
+                };
+                member.keys.retain(|k| *k != note.key);
+                member.keys.push(note.key);
+                events.push(MpeEvent::NoteOn {
+                    note,
+                    velocity: vel.as_int(),
+                });
+                for expression in [
+                    MpeExpression::Bend(member.bend),
+                    MpeExpression::Pressure(member.pressure),
+                    MpeExpression::Slide(member.slide),
+                ] {
+                    events.push(MpeEvent::Expression { note, expression });
+                }
+            }
+            MidiMessage::NoteOn { key, vel } | MidiMessage::NoteOff { key, vel } => {
+                let key = key.as_int();
+                if member.keys.contains(&key) {
+                    member.keys.retain(|k| *k != key);
+                    events.push(MpeEvent::NoteOff {
+                        note: MpeNote { channel, key },
+                        velocity: vel.as_int(),
+                    });
+                }
+            }
+            MidiMessage::PitchBend { bend } => {
+                member.bend = bend.as_f64() * self.zone.pitch_bend_range;
+                Self::express(
+                    &mut events,
+                    channel,
+                    member,
+                    MpeExpression::Bend(member.bend),
+                );
+            }
+            MidiMessage::ChannelAftertouch { vel } => {
+                member.pressure = vel.as_int() as f64 / 127.0;
+                let expression = MpeExpression::Pressure(member.pressure);
+                Self::express(&mut events, channel, member, expression);
+            }
+            MidiMessage::Aftertouch { key, vel } => {
+                // Not part of MPE, but some controllers send it anyway, and
+                // it can only mean this note.
+                let note = MpeNote {
+                    channel,
+                    key: key.as_int(),
+                };
+                if member.keys.contains(&note.key) {
+                    events.push(MpeEvent::Expression {
+                        note,
+                        expression: MpeExpression::Pressure(vel.as_int() as f64 / 127.0),
+                    });
+                }
+            }
+            MidiMessage::Controller { controller, value }
+                if controller.as_int() == MpeZone::SLIDE_CC =>
+            {
+                member.slide = value.as_int() as f64 / 127.0;
+                Self::express(
+                    &mut events,
+                    channel,
+                    member,
+                    MpeExpression::Slide(member.slide),
+                );
+            }
+            _ => {}
+        }
+        Some(events)
+    }
+
+    fn express(
+        events: &mut Vec<MpeEvent>,
+        channel: MidiChannel,
+        member: &MemberChannel,
+        expression: MpeExpression,
+    ) {
+        for key in member.keys.iter() {
+            events.push(MpeEvent::Expression {
+                note: MpeNote { channel, key: *key },
+                expression,
+            });
+        }
+    }
+}
+
+/// Implemented by instruments that can give each voice its own expression.
+/// The default implementation plays MPE input like ordinary MIDI on the
+/// zone's master channel, without the per-note expression, so that any
+/// instrument can at least be played from an MPE controller. Instruments with
+/// per-voice pitch, pressure, or timbre override it and apply each
+/// [MpeEvent::Expression] to the voice playing that [MpeNote].
+pub trait HandlesMpe: HandlesMidi {
+    /// Handles one event from the zone whose master channel is `master`.
+    fn handle_mpe_event(
+        &mut self,
+        master: MidiChannel,
+        event: &MpeEvent,
+        midi_messages_fn: &mut MidiMessagesFn,
+    ) {
+        let message = match *event {
+            MpeEvent::NoteOn { note, velocity } => MidiMessage::NoteOn {
+                key: note.key.into(),
+                vel: velocity.into(),
+            },
+            MpeEvent::NoteOff { note, velocity } => MidiMessage::NoteOff {
+                key: note.key.into(),
+                vel: velocity.into(),
+            },
+            MpeEvent::Master(message) => message,
+            MpeEvent::Expression { .. } => return,
+        };
+        self.handle_midi_message(master, message, midi_messages_fn);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::{num::u14, PitchBend};
+
+    fn note_on(key: u8) -> MidiMessage {
+        MidiMessage::NoteOn {
+            key: key.into(),
+            vel: 100.into(),
+        }
+    }
+
+    fn bends(events: &[MpeEvent]) -> Vec<(MpeNote, f64)> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                MpeEvent::Expression {
+                    note,
+                    expression: MpeExpression::Bend(semitones),
+                } => Some((*note, *semitones)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn zones_know_their_members() {
+        let lower = MpeZone::new_lower(7);
+        assert!(!lower.is_member(MidiChannel(0)));
+        assert!(lower.is_member(MidiChannel(1)));
+        assert!(lower.is_member(MidiChannel(7)));
+        assert!(!lower.is_member(MidiChannel(8)));
+
+        let upper = MpeZone::new_upper(3);
+        assert!(!upper.is_member(MidiChannel(15)));
+        assert!(upper.is_member(MidiChannel(14)));
+        assert!(upper.is_member(MidiChannel(12)));
+        assert!(!upper.is_member(MidiChannel(11)));
+    }
+
+    #[test]
+    fn notes_in_a_chord_bend_independently() {
+        let mut mpe = MpeInput::new_with(MpeZone::new_lower(15));
+        let c = MpeNote {
+            channel: MidiChannel(1),
+            key: 60,
+        };
+        let e = MpeNote {
+            channel: MidiChannel(2),
+            key: 64,
+        };
+        let on = mpe.handle(c.channel, note_on(c.key)).unwrap();
+        assert_eq!(
+            on[0],
+            MpeEvent::NoteOn {
+                note: c,
+                velocity: 100
+            }
+        );
+        assert_eq!(bends(&on), vec![(c, 0.0)]);
+        let _ = mpe.handle(e.channel, note_on(e.key));
+
+        // Gliding the E up a whole step touches only the E.
+        let whole_step = 2.0 / MpeZone::DEFAULT_PITCH_BEND_RANGE;
+        let bend = PitchBend(u14::new((8192.0 + whole_step * 8191.0).round() as u16));
+        let events = mpe
+            .handle(e.channel, MidiMessage::PitchBend { bend })
+            .unwrap();
+        let bent = bends(&events);
+        assert_eq!(bent.len(), 1);
+        assert_eq!(bent[0].0, e);
+        assert!((bent[0].1 - 2.0).abs() < 0.01);
+
+        // Pressure and slide are per-note too.
+        let events = mpe
+            .handle(
+                c.channel,
+                MidiMessage::ChannelAftertouch { vel: 127.into() },
+            )
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![MpeEvent::Expression {
+                note: c,
+                expression: MpeExpression::Pressure(1.0)
+            }]
+        );
+        let events = mpe
+            .handle(
+                e.channel,
+                MidiMessage::Controller {
+                    controller: MpeZone::SLIDE_CC.into(),
+                    value: 0.into(),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![MpeEvent::Expression {
+                note: e,
+                expression: MpeExpression::Slide(0.0)
+            }]
+        );
+
+        // Master-channel messages are zone-wide, and other channels aren't
+        // MPE's business.
+        assert_eq!(
+            mpe.handle(MidiChannel(0), MidiMessage::PitchBend { bend }),
+            Some(vec![MpeEvent::Master(MidiMessage::PitchBend { bend })])
+        );
+        let mut lower_half = MpeInput::new_with(MpeZone::new_lower(4));
+        assert!(lower_half.handle(MidiChannel(9), note_on(36)).is_none());
+
+        let events = mpe
+            .handle(
+                e.channel,
+                MidiMessage::NoteOff {
+                    key: e.key.into(),
+                    vel: 0.into(),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![MpeEvent::NoteOff {
+                note: e,
+                velocity: 0
+            }]
+        );
+        assert!(mpe
+            .handle(e.channel, MidiMessage::PitchBend { bend })
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,408 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use super::NoteDivision;
+use eframe::egui::Ui;
+use ensnare_core::midi::prelude::*;
+use ensnare_core::prelude::*;
+use ensnare_core::traits::{
+    Configurable, ControlEventsFn, Controls, Displays, EntityEvent, HandlesMidi, MidiMessagesFn,
+    Serializable,
+};
+use ensnare_proc_macros::{Control, IsController, Uid};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+#[derive(Clone, Debug, PartialEq)]
+struct HeldNote {
+    channel: MidiChannel,
+    key: u8,
+    velocity: u8,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NoteRepeatEphemerals {
+    range: Range<MusicalTime>,
+    is_performing: bool,
+    held: Vec<HeldNote>,
+    /// Notes that have been struck and not yet ended, as (channel, key, time
+    /// the note-off is due).
+    sounding: Vec<(MidiChannel, u8, MusicalTime)>,
+    /// Messages that arrived through handle_midi_message() and go out with the
+    /// next work().
+    incoming: Vec<(MidiChannel, MidiMessage)>,
+}
+
+/// [NoteRepeat] is the note repeat (or roll) button on a drum machine. It sits
+/// between a MIDI source and an instrument. While a key is held, it strikes
+/// the key again on every [NoteDivision] of the beat grid, so holding a snare
+/// with the default settings plays a sixteenth-note roll locked to the tempo.
+/// Releasing the key ends the roll.
+///
+/// The first hit goes out as soon as the key is pressed; the repeats follow on
+/// the grid, not relative to the press. With a `ratchet` above 1, each
+/// division is split into that many hits, which is the classic way to
+/// accelerate a roll into a fill.
+#[derive(Serialize, Deserialize, Clone, Control, IsController, Debug, Uid)]
+pub struct NoteRepeat {
+    uid: Uid,
+
+    division: NoteDivision,
+
+    /// How many hits to play per division, from 1 to
+    /// [NoteRepeat::MAX_RATCHET].
+    ratchet: usize,
+
+    /// How long each hit lasts, as a fraction of the time between hits.
+    gate: f64,
+
+    #[serde(skip)]
+    e: NoteRepeatEphemerals,
+}
+impl Default for NoteRepeat {
+    fn default() -> Self {
+        Self::new_with(NoteDivision::Sixteenth)
+    }
+}
+impl NoteRepeat {
+    /// The most hits per division. Beyond this, a roll at a fast tempo turns
+    /// into a buzz.
+    pub const MAX_RATCHET: usize = 8;
+
+    #[allow(missing_docs)]
+    pub fn new_with(division: NoteDivision) -> Self {
+        Self {
+            uid: Default::default(),
+            division,
+            ratchet: 1,
+            gate: 0.5,
+            e: Default::default(),
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn division(&self) -> NoteDivision {
+        self.division
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_division(&mut self, division: NoteDivision) {
+        self.division = division;
+    }
+
+    #[allow(missing_docs)]
+    pub fn ratchet(&self) -> usize {
+        self.ratchet
+    }
+
+    /// Sets the number of hits per division, clamped to
+    /// 1..=[NoteRepeat::MAX_RATCHET]. Doubling it doubles the rate of the
+    /// roll.
+    pub fn set_ratchet(&mut self, ratchet: usize) {
+        self.ratchet = ratchet.clamp(1, Self::MAX_RATCHET);
+    }
+
+    #[allow(missing_docs)]
+    pub fn gate(&self) -> f64 {
+        self.gate
+    }
+
+    /// Sets the length of each hit as a fraction of the time between hits.
+    /// It's kept below 1.0 so that each hit ends before the next one starts.
+    pub fn set_gate(&mut self, gate: f64) {
+        self.gate = if gate.is_finite() {
+            gate.clamp(0.01, 0.99)
+        } else {
+            0.5
+        };
+    }
+
+    /// The time between hits.
+    pub fn interval(&self) -> MusicalTime {
+        let units = self.division.musical_time().total_units() / self.ratchet;
+        MusicalTime::new_with_units(units.max(1))
+    }
+
+    /// Whether any keys are being held.
+    pub fn is_repeating(&self) -> bool {
+        !self.e.held.is_empty()
+    }
+
+    fn hit_length(&self) -> MusicalTime {
+        let units = (self.interval().total_units() as f64 * self.gate).round() as usize;
+        MusicalTime::new_with_units(units.max(1))
+    }
+
+    fn send_note_off(
+        &self,
+        channel: MidiChannel,
+        key: u8,
+        control_events_fn: &mut ControlEventsFn,
+    ) {
+        control_events_fn(
+            self.uid,
+            EntityEvent::Midi(
+                channel,
+                MidiMessage::NoteOff {
+                    key: key.into(),
+                    vel: 0.into(),
+                },
+            ),
+        );
+    }
+
+    // Strikes a held note at `time`, ending it first if it's still sounding.
+    fn strike(
+        &mut self,
+        note: &HeldNote,
+        time: MusicalTime,
+        control_events_fn: &mut ControlEventsFn,
+    ) {
+        self.end_note(note.channel, note.key, control_events_fn);
+        control_events_fn(
+            self.uid,
+            EntityEvent::Midi(
+                note.channel,
+                MidiMessage::NoteOn {
+                    key: note.key.into(),
+                    vel: note.velocity.into(),
+                },
+            ),
+        );
+        self.e
+            .sounding
+            .push((note.channel, note.key, time + self.hit_length()));
+    }
+
+    fn end_note(&mut self, channel: MidiChannel, key: u8, control_events_fn: &mut ControlEventsFn) {
+        if let Some(index) = self
+            .e
+            .sounding
+            .iter()
+            .position(|(c, k, _)| *c == channel && *k == key)
+        {
+            self.e.sounding.remove(index);
+            self.send_note_off(channel, key, control_events_fn);
+        }
+    }
+
+    fn release_all(&mut self, control_events_fn: &mut ControlEventsFn) {
+        for (channel, key, _) in std::mem::take(&mut self.e.sounding) {
+            self.send_note_off(channel, key, control_events_fn);
+        }
+    }
+}
+impl HandlesMidi for NoteRepeat {
+    fn handle_midi_message(
+        &mut self,
+        channel: MidiChannel,
+        message: MidiMessage,
+        _midi_messages_fn: &mut MidiMessagesFn,
+    ) {
+        match message {
+            MidiMessage::NoteOn { key, vel } if vel.as_int() != 0 => {
+                let key = key.as_int();
+                self.e
+                    .held
+                    .retain(|n| !(n.channel == channel && n.key == key));
+                self.e.held.push(HeldNote {
+                    channel,
+                    key,
+                    velocity: vel.as_int(),
+                });
+            }
+            MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                let key = key.as_int();
+                self.e
+                    .held
+                    .retain(|n| !(n.channel == channel && n.key == key));
+            }
+            _ => {}
+        }
+        self.e.incoming.push((channel, message));
+    }
+}
+impl Displays for NoteRepeat {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        ui.label(format!(
+            "Note repeat: {:?} × {}",
+            self.division, self.ratchet
+        ))
+    }
+}
+impl Serializable for NoteRepeat {}
+impl Configurable for NoteRepeat {}
+impl Controls for NoteRepeat {
+    fn update_time(&mut self, range: &Range<MusicalTime>) {
+        self.e.range = range.clone();
+    }
+
+    fn work(&mut self, control_events_fn: &mut ControlEventsFn) {
+        // Key presses and releases go out first. A press is the roll's first
+        // hit, and a release ends whatever hit is sounding.
+        let mut just_pressed: Vec<(MidiChannel, u8)> = Vec::default();
+        for (channel, message) in std::mem::take(&mut self.e.incoming) {
+            match message {
+                MidiMessage::NoteOn { key, vel } if vel.as_int() != 0 => {
+                    let note = HeldNote {
+                        channel,
+                        key: key.as_int(),
+                        velocity: vel.as_int(),
+                    };
+                    let start = self.e.range.start;
+                    self.strike(&note, start, control_events_fn);
+                    just_pressed.push((channel, note.key));
+                }
+                MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                    self.end_note(channel, key.as_int(), control_events_fn);
+                }
+                _ => control_events_fn(self.uid, EntityEvent::Midi(channel, message)),
+            }
+        }
+
+        if !self.e.is_performing {
+            // Without a moving transport, there's no grid to repeat on, so
+            // each hit just lasts until its key is released.
+            return;
+        }
+
+        let range = self.e.range.clone();
+        let mut due: Vec<(MidiChannel, u8, MusicalTime)> = Vec::default();
+        self.e.sounding.retain(|(channel, key, time)| {
+            if *time < range.end {
+                due.push((*channel, *key, *time));
+                false
+            } else {
+                true
+            }
+        });
+        due.sort_by_key(|(_, _, time)| *time);
+        for (channel, key, _) in due {
+            self.send_note_off(channel, key, control_events_fn);
+        }
+
+        let interval = self.interval().total_units();
+        let mut boundary = range.start.total_units().div_ceil(interval) * interval;
+        while boundary < range.end.total_units() {
+            let time = MusicalTime::new_with_units(boundary);
+            for note in self.e.held.clone() {
+                // A press that lands on the grid is already this hit.
+                if time == range.start && just_pressed.contains(&(note.channel, note.key)) {
+                    continue;
+                }
+                self.strike(&note, time, control_events_fn);
+            }
+            boundary += interval;
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        true
+    }
+
+    fn play(&mut self) {
+        self.e.is_performing = true;
+    }
+
+    fn stop(&mut self) {
+        self.e.is_performing = false;
+        self.release_all(&mut |_, _| {});
+    }
+
+    fn skip_to_start(&mut self) {
+        self.e.range = MusicalTime::default()..MusicalTime::default();
+    }
+
+    fn is_performing(&self) -> bool {
+        self.e.is_performing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNARE: u8 = 38;
+    const CHANNEL: MidiChannel = MidiChannel(9);
+
+    fn press(r: &mut NoteRepeat, key: u8) {
+        r.handle_midi_message(
+            CHANNEL,
+            MidiMessage::NoteOn {
+                key: key.into(),
+                vel: 100.into(),
+            },
+            &mut |_, _| {},
+        );
+    }
+
+    fn release(r: &mut NoteRepeat, key: u8) {
+        r.handle_midi_message(
+            CHANNEL,
+            MidiMessage::NoteOff {
+                key: key.into(),
+                vel: 0.into(),
+            },
+            &mut |_, _| {},
+        );
+    }
+
+    // Runs one unit at a time and returns the times of the note-ons and
+    // note-offs, in units.
+    fn run(r: &mut NoteRepeat, range: Range<usize>) -> (Vec<usize>, Vec<usize>) {
+        let mut ons = Vec::default();
+        let mut offs = Vec::default();
+        for units in range {
+            r.update_time(
+                &(MusicalTime::new_with_units(units)..MusicalTime::new_with_units(units + 1)),
+            );
+            r.work(&mut |_, event| match event {
+                EntityEvent::Midi(_, MidiMessage::NoteOn { .. }) => ons.push(units),
+                EntityEvent::Midi(_, MidiMessage::NoteOff { .. }) => offs.push(units),
+                _ => {}
+            });
+        }
+        (ons, offs)
+    }
+
+    #[test]
+    fn held_note_rolls_on_the_grid() {
+        let mut r = NoteRepeat::default();
+        r.play();
+        let sixteenth = NoteDivision::Sixteenth.musical_time().total_units();
+        let beat = MusicalTime::UNITS_IN_BEAT;
+
+        // Pressed a little after the first sixteenth: it sounds right away,
+        // and then the repeats snap to the grid.
+        let pressed_at = sixteenth + 3;
+        let _ = run(&mut r, 0..pressed_at);
+        press(&mut r, SNARE);
+        assert!(r.is_repeating());
+        let last_hit = sixteenth * 3;
+        let (ons, offs) = run(&mut r, pressed_at..last_hit + 1);
+        assert_eq!(ons, vec![pressed_at, sixteenth * 2, last_hit]);
+        assert_eq!(offs.len(), 2, "each hit should end before the next");
+
+        // Releasing the key mid-hit ends that hit and the roll.
+        release(&mut r, SNARE);
+        assert!(!r.is_repeating());
+        let (ons, offs) = run(&mut r, last_hit + 1..beat * 2);
+        assert!(ons.is_empty());
+        assert_eq!(offs, vec![last_hit + 1]);
+    }
+
+    #[test]
+    fn ratchet_doubles_the_rate() {
+        let mut r = NoteRepeat::default();
+        r.play();
+        press(&mut r, SNARE);
+        let beat = MusicalTime::UNITS_IN_BEAT;
+        let (plain, _) = run(&mut r, 0..beat);
+        r.set_ratchet(2);
+        let (ratcheted, _) = run(&mut r, beat..beat * 2);
+        assert_eq!(plain.len(), 4);
+        assert_eq!(ratcheted.len(), 8);
+
+        r.set_ratchet(100);
+        assert_eq!(r.ratchet(), NoteRepeat::MAX_RATCHET);
+    }
+}
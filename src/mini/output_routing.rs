@@ -0,0 +1,145 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Sends one stereo bus to a pair of physical output channels.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OutputRoute {
+    /// Which bus. Bus 0 is the main mix.
+    pub bus: usize,
+
+    /// The output channel that the bus's left side goes to. The right side
+    /// goes to the next channel.
+    pub first_channel: usize,
+}
+
+/// Describes how stereo buses map onto an audio interface's output channels,
+/// for interfaces with more than two outputs. The default is two channels
+/// with the main mix on them, which is exactly the classic stereo path.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OutputRouting {
+    channel_count: usize,
+    routes: Vec<OutputRoute>,
+}
+impl Default for OutputRouting {
+    fn default() -> Self {
+        Self {
+            channel_count: 2,
+            routes: vec![OutputRoute {
+                bus: 0,
+                first_channel: 0,
+            }],
+        }
+    }
+}
+impl OutputRouting {
+    /// Creates a routing for the given number of output channels, with the
+    /// main mix on the first two.
+    pub fn new_with(channel_count: usize) -> Self {
+        Self {
+            channel_count: channel_count.max(1),
+            ..Default::default()
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    /// Routes a bus to a channel pair, replacing any earlier route for that
+    /// bus. Returns an error if the pair doesn't fit.
+    pub fn set_route(&mut self, bus: usize, first_channel: usize) -> anyhow::Result<()> {
+        if first_channel + 1 >= self.channel_count && self.channel_count > 1 {
+            return Err(anyhow::anyhow!(
+                "channels {}-{} don't exist on a {}-channel output",
+                first_channel + 1,
+                first_channel + 2,
+                self.channel_count
+            ));
+        }
+        self.routes.retain(|r| r.bus != bus);
+        self.routes.push(OutputRoute { bus, first_channel });
+        Ok(())
+    }
+
+    /// Stops sending a bus to any output.
+    pub fn remove_route(&mut self, bus: usize) {
+        self.routes.retain(|r| r.bus != bus);
+    }
+
+    #[allow(missing_docs)]
+    pub fn routes(&self) -> &[OutputRoute] {
+        &self.routes
+    }
+
+    /// Mixes one sample from each bus into one interleaved output frame.
+    /// `buses[i]` is bus i's sample. Buses sharing channels are summed, and
+    /// channels that nothing is routed to are silent. On a mono output, left
+    /// and right are averaged.
+    pub fn render_frame(&self, buses: &[StereoSample], frame: &mut [f32]) {
+        frame.iter_mut().for_each(|s| *s = 0.0);
+        for route in self.routes.iter() {
+            let Some(sample) = buses.get(route.bus) else {
+                continue;
+            };
+            if frame.len() == 1 {
+                frame[0] += ((sample.0 .0 + sample.1 .0) / 2.0) as f32;
+                continue;
+            }
+            if let Some(left) = frame.get_mut(route.first_channel) {
+                *left += sample.0 .0 as f32;
+            }
+            if let Some(right) = frame.get_mut(route.first_channel + 1) {
+                *right += sample.1 .0 as f32;
+            }
+        }
+    }
+
+    /// Fills an interleaved output buffer, like the one an audio callback
+    /// gets, one frame per element of `frames`.
+    pub fn render(&self, frames: &[Vec<StereoSample>], data: &mut [f32]) {
+        for (frame, buses) in data.chunks_exact_mut(self.channel_count).zip(frames) {
+            self.render_frame(buses, frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_plain_stereo() {
+        let routing = OutputRouting::default();
+        let mut frame = [0.0f32; 2];
+        routing.render_frame(&[StereoSample(Sample(0.25), Sample(-0.5))], &mut frame);
+        assert_eq!(frame, [0.25, -0.5]);
+    }
+
+    #[test]
+    fn click_to_outputs_three_and_four() {
+        const MAIN: usize = 0;
+        const CLICK: usize = 1;
+        let mut routing = OutputRouting::new_with(4);
+        assert!(routing.set_route(CLICK, 2).is_ok());
+        assert!(
+            routing.set_route(CLICK, 3).is_err(),
+            "channel 5 doesn't exist"
+        );
+
+        let mix = StereoSample(Sample(0.5), Sample(0.5));
+        let click = StereoSample(Sample(1.0), Sample(1.0));
+        let mut data = [0.0f32; 8];
+        routing.render(
+            &[vec![mix, click], vec![mix, StereoSample::SILENCE]],
+            &mut data,
+        );
+        assert_eq!(data, [0.5, 0.5, 1.0, 1.0, 0.5, 0.5, 0.0, 0.0]);
+
+        routing.remove_route(MAIN);
+        routing.render_frame(&[mix, click], &mut data[0..4]);
+        assert_eq!(data[0..4], [0.0, 0.0, 1.0, 1.0]);
+    }
+}
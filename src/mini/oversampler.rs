@@ -0,0 +1,203 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use serde::{Deserialize, Serialize};
+
+/// How many times faster than the engine sample rate an [Oversampler] runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OversampleFactor {
+    /// No oversampling. The processing function sees the original samples.
+    #[default]
+    One,
+    #[allow(missing_docs)]
+    Two,
+    #[allow(missing_docs)]
+    Four,
+}
+impl OversampleFactor {
+    #[allow(missing_docs)]
+    pub fn value(&self) -> usize {
+        match self {
+            OversampleFactor::One => 1,
+            OversampleFactor::Two => 2,
+            OversampleFactor::Four => 4,
+        }
+    }
+}
+
+/// Runs a nonlinear process (like a bitcrusher's sample-rate and bit-depth
+/// reduction) at a multiple of the engine sample rate, so that the harmonics
+/// it creates land above the engine's Nyquist frequency and can be filtered
+/// out before they fold back down as aliases.
+///
+/// Each input sample is zero-stuffed up to the higher rate and smoothed by an
+/// interpolation filter. After processing, a decimation filter removes
+/// everything above the original Nyquist frequency, and every Nth sample is
+/// kept. Both filters are windowed-sinc low-passes.
+///
+/// With [OversampleFactor::One], [Oversampler::process()] simply calls the
+/// processing function, so the output is identical to not using an
+/// [Oversampler] at all.
+#[derive(Clone, Debug, Default)]
+pub struct Oversampler {
+    factor: OversampleFactor,
+    coefficients: Vec<f64>,
+    interpolator: FirFilter,
+    decimator: FirFilter,
+}
+impl Oversampler {
+    /// Filter length per unit of oversampling factor. Longer filters have a
+    /// sharper cutoff but add latency.
+    const TAPS_PER_FACTOR: usize = 16;
+
+    /// The filter cutoff as a fraction of the original Nyquist frequency.
+    /// Slightly below 1.0 so that the transition band is mostly above the
+    /// audible range, where aliases would be heard.
+    const CUTOFF_RATIO: f64 = 0.9;
+
+    #[allow(missing_docs)]
+    pub fn new_with(factor: OversampleFactor) -> Self {
+        let mut r = Self::default();
+        r.set_factor(factor);
+        r
+    }
+
+    #[allow(missing_docs)]
+    pub fn factor(&self) -> OversampleFactor {
+        self.factor
+    }
+
+    /// Changes the factor. This resets the filters' state.
+    pub fn set_factor(&mut self, factor: OversampleFactor) {
+        self.factor = factor;
+        self.coefficients = if factor == OversampleFactor::One {
+            Vec::default()
+        } else {
+            Self::design_low_pass(factor.value())
+        };
+        self.interpolator = FirFilter::new_with(self.coefficients.len());
+        self.decimator = FirFilter::new_with(self.coefficients.len());
+    }
+
+    /// Runs one input sample through `f` at the oversampled rate, and returns
+    /// one output sample.
+    pub fn process(&mut self, input: f64, mut f: impl FnMut(f64) -> f64) -> f64 {
+        let factor = self.factor.value();
+        if factor == 1 {
+            return f(input);
+        }
+        let mut output = 0.0;
+        for i in 0..factor {
+            // Zero-stuffing spreads the signal's energy across `factor`
+            // samples, so the first one is scaled up to keep unity gain.
+            let stuffed = if i == 0 { input * factor as f64 } else { 0.0 };
+            let upsampled = self.interpolator.filter(&self.coefficients, stuffed);
+            output = self.decimator.filter(&self.coefficients, f(upsampled));
+        }
+        output
+    }
+
+    // A Blackman-windowed sinc low-pass at the original Nyquist frequency,
+    // expressed at the oversampled rate, normalized to unity DC gain.
+    fn design_low_pass(factor: usize) -> Vec<f64> {
+        let taps = Self::TAPS_PER_FACTOR * factor + 1;
+        let cutoff = Self::CUTOFF_RATIO * 0.5 / factor as f64;
+        let middle = (taps - 1) as f64 / 2.0;
+        let mut coefficients: Vec<f64> = (0..taps)
+            .map(|n| {
+                let x = n as f64 - middle;
+                let sinc = if x == 0.0 {
+                    2.0 * cutoff
+                } else {
+                    (2.0 * std::f64::consts::PI * cutoff * x).sin() / (std::f64::consts::PI * x)
+                };
+                let phase = 2.0 * std::f64::consts::PI * n as f64 / (taps - 1) as f64;
+                let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+                sinc * window
+            })
+            .collect();
+        let sum: f64 = coefficients.iter().sum();
+        coefficients.iter_mut().for_each(|c| *c /= sum);
+        coefficients
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct FirFilter {
+    history: Vec<f64>,
+    position: usize,
+}
+impl FirFilter {
+    fn new_with(taps: usize) -> Self {
+        Self {
+            history: vec![0.0; taps],
+            position: 0,
+        }
+    }
+
+    fn filter(&mut self, coefficients: &[f64], input: f64) -> f64 {
+        let taps = self.history.len();
+        self.history[self.position] = input;
+        let mut sum = 0.0;
+        for (i, coefficient) in coefficients.iter().enumerate() {
+            sum += coefficient * self.history[(self.position + taps - i) % taps];
+        }
+        self.position = (self.position + 1) % taps;
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crush(x: f64) -> f64 {
+        (x * 4.0).round() / 4.0
+    }
+
+    #[test]
+    fn factor_one_is_identical_to_no_oversampling() {
+        let mut oversampler = Oversampler::new_with(OversampleFactor::One);
+        for i in 0..100 {
+            let x = (i as f64 * 0.37).sin();
+            assert_eq!(oversampler.process(x, crush), crush(x));
+        }
+    }
+
+    #[test]
+    fn oversampling_preserves_low_frequencies() {
+        for factor in [OversampleFactor::Two, OversampleFactor::Four] {
+            let mut oversampler = Oversampler::new_with(factor);
+            let mut last = 0.0;
+            for _ in 0..200 {
+                last = oversampler.process(0.5, |x| x);
+            }
+            assert!(
+                (last - 0.5).abs() < 0.001,
+                "{factor:?}: DC should pass at unity gain, got {last}"
+            );
+        }
+    }
+
+    #[test]
+    fn oversampling_removes_content_above_original_nyquist() {
+        for factor in [OversampleFactor::Two, OversampleFactor::Four] {
+            let mut oversampler = Oversampler::new_with(factor);
+
+            // The process emits a tone at the oversampled rate's Nyquist
+            // frequency. Without the decimation filter, it would alias down
+            // to full-scale DC.
+            let mut sign = 1.0;
+            let mut peak: f64 = 0.0;
+            for i in 0..200 {
+                let output = oversampler.process(0.0, |_| {
+                    sign = -sign;
+                    sign
+                });
+                if i > 100 {
+                    peak = peak.max(output.abs());
+                }
+            }
+            assert!(peak < 0.01, "{factor:?}: alias leaked through at {peak}");
+        }
+    }
+}
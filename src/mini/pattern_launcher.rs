@@ -0,0 +1,203 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// The grid that queued patterns snap to when they launch.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LaunchQuantum {
+    /// Launch on the next beat.
+    Beat,
+    /// Launch on the next downbeat.
+    #[default]
+    Bar,
+}
+impl LaunchQuantum {
+    /// The length of one grid step, in [MusicalTime] units.
+    pub fn units(&self, time_signature: &TimeSignature) -> usize {
+        match self {
+            LaunchQuantum::Beat => MusicalTime::UNITS_IN_BEAT,
+            LaunchQuantum::Bar => time_signature.top.max(1) * MusicalTime::UNITS_IN_BEAT,
+        }
+    }
+
+    /// The length of one grid step, in beats.
+    pub fn beats(&self, time_signature: &TimeSignature) -> usize {
+        self.units(time_signature) / MusicalTime::UNITS_IN_BEAT
+    }
+
+    /// The first grid boundary strictly after `now`. A time exactly on a
+    /// boundary gets the next one, because the slice at `now` may already be
+    /// playing.
+    pub fn next_boundary_after(
+        &self,
+        now: MusicalTime,
+        time_signature: &TimeSignature,
+    ) -> MusicalTime {
+        let quantum = self.units(time_signature);
+        let boundaries = now.total_units() / quantum + 1;
+        MusicalTime::new_with_units(boundaries * quantum)
+    }
+}
+
+/// [PatternLauncher] handles Ableton-style clip launching for a sequencer. A
+/// queued pattern doesn't start right away; the current one keeps playing
+/// until the next bar (or beat) boundary, and the new one takes over exactly
+/// there. Queuing another pattern before that happens replaces the queued one.
+///
+/// The launcher only decides which pattern is current. The sequencer that owns
+/// it calls [PatternLauncher::update_time()] with each time slice before doing
+/// its own work, and plays the current pattern relative to
+/// [PatternLauncher::pattern_start()].
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PatternLauncher<T> {
+    quantum: LaunchQuantum,
+    time_signature: TimeSignature,
+    current: Option<T>,
+    pattern_start: MusicalTime,
+
+    #[serde(skip)]
+    queued: Option<(T, MusicalTime)>,
+}
+impl<T: Clone> PatternLauncher<T> {
+    #[allow(missing_docs)]
+    pub fn new_with(quantum: LaunchQuantum, time_signature: TimeSignature) -> Self {
+        Self {
+            quantum,
+            time_signature,
+            current: None,
+            pattern_start: MusicalTime::default(),
+            queued: None,
+        }
+    }
+
+    /// Schedules `pattern_id` to start at the first boundary after `now`, and
+    /// returns that time. If nothing is playing yet, there's nothing to wait
+    /// for, so the pattern becomes current immediately, starting at `now`.
+    pub fn queue_pattern(&mut self, pattern_id: T, now: MusicalTime) -> MusicalTime {
+        if self.current.is_none() {
+            self.current = Some(pattern_id);
+            self.pattern_start = now;
+            self.queued = None;
+            return now;
+        }
+        let launch_time = self.quantum.next_boundary_after(now, &self.time_signature);
+        self.queued = Some((pattern_id, launch_time));
+        launch_time
+    }
+
+    /// Forgets the queued pattern, if any. The current one keeps playing.
+    pub fn cancel_queued_pattern(&mut self) {
+        self.queued = None;
+    }
+
+    /// Swaps in the queued pattern if its launch time has arrived by the end
+    /// of `range`. Returns the launch time if that happened.
+    pub fn update_time(&mut self, range: &Range<MusicalTime>) -> Option<MusicalTime> {
+        let launch_time = self.queued.as_ref()?.1;
+        if launch_time >= range.end {
+            return None;
+        }
+        let (pattern_id, launch_time) = self.queued.take()?;
+        self.current = Some(pattern_id);
+        self.pattern_start = launch_time;
+        Some(launch_time)
+    }
+
+    /// The pattern that's playing now.
+    pub fn current(&self) -> Option<&T> {
+        self.current.as_ref()
+    }
+
+    /// The pattern waiting to launch, and when it will.
+    pub fn queued(&self) -> Option<(&T, MusicalTime)> {
+        self.queued.as_ref().map(|(id, time)| (id, *time))
+    }
+
+    /// When the current pattern started. A sequencer plays the current pattern
+    /// from its beginning at this time.
+    pub fn pattern_start(&self) -> MusicalTime {
+        self.pattern_start
+    }
+
+    #[allow(missing_docs)]
+    pub fn quantum(&self) -> LaunchQuantum {
+        self.quantum
+    }
+
+    /// Changes the launch grid. A pattern that's already queued keeps its
+    /// launch time.
+    pub fn set_quantum(&mut self, quantum: LaunchQuantum) {
+        self.quantum = quantum;
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beats(n: usize) -> MusicalTime {
+        MusicalTime::new_with_beats(n)
+    }
+
+    fn half_beat() -> MusicalTime {
+        MusicalTime::new_with_units(MusicalTime::UNITS_IN_BEAT / 2)
+    }
+
+    #[test]
+    fn queued_pattern_launches_on_next_bar() {
+        let mut launcher = PatternLauncher::new_with(LaunchQuantum::Bar, TimeSignature::default());
+        assert_eq!(launcher.queue_pattern("intro", beats(0)), beats(0));
+        assert_eq!(launcher.current(), Some(&"intro"));
+
+        // Queued halfway through the first bar, so it waits for beat 4.
+        let now = beats(1) + half_beat();
+        assert_eq!(launcher.queue_pattern("verse", now), beats(4));
+        assert_eq!(launcher.update_time(&(now..beats(2))), None);
+        assert_eq!(launcher.update_time(&(beats(3)..beats(4))), None);
+        assert_eq!(launcher.current(), Some(&"intro"));
+
+        assert_eq!(launcher.update_time(&(beats(4)..beats(5))), Some(beats(4)));
+        assert_eq!(launcher.current(), Some(&"verse"));
+        assert_eq!(launcher.pattern_start(), beats(4));
+        assert!(launcher.queued().is_none());
+    }
+
+    #[test]
+    fn second_queue_replaces_first() {
+        let mut launcher = PatternLauncher::new_with(LaunchQuantum::Bar, TimeSignature::default());
+        launcher.queue_pattern(1, beats(0));
+        launcher.queue_pattern(2, beats(1));
+        launcher.queue_pattern(3, beats(2));
+        assert_eq!(launcher.queued(), Some((&3, beats(4))));
+
+        launcher.update_time(&(beats(4)..beats(5)));
+        assert_eq!(launcher.current(), Some(&3));
+        assert_eq!(launcher.update_time(&(beats(8)..beats(9))), None);
+        assert_eq!(
+            launcher.current(),
+            Some(&3),
+            "pattern 2 shouldn't launch later"
+        );
+    }
+
+    #[test]
+    fn beat_quantum_launches_sooner() {
+        let mut launcher = PatternLauncher::new_with(LaunchQuantum::Beat, TimeSignature::default());
+        launcher.queue_pattern(1, beats(0));
+
+        // Exactly on a boundary, the pattern waits for the next one, because
+        // the slice at `now` may already be playing.
+        assert_eq!(launcher.queue_pattern(2, beats(1)), beats(2));
+        assert_eq!(launcher.queue_pattern(2, beats(1) + half_beat()), beats(2));
+
+        launcher.set_quantum(LaunchQuantum::Bar);
+        assert_eq!(launcher.queue_pattern(2, beats(5)), beats(8));
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::midi::prelude::*;
+use ensnare_core::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Tracks an instrument's pitch-bend wheel. An instrument holds one, passes it
+/// every incoming MIDI message, and multiplies its voices' frequencies by
+/// [PitchBender::frequency_ratio()] on every tick, so that held notes follow
+/// the wheel continuously.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PitchBender {
+    /// How far the wheel bends at its extremes, in semitones.
+    pitch_bend_range: f64,
+
+    /// The wheel position, -1.0..=1.0.
+    #[serde(skip)]
+    bend: f64,
+}
+impl Default for PitchBender {
+    fn default() -> Self {
+        Self {
+            pitch_bend_range: Self::DEFAULT_RANGE_SEMITONES,
+            bend: 0.0,
+        }
+    }
+}
+impl PitchBender {
+    /// The General MIDI default bend range.
+    pub const DEFAULT_RANGE_SEMITONES: f64 = 2.0;
+
+    /// Updates the wheel position if the message is a pitch bend. Returns
+    /// whether it was.
+    pub fn handle_midi_message(&mut self, message: &MidiMessage) -> bool {
+        if let MidiMessage::PitchBend { bend } = message {
+            self.bend = bend.as_f64();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The amount to multiply a note's frequency by.
+    pub fn frequency_ratio(&self) -> f64 {
+        2.0f64.powf(self.bend * self.pitch_bend_range / 12.0)
+    }
+
+    /// Applies the current bend to a frequency.
+    pub fn bend_frequency(&self, frequency: FrequencyHz) -> FrequencyHz {
+        FrequencyHz(frequency.0 * self.frequency_ratio())
+    }
+
+    #[allow(missing_docs)]
+    pub fn pitch_bend_range(&self) -> f64 {
+        self.pitch_bend_range
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_pitch_bend_range(&mut self, semitones: f64) {
+        self.pitch_bend_range = semitones.clamp(0.0, 48.0);
+    }
+
+    /// The current wheel position, -1.0..=1.0.
+    pub fn bend(&self) -> f64 {
+        self.bend
+    }
+
+    /// Recenters the wheel, as on a reset or all-notes-off.
+    pub fn reset(&mut self) {
+        self.bend = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::PitchBend;
+
+    #[test]
+    fn bends_by_configured_range() {
+        let mut pb = PitchBender::default();
+        assert_eq!(pb.pitch_bend_range(), 2.0);
+        assert_eq!(pb.frequency_ratio(), 1.0);
+
+        assert!(pb.handle_midi_message(&MidiMessage::PitchBend {
+            bend: PitchBend::from_f64(1.0)
+        }));
+        let up = pb.bend_frequency(FrequencyHz(440.0)).0;
+        let two_semitones_up = 440.0 * 2.0f64.powf(2.0 / 12.0);
+        assert!(
+            (up - two_semitones_up).abs() < 0.1,
+            "full bend should be two semitones: {up}"
+        );
+
+        // The wheel is continuous, so halfway should be halfway in pitch.
+        pb.handle_midi_message(&MidiMessage::PitchBend {
+            bend: PitchBend::from_f64(-0.5),
+        });
+        let down = pb.bend_frequency(FrequencyHz(440.0)).0;
+        assert!((down - 440.0 * 2.0f64.powf(-1.0 / 12.0)).abs() < 0.1);
+
+        pb.handle_midi_message(&MidiMessage::PitchBend {
+            bend: PitchBend::mid_raw_value(),
+        });
+        assert!((pb.frequency_ratio() - 1.0).abs() < 0.001);
+
+        assert!(!pb.handle_midi_message(&MidiMessage::NoteOn {
+            key: 60.into(),
+            vel: 127.into()
+        }));
+    }
+
+    #[test]
+    fn range_is_configurable() {
+        let mut pb = PitchBender::default();
+        pb.set_pitch_bend_range(12.0);
+        pb.handle_midi_message(&MidiMessage::PitchBend {
+            bend: PitchBend::from_f64(1.0),
+        });
+        assert!((pb.frequency_ratio() - 2.0).abs() < 0.01);
+        pb.reset();
+        assert_eq!(pb.frequency_ratio(), 1.0);
+    }
+}
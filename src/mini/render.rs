@@ -0,0 +1,111 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::midi::prelude::*;
+use ensnare_core::prelude::*;
+use ensnare_core::traits::{Configurable, Generates, HandlesMidi, TransformsAudio};
+use std::f64::consts::TAU;
+
+/// Anything that [render_entity_for()] can play as an instrument.
+pub trait RenderableInstrument: Generates<StereoSample> + HandlesMidi + Configurable {}
+impl<T: Generates<StereoSample> + HandlesMidi + Configurable> RenderableInstrument for T {}
+
+/// Anything that [render_entity_for()] can feed a test signal through.
+pub trait RenderableEffect: TransformsAudio + Configurable {}
+impl<T: TransformsAudio + Configurable> RenderableEffect for T {}
+
+/// The entity that [render_entity_for()] should render, and how to drive it.
+pub enum EntityUnderTest<'a> {
+    /// An instrument, with the MIDI key to hold down for the whole render, if
+    /// any.
+    Instrument(&'a mut dyn RenderableInstrument, Option<u8>),
+    /// An effect, which gets a [TEST_SIGNAL_FREQUENCY] sine at half of full
+    /// scale as its input.
+    Effect(&'a mut dyn RenderableEffect),
+}
+
+/// The frequency of the sine that [render_entity_for()] feeds to effects.
+pub const TEST_SIGNAL_FREQUENCY: f64 = 440.0;
+
+/// Renders a single entity on its own, the way the old `write_source_to_file()`
+/// and `write_effect_to_file()` helpers did, so that each entity's tests can
+/// audition it the same way. The entity gets `sample_rate` first.
+pub fn render_entity_for(
+    entity: EntityUnderTest,
+    seconds: f64,
+    sample_rate: SampleRate,
+) -> Vec<StereoSample> {
+    let frame_count = (seconds.max(0.0) * sample_rate.0 as f64).round() as usize;
+    let mut samples = vec![StereoSample::SILENCE; frame_count];
+    match entity {
+        EntityUnderTest::Instrument(instrument, key) => {
+            instrument.update_sample_rate(sample_rate);
+            if let Some(key) = key {
+                instrument.handle_midi_message(
+                    MidiChannel(0),
+                    MidiMessage::NoteOn {
+                        key: key.into(),
+                        vel: 127.into(),
+                    },
+                    &mut |_, _| {},
+                );
+            }
+            instrument.generate_batch_values(&mut samples);
+        }
+        EntityUnderTest::Effect(effect) => {
+            effect.update_sample_rate(sample_rate);
+            for (i, sample) in samples.iter_mut().enumerate() {
+                let input =
+                    0.5 * (TAU * TEST_SIGNAL_FREQUENCY * i as f64 / sample_rate.0 as f64).sin();
+                *sample = effect.transform_audio(StereoSample::from(input));
+            }
+        }
+    }
+    samples
+}
+
+/// The basic sanity check for a rendered entity: it made some sound, and none
+/// of it is NaN, infinite, or far past full scale.
+pub fn assert_audible_and_bounded(samples: &[StereoSample]) {
+    const LIMIT: f64 = 4.0;
+    assert!(
+        samples
+            .iter()
+            .any(|s| s.0 .0.abs() > 1.0e-6 || s.1 .0.abs() > 1.0e-6),
+        "the render is silent"
+    );
+    for (i, s) in samples.iter().enumerate() {
+        assert!(
+            s.0 .0.is_finite() && s.1 .0.is_finite(),
+            "frame {i} isn't finite: {s:?}"
+        );
+        assert!(
+            s.0 .0.abs() <= LIMIT && s.1 .0.abs() <= LIMIT,
+            "frame {i} is out of bounds: {s:?}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mini::{AudioInput, RingModulator};
+
+    #[test]
+    fn renders_the_requested_length() {
+        let mut effect = RingModulator::default();
+        let samples =
+            render_entity_for(EntityUnderTest::Effect(&mut effect), 0.5, SampleRate(1000));
+        assert_eq!(samples.len(), 500);
+        assert_eq!(effect.sample_rate(), SampleRate(1000));
+
+        // An unconnected input is silent, but it still renders.
+        let mut instrument = AudioInput::default();
+        let samples = render_entity_for(
+            EntityUnderTest::Instrument(&mut instrument, Some(60)),
+            0.25,
+            SampleRate(1000),
+        );
+        assert_eq!(samples.len(), 250);
+        assert!(samples.iter().all(|s| *s == StereoSample::SILENCE));
+    }
+}
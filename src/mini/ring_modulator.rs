@@ -0,0 +1,239 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use eframe::egui::Ui;
+use ensnare_core::prelude::*;
+use ensnare_core::traits::{Configurable, Displays, Serializable, TransformsAudio};
+use ensnare_proc_macros::{Control, IsEffect, Uid};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+
+/// The shape of a [RingModulator]'s carrier.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CarrierWaveform {
+    /// Smooth and bell-like.
+    #[default]
+    Sine,
+    /// Harsher, because it adds the input's odd harmonics as sidebands.
+    Square,
+}
+impl From<ControlValue> for CarrierWaveform {
+    fn from(value: ControlValue) -> Self {
+        if value.0 < 0.5 {
+            CarrierWaveform::Sine
+        } else {
+            CarrierWaveform::Square
+        }
+    }
+}
+impl From<CarrierWaveform> for ControlValue {
+    fn from(value: CarrierWaveform) -> Self {
+        match value {
+            CarrierWaveform::Sine => ControlValue(0.0),
+            CarrierWaveform::Square => ControlValue(1.0),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RingModulatorEphemerals {
+    sample_rate: SampleRate,
+
+    /// Position within the carrier's cycle, 0.0..1.0.
+    phase: f64,
+
+    /// The carrier's value for the current frame.
+    carrier: f64,
+}
+
+/// [RingModulator] multiplies its input by an internal oscillator. The output
+/// contains the sums and differences of the input's frequencies and the
+/// carrier's, which gives the classic metallic, robotic sound.
+#[derive(Serialize, Deserialize, Clone, Control, IsEffect, Debug, Uid)]
+pub struct RingModulator {
+    uid: Uid,
+
+    #[control]
+    carrier_frequency: FrequencyHz,
+
+    #[control]
+    waveform: CarrierWaveform,
+
+    /// 0.0 is all dry, 1.0 is all modulated.
+    #[control]
+    mix: Normal,
+
+    #[serde(skip)]
+    e: RingModulatorEphemerals,
+}
+impl Default for RingModulator {
+    fn default() -> Self {
+        Self::new_with(FrequencyHz(440.0), CarrierWaveform::Sine, Normal::from(1.0))
+    }
+}
+impl RingModulator {
+    #[allow(missing_docs)]
+    pub fn new_with(
+        carrier_frequency: FrequencyHz,
+        waveform: CarrierWaveform,
+        mix: Normal,
+    ) -> Self {
+        Self {
+            uid: Default::default(),
+            carrier_frequency,
+            waveform,
+            mix,
+            e: RingModulatorEphemerals {
+                sample_rate: SampleRate::DEFAULT,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn carrier_frequency(&self) -> FrequencyHz {
+        self.carrier_frequency
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_carrier_frequency(&mut self, carrier_frequency: FrequencyHz) {
+        self.carrier_frequency = carrier_frequency;
+    }
+
+    #[allow(missing_docs)]
+    pub fn waveform(&self) -> CarrierWaveform {
+        self.waveform
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_waveform(&mut self, waveform: CarrierWaveform) {
+        self.waveform = waveform;
+    }
+
+    #[allow(missing_docs)]
+    pub fn mix(&self) -> Normal {
+        self.mix
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_mix(&mut self, mix: Normal) {
+        self.mix = mix;
+    }
+
+    fn carrier_at(&self, phase: f64) -> f64 {
+        match self.waveform {
+            CarrierWaveform::Sine => (phase * TAU).sin(),
+            CarrierWaveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+
+    // Computes the carrier for this frame and moves on to the next one.
+    fn advance_carrier(&mut self) {
+        self.e.carrier = self.carrier_at(self.e.phase);
+        if self.e.sample_rate.0 != 0 {
+            self.e.phase = (self.e.phase + self.carrier_frequency.0 / self.e.sample_rate.0 as f64)
+                .rem_euclid(1.0);
+        }
+    }
+
+    fn modulate(&self, input: Sample) -> Sample {
+        let mix = self.mix.0;
+        Sample(input.0 * (1.0 - mix) + input.0 * self.e.carrier * mix)
+    }
+}
+impl TransformsAudio for RingModulator {
+    // Both channels share one carrier, so the carrier advances once per frame
+    // rather than once per channel.
+    fn transform_audio(&mut self, input_sample: StereoSample) -> StereoSample {
+        self.advance_carrier();
+        StereoSample(self.modulate(input_sample.0), self.modulate(input_sample.1))
+    }
+
+    fn transform_channel(&mut self, _channel: usize, input_sample: Sample) -> Sample {
+        self.modulate(input_sample)
+    }
+}
+impl Configurable for RingModulator {
+    fn sample_rate(&self) -> SampleRate {
+        self.e.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.e.sample_rate = sample_rate;
+    }
+}
+impl Serializable for RingModulator {}
+impl Displays for RingModulator {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        ui.label(format!(
+            "Ring modulator: {:.1} Hz {:?}",
+            self.carrier_frequency.0, self.waveform
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mini::{assert_audible_and_bounded, render_entity_for, EntityUnderTest};
+
+    fn run(rm: &mut RingModulator, frames: usize) -> Vec<f64> {
+        (0..frames)
+            .map(|_| rm.transform_audio(StereoSample::from(1.0)).0 .0)
+            .collect()
+    }
+
+    #[test]
+    fn multiplies_by_carrier() {
+        // A carrier at a quarter of the sample rate takes four frames per
+        // cycle.
+        let mut rm = RingModulator::new_with(
+            FrequencyHz(1000.0),
+            CarrierWaveform::Sine,
+            Normal::from(1.0),
+        );
+        rm.update_sample_rate(SampleRate(4000));
+        let output = run(&mut rm, 8);
+        let expected = [0.0, 1.0, 0.0, -1.0, 0.0, 1.0, 0.0, -1.0];
+        for (actual, expected) in output.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1.0e-9, "{output:?}");
+        }
+
+        rm.set_waveform(CarrierWaveform::Square);
+        assert_eq!(run(&mut rm, 4), vec![1.0, 1.0, -1.0, -1.0]);
+    }
+
+    #[test]
+    fn mix_blends_dry_and_wet() {
+        let mut rm = RingModulator::new_with(
+            FrequencyHz(1000.0),
+            CarrierWaveform::Square,
+            Normal::from(0.0),
+        );
+        rm.update_sample_rate(SampleRate(4000));
+        assert_eq!(run(&mut rm, 4), vec![1.0; 4], "fully dry");
+
+        rm.set_mix(Normal::from(0.5));
+        assert_eq!(run(&mut rm, 4), vec![1.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn waveform_converts_to_and_from_control_values() {
+        for waveform in [CarrierWaveform::Sine, CarrierWaveform::Square] {
+            let value: ControlValue = waveform.into();
+            assert_eq!(CarrierWaveform::from(value), waveform);
+        }
+    }
+
+    #[test]
+    fn renders_sanely() {
+        let mut rm = RingModulator::default();
+        let samples = render_entity_for(EntityUnderTest::Effect(&mut rm), 0.1, SampleRate::DEFAULT);
+        assert_audible_and_bounded(&samples);
+    }
+}
@@ -1,5 +1,7 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
+use ensnare_core::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug)]
@@ -16,3 +18,100 @@ impl Default for Rng {
         ))
     }
 }
+impl Rng {
+    /// Creates an [Rng] that always produces the same sequence for the same
+    /// seed. Use this whenever the output ends up in a render.
+    pub fn new_with_seed(seed: u128) -> Self {
+        Self(oorandom::Rand64::new(seed))
+    }
+}
+
+/// Anything whose output depends on a random sequence, and that can restart
+/// that sequence from a given seed.
+pub trait Seeded {
+    /// Restarts the random sequence from `seed`.
+    fn reseed(&mut self, seed: u64);
+}
+
+/// [SeedBank] holds a project's master seed and derives a seed for each entity
+/// from it. Whoever owns the entities keeps one [SeedBank] with the project
+/// and reseeds each [Seeded] entity from it before rendering, so that the same
+/// project always renders the same way, noise and all.
+///
+/// An entity's seed depends only on the master seed and the entity's [Uid].
+/// Adding or removing other entities doesn't change it, and neither does the
+/// order in which entities are reseeded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeedBank {
+    master_seed: u64,
+}
+impl SeedBank {
+    #[allow(missing_docs)]
+    pub fn new_with(master_seed: u64) -> Self {
+        Self { master_seed }
+    }
+
+    /// A [SeedBank] with an arbitrary master seed, for a new project.
+    pub fn new_random() -> Self {
+        Self::new_with(Rng::default().0.rand_u64())
+    }
+
+    #[allow(missing_docs)]
+    pub fn master_seed(&self) -> u64 {
+        self.master_seed
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_master_seed(&mut self, master_seed: u64) {
+        self.master_seed = master_seed;
+    }
+
+    /// The seed for the entity with the given [Uid].
+    pub fn seed_for(&self, uid: Uid) -> u64 {
+        Self::mix(self.master_seed ^ Self::mix(uid.0 as u64))
+    }
+
+    /// A fresh [Rng] for the entity with the given [Uid].
+    pub fn rng_for(&self, uid: Uid) -> Rng {
+        Rng::new_with_seed(self.seed_for(uid) as u128)
+    }
+
+    /// Reseeds `entity` with the seed for `uid`.
+    pub fn reseed(&self, uid: Uid, entity: &mut dyn Seeded) {
+        entity.reseed(self.seed_for(uid));
+    }
+
+    // SplitMix64's finalizer. Neighboring inputs, like consecutive Uids,
+    // come out unrelated.
+    pub(super) fn mix(value: u64) -> u64 {
+        let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeds_depend_on_master_and_uid_only() {
+        let bank = SeedBank::new_with(1234);
+        assert_eq!(
+            bank.seed_for(Uid(1)),
+            SeedBank::new_with(1234).seed_for(Uid(1))
+        );
+        assert_ne!(bank.seed_for(Uid(1)), bank.seed_for(Uid(2)));
+        assert_ne!(
+            bank.seed_for(Uid(1)),
+            SeedBank::new_with(1235).seed_for(Uid(1))
+        );
+
+        let mut a = bank.rng_for(Uid(3));
+        let mut b = bank.rng_for(Uid(3));
+        for _ in 0..16 {
+            assert_eq!(a.0.rand_u64(), b.0.rand_u64());
+        }
+    }
+}
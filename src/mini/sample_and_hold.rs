@@ -0,0 +1,201 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use super::{
+    rng::{Rng, Seeded},
+    tempo_delay::NoteDivision,
+};
+use eframe::egui::Ui;
+use ensnare_core::prelude::*;
+use ensnare_core::traits::{
+    Configurable, ControlEventsFn, Controls, Displays, EntityEvent, HandlesMidi, Serializable,
+};
+use ensnare_proc_macros::{Control, IsController, Uid};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Default)]
+pub struct SampleAndHoldLfoEphemerals {
+    range: Range<MusicalTime>,
+    last_step: Option<usize>,
+    is_performing: bool,
+}
+
+/// [SampleAndHoldLfo] is a stepped random modulation source, the classic
+/// sample-and-hold of acid basslines. At the start of each step it picks a new
+/// random value and holds it until the next one. Steps are locked to the song's
+/// beat grid, so linking the output to a filter cutoff gives rhythmic,
+/// stepped movement.
+///
+/// Each step's value depends only on the seed and the step's position in the
+/// song, so a render is the same every time, even after seeking.
+#[derive(Serialize, Deserialize, Clone, Control, IsController, Debug, Default, Uid)]
+pub struct SampleAndHoldLfo {
+    uid: Uid,
+
+    /// How long each value is held.
+    division: NoteDivision,
+
+    seed: u64,
+
+    #[serde(skip)]
+    e: SampleAndHoldLfoEphemerals,
+}
+impl SampleAndHoldLfo {
+    #[allow(missing_docs)]
+    pub fn new_with(division: NoteDivision, seed: u64) -> Self {
+        Self {
+            division,
+            seed,
+            ..Default::default()
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn division(&self) -> NoteDivision {
+        self.division
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_division(&mut self, division: NoteDivision) {
+        self.division = division;
+        self.e.last_step = None;
+    }
+
+    /// The value held during the given step, 0.0..1.0.
+    pub fn value_for_step(&self, step: usize) -> f64 {
+        Rng::new_with_seed(((self.seed as u128) << 64) | step as u128)
+            .0
+            .rand_float()
+    }
+
+    fn step_at(&self, time: MusicalTime) -> usize {
+        time.total_units() / self.division.musical_time().total_units()
+    }
+}
+impl Seeded for SampleAndHoldLfo {
+    fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.e.last_step = None;
+    }
+}
+impl HandlesMidi for SampleAndHoldLfo {}
+impl Displays for SampleAndHoldLfo {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        ui.label(format!("Sample & hold: {:?}", self.division))
+    }
+}
+impl Serializable for SampleAndHoldLfo {}
+impl Configurable for SampleAndHoldLfo {}
+impl Controls for SampleAndHoldLfo {
+    fn update_time(&mut self, range: &Range<MusicalTime>) {
+        self.e.range = range.clone();
+    }
+
+    fn work(&mut self, control_events_fn: &mut ControlEventsFn) {
+        if !self.e.is_performing {
+            return;
+        }
+        let step = self.step_at(self.e.range.start);
+        if self.e.last_step != Some(step) {
+            self.e.last_step = Some(step);
+            control_events_fn(
+                self.uid,
+                EntityEvent::Control(ControlValue(self.value_for_step(step))),
+            );
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        // It runs forever.
+        true
+    }
+
+    fn play(&mut self) {
+        self.e.is_performing = true;
+    }
+
+    fn stop(&mut self) {
+        self.e.is_performing = false;
+    }
+
+    fn skip_to_start(&mut self) {
+        self.e.range = MusicalTime::default()..MusicalTime::default();
+        self.e.last_step = None;
+    }
+
+    fn is_performing(&self) -> bool {
+        self.e.is_performing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mini::SeedBank;
+
+    fn run(lfo: &mut SampleAndHoldLfo, beats: usize) -> Vec<(usize, f64)> {
+        let mut values = Vec::default();
+        let slice = MusicalTime::UNITS_IN_BEAT / 64;
+        for units in (0..beats * MusicalTime::UNITS_IN_BEAT).step_by(slice) {
+            lfo.update_time(
+                &(MusicalTime::new_with_units(units)..MusicalTime::new_with_units(units + slice)),
+            );
+            lfo.work(&mut |_, event| {
+                if let EntityEvent::Control(value) = event {
+                    values.push((units, value.0));
+                }
+            });
+        }
+        values
+    }
+
+    #[test]
+    fn holds_a_new_value_each_step() {
+        let mut lfo = SampleAndHoldLfo::new_with(NoteDivision::Sixteenth, 42);
+        lfo.play();
+        let values = run(&mut lfo, 2);
+        assert_eq!(values.len(), 8, "two beats of sixteenths");
+        for (i, (units, value)) in values.iter().enumerate() {
+            assert_eq!(*units / (MusicalTime::UNITS_IN_BEAT / 4), i);
+            assert!((0.0..1.0).contains(value));
+        }
+        assert!(
+            values.windows(2).any(|w| w[0].1 != w[1].1),
+            "values should vary"
+        );
+    }
+
+    #[test]
+    fn replays_identically() {
+        let mut lfo = SampleAndHoldLfo::new_with(NoteDivision::Eighth, 7);
+        lfo.play();
+        let first = run(&mut lfo, 4);
+        lfo.skip_to_start();
+        let second = run(&mut lfo, 4);
+        assert_eq!(first, second);
+
+        let mut stopped = SampleAndHoldLfo::new_with(NoteDivision::Eighth, 7);
+        assert!(run(&mut stopped, 4).is_empty());
+    }
+
+    #[test]
+    fn seed_bank_makes_renders_repeatable() {
+        // Two separate loads of the same project, whatever seeds the LFOs
+        // were constructed with, render identically once the bank has
+        // reseeded them.
+        let bank = SeedBank::new_with(99);
+        let render = |construction_seed: u64| {
+            let mut lfo = SampleAndHoldLfo::new_with(NoteDivision::Sixteenth, construction_seed);
+            bank.reseed(Uid(5), &mut lfo);
+            lfo.play();
+            run(&mut lfo, 2)
+        };
+        let first = render(1);
+        assert_eq!(first, render(2));
+
+        let mut other = SampleAndHoldLfo::new_with(NoteDivision::Sixteenth, 1);
+        bank.reseed(Uid(6), &mut other);
+        other.play();
+        assert_ne!(first, run(&mut other, 2), "each entity gets its own seed");
+    }
+}
@@ -0,0 +1,190 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use anyhow::anyhow;
+use ensnare_core::prelude::*;
+use groove_utils::Paths;
+use std::{
+    io::{BufReader, Read},
+    path::Path,
+};
+
+/// Converts a buffer of samples from one sample rate to another using linear
+/// interpolation. The result has the same duration as the input.
+pub fn resample(samples: &[StereoSample], from: SampleRate, to: SampleRate) -> Vec<StereoSample> {
+    if from == to || samples.is_empty() || from.0 == 0 || to.0 == 0 {
+        return samples.to_vec();
+    }
+    let ratio = from.0 as f64 / to.0 as f64;
+    let output_len = ((samples.len() as f64) / ratio).round().max(1.0) as usize;
+    let last = samples.len() - 1;
+    (0..output_len)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = (position.floor() as usize).min(last);
+            let fraction = position - index as f64;
+            let a = samples[index];
+            let b = samples[(index + 1).min(last)];
+            StereoSample(
+                Sample(a.0 .0 + (b.0 .0 - a.0 .0) * fraction),
+                Sample(a.1 .0 + (b.1 .0 - a.1 .0) * fraction),
+            )
+        })
+        .collect()
+}
+
+/// Audio loaded from a file, kept at the engine's sample rate so that it
+/// plays at the right pitch no matter what rate the file was recorded at. The
+/// original is kept too, so that a change in the engine's rate resamples from
+/// the source rather than compounding conversions.
+#[derive(Clone, Debug, Default)]
+pub struct SampleData {
+    source: Vec<StereoSample>,
+    source_sample_rate: SampleRate,
+    samples: Vec<StereoSample>,
+    sample_rate: SampleRate,
+}
+impl SampleData {
+    /// Creates a [SampleData] from samples at `source_sample_rate`, converted
+    /// for an engine running at `engine_sample_rate`.
+    pub fn new_with(
+        source: Vec<StereoSample>,
+        source_sample_rate: SampleRate,
+        engine_sample_rate: SampleRate,
+    ) -> Self {
+        let mut r = Self {
+            source,
+            source_sample_rate,
+            samples: Default::default(),
+            sample_rate: Default::default(),
+        };
+        r.update_sample_rate(engine_sample_rate);
+        r
+    }
+
+    /// Reads a WAV file. Its sample rate comes from the file header. Mono
+    /// files are duplicated to stereo.
+    pub fn new_from_wav(path: &Path, engine_sample_rate: SampleRate) -> anyhow::Result<Self> {
+        let reader = hound::WavReader::open(path)
+            .map_err(|e| anyhow!("Couldn't open {}: {}", path.display(), e))?;
+        Self::new_from_wav_reader(reader, engine_sample_rate)
+    }
+
+    /// Finds the named WAV file in the usual places and reads it.
+    pub fn new_from_file(
+        paths: &Paths,
+        filename: &Path,
+        engine_sample_rate: SampleRate,
+    ) -> anyhow::Result<Self> {
+        let file = paths
+            .search_and_open(filename)
+            .map_err(|e| anyhow!("Couldn't open {}: {}", filename.display(), e))?;
+        let reader = hound::WavReader::new(BufReader::new(file))
+            .map_err(|e| anyhow!("Couldn't read {}: {}", filename.display(), e))?;
+        Self::new_from_wav_reader(reader, engine_sample_rate)
+    }
+
+    fn new_from_wav_reader<R: Read>(
+        mut reader: hound::WavReader<R>,
+        engine_sample_rate: SampleRate,
+    ) -> anyhow::Result<Self> {
+        let spec = reader.spec();
+        let values: Vec<f64> = match spec.sample_format {
+            hound::SampleFormat::Float => reader
+                .samples::<f32>()
+                .map(|s| s.map(|s| s as f64))
+                .collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = (1i64 << (spec.bits_per_sample - 1)) as f64;
+                reader
+                    .samples::<i32>()
+                    .map(|s| s.map(|s| s as f64 / scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        let channels = spec.channels.max(1) as usize;
+        let source = values
+            .chunks_exact(channels)
+            .map(|frame| {
+                let left = frame[0];
+                let right = if channels > 1 { frame[1] } else { left };
+                StereoSample(Sample(left), Sample(right))
+            })
+            .collect();
+        Ok(Self::new_with(
+            source,
+            SampleRate(spec.sample_rate as usize),
+            engine_sample_rate,
+        ))
+    }
+
+    /// Re-evaluates the samples for a new engine sample rate.
+    pub fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        if sample_rate != self.sample_rate || self.samples.is_empty() {
+            self.sample_rate = sample_rate;
+            self.samples = resample(&self.source, self.source_sample_rate, sample_rate);
+        }
+    }
+
+    /// The samples, at [SampleData::sample_rate()].
+    pub fn samples(&self) -> &[StereoSample] {
+        &self.samples
+    }
+
+    /// The engine sample rate that the samples have been converted to.
+    pub fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    /// The sample rate of the original audio.
+    pub fn source_sample_rate(&self) -> SampleRate {
+        self.source_sample_rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp(len: usize) -> Vec<StereoSample> {
+        (0..len)
+            .map(|i| {
+                let v = i as f64 / len as f64;
+                StereoSample(Sample(v), Sample(-v))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn duration_is_preserved() {
+        // One second at 48KHz should be one second at 44.1KHz.
+        let data = SampleData::new_with(ramp(48000), SampleRate(48000), SampleRate(44100));
+        assert_eq!(data.samples().len(), 44100);
+        assert_eq!(data.source_sample_rate(), SampleRate(48000));
+
+        // Interpolation should keep the shape of the ramp.
+        let midpoint = data.samples()[22050];
+        assert!((midpoint.0 .0 - 0.5).abs() < 0.001);
+        assert!((midpoint.1 .0 + 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn matching_rates_pass_through() {
+        let source = ramp(100);
+        let data = SampleData::new_with(source.clone(), SampleRate(44100), SampleRate(44100));
+        assert_eq!(data.samples(), source.as_slice());
+    }
+
+    #[test]
+    fn engine_rate_change_resamples_from_source() {
+        let mut data = SampleData::new_with(ramp(44100), SampleRate(44100), SampleRate(22050));
+        assert_eq!(data.samples().len(), 22050);
+        data.update_sample_rate(SampleRate(88200));
+        assert_eq!(data.samples().len(), 88200);
+        data.update_sample_rate(SampleRate(44100));
+        assert_eq!(
+            data.samples(),
+            ramp(44100).as_slice(),
+            "going back to the source rate should be lossless"
+        );
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::prelude::*;
+
+/// The sample rate that an entity has been configured with, which is unset
+/// until the first `update_sample_rate()`.
+///
+/// Entities are usually created before anyone knows what rate the audio device
+/// runs at. Anything they precompute from a guessed rate, like a delay buffer
+/// or filter coefficients, is wasted work at best and wrong at worst. An
+/// entity that keeps one of these instead of a bare [SampleRate] can skip
+/// rate-dependent setup until the real rate arrives. If it's asked to produce
+/// audio first, that's a bug in the caller, so debug builds assert; release
+/// builds fall back to [SampleRate::DEFAULT], which is what entities always
+/// used to assume.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConfiguredSampleRate(Option<SampleRate>);
+impl ConfiguredSampleRate {
+    #[allow(missing_docs)]
+    pub fn set(&mut self, sample_rate: SampleRate) {
+        self.0 = Some(sample_rate);
+    }
+
+    /// Whether update_sample_rate() has been called.
+    pub fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// The configured rate, or [SampleRate::DEFAULT] if there isn't one yet.
+    /// This is what `Configurable::sample_rate()` should report.
+    pub fn get(&self) -> SampleRate {
+        self.0.unwrap_or(SampleRate::DEFAULT)
+    }
+
+    /// Like [ConfiguredSampleRate::get()], for code that's about to generate
+    /// or process audio. In debug builds, it asserts that the rate was set.
+    pub fn expect_set(&self) -> SampleRate {
+        debug_assert!(
+            self.is_set(),
+            "an entity was ticked before update_sample_rate() was called"
+        );
+        self.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_unset_and_reports_the_default() {
+        let mut sample_rate = ConfiguredSampleRate::default();
+        assert!(!sample_rate.is_set());
+        assert_eq!(sample_rate.get(), SampleRate::DEFAULT);
+
+        sample_rate.set(SampleRate(96000));
+        assert!(sample_rate.is_set());
+        assert_eq!(sample_rate.expect_set(), SampleRate(96000));
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use super::tuning::Temperament;
+use anyhow::anyhow;
+use groove_utils::Paths;
+use std::{io::Read, path::Path};
+
+/// A scale read from a [Scala](https://www.huygens-fokker.org/scala/scl_format.html)
+/// `.scl` file, the standard interchange format for microtonal tunings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScalaScale {
+    description: String,
+
+    /// Each degree above the base note, as a frequency ratio. The last one is
+    /// the interval that the scale repeats at.
+    ratios: Vec<f64>,
+}
+impl ScalaScale {
+    /// Finds the named file in the usual places and parses it.
+    pub fn new_from_file(paths: &Paths, filename: &Path) -> anyhow::Result<Self> {
+        let mut file = paths
+            .search_and_open(filename)
+            .map_err(|e| anyhow!("Couldn't open {}: {}", filename.display(), e))?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)
+            .map_err(|e| anyhow!("Couldn't read {}: {}", filename.display(), e))?;
+        Self::new_from_str(&contents)
+            .map_err(|e| anyhow!("Couldn't load {}: {}", filename.display(), e))
+    }
+
+    /// Parses the contents of a `.scl` file. Lines starting with `!` are
+    /// comments. The first other line is a description, the next is the number
+    /// of notes, and then come the notes, one per line. A note with a period
+    /// is in cents, and one without is a ratio like `3/2` or `2`.
+    pub fn new_from_str(contents: &str) -> anyhow::Result<Self> {
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim_end_matches('\r')))
+            .filter(|(_, line)| !line.starts_with('!'));
+
+        let Some((_, description)) = lines.next() else {
+            return Err(anyhow!("the file is empty"));
+        };
+        let Some((line_number, count)) = lines.next() else {
+            return Err(anyhow!("the file has no note count"));
+        };
+        let count: usize = count
+            .split_whitespace()
+            .next()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| anyhow!("line {line_number}: expected a note count"))?;
+        if count == 0 {
+            return Err(anyhow!("line {line_number}: the scale has no notes"));
+        }
+
+        let mut ratios = Vec::with_capacity(count);
+        for (line_number, line) in lines.take(count) {
+            let token = line
+                .split_whitespace()
+                .next()
+                .ok_or_else(|| anyhow!("line {line_number}: expected a note"))?;
+            let ratio = Self::parse_pitch(token)
+                .ok_or_else(|| anyhow!("line {line_number}: '{token}' isn't a valid pitch"))?;
+            ratios.push(ratio);
+        }
+        if ratios.len() != count {
+            return Err(anyhow!(
+                "expected {count} notes, but found {}",
+                ratios.len()
+            ));
+        }
+        if ratios[count - 1] <= 1.0 {
+            return Err(anyhow!(
+                "the last note must be above 1/1, because the scale repeats there"
+            ));
+        }
+        Ok(Self {
+            description: description.trim().to_string(),
+            ratios,
+        })
+    }
+
+    #[allow(missing_docs)]
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    #[allow(missing_docs)]
+    pub fn ratios(&self) -> &[f64] {
+        &self.ratios
+    }
+
+    /// Returns a [Temperament] that maps `base_key` to the scale's 1/1 and
+    /// each key above or below it to the next degree.
+    pub fn to_temperament(&self, base_key: u8) -> Temperament {
+        Temperament::Scale {
+            ratios: self.ratios.clone(),
+            base_key: base_key.min(127),
+        }
+    }
+
+    fn parse_pitch(token: &str) -> Option<f64> {
+        let ratio = if token.contains('.') {
+            let cents: f64 = token.parse().ok()?;
+            2.0f64.powf(cents / 1200.0)
+        } else if let Some((numerator, denominator)) = token.split_once('/') {
+            let numerator: u64 = numerator.parse().ok()?;
+            let denominator: u64 = denominator.parse().ok()?;
+            if denominator == 0 {
+                return None;
+            }
+            numerator as f64 / denominator as f64
+        } else {
+            token.parse::<u64>().ok()? as f64
+        };
+        if ratio > 0.0 && ratio.is_finite() {
+            Some(ratio)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mini::Tuning;
+    use ensnare_core::prelude::*;
+
+    fn nineteen_edo() -> String {
+        let mut s = String::from("! 19edo.scl\n!\n19 equal divisions of the octave\n 19\n!\n");
+        for i in 1..19 {
+            s.push_str(&format!(" {:.5}\n", i as f64 * 1200.0 / 19.0));
+        }
+        s.push_str(" 2/1\n");
+        s
+    }
+
+    #[test]
+    fn plays_nineteen_edo() {
+        let scale = ScalaScale::new_from_str(&nineteen_edo()).unwrap();
+        assert_eq!(scale.description(), "19 equal divisions of the octave");
+        assert_eq!(scale.ratios().len(), 19);
+
+        let tuning = Tuning::new_with(FrequencyHz(440.0), scale.to_temperament(60));
+        let base = tuning.frequency(60).0;
+        assert!((base - Tuning::default().frequency(60).0).abs() < 1.0e-9);
+        for key in 40..100u8 {
+            let expected = base * 2.0f64.powf((key as f64 - 60.0) / 19.0);
+            let actual = tuning.frequency(key).0;
+            assert!(
+                (actual / expected - 1.0).abs() < 1.0e-6,
+                "key {key}: {actual} vs {expected}"
+            );
+        }
+        assert!((tuning.frequency(79).0 - base * 2.0).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn parses_ratios_and_cents() {
+        let scale =
+            ScalaScale::new_from_str("Just major triad\n3\n5/4\n701.955 fifth\n2\n").unwrap();
+        assert_eq!(scale.ratios()[0], 1.25);
+        assert!((scale.ratios()[1] - 1.5).abs() < 1.0e-6);
+        assert_eq!(scale.ratios()[2], 2.0);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        for (contents, expected) in [
+            ("", "empty"),
+            ("desc\n", "no note count"),
+            ("desc\nthree\n", "note count"),
+            ("desc\n3\n5/4\n2/1\n", "expected 3 notes"),
+            ("desc\n2\n5/0\n2/1\n", "'5/0' isn't a valid pitch"),
+            ("desc\n2\n-3/2\n2/1\n", "line 3"),
+            ("desc\n1\n1/2\n", "must be above 1/1"),
+        ] {
+            let err = ScalaScale::new_from_str(contents).unwrap_err().to_string();
+            assert!(err.contains(expected), "{contents:?}: got '{err}'");
+        }
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use serde::{Deserialize, Serialize};
+
+/// The interval patterns that a [Scale] can use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScaleMode {
+    #[default]
+    #[allow(missing_docs)]
+    Major,
+    #[allow(missing_docs)]
+    Minor,
+    #[allow(missing_docs)]
+    Dorian,
+    #[allow(missing_docs)]
+    Phrygian,
+    #[allow(missing_docs)]
+    Lydian,
+    #[allow(missing_docs)]
+    Mixolydian,
+    #[allow(missing_docs)]
+    Locrian,
+    #[allow(missing_docs)]
+    MajorPentatonic,
+    #[allow(missing_docs)]
+    MinorPentatonic,
+}
+impl ScaleMode {
+    /// Semitones above the root for each degree of the scale.
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            ScaleMode::Major => &[0, 2, 4, 5, 7, 9, 11],
+            ScaleMode::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            ScaleMode::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            ScaleMode::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            ScaleMode::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            ScaleMode::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            ScaleMode::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            ScaleMode::MajorPentatonic => &[0, 2, 4, 7, 9],
+            ScaleMode::MinorPentatonic => &[0, 3, 5, 7, 10],
+        }
+    }
+}
+
+/// A musical scale: a root pitch class (0 = C, 1 = C#, ... 11 = B) and a
+/// [ScaleMode].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scale {
+    root: u8,
+    mode: ScaleMode,
+}
+impl Scale {
+    #[allow(missing_docs)]
+    pub fn new_with(root: u8, mode: ScaleMode) -> Self {
+        Self {
+            root: root % 12,
+            mode,
+        }
+    }
+
+    /// Whether the MIDI key is one of this scale's notes, in any octave.
+    pub fn contains(&self, key: u8) -> bool {
+        let degree = ((key as u16 + 12 - self.root as u16) % 12) as u8;
+        self.mode.intervals().contains(&degree)
+    }
+
+    /// Returns the in-scale MIDI key nearest to `key`. Ties go to the lower
+    /// note. The result is always a valid MIDI key.
+    pub fn snap(&self, key: u8) -> u8 {
+        let key = key.min(127);
+        for distance in 0..12 {
+            if let Some(lower) = key.checked_sub(distance) {
+                if self.contains(lower) {
+                    return lower;
+                }
+            }
+            let higher = key + distance;
+            if higher <= 127 && self.contains(higher) {
+                return higher;
+            }
+        }
+        key
+    }
+
+    #[allow(missing_docs)]
+    pub fn root(&self) -> u8 {
+        self.root
+    }
+
+    #[allow(missing_docs)]
+    pub fn mode(&self) -> ScaleMode {
+        self.mode
+    }
+}
+
+/// Constrains note entry to a [Scale]. When disabled, keys pass through
+/// unchanged, so chromatic entry still works.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScaleSnap {
+    scale: Scale,
+    is_enabled: bool,
+}
+impl ScaleSnap {
+    #[allow(missing_docs)]
+    pub fn new_with(scale: Scale) -> Self {
+        Self {
+            scale,
+            is_enabled: true,
+        }
+    }
+
+    /// Returns the key that a note placed or dragged to `key` should end up
+    /// on.
+    pub fn apply(&self, key: u8) -> u8 {
+        if self.is_enabled {
+            self.scale.snap(key)
+        } else {
+            key
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn scale(&self) -> Scale {
+        self.scale
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_scale(&mut self, scale: Scale) {
+        self.scale = scale;
+    }
+
+    #[allow(missing_docs)]
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_enabled(&mut self, is_enabled: bool) {
+        self.is_enabled = is_enabled;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn d_dorian_snapping() {
+        // D dorian has the same notes as C major: D E F G A B C.
+        let scale = Scale::new_with(2, ScaleMode::Dorian);
+        for key in [60, 62, 64, 65, 67, 69, 71, 72] {
+            assert!(scale.contains(key), "{key} should be in D dorian");
+            assert_eq!(scale.snap(key), key);
+        }
+        assert!(!scale.contains(61));
+        assert_eq!(scale.snap(61), 60, "C# is equidistant; ties go down");
+        assert_eq!(scale.snap(66), 65, "F# snaps down to F");
+        assert_eq!(scale.snap(70), 69, "A# snaps down to A");
+    }
+
+    #[test]
+    fn snapping_stays_in_range() {
+        let scale = Scale::new_with(1, ScaleMode::MajorPentatonic);
+        for key in 0..=127 {
+            let snapped = scale.snap(key);
+            assert!(snapped <= 127);
+            assert!(scale.contains(snapped));
+        }
+        assert_eq!(scale.snap(255), scale.snap(127));
+    }
+
+    #[test]
+    fn snap_can_be_disabled() {
+        let mut snap = ScaleSnap::new_with(Scale::new_with(0, ScaleMode::Major));
+        assert_eq!(snap.apply(61), 60);
+        snap.set_enabled(false);
+        assert_eq!(snap.apply(61), 61);
+    }
+}
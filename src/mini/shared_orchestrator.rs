@@ -0,0 +1,295 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use crate::panels::NeedsAudioFn;
+use ensnare_core::core::AudioQueue;
+use ensnare_core::prelude::*;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, LockResult, Mutex, MutexGuard,
+};
+
+#[derive(Debug, Default)]
+struct SharedOrchestratorStats {
+    frames_rendered: AtomicUsize,
+    frames_played: AtomicUsize,
+    underruns: AtomicUsize,
+    poisoned_renders: AtomicUsize,
+}
+
+/// [SharedOrchestrator] lets the UI, the orchestrator's service thread, and the
+/// audio service share one orchestrator without the realtime audio callback
+/// ever waiting on a lock.
+///
+/// There are two sides. The producer side is the NeedsAudio handler of
+/// [AudioPanel](crate::panels::AudioPanel), an ordinary thread that locks the
+/// orchestrator, renders, and pushes the frames onto the lock-free
+/// [AudioQueue]. If the UI is holding the lock, the producer waits, but that's
+/// harmless as long as the queue has enough frames to cover the wait. The
+/// consumer side is the realtime callback, which only ever drains the queue
+/// through [SharedOrchestrator::drain()]. It never touches the [Mutex], so lock
+/// contention can delay rendering but can't stall playback. An empty queue
+/// plays silence and counts as an underrun.
+#[derive(Debug)]
+pub struct SharedOrchestrator<T> {
+    orchestrator: Arc<Mutex<T>>,
+    stats: Arc<SharedOrchestratorStats>,
+}
+impl<T> Clone for SharedOrchestrator<T> {
+    fn clone(&self) -> Self {
+        Self {
+            orchestrator: Arc::clone(&self.orchestrator),
+            stats: Arc::clone(&self.stats),
+        }
+    }
+}
+impl<T> SharedOrchestrator<T> {
+    /// Frames are rendered in chunks of this size, so each lock covers a
+    /// whole request but each render call stays small.
+    pub const CHUNK_SIZE: usize = 64;
+
+    /// Wraps an orchestrator that's already shared, like the one in
+    /// [OrchestratorPanel](crate::panels::OrchestratorPanel).
+    pub fn new_with(orchestrator: Arc<Mutex<T>>) -> Self {
+        Self {
+            orchestrator,
+            stats: Default::default(),
+        }
+    }
+
+    /// Locks the orchestrator for anything other than rendering. Never call
+    /// this from the realtime callback.
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        self.orchestrator.lock()
+    }
+
+    #[allow(missing_docs)]
+    pub fn orchestrator(&self) -> &Arc<Mutex<T>> {
+        &self.orchestrator
+    }
+
+    /// The producer side. Locks the orchestrator once, renders up to `count`
+    /// frames with `render`, and pushes them onto the queue. Stops early if
+    /// the queue fills up, and returns the number of frames pushed.
+    pub fn render_into<F>(&self, queue: &AudioQueue, count: usize, render: &mut F) -> usize
+    where
+        F: FnMut(&mut T, &mut [StereoSample]),
+    {
+        let room = queue.capacity() - queue.len();
+        let count = count.min(room);
+        if count == 0 {
+            return 0;
+        }
+        let Ok(mut orchestrator) = self.orchestrator.lock() else {
+            self.stats.poisoned_renders.fetch_add(1, Ordering::Relaxed);
+            return 0;
+        };
+        let mut buffer = [StereoSample::SILENCE; Self::CHUNK_SIZE];
+        let mut pushed = 0;
+        while pushed < count {
+            let len = (count - pushed).min(Self::CHUNK_SIZE);
+            render(&mut orchestrator, &mut buffer[..len]);
+            for sample in buffer[..len].iter() {
+                if queue.push(*sample).is_err() {
+                    self.stats
+                        .frames_rendered
+                        .fetch_add(pushed, Ordering::Relaxed);
+                    return pushed;
+                }
+                pushed += 1;
+            }
+        }
+        self.stats
+            .frames_rendered
+            .fetch_add(pushed, Ordering::Relaxed);
+        pushed
+    }
+
+    /// Builds a [NeedsAudioFn] for
+    /// [AudioPanel::new_with()](crate::panels::AudioPanel::new_with) that
+    /// renders with `render` whenever the audio service asks for frames.
+    pub fn needs_audio_fn<F>(&self, mut render: F) -> NeedsAudioFn
+    where
+        F: FnMut(&mut T, &mut [StereoSample]) + Send + Sync + 'static,
+        T: Send + 'static,
+    {
+        let shared = self.clone();
+        Box::new(move |queue, count| {
+            shared.render_into(queue, count, &mut render);
+        })
+    }
+
+    /// The consumer side, safe to call from the realtime callback. Fills
+    /// `frames` from the queue without locking anything, padding with silence
+    /// if the queue runs dry. Returns the number of frames that came from the
+    /// queue.
+    pub fn drain(&self, queue: &AudioQueue, frames: &mut [StereoSample]) -> usize {
+        let mut drained = 0;
+        for frame in frames.iter_mut() {
+            if let Some(sample) = queue.pop() {
+                *frame = sample;
+                drained += 1;
+            } else {
+                *frame = StereoSample::SILENCE;
+            }
+        }
+        self.stats
+            .frames_played
+            .fetch_add(frames.len(), Ordering::Relaxed);
+        if drained < frames.len() {
+            self.stats.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+        drained
+    }
+
+    /// How many times [SharedOrchestrator::drain()] found the queue short.
+    pub fn underrun_count(&self) -> usize {
+        self.stats.underruns.load(Ordering::Relaxed)
+    }
+
+    /// How many times [SharedOrchestrator::render_into()] rendered nothing
+    /// because a thread panicked while holding the lock. The UI checks this
+    /// rather than the render printing a warning, which could block the
+    /// thread that's keeping the queue full.
+    pub fn poisoned_render_count(&self) -> usize {
+        self.stats.poisoned_renders.load(Ordering::Relaxed)
+    }
+
+    /// The total frames pushed by [SharedOrchestrator::render_into()].
+    pub fn frames_rendered(&self) -> usize {
+        self.stats.frames_rendered.load(Ordering::Relaxed)
+    }
+
+    /// The total frames handed out by [SharedOrchestrator::drain()], including
+    /// silence.
+    pub fn frames_played(&self) -> usize {
+        self.stats.frames_played.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam::queue::ArrayQueue;
+    use std::{
+        sync::atomic::AtomicBool,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    // Stands in for an orchestrator: each frame is the next value of a
+    // counter, so the consumer can tell whether anything was lost.
+    #[derive(Debug, Default)]
+    struct Counter(usize);
+    fn render_counter(counter: &mut Counter, frames: &mut [StereoSample]) {
+        for frame in frames.iter_mut() {
+            counter.0 += 1;
+            *frame = StereoSample::from(counter.0 as f64);
+        }
+    }
+
+    #[test]
+    fn drain_never_waits_for_the_lock() {
+        let shared = SharedOrchestrator::new_with(Arc::new(Mutex::new(Counter::default())));
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(256));
+        assert_eq!(shared.render_into(&queue, 100, &mut render_counter), 100);
+
+        // Even while someone else holds the lock, draining works.
+        let _guard = shared.lock().unwrap();
+        let mut frames = [StereoSample::SILENCE; 64];
+        assert_eq!(shared.drain(&queue, &mut frames), 64);
+        assert_eq!(frames[63], StereoSample::from(64.0));
+        assert_eq!(shared.drain(&queue, &mut frames), 36);
+        assert_eq!(frames[36], StereoSample::SILENCE);
+        assert_eq!(shared.underrun_count(), 1);
+        assert_eq!(shared.frames_played(), 128);
+    }
+
+    #[test]
+    fn render_stops_when_queue_is_full() {
+        let shared = SharedOrchestrator::new_with(Arc::new(Mutex::new(Counter::default())));
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(100));
+        assert_eq!(shared.render_into(&queue, 1000, &mut render_counter), 100);
+        assert_eq!(shared.render_into(&queue, 1000, &mut render_counter), 0);
+        assert_eq!(
+            shared.lock().unwrap().0,
+            100,
+            "nothing should be rendered that can't be queued"
+        );
+    }
+
+    #[test]
+    fn poisoned_lock_is_counted_not_rendered() {
+        let shared = SharedOrchestrator::new_with(Arc::new(Mutex::new(Counter::default())));
+        let poisoner = shared.clone();
+        let _ = thread::spawn(move || {
+            let _guard = poisoner.lock();
+            panic!("poisoning the lock on purpose");
+        })
+        .join();
+
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(100));
+        assert_eq!(shared.render_into(&queue, 10, &mut render_counter), 0);
+        assert_eq!(shared.poisoned_render_count(), 1);
+        assert_eq!(shared.frames_rendered(), 0);
+    }
+
+    // A UI thread grabs the lock over and over, holding it for a while each
+    // time, as a heavy repaint would. The producer is stuck behind it, but the
+    // queue has enough headroom that the consumer never runs dry.
+    #[test]
+    fn lock_contention_does_not_cause_dropouts() {
+        const CALLBACK_FRAMES: usize = 64;
+        const CALLBACK_INTERVAL: Duration = Duration::from_millis(2);
+        const TARGET_FILL: usize = 2048;
+        const CALLBACKS: usize = 250;
+
+        let shared = SharedOrchestrator::new_with(Arc::new(Mutex::new(Counter::default())));
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(TARGET_FILL * 2));
+        shared.render_into(&queue, TARGET_FILL, &mut render_counter);
+        let is_done = Arc::new(AtomicBool::new(false));
+
+        let ui = {
+            let shared = shared.clone();
+            let is_done = Arc::clone(&is_done);
+            thread::spawn(move || {
+                while !is_done.load(Ordering::Relaxed) {
+                    if let Ok(_guard) = shared.lock() {
+                        thread::sleep(Duration::from_millis(5));
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+        let producer = {
+            let shared = shared.clone();
+            let queue = Arc::clone(&queue);
+            let is_done = Arc::clone(&is_done);
+            thread::spawn(move || {
+                while !is_done.load(Ordering::Relaxed) {
+                    let needed = TARGET_FILL.saturating_sub(queue.len());
+                    shared.render_into(&queue, needed, &mut render_counter);
+                    thread::sleep(Duration::from_micros(500));
+                }
+            })
+        };
+
+        let mut frames = [StereoSample::SILENCE; CALLBACK_FRAMES];
+        let mut expected = 1.0;
+        let start = Instant::now();
+        for i in 0..CALLBACKS {
+            shared.drain(&queue, &mut frames);
+            for frame in frames.iter() {
+                assert_eq!(*frame, StereoSample::from(expected), "callback {i}");
+                expected += 1.0;
+            }
+            let next = start + CALLBACK_INTERVAL * (i as u32 + 1);
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+        }
+        is_done.store(true, Ordering::Relaxed);
+        let _ = ui.join();
+        let _ = producer.join();
+
+        assert_eq!(shared.underrun_count(), 0);
+        assert_eq!(shared.frames_played(), CALLBACKS * CALLBACK_FRAMES);
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::prelude::*;
+
+/// Ramps a parameter toward its target over a fixed time instead of jumping,
+/// which is what causes zipper noise when automation or a MIDI CC moves a
+/// gain or cutoff in coarse steps. An entity keeps one per smoothed parameter,
+/// calls [SmoothedValue::set_target()] from the parameter's setter, and calls
+/// [SmoothedValue::next()] once per frame.
+///
+/// The ramp is linear and counted in frames, so it lands exactly on the
+/// target when it's done, rather than creeping toward it forever the way a
+/// one-pole smoother does.
+#[derive(Clone, Debug)]
+pub struct SmoothedValue {
+    current: ParameterType,
+    target: ParameterType,
+    /// How much the value changes each frame during a ramp.
+    step: ParameterType,
+    /// Frames left in the current ramp.
+    remaining: usize,
+    ramp_seconds: f64,
+    sample_rate: SampleRate,
+}
+impl Default for SmoothedValue {
+    fn default() -> Self {
+        Self::new_with(0.0, Self::DEFAULT_RAMP_SECONDS)
+    }
+}
+impl SmoothedValue {
+    /// 20 milliseconds is long enough to hide stepping and short enough that
+    /// the parameter still feels immediate.
+    pub const DEFAULT_RAMP_SECONDS: f64 = 0.02;
+
+    /// Starts settled at `value`.
+    pub fn new_with(value: ParameterType, ramp_seconds: f64) -> Self {
+        Self {
+            current: value,
+            target: value,
+            step: 0.0,
+            remaining: 0,
+            ramp_seconds: ramp_seconds.max(0.0),
+            sample_rate: SampleRate::DEFAULT,
+        }
+    }
+
+    /// Starts a ramp from the current value to `target`. A ramp time of zero
+    /// jumps straight there.
+    pub fn set_target(&mut self, target: ParameterType) {
+        self.target = target;
+        self.remaining = (self.ramp_seconds * self.sample_rate.0 as f64).round() as usize;
+        if self.remaining == 0 {
+            self.current = target;
+            self.step = 0.0;
+        } else {
+            self.step = (target - self.current) / self.remaining as f64;
+        }
+    }
+
+    /// Jumps to `value` without ramping, as when a project loads.
+    pub fn set_immediately(&mut self, value: ParameterType) {
+        self.current = value;
+        self.target = value;
+        self.step = 0.0;
+        self.remaining = 0;
+    }
+
+    /// Advances one frame and returns the value for it.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> ParameterType {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            if self.remaining == 0 {
+                self.current = self.target;
+            } else {
+                self.current += self.step;
+            }
+        }
+        self.current
+    }
+
+    /// Advances `frames` frames at once, for entities that skip work while
+    /// silent.
+    pub fn skip(&mut self, frames: usize) {
+        if frames >= self.remaining {
+            self.current = self.target;
+            self.remaining = 0;
+        } else {
+            self.remaining -= frames;
+            self.current += self.step * frames as f64;
+        }
+    }
+
+    /// The value for the most recent frame.
+    pub fn current(&self) -> ParameterType {
+        self.current
+    }
+
+    #[allow(missing_docs)]
+    pub fn target(&self) -> ParameterType {
+        self.target
+    }
+
+    /// Whether a ramp is in progress. Entities can use the unsmoothed target
+    /// while it's false.
+    pub fn is_smoothing(&self) -> bool {
+        self.remaining > 0
+    }
+
+    #[allow(missing_docs)]
+    pub fn ramp_seconds(&self) -> f64 {
+        self.ramp_seconds
+    }
+
+    /// Changes the ramp time. A ramp in progress keeps its old length.
+    pub fn set_ramp_seconds(&mut self, ramp_seconds: f64) {
+        self.ramp_seconds = ramp_seconds.max(0.0);
+    }
+
+    /// Ramps are counted in frames, so a new rate finishes any ramp in
+    /// progress.
+    pub fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        self.set_immediately(self.target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps_linearly_and_lands_exactly() {
+        let mut v = SmoothedValue::new_with(0.0, 0.001);
+        v.update_sample_rate(SampleRate(10000));
+        v.set_target(0.3);
+        assert!(v.is_smoothing());
+        let ramp: Vec<ParameterType> = (0..10).map(|_| v.next()).collect();
+        for pair in ramp.windows(2) {
+            assert!(pair[1] > pair[0], "the ramp should climb every frame");
+            assert!((pair[1] - pair[0] - 0.03).abs() < 1e-12);
+        }
+        assert_eq!(ramp[9], 0.3, "a held target should settle exactly");
+        assert!(!v.is_smoothing());
+        for _ in 0..1000 {
+            assert_eq!(v.next(), 0.3);
+        }
+
+        // Retargeting mid-ramp starts from wherever it got to.
+        v.set_target(1.0);
+        v.next();
+        v.set_target(0.0);
+        let from = v.current();
+        assert!((v.next() - from * 0.9).abs() < 1e-12);
+        v.skip(100);
+        assert_eq!(v.current(), 0.0);
+    }
+
+    #[test]
+    fn zero_ramp_jumps() {
+        let mut v = SmoothedValue::new_with(0.5, 0.0);
+        v.set_target(0.25);
+        assert!(!v.is_smoothing());
+        assert_eq!(v.next(), 0.25);
+
+        v.set_ramp_seconds(1.0);
+        v.set_target(1.0);
+        v.set_immediately(0.75);
+        assert_eq!(v.next(), 0.75);
+        assert_eq!(v.target(), 0.75);
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use anyhow::anyhow;
+use ensnare_core::prelude::*;
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::f64::consts::TAU;
+
+/// Measures the magnitude spectrum of a rendered buffer, for display or for
+/// checking a render. Returns one (frequency in Hz, magnitude) pair per FFT
+/// bin, from DC up to Nyquist, so there are `fft_size / 2 + 1` of them.
+///
+/// The two channels are averaged to mono. Each block of `fft_size` frames gets
+/// a Hann window, and blocks overlap by half. The magnitudes of all the blocks
+/// are averaged, which smooths out the noise in a long buffer. A buffer
+/// shorter than `fft_size` is zero-padded.
+///
+/// Magnitudes are scaled so that a full-scale sine centered on a bin reads
+/// 1.0, whatever `fft_size` is. Convert to dB with `20.0 * m.log10()`.
+pub fn analyze_spectrum(
+    samples: &[StereoSample],
+    sample_rate: SampleRate,
+    fft_size: usize,
+) -> anyhow::Result<Vec<(f64, f64)>> {
+    if fft_size < 2 {
+        return Err(anyhow!("FFT size must be at least 2, not {fft_size}"));
+    }
+    let mono: Vec<f64> = samples.iter().map(|s| (s.0 .0 + s.1 .0) / 2.0).collect();
+    let window: Vec<f64> = (0..fft_size)
+        .map(|i| 0.5 - 0.5 * (TAU * i as f64 / fft_size as f64).cos())
+        .collect();
+
+    // A one-sided spectrum folds the negative frequencies into the positive
+    // ones, hence the 2.0. Dividing by the window's sum undoes its gain.
+    let scale = 2.0 / window.iter().sum::<f64>();
+
+    let fft = FftPlanner::new().plan_fft_forward(fft_size);
+    let hop = fft_size / 2;
+    let bin_count = fft_size / 2 + 1;
+    let mut magnitudes = vec![0.0; bin_count];
+    let mut block_count = 0;
+    let mut buffer = vec![Complex::default(); fft_size];
+    let mut start = 0;
+    loop {
+        for (i, c) in buffer.iter_mut().enumerate() {
+            let value = mono.get(start + i).copied().unwrap_or_default();
+            *c = Complex::new(value * window[i], 0.0);
+        }
+        fft.process(&mut buffer);
+        for (magnitude, c) in magnitudes.iter_mut().zip(buffer.iter()) {
+            *magnitude += c.norm() * scale;
+        }
+        block_count += 1;
+        start += hop;
+        if start + fft_size > mono.len() {
+            break;
+        }
+    }
+
+    let bin_width = sample_rate.0 as f64 / fft_size as f64;
+    Ok(magnitudes
+        .into_iter()
+        .enumerate()
+        .map(|(bin, magnitude)| {
+            // DC and Nyquist have no negative twin to fold in.
+            let magnitude = if bin == 0 || (bin == fft_size / 2 && fft_size % 2 == 0) {
+                magnitude / 2.0
+            } else {
+                magnitude
+            };
+            (bin as f64 * bin_width, magnitude / block_count as f64)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_a_sine() {
+        let sample_rate = SampleRate(48000);
+        let fft_size = 4096;
+
+        // Exactly on bin 85, so there's no scalloping loss.
+        let frequency = 85.0 * 48000.0 / fft_size as f64;
+        let samples: Vec<StereoSample> = (0..fft_size * 4)
+            .map(|i| StereoSample::from(0.5 * (TAU * frequency * i as f64 / 48000.0).sin()))
+            .collect();
+        let spectrum = analyze_spectrum(&samples, sample_rate, fft_size).unwrap();
+        assert_eq!(spectrum.len(), fft_size / 2 + 1);
+
+        let (peak_frequency, peak_magnitude) = spectrum
+            .iter()
+            .copied()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        assert_eq!(peak_frequency, frequency);
+        assert!(
+            (peak_magnitude - 0.5).abs() < 0.001,
+            "magnitude should match the sine's amplitude, not {peak_magnitude}"
+        );
+        assert!(spectrum[200].1 < 0.0001, "far bins should be quiet");
+    }
+
+    #[test]
+    fn handles_short_and_silent_buffers() {
+        let sample_rate = SampleRate(44100);
+        let spectrum = analyze_spectrum(&[StereoSample::SILENCE; 100], sample_rate, 256).unwrap();
+        assert_eq!(spectrum.len(), 129);
+        assert!(spectrum.iter().all(|(_, m)| *m == 0.0));
+        assert_eq!(spectrum[128].0, 22050.0);
+
+        assert!(analyze_spectrum(&[], sample_rate, 1).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Reduces the bit depth of a stereo signal, the way a bitcrusher does.
+///
+/// By default, each channel is quantized independently, so the artifacts in
+/// the left and right channels are uncorrelated and smear the stereo image.
+/// With `link_channels`, the quantization decision is made once, from the
+/// mono sum, and the same correction is applied to both channels. The
+/// artifacts are then identical in both channels and stay in the center.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StereoQuantizer {
+    bits: u8,
+    link_channels: bool,
+}
+impl Default for StereoQuantizer {
+    fn default() -> Self {
+        Self::new_with(8, false)
+    }
+}
+impl StereoQuantizer {
+    #[allow(missing_docs)]
+    pub fn new_with(bits: u8, link_channels: bool) -> Self {
+        Self {
+            bits: bits.clamp(1, 24),
+            link_channels,
+        }
+    }
+
+    /// Rounds a -1.0..=1.0 value to the nearest of 2^bits levels.
+    pub fn quantize(&self, value: f64) -> f64 {
+        let scale = (1u32 << (self.bits - 1)) as f64;
+        (value * scale).round() / scale
+    }
+
+    /// Quantizes both channels, linked or not.
+    pub fn transform_audio(&self, input: StereoSample) -> StereoSample {
+        let (left, right) = (input.0 .0, input.1 .0);
+        if self.link_channels {
+            let mid = (left + right) / 2.0;
+            let error = self.quantize(mid) - mid;
+            StereoSample(Sample(left + error), Sample(right + error))
+        } else {
+            StereoSample(Sample(self.quantize(left)), Sample(self.quantize(right)))
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_bits(&mut self, bits: u8) {
+        self.bits = bits.clamp(1, 24);
+    }
+
+    #[allow(missing_docs)]
+    pub fn link_channels(&self) -> bool {
+        self.link_channels
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_link_channels(&mut self, link_channels: bool) {
+        self.link_channels = link_channels;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlinked_quantizes_channels_independently() {
+        let q = StereoQuantizer::new_with(3, false);
+        let output = q.transform_audio(StereoSample(Sample(0.3), Sample(-0.6)));
+        assert_eq!(output, StereoSample(Sample(0.25), Sample(-0.5)));
+    }
+
+    #[test]
+    fn linked_applies_identical_error_to_both_channels() {
+        let q = StereoQuantizer::new_with(3, true);
+        let input = StereoSample(Sample(0.3), Sample(-0.1));
+        let output = q.transform_audio(input);
+        let left_error = output.0 .0 - input.0 .0;
+        let right_error = output.1 .0 - input.1 .0;
+        assert!((left_error - right_error).abs() < 1.0e-12);
+
+        // The mono sum lands exactly on a quantization level.
+        let mid = (output.0 .0 + output.1 .0) / 2.0;
+        assert_eq!(mid, q.quantize(mid));
+    }
+
+    #[test]
+    fn linked_and_unlinked_agree_on_mono_input() {
+        let linked = StereoQuantizer::new_with(4, true);
+        let unlinked = StereoQuantizer::new_with(4, false);
+        for i in 0..50 {
+            let x = (i as f64 * 0.13).sin();
+            let input = StereoSample(Sample(x), Sample(x));
+            let a = linked.transform_audio(input);
+            let b = unlinked.transform_audio(input);
+            assert!((a.0 .0 - b.0 .0).abs() < 1.0e-12);
+            assert!((a.1 .0 - b.1 .0).abs() < 1.0e-12);
+        }
+    }
+}
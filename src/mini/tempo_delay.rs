@@ -0,0 +1,303 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use super::ConfiguredSampleRate;
+use ensnare_core::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A note length that a tempo-synced effect can lock to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoteDivision {
+    #[allow(missing_docs)]
+    Whole,
+    #[allow(missing_docs)]
+    Half,
+    #[default]
+    #[allow(missing_docs)]
+    Quarter,
+    #[allow(missing_docs)]
+    DottedEighth,
+    #[allow(missing_docs)]
+    Eighth,
+    #[allow(missing_docs)]
+    EighthTriplet,
+    #[allow(missing_docs)]
+    Sixteenth,
+    #[allow(missing_docs)]
+    ThirtySecond,
+}
+impl NoteDivision {
+    /// The length of the division in beats, where a beat is a quarter note.
+    pub fn beats(&self) -> f64 {
+        match self {
+            NoteDivision::Whole => 4.0,
+            NoteDivision::Half => 2.0,
+            NoteDivision::Quarter => 1.0,
+            NoteDivision::DottedEighth => 0.75,
+            NoteDivision::Eighth => 0.5,
+            NoteDivision::EighthTriplet => 1.0 / 3.0,
+            NoteDivision::Sixteenth => 0.25,
+            NoteDivision::ThirtySecond => 0.125,
+        }
+    }
+
+    /// The length of the division as [MusicalTime], rounded to the nearest
+    /// unit, and never zero.
+    pub fn musical_time(&self) -> MusicalTime {
+        let units = (self.beats() * MusicalTime::UNITS_IN_BEAT as f64).round() as usize;
+        MusicalTime::new_with_units(units.max(1))
+    }
+
+    /// The length of the division in seconds at the given tempo.
+    pub fn seconds(&self, tempo: Tempo) -> f64 {
+        self.beats() * 60.0 / tempo.0
+    }
+}
+
+/// A delay line whose time is either a free value in seconds or locked to
+/// the song tempo.
+///
+/// The buffer is allocated for [TempoSyncedDelay::MAX_SECONDS] when the
+/// sample rate or tempo is set, never during process(), and changing the delay
+/// time never resizes it. Instead, the read position
+/// glides toward the new delay time, a fraction of a frame per frame, which
+/// bends the pitch of the echoes briefly rather than producing a click.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TempoSyncedDelay {
+    /// The delay time when sync is off.
+    seconds: f64,
+
+    /// Whether the delay time comes from the tempo and division.
+    sync: bool,
+
+    /// The note length to use when sync is on.
+    division: NoteDivision,
+
+    #[serde(skip)]
+    tempo: Tempo,
+    #[serde(skip)]
+    sample_rate: ConfiguredSampleRate,
+    /// Empty until the sample rate or tempo is set.
+    #[serde(skip)]
+    buffer: Vec<f64>,
+    #[serde(skip)]
+    write_index: usize,
+    /// The delay currently being read, in frames. Moves toward the target.
+    #[serde(skip)]
+    current_delay_frames: f64,
+}
+impl Default for TempoSyncedDelay {
+    fn default() -> Self {
+        Self::new_with(0.5)
+    }
+}
+impl TempoSyncedDelay {
+    /// The longest delay time supported.
+    pub const MAX_SECONDS: f64 = 4.0;
+
+    /// How far the read position may move per frame while gliding to a new
+    /// delay time.
+    const GLIDE_FRAMES_PER_FRAME: f64 = 0.5;
+
+    #[allow(missing_docs)]
+    pub fn new_with(seconds: f64) -> Self {
+        Self {
+            seconds: seconds.clamp(0.0, Self::MAX_SECONDS),
+            sync: false,
+            division: Default::default(),
+            tempo: Tempo::default(),
+            sample_rate: Default::default(),
+            buffer: Default::default(),
+            write_index: 0,
+            current_delay_frames: 0.0,
+        }
+    }
+
+    /// The delay time in effect, in seconds.
+    pub fn effective_seconds(&self) -> f64 {
+        let seconds = if self.sync {
+            self.division.seconds(self.tempo)
+        } else {
+            self.seconds
+        };
+        seconds.clamp(0.0, Self::MAX_SECONDS)
+    }
+
+    fn target_delay_frames(&self) -> f64 {
+        self.effective_seconds() * self.sample_rate.get().0 as f64
+    }
+
+    /// Reallocates the buffer for the new rate, which clears it.
+    pub fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate.set(sample_rate);
+        self.allocate();
+    }
+
+    /// Follows a tempo change. When synced, the delay time glides to match.
+    /// If there's no buffer yet, this allocates one for the configured rate,
+    /// or the default rate until one is configured.
+    pub fn update_tempo(&mut self, tempo: Tempo) {
+        self.tempo = tempo;
+        if self.buffer.is_empty() {
+            self.allocate();
+        }
+    }
+
+    fn allocate(&mut self) {
+        let len = (Self::MAX_SECONDS * self.sample_rate.get().0 as f64).ceil() as usize + 2;
+        self.buffer = vec![0.0; len];
+        self.write_index = 0;
+        self.current_delay_frames = self.target_delay_frames();
+    }
+
+    /// Writes one input sample and returns the delayed output. It never
+    /// allocates. Before the sample rate or tempo is set, there's nothing to
+    /// delay into, so the output is silent.
+    pub fn process(&mut self, input: f64) -> f64 {
+        if self.buffer.is_empty() {
+            let _ = self.sample_rate.expect_set();
+            return 0.0;
+        }
+        let target = self.target_delay_frames();
+        let difference = target - self.current_delay_frames;
+        self.current_delay_frames +=
+            difference.clamp(-Self::GLIDE_FRAMES_PER_FRAME, Self::GLIDE_FRAMES_PER_FRAME);
+
+        let len = self.buffer.len();
+        self.buffer[self.write_index] = input;
+
+        // Linear interpolation between the two frames around the read
+        // position, so that gliding is smooth.
+        let read_position = self.write_index as f64 - self.current_delay_frames;
+        let read_position = read_position.rem_euclid(len as f64);
+        let index = read_position.floor() as usize % len;
+        let fraction = read_position.fract();
+        let a = self.buffer[index];
+        let b = self.buffer[(index + 1) % len];
+        let output = a + (b - a) * fraction;
+
+        self.write_index = (self.write_index + 1) % len;
+        output
+    }
+
+    #[allow(missing_docs)]
+    pub fn seconds(&self) -> f64 {
+        self.seconds
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_seconds(&mut self, seconds: f64) {
+        self.seconds = seconds.clamp(0.0, Self::MAX_SECONDS);
+    }
+
+    #[allow(missing_docs)]
+    pub fn sync(&self) -> bool {
+        self.sync
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_sync(&mut self, sync: bool) {
+        self.sync = sync;
+    }
+
+    #[allow(missing_docs)]
+    pub fn division(&self) -> NoteDivision {
+        self.division
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_division(&mut self, division: NoteDivision) {
+        self.division = division;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synced_time_follows_tempo() {
+        let mut delay = TempoSyncedDelay::new_with(0.1);
+        delay.update_tempo(Tempo(120.0));
+        assert_eq!(delay.effective_seconds(), 0.1, "sync off uses free time");
+
+        delay.set_sync(true);
+        delay.set_division(NoteDivision::DottedEighth);
+        assert_eq!(delay.effective_seconds(), 0.375);
+
+        delay.update_tempo(Tempo(60.0));
+        assert_eq!(delay.effective_seconds(), 0.75);
+    }
+
+    #[test]
+    fn impulse_comes_back_after_delay_time() {
+        let mut delay = TempoSyncedDelay::new_with(0.0);
+        delay.update_sample_rate(SampleRate(100));
+        delay.set_seconds(0.1);
+        delay.update_sample_rate(SampleRate(100));
+
+        let outputs: Vec<f64> = (0..20)
+            .map(|i| delay.process(if i == 0 { 1.0 } else { 0.0 }))
+            .collect();
+        assert_eq!(outputs[10], 1.0);
+        assert_eq!(outputs.iter().filter(|v| **v != 0.0).count(), 1);
+    }
+
+    #[test]
+    fn changing_time_glides_without_jumps() {
+        let mut delay = TempoSyncedDelay::new_with(0.5);
+        delay.update_sample_rate(SampleRate(1000));
+
+        // Fill the buffer with a slow ramp, then halve the delay time. Reading
+        // a ramp, a jump in the read position would show up as a jump in the
+        // output.
+        let mut previous = 0.0;
+        for i in 0..2000 {
+            if i == 1000 {
+                delay.set_seconds(0.25);
+            }
+            let output = delay.process(i as f64 / 1000.0);
+            if i > 600 {
+                assert!(
+                    (output - previous).abs() < 0.002,
+                    "frame {i}: output jumped from {previous} to {output}"
+                );
+            }
+            previous = output;
+        }
+    }
+
+    #[test]
+    fn buffer_waits_for_the_sample_rate() {
+        let mut early = TempoSyncedDelay::new_with(0.1);
+        assert!(early.buffer.is_empty(), "nothing should be allocated yet");
+        early.update_sample_rate(SampleRate(1000));
+        assert_eq!(early.buffer.len(), 4002);
+
+        // Whether the rate arrives before or after other setup makes no
+        // difference.
+        let mut late = TempoSyncedDelay::new_with(0.5);
+        late.set_seconds(0.1);
+        late.update_sample_rate(SampleRate(1000));
+        for i in 0..300 {
+            let input = if i % 50 == 0 { 1.0 } else { 0.0 };
+            assert_eq!(early.process(input), late.process(input));
+        }
+    }
+
+    #[test]
+    fn tempo_allocates_the_buffer_too() {
+        let mut delay = TempoSyncedDelay::new_with(0.1);
+        delay.update_tempo(Tempo(120.0));
+        assert_eq!(
+            delay.buffer.len(),
+            (TempoSyncedDelay::MAX_SECONDS * SampleRate::DEFAULT.0 as f64).ceil() as usize + 2
+        );
+
+        // Another tempo leaves the buffer alone, and the real rate replaces it.
+        let capacity = delay.buffer.capacity();
+        delay.update_tempo(Tempo(90.0));
+        assert_eq!(delay.buffer.capacity(), capacity);
+        delay.update_sample_rate(SampleRate(1000));
+        assert_eq!(delay.buffer.len(), 4002);
+    }
+}
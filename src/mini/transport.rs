@@ -8,18 +8,32 @@ use ensnare_core::traits::{
 };
 use ensnare_proc_macros::{Control, IsController, Uid};
 use serde::{Deserialize, Serialize};
-use std::ops::Range;
+use std::{collections::VecDeque, ops::Range, time::Instant};
 
 #[derive(Debug, Clone, Default)]
 pub struct TransportEphemerals {
     /// The global time pointer within the song.
     current_time: MusicalTime,
 
-    current_frame: usize,
+    /// Frames elapsed since the start of the song. This is a u64 rather than
+    /// a usize so that a 32-bit build doesn't roll over after about 27 hours
+    /// at 44.1KHz.
+    current_frame: u64,
+
+    /// Frames elapsed since the transport was created, whether or not it was
+    /// performing. It shares an origin with current_frame but never stops or
+    /// rewinds.
+    free_running_frame: u64,
 
     sample_rate: SampleRate,
 
     is_performing: bool,
+
+    /// When tap() was last called.
+    last_tap: Option<Instant>,
+
+    /// The most recent intervals between taps, in seconds, oldest first.
+    tap_intervals: VecDeque<f64>,
 }
 
 /// [Transport] is the global clock. It keeps track of the current position in
@@ -43,6 +57,25 @@ pub struct Transport {
 }
 impl HandlesMidi for Transport {}
 impl Transport {
+    /// How many of the most recent tap intervals tap() averages.
+    pub const TAP_WINDOW: usize = 4;
+
+    /// The longest that the first two taps can be apart, in seconds. That's
+    /// 30 BPM.
+    pub const MAX_TAP_INTERVAL: f64 = 2.0;
+
+    /// A stopped transport at the start of the song, with the given tempo and
+    /// sample rate. Code that keeps its own clock can use one to get the same
+    /// time conversions as everything else.
+    pub fn new_with(tempo: Tempo, sample_rate: SampleRate) -> Self {
+        let mut r = Self {
+            tempo,
+            ..Default::default()
+        };
+        r.e.sample_rate = sample_rate;
+        r
+    }
+
     /// Returns the current [Tempo].
     pub fn tempo(&self) -> Tempo {
         self.tempo
@@ -53,42 +86,184 @@ impl Transport {
         self.tempo = tempo;
     }
 
+    /// Sets the tempo from the time between calls, as when the user taps a
+    /// button along with the music. The tempo is the average of the last
+    /// [Transport::TAP_WINDOW] intervals, so it settles within a few taps.
+    /// The first tap only starts the count. A tap more than twice as late as
+    /// the average starts a new count, so that tapping again after a pause
+    /// doesn't drag the tempo toward zero.
+    ///
+    /// Returns the new tempo, or None if there weren't enough taps yet.
+    pub fn tap(&mut self) -> Option<Tempo> {
+        self.tap_at(Instant::now())
+    }
+
+    fn tap_at(&mut self, now: Instant) -> Option<Tempo> {
+        let last_tap = self.e.last_tap.replace(now)?;
+        let interval = now.duration_since(last_tap).as_secs_f64();
+        let average = self.average_tap_interval();
+        let is_outlier = match average {
+            Some(average) => interval > average * 2.0,
+            None => interval > Self::MAX_TAP_INTERVAL,
+        };
+        if is_outlier || interval <= 0.0 {
+            self.e.tap_intervals.clear();
+            return None;
+        }
+        self.e.tap_intervals.push_back(interval);
+        if self.e.tap_intervals.len() > Self::TAP_WINDOW {
+            self.e.tap_intervals.pop_front();
+        }
+        let tempo = Tempo(60.0 / self.average_tap_interval()?);
+        self.set_tempo(tempo);
+        Some(tempo)
+    }
+
+    fn average_tap_interval(&self) -> Option<f64> {
+        if self.e.tap_intervals.is_empty() {
+            None
+        } else {
+            Some(self.e.tap_intervals.iter().sum::<f64>() / self.e.tap_intervals.len() as f64)
+        }
+    }
+
     /// Advances the clock by the given number of frames. Returns the time range
     /// from the prior time to now.
+    ///
+    /// While performing, the range comes from the song position. While
+    /// stopped, the song position stays put, and the range instead comes from
+    /// a free-running clock that always moves forward. That lets devices like
+    /// an arpeggiator respond to a MIDI keyboard with the transport stopped,
+    /// while sequencers, which look at their own performance state, stay idle
+    /// until play().
     pub fn advance(&mut self, frames: usize) -> Range<MusicalTime> {
+        let new_free_running_frame = self.e.free_running_frame + frames as u64;
+        let free_running_range = self.frames_to_musical_time(self.e.free_running_frame)
+            ..self.frames_to_musical_time(new_free_running_frame);
+        self.e.free_running_frame = new_free_running_frame;
+
+        if !self.is_performing() {
+            return free_running_range;
+        }
+
         // Calculate the work time range. Note that the range can be zero, which
         // will happen if frames advance faster than MusicalTime units.
-        let new_frames = self.e.current_frame + frames;
-        let new_time = MusicalTime::new_with_frames(self.tempo, self.e.sample_rate, new_frames);
-        let length = new_time - self.e.current_time;
-        let range = self.e.current_time..self.e.current_time + length;
-
-        // If we aren't performing, then we don't advance the clock, but we do
-        // give devices the appearance of time moving forward by providing them
-        // a (usually) nonzero time range.
-        //
-        // This is another reason why devices will sometimes get the same time
-        // range twice. It's also why very high sample rates will make
-        // MusicalTime inaccurate for devices like an arpeggiator that depend on
-        // this time source *and* are supposed to operate interactively while
-        // not performing (performance is stopped, but a MIDI track is selected,
-        // and the user expects to hear the arp respond normally to MIDI
-        // keyboard events). TODO: define a better way for these kinds of
-        // devices; maybe they need a different clock that genuinely moves
-        // forward (except when the performance starts). It should share the
-        // same origin as the real clock, but increases regardless of
-        // performance status.
-        if self.is_performing() {
-            self.e.current_frame = new_frames;
-            self.e.current_time = new_time;
-        }
+        let new_frames = self.e.current_frame + frames as u64;
+        let new_time = self.frames_to_musical_time(new_frames);
+        let range = self.e.current_time..new_time;
+        self.e.current_frame = new_frames;
+        self.e.current_time = new_time;
         range
     }
 
+    /// Returns the time on the free-running clock, which advances even while
+    /// the transport is stopped.
+    pub fn free_running_time(&self) -> MusicalTime {
+        self.frames_to_musical_time(self.e.free_running_frame)
+    }
+
     #[allow(missing_docs)]
     pub fn current_time(&self) -> MusicalTime {
         self.e.current_time
     }
+
+    /// Returns the number of frames elapsed since the start of the song.
+    pub fn current_frame(&self) -> u64 {
+        self.e.current_frame
+    }
+
+    /// Converts a frame count to [MusicalTime] at the current tempo and sample
+    /// rate, rounding down to the last whole unit. This is the conversion that
+    /// [Transport::advance()] uses, so a time computed here is exactly the
+    /// time that the clock will report at that frame.
+    ///
+    /// The math doesn't go through usize, so that the result stays exact on
+    /// 32-bit builds after the frame count passes u32::MAX. Integral tempos
+    /// (the usual case) use exact integer math; anything else falls back to
+    /// f64, which is still exact to the frame for centuries of audio. A time
+    /// past what [MusicalTime] can hold saturates at its largest value rather
+    /// than wrapping back toward zero.
+    pub fn frames_to_musical_time(&self, frames: u64) -> MusicalTime {
+        let sample_rate = self.e.sample_rate.0 as u128;
+        if sample_rate == 0 {
+            return MusicalTime::default();
+        }
+        let bpm = self.tempo.0;
+        let units = if Self::is_integral(bpm) {
+            frames as u128 * bpm as u128 * MusicalTime::UNITS_IN_BEAT as u128 / (60 * sample_rate)
+        } else {
+            (frames as f64 * bpm / 60.0 / sample_rate as f64 * MusicalTime::UNITS_IN_BEAT as f64)
+                .floor() as u128
+        };
+        MusicalTime::new_with_units(usize::try_from(units).unwrap_or(usize::MAX))
+    }
+
+    /// Converts a [MusicalTime] to the first frame at which the clock reaches
+    /// it. That's the frame in which anything scheduled for that time should
+    /// happen, so `frames_to_musical_time(musical_time_to_frames(t)) >= t`,
+    /// and the frame before is always earlier than `t`.
+    pub fn musical_time_to_frames(&self, time: MusicalTime) -> u64 {
+        let sample_rate = self.e.sample_rate.0 as u128;
+        let bpm = self.tempo.0;
+        if sample_rate == 0 || bpm <= 0.0 {
+            return 0;
+        }
+        let units = time.total_units() as u128;
+        if Self::is_integral(bpm) {
+            let numerator = units * 60 * sample_rate;
+            let denominator = bpm as u128 * MusicalTime::UNITS_IN_BEAT as u128;
+            ((numerator + denominator - 1) / denominator) as u64
+        } else {
+            // Estimate, then correct for f64 rounding so that the answer is
+            // consistent with frames_to_musical_time().
+            let mut frames = (units as f64 * 60.0 * sample_rate as f64
+                / (bpm * MusicalTime::UNITS_IN_BEAT as f64))
+                .ceil() as u64;
+            while frames > 0 && self.frames_to_musical_time(frames - 1) >= time {
+                frames -= 1;
+            }
+            while self.frames_to_musical_time(frames) < time {
+                frames += 1;
+            }
+            frames
+        }
+    }
+
+    /// Converts a duration in seconds to frames at the current sample rate,
+    /// rounding to the nearest frame. Negative durations are zero frames.
+    pub fn seconds_to_frames(&self, seconds: f64) -> u64 {
+        (seconds.max(0.0) * self.e.sample_rate.0 as f64).round() as u64
+    }
+
+    /// Converts a frame count to seconds at the current sample rate.
+    pub fn frames_to_seconds(&self, frames: u64) -> f64 {
+        if self.e.sample_rate.0 == 0 {
+            return 0.0;
+        }
+        frames as f64 / self.e.sample_rate.0 as f64
+    }
+
+    /// Converts a duration in seconds to [MusicalTime] at the current tempo,
+    /// by way of frames, so that it agrees with what the clock will report.
+    pub fn seconds_to_musical_time(&self, seconds: f64) -> MusicalTime {
+        self.frames_to_musical_time(self.seconds_to_frames(seconds))
+    }
+
+    /// Converts a [MusicalTime] to seconds at the current tempo, by way of
+    /// frames.
+    pub fn musical_time_to_seconds(&self, time: MusicalTime) -> f64 {
+        self.frames_to_seconds(self.musical_time_to_frames(time))
+    }
+
+    fn is_integral(bpm: f64) -> bool {
+        bpm.fract() == 0.0 && bpm >= 0.0
+    }
+
+    #[cfg(test)]
+    fn set_current_frame(&mut self, frame: u64) {
+        self.e.current_frame = frame;
+        self.e.current_time = self.frames_to_musical_time(frame);
+    }
 }
 impl Displays for Transport {
     fn ui(&mut self, _ui: &mut Ui) -> eframe::egui::Response {
@@ -117,7 +292,7 @@ impl Controls for Transport {
     fn update_time(&mut self, range: &Range<MusicalTime>) {
         // Nothing - we calculated the range, so we don't need to do anything with it.
         debug_assert!(
-            self.e.current_time == range.end,
+            !self.is_performing() || self.e.current_time == range.end,
             "Transport::update_time() was called with the range ..{} but current_time is {}",
             range.end,
             self.e.current_time
@@ -186,4 +361,180 @@ mod tests {
             transport.skip_to_start();
         }
     }
+
+    #[test]
+    fn time_stays_continuous_past_32_bit_frame_counts() {
+        let mut transport = Transport::default();
+        transport.update_tempo(Tempo(120.0));
+        transport.update_sample_rate(SampleRate(44100));
+        transport.play();
+
+        // Start just shy of the point where a 32-bit usize would roll over.
+        let start_frame = u32::MAX as u64 - 1000;
+        transport.set_current_frame(start_frame);
+        let mut previous_end = transport.current_time();
+        for _ in 0..2000 {
+            let range = transport.advance(1);
+            assert_eq!(
+                range.start, previous_end,
+                "time ranges should be contiguous"
+            );
+            assert!(range.end >= range.start, "time shouldn't go backward");
+            previous_end = range.end;
+        }
+        assert_eq!(transport.current_frame(), start_frame + 2000);
+
+        // 44,100 frames per second at 120 BPM is two beats per second.
+        let expected_units =
+            (transport.current_frame() as u128 * 2 * MusicalTime::UNITS_IN_BEAT as u128 / 44100)
+                as usize;
+        assert_eq!(transport.current_time().total_units(), expected_units);
+    }
+
+    #[test]
+    fn musical_time_saturates_instead_of_wrapping() {
+        let mut transport = Transport::default();
+        // One frame per second at 60 BPM is exactly one beat per frame.
+        transport.update_tempo(Tempo(60.0));
+        transport.update_sample_rate(SampleRate(1));
+        let units_per_frame = MusicalTime::UNITS_IN_BEAT as u64;
+
+        // The last frame whose time fits in a usize converts exactly...
+        let last_frame = usize::MAX as u64 / units_per_frame;
+        assert_eq!(
+            transport.frames_to_musical_time(last_frame).total_units() as u64,
+            last_frame * units_per_frame
+        );
+
+        // ...and the ones after it pin to the largest time instead of
+        // wrapping.
+        for frames in [last_frame + 1, last_frame * 2, u64::MAX] {
+            assert_eq!(
+                transport.frames_to_musical_time(frames).total_units(),
+                usize::MAX,
+                "frames {frames}"
+            );
+        }
+    }
+
+    #[test]
+    fn free_running_clock_advances_while_stopped() {
+        let mut transport = Transport::default();
+        transport.update_tempo(Tempo(60.0));
+        transport.update_sample_rate(SampleRate(100));
+
+        // Stopped: the song position holds, but each range moves forward.
+        let first = transport.advance(50);
+        let second = transport.advance(50);
+        assert_eq!(transport.current_time(), MusicalTime::default());
+        assert_eq!(transport.current_frame(), 0);
+        assert_eq!(first.end, second.start);
+        assert!(second.end > second.start);
+        assert_eq!(
+            transport.free_running_time(),
+            MusicalTime::new_with_beats(1)
+        );
+
+        // Playing: the song position starts from where it was, not from the
+        // free-running clock.
+        transport.play();
+        let range = transport.advance(100);
+        assert_eq!(range.start, MusicalTime::default());
+        assert_eq!(transport.current_time(), MusicalTime::new_with_beats(1));
+        assert_eq!(
+            transport.free_running_time(),
+            MusicalTime::new_with_beats(2)
+        );
+
+        // Stopping and rewinding doesn't rewind the free-running clock.
+        transport.stop();
+        transport.skip_to_start();
+        assert_eq!(transport.current_time(), MusicalTime::default());
+        assert_eq!(
+            transport.free_running_time(),
+            MusicalTime::new_with_beats(2)
+        );
+    }
+
+    #[test]
+    fn conversions_agree_with_the_clock() {
+        for (bpm, sample_rate) in [(120.0, 44100), (128.0, 48000), (97.3, 44100), (60.0, 997)] {
+            let mut transport = Transport::default();
+            transport.update_tempo(Tempo(bpm));
+            transport.update_sample_rate(SampleRate(sample_rate));
+            transport.play();
+
+            for _ in 0..sample_rate * 2 {
+                transport.advance(1);
+                let frame = transport.current_frame();
+                assert_eq!(
+                    transport.frames_to_musical_time(frame),
+                    transport.current_time(),
+                    "{bpm} BPM at {sample_rate} Hz, frame {frame}"
+                );
+            }
+
+            for units in (0..MusicalTime::UNITS_IN_BEAT * 4).step_by(37) {
+                let time = MusicalTime::new_with_units(units);
+                let frame = transport.musical_time_to_frames(time);
+                assert!(transport.frames_to_musical_time(frame) >= time);
+                if frame > 0 {
+                    assert!(
+                        transport.frames_to_musical_time(frame - 1) < time,
+                        "{bpm} BPM at {sample_rate} Hz: frame {frame} isn't the first to reach {time}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tap_tempo_follows_the_taps() {
+        let mut transport = Transport::default();
+        transport.set_tempo(Tempo(128.0));
+        let start = Instant::now();
+        let at = |seconds: f64| start + std::time::Duration::from_secs_f64(seconds);
+
+        // One tap isn't enough.
+        assert!(transport.tap_at(at(0.0)).is_none());
+        assert_eq!(transport.tempo().0, 128.0);
+
+        // Quarter notes at 100 BPM, a little unsteady.
+        let mut tempo = None;
+        for (i, jitter) in [0.01, -0.01, 0.005, -0.005].iter().enumerate() {
+            tempo = transport.tap_at(at((i + 1) as f64 * 0.6 + jitter));
+        }
+        let tempo = tempo.unwrap();
+        assert!((tempo.0 - 100.0).abs() < 1.0, "tempo {}", tempo.0);
+        assert_eq!(transport.tempo().0, tempo.0);
+
+        // After a pause, the count starts over rather than slowing down.
+        assert!(transport.tap_at(at(10.0)).is_none());
+        assert_eq!(transport.tempo().0, tempo.0);
+        let tempo = transport.tap_at(at(10.5)).unwrap();
+        assert!((tempo.0 - 120.0).abs() < 1e-6, "tempo {}", tempo.0);
+    }
+
+    #[test]
+    fn converts_seconds() {
+        let mut transport = Transport::default();
+        transport.update_tempo(Tempo(120.0));
+        transport.update_sample_rate(SampleRate(44100));
+
+        assert_eq!(transport.seconds_to_frames(1.5), 66150);
+        assert_eq!(transport.frames_to_seconds(66150), 1.5);
+        assert_eq!(transport.seconds_to_frames(-1.0), 0);
+        assert_eq!(
+            transport.seconds_to_musical_time(1.0),
+            MusicalTime::new_with_beats(2)
+        );
+        assert_eq!(
+            transport.musical_time_to_seconds(MusicalTime::new_with_beats(3)),
+            1.5
+        );
+        assert_eq!(
+            transport.musical_time_to_frames(MusicalTime::new_with_beats(1)),
+            22050
+        );
+    }
 }
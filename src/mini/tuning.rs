@@ -0,0 +1,176 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How the twelve notes of each octave are spaced.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Temperament {
+    /// Twelve-tone equal temperament. Every semitone is the same size.
+    #[default]
+    Equal,
+
+    /// Each pitch class, starting with C, is offset from equal temperament by
+    /// the given number of cents. This is how historical temperaments are
+    /// usually published.
+    CentsOffsets([f64; 12]),
+
+    /// An arbitrary scale, such as one loaded from a Scala file. `ratios` are
+    /// the scale's degrees above the base note, ending with the ratio that
+    /// the scale repeats at (usually 2.0, the octave). `base_key` plays at its
+    /// standard pitch, and each key above or below it moves one degree.
+    Scale {
+        #[allow(missing_docs)]
+        ratios: Vec<f64>,
+        #[allow(missing_docs)]
+        base_key: u8,
+    },
+}
+
+/// [Tuning] turns MIDI note numbers into frequencies. The default is standard
+/// tuning: twelve-tone equal temperament with A4 at 440Hz.
+///
+/// A temperament's offsets are applied relative to A, so A4 is always exactly
+/// the reference frequency, and an orchestra tuned to 442Hz gets A4 at 442Hz
+/// whatever the temperament.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tuning {
+    reference_frequency: FrequencyHz,
+    temperament: Temperament,
+}
+impl Default for Tuning {
+    fn default() -> Self {
+        Self {
+            reference_frequency: FrequencyHz(Self::STANDARD_A4),
+            temperament: Temperament::Equal,
+        }
+    }
+}
+impl Tuning {
+    /// The frequency of A4 in standard tuning, in Hz.
+    pub const STANDARD_A4: f64 = 440.0;
+
+    /// The MIDI note number of A4.
+    pub const A4_KEY: u8 = 69;
+
+    /// Werckmeister III ("correct temperament No. 1"), as cents from equal
+    /// temperament for C through B.
+    pub const WERCKMEISTER_III: [f64; 12] = [
+        0.0, -9.8, -7.8, -5.9, -9.8, -2.0, -11.7, -3.9, -7.8, -11.7, -3.9, -7.8,
+    ];
+
+    #[allow(missing_docs)]
+    pub fn new_with(reference_frequency: FrequencyHz, temperament: Temperament) -> Self {
+        let mut r = Self {
+            temperament,
+            ..Default::default()
+        };
+        r.set_reference_frequency(reference_frequency);
+        r
+    }
+
+    /// The frequency of A4.
+    pub fn reference_frequency(&self) -> FrequencyHz {
+        self.reference_frequency
+    }
+
+    /// Sets the frequency of A4. Values that aren't positive and finite are
+    /// ignored.
+    pub fn set_reference_frequency(&mut self, frequency: FrequencyHz) {
+        if frequency.0 <= 0.0 || !frequency.0.is_finite() {
+            eprintln!("Warning: ignoring invalid tuning reference {}", frequency.0);
+            return;
+        }
+        self.reference_frequency = frequency;
+    }
+
+    #[allow(missing_docs)]
+    pub fn temperament(&self) -> &Temperament {
+        &self.temperament
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_temperament(&mut self, temperament: Temperament) {
+        self.temperament = temperament;
+    }
+
+    /// The frequency of the given MIDI note.
+    pub fn frequency(&self, key: u8) -> FrequencyHz {
+        if let Temperament::Scale { ratios, base_key } = &self.temperament {
+            return self.scale_frequency(ratios, *base_key, key);
+        }
+        let semitones_from_a4 = key as f64 - Self::A4_KEY as f64 + self.offset_cents(key) / 100.0;
+        FrequencyHz(self.reference_frequency.0 * 2.0f64.powf(semitones_from_a4 / 12.0))
+    }
+
+    // How far the key is from its equal-tempered pitch, relative to A.
+    fn offset_cents(&self, key: u8) -> f64 {
+        match &self.temperament {
+            Temperament::CentsOffsets(offsets) => {
+                offsets[key as usize % 12] - offsets[Self::A4_KEY as usize % 12]
+            }
+            Temperament::Equal | Temperament::Scale { .. } => 0.0,
+        }
+    }
+
+    fn scale_frequency(&self, ratios: &[f64], base_key: u8, key: u8) -> FrequencyHz {
+        let base_frequency = self.reference_frequency.0
+            * 2.0f64.powf((base_key as f64 - Self::A4_KEY as f64) / 12.0);
+        let Some(period) = ratios.last() else {
+            return FrequencyHz(base_frequency);
+        };
+        let steps = key as i32 - base_key as i32;
+        let degree_count = ratios.len() as i32;
+        let periods = steps.div_euclid(degree_count);
+        let degree = steps.rem_euclid(degree_count) as usize;
+        let degree_ratio = if degree == 0 { 1.0 } else { ratios[degree - 1] };
+        FrequencyHz(base_frequency * period.powi(periods) * degree_ratio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_standard_tuning() {
+        let tuning = Tuning::default();
+        assert_eq!(tuning.frequency(69), FrequencyHz(440.0));
+        assert_eq!(tuning.frequency(81), FrequencyHz(880.0));
+        assert_eq!(tuning.frequency(57), FrequencyHz(220.0));
+        let middle_c = tuning.frequency(60).0;
+        assert!((middle_c - 261.6256).abs() < 0.0001, "got {middle_c}");
+    }
+
+    #[test]
+    fn reference_shifts_everything() {
+        let standard = Tuning::default();
+        let mut tuning = Tuning::default();
+        tuning.set_reference_frequency(FrequencyHz(432.0));
+        assert_eq!(tuning.frequency(69), FrequencyHz(432.0));
+        for key in 0..=127 {
+            let ratio = tuning.frequency(key).0 / standard.frequency(key).0;
+            assert!((ratio - 432.0 / 440.0).abs() < 1.0e-12);
+        }
+
+        tuning.set_reference_frequency(FrequencyHz(0.0));
+        assert_eq!(tuning.reference_frequency(), FrequencyHz(432.0));
+    }
+
+    #[test]
+    fn temperament_keeps_a_in_tune() {
+        let tuning = Tuning::new_with(
+            FrequencyHz(440.0),
+            Temperament::CentsOffsets(Tuning::WERCKMEISTER_III),
+        );
+        assert_eq!(tuning.frequency(69), FrequencyHz(440.0));
+
+        // C is 11.7 cents sharp of equal temperament relative to A.
+        let equal_c = Tuning::default().frequency(60).0;
+        let cents = 1200.0 * (tuning.frequency(60).0 / equal_c).log2();
+        assert!((cents - 11.7).abs() < 1.0e-9, "got {cents}");
+
+        // Octaves stay pure.
+        assert!((tuning.frequency(72).0 / tuning.frequency(60).0 - 2.0).abs() < 1.0e-12);
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use eframe::egui::Ui;
+use ensnare_core::prelude::*;
+use ensnare_core::traits::{Configurable, Displays, Serializable, TransformsAudio};
+use ensnare_proc_macros::{Control, IsEffect, Uid};
+use serde::{Deserialize, Serialize};
+
+/// The transfer function that a [Waveshaper] applies to each sample.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WaveshaperCurve {
+    /// Smooth saturation that approaches but never reaches full scale.
+    #[default]
+    Tanh,
+    /// Flat-topped clipping at full scale. The harshest curve.
+    HardClip,
+    /// A cubic that bends gently into full scale, then stays there.
+    SoftClip,
+    /// Reflects anything past full scale back toward zero, so more drive
+    /// adds more folds rather than more clipping.
+    Foldback,
+}
+impl WaveshaperCurve {
+    const ALL: [WaveshaperCurve; 4] = [
+        WaveshaperCurve::Tanh,
+        WaveshaperCurve::HardClip,
+        WaveshaperCurve::SoftClip,
+        WaveshaperCurve::Foldback,
+    ];
+
+    /// Applies the curve to an already-driven value. The result is always in
+    /// -1.0..=1.0.
+    pub fn shape(&self, x: f64) -> f64 {
+        match self {
+            WaveshaperCurve::Tanh => x.tanh(),
+            WaveshaperCurve::HardClip => x.clamp(-1.0, 1.0),
+            WaveshaperCurve::SoftClip => {
+                let x = x.clamp(-1.0, 1.0);
+                1.5 * (x - x * x * x / 3.0)
+            }
+            WaveshaperCurve::Foldback => {
+                // A triangle wave of period 4 that passes through the origin
+                // with slope 1. Computing it with rem_euclid rather than by
+                // repeatedly reflecting keeps it exact and cheap no matter how
+                // many times a heavily driven sample wraps.
+                let t = (x + 1.0).rem_euclid(4.0);
+                if t < 2.0 {
+                    t - 1.0
+                } else {
+                    3.0 - t
+                }
+            }
+        }
+    }
+}
+impl From<ControlValue> for WaveshaperCurve {
+    fn from(value: ControlValue) -> Self {
+        let index = (value.0.clamp(0.0, 1.0) * Self::ALL.len() as f64) as usize;
+        Self::ALL[index.min(Self::ALL.len() - 1)]
+    }
+}
+impl From<WaveshaperCurve> for ControlValue {
+    fn from(value: WaveshaperCurve) -> Self {
+        let index = WaveshaperCurve::ALL
+            .iter()
+            .position(|c| *c == value)
+            .unwrap_or_default();
+        ControlValue((index as f64 + 0.5) / WaveshaperCurve::ALL.len() as f64)
+    }
+}
+
+/// [Waveshaper] adds harmonics by pushing the input through a nonlinear
+/// transfer curve. The output is compensated so that a full-scale input stays
+/// at full scale regardless of drive.
+#[derive(Serialize, Deserialize, Clone, Control, IsEffect, Debug, Uid)]
+pub struct Waveshaper {
+    uid: Uid,
+
+    /// How hard the input is pushed into the curve. 1.0 is unity.
+    #[control]
+    drive: f64,
+
+    #[control]
+    curve: WaveshaperCurve,
+
+    /// 0.0 is all dry, 1.0 is all shaped.
+    #[control]
+    mix: Normal,
+
+    #[serde(skip)]
+    e: WaveshaperEphemerals,
+}
+#[derive(Debug, Default, Clone)]
+pub struct WaveshaperEphemerals {
+    sample_rate: SampleRate,
+    compensation: f64,
+}
+impl Default for Waveshaper {
+    fn default() -> Self {
+        Self::new_with(1.0, WaveshaperCurve::default(), Normal::from(1.0))
+    }
+}
+impl Waveshaper {
+    /// The lowest allowed drive.
+    pub const MIN_DRIVE: f64 = 1.0;
+    /// The highest allowed drive.
+    pub const MAX_DRIVE: f64 = 100.0;
+
+    #[allow(missing_docs)]
+    pub fn new_with(drive: f64, curve: WaveshaperCurve, mix: Normal) -> Self {
+        let mut r = Self {
+            uid: Default::default(),
+            drive: Self::MIN_DRIVE,
+            curve,
+            mix,
+            e: Default::default(),
+        };
+        r.set_drive(drive);
+        r
+    }
+
+    #[allow(missing_docs)]
+    pub fn drive(&self) -> f64 {
+        self.drive
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_drive(&mut self, drive: f64) {
+        if drive.is_finite() {
+            self.drive = drive.clamp(Self::MIN_DRIVE, Self::MAX_DRIVE);
+        } else {
+            eprintln!("Warning: ignoring invalid waveshaper drive {drive}");
+        }
+        self.update_compensation();
+    }
+
+    #[allow(missing_docs)]
+    pub fn curve(&self) -> WaveshaperCurve {
+        self.curve
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_curve(&mut self, curve: WaveshaperCurve) {
+        self.curve = curve;
+        self.update_compensation();
+    }
+
+    #[allow(missing_docs)]
+    pub fn mix(&self) -> Normal {
+        self.mix
+    }
+
+    #[allow(missing_docs)]
+    pub fn set_mix(&mut self, mix: Normal) {
+        self.mix = mix;
+    }
+
+    // Scales the output so that a full-scale input comes out at full scale.
+    // Foldback is skipped because its output at full-scale input can be
+    // anywhere, including zero; it's already bounded to full scale anyway.
+    fn update_compensation(&mut self) {
+        let peak = self.curve.shape(self.drive).abs();
+        self.e.compensation = match self.curve {
+            WaveshaperCurve::Foldback => 1.0,
+            _ if peak > f64::EPSILON => 1.0 / peak,
+            _ => 1.0,
+        };
+    }
+}
+impl TransformsAudio for Waveshaper {
+    fn transform_channel(&mut self, _channel: usize, input_sample: Sample) -> Sample {
+        let dry = input_sample.0;
+        let wet = self.curve.shape(dry * self.drive) * self.e.compensation;
+        let mix = self.mix.0;
+        Sample(dry * (1.0 - mix) + wet * mix)
+    }
+}
+impl Configurable for Waveshaper {
+    fn sample_rate(&self) -> SampleRate {
+        self.e.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.e.sample_rate = sample_rate;
+    }
+}
+impl Serializable for Waveshaper {
+    fn after_deser(&mut self) {
+        self.update_compensation();
+    }
+}
+impl Displays for Waveshaper {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        ui.label(format!("Waveshaper: {:?} x{:.1}", self.curve, self.drive))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mini::{assert_audible_and_bounded, render_entity_for, EntityUnderTest};
+
+    #[test]
+    fn curves_stay_in_range() {
+        for curve in WaveshaperCurve::ALL {
+            for i in -1000..=1000 {
+                let y = curve.shape(i as f64 / 10.0);
+                assert!((-1.0..=1.0).contains(&y), "{curve:?} produced {y}");
+            }
+            assert_eq!(curve.shape(0.0), 0.0, "{curve:?} should pass silence");
+        }
+    }
+
+    #[test]
+    fn foldback_wraps_at_extreme_drive() {
+        let curve = WaveshaperCurve::Foldback;
+        assert_eq!(curve.shape(0.5), 0.5);
+        assert_eq!(curve.shape(1.5), 0.5);
+        assert_eq!(curve.shape(2.5), -0.5);
+        assert_eq!(curve.shape(-1.5), -0.5);
+        assert_eq!(curve.shape(4.5), 0.5, "a full period later");
+        assert!((curve.shape(1000.25) - 0.25).abs() < 1.0e-9);
+        assert!((curve.shape(-1000.25) + 0.25).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn compensation_holds_level_and_mix_blends() {
+        let mut ws = Waveshaper::new_with(10.0, WaveshaperCurve::Tanh, Normal::from(1.0));
+        let full = ws.transform_channel(0, Sample(1.0)).0;
+        assert!((full - 1.0).abs() < 1.0e-9, "full scale stays full scale");
+        let quiet = ws.transform_channel(0, Sample(0.1)).0;
+        assert!(quiet > 0.1 && quiet < 1.0, "quiet input is thickened");
+
+        ws.set_mix(Normal::from(0.0));
+        assert_eq!(ws.transform_channel(0, Sample(0.1)).0, 0.1);
+
+        ws.set_drive(f64::NAN);
+        assert_eq!(ws.drive(), 10.0);
+        ws.set_drive(0.0);
+        assert_eq!(ws.drive(), Waveshaper::MIN_DRIVE);
+    }
+
+    #[test]
+    fn curve_converts_to_and_from_control_values() {
+        for curve in WaveshaperCurve::ALL {
+            let value: ControlValue = curve.into();
+            assert_eq!(WaveshaperCurve::from(value), curve);
+        }
+    }
+
+    #[test]
+    fn renders_sanely() {
+        for curve in WaveshaperCurve::ALL {
+            let mut ws = Waveshaper::new_with(Waveshaper::MAX_DRIVE, curve, Normal::from(1.0));
+            let samples =
+                render_entity_for(EntityUnderTest::Effect(&mut ws), 0.1, SampleRate::DEFAULT);
+            assert_audible_and_bounded(&samples);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use anyhow::anyhow;
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SizedSample, Stream, StreamConfig, SupportedStreamConfig,
+};
+use crossbeam::queue::ArrayQueue;
+use ensnare_core::{core::AudioQueue, prelude::*};
+use std::{fmt::Debug, sync::Arc};
+
+/// Converts interleaved input frames to stereo. Mono input is duplicated to
+/// both sides, and channels beyond the first two are ignored.
+pub fn interleaved_to_stereo(samples: &[f32], channel_count: usize) -> Vec<StereoSample> {
+    let channel_count = channel_count.max(1);
+    samples
+        .chunks_exact(channel_count)
+        .map(|frame| {
+            let left = frame[0] as f64;
+            let right = if channel_count > 1 {
+                frame[1] as f64
+            } else {
+                left
+            };
+            StereoSample(Sample(left), Sample(right))
+        })
+        .collect()
+}
+
+/// A linear-interpolating sample-rate converter for input that arrives at a
+/// different rate than the output runs. It's not audiophile quality, but it
+/// keeps pitch and timing right for monitoring and recording.
+#[derive(Debug)]
+pub struct LinearResampler {
+    ratio: f64,
+    position: f64,
+    previous: StereoSample,
+}
+impl LinearResampler {
+    #[allow(missing_docs)]
+    pub fn new_with(input_rate: SampleRate, output_rate: SampleRate) -> Self {
+        Self {
+            ratio: input_rate.0 as f64 / output_rate.0.max(1) as f64,
+            position: 0.0,
+            previous: StereoSample::SILENCE,
+        }
+    }
+
+    /// Whether input and output rates are the same, so that no conversion is
+    /// needed.
+    pub fn is_passthrough(&self) -> bool {
+        self.ratio == 1.0
+    }
+
+    /// Converts a block of input samples, calling `output_fn` with each output
+    /// sample. State carries over between blocks.
+    pub fn process(&mut self, input: &[StereoSample], output_fn: &mut dyn FnMut(StereoSample)) {
+        if self.is_passthrough() {
+            input.iter().for_each(|s| output_fn(*s));
+            return;
+        }
+        // position is measured in input samples, where -1.0 is self.previous
+        // and 0.0 is input[0].
+        while self.position < input.len() as f64 - 1.0 {
+            let index = self.position.floor();
+            let fraction = self.position - index;
+            let a = if index < 0.0 {
+                self.previous
+            } else {
+                input[index as usize]
+            };
+            let b = input[(index + 1.0) as usize];
+            output_fn(StereoSample(
+                Sample(a.0 .0 + (b.0 .0 - a.0 .0) * fraction),
+                Sample(a.1 .0 + (b.1 .0 - a.1 .0) * fraction),
+            ));
+            self.position += self.ratio;
+        }
+        if let Some(last) = input.last() {
+            self.previous = *last;
+            self.position -= input.len() as f64;
+        }
+    }
+}
+
+/// [AudioInputStream] captures audio from the default input device and pushes
+/// it, as stereo at the output sample rate, onto an [AudioQueue] that a live
+/// source entity can read.
+pub struct AudioInputStream {
+    // Dropping the stream stops capture, so we hold onto it.
+    #[allow(dead_code)]
+    stream: Stream,
+    queue: AudioQueue,
+    sample_rate: SampleRate,
+    channel_count: u16,
+}
+impl Debug for AudioInputStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AudioInputStream")
+            .field("sample_rate", &self.sample_rate)
+            .field("channel_count", &self.channel_count)
+            .finish()
+    }
+}
+impl AudioInputStream {
+    /// How many stereo samples the queue holds before new ones are dropped.
+    /// This is about a quarter second at 44.1KHz.
+    const QUEUE_CAPACITY: usize = 1024 * 12;
+
+    /// Opens the default input device, preferring a configuration at
+    /// `output_sample_rate` so that no conversion is needed. If the device
+    /// can't do that rate, its input is resampled.
+    pub fn create_input_stream(output_sample_rate: SampleRate) -> anyhow::Result<Self> {
+        let host = cpal::default_host();
+        let device = host
+            .default_input_device()
+            .ok_or_else(|| anyhow!("no default audio input device"))?;
+        let supported = Self::choose_config(&device, output_sample_rate)?;
+        let input_sample_rate = SampleRate(supported.sample_rate().0 as usize);
+        let channel_count = supported.channels();
+        let config: StreamConfig = supported.clone().into();
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(Self::QUEUE_CAPACITY));
+
+        let stream = match supported.sample_format() {
+            cpal::SampleFormat::I16 => Self::build_stream::<i16>(
+                &device,
+                &config,
+                &queue,
+                input_sample_rate,
+                output_sample_rate,
+            ),
+            cpal::SampleFormat::U16 => Self::build_stream::<u16>(
+                &device,
+                &config,
+                &queue,
+                input_sample_rate,
+                output_sample_rate,
+            ),
+            cpal::SampleFormat::F32 => Self::build_stream::<f32>(
+                &device,
+                &config,
+                &queue,
+                input_sample_rate,
+                output_sample_rate,
+            ),
+            format => Err(anyhow!("unsupported input sample format {format}")),
+        }?;
+        stream.play()?;
+        Ok(Self {
+            stream,
+            queue,
+            sample_rate: output_sample_rate,
+            channel_count,
+        })
+    }
+
+    fn choose_config(
+        device: &cpal::Device,
+        sample_rate: SampleRate,
+    ) -> anyhow::Result<SupportedStreamConfig> {
+        let wanted = cpal::SampleRate(sample_rate.0 as u32);
+        if let Ok(configs) = device.supported_input_configs() {
+            // Prefer stereo, but take mono at the right rate over stereo at
+            // the wrong one.
+            let mut matching: Vec<_> = configs
+                .filter(|c| c.min_sample_rate() <= wanted && c.max_sample_rate() >= wanted)
+                .collect();
+            matching.sort_by_key(|c| if c.channels() == 2 { 0 } else { 1 });
+            if let Some(config) = matching.into_iter().next() {
+                return Ok(config.with_sample_rate(wanted));
+            }
+        }
+        Ok(device.default_input_config()?)
+    }
+
+    fn build_stream<T>(
+        device: &cpal::Device,
+        config: &StreamConfig,
+        queue: &AudioQueue,
+        input_sample_rate: SampleRate,
+        output_sample_rate: SampleRate,
+    ) -> anyhow::Result<Stream>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let queue = Arc::clone(queue);
+        let channel_count = config.channels as usize;
+        let mut resampler = LinearResampler::new_with(input_sample_rate, output_sample_rate);
+        let mut buffer = Vec::default();
+        let stream = device.build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                buffer.clear();
+                buffer.extend(data.iter().map(|s| f32::from_sample(*s)));
+                let frames = interleaved_to_stereo(&buffer, channel_count);
+                resampler.process(&frames, &mut |sample| {
+                    // If nobody's reading fast enough, drop the newest
+                    // samples rather than block the audio thread.
+                    let _ = queue.push(sample);
+                });
+            },
+            |err| eprintln!("Warning: audio input stream error: {err}"),
+            None,
+        )?;
+        Ok(stream)
+    }
+
+    /// The queue that captured samples arrive on.
+    pub fn queue(&self) -> &AudioQueue {
+        &self.queue
+    }
+
+    /// The sample rate of the samples on the queue, which is the output rate.
+    pub fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    /// How many channels the device is capturing. The queue is always
+    /// stereo.
+    pub fn channel_count(&self) -> u16 {
+        self.channel_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mono_input_is_duplicated() {
+        let stereo = interleaved_to_stereo(&[0.25, -0.5], 1);
+        assert_eq!(
+            stereo,
+            vec![
+                StereoSample(Sample(0.25), Sample(0.25)),
+                StereoSample(Sample(-0.5), Sample(-0.5))
+            ]
+        );
+        let stereo = interleaved_to_stereo(&[0.25, -0.5, 0.1, 0.9, 0.8, 0.7], 3);
+        assert_eq!(
+            stereo,
+            vec![
+                StereoSample(Sample(0.25), Sample(-0.5)),
+                StereoSample(Sample(0.9), Sample(0.8))
+            ]
+        );
+    }
+
+    #[test]
+    fn resampler_produces_expected_count() {
+        let input = vec![StereoSample::SILENCE; 480];
+        let mut r = LinearResampler::new_with(SampleRate(48000), SampleRate(44100));
+        let mut count = 0;
+        for _ in 0..100 {
+            r.process(&input, &mut |_| count += 1);
+        }
+        // 100 blocks of 10ms at 48KHz is one second, which is 44100 samples at
+        // the output rate, give or take one for the block boundary.
+        assert!((44099..=44101).contains(&count), "got {count}");
+
+        let mut r = LinearResampler::new_with(SampleRate(44100), SampleRate(44100));
+        assert!(r.is_passthrough());
+        let mut count = 0;
+        r.process(&input, &mut |_| count += 1);
+        assert_eq!(count, 480);
+    }
+}
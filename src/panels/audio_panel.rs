@@ -1,8 +1,13 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
+#[cfg(feature = "jack")]
+use super::jack_output::JackOutputStream;
+use super::{audio_input::AudioInputStream, cpal_output::CpalOutputStream};
+use anyhow::anyhow;
+use cpal::traits::{DeviceTrait, HostTrait};
 use crossbeam_channel::{Receiver, Sender};
-use eframe::egui::{CollapsingHeader, Ui};
-use ensnare_core::audio::{AudioInterfaceEvent, AudioInterfaceInput, AudioStreamService};
+use eframe::egui::{CollapsingHeader, Color32, ComboBox, Ui};
+use ensnare_core::audio::AudioInterfaceEvent;
 use ensnare_core::core::AudioQueue;
 use ensnare_core::prelude::*;
 use ensnare_core::traits::{Displays, HasSettings};
@@ -20,6 +25,40 @@ use std::{
 pub enum AudioPanelEvent {
     /// The audio interface changed, and sample rate etc. might have changed.
     InterfaceChanged,
+
+    /// Audio input capture started. Captured samples arrive on the queue as
+    /// stereo at the given (output) sample rate.
+    InputReset(SampleRate, AudioQueue),
+
+    /// The names of the available audio output devices.
+    OutputDevices(Vec<String>),
+
+    /// The output is now playing through the named device.
+    OutputDeviceSelected(String),
+
+    /// Something went wrong with an output device, such as selecting one that
+    /// has since been unplugged.
+    OutputDeviceError(String),
+}
+
+/// Which audio system the output stream goes through.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AudioBackend {
+    /// The platform's default audio host, through cpal.
+    #[default]
+    Cpal,
+    /// A JACK client with its own ports in the patchbay. Needs the `jack`
+    /// feature and a running JACK server.
+    Jack,
+}
+impl AudioBackend {
+    #[allow(missing_docs)]
+    pub fn label(&self) -> &'static str {
+        match self {
+            AudioBackend::Cpal => "System default",
+            AudioBackend::Jack => "JACK",
+        }
+    }
 }
 
 /// Contains persistent audio settings.
@@ -28,6 +67,15 @@ pub struct AudioSettings {
     sample_rate: SampleRate,
     channel_count: u16,
 
+    /// The name of the chosen output device, or None for the system default.
+    #[serde(default)]
+    output_device: Option<String>,
+
+    /// The audio system to play through. A change takes effect the next time
+    /// the app starts.
+    #[serde(default)]
+    backend: AudioBackend,
+
     #[serde(skip)]
     has_been_saved: bool,
 }
@@ -36,6 +84,8 @@ impl Default for AudioSettings {
         Self {
             sample_rate: SampleRate::default(),
             channel_count: 2,
+            output_device: None,
+            backend: Default::default(),
             has_been_saved: false,
         }
     }
@@ -58,6 +108,8 @@ impl AudioSettings {
         Self {
             sample_rate,
             channel_count,
+            output_device: None,
+            backend: Default::default(),
             has_been_saved: Default::default(),
         }
     }
@@ -69,6 +121,32 @@ impl AudioSettings {
     pub(crate) fn channel_count(&self) -> u16 {
         self.channel_count
     }
+
+    /// The name of the chosen output device, or None for the system default.
+    pub fn output_device(&self) -> Option<&String> {
+        self.output_device.as_ref()
+    }
+
+    /// Updates the field and marks the struct eligible to save.
+    pub fn set_output_device(&mut self, output_device: Option<String>) {
+        if output_device != self.output_device {
+            self.output_device = output_device;
+            self.needs_save();
+        }
+    }
+
+    #[allow(missing_docs)]
+    pub fn backend(&self) -> AudioBackend {
+        self.backend
+    }
+
+    /// Updates the field and marks the struct eligible to save.
+    pub fn set_backend(&mut self, backend: AudioBackend) {
+        if backend != self.backend {
+            self.backend = backend;
+            self.needs_save();
+        }
+    }
 }
 
 // Thanks https://boydjohnson.dev/blog/impl-debug-for-fn-type/
@@ -79,41 +157,104 @@ impl<F> NeedsAudioFnT for F where F: FnMut(&AudioQueue, usize) + Sync + Send {}
 pub type NeedsAudioFn = Box<dyn NeedsAudioFnT>;
 
 /// [AudioPanel] manages the audio interface.
-#[derive(Debug)]
 pub struct AudioPanel {
-    #[allow(dead_code)]
-    sender: Sender<AudioInterfaceInput>,
     app_receiver: Receiver<AudioPanelEvent>, // to give to the app to receive what we sent
     app_sender: Sender<AudioPanelEvent>,     // for us to send to the app
 
     config: Arc<Mutex<Option<AudioSettings>>>,
+
+    // Shared so that a stream opened on another device can be fed by the same
+    // function.
+    needs_audio_fn: Arc<Mutex<NeedsAudioFn>>,
+
+    output_stream: Option<CpalOutputStream>,
+    output_devices: Vec<String>,
+    /// What went wrong the last time the user chose an output device.
+    output_error: Option<String>,
+
+    input_stream: Option<AudioInputStream>,
+
+    #[cfg(feature = "jack")]
+    jack_output: Option<JackOutputStream>,
+
+    backend: AudioBackend,
+}
+impl Debug for AudioPanel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AudioPanel")
+            .field("config", &self.config)
+            .field("output_stream", &self.output_stream)
+            .field("output_error", &self.output_error)
+            .field("backend", &self.backend)
+            .finish()
+    }
 }
 impl AudioPanel {
-    /// Construct a new [AudioPanel].
+    /// Construct a new [AudioPanel], playing through the default output
+    /// device.
     pub fn new_with(needs_audio_fn: NeedsAudioFn) -> Self {
-        let audio_stream_service = AudioStreamService::default();
-        let sender = audio_stream_service.sender().clone();
+        let mut r = Self::new_with_backend_unstarted(needs_audio_fn, AudioBackend::Cpal);
+        match CpalOutputStream::start(None) {
+            Ok((stream, receiver)) => {
+                r.start_audio_stream(receiver);
+                r.output_stream = Some(stream);
+            }
+            Err(e) => r.report_output_error(format!("couldn't open audio output: {e}")),
+        }
+        r
+    }
 
-        let (app_sender, app_receiver) = crossbeam_channel::unbounded();
+    /// Like new_with(), but plays through the given backend. JACK isn't
+    /// available without the `jack` feature, and needs a running server.
+    pub fn new_with_backend(
+        needs_audio_fn: NeedsAudioFn,
+        backend: AudioBackend,
+    ) -> anyhow::Result<Self> {
+        match backend {
+            AudioBackend::Cpal => Ok(Self::new_with(needs_audio_fn)),
+            #[cfg(feature = "jack")]
+            AudioBackend::Jack => {
+                let (stream, receiver) = JackOutputStream::start()?;
+                let mut r = Self::new_with_backend_unstarted(needs_audio_fn, backend);
+                r.start_audio_stream(receiver);
+                r.jack_output = Some(stream);
+                Ok(r)
+            }
+            #[cfg(not(feature = "jack"))]
+            AudioBackend::Jack => Err(anyhow!("this build doesn't support JACK")),
+        }
+    }
 
-        let r = Self {
-            sender,
+    fn new_with_backend_unstarted(needs_audio_fn: NeedsAudioFn, backend: AudioBackend) -> Self {
+        let (app_sender, app_receiver) = crossbeam_channel::unbounded();
+        Self {
             app_sender,
             app_receiver,
             config: Default::default(),
-        };
-        r.start_audio_stream(needs_audio_fn, audio_stream_service.receiver().clone());
+            needs_audio_fn: Arc::new(Mutex::new(needs_audio_fn)),
+            output_stream: None,
+            output_devices: Default::default(),
+            output_error: None,
+            input_stream: None,
+            #[cfg(feature = "jack")]
+            jack_output: None,
+            backend,
+        }
+    }
 
-        r
+    /// The audio system that the panel is playing through.
+    pub fn backend(&self) -> AudioBackend {
+        self.backend
     }
 
-    fn start_audio_stream(
-        &self,
-        mut needs_audio_fn: NeedsAudioFn,
-        receiver: Receiver<AudioInterfaceEvent>,
-    ) {
+    // Feeds the stream that `receiver` belongs to. The thread ends when the
+    // stream is dropped, which is how a stream is torn down when the user
+    // switches devices.
+    fn start_audio_stream(&self, receiver: Receiver<AudioInterfaceEvent>) {
+        let needs_audio_fn = Arc::clone(&self.needs_audio_fn);
         let config = Arc::clone(&self.config);
         let app_sender = self.app_sender.clone();
+        let backend = self.backend;
         std::thread::spawn(move || {
             let mut queue_opt = None;
             loop {
@@ -121,20 +262,31 @@ impl AudioPanel {
                     match event {
                         AudioInterfaceEvent::Reset(sample_rate, channel_count, queue) => {
                             if let Ok(mut config) = config.lock() {
-                                *config = Some(AudioSettings::new_with(sample_rate, channel_count));
+                                // The interface doesn't know which device the
+                                // user chose, so carry that over.
+                                let output_device =
+                                    config.as_ref().and_then(|c| c.output_device.clone());
+                                let backend = config.as_ref().map(|c| c.backend).unwrap_or(backend);
+                                let mut new_config =
+                                    AudioSettings::new_with(sample_rate, channel_count);
+                                new_config.output_device = output_device;
+                                new_config.backend = backend;
+                                *config = Some(new_config);
                             }
                             let _ = app_sender.send(AudioPanelEvent::InterfaceChanged);
                             queue_opt = Some(queue);
                         }
                         AudioInterfaceEvent::NeedsAudio(_when, count) => {
-                            if let Some(queue) = queue_opt.as_ref() {
+                            if let (Some(queue), Ok(mut needs_audio_fn)) =
+                                (queue_opt.as_ref(), needs_audio_fn.lock())
+                            {
                                 (*needs_audio_fn)(queue, count);
                             }
                         }
-                        AudioInterfaceEvent::Quit => todo!(),
+                        AudioInterfaceEvent::Quit => break,
                     }
                 } else {
-                    eprintln!("Unexpected failure of AudioInterfaceEvent channel");
+                    // The stream is gone.
                     break;
                 }
             }
@@ -162,6 +314,109 @@ impl AudioPanel {
         0
     }
 
+    /// Enumerates the available output devices, and sends them in an
+    /// [AudioPanelEvent::OutputDevices].
+    pub fn refresh_output_devices(&mut self) -> Vec<String> {
+        let names = Self::output_device_names();
+        let _ = self
+            .app_sender
+            .send(AudioPanelEvent::OutputDevices(names.clone()));
+        self.output_devices = names.clone();
+        names
+    }
+
+    fn output_device_names() -> Vec<String> {
+        match cpal::default_host().output_devices() {
+            Ok(devices) => devices.filter_map(|d| d.name().ok()).collect(),
+            Err(e) => {
+                eprintln!("Warning: couldn't enumerate audio output devices: {e}");
+                Vec::default()
+            }
+        }
+    }
+
+    /// Switches playback to the named output device: opens a stream on it,
+    /// and then drops the current one. Sends
+    /// [AudioPanelEvent::OutputDeviceSelected] on success. If the device is
+    /// gone or won't open, the current stream keeps playing, and the panel
+    /// shows the error and sends [AudioPanelEvent::OutputDeviceError].
+    pub fn select_output_device(&mut self, name: &str) -> anyhow::Result<()> {
+        if self.backend != AudioBackend::Cpal {
+            let message = format!("{} chooses its own output devices", self.backend.label());
+            self.report_output_error(message.clone());
+            return Err(anyhow!(message));
+        }
+        if self.output_stream.as_ref().and_then(|s| s.device_name()) == Some(name) {
+            return Ok(());
+        }
+        let (stream, receiver) = match CpalOutputStream::start(Some(name)) {
+            Ok(r) => r,
+            Err(e) => {
+                let message = format!("couldn't switch audio output to {name}: {e}");
+                self.report_output_error(message.clone());
+                return Err(anyhow!(message));
+            }
+        };
+        self.output_stream = None;
+        if let Ok(mut config) = self.config.lock() {
+            if let Some(config) = config.as_mut() {
+                config.set_output_device(Some(name.to_string()));
+            } else {
+                let mut new_config = AudioSettings::default();
+                new_config.set_output_device(Some(name.to_string()));
+                *config = Some(new_config);
+            }
+        }
+        self.start_audio_stream(receiver);
+        self.output_stream = Some(stream);
+        self.output_error = None;
+        let _ = self
+            .app_sender
+            .send(AudioPanelEvent::OutputDeviceSelected(name.to_string()));
+        Ok(())
+    }
+
+    fn report_output_error(&mut self, message: String) {
+        eprintln!("Warning: {message}");
+        self.output_error = Some(message.clone());
+        let _ = self
+            .app_sender
+            .send(AudioPanelEvent::OutputDeviceError(message));
+    }
+
+    /// The name of the chosen output device, or None for the system default.
+    pub fn output_device(&self) -> Option<String> {
+        if let Ok(config) = self.config.lock() {
+            if let Some(config) = config.as_ref() {
+                return config.output_device.clone();
+            }
+        }
+        None
+    }
+
+    /// Starts capturing from the default audio input device, matched to the
+    /// output's sample rate. Sends [AudioPanelEvent::InputReset] with the
+    /// queue that captured samples will arrive on.
+    pub fn start_input(&mut self) -> anyhow::Result<()> {
+        let stream = AudioInputStream::create_input_stream(self.sample_rate())?;
+        let _ = self.app_sender.send(AudioPanelEvent::InputReset(
+            stream.sample_rate(),
+            stream.queue().clone(),
+        ));
+        self.input_stream = Some(stream);
+        Ok(())
+    }
+
+    /// Stops audio input capture.
+    pub fn stop_input(&mut self) {
+        self.input_stream = None;
+    }
+
+    /// Whether audio input capture is running.
+    pub fn is_input_active(&self) -> bool {
+        self.input_stream.is_some()
+    }
+
     /// The receive side of the [AudioPanelEvent] channel
     pub fn receiver(&self) -> &Receiver<AudioPanelEvent> {
         &self.app_receiver
@@ -174,6 +429,51 @@ impl AudioPanel {
     }
 }
 
+impl Displays for AudioPanel {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        CollapsingHeader::new("Audio")
+            .default_open(true)
+            .show(ui, |ui| {
+                if let Ok(mut config) = self.config.lock() {
+                    if let Some(config) = config.as_mut() {
+                        ui.label(format!("Sample rate: {}", config.sample_rate()));
+                        ui.label(format!("Channels: {}", config.channel_count()));
+                        ui.label(format!("Backend: {}", config.backend().label()));
+                    }
+                }
+                if self.backend != AudioBackend::Cpal {
+                    return;
+                }
+                let current = self
+                    .output_stream
+                    .as_ref()
+                    .and_then(|s| s.device_name())
+                    .unwrap_or("System default")
+                    .to_string();
+                let mut choice = None;
+                let combo = ComboBox::from_label("Output device")
+                    .selected_text(&current)
+                    .show_ui(ui, |ui| {
+                        for name in self.output_devices.iter() {
+                            if ui.selectable_label(*name == current, name).clicked() {
+                                choice = Some(name.clone());
+                            }
+                        }
+                    });
+                if combo.response.clicked() {
+                    let _ = self.refresh_output_devices();
+                }
+                if let Some(name) = choice {
+                    let _ = self.select_output_device(&name);
+                }
+                if let Some(error) = self.output_error.as_ref() {
+                    ui.colored_label(Color32::LIGHT_RED, error);
+                }
+            })
+            .header_response
+    }
+}
+
 /// Wraps an [AudioSettingsWidget] as a [Widget](eframe::egui::Widget). Mutates the given view_range.
 pub fn audio_settings(settings: &mut AudioSettings) -> impl eframe::egui::Widget + '_ {
     move |ui: &mut eframe::egui::Ui| AudioSettingsWidget::new_with(settings).ui(ui)
@@ -195,6 +495,14 @@ impl<'a> Displays for AudioSettingsWidget<'a> {
             .show(ui, |ui| {
                 ui.label(format!("Sample rate: {}", self.settings.sample_rate()));
                 ui.label(format!("Channels: {}", self.settings.channel_count()));
+                ui.label(format!("Backend: {}", self.settings.backend().label()));
+                ui.label(format!(
+                    "Output device: {}",
+                    self.settings
+                        .output_device()
+                        .map(|s| s.as_str())
+                        .unwrap_or("System default")
+                ));
             })
             .header_response
     }
@@ -0,0 +1,102 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use crate::control::ParamDescriptor;
+use eframe::egui::{Slider, Ui};
+use ensnare_core::{control::ControlValue, prelude::*, traits::Controllable};
+
+/// A generic editor that draws one labeled slider per control, using only the
+/// [ParamDescriptor]s that #[derive(Control)] generates. It needs to know
+/// nothing about the concrete type of the entity it's editing.
+#[derive(Clone, Debug, Default)]
+pub struct ControlEditorPanel {
+    /// The entity whose values are in `values`.
+    uid: Option<Uid>,
+
+    /// The values the sliders are currently showing. [Controllable] is
+    /// write-only, so we track them ourselves, starting from the values that
+    /// the entity reported when we started editing it.
+    values: Vec<f64>,
+}
+impl ControlEditorPanel {
+    /// Draws a slider for each descriptor, and sends changed values to the
+    /// target entity. Sliders work in each control's native range, but
+    /// setters expect 0..=1, so values are normalized on the way out.
+    ///
+    /// `current_values` are the entity's actual values, in native units, in
+    /// the same order as `descriptors`. They're read whenever the editor
+    /// switches to a different entity.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        uid: Uid,
+        descriptors: &[ParamDescriptor],
+        current_values: &[f64],
+        target: &mut dyn Controllable,
+    ) {
+        self.conform(uid, descriptors, current_values);
+        ui.vertical(|ui| {
+            for (i, descriptor) in descriptors.iter().enumerate() {
+                let mut slider = Slider::new(&mut self.values[i], descriptor.min..=descriptor.max)
+                    .text(&descriptor.name);
+                if !descriptor.unit.is_empty() {
+                    slider = slider.suffix(format!(" {}", descriptor.unit));
+                }
+                if ui.add(slider).changed() {
+                    target.control_set_param_by_index(
+                        descriptor.index,
+                        ControlValue(descriptor.normalize(self.values[i])),
+                    );
+                }
+            }
+        });
+    }
+
+    // Starts over from the entity's real values if we were editing some other
+    // entity, or if its controls have changed. Values it doesn't report fall
+    // back to their defaults.
+    fn conform(&mut self, uid: Uid, descriptors: &[ParamDescriptor], current_values: &[f64]) {
+        if self.uid == Some(uid) && self.values.len() == descriptors.len() {
+            return;
+        }
+        self.uid = Some(uid);
+        self.values = descriptors
+            .iter()
+            .enumerate()
+            .map(|(i, d)| current_values.get(i).copied().unwrap_or(d.default))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ensnare_core::control::ControlIndex;
+
+    fn descriptor(index: usize, default: f64) -> ParamDescriptor {
+        ParamDescriptor {
+            name: format!("control-{index}"),
+            index: ControlIndex(index),
+            min: -10.0,
+            max: 10.0,
+            default,
+            unit: String::default(),
+        }
+    }
+
+    #[test]
+    fn values_come_from_the_entity_and_follow_its_uid() {
+        let descriptors = vec![descriptor(0, 0.0), descriptor(1, 0.0)];
+        let mut editor = ControlEditorPanel::default();
+        editor.conform(Uid(1), &descriptors, &[3.0, -4.0]);
+        assert_eq!(editor.values, vec![3.0, -4.0], "not the defaults");
+
+        // While editing the same entity, the sliders keep their own values.
+        editor.values[0] = 5.0;
+        editor.conform(Uid(1), &descriptors, &[3.0, -4.0]);
+        assert_eq!(editor.values, vec![5.0, -4.0]);
+
+        // Another entity with the same number of controls starts over.
+        editor.conform(Uid(2), &descriptors, &[7.0]);
+        assert_eq!(editor.values, vec![7.0, 0.0]);
+    }
+}
@@ -1,10 +1,15 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
-use eframe::egui::Ui;
-use ensnare_core::{time::Transport, traits::Displays, widgets::core::transport};
+use eframe::egui::{Key, Ui};
+use ensnare_core::{
+    time::Transport,
+    traits::{Controls, Displays},
+    widgets::core::transport,
+};
 use std::path::PathBuf;
 
 /// Actions the user might take via the control panel.
+#[derive(Debug, PartialEq)]
 pub enum ControlPanelAction {
     /// Play button pressed.
     Play,
@@ -23,6 +28,19 @@ pub enum ControlPanelAction {
 
     /// The user pressed the settings icon.
     ToggleSettings,
+
+    /// The user asked to turn looping on or off.
+    ToggleLoop,
+
+    /// The user asked to silence all stuck notes.
+    Panic,
+
+    /// The user asked to join or leave the Ableton Link session.
+    ToggleLink,
+
+    /// The user tapped the tempo button. The tempo comes from the time
+    /// between taps; see `Transport::tap()`.
+    TapTempo,
 }
 
 /// [ControlPanel] is the UI component at the top of the main window. Transport,
@@ -33,6 +51,9 @@ pub struct ControlPanel {
     // TODO: this is awful. I think it goes away when we factor out ui() and
     // make it into a temp reference
     transport_copy: Transport,
+
+    /// Whether the app is in a Link session, and with how many peers.
+    link_status: Option<usize>,
 }
 impl ControlPanel {
     /// Renders the control bar and maybe returns a UI action.
@@ -46,6 +67,31 @@ impl ControlPanel {
             if ui.button("stop").clicked() {
                 action = Some(ControlPanelAction::Stop);
             }
+            let link_label = match self.link_status {
+                Some(peers) => format!("link ({peers})"),
+                None => "link".to_string(),
+            };
+            if ui
+                .selectable_label(self.link_status.is_some(), link_label)
+                .on_hover_text("Sync tempo and bars with Ableton Link peers")
+                .clicked()
+            {
+                action = Some(ControlPanelAction::ToggleLink);
+            }
+            if ui
+                .button("tap")
+                .on_hover_text("Tap along to set the tempo")
+                .clicked()
+            {
+                action = Some(ControlPanelAction::TapTempo);
+            }
+            if ui
+                .button("panic")
+                .on_hover_text("Silence all notes")
+                .clicked()
+            {
+                action = Some(ControlPanelAction::Panic);
+            }
             ui.separator();
             if ui.button("new").clicked() {
                 action = Some(ControlPanelAction::New);
@@ -62,10 +108,50 @@ impl ControlPanel {
             }
         });
 
+        if action.is_none() {
+            action = self.handle_keys(ui);
+        }
         action
     }
 
-    /// Updates the copy of [Transport] with a fresh one.
+    /// Space toggles play/stop, Return stops, and L toggles looping. Keys are
+    /// ignored while a widget like a text field is taking keyboard input.
+    /// Space relies on the app having called [ControlPanel::set_transport()]
+    /// this frame.
+    fn handle_keys(&self, ui: &mut Ui) -> Option<ControlPanelAction> {
+        if ui.ctx().wants_keyboard_input() {
+            return None;
+        }
+        ui.input(|i| {
+            [Key::Space, Key::Enter, Key::L]
+                .into_iter()
+                .filter(|key| i.key_pressed(*key))
+                .find_map(|key| self.action_for_key(key))
+        })
+    }
+
+    // The action for a shortcut key, given whether the transport is playing.
+    fn action_for_key(&self, key: Key) -> Option<ControlPanelAction> {
+        match key {
+            // Reflect the actual play state, rather than always asking to
+            // play.
+            Key::Space if self.transport_copy.is_performing() => Some(ControlPanelAction::Stop),
+            Key::Space => Some(ControlPanelAction::Play),
+            Key::Enter => Some(ControlPanelAction::Stop),
+            Key::L => Some(ControlPanelAction::ToggleLoop),
+            _ => None,
+        }
+    }
+
+    /// Shows whether the app is in a Link session, and with how many peers,
+    /// or None if it isn't.
+    pub fn set_link_status(&mut self, link_status: Option<usize>) {
+        self.link_status = link_status;
+    }
+
+    /// Updates the copy of [Transport] with a fresh one. Call this every
+    /// frame before [ControlPanel::show_with_action()], so that the transport
+    /// display and the Space key reflect the actual play state.
     pub fn set_transport(&mut self, transport: Transport) {
         self.transport_copy = transport;
     }
@@ -171,3 +257,55 @@ impl ControlBar {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transport(is_performing: bool) -> Transport {
+        let mut transport = Transport::default();
+        if is_performing {
+            transport.play();
+        }
+        transport
+    }
+
+    #[test]
+    fn keys_map_to_transport_actions() {
+        let mut panel = ControlPanel::default();
+        assert_eq!(
+            panel.action_for_key(Key::Enter),
+            Some(ControlPanelAction::Stop)
+        );
+        assert_eq!(panel.action_for_key(Key::A), None);
+        panel.set_transport(transport(true));
+        assert_eq!(
+            panel.action_for_key(Key::L),
+            Some(ControlPanelAction::ToggleLoop)
+        );
+    }
+
+    #[test]
+    fn space_follows_the_pushed_play_state() {
+        let mut panel = ControlPanel::default();
+        panel.set_transport(transport(false));
+        assert_eq!(
+            panel.action_for_key(Key::Space),
+            Some(ControlPanelAction::Play)
+        );
+
+        panel.set_transport(transport(true));
+        assert_eq!(
+            panel.action_for_key(Key::Space),
+            Some(ControlPanelAction::Stop),
+            "space should stop, not play again"
+        );
+
+        panel.set_transport(transport(false));
+        assert_eq!(
+            panel.action_for_key(Key::Space),
+            Some(ControlPanelAction::Play),
+            "space should play again once stopped"
+        );
+    }
+}
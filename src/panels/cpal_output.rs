@@ -0,0 +1,176 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use anyhow::anyhow;
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SizedSample, Stream, StreamConfig,
+};
+use crossbeam::queue::ArrayQueue;
+use crossbeam_channel::{Receiver, Sender};
+use ensnare_core::{audio::AudioInterfaceEvent, core::AudioQueue, prelude::*};
+use std::{fmt::Debug, sync::Arc, time::Instant};
+
+/// Moves stereo samples from `queue` into an interleaved buffer of
+/// `channel_count` channels. Mono gets the average of left and right, and
+/// channels beyond the first two are silent. If the queue runs dry, the rest
+/// of the buffer is silence. Returns how many frames came up short.
+pub fn drain_to_interleaved<T>(queue: &AudioQueue, data: &mut [T], channel_count: usize) -> usize
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channel_count = channel_count.max(1);
+    let mut underruns = 0;
+    for frame in data.chunks_mut(channel_count) {
+        let sample = queue.pop().unwrap_or_else(|| {
+            underruns += 1;
+            StereoSample::SILENCE
+        });
+        let (left, right) = (sample.0 .0 as f32, sample.1 .0 as f32);
+        for (i, value) in frame.iter_mut().enumerate() {
+            *value = T::from_sample(match (i, channel_count) {
+                (0, 1) => (left + right) / 2.0,
+                (0, _) => left,
+                (1, _) => right,
+                _ => 0.0,
+            });
+        }
+    }
+    underruns
+}
+
+/// Plays audio through a cpal output device, either the system default or
+/// one chosen by name. Like
+/// [JackOutputStream](crate::panels::JackOutputStream), it sends an
+/// [AudioInterfaceEvent::Reset] with the queue it plays from, and an
+/// [AudioInterfaceEvent::NeedsAudio] after each callback. Dropping it closes
+/// the device and ends the events, so switching devices is a matter of
+/// starting a new one and dropping the old.
+pub struct CpalOutputStream {
+    // Dropping the stream stops playback, so we hold onto it.
+    #[allow(dead_code)]
+    stream: Stream,
+    device_name: Option<String>,
+    sample_rate: SampleRate,
+}
+impl Debug for CpalOutputStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CpalOutputStream")
+            .field("device_name", &self.device_name)
+            .field("sample_rate", &self.sample_rate)
+            .finish()
+    }
+}
+impl CpalOutputStream {
+    /// How many stereo samples the queue holds. About 90 milliseconds at
+    /// 44.1KHz.
+    const QUEUE_CAPACITY: usize = 4096;
+
+    /// Opens the named output device, or the default one if `device_name` is
+    /// None, and starts playing. Returns the stream, which plays as long as
+    /// it's kept, and the receiving end of its [AudioInterfaceEvent]s.
+    pub fn start(
+        device_name: Option<&str>,
+    ) -> anyhow::Result<(Self, Receiver<AudioInterfaceEvent>)> {
+        let host = cpal::default_host();
+        let device = match device_name {
+            Some(name) => host
+                .output_devices()?
+                .find(|d| d.name().is_ok_and(|n| n == name))
+                .ok_or_else(|| anyhow!("audio output device {name} is not available"))?,
+            None => host
+                .default_output_device()
+                .ok_or_else(|| anyhow!("no default audio output device"))?,
+        };
+        let supported = device.default_output_config()?;
+        let sample_rate = SampleRate(supported.sample_rate().0 as usize);
+        let config: StreamConfig = supported.clone().into();
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(Self::QUEUE_CAPACITY));
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let _ = sender.send(AudioInterfaceEvent::Reset(
+            sample_rate,
+            config.channels,
+            Arc::clone(&queue),
+        ));
+        let _ = sender.send(AudioInterfaceEvent::NeedsAudio(
+            Instant::now(),
+            Self::QUEUE_CAPACITY / 2,
+        ));
+
+        let stream = match supported.sample_format() {
+            cpal::SampleFormat::I16 => Self::build_stream::<i16>(&device, &config, queue, sender),
+            cpal::SampleFormat::U16 => Self::build_stream::<u16>(&device, &config, queue, sender),
+            cpal::SampleFormat::F32 => Self::build_stream::<f32>(&device, &config, queue, sender),
+            format => Err(anyhow!("unsupported output sample format {format}")),
+        }?;
+        stream.play()?;
+        Ok((
+            Self {
+                stream,
+                device_name: device_name.map(|n| n.to_string()),
+                sample_rate,
+            },
+            receiver,
+        ))
+    }
+
+    fn build_stream<T>(
+        device: &cpal::Device,
+        config: &StreamConfig,
+        queue: AudioQueue,
+        sender: Sender<AudioInterfaceEvent>,
+    ) -> anyhow::Result<Stream>
+    where
+        T: SizedSample + FromSample<f32>,
+    {
+        let channel_count = config.channels as usize;
+        let stream = device.build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let _ = drain_to_interleaved(&queue, data, channel_count);
+                let _ = sender.send(AudioInterfaceEvent::NeedsAudio(
+                    Instant::now(),
+                    data.len() / channel_count.max(1),
+                ));
+            },
+            |err| eprintln!("Warning: audio output stream error: {err}"),
+            None,
+        )?;
+        Ok(stream)
+    }
+
+    /// The device's name, or None if it's the system default.
+    pub fn device_name(&self) -> Option<&str> {
+        self.device_name.as_deref()
+    }
+
+    #[allow(missing_docs)]
+    pub fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drain_interleaves_and_fills_with_silence() {
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(8));
+        let _ = queue.push(StereoSample(Sample(0.5), Sample(-0.5)));
+        let _ = queue.push(StereoSample(Sample(0.25), Sample(-0.25)));
+        let mut data = [1.0f32; 6];
+        assert_eq!(drain_to_interleaved(&queue, &mut data, 2), 1);
+        assert_eq!(data, [0.5, -0.5, 0.25, -0.25, 0.0, 0.0]);
+
+        let _ = queue.push(StereoSample(Sample(0.5), Sample(0.25)));
+        let mut data = [1.0f32; 3];
+        assert_eq!(drain_to_interleaved(&queue, &mut data, 3), 0);
+        assert_eq!(data, [0.5, 0.25, 0.0], "extra channels are silent");
+
+        let _ = queue.push(StereoSample(Sample(0.5), Sample(0.25)));
+        let mut data = [1.0f32; 1];
+        assert_eq!(drain_to_interleaved(&queue, &mut data, 1), 0);
+        assert_eq!(data, [0.375], "mono is the average");
+    }
+}
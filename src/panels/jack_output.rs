@@ -0,0 +1,161 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+use ensnare_core::{core::AudioQueue, prelude::*};
+
+/// Moves stereo samples from `queue` into the two planar buffers that a JACK
+/// period wants. If the queue runs dry, the rest of the period is silence.
+/// Returns how many frames came up short.
+pub fn drain_to_planar(queue: &AudioQueue, left: &mut [f32], right: &mut [f32]) -> usize {
+    let mut underruns = 0;
+    for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+        let sample = queue.pop().unwrap_or_else(|| {
+            underruns += 1;
+            StereoSample::SILENCE
+        });
+        *l = sample.0 .0 as f32;
+        *r = sample.1 .0 as f32;
+    }
+    underruns
+}
+
+#[cfg(feature = "jack")]
+pub use stream::JackOutputStream;
+
+#[cfg(feature = "jack")]
+mod stream {
+    use super::drain_to_planar;
+    use anyhow::anyhow;
+    use crossbeam::queue::ArrayQueue;
+    use crossbeam_channel::Receiver;
+    use ensnare_core::{audio::AudioInterfaceEvent, core::AudioQueue, prelude::*};
+    use std::{fmt::Debug, sync::Arc, time::Instant};
+
+    type ProcessFn = Box<dyn FnMut(&jack::Client, &jack::ProcessScope) -> jack::Control + Send>;
+
+    /// Plays audio through a JACK client, as an alternative to
+    /// [CpalOutputStream](crate::panels::CpalOutputStream). The client registers the ports [JackOutputStream::LEFT_PORT] and
+    /// [JackOutputStream::RIGHT_PORT], which show up in patchbays like
+    /// qjackctl, and connects them to the first two physical outputs to start
+    /// with.
+    ///
+    /// It speaks the same protocol: it sends an
+    /// [AudioInterfaceEvent::Reset] with the queue it plays from, and an
+    /// [AudioInterfaceEvent::NeedsAudio] after each period, so the code that
+    /// feeds the cpal stream feeds this one unchanged. JACK sets the sample
+    /// rate and period size, not us.
+    pub struct JackOutputStream {
+        // Dropping the client deactivates it and removes its ports.
+        #[allow(dead_code)]
+        client: jack::AsyncClient<(), jack::ClosureProcessHandler<ProcessFn>>,
+        sample_rate: SampleRate,
+    }
+    impl Debug for JackOutputStream {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("JackOutputStream")
+                .field("sample_rate", &self.sample_rate)
+                .finish()
+        }
+    }
+    impl JackOutputStream {
+        #[allow(missing_docs)]
+        pub const CLIENT_NAME: &'static str = "groove";
+        #[allow(missing_docs)]
+        pub const LEFT_PORT: &'static str = "out_left";
+        #[allow(missing_docs)]
+        pub const RIGHT_PORT: &'static str = "out_right";
+
+        /// JACK's name for the type of its audio ports.
+        const AUDIO_PORT_TYPE: &'static str = "32 bit float mono audio";
+
+        /// How many periods of audio the queue holds. More is safer against
+        /// underruns, at the cost of latency.
+        const PERIODS_BUFFERED: usize = 3;
+
+        /// Connects to the running JACK server and starts playing. Returns the
+        /// stream, which plays as long as it's kept, and the receiving end of
+        /// its [AudioInterfaceEvent]s.
+        pub fn start() -> anyhow::Result<(Self, Receiver<AudioInterfaceEvent>)> {
+            let (client, _status) =
+                jack::Client::new(Self::CLIENT_NAME, jack::ClientOptions::NO_START_SERVER)
+                    .map_err(|e| anyhow!("couldn't connect to the JACK server: {e}"))?;
+            let mut left = client.register_port(Self::LEFT_PORT, jack::AudioOut::default())?;
+            let mut right = client.register_port(Self::RIGHT_PORT, jack::AudioOut::default())?;
+            let sample_rate = SampleRate(client.sample_rate());
+            let period = client.buffer_size() as usize;
+            let queue: AudioQueue = Arc::new(ArrayQueue::new(period * Self::PERIODS_BUFFERED));
+
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            let _ = sender.send(AudioInterfaceEvent::Reset(
+                sample_rate,
+                2,
+                Arc::clone(&queue),
+            ));
+            let _ = sender.send(AudioInterfaceEvent::NeedsAudio(
+                Instant::now(),
+                period * (Self::PERIODS_BUFFERED - 1),
+            ));
+
+            let process: ProcessFn = Box::new(move |_, ps| {
+                let left = left.as_mut_slice(ps);
+                let right = right.as_mut_slice(ps);
+                let _ = drain_to_planar(&queue, left, right);
+                let _ = sender.send(AudioInterfaceEvent::NeedsAudio(Instant::now(), left.len()));
+                jack::Control::Continue
+            });
+            let client = client
+                .activate_async((), jack::ClosureProcessHandler::new(process))
+                .map_err(|e| anyhow!("couldn't activate the JACK client: {e}"))?;
+            Self::connect_to_system(client.as_client());
+
+            Ok((
+                Self {
+                    client,
+                    sample_rate,
+                },
+                receiver,
+            ))
+        }
+
+        #[allow(missing_docs)]
+        pub fn sample_rate(&self) -> SampleRate {
+            self.sample_rate
+        }
+
+        // Patches our outputs to the first two physical playback ports, so
+        // that there's sound before the user touches the patchbay.
+        fn connect_to_system(client: &jack::Client) {
+            let playback = client.ports(
+                None,
+                Some(Self::AUDIO_PORT_TYPE),
+                jack::PortFlags::IS_INPUT | jack::PortFlags::IS_PHYSICAL,
+            );
+            let ours =
+                [Self::LEFT_PORT, Self::RIGHT_PORT].map(|port| format!("{}:{port}", client.name()));
+            for (source, destination) in ours.iter().zip(playback.iter()) {
+                if let Err(e) = client.connect_ports_by_name(source, destination) {
+                    eprintln!("Warning: couldn't connect {source} to {destination}: {e}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam::queue::ArrayQueue;
+    use std::sync::Arc;
+
+    #[test]
+    fn drain_fills_with_silence_on_underrun() {
+        let queue: AudioQueue = Arc::new(ArrayQueue::new(8));
+        let _ = queue.push(StereoSample(Sample(0.5), Sample(-0.5)));
+        let _ = queue.push(StereoSample(Sample(0.25), Sample(-0.25)));
+        let mut left = [1.0f32; 4];
+        let mut right = [1.0f32; 4];
+        assert_eq!(drain_to_planar(&queue, &mut left, &mut right), 2);
+        assert_eq!(left, [0.5, 0.25, 0.0, 0.0]);
+        assert_eq!(right, [-0.5, -0.25, 0.0, 0.0]);
+        assert!(queue.is_empty());
+    }
+}
@@ -1,11 +1,12 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
-use crate::panels::{audio_panel::AudioSettings, AudioPanelEvent};
+use crate::panels::{audio_panel::AudioSettings, AudioPanelEvent, MidiOutputSender};
 use crossbeam_channel::{Receiver, Sender};
 use eframe::egui::{CollapsingHeader, Ui};
-use ensnare_core::core::StereoSample;
+use ensnare_core::{core::StereoSample, midi::prelude::*};
 use groove_audio::{AudioInterfaceEvent, AudioInterfaceInput, AudioQueue, AudioStreamService};
 use groove_core::{traits::gui::Displays, SAMPLE_BUFFER_SIZE};
+use groove_orchestration::messages::{GrooveEvent, Internal};
 use std::{
     fmt::Debug,
     sync::{Arc, Mutex, MutexGuard},
@@ -19,12 +20,17 @@ pub struct AudioPanel {
     app_receiver: Receiver<AudioPanelEvent>, // to give to the app to receive what we sent
     app_sender: Sender<AudioPanelEvent>,     // for us to send to the app
     orchestrator: Arc<Mutex<Orchestrator>>,
+    midi_output: Option<MidiOutputSender>,
 
     config: Arc<Mutex<Option<AudioSettings>>>,
 }
 impl AudioPanel {
-    /// Construct a new [AudioPanel].
-    pub fn new_with(orchestrator: Arc<Mutex<Orchestrator>>) -> Self {
+    /// Construct a new [AudioPanel]. MIDI that the [Orchestrator] produces for
+    /// external devices goes to `midi_output`.
+    pub fn new_with(
+        orchestrator: Arc<Mutex<Orchestrator>>,
+        midi_output: Option<MidiOutputSender>,
+    ) -> Self {
         let audio_stream_service = AudioStreamService::default();
         let sender = audio_stream_service.sender().clone();
 
@@ -35,6 +41,7 @@ impl AudioPanel {
             app_sender,
             app_receiver,
             orchestrator: Arc::clone(&orchestrator),
+            midi_output,
             config: Default::default(),
         };
         r.start_audio_stream(audio_stream_service.receiver().clone());
@@ -49,10 +56,12 @@ impl AudioPanel {
 
     fn start_audio_stream(&self, receiver: Receiver<AudioInterfaceEvent>) {
         let orchestrator = Arc::clone(&self.orchestrator);
+        let midi_output = self.midi_output.clone();
         let config = Arc::clone(&self.config);
         let app_sender = self.app_sender.clone();
         std::thread::spawn(move || {
             let mut queue_opt = None;
+            let mut midi_messages = Vec::default();
             loop {
                 if let Ok(event) = receiver.recv() {
                     match event {
@@ -66,7 +75,13 @@ impl AudioPanel {
                         AudioInterfaceEvent::NeedsAudio(_when, count) => {
                             if let Some(queue) = queue_opt.as_ref() {
                                 if let Ok(o) = orchestrator.lock() {
-                                    Self::generate_audio(o, queue, (count / 64) as u8);
+                                    Self::generate_audio(
+                                        o,
+                                        queue,
+                                        (count / 64) as u8,
+                                        midi_output.as_ref(),
+                                        &mut midi_messages,
+                                    );
                                 }
                             }
                         }
@@ -84,6 +99,8 @@ impl AudioPanel {
         mut orchestrator: MutexGuard<Orchestrator>,
         queue: &AudioQueue,
         buffer_count: u8,
+        midi_output: Option<&MidiOutputSender>,
+        midi_messages: &mut Vec<(MidiChannel, MidiMessage)>,
     ) {
         let mut samples = [StereoSample::SILENCE; SAMPLE_BUFFER_SIZE];
         for _ in 0..buffer_count {
@@ -97,17 +114,27 @@ impl AudioPanel {
                 let _ = queue.push(sample);
             }
 
+            // Everything this buffer sent to external devices goes out in one
+            // send.
+            midi_messages.clear();
             match response.0 {
-                groove_orchestration::messages::Internal::None => {}
-                groove_orchestration::messages::Internal::Single(_event) => {
-                    //                    self.handle_groove_event(event);
-                }
-                groove_orchestration::messages::Internal::Batch(events) => {
-                    for _event in events {
-                        //                      self.handle_groove_event(event)
+                Internal::None => {}
+                Internal::Single(event) => Self::collect_midi(event, midi_messages),
+                Internal::Batch(events) => {
+                    for event in events {
+                        Self::collect_midi(event, midi_messages);
                     }
                 }
             }
+            if let Some(midi_output) = midi_output {
+                midi_output.send_batch(midi_messages);
+            }
+        }
+    }
+
+    fn collect_midi(event: GrooveEvent, midi_messages: &mut Vec<(MidiChannel, MidiMessage)>) {
+        if let GrooveEvent::MidiToExternal(channel, message) = event {
+            midi_messages.push((channel, message));
         }
     }
 
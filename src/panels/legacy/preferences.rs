@@ -1,13 +1,14 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
 use eframe::egui::{CollapsingHeader, Ui};
-use groove_orchestration::Orchestrator;
+use groove_orchestration::{osc::OscServer, Orchestrator};
 use groove_settings::SongSettings;
 use groove_utils::Paths;
 use serde::{Deserialize, Serialize};
 use std::{
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 /// User-specific preferences for the whole app
@@ -17,6 +18,25 @@ pub struct Preferences {
     selected_midi_input: Option<String>,
     /// The last-selected MIDI output
     selected_midi_output: Option<String>,
+    /// The last-selected audio output device
+    #[serde(default)]
+    selected_audio_output: Option<String>,
+
+    /// The names of the panels that were open when the app last ran
+    #[serde(default)]
+    open_panels: Vec<String>,
+
+    /// Where to send the OSC state feed, like "192.168.1.20:9000", if anywhere
+    #[serde(default)]
+    osc_feed_destination: Option<String>,
+
+    /// Where to listen for OSC control, like "127.0.0.1:8000", if at all
+    #[serde(default)]
+    osc_server_address: Option<String>,
+
+    /// Whether to serve the browser API on this machine
+    #[serde(default)]
+    is_web_api_enabled: bool,
 
     /// Whether we should reload the last-loaded project on startup
     should_reload_last_project: bool,
@@ -26,8 +46,20 @@ pub struct Preferences {
 
     #[serde(skip)]
     is_saved: bool,
+
+    /// When the most recent unsaved change happened, if there is one
+    #[serde(skip)]
+    last_change: Option<Instant>,
 }
 impl Preferences {
+    /// How long after the last change we wait before saving, so that a burst
+    /// of changes is written once.
+    pub const SAVE_DELAY: Duration = Duration::from_secs(2);
+
+    /// The name that [Preferences::set_panel_open()] knows the settings
+    /// sidebar by.
+    pub const SETTINGS_PANEL: &str = "settings";
+
     /// Loads preferences from a well-known location, and creates a Preferences
     /// struct
     pub async fn load() -> anyhow::Result<Self, anyhow::Error> {
@@ -101,8 +133,30 @@ impl Preferences {
     }
 
     fn mark_dirty(&mut self) {
-        if !self.is_saved && futures::executor::block_on(self.save()).is_ok() {
-            self.is_saved = true;
+        self.is_saved = false;
+        self.last_change = Some(Instant::now());
+    }
+
+    fn is_save_due(&self, now: Instant) -> bool {
+        self.last_change
+            .is_some_and(|last_change| now.duration_since(last_change) >= Self::SAVE_DELAY)
+    }
+
+    /// Saves unsaved changes once nothing has changed for
+    /// [Preferences::SAVE_DELAY]. Call it once per frame.
+    pub fn save_if_due(&mut self, now: Instant) {
+        if self.is_save_due(now) {
+            self.save_if_dirty();
+        }
+    }
+
+    /// Saves unsaved changes right away, as when the app is quitting.
+    pub fn save_if_dirty(&mut self) {
+        if self.last_change.take().is_none() {
+            return;
+        }
+        if let Err(e) = futures::executor::block_on(self.save()) {
+            eprintln!("Warning: couldn't save preferences: {e}");
         }
     }
 
@@ -128,11 +182,116 @@ impl Preferences {
         self.mark_dirty();
     }
 
+    /// currently selected audio output device
+    pub fn selected_audio_output(&self) -> Option<&String> {
+        self.selected_audio_output.as_ref()
+    }
+
+    /// Set current audio output device
+    pub fn set_selected_audio_output(&mut self, selected_audio_output: &str) {
+        if self.selected_audio_output.as_deref() != Some(selected_audio_output) {
+            self.selected_audio_output = Some(selected_audio_output.to_string());
+            self.mark_dirty();
+        }
+    }
+
+    /// Returns the stored selection only if it's among the devices that
+    /// currently exist. A device that has since been unplugged is skipped
+    /// rather than treated as an error.
+    pub fn restorable_device<'a>(
+        selection: Option<&String>,
+        available: &'a [String],
+    ) -> Option<&'a String> {
+        selection.and_then(|name| available.iter().find(|a| *a == name))
+    }
+
+    /// names of the panels that were open last time
+    pub fn open_panels(&self) -> &[String] {
+        &self.open_panels
+    }
+
+    /// Whether the named panel was open last time
+    pub fn is_panel_open(&self, name: &str) -> bool {
+        self.open_panels.iter().any(|p| p == name)
+    }
+
+    /// Records whether the named panel is open
+    pub fn set_panel_open(&mut self, name: &str, is_open: bool) {
+        if self.is_panel_open(name) == is_open {
+            return;
+        }
+        if is_open {
+            self.open_panels.push(name.to_string());
+        } else {
+            self.open_panels.retain(|p| p != name);
+        }
+        self.mark_dirty();
+    }
+
+    /// where the OSC state feed goes, if anywhere
+    pub fn osc_feed_destination(&self) -> Option<&String> {
+        self.osc_feed_destination.as_ref()
+    }
+
+    /// Set where the OSC state feed goes, or None to turn it off
+    pub fn set_osc_feed_destination(&mut self, destination: Option<&str>) {
+        if self.osc_feed_destination.as_deref() != destination {
+            self.osc_feed_destination = destination.map(|d| d.to_string());
+            self.mark_dirty();
+        }
+    }
+
+    /// where to listen for OSC control, or None if we shouldn't
+    pub fn osc_server_address(&self) -> Option<&String> {
+        self.osc_server_address.as_ref()
+    }
+
+    /// Set where to listen for OSC control, or None to turn it off. It takes
+    /// effect on the next start.
+    pub fn set_osc_server_address(&mut self, address: Option<&str>) {
+        if self.osc_server_address.as_deref() != address {
+            self.osc_server_address = address.map(|a| a.to_string());
+            self.mark_dirty();
+        }
+    }
+
+    /// Whether the browser API should run
+    pub fn is_web_api_enabled(&self) -> bool {
+        self.is_web_api_enabled
+    }
+
+    /// Set whether the browser API should run. It takes effect on the next
+    /// start.
+    pub fn set_is_web_api_enabled(&mut self, is_web_api_enabled: bool) {
+        if self.is_web_api_enabled != is_web_api_enabled {
+            self.is_web_api_enabled = is_web_api_enabled;
+            self.mark_dirty();
+        }
+    }
+
     /// filename of most recently loaded project
     pub fn project_filename(&self) -> Option<&PathBuf> {
         self.last_project_filename.as_ref()
     }
 
+    /// The most recently loaded project, if we should reload it and it's
+    /// still on disk.
+    pub fn restorable_project_filename(&self) -> Option<&PathBuf> {
+        if !self.should_reload_last_project {
+            return None;
+        }
+        self.last_project_filename.as_ref().filter(|path| {
+            let exists = path.exists();
+            if !exists {
+                eprintln!(
+                    "Warning: last project {} no longer exists; skipping",
+                    path.display()
+                );
+            }
+            exists
+        })
+    }
+
     /// update most recently loaded project filename
     pub fn set_project_filename(&mut self, project_filename: &Path) {
         let should_update = if let Some(filename) = &self.last_project_filename {
@@ -175,7 +334,94 @@ impl Displays for Preferences {
                 {
                     self.mark_dirty();
                 }
+                let mut is_osc_server_enabled = self.osc_server_address.is_some();
+                if ui
+                    .checkbox(
+                        &mut is_osc_server_enabled,
+                        "Listen for OSC control (restart to apply)",
+                    )
+                    .changed()
+                {
+                    self.osc_server_address =
+                        is_osc_server_enabled.then(|| OscServer::DEFAULT_ADDRESS.to_string());
+                    self.mark_dirty();
+                }
+                if let Some(address) = self.osc_server_address.as_mut() {
+                    let response = ui
+                        .horizontal(|ui| {
+                            ui.label("OSC address");
+                            ui.text_edit_singleline(address)
+                        })
+                        .inner;
+                    if response.lost_focus() {
+                        self.mark_dirty();
+                    }
+                }
+                if ui
+                    .checkbox(
+                        &mut self.is_web_api_enabled,
+                        "Let browsers on this computer control the app (restart to apply)",
+                    )
+                    .changed()
+                {
+                    self.mark_dirty();
+                }
             })
             .header_response
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn older_prefs_files_still_parse() {
+        let json = r#"{
+            "selected_midi_input": "Keystation",
+            "selected_midi_output": null,
+            "should_reload_last_project": true,
+            "last_project_filename": "/nonexistent/project.json"
+        }"#;
+        let prefs: Preferences = serde_json::from_str(json).unwrap();
+        assert_eq!(prefs.selected_midi_input().unwrap(), "Keystation");
+        assert!(prefs.selected_audio_output().is_none());
+        assert!(prefs.open_panels().is_empty());
+        assert!(!prefs.is_web_api_enabled());
+        assert!(prefs.osc_server_address().is_none());
+
+        // The project is gone, so we shouldn't try to reload it.
+        assert!(prefs.project_filename().is_some());
+        assert!(prefs.restorable_project_filename().is_none());
+    }
+
+    #[test]
+    fn missing_devices_are_skipped() {
+        let available = vec!["Speakers".to_string(), "Headphones".to_string()];
+        let selection = "Headphones".to_string();
+        assert_eq!(
+            Preferences::restorable_device(Some(&selection), &available),
+            Some(&selection)
+        );
+        let gone = "USB Interface".to_string();
+        assert!(Preferences::restorable_device(Some(&gone), &available).is_none());
+        assert!(Preferences::restorable_device(None, &available).is_none());
+    }
+
+    #[test]
+    fn changes_are_saved_after_a_quiet_period() {
+        let mut prefs = Preferences::default();
+        assert!(!prefs.is_save_due(Instant::now() + Preferences::SAVE_DELAY));
+
+        prefs.set_panel_open(Preferences::SETTINGS_PANEL, true);
+        let now = Instant::now();
+        assert!(!prefs.is_save_due(now));
+        assert!(prefs.is_save_due(now + Preferences::SAVE_DELAY));
+
+        // Another change restarts the wait.
+        prefs.set_is_web_api_enabled(true);
+        let last_change = prefs.last_change.unwrap();
+        assert!(!prefs.is_save_due(last_change + Preferences::SAVE_DELAY / 2));
+        assert!(prefs.is_save_due(last_change + Preferences::SAVE_DELAY));
+    }
+}
@@ -1,23 +1,382 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
+use crate::mini::{MidiClockEvent, MidiClockFollower, MidiRealtime, MpeEvent, MpeInput, MpeZone};
 use crossbeam_channel::{Receiver, Sender};
 use eframe::egui::{CollapsingHeader, ComboBox, Ui};
 use ensnare_core::{midi::prelude::*, traits::prelude::*};
 use ensnare_midi_interface::{
     MidiInterfaceEvent, MidiInterfaceInput, MidiInterfaceService, MidiPortDescriptor,
 };
+use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+use midly::{num::u14, PitchBend};
 use serde::{Deserialize, Serialize};
 use std::{
     sync::{Arc, Mutex},
     time::Instant,
 };
 
+/// Maps each incoming MIDI channel to the channel the app should see. The
+/// default is the identity map.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MidiChannelMap {
+    map: [MidiChannel; 16],
+}
+impl Default for MidiChannelMap {
+    fn default() -> Self {
+        Self {
+            map: core::array::from_fn(|i| MidiChannel(i as u8)),
+        }
+    }
+}
+impl MidiChannelMap {
+    /// Returns the channel that `channel` is mapped to.
+    pub fn map(&self, channel: MidiChannel) -> MidiChannel {
+        self.map.get(channel.0 as usize).copied().unwrap_or(channel)
+    }
+
+    /// Routes incoming `from` messages to `to`.
+    pub fn set(&mut self, from: MidiChannel, to: MidiChannel) {
+        if let Some(slot) = self.map.get_mut(from.0 as usize) {
+            *slot = to;
+        }
+    }
+
+    /// Folds all incoming channels onto `to` ("omni").
+    pub fn set_omni(&mut self, to: MidiChannel) {
+        self.map = [to; 16];
+    }
+
+    /// Whether this map leaves every channel alone.
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Reshapes the velocities of incoming note-on messages.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum VelocityCurve {
+    /// Velocities pass through unchanged.
+    #[default]
+    Linear,
+    /// Lighter playing produces louder notes.
+    Soft,
+    /// Harder playing is needed to produce loud notes.
+    Hard,
+    /// A lookup table of 128 output values, indexed by input velocity.
+    Custom(Vec<u8>),
+}
+impl VelocityCurve {
+    /// Maps an input velocity to an output velocity. A velocity of zero (which
+    /// means note-off) stays zero, and nothing else ever becomes zero.
+    pub fn apply(&self, velocity: u8) -> u8 {
+        if velocity == 0 {
+            return 0;
+        }
+        let velocity = velocity.min(127);
+        let mapped = match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Soft => Self::apply_exponent(velocity, 0.6),
+            VelocityCurve::Hard => Self::apply_exponent(velocity, 1.6),
+            VelocityCurve::Custom(table) => {
+                table.get(velocity as usize).copied().unwrap_or(velocity)
+            }
+        };
+        mapped.clamp(1, 127)
+    }
+
+    fn apply_exponent(velocity: u8, exponent: f64) -> u8 {
+        ((velocity as f64 / 127.0).powf(exponent) * 127.0).round() as u8
+    }
+
+    fn apply_to_message(&self, message: MidiMessage) -> MidiMessage {
+        match message {
+            MidiMessage::NoteOn { key, vel } => MidiMessage::NoteOn {
+                key,
+                vel: self.apply(vel.as_int()).into(),
+            },
+            _ => message,
+        }
+    }
+}
+
+/// Serializes a batch of MIDI messages into one buffer, so that an output can
+/// send the whole batch with a single `connection.send()` instead of one
+/// syscall per message.
+///
+/// Every message keeps its own status byte. Running status would save a few
+/// bytes, but then what the receiver sees would differ from sending the
+/// messages one at a time, and some drivers treat each send as a separate
+/// packet that has to start with a status byte.
+pub fn encode_midi_batch(messages: &[(MidiChannel, MidiMessage)]) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(messages.len() * 3);
+    encode_midi_batch_into(messages, &mut buffer);
+    buffer
+}
+
+/// Like [encode_midi_batch()], but appends to `buffer`, so that a caller that
+/// sends often can reuse one buffer.
+pub fn encode_midi_batch_into(messages: &[(MidiChannel, MidiMessage)], buffer: &mut Vec<u8>) {
+    for (channel, message) in messages {
+        encode_midi_message(*channel, message, buffer);
+    }
+}
+
+/// Appends the wire bytes of a single channel message to `buffer`.
+pub fn encode_midi_message(channel: MidiChannel, message: &MidiMessage, buffer: &mut Vec<u8>) {
+    let channel = channel.0 & 0x0f;
+    match message {
+        MidiMessage::NoteOff { key, vel } => {
+            buffer.extend_from_slice(&[0x80 | channel, key.as_int(), vel.as_int()])
+        }
+        MidiMessage::NoteOn { key, vel } => {
+            buffer.extend_from_slice(&[0x90 | channel, key.as_int(), vel.as_int()])
+        }
+        MidiMessage::Aftertouch { key, vel } => {
+            buffer.extend_from_slice(&[0xa0 | channel, key.as_int(), vel.as_int()])
+        }
+        MidiMessage::Controller { controller, value } => {
+            buffer.extend_from_slice(&[0xb0 | channel, controller.as_int(), value.as_int()])
+        }
+        MidiMessage::ProgramChange { program } => {
+            buffer.extend_from_slice(&[0xc0 | channel, program.as_int()])
+        }
+        MidiMessage::ChannelAftertouch { vel } => {
+            buffer.extend_from_slice(&[0xd0 | channel, vel.as_int()])
+        }
+        MidiMessage::PitchBend { bend } => {
+            let value = bend.0.as_int();
+            buffer.extend_from_slice(&[
+                0xe0 | channel,
+                (value & 0x7f) as u8,
+                ((value >> 7) & 0x7f) as u8,
+            ])
+        }
+    }
+}
+
+/// A message that [MidiByteParser] found.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParsedMidi {
+    #[allow(missing_docs)]
+    Channel(MidiChannel, MidiMessage),
+    /// A MIDI clock sync message.
+    Realtime(MidiRealtime),
+}
+
+/// Turns raw MIDI bytes from an input port into channel messages without ever
+/// panicking. Cheap USB cables and hubs deliver partial messages, stray data
+/// bytes, and realtime bytes wedged between a status byte and its data, and a
+/// bare `LiveEvent::parse(bytes).unwrap()` takes down the MIDI thread on the
+/// first one of those.
+///
+/// The parser keeps state across calls, so a message split across two input
+/// callbacks comes out whole. It honors running status (a data byte with no
+/// status byte repeats the previous channel status), lets realtime bytes
+/// through wherever they appear without disturbing the message around them,
+/// skips system exclusive and system common messages, and drops anything
+/// else that doesn't make sense with a warning.
+#[derive(Debug, Default)]
+pub struct MidiByteParser {
+    running_status: Option<u8>,
+    data: Vec<u8>,
+    is_in_sysex: bool,
+}
+impl MidiByteParser {
+    /// Feeds `bytes` to the parser and returns every complete channel message
+    /// they finish. Realtime messages are dropped; see parse_all().
+    pub fn parse(&mut self, bytes: &[u8]) -> Vec<(MidiChannel, MidiMessage)> {
+        self.parse_all(bytes)
+            .into_iter()
+            .filter_map(|input| match input {
+                ParsedMidi::Channel(channel, message) => Some((channel, message)),
+                ParsedMidi::Realtime(_) => None,
+            })
+            .collect()
+    }
+
+    /// Like parse(), but also returns the clock, start, continue, and stop
+    /// messages, in the order they arrived.
+    pub fn parse_all(&mut self, bytes: &[u8]) -> Vec<ParsedMidi> {
+        let mut messages = Vec::default();
+        for &byte in bytes {
+            if let Some(message) = self.parse_byte(byte) {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    /// Forgets any partial message and the running status, as after a port is
+    /// reopened.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn parse_byte(&mut self, byte: u8) -> Option<ParsedMidi> {
+        match byte {
+            // Realtime messages (clock, start, stop, active sensing, ...) can
+            // appear anywhere, even mid-message, and don't disturb anything.
+            0xf8..=0xff => MidiRealtime::from_byte(byte).map(ParsedMidi::Realtime),
+            0xf0 => {
+                self.is_in_sysex = true;
+                self.running_status = None;
+                self.data.clear();
+                None
+            }
+            0xf7 => {
+                self.is_in_sysex = false;
+                None
+            }
+            // System common messages cancel running status. We don't use
+            // any of them, so their data bytes are dropped as strays below.
+            0xf1..=0xf6 => {
+                self.is_in_sysex = false;
+                self.running_status = None;
+                self.data.clear();
+                None
+            }
+            0x80..=0xef => {
+                if self.is_in_sysex {
+                    eprintln!("Warning: MIDI sysex ended without 0xF7");
+                    self.is_in_sysex = false;
+                }
+                if !self.data.is_empty() {
+                    eprintln!("Warning: dropping partial MIDI message {:02x?}", self.data);
+                    self.data.clear();
+                }
+                self.running_status = Some(byte);
+                None
+            }
+            _ => {
+                if self.is_in_sysex {
+                    return None;
+                }
+                let Some(status) = self.running_status else {
+                    eprintln!("Warning: dropping stray MIDI data byte {byte:#04x}");
+                    return None;
+                };
+                self.data.push(byte);
+                if self.data.len() < Self::data_len(status) {
+                    return None;
+                }
+                let (channel, message) = Self::decode(status, &self.data);
+                self.data.clear();
+                Some(ParsedMidi::Channel(channel, message))
+            }
+        }
+    }
+
+    fn data_len(status: u8) -> usize {
+        match status & 0xf0 {
+            0xc0 | 0xd0 => 1,
+            _ => 2,
+        }
+    }
+
+    // `status` is a channel status byte and `data` holds exactly as many data
+    // bytes as it needs, all below 0x80.
+    fn decode(status: u8, data: &[u8]) -> (MidiChannel, MidiMessage) {
+        let channel = MidiChannel(status & 0x0f);
+        let message = match status & 0xf0 {
+            0x80 => MidiMessage::NoteOff {
+                key: data[0].into(),
+                vel: data[1].into(),
+            },
+            0x90 => MidiMessage::NoteOn {
+                key: data[0].into(),
+                vel: data[1].into(),
+            },
+            0xa0 => MidiMessage::Aftertouch {
+                key: data[0].into(),
+                vel: data[1].into(),
+            },
+            0xb0 => MidiMessage::Controller {
+                controller: data[0].into(),
+                value: data[1].into(),
+            },
+            0xc0 => MidiMessage::ProgramChange {
+                program: data[0].into(),
+            },
+            0xd0 => MidiMessage::ChannelAftertouch {
+                vel: data[0].into(),
+            },
+            _ => MidiMessage::PitchBend {
+                bend: PitchBend(u14::from(data[0] as u16 | (data[1] as u16) << 7)),
+            },
+        };
+        (channel, message)
+    }
+}
+
+/// Identifies a MIDI port across restarts. Port indexes change whenever devices
+/// are plugged in or removed, and names aren't unique (two identical keyboards
+/// have the same name), so we key on the name plus the port's ordinal among
+/// ports sharing that name. midir doesn't give us its platform port IDs
+/// through [MidiPortDescriptor], or we'd prefer those.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MidiPortKey {
+    name: String,
+    ordinal: usize,
+}
+impl MidiPortKey {
+    /// Creates the key for `port`, which should be one of `ports`.
+    pub fn new_with(port: &MidiPortDescriptor, ports: &[MidiPortDescriptor]) -> Self {
+        let ordinal = ports
+            .iter()
+            .filter(|p| p.name == port.name && p.index < port.index)
+            .count();
+        Self {
+            name: port.name.clone(),
+            ordinal,
+        }
+    }
+
+    /// Finds the port in the current list that this key refers to.
+    pub fn restore_port<'a>(
+        &self,
+        ports: &'a [MidiPortDescriptor],
+    ) -> Option<&'a MidiPortDescriptor> {
+        let mut same_name: Vec<&MidiPortDescriptor> =
+            ports.iter().filter(|p| p.name == self.name).collect();
+        same_name.sort_by_key(|p| p.index);
+        same_name.get(self.ordinal).copied()
+    }
+}
+
 /// Contains persistent MIDI settings.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MidiSettings {
     selected_input: Option<MidiPortDescriptor>,
     selected_output: Option<MidiPortDescriptor>,
 
+    /// Stable identifiers for the selected ports, used to find them again at
+    /// startup.
+    #[serde(default)]
+    selected_input_key: Option<MidiPortKey>,
+    #[serde(default)]
+    selected_output_key: Option<MidiPortKey>,
+
+    /// Whether incoming MIDI should also be echoed to the selected output.
+    #[serde(default)]
+    should_route_thru: bool,
+
+    /// Remaps the channels of incoming MIDI messages.
+    #[serde(default)]
+    input_channel_map: MidiChannelMap,
+
+    /// Reshapes the velocities of incoming MIDI note-ons.
+    #[serde(default)]
+    velocity_curve: VelocityCurve,
+
+    /// If set, incoming MIDI in this zone is treated as MPE, and its member
+    /// channels aren't remapped.
+    #[serde(default)]
+    mpe_zone: Option<MpeZone>,
+
+    /// Whether the transport follows MIDI clock from the selected input.
+    #[serde(default)]
+    should_follow_midi_clock: bool,
+
     #[serde(skip)]
     has_been_saved: bool,
 
@@ -31,6 +390,13 @@ impl Default for MidiSettings {
         Self {
             selected_input: Default::default(),
             selected_output: Default::default(),
+            selected_input_key: Default::default(),
+            selected_output_key: Default::default(),
+            should_route_thru: Default::default(),
+            input_channel_map: Default::default(),
+            velocity_curve: Default::default(),
+            mpe_zone: Default::default(),
+            should_follow_midi_clock: Default::default(),
             has_been_saved: Default::default(),
             last_input_instant: Self::create_last_input_instant(),
             last_output_instant: Instant::now(),
@@ -51,21 +417,133 @@ impl HasSettings for MidiSettings {
     }
 }
 impl MidiSettings {
-    /// Updates the field and marks the struct eligible to save.
-    pub fn set_input(&mut self, input: Option<MidiPortDescriptor>) {
-        if input != self.selected_input {
+    /// Updates the field and marks the struct eligible to save. `ports` is
+    /// the current list of inputs, which is needed to identify the port
+    /// reliably later.
+    pub fn set_input(&mut self, input: Option<MidiPortDescriptor>, ports: &[MidiPortDescriptor]) {
+        let key = input
+            .as_ref()
+            .map(|port| MidiPortKey::new_with(port, ports));
+        if input != self.selected_input || key != self.selected_input_key {
             self.selected_input = input;
+            self.selected_input_key = key;
             self.needs_save();
         }
     }
-    /// Updates the field and marks the struct eligible to save.
-    pub fn set_output(&mut self, output: Option<MidiPortDescriptor>) {
-        if output != self.selected_output {
+    /// Updates the field and marks the struct eligible to save. `ports` is
+    /// the current list of outputs.
+    pub fn set_output(&mut self, output: Option<MidiPortDescriptor>, ports: &[MidiPortDescriptor]) {
+        let key = output
+            .as_ref()
+            .map(|port| MidiPortKey::new_with(port, ports));
+        if output != self.selected_output || key != self.selected_output_key {
             self.selected_output = output;
+            self.selected_output_key = key;
+            self.needs_save();
+        }
+    }
+
+    // Settings saved before we had keys have only the descriptor. The best we
+    // can do for those is the first port with the same name.
+    fn input_key(&self) -> Option<MidiPortKey> {
+        self.selected_input_key.clone().or_else(|| {
+            self.selected_input.as_ref().map(|port| MidiPortKey {
+                name: port.name.clone(),
+                ordinal: 0,
+            })
+        })
+    }
+
+    fn output_key(&self) -> Option<MidiPortKey> {
+        self.selected_output_key.clone().or_else(|| {
+            self.selected_output.as_ref().map(|port| MidiPortKey {
+                name: port.name.clone(),
+                ordinal: 0,
+            })
+        })
+    }
+
+    /// Whether MIDI thru is enabled.
+    pub fn should_route_thru(&self) -> bool {
+        self.should_route_thru
+    }
+
+    /// Updates the field and marks the struct eligible to save.
+    pub fn set_should_route_thru(&mut self, should_route_thru: bool) {
+        if should_route_thru != self.should_route_thru {
+            self.should_route_thru = should_route_thru;
+            self.needs_save();
+        }
+    }
+
+    /// Returns the incoming channel map.
+    pub fn input_channel_map(&self) -> &MidiChannelMap {
+        &self.input_channel_map
+    }
+
+    /// Replaces the incoming channel map and marks the struct eligible to save.
+    pub fn set_input_channel_map(&mut self, input_channel_map: MidiChannelMap) {
+        if input_channel_map != self.input_channel_map {
+            self.input_channel_map = input_channel_map;
+            self.needs_save();
+        }
+    }
+
+    /// Returns the incoming velocity curve.
+    pub fn velocity_curve(&self) -> &VelocityCurve {
+        &self.velocity_curve
+    }
+
+    /// Updates the field and marks the struct eligible to save.
+    pub fn set_velocity_curve(&mut self, velocity_curve: VelocityCurve) {
+        if velocity_curve != self.velocity_curve {
+            self.velocity_curve = velocity_curve;
+            self.needs_save();
+        }
+    }
+
+    /// Returns the MPE zone, if MPE input is on.
+    pub fn mpe_zone(&self) -> Option<&MpeZone> {
+        self.mpe_zone.as_ref()
+    }
+
+    /// Turns MPE input on for the given zone, or off for None, and marks the
+    /// struct eligible to save.
+    pub fn set_mpe_zone(&mut self, mpe_zone: Option<MpeZone>) {
+        if mpe_zone != self.mpe_zone {
+            self.mpe_zone = mpe_zone;
+            self.needs_save();
+        }
+    }
+
+    /// Whether the transport follows MIDI clock from the selected input.
+    pub fn should_follow_midi_clock(&self) -> bool {
+        self.should_follow_midi_clock
+    }
+
+    /// Updates the field and marks the struct eligible to save.
+    pub fn set_should_follow_midi_clock(&mut self, should_follow_midi_clock: bool) {
+        if should_follow_midi_clock != self.should_follow_midi_clock {
+            self.should_follow_midi_clock = should_follow_midi_clock;
             self.needs_save();
         }
     }
 
+    /// Whether incoming MIDI should be echoed out right now. If the input and
+    /// output are the same physical device, then echoing would make a feedback
+    /// loop, so we don't. A device's input and output ports share its
+    /// [MidiPortKey], while a second identical device has a different one, so
+    /// thru between two of the same keyboard still works.
+    fn is_thru_active(&self) -> bool {
+        if !self.should_route_thru {
+            return false;
+        }
+        match (self.input_key(), self.output_key()) {
+            (Some(input), Some(output)) => input != output,
+            _ => false,
+        }
+    }
+
     fn create_last_input_instant() -> Arc<Mutex<Instant>> {
         Arc::new(Mutex::new(Instant::now()))
     }
@@ -77,6 +555,14 @@ pub enum MidiPanelEvent {
     /// A MIDI message arrived from the interface.
     Midi(MidiChannel, MidiMessage),
 
+    /// A message from an MPE controller, translated for the instrument on the
+    /// zone's master channel.
+    Mpe(MidiChannel, MpeEvent),
+
+    /// The external MIDI clock asks the transport to change tempo, start,
+    /// continue, or stop.
+    Clock(MidiClockEvent),
+
     /// The user has picked a MIDI input. Switch to it.
     ///
     /// Inputs are sent by the PC to the interface.
@@ -91,6 +577,191 @@ pub enum MidiPanelEvent {
     PortsRefreshed,
 }
 
+/// Handles what arrives at the selected MIDI input, and forwards the results
+/// to the app (and to the output, if thru is on). Each open port gets its own,
+/// because the MPE state belongs to the port.
+#[derive(Debug)]
+struct MidiInputHandler {
+    settings: Arc<Mutex<MidiSettings>>,
+    clock_follower: Arc<Mutex<MidiClockFollower>>,
+    output_port: Arc<Mutex<MidiOutputPort>>,
+    app_sender: Sender<MidiPanelEvent>,
+    mpe: Option<MpeInput>,
+}
+impl MidiInputHandler {
+    fn new_with(
+        settings: Arc<Mutex<MidiSettings>>,
+        clock_follower: Arc<Mutex<MidiClockFollower>>,
+        output_port: Arc<Mutex<MidiOutputPort>>,
+        app_sender: Sender<MidiPanelEvent>,
+    ) -> Self {
+        Self {
+            settings,
+            clock_follower,
+            output_port,
+            app_sender,
+            mpe: None,
+        }
+    }
+
+    // Shares everything but the per-port state.
+    fn new_for_port(&self) -> Self {
+        Self::new_with(
+            Arc::clone(&self.settings),
+            Arc::clone(&self.clock_follower),
+            Arc::clone(&self.output_port),
+            self.app_sender.clone(),
+        )
+    }
+
+    // Called from the input port's callback with whatever bytes the driver
+    // delivered, which might not be whole messages. `parser` belongs to the
+    // port, so partial messages and running status carry over correctly.
+    // Realtime messages are handled right here rather than queued, because
+    // the clock's tempo is measured from when the ticks show up.
+    fn handle_bytes(&mut self, parser: &mut MidiByteParser, bytes: &[u8]) {
+        for parsed in parser.parse_all(bytes) {
+            match parsed {
+                ParsedMidi::Channel(channel, message) => self.handle_message(channel, message),
+                ParsedMidi::Realtime(message) => self.handle_realtime(message),
+            }
+        }
+    }
+
+    // Does nothing unless MIDI clock sync is on.
+    fn handle_realtime(&self, message: MidiRealtime) {
+        let at = Instant::now();
+        let is_following = self
+            .settings
+            .lock()
+            .is_ok_and(|settings| settings.should_follow_midi_clock());
+        if !is_following {
+            return;
+        }
+        let event = self
+            .clock_follower
+            .lock()
+            .ok()
+            .and_then(|mut follower| follower.handle(message, at));
+        if let Some(event) = event {
+            let _ = self.app_sender.send(MidiPanelEvent::Clock(event));
+        }
+    }
+
+    fn handle_message(&mut self, channel: MidiChannel, message: MidiMessage) {
+        let mut should_route_thru = false;
+        let mut mpe_events = None;
+        let (mut channel, mut message) = (channel, message);
+        if let Ok(mut settings) = self.settings.lock() {
+            settings.last_input_instant = MidiSettings::create_last_input_instant();
+            should_route_thru = settings.is_thru_active();
+            message = settings.velocity_curve.apply_to_message(message);
+            if self.mpe.as_ref().map(|m| m.zone()) != settings.mpe_zone.as_ref() {
+                self.mpe = settings.mpe_zone.map(MpeInput::new_with);
+            }
+            // MPE needs the real member channels, so the channel map applies
+            // only outside the zone.
+            mpe_events = self
+                .mpe
+                .as_mut()
+                .and_then(|mpe| mpe.handle(channel, message));
+            if mpe_events.is_none() {
+                channel = settings.input_channel_map.map(channel);
+            }
+        }
+        if should_route_thru {
+            if let Ok(mut output_port) = self.output_port.lock() {
+                output_port.send_batch(&[(channel, message)]);
+            }
+        }
+        if let (Some(events), Some(mpe)) = (mpe_events, self.mpe.as_ref()) {
+            let master = mpe.zone().master();
+            for event in events {
+                let _ = self.app_sender.send(MidiPanelEvent::Mpe(master, event));
+            }
+        } else {
+            let _ = self.app_sender.send(MidiPanelEvent::Midi(channel, message));
+        }
+    }
+}
+
+// midir's connection isn't Debug.
+struct MidiInputPort(Option<MidiInputConnection<MidiByteParser>>);
+impl std::fmt::Debug for MidiInputPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MidiInputPort")
+            .field(&self.0.is_some())
+            .finish()
+    }
+}
+
+/// Where [MidiOutputPort] sends its bytes: the midir connection, or a
+/// recorder in tests.
+trait MidiByteSink: Send {
+    fn send_bytes(&mut self, bytes: &[u8]) -> Result<(), String>;
+}
+impl MidiByteSink for MidiOutputConnection {
+    fn send_bytes(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.send(bytes).map_err(|e| e.to_string())
+    }
+}
+
+// The selected output. Like the input, we open it ourselves rather than
+// through the interface service, so that a batch of messages can go out in a
+// single send().
+#[derive(Default)]
+struct MidiOutputPort {
+    sink: Option<Box<dyn MidiByteSink>>,
+    buffer: Vec<u8>,
+}
+impl std::fmt::Debug for MidiOutputPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MidiOutputPort")
+            .field(&self.sink.is_some())
+            .finish()
+    }
+}
+impl MidiOutputPort {
+    // Sends the whole batch in one send(). The bytes are exactly those of
+    // sending each message on its own; see encode_midi_batch().
+    fn send_batch(&mut self, messages: &[(MidiChannel, MidiMessage)]) {
+        let Some(sink) = self.sink.as_mut() else {
+            return;
+        };
+        if messages.is_empty() {
+            return;
+        }
+        self.buffer.clear();
+        encode_midi_batch_into(messages, &mut self.buffer);
+        if let Err(e) = sink.send_bytes(&self.buffer) {
+            eprintln!("Warning: couldn't send MIDI: {e}");
+        }
+    }
+}
+
+/// Sends MIDI to the selected output from any thread, such as the one that
+/// renders audio. Get one from [MidiPanel::output_sender()].
+#[derive(Clone, Debug)]
+pub struct MidiOutputSender {
+    output_port: Arc<Mutex<MidiOutputPort>>,
+    settings: Arc<Mutex<MidiSettings>>,
+}
+impl MidiOutputSender {
+    /// Sends `messages` to the selected output as one buffer, in order. The
+    /// device receives the same bytes as if they had been sent one at a time.
+    pub fn send_batch(&self, messages: &[(MidiChannel, MidiMessage)]) {
+        if messages.is_empty() {
+            return;
+        }
+        if let Ok(mut settings) = self.settings.lock() {
+            settings.last_output_instant = Instant::now();
+        }
+        if let Ok(mut output_port) = self.output_port.lock() {
+            output_port.send_batch(messages);
+        }
+    }
+}
+
 /// [MidiPanel] manages external MIDI hardware interfaces.
 #[derive(Debug)]
 pub struct MidiPanel {
@@ -102,6 +773,14 @@ pub struct MidiPanel {
     outputs: Arc<Mutex<Vec<MidiPortDescriptor>>>,
 
     settings: Arc<Mutex<MidiSettings>>,
+
+    clock_follower: Arc<Mutex<MidiClockFollower>>,
+
+    // We open the input port ourselves rather than through the interface
+    // service, so that its bytes go through a MidiByteParser.
+    input_port: Arc<Mutex<MidiInputPort>>,
+
+    output_port: Arc<Mutex<MidiOutputPort>>,
 }
 impl MidiPanel {
     /// Creates a new [MidiPanel].
@@ -120,21 +799,37 @@ impl MidiPanel {
             outputs: Default::default(),
 
             settings,
+
+            clock_follower: Default::default(),
+
+            input_port: Arc::new(Mutex::new(MidiInputPort(None))),
+            output_port: Default::default(),
         };
         r.start_midi_interface(midi_interface_service.receiver().clone());
-        r.conform_selections_to_settings();
         r
     }
 
-    /// Sends a [MidiInterfaceInput] message to the service.
+    /// Sends a [MidiInterfaceInput] message to the service. MIDI messages go
+    /// to the selected output, as [MidiPanel::send_batch()] sends them.
     pub fn send(&mut self, input: MidiInterfaceInput) {
-        if let MidiInterfaceInput::Midi(..) = input {
-            if let Ok(mut settings) = self.settings.lock() {
-                settings.last_output_instant = Instant::now();
-            }
+        if let MidiInterfaceInput::Midi(channel, message) = input {
+            self.send_batch(&[(channel, message)]);
+        } else {
+            let _ = self.sender.send(input);
         }
+    }
 
-        let _ = self.sender.send(input);
+    /// Sends `messages` to the selected output in a single send.
+    pub fn send_batch(&mut self, messages: &[(MidiChannel, MidiMessage)]) {
+        self.output_sender().send_batch(messages);
+    }
+
+    /// A handle for sending MIDI to the selected output from other threads.
+    pub fn output_sender(&self) -> MidiOutputSender {
+        MidiOutputSender {
+            output_port: Arc::clone(&self.output_port),
+            settings: Arc::clone(&self.settings),
+        }
     }
 
     // Sits in a loop, watching the receiving side of the event channel and
@@ -144,6 +839,9 @@ impl MidiPanel {
         let outputs = Arc::clone(&self.outputs);
         let settings = Arc::clone(&self.settings);
         let app_sender = self.app_sender.clone();
+        let input_port = Arc::clone(&self.input_port);
+        let output_port = Arc::clone(&self.output_port);
+        let mut handler = self.input_handler();
         std::thread::spawn(move || {
             let mut inputs_refreshed = false;
             let mut outputs_refreshed = false;
@@ -159,8 +857,9 @@ impl MidiPanel {
                             }
                         }
                         MidiInterfaceEvent::InputPortSelected(port) => {
-                            if let Ok(mut settings) = settings.lock() {
-                                settings.set_input(port);
+                            if let (Ok(mut settings), Ok(inputs)) = (settings.lock(), inputs.lock())
+                            {
+                                settings.set_input(port, &inputs);
                             }
                         }
                         MidiInterfaceEvent::OutputPorts(ports) => {
@@ -170,16 +869,16 @@ impl MidiPanel {
                             }
                         }
                         MidiInterfaceEvent::OutputPortSelected(port) => {
-                            if let Ok(mut settings) = settings.lock() {
-                                settings.set_output(port);
+                            if let (Ok(mut settings), Ok(outputs)) =
+                                (settings.lock(), outputs.lock())
+                            {
+                                settings.set_output(port, &outputs);
                             }
                         }
+                        // Only ports that the service opened, which
+                        // shouldn't include inputs anymore.
                         MidiInterfaceEvent::Midi(channel, message) => {
-                            if let Ok(mut settings) = settings.lock() {
-                                settings.last_input_instant =
-                                    MidiSettings::create_last_input_instant();
-                            }
-                            let _ = app_sender.send(MidiPanelEvent::Midi(channel, message));
+                            handler.handle_message(channel, message);
                         }
                         MidiInterfaceEvent::Quit => break,
                     }
@@ -189,6 +888,15 @@ impl MidiPanel {
                 }
                 if !refresh_sent && inputs_refreshed && outputs_refreshed {
                     refresh_sent = true;
+                    Self::conform_selections_to_settings(
+                        &settings,
+                        &inputs,
+                        &outputs,
+                        &input_port,
+                        &output_port,
+                        handler.new_for_port(),
+                        &app_sender,
+                    );
                     let _ = app_sender.send(MidiPanelEvent::PortsRefreshed);
                 }
             }
@@ -207,22 +915,168 @@ impl MidiPanel {
 
     /// Handles a change in selected MIDI input.
     pub fn select_input(&mut self, port: &MidiPortDescriptor) {
-        let _ = self
-            .sender
-            .send(MidiInterfaceInput::SelectMidiInput(port.clone()));
-        let _ = self
-            .app_sender
-            .send(MidiPanelEvent::SelectInput(port.clone()));
+        Self::open_input(
+            port,
+            &self.settings,
+            &self.inputs,
+            &self.input_port,
+            self.input_handler(),
+            &self.app_sender,
+        );
+    }
+
+    fn input_handler(&self) -> MidiInputHandler {
+        MidiInputHandler::new_with(
+            Arc::clone(&self.settings),
+            Arc::clone(&self.clock_follower),
+            Arc::clone(&self.output_port),
+            self.app_sender.clone(),
+        )
+    }
+
+    // Opens `port` with its own MidiByteParser, replacing any input that was
+    // already open, and records it as the selected input.
+    fn open_input(
+        port: &MidiPortDescriptor,
+        settings: &Mutex<MidiSettings>,
+        inputs: &Mutex<Vec<MidiPortDescriptor>>,
+        input_port: &Mutex<MidiInputPort>,
+        handler: MidiInputHandler,
+        app_sender: &Sender<MidiPanelEvent>,
+    ) {
+        // Some backends won't open a port that's already open.
+        if let Ok(mut input_port) = input_port.lock() {
+            input_port.0 = None;
+        }
+        let connection = MidiInput::new("groove")
+            .map_err(|e| e.to_string())
+            .and_then(|input| {
+                let ports = input.ports();
+                let Some(midir_port) = ports
+                    .get(port.index)
+                    .filter(|p| input.port_name(p).is_ok_and(|name| name == port.name))
+                else {
+                    return Err(format!("port {} is gone", port.name));
+                };
+                input
+                    .connect(
+                        midir_port,
+                        "groove-input",
+                        move |_, bytes, parser| handler.handle_bytes(parser, bytes),
+                        MidiByteParser::default(),
+                    )
+                    .map_err(|e| e.to_string())
+            });
+        match connection {
+            Ok(connection) => {
+                if let Ok(mut input_port) = input_port.lock() {
+                    input_port.0 = Some(connection);
+                }
+                if let (Ok(mut settings), Ok(inputs)) = (settings.lock(), inputs.lock()) {
+                    settings.set_input(Some(port.clone()), &inputs);
+                }
+                let _ = app_sender.send(MidiPanelEvent::SelectInput(port.clone()));
+            }
+            Err(e) => eprintln!("Warning: couldn't open MIDI input {}: {e}", port.name),
+        }
+    }
+
+    // Opens `port` for output, replacing any output that was already open,
+    // and records it as the selected output.
+    fn open_output(
+        port: &MidiPortDescriptor,
+        settings: &Mutex<MidiSettings>,
+        outputs: &Mutex<Vec<MidiPortDescriptor>>,
+        output_port: &Mutex<MidiOutputPort>,
+        app_sender: &Sender<MidiPanelEvent>,
+    ) {
+        if let Ok(mut output_port) = output_port.lock() {
+            output_port.sink = None;
+        }
+        let connection = MidiOutput::new("groove")
+            .map_err(|e| e.to_string())
+            .and_then(|output| {
+                let ports = output.ports();
+                let Some(midir_port) = ports
+                    .get(port.index)
+                    .filter(|p| output.port_name(p).is_ok_and(|name| name == port.name))
+                else {
+                    return Err(format!("port {} is gone", port.name));
+                };
+                output
+                    .connect(midir_port, "groove-output")
+                    .map_err(|e| e.to_string())
+            });
+        match connection {
+            Ok(connection) => {
+                if let Ok(mut output_port) = output_port.lock() {
+                    output_port.sink = Some(Box::new(connection));
+                }
+                if let (Ok(mut settings), Ok(outputs)) = (settings.lock(), outputs.lock()) {
+                    settings.set_output(Some(port.clone()), &outputs);
+                }
+                let _ = app_sender.send(MidiPanelEvent::SelectOutput(port.clone()));
+            }
+            Err(e) => eprintln!("Warning: couldn't open MIDI output {}: {e}", port.name),
+        }
+    }
+
+    /// Turns MIDI thru on or off. When on, everything arriving at the selected
+    /// input is also sent to the selected output, in addition to going to the
+    /// app as usual.
+    pub fn set_thru(&mut self, should_route_thru: bool) {
+        if let Ok(mut settings) = self.settings.lock() {
+            settings.set_should_route_thru(should_route_thru);
+        }
+    }
+
+    /// Turns MIDI clock sync on or off. When on, the transport follows the
+    /// tempo and the start, stop, and continue messages of the clock arriving
+    /// at the selected input.
+    pub fn set_follow_midi_clock(&mut self, should_follow_midi_clock: bool) {
+        if let Ok(mut settings) = self.settings.lock() {
+            settings.set_should_follow_midi_clock(should_follow_midi_clock);
+        }
+        if let Ok(mut follower) = self.clock_follower.lock() {
+            follower.reset();
+        }
+    }
+
+    /// Routes MIDI arriving on channel `from` to channel `to` before the app
+    /// sees it.
+    pub fn set_input_channel_map(&mut self, from: MidiChannel, to: MidiChannel) {
+        if let Ok(mut settings) = self.settings.lock() {
+            let mut map = settings.input_channel_map().clone();
+            map.set(from, to);
+            settings.set_input_channel_map(map);
+        }
+    }
+
+    /// Sets the curve applied to incoming note-on velocities.
+    pub fn set_velocity_curve(&mut self, velocity_curve: VelocityCurve) {
+        if let Ok(mut settings) = self.settings.lock() {
+            settings.set_velocity_curve(velocity_curve);
+        }
+    }
+
+    /// Routes MIDI arriving on any channel to channel `to`.
+    pub fn set_input_omni(&mut self, to: MidiChannel) {
+        if let Ok(mut settings) = self.settings.lock() {
+            let mut map = MidiChannelMap::default();
+            map.set_omni(to);
+            settings.set_input_channel_map(map);
+        }
     }
 
     /// Handles a change in selected MIDI output.
     pub fn select_output(&mut self, port: &MidiPortDescriptor) {
-        let _ = self
-            .sender
-            .send(MidiInterfaceInput::SelectMidiOutput(port.clone()));
-        let _ = self
-            .app_sender
-            .send(MidiPanelEvent::SelectOutput(port.clone()));
+        Self::open_output(
+            port,
+            &self.settings,
+            &self.outputs,
+            &self.output_port,
+            &self.app_sender,
+        );
     }
 
     /// The receive side of the [MidiPanelEvent] channel
@@ -247,21 +1101,39 @@ impl MidiPanel {
     }
 
     /// When settings are loaded, we have to look at them and update the actual
-    /// state to match.
-    fn conform_selections_to_settings(&mut self) {
-        let (input, output) = if let Ok(settings) = self.settings.lock() {
-            (
-                settings.selected_input.clone(),
-                settings.selected_output.clone(),
-            )
+    /// state to match. This has to wait until the port lists have arrived,
+    /// because the saved port indexes might not be valid anymore.
+    fn conform_selections_to_settings(
+        settings: &Mutex<MidiSettings>,
+        inputs: &Mutex<Vec<MidiPortDescriptor>>,
+        outputs: &Mutex<Vec<MidiPortDescriptor>>,
+        input_port: &Mutex<MidiInputPort>,
+        output_port: &Mutex<MidiOutputPort>,
+        handler: MidiInputHandler,
+        app_sender: &Sender<MidiPanelEvent>,
+    ) {
+        let (input_key, output_key) = if let Ok(settings) = settings.lock() {
+            (settings.input_key(), settings.output_key())
         } else {
             (None, None)
         };
+        let input = input_key.and_then(|key| {
+            inputs
+                .lock()
+                .ok()
+                .and_then(|inputs| key.restore_port(&inputs).cloned())
+        });
         if let Some(port) = input {
-            self.select_input(&port);
+            Self::open_input(&port, settings, inputs, input_port, handler, app_sender);
         }
+        let output = output_key.and_then(|key| {
+            outputs
+                .lock()
+                .ok()
+                .and_then(|outputs| key.restore_port(&outputs).cloned())
+        });
         if let Some(port) = output {
-            self.select_output(&port);
+            Self::open_output(&port, settings, outputs, output_port, app_sender);
         }
     }
 }
@@ -342,7 +1214,7 @@ impl<'a> Displays for MidiSettingsWidget<'a> {
                             .selectable_value(&mut selected_index, port.index, port.name.clone())
                             .changed()
                         {
-                            self.settings.set_input(Some(port.clone()));
+                            self.settings.set_input(Some(port.clone()), self.inputs);
                             *self.new_input = Some(port.clone());
                         }
                     }
@@ -363,13 +1235,421 @@ impl<'a> Displays for MidiSettingsWidget<'a> {
                             .selectable_value(&mut selected_index, port.index, port.name.clone())
                             .changed()
                         {
-                            self.settings.set_output(Some(port.clone()));
+                            self.settings.set_output(Some(port.clone()), self.outputs);
                             *self.new_output = Some(port.clone());
                         }
                     }
                 });
                 ui.end_row();
+
+                let mut should_route_thru = self.settings.should_route_thru();
+                if ui
+                    .checkbox(&mut should_route_thru, "MIDI thru")
+                    .on_hover_text("Echo MIDI input to MIDI output")
+                    .changed()
+                {
+                    self.settings.set_should_route_thru(should_route_thru);
+                }
+
+                let mut should_follow_midi_clock = self.settings.should_follow_midi_clock();
+                if ui
+                    .checkbox(&mut should_follow_midi_clock, "Follow MIDI clock")
+                    .on_hover_text("Sync tempo and play/stop to MIDI clock input")
+                    .changed()
+                {
+                    self.settings
+                        .set_should_follow_midi_clock(should_follow_midi_clock);
+                }
             })
             .header_response
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn midi_batch_matches_individual_messages() {
+        let messages = vec![
+            (
+                MidiChannel(0),
+                MidiMessage::NoteOn {
+                    key: 60.into(),
+                    vel: 100.into(),
+                },
+            ),
+            (
+                MidiChannel(0),
+                MidiMessage::NoteOn {
+                    key: 64.into(),
+                    vel: 100.into(),
+                },
+            ),
+            (
+                MidiChannel(9),
+                MidiMessage::ProgramChange { program: 5.into() },
+            ),
+            (
+                MidiChannel(0),
+                MidiMessage::NoteOff {
+                    key: 60.into(),
+                    vel: 0.into(),
+                },
+            ),
+        ];
+        let batch = encode_midi_batch(&messages);
+        let mut individual = Vec::new();
+        for (channel, message) in messages.iter() {
+            let mut buffer = Vec::new();
+            encode_midi_message(*channel, message, &mut buffer);
+            individual.extend(buffer);
+        }
+        assert_eq!(batch, individual);
+        assert_eq!(
+            batch,
+            vec![0x90, 60, 100, 0x90, 64, 100, 0xc9, 5, 0x80, 60, 0]
+        );
+        assert!(encode_midi_batch(&[]).is_empty());
+    }
+
+    #[derive(Clone, Default)]
+    struct RecordingSink(Arc<Mutex<Vec<Vec<u8>>>>);
+    impl MidiByteSink for RecordingSink {
+        fn send_bytes(&mut self, bytes: &[u8]) -> Result<(), String> {
+            self.0.lock().unwrap().push(bytes.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn output_port_batch_sends_same_bytes_as_one_at_a_time() {
+        let messages = vec![
+            (
+                MidiChannel(0),
+                MidiMessage::NoteOn {
+                    key: 60.into(),
+                    vel: 100.into(),
+                },
+            ),
+            (
+                MidiChannel(0),
+                MidiMessage::NoteOn {
+                    key: 64.into(),
+                    vel: 100.into(),
+                },
+            ),
+            (
+                MidiChannel(3),
+                MidiMessage::Controller {
+                    controller: 7.into(),
+                    value: 90.into(),
+                },
+            ),
+            (
+                MidiChannel(0),
+                MidiMessage::NoteOff {
+                    key: 60.into(),
+                    vel: 0.into(),
+                },
+            ),
+        ];
+
+        let batched = RecordingSink::default();
+        let mut port = MidiOutputPort {
+            sink: Some(Box::new(batched.clone())),
+            ..Default::default()
+        };
+        port.send_batch(&messages);
+        port.send_batch(&[]);
+
+        let single = RecordingSink::default();
+        let mut port = MidiOutputPort {
+            sink: Some(Box::new(single.clone())),
+            ..Default::default()
+        };
+        for message in messages.iter() {
+            port.send_batch(&[*message]);
+        }
+
+        let batched = batched.0.lock().unwrap();
+        let single = single.0.lock().unwrap();
+        assert_eq!(batched.len(), 1, "a batch should go out in one send");
+        assert_eq!(single.len(), messages.len());
+        assert_eq!(batched[0], single.concat());
+    }
+
+    #[test]
+    fn midi_byte_parser_round_trips_and_handles_running_status() {
+        let messages = vec![
+            (
+                MidiChannel(2),
+                MidiMessage::NoteOn {
+                    key: 60.into(),
+                    vel: 100.into(),
+                },
+            ),
+            (
+                MidiChannel(2),
+                MidiMessage::ChannelAftertouch { vel: 33.into() },
+            ),
+            (
+                MidiChannel(15),
+                MidiMessage::PitchBend {
+                    bend: PitchBend(u14::from(0x1234)),
+                },
+            ),
+        ];
+        let mut parser = MidiByteParser::default();
+        assert_eq!(parser.parse(&encode_midi_batch(&messages)), messages);
+
+        // Running status, with a clock byte in the middle of a message and the
+        // last message split across two calls.
+        let note = |key: u8, vel: u8| {
+            (
+                MidiChannel(0),
+                MidiMessage::NoteOn {
+                    key: key.into(),
+                    vel: vel.into(),
+                },
+            )
+        };
+        assert_eq!(
+            parser.parse(&[0x90, 60, 100, 64, 0xf8, 100, 67]),
+            vec![note(60, 100), note(64, 100)]
+        );
+        assert_eq!(parser.parse(&[0]), vec![note(67, 0)]);
+    }
+
+    #[test]
+    fn midi_byte_parser_passes_clock_messages_through() {
+        let mut parser = MidiByteParser::default();
+        assert_eq!(
+            parser.parse_all(&[0xfa, 0x90, 60, 0xf8, 100, 0xfe, 0xfc]),
+            vec![
+                ParsedMidi::Realtime(MidiRealtime::Start),
+                ParsedMidi::Realtime(MidiRealtime::Clock),
+                ParsedMidi::Channel(
+                    MidiChannel(0),
+                    MidiMessage::NoteOn {
+                        key: 60.into(),
+                        vel: 100.into(),
+                    }
+                ),
+                ParsedMidi::Realtime(MidiRealtime::Stop),
+            ]
+        );
+    }
+
+    #[test]
+    fn midi_byte_parser_survives_garbage() {
+        let mut parser = MidiByteParser::default();
+
+        // Stray data bytes, a truncated message, sysex, and system common
+        // messages all get skipped without losing the good messages after them.
+        assert!(parser.parse(&[1, 2, 3, 0x90, 60]).is_empty());
+        assert!(parser
+            .parse(&[0xf0, 0x7e, 0x7f, 0x09, 0x01, 0xf7])
+            .is_empty());
+        assert!(
+            parser.parse(&[60, 100]).is_empty(),
+            "sysex cancels running status"
+        );
+        assert!(parser.parse(&[0xf2, 0x10, 0x20]).is_empty());
+        assert_eq!(
+            parser.parse(&[0xb0, 7, 127]),
+            vec![(
+                MidiChannel(0),
+                MidiMessage::Controller {
+                    controller: 7.into(),
+                    value: 127.into(),
+                },
+            )]
+        );
+
+        let junk: Vec<u8> = (0..=255).chain((0..=255).rev()).collect();
+        let _ = parser.parse(&junk);
+        parser.reset();
+        assert!(parser.parse(&[60, 100]).is_empty());
+    }
+
+    #[test]
+    fn input_handler_reassembles_split_messages() {
+        let (sender, _) = crossbeam_channel::unbounded();
+        let (app_sender, app_receiver) = crossbeam_channel::unbounded();
+        let mut handler =
+            MidiInputHandler::new_with(Default::default(), Default::default(), sender, app_sender);
+        let mut parser = MidiByteParser::default();
+
+        // A note-on split across callbacks, then another by running status.
+        handler.handle_bytes(&mut parser, &[0x91, 60]);
+        assert!(app_receiver.try_recv().is_err());
+        handler.handle_bytes(&mut parser, &[100, 64, 90]);
+        let keys: Vec<u8> = app_receiver
+            .try_iter()
+            .map(|event| match event {
+                MidiPanelEvent::Midi(channel, MidiMessage::NoteOn { key, .. }) => {
+                    assert_eq!(channel, MidiChannel(1));
+                    key.as_int()
+                }
+                _ => panic!("unexpected {event:?}"),
+            })
+            .collect();
+        assert_eq!(keys, vec![60, 64]);
+    }
+
+    #[test]
+    fn input_handler_follows_the_clock() {
+        let settings: Arc<Mutex<MidiSettings>> = Default::default();
+        let (sender, _) = crossbeam_channel::unbounded();
+        let (app_sender, app_receiver) = crossbeam_channel::unbounded();
+        let mut handler = MidiInputHandler::new_with(
+            Arc::clone(&settings),
+            Default::default(),
+            sender,
+            app_sender,
+        );
+        let mut parser = MidiByteParser::default();
+
+        // Ignored until clock sync is on.
+        handler.handle_bytes(&mut parser, &[0xfa]);
+        assert!(app_receiver.try_recv().is_err());
+
+        settings.lock().unwrap().set_should_follow_midi_clock(true);
+        handler.handle_bytes(&mut parser, &[0xfa, 0x90, 60, 0xf8, 100, 0xfb, 0xfc]);
+        let events: Vec<_> = app_receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                MidiPanelEvent::Clock(event) => Some(event),
+                MidiPanelEvent::Midi(..) => None,
+                _ => panic!("unexpected {event:?}"),
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                MidiClockEvent::Start,
+                MidiClockEvent::Continue,
+                MidiClockEvent::Stop
+            ]
+        );
+    }
+
+    #[test]
+    fn channel_map() {
+        let mut map = MidiChannelMap::default();
+        assert!(map.is_identity());
+        assert_eq!(map.map(MidiChannel(0)), MidiChannel(0));
+        assert_eq!(map.map(MidiChannel(15)), MidiChannel(15));
+
+        map.set(MidiChannel(0), MidiChannel(9));
+        assert!(!map.is_identity());
+        assert_eq!(map.map(MidiChannel(0)), MidiChannel(9));
+        assert_eq!(map.map(MidiChannel(1)), MidiChannel(1));
+
+        map.set_omni(MidiChannel(4));
+        for i in 0..16 {
+            assert_eq!(map.map(MidiChannel(i)), MidiChannel(4));
+        }
+    }
+
+    #[test]
+    fn port_keys_distinguish_identical_devices() {
+        let ports = vec![
+            MidiPortDescriptor {
+                index: 0,
+                name: "Through".to_string(),
+            },
+            MidiPortDescriptor {
+                index: 1,
+                name: "Keyboard".to_string(),
+            },
+            MidiPortDescriptor {
+                index: 2,
+                name: "Keyboard".to_string(),
+            },
+        ];
+        let key = MidiPortKey::new_with(&ports[2], &ports);
+        assert_eq!(key.ordinal, 1);
+
+        // After a restart, another device shows up first and shifts indexes.
+        let ports_after_restart = vec![
+            MidiPortDescriptor {
+                index: 0,
+                name: "New Device".to_string(),
+            },
+            MidiPortDescriptor {
+                index: 1,
+                name: "Through".to_string(),
+            },
+            MidiPortDescriptor {
+                index: 2,
+                name: "Keyboard".to_string(),
+            },
+            MidiPortDescriptor {
+                index: 3,
+                name: "Keyboard".to_string(),
+            },
+        ];
+        assert_eq!(
+            key.restore_port(&ports_after_restart),
+            Some(&ports_after_restart[3])
+        );
+
+        // If one of the keyboards is unplugged, the second one is gone.
+        assert_eq!(key.restore_port(&ports_after_restart[0..3]), None);
+    }
+
+    #[test]
+    fn thru_is_blocked_only_for_the_same_device() {
+        let ports = vec![
+            MidiPortDescriptor {
+                index: 0,
+                name: "Keyboard".to_string(),
+            },
+            MidiPortDescriptor {
+                index: 1,
+                name: "Keyboard".to_string(),
+            },
+        ];
+        let mut settings = MidiSettings::default();
+        settings.set_should_route_thru(true);
+        settings.set_input(Some(ports[0].clone()), &ports);
+        settings.set_output(Some(ports[0].clone()), &ports);
+        assert!(
+            !settings.is_thru_active(),
+            "echoing a device to itself would loop"
+        );
+
+        // The second of two identical keyboards is a different device.
+        settings.set_output(Some(ports[1].clone()), &ports);
+        assert!(settings.is_thru_active());
+
+        settings.set_should_route_thru(false);
+        assert!(!settings.is_thru_active());
+    }
+
+    #[test]
+    fn velocity_curves() {
+        for v in 0..=127 {
+            assert_eq!(VelocityCurve::Linear.apply(v), v);
+        }
+        for curve in [
+            VelocityCurve::Soft,
+            VelocityCurve::Hard,
+            VelocityCurve::Custom(vec![0; 128]),
+        ] {
+            assert_eq!(curve.apply(0), 0, "{curve:?} should leave note-offs alone");
+            for v in 1..=127 {
+                assert_ne!(curve.apply(v), 0, "{curve:?} turned {v} into a note-off");
+            }
+            assert!(curve.apply(127) <= 127);
+        }
+        assert!(VelocityCurve::Soft.apply(64) > 64);
+        assert!(VelocityCurve::Hard.apply(64) < 64);
+
+        let table: Vec<u8> = (0..128).map(|v| (127 - v) as u8).collect();
+        assert_eq!(VelocityCurve::Custom(table.clone()).apply(1), 126);
+        assert_eq!(VelocityCurve::Custom(table).apply(127), 1);
+    }
+}
@@ -1,18 +1,34 @@
 // Copyright (c) 2023 Mike Tsao. All rights reserved.
 
-pub use audio_panel::{audio_settings, AudioPanel, AudioPanelEvent, AudioSettings, NeedsAudioFn};
+pub use audio_input::{interleaved_to_stereo, AudioInputStream, LinearResampler};
+pub use audio_panel::{
+    audio_settings, AudioBackend, AudioPanel, AudioPanelEvent, AudioSettings, NeedsAudioFn,
+};
+pub use control_editor::ControlEditorPanel;
 pub use control_panel::{ControlPanel, ControlPanelAction};
+pub use cpal_output::{drain_to_interleaved, CpalOutputStream};
+pub use jack_output::drain_to_planar;
+#[cfg(feature = "jack")]
+pub use jack_output::JackOutputStream;
 #[cfg(obsolete)]
 pub use legacy::{
     preferences::Preferences,
     thing_browser::{EntityBrowser, EntityBrowserEvent, EntityBrowserNode},
 };
-pub use midi_panel::{midi_settings, MidiPanel, MidiPanelEvent, MidiSettings};
+pub use midi_panel::{
+    encode_midi_batch, encode_midi_batch_into, encode_midi_message, midi_settings, MidiByteParser,
+    MidiChannelMap, MidiOutputSender, MidiPanel, MidiPanelEvent, MidiPortKey, MidiSettings,
+    ParsedMidi, VelocityCurve,
+};
 pub use orchestrator_panel::{OrchestratorEvent, OrchestratorInput, OrchestratorPanel};
 pub use palette_panel::{PaletteAction, PalettePanel};
 
+mod audio_input;
 mod audio_panel;
+mod control_editor;
 mod control_panel;
+mod cpal_output;
+mod jack_output;
 #[cfg(obsolete)]
 mod legacy;
 mod midi_panel;
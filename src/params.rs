@@ -0,0 +1,41 @@
+// Copyright (c) 2023 Mike Tsao. All rights reserved.
+
+/// Maps a field type to the type that represents it in a #[derive(Params)]
+/// struct. #[derive(Params)] implements it for each struct it's applied to,
+/// with `Params` set to the generated XxxParams struct. Every [Copy] type
+/// (primitives, plain enums, newtypes) is its own Params, so a field of such a
+/// type needs no #[params(leaf = true)] annotation.
+///
+/// This is also why a #[derive(Params)] struct can't be [Copy]; its impl would
+/// conflict with the blanket one.
+pub trait ParamsOf {
+    /// The type stored in the XxxParams struct.
+    type Params;
+
+    /// What the XxxParams getter returns: the value itself for leaves, and a
+    /// reference for nested Params structs.
+    type Get<'a>
+    where
+        Self::Params: 'a;
+
+    /// Converts the field to its Params representation.
+    fn params_of(&self) -> Self::Params;
+
+    /// Implements the XxxParams getter for a field of this type.
+    fn params_get(params: &Self::Params) -> Self::Get<'_>;
+}
+impl<T: Copy> ParamsOf for T {
+    type Params = T;
+    type Get<'a>
+        = T
+    where
+        T: 'a;
+
+    fn params_of(&self) -> T {
+        *self
+    }
+
+    fn params_get(params: &T) -> T {
+        *params
+    }
+}